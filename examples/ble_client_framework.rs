@@ -74,7 +74,7 @@ async fn main_loop(
             let read = match characteristic.read_async().await {
                 Ok(read) => get_number_from_bytes(read),
                 Err(err) => match err {
-                    BleError::CharacteristicNotReadable => continue,
+                    BleError::CharacteristicNotReadable(_) => continue,
                     _ => panic!("{:?}", err),
                 },
             };
@@ -94,7 +94,7 @@ async fn main_loop(
 
             if let Err(err) = characteristic.write_async(&new_value.to_be_bytes()).await {
                 match err {
-                    BleError::CharacteristicNotWritable => continue,
+                    BleError::CharacteristicNotWritable(_) => continue,
                     _ => panic!("{:?}", err),
                 }
             }
@@ -0,0 +1,584 @@
+use super::{I2SError, I2SOutput};
+use std::{
+    f32::consts::TAU,
+    io::{self, Read},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+const MAX_VOLUME: u8 = 100;
+/// Frames generated or decoded before each write to the I2S output
+const FRAMES_PER_WRITE: usize = 256;
+/// Size of each of the two buffers used to stream clips
+const STREAM_BUFFER_SIZE: usize = 4096;
+const STREAM_THREAD_STACK_SIZE: usize = 4096;
+const WAV_HEADER_SIZE: usize = 12;
+const CHUNK_HEADER_SIZE: usize = 8;
+const WAV_FORMAT_PCM: u16 = 1;
+const WAV_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// Error types related to audio playback.
+#[derive(Debug)]
+pub enum AudioError {
+    I2SError(I2SError),
+    InvalidArg,
+    InvalidWav,
+    ReadError,
+    UnsupportedFormat,
+}
+
+/// Enums the waveforms of the tones:
+/// * `Sine`: A pure tone, soft to the ear.
+/// * `Square`: A buzzer like tone, louder at the same volume.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Square,
+}
+
+/// Format of the samples of a PCM clip:
+/// * `channels`: 1 for mono or 2 for stereo.
+/// * `sample_rate`: The sample rate in Hz.
+/// * `bits_per_sample`: 8 (unsigned) or 16 (signed, little endian).
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PcmFormat {
+    channels: u16,
+    sample_rate: u32,
+    bits_per_sample: u16,
+}
+
+/// Tiny audio engine on top of an I2SOutput, that plays tones and short PCM or WAV clips, for
+/// example for alarms and voice prompts. Every playback blocks until the clip ends.
+pub struct AudioPlayer<'a> {
+    output: I2SOutput<'a>,
+    volume: u8,
+}
+
+/// Converts the frames of a clip to stereo frames at the sample rate of the output, dropping or
+/// repeating frames when the rates differ.
+struct FrameConverter {
+    format: PcmFormat,
+    ratio: f64,
+    input_frames: u64,
+    next_output: f64,
+}
+
+impl Waveform {
+    /// Gets the value of the waveform at a point of its period
+    ///
+    /// # Arguments
+    ///
+    /// - `phase`: The point of the period, from 0 to 1
+    ///
+    /// # Returns
+    ///
+    /// The value, from -1 to 1
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Sine => (TAU * phase).sin(),
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+        }
+    }
+}
+
+impl PcmFormat {
+    /// Gets the size in bytes of a frame, one sample of every channel
+    ///
+    /// # Returns
+    ///
+    /// The size of a frame
+    fn frame_size(&self) -> usize {
+        self.channels as usize * (self.bits_per_sample as usize / 8)
+    }
+
+    /// Checks that the format can be played
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the format is supported, or an `AudioError` if it is not.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::UnsupportedFormat`: If the channels or bits per sample are not supported
+    fn validate(&self) -> Result<(), AudioError> {
+        let supported = matches!(self.channels, 1 | 2)
+            && matches!(self.bits_per_sample, 8 | 16)
+            && self.sample_rate > 0;
+        supported.then_some(()).ok_or(AudioError::UnsupportedFormat)
+    }
+}
+
+impl FrameConverter {
+    /// Creates a new FrameConverter
+    ///
+    /// # Arguments
+    ///
+    /// - `format`: The format of the clip
+    /// - `output_rate`: The sample rate of the output, in Hz
+    ///
+    /// # Returns
+    ///
+    /// The new FrameConverter
+    fn new(format: PcmFormat, output_rate: u32) -> Self {
+        FrameConverter {
+            format,
+            ratio: format.sample_rate as f64 / output_rate as f64,
+            input_frames: 0,
+            next_output: 0.0,
+        }
+    }
+
+    /// Adds an input frame, pushing the stereo frames it becomes on the output
+    ///
+    /// # Arguments
+    ///
+    /// - `left`: The sample of the left channel, or the only one of a mono clip
+    /// - `right`: The sample of the right channel
+    /// - `out`: Where the interleaved stereo samples are pushed
+    fn push_frame(&mut self, left: i16, right: i16, out: &mut Vec<i16>) {
+        self.input_frames += 1;
+        while self.next_output < self.input_frames as f64 {
+            out.push(left);
+            out.push(right);
+            self.next_output += self.ratio;
+        }
+    }
+
+    /// Decodes the bytes of complete frames
+    ///
+    /// # Arguments
+    ///
+    /// - `bytes`: The frames, in the format of the clip
+    /// - `out`: Where the interleaved stereo samples are pushed
+    fn decode(&mut self, bytes: &[u8], out: &mut Vec<i16>) {
+        let sample_size = self.format.bits_per_sample as usize / 8;
+        for frame in bytes.chunks_exact(self.format.frame_size()) {
+            let mut samples = frame.chunks_exact(sample_size).map(|sample| match sample {
+                [byte] => (*byte as i16 - 128) << 8,
+                [low, high] => i16::from_le_bytes([*low, *high]),
+                _ => 0,
+            });
+            let left = samples.next().unwrap_or(0);
+            let right = samples.next().unwrap_or(left);
+            self.push_frame(left, right, out);
+        }
+    }
+}
+
+impl<'a> AudioPlayer<'a> {
+    /// Creates a new AudioPlayer at full volume
+    ///
+    /// # Arguments
+    ///
+    /// - `output`: The I2SOutput where the audio is played
+    ///
+    /// # Returns
+    ///
+    /// The new AudioPlayer
+    pub fn new(output: I2SOutput<'a>) -> Self {
+        AudioPlayer {
+            output,
+            volume: MAX_VOLUME,
+        }
+    }
+
+    /// Sets the volume of every following playback
+    ///
+    /// # Arguments
+    ///
+    /// - `volume`: The volume, from 0 (mute) to 100 (full scale). Greater values are treated as 100
+    pub fn set_volume(&mut self, volume: u8) {
+        self.volume = volume.min(MAX_VOLUME);
+    }
+
+    /// Gets the volume
+    ///
+    /// # Returns
+    ///
+    /// The volume, from 0 to 100
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    /// Consumes the AudioPlayer, returning its I2SOutput
+    ///
+    /// # Returns
+    ///
+    /// The I2SOutput of the AudioPlayer
+    pub fn into_output(self) -> I2SOutput<'a> {
+        self.output
+    }
+
+    /// Plays a tone
+    ///
+    /// # Arguments
+    ///
+    /// - `frequency_hz`: The frequency of the tone, below half the sample rate of the output
+    /// - `duration`: How long the tone is played
+    /// - `waveform`: The Waveform of the tone
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the tone was played, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::InvalidArg`: If the frequency is not positive or is too high for the output
+    /// - `AudioError::I2SError`: If the samples could not be written
+    pub fn play_tone(
+        &mut self,
+        frequency_hz: f32,
+        duration: Duration,
+        waveform: Waveform,
+    ) -> Result<(), AudioError> {
+        let sample_rate = self.output.sample_rate() as f32;
+        if !(frequency_hz > 0.0 && frequency_hz < sample_rate / 2.0) {
+            return Err(AudioError::InvalidArg);
+        }
+        let step = frequency_hz / sample_rate;
+        let mut phase = 0.0_f32;
+        let samples = (0..self.frames_in(duration)).map(|_| {
+            let sample = (waveform.sample(phase) * i16::MAX as f32) as i16;
+            phase = (phase + step).fract();
+            sample
+        });
+        self.play_mono(samples)
+    }
+
+    /// Plays silence, for example between the tones of an alarm
+    ///
+    /// # Arguments
+    ///
+    /// - `duration`: How long the silence lasts
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the silence was played, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::I2SError`: If the samples could not be written
+    pub fn play_silence(&mut self, duration: Duration) -> Result<(), AudioError> {
+        let frames = self.frames_in(duration);
+        self.play_mono((0..frames).map(|_| 0))
+    }
+
+    /// Plays raw 16 bit PCM samples, such as a clip stored in flash with `include_bytes!` and
+    /// converted to samples
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: The samples, interleaved if the clip is stereo
+    /// - `channels`: 1 for mono or 2 for stereo
+    /// - `sample_rate`: The sample rate of the clip in Hz. If it differs from the output, the clip
+    ///   is resampled
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the clip was played, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::UnsupportedFormat`: If the amount of channels or the sample rate is not supported
+    /// - `AudioError::I2SError`: If the samples could not be written
+    pub fn play_pcm(
+        &mut self,
+        samples: &[i16],
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<(), AudioError> {
+        let format = PcmFormat {
+            channels,
+            sample_rate,
+            bits_per_sample: 16,
+        };
+        format.validate()?;
+        let mut converter = FrameConverter::new(format, self.output.sample_rate());
+        let mut out = Vec::with_capacity(FRAMES_PER_WRITE * 2);
+        for frame in samples.chunks_exact(channels as usize) {
+            let right = frame.get(1).copied().unwrap_or(frame[0]);
+            converter.push_frame(frame[0], right, &mut out);
+            if out.len() >= FRAMES_PER_WRITE * 2 {
+                self.write(&mut out)?;
+            }
+        }
+        self.write(&mut out)
+    }
+
+    /// Plays a WAV clip stored in memory, for example in flash with `include_bytes!`
+    ///
+    /// # Arguments
+    ///
+    /// - `wav`: The bytes of the WAV file
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the clip was played, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::InvalidWav`: If the bytes are not a WAV file
+    /// - `AudioError::UnsupportedFormat`: If the clip is not 8 or 16 bit PCM, mono or stereo
+    /// - `AudioError::I2SError`: If the samples could not be written
+    pub fn play_wav_bytes(&mut self, wav: &[u8]) -> Result<(), AudioError> {
+        let mut reader = wav;
+        let (format, data_len) = read_wav_header(&mut reader)?;
+        let data = &reader[..data_len.min(reader.len())];
+        let mut converter = FrameConverter::new(format, self.output.sample_rate());
+        let mut out = Vec::with_capacity(FRAMES_PER_WRITE * 2);
+        for frames in data.chunks(FRAMES_PER_WRITE * format.frame_size()) {
+            converter.decode(frames, &mut out);
+            self.write(&mut out)?;
+        }
+        Ok(())
+    }
+
+    /// Streams a WAV clip from a reader, such as a file on an SD card. The clip is read on a second
+    /// thread into one of two buffers while the other one is played, so the reads do not cause gaps
+    /// in the audio.
+    ///
+    /// # Arguments
+    ///
+    /// - `reader`: The reader of the WAV file
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the clip was played, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::InvalidWav`: If the file is not a WAV file
+    /// - `AudioError::UnsupportedFormat`: If the clip is not 8 or 16 bit PCM, mono or stereo
+    /// - `AudioError::ReadError`: If the file could not be read
+    /// - `AudioError::I2SError`: If the samples could not be written
+    pub fn play_wav<R: Read + Send>(&mut self, mut reader: R) -> Result<(), AudioError> {
+        let (format, data_len) = read_wav_header(&mut reader)?;
+        let buffer_size = STREAM_BUFFER_SIZE - STREAM_BUFFER_SIZE % format.frame_size();
+        let (filled_sender, filled_receiver) = mpsc::sync_channel::<(Vec<u8>, usize)>(1);
+        let (empty_sender, empty_receiver) = mpsc::channel::<Vec<u8>>();
+        for _ in 0..2 {
+            _ = empty_sender.send(vec![0; buffer_size]);
+        }
+
+        thread::scope(|scope| {
+            let reader = &mut reader;
+            let stream = thread::Builder::new()
+                .stack_size(STREAM_THREAD_STACK_SIZE)
+                .spawn_scoped(scope, move || {
+                    let mut remaining = data_len;
+                    while remaining > 0 {
+                        let Ok(mut buffer) = empty_receiver.recv() else {
+                            return Ok(());
+                        };
+                        let wanted = remaining.min(buffer.len());
+                        let len = read_up_to(reader, &mut buffer[..wanted])
+                            .map_err(|_| AudioError::ReadError)?;
+                        if len == 0 || filled_sender.send((buffer, len)).is_err() {
+                            return Ok(());
+                        }
+                        remaining -= len;
+                    }
+                    Ok(())
+                })
+                .map_err(|_| AudioError::ReadError)?;
+
+            let mut converter = FrameConverter::new(format, self.output.sample_rate());
+            let mut out = Vec::with_capacity(buffer_size);
+            let mut res = Ok(());
+            while let Ok((buffer, len)) = filled_receiver.recv() {
+                converter.decode(&buffer[..len], &mut out);
+                res = self.write(&mut out);
+                if res.is_err() {
+                    break;
+                }
+                // The stream thread ends after reading the last buffer, so it may not take it back
+                _ = empty_sender.send(buffer);
+            }
+            drop(filled_receiver);
+            drop(empty_sender);
+            let stream_res = stream.join().unwrap_or(Err(AudioError::ReadError));
+            res.and(stream_res)
+        })
+    }
+
+    /// Plays mono samples generated by the player
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: The samples to play
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the samples were played, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::I2SError`: If the samples could not be written
+    fn play_mono<I: Iterator<Item = i16>>(&mut self, samples: I) -> Result<(), AudioError> {
+        let mut out = Vec::with_capacity(FRAMES_PER_WRITE * 2);
+        for sample in samples {
+            out.push(sample);
+            out.push(sample);
+            if out.len() >= FRAMES_PER_WRITE * 2 {
+                self.write(&mut out)?;
+            }
+        }
+        self.write(&mut out)
+    }
+
+    /// Applies the volume to the stereo samples and writes them, leaving the vector empty
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: The interleaved stereo samples
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the samples were written, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::I2SError`: If the samples could not be written
+    fn write(&mut self, samples: &mut Vec<i16>) -> Result<(), AudioError> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        if self.volume < MAX_VOLUME {
+            for sample in samples.iter_mut() {
+                *sample = (*sample as i32 * self.volume as i32 / MAX_VOLUME as i32) as i16;
+            }
+        }
+        let res = self.output.write_samples(samples);
+        samples.clear();
+        res.map_err(AudioError::I2SError)
+    }
+
+    /// Gets the amount of frames the output plays in a duration
+    fn frames_in(&self, duration: Duration) -> usize {
+        (duration.as_secs_f64() * self.output.sample_rate() as f64) as usize
+    }
+}
+
+/// Reads the header of a WAV file, leaving the reader at the start of the samples
+///
+/// # Arguments
+///
+/// - `reader`: The reader of the WAV file
+///
+/// # Returns
+///
+/// A `Result` with the format of the samples and the size of the data in bytes, or an
+/// `AudioError` if it fails.
+///
+/// # Errors
+///
+/// - `AudioError::InvalidWav`: If the file is not a WAV file, or ends before the samples
+/// - `AudioError::UnsupportedFormat`: If the clip is not 8 or 16 bit PCM, mono or stereo
+fn read_wav_header<R: Read>(reader: &mut R) -> Result<(PcmFormat, usize), AudioError> {
+    let mut header = [0; WAV_HEADER_SIZE];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| AudioError::InvalidWav)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(AudioError::InvalidWav);
+    }
+
+    let mut format = None;
+    loop {
+        let mut chunk_header = [0; CHUNK_HEADER_SIZE];
+        reader
+            .read_exact(&mut chunk_header)
+            .map_err(|_| AudioError::InvalidWav)?;
+        let size = u32::from_le_bytes([
+            chunk_header[4],
+            chunk_header[5],
+            chunk_header[6],
+            chunk_header[7],
+        ]) as usize;
+
+        match &chunk_header[0..4] {
+            b"fmt " => {
+                let mut fmt = vec![0; size + size % 2];
+                reader
+                    .read_exact(&mut fmt)
+                    .map_err(|_| AudioError::InvalidWav)?;
+                format = Some(parse_fmt_chunk(&fmt)?);
+            }
+            b"data" => {
+                let format = format.ok_or(AudioError::InvalidWav)?;
+                format.validate()?;
+                return Ok((format, size));
+            }
+            _ => {
+                let skip = (size + size % 2) as u64;
+                io::copy(&mut reader.by_ref().take(skip), &mut io::sink())
+                    .map_err(|_| AudioError::InvalidWav)?;
+            }
+        }
+    }
+}
+
+/// Parses the format chunk of a WAV file
+///
+/// # Arguments
+///
+/// - `fmt`: The content of the chunk
+///
+/// # Returns
+///
+/// A `Result` with the PcmFormat, or an `AudioError` if it fails.
+///
+/// # Errors
+///
+/// - `AudioError::InvalidWav`: If the chunk is too short
+/// - `AudioError::UnsupportedFormat`: If the samples are not PCM
+fn parse_fmt_chunk(fmt: &[u8]) -> Result<PcmFormat, AudioError> {
+    if fmt.len() < 16 {
+        return Err(AudioError::InvalidWav);
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([fmt[i], fmt[i + 1]]);
+    let audio_format = u16_at(0);
+    if audio_format != WAV_FORMAT_PCM && audio_format != WAV_FORMAT_EXTENSIBLE {
+        return Err(AudioError::UnsupportedFormat);
+    }
+    Ok(PcmFormat {
+        channels: u16_at(2),
+        sample_rate: u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]),
+        bits_per_sample: u16_at(14),
+    })
+}
+
+/// Reads until the buffer is full or the reader ends
+///
+/// # Returns
+///
+/// A `Result` with the amount of bytes read, or the error of the reader
+fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        match reader.read(&mut buffer[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(len)
+}
+
+impl From<I2SError> for AudioError {
+    /// Creates an AudioError from an I2SError
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: The I2SError to transform
+    ///
+    /// # Returns
+    ///
+    /// The new AudioError
+    fn from(value: I2SError) -> Self {
+        AudioError::I2SError(value)
+    }
+}
@@ -0,0 +1,142 @@
+use super::I2SError;
+use crate::microcontroller_src::peripherals::Peripheral;
+use esp_idf_svc::hal::{
+    delay::BLOCK,
+    gpio::AnyIOPin,
+    i2s::{
+        config::{DataBitWidth, StdConfig},
+        I2sDriver, I2sRx,
+    },
+};
+
+/// Bytes of each stereo frame received, two 32 bit slots
+const FRAME_SIZE: usize = 8;
+
+/// An I2S input driver, that receives samples from a digital microphone such as the INMP441 or the
+/// SPH0645. The microphone must send its samples on the left slot (L/R pin to ground).
+pub struct I2SInput<'a> {
+    driver: I2sDriver<'a, I2sRx>,
+    sample_rate: u32,
+    frames: Vec<u8>,
+}
+
+impl<'a> I2SInput<'a> {
+    /// Creates a new I2S input driver, ready to read samples.
+    ///
+    /// # Arguments
+    ///
+    /// - `bclk_per`: The peripheral pin connected to BCLK (bit clock, also called SCK).
+    /// - `ws_per`: The peripheral pin connected to WS (word select).
+    /// - `din_per`: The peripheral pin connected to the data output of the microphone (SD).
+    /// - `i2s_per`: The I2S peripheral to use. ESP32 C6 only has I2S0.
+    /// - `sample_rate`: The sample rate in Hz, for example 16_000.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `I2SInput` instance, or an `I2SError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `I2SError::PeripheralError`: If a pin or the I2S peripheral is not available.
+    /// - `I2SError::InvalidArg`: If the sample rate is not supported.
+    /// - `I2SError::DriverError`: If there is an error initializing the driver.
+    pub(crate) fn new(
+        bclk_per: Peripheral,
+        ws_per: Peripheral,
+        din_per: Peripheral,
+        i2s_per: Peripheral,
+        sample_rate: u32,
+    ) -> Result<I2SInput<'a>, I2SError> {
+        let bclk = bclk_per
+            .into_any_io_pin()
+            .map_err(I2SError::PeripheralError)?;
+        let ws = ws_per
+            .into_any_io_pin()
+            .map_err(I2SError::PeripheralError)?;
+        let din = din_per
+            .into_any_io_pin()
+            .map_err(I2SError::PeripheralError)?;
+        let i2s = i2s_per.into_i2s0().map_err(I2SError::PeripheralError)?;
+
+        let config = StdConfig::philips(sample_rate, DataBitWidth::Bits32);
+        let mut driver =
+            I2sDriver::new_std_rx(i2s, &config, bclk, din, Option::<AnyIOPin>::None, ws)
+                .map_err(I2SError::from_driver_context)?;
+        driver.rx_enable().map_err(I2SError::from_driver_context)?;
+
+        Ok(I2SInput {
+            driver,
+            sample_rate,
+            frames: vec![],
+        })
+    }
+
+    /// Wraps an I2sDriver already created through esp-idf-hal, in order to use it with the framework.
+    /// The driver must be configured for 32 bit stereo samples and have its channel enabled.
+    ///
+    /// # Arguments
+    ///
+    /// - `driver`: The I2sDriver to wrap
+    /// - `sample_rate`: The sample rate the driver was configured with, in Hz
+    ///
+    /// # Returns
+    ///
+    /// The new `I2SInput` instance
+    pub fn from_raw(driver: I2sDriver<'a, I2sRx>, sample_rate: u32) -> I2SInput<'a> {
+        I2SInput {
+            driver,
+            sample_rate,
+            frames: vec![],
+        }
+    }
+
+    /// Consumes the `I2SInput`, returning the underlying I2sDriver in order to use esp-idf-hal features
+    /// not covered by the framework.
+    ///
+    /// # Returns
+    ///
+    /// The I2sDriver of the `I2SInput`
+    pub fn into_inner(self) -> I2sDriver<'a, I2sRx> {
+        self.driver
+    }
+
+    /// Gets the sample rate of the input
+    ///
+    /// # Returns
+    ///
+    /// The sample rate in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Reads mono samples, blocking until at least one is received. The 24 or 32 bit samples of
+    /// the microphone are reduced to 16 bits.
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: Where the samples are stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of samples read, or an `I2SError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `I2SError::ReadError`: If the samples could not be read
+    pub fn read_samples(&mut self, samples: &mut [i16]) -> Result<usize, I2SError> {
+        self.frames.resize(samples.len() * FRAME_SIZE, 0);
+        let len = self
+            .driver
+            .read(&mut self.frames, BLOCK)
+            .map_err(|_| I2SError::ReadError)?;
+
+        let frames = self.frames[..len].chunks_exact(FRAME_SIZE);
+        let read = frames.len();
+        for (sample, frame) in samples.iter_mut().zip(frames) {
+            let left = i32::from_le_bytes([frame[0], frame[1], frame[2], frame[3]]);
+            *sample = (left >> 16) as i16;
+        }
+        Ok(read)
+    }
+}
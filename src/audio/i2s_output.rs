@@ -0,0 +1,160 @@
+use crate::microcontroller_src::peripherals::{Peripheral, PeripheralError};
+use esp_idf_svc::{
+    hal::{
+        delay::BLOCK,
+        gpio::AnyIOPin,
+        i2s::{
+            config::{DataBitWidth, StdConfig},
+            I2sDriver, I2sTx,
+        },
+    },
+    sys::{EspError, ESP_ERR_INVALID_ARG},
+};
+
+/// Error types related to I2S operations.
+#[derive(Debug)]
+pub enum I2SError {
+    DriverError,
+    InvalidArg,
+    PeripheralError(PeripheralError),
+    ReadError,
+    WriteError,
+}
+
+/// An I2S output driver, that sends 16 bit stereo samples in the standard (Philips) format to an
+/// external DAC or amplifier, such as the MAX98357A or the PCM5102.
+pub struct I2SOutput<'a> {
+    driver: I2sDriver<'a, I2sTx>,
+    sample_rate: u32,
+}
+
+impl<'a> I2SOutput<'a> {
+    /// Creates a new I2S output driver, ready to write samples.
+    ///
+    /// # Arguments
+    ///
+    /// - `bclk_per`: The peripheral pin connected to BCLK (bit clock).
+    /// - `ws_per`: The peripheral pin connected to WS (word select, also called LRCLK).
+    /// - `dout_per`: The peripheral pin connected to DIN of the DAC.
+    /// - `i2s_per`: The I2S peripheral to use. ESP32 C6 only has I2S0.
+    /// - `sample_rate`: The sample rate in Hz, for example 16_000 or 44_100.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `I2SOutput` instance, or an `I2SError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `I2SError::PeripheralError`: If a pin or the I2S peripheral is not available.
+    /// - `I2SError::InvalidArg`: If the sample rate is not supported.
+    /// - `I2SError::DriverError`: If there is an error initializing the driver.
+    pub(crate) fn new(
+        bclk_per: Peripheral,
+        ws_per: Peripheral,
+        dout_per: Peripheral,
+        i2s_per: Peripheral,
+        sample_rate: u32,
+    ) -> Result<I2SOutput<'a>, I2SError> {
+        let bclk = bclk_per
+            .into_any_io_pin()
+            .map_err(I2SError::PeripheralError)?;
+        let ws = ws_per
+            .into_any_io_pin()
+            .map_err(I2SError::PeripheralError)?;
+        let dout = dout_per
+            .into_any_io_pin()
+            .map_err(I2SError::PeripheralError)?;
+        let i2s = i2s_per.into_i2s0().map_err(I2SError::PeripheralError)?;
+
+        let config = StdConfig::philips(sample_rate, DataBitWidth::Bits16);
+        let mut driver =
+            I2sDriver::new_std_tx(i2s, &config, bclk, dout, Option::<AnyIOPin>::None, ws)
+                .map_err(I2SError::from_driver_context)?;
+        driver.tx_enable().map_err(I2SError::from_driver_context)?;
+
+        Ok(I2SOutput {
+            driver,
+            sample_rate,
+        })
+    }
+
+    /// Wraps an I2sDriver already created through esp-idf-hal, in order to use it with the framework.
+    /// The driver must be configured for 16 bit stereo samples and have its channel enabled.
+    ///
+    /// # Arguments
+    ///
+    /// - `driver`: The I2sDriver to wrap
+    /// - `sample_rate`: The sample rate the driver was configured with, in Hz
+    ///
+    /// # Returns
+    ///
+    /// The new `I2SOutput` instance
+    pub fn from_raw(driver: I2sDriver<'a, I2sTx>, sample_rate: u32) -> I2SOutput<'a> {
+        I2SOutput {
+            driver,
+            sample_rate,
+        }
+    }
+
+    /// Consumes the `I2SOutput`, returning the underlying I2sDriver in order to use esp-idf-hal features
+    /// not covered by the framework.
+    ///
+    /// # Returns
+    ///
+    /// The I2sDriver of the `I2SOutput`
+    pub fn into_inner(self) -> I2sDriver<'a, I2sTx> {
+        self.driver
+    }
+
+    /// Gets the sample rate of the output
+    ///
+    /// # Returns
+    ///
+    /// The sample rate in Hz
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Writes interleaved stereo samples (left, right, left, right, ...), blocking until every sample
+    /// was queued on the DMA buffers.
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: The samples to write
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if every sample was written, or an `I2SError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `I2SError::WriteError`: If the samples could not be written
+    pub fn write_samples(&mut self, samples: &[i16]) -> Result<(), I2SError> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        self.driver
+            .write_all(&bytes, BLOCK)
+            .map_err(|_| I2SError::WriteError)
+    }
+}
+
+impl I2SError {
+    /// Creates a new I2SError from an EspError.
+    ///
+    /// # Arguments
+    ///
+    /// - `error`: Source code.
+    ///
+    /// # Returns
+    ///
+    /// The I2SError instance that corresponds to the EspError received
+    pub fn from_driver_context(error: EspError) -> Self {
+        match error.code() {
+            ESP_ERR_INVALID_ARG => I2SError::InvalidArg,
+            _ => I2SError::DriverError,
+        }
+    }
+}
@@ -0,0 +1,9 @@
+mod audio_player;
+mod i2s_input;
+mod i2s_output;
+mod sound_meter;
+
+pub use audio_player::*;
+pub use i2s_input::*;
+pub use i2s_output::*;
+pub use sound_meter::*;
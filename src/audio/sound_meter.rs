@@ -0,0 +1,400 @@
+use super::{AudioError, I2SInput};
+use crate::gpio::analog::AnalogIn;
+use std::time::{Duration, Instant};
+
+const DEFAULT_WINDOW: Duration = Duration::from_millis(50);
+/// Middle value of the 12 bit readings of the ADC, where an analog microphone is biased
+const ADC_MIDPOINT: i32 = 2048;
+/// Shift that takes a 12 bit reading to the 16 bit range
+const ADC_TO_16_BITS: u32 = 4;
+/// Fraction of the threshold the level must go under before a threshold callback can fire again
+const THRESHOLD_HYSTERESIS: f32 = 0.8;
+
+type LevelCallback<'a> = dyn FnMut(SoundLevel) + 'a;
+
+/// Source of microphone samples used by a SoundMeter
+pub trait SoundSource {
+    /// Gets the sample rate of the source
+    ///
+    /// # Returns
+    ///
+    /// The sample rate in Hz
+    fn sample_rate(&self) -> u32;
+
+    /// Reads mono samples, blocking until at least one is available
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: Where the samples are stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of samples read, or an `AudioError` if it fails.
+    fn read_samples(&mut self, samples: &mut [i16]) -> Result<usize, AudioError>;
+}
+
+/// Enums the measures of a window a threshold can be set on:
+/// * `Rms`: The root mean square, that follows the loudness perceived.
+/// * `Peak`: The greatest absolute sample, that follows short sounds such as knocks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LevelMetric {
+    Rms,
+    Peak,
+}
+
+/// Levels of a window of samples, from 0 (silence) to 1 (full scale):
+/// * `rms`: The root mean square of the samples.
+/// * `peak`: The greatest absolute sample.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SoundLevel {
+    pub rms: f32,
+    pub peak: f32,
+}
+
+/// Analog microphone module, such as the MAX4466 or MAX9814, read through the ADC at a fixed
+/// sample rate. Only suitable for metering, since the ADC is read one sample at a time.
+pub struct AnalogMicrophone<'a> {
+    analog_in: AnalogIn<'a>,
+    sample_rate: u32,
+    period: Duration,
+    next_sample: Instant,
+}
+
+/// Callback executed when a measure of the level goes over a threshold
+struct ThresholdCallback<'a> {
+    metric: LevelMetric,
+    threshold: f32,
+    armed: bool,
+    callback: Box<LevelCallback<'a>>,
+}
+
+/// Detects two claps in a row: two windows whose peak goes over the threshold after a quiet window,
+/// separated by a gap between the min and the max.
+struct DoubleClapDetector<'a> {
+    threshold: f32,
+    min_gap: Duration,
+    max_gap: Duration,
+    was_loud: bool,
+    last_clap: Option<Duration>,
+    callback: Box<dyn FnMut() + 'a>,
+}
+
+/// Processing layer over a microphone, that measures the RMS and peak levels of the sound in fixed
+/// windows and executes callbacks when they go over thresholds, or when two claps are heard.
+///
+/// Each call to [Self::poll] reads one window from the source, blocking for its duration, so it
+/// is usually called in the main loop or from its own thread.
+pub struct SoundMeter<'a, S: SoundSource> {
+    source: S,
+    samples: Vec<i16>,
+    window: Duration,
+    elapsed: Duration,
+    level: SoundLevel,
+    thresholds: Vec<ThresholdCallback<'a>>,
+    double_clap: Option<DoubleClapDetector<'a>>,
+}
+
+impl SoundLevel {
+    /// Gets a measure of the level
+    ///
+    /// # Arguments
+    ///
+    /// - `metric`: The LevelMetric to get
+    ///
+    /// # Returns
+    ///
+    /// The level, from 0 to 1
+    pub fn get(&self, metric: LevelMetric) -> f32 {
+        match metric {
+            LevelMetric::Rms => self.rms,
+            LevelMetric::Peak => self.peak,
+        }
+    }
+
+    /// Gets the RMS level in decibels relative to full scale
+    ///
+    /// # Returns
+    ///
+    /// The level in dBFS, 0 at full scale and negative below it
+    pub fn rms_dbfs(&self) -> f32 {
+        to_dbfs(self.rms)
+    }
+
+    /// Gets the peak level in decibels relative to full scale
+    ///
+    /// # Returns
+    ///
+    /// The level in dBFS, 0 at full scale and negative below it
+    pub fn peak_dbfs(&self) -> f32 {
+        to_dbfs(self.peak)
+    }
+
+    /// Measures the levels of a window of samples, removing its DC offset
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: The samples of the window
+    ///
+    /// # Returns
+    ///
+    /// The SoundLevel of the window
+    fn measure(samples: &[i16]) -> Self {
+        if samples.is_empty() {
+            return SoundLevel::default();
+        }
+        let mean = samples.iter().map(|s| *s as f32).sum::<f32>() / samples.len() as f32;
+        let (mut squares, mut peak) = (0.0_f32, 0.0_f32);
+        for sample in samples {
+            let value = (*sample as f32 - mean) / i16::MAX as f32;
+            squares += value * value;
+            peak = peak.max(value.abs());
+        }
+        SoundLevel {
+            rms: (squares / samples.len() as f32).sqrt().min(1.0),
+            peak: peak.min(1.0),
+        }
+    }
+}
+
+impl<'a> AnalogMicrophone<'a> {
+    /// Creates a new AnalogMicrophone
+    ///
+    /// # Arguments
+    ///
+    /// - `analog_in`: The AnalogIn of the pin connected to the output of the microphone
+    /// - `sample_rate`: The sample rate in Hz. A few kHz are enough for metering
+    ///
+    /// # Returns
+    ///
+    /// The new AnalogMicrophone
+    pub fn new(analog_in: AnalogIn<'a>, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate.max(1);
+        AnalogMicrophone {
+            analog_in,
+            sample_rate,
+            period: Duration::from_secs(1) / sample_rate,
+            next_sample: Instant::now(),
+        }
+    }
+
+    /// Consumes the AnalogMicrophone, returning its AnalogIn
+    ///
+    /// # Returns
+    ///
+    /// The AnalogIn of the AnalogMicrophone
+    pub fn into_inner(self) -> AnalogIn<'a> {
+        self.analog_in
+    }
+}
+
+impl<'a> SoundSource for AnalogMicrophone<'a> {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn read_samples(&mut self, samples: &mut [i16]) -> Result<usize, AudioError> {
+        self.next_sample = self.next_sample.max(Instant::now());
+        for sample in samples.iter_mut() {
+            while Instant::now() < self.next_sample {
+                std::hint::spin_loop();
+            }
+            let raw = self
+                .analog_in
+                .read_raw()
+                .map_err(|_| AudioError::ReadError)?;
+            *sample = ((raw as i32 - ADC_MIDPOINT) << ADC_TO_16_BITS) as i16;
+            self.next_sample += self.period;
+        }
+        Ok(samples.len())
+    }
+}
+
+impl<'a> SoundSource for I2SInput<'a> {
+    fn sample_rate(&self) -> u32 {
+        I2SInput::sample_rate(self)
+    }
+
+    fn read_samples(&mut self, samples: &mut [i16]) -> Result<usize, AudioError> {
+        I2SInput::read_samples(self, samples).map_err(AudioError::I2SError)
+    }
+}
+
+impl<'a> ThresholdCallback<'a> {
+    /// Executes the callback if the level went over the threshold. It fires again only after the
+    /// level goes under 80% of the threshold.
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The SoundLevel of the last window
+    fn handle(&mut self, level: SoundLevel) {
+        let value = level.get(self.metric);
+        if self.armed && value >= self.threshold {
+            self.armed = false;
+            (self.callback)(level);
+        } else if value < self.threshold * THRESHOLD_HYSTERESIS {
+            self.armed = true;
+        }
+    }
+}
+
+impl<'a> DoubleClapDetector<'a> {
+    /// Executes the callback if the window completes a double clap
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The SoundLevel of the window
+    /// - `now`: Time of the window since the meter started
+    fn handle(&mut self, level: SoundLevel, now: Duration) {
+        let loud = level.peak >= self.threshold;
+        let onset = loud && !self.was_loud;
+        self.was_loud = loud;
+        if !onset {
+            return;
+        }
+
+        match self.last_clap.map(|last| now - last) {
+            Some(gap) if gap >= self.min_gap && gap <= self.max_gap => {
+                self.last_clap = None;
+                (self.callback)();
+            }
+            Some(gap) if gap < self.min_gap => {}
+            _ => self.last_clap = Some(now),
+        }
+    }
+}
+
+impl<'a, S: SoundSource> SoundMeter<'a, S> {
+    /// Creates a new SoundMeter with windows of 50ms
+    ///
+    /// # Arguments
+    ///
+    /// - `source`: The SoundSource of the samples, such as an I2SInput or an AnalogMicrophone
+    ///
+    /// # Returns
+    ///
+    /// The new SoundMeter
+    pub fn new(source: S) -> Self {
+        let mut meter = SoundMeter {
+            source,
+            samples: vec![],
+            window: DEFAULT_WINDOW,
+            elapsed: Duration::ZERO,
+            level: SoundLevel::default(),
+            thresholds: vec![],
+            double_clap: None,
+        };
+        meter.set_window(DEFAULT_WINDOW);
+        meter
+    }
+
+    /// Sets the duration of the windows the levels are measured on. Shorter windows react faster
+    /// but the levels are less stable.
+    ///
+    /// # Arguments
+    ///
+    /// - `window`: The duration of each window
+    pub fn set_window(&mut self, window: Duration) {
+        let len = (window.as_secs_f64() * self.source.sample_rate() as f64) as usize;
+        self.samples = vec![0; len.max(1)];
+        self.window = window;
+    }
+
+    /// Sets a callback executed when a measure of the level goes over a threshold. It is executed
+    /// once each time the threshold is crossed, and fires again only after the level goes under 80%
+    /// of the threshold.
+    ///
+    /// # Arguments
+    ///
+    /// - `metric`: The LevelMetric compared
+    /// - `threshold`: The threshold, from 0 to 1
+    /// - `callback`: A closure that receives the SoundLevel of the window
+    pub fn on_threshold<C: FnMut(SoundLevel) + 'a>(
+        &mut self,
+        metric: LevelMetric,
+        threshold: f32,
+        callback: C,
+    ) {
+        self.thresholds.push(ThresholdCallback {
+            metric,
+            threshold,
+            armed: true,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Sets the callback executed when two claps are heard in a row
+    ///
+    /// # Arguments
+    ///
+    /// - `threshold`: The peak level, from 0 to 1, a clap must reach
+    /// - `min_gap`: The min time between both claps, so the echo of a clap is not taken as another one
+    /// - `max_gap`: The max time between both claps
+    /// - `callback`: A closure executed after the second clap
+    pub fn on_double_clap<C: FnMut() + 'a>(
+        &mut self,
+        threshold: f32,
+        min_gap: Duration,
+        max_gap: Duration,
+        callback: C,
+    ) {
+        self.double_clap = Some(DoubleClapDetector {
+            threshold,
+            min_gap,
+            max_gap,
+            was_loud: false,
+            last_clap: None,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Reads a window from the source, measures its levels and executes the callbacks of the
+    /// thresholds crossed. Blocks for the duration of the window.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the SoundLevel of the window, or an `AudioError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AudioError::ReadError`: If the samples could not be read from an AnalogMicrophone
+    /// - `AudioError::I2SError`: If the samples could not be read from an I2SInput
+    pub fn poll(&mut self) -> Result<SoundLevel, AudioError> {
+        let mut filled = 0;
+        while filled < self.samples.len() {
+            filled += self.source.read_samples(&mut self.samples[filled..])?;
+        }
+        self.level = SoundLevel::measure(&self.samples);
+        self.elapsed += self.window;
+
+        for threshold in &mut self.thresholds {
+            threshold.handle(self.level);
+        }
+        if let Some(detector) = self.double_clap.as_mut() {
+            detector.handle(self.level, self.elapsed);
+        }
+        Ok(self.level)
+    }
+
+    /// Gets the levels of the last window read
+    ///
+    /// # Returns
+    ///
+    /// The SoundLevel of the last window
+    pub fn level(&self) -> SoundLevel {
+        self.level
+    }
+
+    /// Consumes the SoundMeter, returning its source
+    ///
+    /// # Returns
+    ///
+    /// The SoundSource of the SoundMeter
+    pub fn into_source(self) -> S {
+        self.source
+    }
+}
+
+/// Converts a level from 0 to 1 to decibels relative to full scale
+fn to_dbfs(level: f32) -> f32 {
+    20.0 * level.max(f32::MIN_POSITIVE).log10()
+}
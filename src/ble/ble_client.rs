@@ -1,20 +1,46 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use esp32_nimble::{BLEClient, BLEDevice, BLEScan};
-use esp_idf_svc::hal::task::block_on;
+use esp32_nimble::{BLEAddress, BLEClient, BLEDevice, BLEScan};
+use esp_idf_svc::{
+    hal::task::block_on,
+    timer::{EspTaskTimerService, EspTimer},
+};
+use futures::FutureExt;
 const BLOCK: i32 = i32::MAX;
 const MS_BETWEEN_SCANS: u16 = 100;
+/// Amount of addresses remembered by the throttling of a background scan before forgetting the
+/// ones whose window already ended
+const MAX_THROTTLED_ADDRESSES: usize = 256;
 
 use crate::{
+    sensors::DateTime,
     utils::{
         auxiliary::{SharableRef, SharableRefExt},
         esp32_framework_error::Esp32FrameworkError,
         notification::Notifier,
+        queue_config::{queue_capacities, record_dropped_item},
     },
     InterruptDriver,
 };
 
-use super::utils::{BleAdvertisedDevice, BleError, BleId, RemoteCharacteristic};
+use super::utils::{
+    ble_standard_uuids::{StandardCharacteristicId, StandardServiceId},
+    own_address, AdjustReason, BleAdvertisedDevice, BleError, BleId, ConnectionInformation,
+    CurrentTimeService, OwnAddressType, PairingCallbacks, ReconnectPolicy, RemoteCharacteristic,
+    RemoteService, ScanFilter, ScanPolicy, Security, TxPower, TxPowerTarget,
+};
+use super::{
+    ble_client_connection::{BleClientConnection, ConnectionUpdater},
+    L2capChannel,
+};
 
 /// Driver responsible for handling the client-end of ble connections. Can be used to read, write or notify
 /// on characteristics of services of connected clients
@@ -22,13 +48,45 @@ struct _BleClient {
     ble_client: BLEClient,
     ble_scan: &'static mut BLEScan,
     connected: bool,
+    last_address: Option<BLEAddress>,
     time_between_scans: u16,
+    own_address_type: OwnAddressType,
+    discovered_services: Option<Vec<RemoteService>>,
+    service_changed: Option<Arc<AtomicBool>>,
     notifier: Notifier,
 }
 
-#[derive(Default)]
+/// State of the automatic reconnection of a BleClient:
+/// - `policy`: The ReconnectPolicy followed.
+/// - `link_lost`: Set by the BLE task when the link drops.
+/// - `attempt_due`: Set by the timer when the backoff of the next attempt ends.
+/// - `timer`: Timer used to wait the backoff.
+/// - `attempts`: Amount of failed attempts since the link dropped.
+struct Reconnection {
+    policy: ReconnectPolicy,
+    link_lost: Arc<AtomicBool>,
+    attempt_due: Arc<AtomicBool>,
+    timer: EspTimer<'static>,
+    attempts: u32,
+    on_reconnected: Option<Box<dyn FnMut()>>,
+    on_gave_up: Option<Box<dyn FnMut()>>,
+}
+
+/// State of a background scan of a BleClient:
+/// - `results`: Devices found by the scan, waiting for the callback.
+/// - `callback`: Callback executed on the update loop for each device found.
+struct BackgroundScan {
+    results: Arc<Mutex<VecDeque<BleAdvertisedDevice>>>,
+    callback: Box<dyn FnMut(BleAdvertisedDevice)>,
+}
+
 struct BleClientUpdater {
     remote_characteristics: HashMap<BleId, RemoteCharacteristic>,
+    pairing: PairingCallbacks<'static>,
+    reconnection: Option<Reconnection>,
+    connections: Vec<SharableRef<ConnectionUpdater>>,
+    background_scan: Option<BackgroundScan>,
+    on_service_changed: Option<Box<dyn FnMut(Vec<RemoteService>)>>,
 }
 
 impl BleClientUpdater {
@@ -38,6 +96,19 @@ impl BleClientUpdater {
     }
 }
 
+impl BackgroundScan {
+    /// Executes the callback for every device found since the last update
+    fn handle_results(&mut self) {
+        loop {
+            let device = self.results.lock().unwrap().pop_front();
+            match device {
+                Some(device) => (self.callback)(device),
+                None => break,
+            }
+        }
+    }
+}
+
 /// Driver responsible for handling the client-end of ble connections. Can be used to read, write or notify
 /// on characteristics of services of connected clients
 pub struct BleClient {
@@ -61,7 +132,11 @@ impl _BleClient {
             ble_client: BLEClient::new(),
             ble_scan: ble_device.get_scan(),
             connected: false,
+            last_address: None,
             time_between_scans: MS_BETWEEN_SCANS,
+            own_address_type: OwnAddressType::Public,
+            discovered_services: None,
+            service_changed: None,
             notifier,
         }
     }
@@ -110,7 +185,7 @@ impl _BleClient {
 
         match device {
             Some(device) => Ok(BleAdvertisedDevice::from(&device)),
-            None => Err(BleError::DeviceNotFound),
+            None => Err(BleError::DeviceNotFound(None)),
         }
     }
 
@@ -182,6 +257,57 @@ impl _BleClient {
             .await
     }
 
+    /// Blocking method that scans for a given duration and returns every device found that fulfills
+    /// the filter. Each device is returned only once.
+    ///
+    /// # Arguments
+    ///
+    /// - `duration`: The duration of the scan
+    /// - `filter`: A `&ScanFilter` with the conditions a device must fulfill to be returned. If the filter
+    /// stops on the first match, the scan ends as soon as a device fulfills it.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with a vector of the BleAdvertisedDevices that fulfill the filter, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: on errors of the scan
+    pub fn scan(
+        &mut self,
+        duration: Duration,
+        filter: &ScanFilter,
+    ) -> Result<Vec<BleAdvertisedDevice>, BleError> {
+        block_on(self.scan_async(duration, filter))
+    }
+
+    /// Non blocking async version of [Self::scan]
+    pub async fn scan_async(
+        &mut self,
+        duration: Duration,
+        filter: &ScanFilter,
+    ) -> Result<Vec<BleAdvertisedDevice>, BleError> {
+        self._start_scan();
+        let duration = duration.as_millis().min(i32::MAX as u128) as i32;
+        let found: Mutex<Vec<BleAdvertisedDevice>> = Mutex::new(vec![]);
+
+        self.ble_scan
+            .find_device(duration, |adv| {
+                let device = BleAdvertisedDevice::from(adv);
+                if !filter.matches(&device) {
+                    return false;
+                }
+                let mut found = found.lock().unwrap();
+                if !found.iter().any(|d| d.addr() == device.addr()) {
+                    found.push(device);
+                }
+                filter.stops_on_first_match()
+            })
+            .await?;
+
+        Ok(found.into_inner().unwrap())
+    }
+
     /// Blocking method that attempts to connect to a device.
     ///
     /// # Arguments
@@ -219,9 +345,89 @@ impl _BleClient {
             .await
             .map_err(BleError::from_connection_context)?;
         self.connected = true;
+        self.discovered_services = None;
+        self.last_address = Some(*device.addr());
+        _ = self.subscribe_service_changed_async().await;
         Ok(())
     }
 
+    /// Attempts to connect again to the last device the client connected to
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the connection was established, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::DeviceNotFound`: If the client never connected to a device, or it was not found
+    /// - `BleError::Code`: on other errors
+    async fn reconnect_async(&mut self) -> Result<(), BleError> {
+        let address = self.last_address.ok_or(BleError::DeviceNotFound(None))?;
+        self.ble_client
+            .connect(&address)
+            .await
+            .map_err(BleError::from_connection_context)?;
+        self.connected = true;
+        self.discovered_services = None;
+        _ = self.subscribe_service_changed_async().await;
+        Ok(())
+    }
+
+    /// Subscribes to the Service Changed indications of the device, if a callback was set with
+    /// [BleClient::on_service_changed]. Devices without the characteristic in their GATT service
+    /// never change their services, so they are skipped.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the client subscribed or there was nothing to subscribe to, or a
+    /// `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the connection dropped
+    /// - `BleError::Code`: If the device rejected the subscription
+    async fn subscribe_service_changed_async(&mut self) -> Result<(), BleError> {
+        let Some(changed) = self.service_changed.clone() else {
+            return Ok(());
+        };
+        let service_id = BleId::from_standard_service(StandardServiceId::GATT);
+        let characteristic_id =
+            BleId::from_standard_characteristic(StandardCharacteristicId::ServiceChanged);
+        let Ok(service) = self.ble_client.get_service(service_id.to_uuid()).await else {
+            return Ok(());
+        };
+        let Ok(characteristic) = service
+            .get_characteristic(characteristic_id.to_uuid())
+            .await
+        else {
+            return Ok(());
+        };
+        let notifier = self.notifier.clone();
+        characteristic.on_notify(move |_| {
+            changed.store(true, Ordering::SeqCst);
+            notifier.notify();
+        });
+        characteristic.subscribe_indicate(false).await?;
+        Ok(())
+    }
+
+    /// Checks whether the device indicated that its services changed since the last check, and
+    /// if so forgets the discovered services, since their handles may no longer be valid
+    ///
+    /// # Returns
+    ///
+    /// True if the services changed, False if not
+    fn take_service_changed(&mut self) -> bool {
+        let changed = self
+            .service_changed
+            .as_ref()
+            .is_some_and(|changed| changed.swap(false, Ordering::SeqCst));
+        if changed {
+            self.discovered_services = None;
+        }
+        changed
+    }
+
     /// Blocking method that attempts to get all service ids of a given of the current connection
     ///
     /// # Returns
@@ -239,6 +445,9 @@ impl _BleClient {
     /// Non blocking async version of [BleClient::get_all_service_ids]
     pub async fn get_all_service_ids_async(&mut self) -> Result<Vec<BleId>, BleError> {
         self.is_connected()?;
+        if let Some(services) = &self.discovered_services {
+            return Ok(services.iter().map(|service| service.id.clone()).collect());
+        }
         let remote_services = self.ble_client.get_services().await?;
         let services = remote_services
             .map(|remote_service| BleId::from(remote_service.uuid()))
@@ -253,19 +462,14 @@ impl _BleClient {
         characteristic_id: &BleId,
     ) -> Result<RemoteCharacteristic, BleError> {
         self.is_connected()?;
-        let remote_service = self
-            .ble_client
-            .get_service(service_id.to_uuid())
-            .await
-            .map_err(BleError::from_service_context)?;
-        let remote_characteristic = remote_service
-            .get_characteristic(characteristic_id.to_uuid())
-            .await
-            .map_err(BleError::from_characteristic_context)?;
-        Ok(RemoteCharacteristic::new(
-            remote_characteristic,
-            self.notifier.clone(),
-        ))
+        get_remote_characteristic(
+            &mut self.ble_client,
+            self.discovered_services.as_deref(),
+            &self.notifier,
+            service_id,
+            characteristic_id,
+        )
+        .await
     }
 
     /// Inner version of [BleClient::get_all_characteristics_async]
@@ -274,19 +478,89 @@ impl _BleClient {
         service_id: &BleId,
     ) -> Result<Vec<RemoteCharacteristic>, BleError> {
         self.is_connected()?;
-        let remote_service = self
-            .ble_client
-            .get_service(service_id.to_uuid())
+        get_remote_characteristics(
+            &mut self.ble_client,
+            self.discovered_services.as_deref(),
+            &self.notifier,
+            service_id,
+        )
+        .await
+    }
+
+    /// Inner version of [BleClient::get_services_async]. Discovers every service and characteristic
+    /// of the device the first time it is called on a connection, and then answers from the cache.
+    async fn _get_services_async(&mut self) -> Result<Vec<RemoteService>, BleError> {
+        self.is_connected()?;
+        if self.discovered_services.is_none() {
+            let discovered = discover_services(&mut self.ble_client, &self.notifier).await?;
+            self.discovered_services = Some(discovered);
+        }
+        let services = self.discovered_services.as_ref().unwrap();
+        Ok(services.iter().map(|service| service.clone()).collect())
+    }
+
+    /// Blocking method that reads the time of the Current Time Service of the current connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `DateTime` of the server or `BleError` if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if there is no connection stablished to go look for a service
+    /// - `BleError::ServiceNotFound`: if the device does not have a Current Time Service
+    /// - `BleError::CharacteristicNotFound`: if the service does not have a Current Time characteristic
+    /// - `BleError::InvalidParameters`: if the server answered with an invalid time
+    /// - `BleError::Code`: on other errors
+    pub fn get_current_time(&mut self) -> Result<DateTime, BleError> {
+        block_on(self.get_current_time_async())
+    }
+
+    /// Non blocking async version of [Self::get_current_time]
+    pub async fn get_current_time_async(&mut self) -> Result<DateTime, BleError> {
+        let mut characteristic = self
+            ._get_characteristic_async(
+                &CurrentTimeService::id(),
+                &CurrentTimeService::characteristic_id(),
+            )
+            .await?;
+        let data = characteristic.read_async().await?;
+        CurrentTimeService::from_bytes(&data)
+    }
+
+    /// Blocking method that sets the time of the Current Time Service of the current connection.
+    ///
+    /// # Arguments
+    ///
+    /// - `date_time`: The time to set on the server
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` if the time was written or `BleError` if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if there is no connection stablished to go look for a service
+    /// - `BleError::ServiceNotFound`: if the device does not have a Current Time Service
+    /// - `BleError::CharacteristicNotFound`: if the service does not have a Current Time characteristic
+    /// - `BleError::CharacteristicNotWritable`: if the server does not allow setting its time
+    /// - `BleError::Code`: on other errors
+    pub fn set_current_time(&mut self, date_time: &DateTime) -> Result<(), BleError> {
+        block_on(self.set_current_time_async(date_time))
+    }
+
+    /// Non blocking async version of [Self::set_current_time]
+    pub async fn set_current_time_async(&mut self, date_time: &DateTime) -> Result<(), BleError> {
+        let mut characteristic = self
+            ._get_characteristic_async(
+                &CurrentTimeService::id(),
+                &CurrentTimeService::characteristic_id(),
+            )
+            .await?;
+        characteristic
+            .write_async(&CurrentTimeService::to_bytes(
+                date_time,
+                AdjustReason::ManualTimeUpdate,
+            ))
             .await
-            .map_err(BleError::from_service_context)?;
-        let remote_characteristics = remote_service
-            .get_characteristics()
-            .await?
-            .map(|remote_characteristic| {
-                RemoteCharacteristic::new(remote_characteristic, self.notifier.clone())
-            })
-            .collect();
-        Ok(remote_characteristics)
     }
 
     /// Sets the amount of ms for between scans
@@ -294,6 +568,62 @@ impl _BleClient {
         self.time_between_scans = ms_between_scans
     }
 
+    /// Sets whether scans only process the advertisements of the devices on the whitelist. The whitelist
+    /// is shared by the whole BLE stack. By default every advertisement is processed.
+    ///
+    /// # Arguments
+    ///
+    /// - `policy`: The ScanPolicy to use
+    pub fn set_scan_filter_policy(&mut self, policy: ScanPolicy) {
+        self.ble_scan.filter_policy(policy.get_code());
+    }
+
+    /// Gets the address of the client. If the address type is `ResolvablePrivate` it is the identity
+    /// address, since the private address used changes periodically.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the BLEAddress of the client, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the address could not be read from the controller
+    pub fn address(&self) -> Result<BLEAddress, BleError> {
+        own_address(&self.own_address_type)
+    }
+
+    /// Gets the type of the address the client scans and connects with
+    ///
+    /// # Returns
+    ///
+    /// The OwnAddressType in use, `Public` unless it was changed
+    pub fn address_type(&self) -> OwnAddressType {
+        self.own_address_type
+    }
+
+    /// Sets the type of the address the client scans and connects with. It is used from the next
+    /// scan or connection on. A `NonResolvablePrivate` address only allows scanning, and the
+    /// rotation interval of the `ResolvablePrivate` address is set with the
+    /// `CONFIG_BT_NIMBLE_RPA_TIMEOUT` option of the sdkconfig.
+    ///
+    /// # Arguments
+    ///
+    /// - `address_type`: The new OwnAddressType
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the address type was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the given random static address is not valid
+    /// - `BleError::Code`: If the controller rejected the random address
+    pub fn set_address_type(&mut self, address_type: OwnAddressType) -> Result<(), BleError> {
+        address_type.apply(BLEDevice::take())?;
+        self.own_address_type = address_type;
+        Ok(())
+    }
+
     /// Starts a scan in order to find devices to connect to
     fn _start_scan(&mut self) {
         self.ble_scan
@@ -335,9 +665,146 @@ impl _BleClient {
             .map_err(BleError::from_connection_params_context)
     }
 
+    /// Reads the signal strength of the current connection
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the RSSI in dBm, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: if there is no connection stablished
+    /// - `BleError::Code`: on other errors
+    pub fn read_rssi(&mut self) -> Result<i8, BleError> {
+        self.is_connected()?;
+        self.ble_client.get_rssi().map_err(BleError::from)
+    }
+
+    /// Sets the transmission power of the current connection, for example to keep a long range
+    /// link without raising the default power
+    ///
+    /// # Arguments
+    ///
+    /// - `tx_power`: The TxPower to use
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the power was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: if there is no connection stablished
+    /// - `BleError::Code`: if the controller rejects the level
+    pub fn set_tx_power(&mut self, tx_power: TxPower) -> Result<(), BleError> {
+        self.is_connected()?;
+        TxPowerTarget::Connection(self.ble_client.conn_handle()).set(tx_power)
+    }
+
+    /// Gets the transmission power of the current connection
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the TxPower, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: if there is no connection stablished
+    /// - `BleError::NotFound`: if the controller could not inform the level
+    pub fn tx_power(&mut self) -> Result<TxPower, BleError> {
+        self.is_connected()?;
+        TxPowerTarget::Connection(self.ble_client.conn_handle()).get()
+    }
+
+    /// Opens an L2CAP connection oriented channel with the connected server, blocking until the
+    /// server accepts or rejects it.
+    ///
+    /// # Arguments
+    ///
+    /// - `psm`: The Protocol/Service Multiplexer the server listens on, from 0x01 to 0xFF
+    /// - `mtu`: The max size of the SDUs the client can receive, at least 23 bytes
+    /// - `timeout`: Max time to wait for the server to answer, or None to wait indefinitely
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new L2capChannel, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the client is not connected
+    /// - `BleError::InvalidParameters`: If the PSM is not a LE PSM or the MTU is smaller than 23
+    /// - `BleError::TimeOut`: If the server did not answer in time
+    /// - `BleError::Code`: If the server rejected the channel or NimBLE could not open it
+    pub fn open_l2cap_channel(
+        &mut self,
+        psm: u16,
+        mtu: u16,
+        timeout: Option<Duration>,
+    ) -> Result<L2capChannel, BleError> {
+        self.is_connected()?;
+        L2capChannel::open(self.ble_client.conn_handle(), psm, mtu, timeout)
+    }
+
+    /// Pairs with the connected server, or encrypts the connection with the keys of a previous
+    /// bonding, using the given security. Blocks until the pairing ends.
+    ///
+    /// Pairings that need the user, such as passkey entry or numeric comparison, answer through
+    /// [BleClient::on_passkey_entry] and [BleClient::on_numeric_comparison], which only run on the
+    /// update loop. In that case use [Self::secure_connection_async] with
+    /// [crate::Microcontroller::block_on], since this method would not let them run. If this
+    /// device has to display the passkey, the one of the `security` is used.
+    ///
+    /// # Arguments
+    ///
+    /// - `security`: The Security used for the pairing. It replaces the one of every other BLE
+    ///   driver of the device
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the ConnectionInformation of the secured connection, whose `bonded`,
+    /// `encrypted` and `authenticated` fields tell the outcome of the pairing, or a `BleError` if
+    /// it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the client is not connected, or the connection dropped
+    /// - `BleError::InvalidParameters`, `BleError::InvalidPasskey` or
+    ///   `BleError::MitmWithoutIOCapabilities`: If the `security` is not valid, see
+    ///   [Security::validate]
+    /// - `BleError::PairingFailed`: If either device rejected the pairing or the encryption, for
+    ///   example due to a wrong passkey or a bonding forgotten by the server
+    /// - `BleError::Code`: on other errors
+    pub fn secure_connection(
+        &mut self,
+        security: Security,
+    ) -> Result<ConnectionInformation, BleError> {
+        block_on(self.secure_connection_async(security))
+    }
+
+    /// Non blocking async version of [Self::secure_connection]
+    pub async fn secure_connection_async(
+        &mut self,
+        security: Security,
+    ) -> Result<ConnectionInformation, BleError> {
+        self.is_connected()?;
+        security.apply(BLEDevice::take())?;
+        self.ble_client
+            .secure_connection()
+            .await
+            .map_err(BleError::from_pairing_context)?;
+        let desc = self
+            .ble_client
+            .desc()
+            .map_err(BleError::from_connection_context)?;
+        Ok(ConnectionInformation::from_bleconn_desc(
+            &desc,
+            true,
+            Ok(()),
+        ))
+    }
+
     fn is_connected(&mut self) -> Result<(), BleError> {
         if !self.connected || !self.ble_client.connected() {
-            return Err(BleError::Disconnected);
+            return Err(BleError::Disconnected(None));
         }
         Ok(())
     }
@@ -355,10 +822,11 @@ impl _BleClient {
     ///
     pub fn disconnect(&mut self) -> Result<(), BleError> {
         self.connected = false;
+        self.discovered_services = None;
         match self.ble_client.disconnect().map_err(BleError::from) {
             Ok(_) => Ok(()),
             Err(err) => match err {
-                BleError::DeviceNotFound => Ok(()),
+                BleError::DeviceNotFound(_) => Ok(()),
                 _ => Err(err),
             },
         }
@@ -377,9 +845,393 @@ impl BleClient {
     /// A [BleClient] with the default time_between_scans, ready to connect to a ble server
     pub(crate) fn new(ble_device: &mut BLEDevice, notifier: Notifier) -> Self {
         Self {
-            inner: SharableRef::new_sharable(_BleClient::new(ble_device, notifier)),
-            updater: SharableRef::new_sharable(BleClientUpdater::default()),
+            inner: SharableRef::new_sharable(_BleClient::new(ble_device, notifier.clone())),
+            updater: SharableRef::new_sharable(BleClientUpdater {
+                remote_characteristics: HashMap::new(),
+                pairing: PairingCallbacks::new(notifier),
+                reconnection: None,
+                connections: Vec::new(),
+                background_scan: None,
+                on_service_changed: None,
+            }),
+        }
+    }
+
+    /// Opens another connection to a device, besides the one handled by the client itself, so
+    /// several peripherals can be used at the same time. Each connection has its own
+    /// characteristics and disconnect callback, and is closed when dropped.
+    ///
+    /// # Arguments
+    ///
+    /// - `device`: The device to connect to, found with [Self::find_device] or [Self::scan]
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new BleClientConnection, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::DeviceNotConnectable`: if the device does not accept connections
+    /// - `BleError::DeviceNotFound`: if the device was not found when trying to connect to it
+    /// - `BleError::Code`: on other errors, such as reaching the maximum amount of connections
+    pub fn open_connection(
+        &mut self,
+        device: &BleAdvertisedDevice,
+    ) -> Result<BleClientConnection, BleError> {
+        block_on(self.open_connection_async(device))
+    }
+
+    /// Non blocking async version of [Self::open_connection]
+    pub async fn open_connection_async(
+        &mut self,
+        device: &BleAdvertisedDevice,
+    ) -> Result<BleClientConnection, BleError> {
+        let notifier = self.inner.deref().notifier.clone();
+        let (connection, updater) = BleClientConnection::connect(device, notifier).await?;
+        self.updater.deref_mut().connections.push(updater);
+        Ok(connection)
+    }
+
+    /// Starts a passive scan that runs until [Self::stop_background_scan] is called, executing the
+    /// callback for each device found that fulfills the filter. Useful to listen to beacons without
+    /// being flooded with their repeated advertisements:
+    /// - Without a throttle window, the controller filters the duplicated advertisements, so each
+    ///   device is reported once.
+    /// - With a throttle window, every advertisement reaches the client, but each address is
+    ///   reported at most once per window. Useful for beacons whose data changes over time.
+    ///
+    /// While the background scan runs, [Self::find_device] and [Self::scan] can not be used. Starting
+    /// a new background scan replaces the previous one.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used. Devices found while the amount of pending devices is at the `ble_sightings`
+    /// capacity of [crate::utils::queue_config::QueueCapacities] are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// - `filter`: A `ScanFilter` with the conditions a device must fulfill to be reported. Whether it
+    ///   stops on the first match is ignored, since the scan runs until stopped.
+    /// - `throttle`: The minimum time between reports of the same address, or None to use the duplicate
+    ///   filtering of the controller
+    /// - `callback`: A closure that receives each device found
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the scan started, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the scan could not be started
+    pub fn start_background_scan<C: FnMut(BleAdvertisedDevice) + 'static>(
+        &mut self,
+        filter: ScanFilter,
+        throttle: Option<Duration>,
+        callback: C,
+    ) -> Result<(), BleError> {
+        self.stop_background_scan()?;
+        let results = Arc::new(Mutex::new(VecDeque::new()));
+        let results_ref = results.clone();
+        let capacity = queue_capacities().ble_sightings;
+        let mut last_reported: HashMap<BLEAddress, Instant> = HashMap::new();
+
+        let mut inner = self.inner.deref_mut();
+        let notifier = inner.notifier.clone();
+        let time_between_scans = inner.time_between_scans;
+        inner
+            .ble_scan
+            .active_scan(false)
+            .filter_duplicates(throttle.is_none())
+            .interval(time_between_scans.max(1))
+            .window(time_between_scans.max(2) - 1)
+            .on_result(move |_, adv| {
+                let device = BleAdvertisedDevice::from(adv);
+                if !filter.matches(&device) {
+                    return;
+                }
+                if let Some(window) = throttle {
+                    let now = Instant::now();
+                    let throttled = last_reported
+                        .get(device.addr())
+                        .is_some_and(|last| now.duration_since(*last) < window);
+                    if throttled {
+                        return;
+                    }
+                    if last_reported.len() >= MAX_THROTTLED_ADDRESSES {
+                        last_reported.retain(|_, last| now.duration_since(*last) < window);
+                    }
+                    last_reported.insert(*device.addr(), now);
+                }
+                let mut results = results_ref.lock().unwrap();
+                if results.len() < capacity {
+                    results.push_back(device);
+                } else {
+                    record_dropped_item();
+                }
+                drop(results);
+                notifier.notify();
+            });
+
+        // The scan procedure begins the first time the future is polled, and then the future only waits
+        // for the procedure to end. Since it never ends, polling it once leaves the scan running.
+        if let Some(res) = inner.ble_scan.start(BLOCK).now_or_never() {
+            res?;
+        }
+        drop(inner);
+
+        self.updater.deref_mut().background_scan = Some(BackgroundScan {
+            results,
+            callback: Box::new(callback),
+        });
+        Ok(())
+    }
+
+    /// Stops the scan started with [Self::start_background_scan]. The devices found that are still
+    /// waiting for the callback are discarded. Does nothing if no background scan is running.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the scan stopped, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the scan could not be stopped
+    pub fn stop_background_scan(&mut self) -> Result<(), BleError> {
+        if self.updater.deref_mut().background_scan.take().is_none() {
+            return Ok(());
+        }
+        self.inner.deref_mut().ble_scan.stop()?;
+        Ok(())
+    }
+
+    /// Checks whether a scan started with [Self::start_background_scan] is running
+    ///
+    /// # Returns
+    ///
+    /// True if the background scan is running, False if not
+    pub fn is_background_scanning(&self) -> bool {
+        self.updater.deref().background_scan.is_some()
+    }
+
+    /// Sets the policy followed to reconnect to the last device when the link drops unexpectedly.
+    /// Attempts are made from the update loop, blocking it while connecting. Disconnecting through
+    /// [Self::disconnect] does not trigger a reconnection.
+    ///
+    /// Note: For the reconnection to happen, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `policy`: The ReconnectPolicy to follow
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the policy was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the timer used for the backoff could not be created
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) -> Result<(), BleError> {
+        let notifier = self.inner.deref().notifier.clone();
+        let link_lost = Arc::new(AtomicBool::new(false));
+        let attempt_due = Arc::new(AtomicBool::new(false));
+
+        let timer_flag = attempt_due.clone();
+        let timer_notifier = notifier.clone();
+        let timer = EspTaskTimerService::new()
+            .and_then(|service| {
+                service.timer(move || {
+                    timer_flag.store(true, Ordering::SeqCst);
+                    timer_notifier.notify();
+                })
+            })
+            .map_err(|err| BleError::Code(err.code() as u32, err.to_string()))?;
+
+        let lost_flag = link_lost.clone();
+        self.inner.deref_mut().ble_client.on_disconnect(move |_| {
+            lost_flag.store(true, Ordering::SeqCst);
+            notifier.notify();
+        });
+
+        let mut updater = self.updater.deref_mut();
+        let (on_reconnected, on_gave_up) = match updater.reconnection.take() {
+            Some(previous) => (previous.on_reconnected, previous.on_gave_up),
+            None => (None, None),
+        };
+        updater.reconnection = Some(Reconnection {
+            policy,
+            link_lost,
+            attempt_due,
+            timer,
+            attempts: 0,
+            on_reconnected,
+            on_gave_up,
+        });
+        Ok(())
+    }
+
+    /// Sets the callback executed when the client reconnects after the link dropped. Does nothing if
+    /// no policy was set with [Self::set_reconnect_policy].
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure executed after reconnecting
+    pub fn on_reconnected<C: FnMut() + 'static>(&mut self, callback: C) {
+        if let Some(reconnection) = self.updater.deref_mut().reconnection.as_mut() {
+            reconnection.on_reconnected = Some(Box::new(callback));
+        }
+    }
+
+    /// Sets the callback executed when every attempt of the policy failed. Does nothing if no policy
+    /// was set with [Self::set_reconnect_policy].
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure executed after giving up
+    pub fn on_gave_up<C: FnMut() + 'static>(&mut self, callback: C) {
+        if let Some(reconnection) = self.updater.deref_mut().reconnection.as_mut() {
+            reconnection.on_gave_up = Some(Box::new(callback));
+        }
+    }
+
+    /// Sets the callback executed when the connected device indicates that its services changed, for
+    /// example after a firmware update that rearranged its attributes. The discovered services are
+    /// forgotten and discovered again before executing the callback, which receives them. The
+    /// characteristics gotten before the change may point to attributes that no longer exist, so
+    /// they must be gotten again, and their callbacks set again. The client subscribes to the
+    /// indications on every connection, including the reconnections of [Self::set_reconnect_policy].
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the services discovered after the change
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the callback was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the connection dropped while subscribing
+    /// - `BleError::Code`: If the connected device rejected the subscription
+    pub fn on_service_changed<C: FnMut(Vec<RemoteService>) + 'static>(
+        &mut self,
+        callback: C,
+    ) -> Result<(), BleError> {
+        self.updater.deref_mut().on_service_changed = Some(Box::new(callback));
+        let mut inner = self.inner.deref_mut();
+        if inner.service_changed.is_some() {
+            return Ok(());
+        }
+        inner.service_changed = Some(Arc::new(AtomicBool::new(false)));
+        if inner.is_connected().is_err() {
+            return Ok(());
+        }
+        block_on(inner.subscribe_service_changed_async())
+    }
+
+    /// Starts the backoff when the link dropped, and attempts to reconnect when it ends
+    fn handle_reconnection(&mut self) {
+        let mut updater = self.updater.deref_mut();
+        let Some(reconnection) = updater.reconnection.as_mut() else {
+            return;
+        };
+
+        if reconnection.link_lost.swap(false, Ordering::SeqCst) {
+            let mut inner = self.inner.deref_mut();
+            // A disconnection requested by the user already cleared the flag
+            if !inner.connected {
+                return;
+            }
+            inner.connected = false;
+            reconnection.attempts = 0;
+            _ = reconnection.timer.after(reconnection.policy.backoff(0));
         }
+
+        if !reconnection.attempt_due.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        if block_on(self.inner.deref_mut().reconnect_async()).is_ok() {
+            if let Some(callback) = reconnection.on_reconnected.as_mut() {
+                callback();
+            }
+            return;
+        }
+        reconnection.attempts += 1;
+        if reconnection.attempts >= reconnection.policy.max_attempts() {
+            if let Some(callback) = reconnection.on_gave_up.as_mut() {
+                callback();
+            }
+            return;
+        }
+        let backoff = reconnection.policy.backoff(reconnection.attempts);
+        _ = reconnection.timer.after(backoff);
+    }
+
+    /// Discovers the services again when the device indicated that they changed, and executes the
+    /// callback with them. If the discovery fails, the services are discovered when next needed.
+    fn handle_service_changed(&mut self) {
+        if !self.inner.deref_mut().take_service_changed() {
+            return;
+        }
+        let Ok(services) = block_on(self.get_services_async()) else {
+            return;
+        };
+        if let Some(callback) = self.updater.deref_mut().on_service_changed.as_mut() {
+            callback(services);
+        }
+    }
+
+    /// Sets the callback executed during a numeric comparison pairing, where both devices show the same
+    /// number and the user must confirm that they match. If the callback is not answered in time the
+    /// pairing is rejected.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the number to compare and returns true to accept the pairing
+    pub fn on_numeric_comparison<C: FnMut(u32) -> bool + 'static>(&mut self, callback: C) {
+        let mut updater = self.updater.deref_mut();
+        updater.pairing.set_numeric_comparison(callback);
+        let channel = updater.pairing.channel();
+        self.inner
+            .deref_mut()
+            .ble_client
+            .on_confirm_pin(move |number| channel.confirm_number(number));
+    }
+
+    /// Sets the callback executed when the server displays a passkey that must be entered on the client.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that returns the passkey entered by the user
+    pub fn on_passkey_entry<C: FnMut() -> u32 + 'static>(&mut self, callback: C) {
+        let mut updater = self.updater.deref_mut();
+        updater.pairing.set_passkey_entry(callback);
+        let channel = updater.pairing.channel();
+        self.inner
+            .deref_mut()
+            .ble_client
+            .on_passkey_request(move || channel.enter_passkey());
     }
 
     /// Blocking method that attempts to get a characteristic from a service of the current connection.
@@ -430,6 +1282,73 @@ impl BleClient {
         block_on(self.get_all_characteristics_async(service_id))
     }
 
+    /// Blocking method that discovers every service of the current connection with all of its
+    /// characteristics. The result is cached until the client disconnects, so later calls, and
+    /// [Self::get_characteristic], [Self::get_all_characteristics] and [Self::find_characteristic],
+    /// are answered without asking the server again. Discovering everything once right after
+    /// connecting is much faster than looking up each characteristic separately.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec<RemoteService>` with the services of the device, or `BleError`
+    /// if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if there is no connection stablished to go look for a service
+    /// - `BleError::Code`: on other errors
+    pub fn get_services(&mut self) -> Result<Vec<RemoteService>, BleError> {
+        block_on(self.get_services_async())
+    }
+
+    /// Blocking method that gets a characteristic from the discovered services, running the
+    /// discovery of [Self::get_services] first if it was not run on the current connection.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The id of the service which owns the characteristic.
+    /// - `characteristic_id`: The id of the desired characterisitc, of the given service.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `RemoteCharacteristic`, or `BleError` if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if there is no connection stablished to go look for a service
+    /// - `BleError::ServiceNotFound`: if the device does not have a service of the specified id
+    /// - `BleError::CharacteristicNotFound`: if the devices's service does not have a characteristic of the
+    ///    specified id
+    /// - `BleError::Code`: on other errors
+    pub fn find_characteristic(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<RemoteCharacteristic, BleError> {
+        block_on(self.find_characteristic_async(service_id, characteristic_id))
+    }
+
+    /// Non blocking async version of [Self::get_services]
+    pub async fn get_services_async(&mut self) -> Result<Vec<RemoteService>, BleError> {
+        let services = self.inner.deref_mut()._get_services_async().await?;
+        let mut updater = self.updater.borrow_mut();
+        for service in &services {
+            for c in &service.characteristics {
+                updater.add_characteristic(c);
+            }
+        }
+        Ok(services)
+    }
+
+    /// Non blocking async version of [Self::find_characteristic]
+    pub async fn find_characteristic_async(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<RemoteCharacteristic, BleError> {
+        self.get_services_async().await?;
+        self.get_characteristic_async(service_id, characteristic_id)
+            .await
+    }
+
     /// Non blocking async version of [Self::get_characteristic]
     pub async fn get_characteristic_async(
         &mut self,
@@ -465,11 +1384,27 @@ impl BleClient {
 }
 
 impl<'a> InterruptDriver<'a> for BleClient {
-    /// Updates all characteristics that have been gotten
+    /// Updates all characteristics that have been gotten, reports the devices found by the
+    /// background scan, and discovers the services again if the device changed them
     fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
-        for c in self.updater.deref_mut().remote_characteristics.values_mut() {
+        let mut updater = self.updater.deref_mut();
+        for c in updater.remote_characteristics.values_mut() {
             c.execute_if_notified()
         }
+        updater.pairing.handle_events();
+        // Connections dropped by the user are only referenced by the updater
+        updater
+            .connections
+            .retain(|connection| Rc::strong_count(connection) > 1);
+        for connection in &updater.connections {
+            connection.borrow_mut().handle_events();
+        }
+        if let Some(background_scan) = updater.background_scan.as_mut() {
+            background_scan.handle_results();
+        }
+        drop(updater);
+        self.handle_reconnection();
+        self.handle_service_changed();
         Ok(())
     }
 
@@ -479,4 +1414,165 @@ impl<'a> InterruptDriver<'a> for BleClient {
             updater: self.updater.clone(),
         })
     }
+
+    /// Stops the background scan and disconnects, forgetting the reconnect policy so the client does
+    /// not connect again
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.updater.deref_mut().reconnection = None;
+        self.stop_background_scan()?;
+        Ok(self.disconnect()?)
+    }
+}
+
+/// Gets a service from the cache of discovered services
+///
+/// # Arguments
+///
+/// - `services`: The discovered services
+/// - `service_id`: The id of the service
+///
+/// # Returns
+///
+/// A `Result` with the RemoteService, or a `BleError` if it was not discovered
+///
+/// # Errors
+///
+/// - `BleError::ServiceNotFound`: If the device does not have a service of the specified id
+fn find_discovered_service<'a>(
+    services: &'a [RemoteService],
+    service_id: &BleId,
+) -> Result<&'a RemoteService, BleError> {
+    services
+        .iter()
+        .find(|service| service.id == *service_id)
+        .ok_or(BleError::ServiceNotFound(None))
+}
+
+/// Gets a characteristic from a service of a connection, answering from the cache of discovered
+/// services if there is one
+///
+/// # Arguments
+///
+/// - `ble_client`: The BLEClient of the connection
+/// - `discovered_services`: The services discovered on the connection, if they were discovered
+/// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] after a notification
+/// - `service_id`: The id of the service which owns the characteristic
+/// - `characteristic_id`: The id of the characteristic
+///
+/// # Returns
+///
+/// A `Result` with the RemoteCharacteristic, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::ServiceNotFound`: If the device does not have a service of the specified id
+/// - `BleError::CharacteristicNotFound`: If the service does not have a characteristic of the
+///   specified id
+/// - `BleError::Code`: on other errors
+pub(super) async fn get_remote_characteristic(
+    ble_client: &mut BLEClient,
+    discovered_services: Option<&[RemoteService]>,
+    notifier: &Notifier,
+    service_id: &BleId,
+    characteristic_id: &BleId,
+) -> Result<RemoteCharacteristic, BleError> {
+    if let Some(services) = discovered_services {
+        return find_discovered_service(services, service_id)?
+            .characteristic(characteristic_id)
+            .ok_or(BleError::CharacteristicNotFound(None));
+    }
+    let conn_handle = ble_client.conn_handle();
+    let remote_service = ble_client
+        .get_service(service_id.to_uuid())
+        .await
+        .map_err(BleError::from_service_context)?;
+    let remote_characteristic = remote_service
+        .get_characteristic(characteristic_id.to_uuid())
+        .await
+        .map_err(BleError::from_characteristic_context)?;
+    Ok(RemoteCharacteristic::new(
+        remote_characteristic,
+        notifier.clone(),
+        conn_handle,
+    ))
+}
+
+/// Gets every characteristic of a service of a connection, answering from the cache of discovered
+/// services if there is one
+///
+/// # Arguments
+///
+/// - `ble_client`: The BLEClient of the connection
+/// - `discovered_services`: The services discovered on the connection, if they were discovered
+/// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] after a notification
+/// - `service_id`: The id of the service
+///
+/// # Returns
+///
+/// A `Result` with the characteristics of the service, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::ServiceNotFound`: If the device does not have a service of the specified id
+/// - `BleError::Code`: on other errors
+pub(super) async fn get_remote_characteristics(
+    ble_client: &mut BLEClient,
+    discovered_services: Option<&[RemoteService]>,
+    notifier: &Notifier,
+    service_id: &BleId,
+) -> Result<Vec<RemoteCharacteristic>, BleError> {
+    if let Some(services) = discovered_services {
+        return Ok(find_discovered_service(services, service_id)?
+            .clone()
+            .characteristics);
+    }
+    let conn_handle = ble_client.conn_handle();
+    let remote_service = ble_client
+        .get_service(service_id.to_uuid())
+        .await
+        .map_err(BleError::from_service_context)?;
+    let remote_characteristics = remote_service
+        .get_characteristics()
+        .await?
+        .map(|remote_characteristic| {
+            RemoteCharacteristic::new(remote_characteristic, notifier.clone(), conn_handle)
+        })
+        .collect();
+    Ok(remote_characteristics)
+}
+
+/// Discovers every service of a connection with all of its characteristics
+///
+/// # Arguments
+///
+/// - `ble_client`: The BLEClient of the connection
+/// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] after a notification
+///
+/// # Returns
+///
+/// A `Result` with the services of the device, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::Code`: If the discovery fails
+pub(super) async fn discover_services(
+    ble_client: &mut BLEClient,
+    notifier: &Notifier,
+) -> Result<Vec<RemoteService>, BleError> {
+    let conn_handle = ble_client.conn_handle();
+    let mut discovered = vec![];
+    for remote_service in ble_client.get_services().await? {
+        let characteristics = remote_service
+            .get_characteristics()
+            .await?
+            .map(|remote_characteristic| {
+                RemoteCharacteristic::new(remote_characteristic, notifier.clone(), conn_handle)
+            })
+            .collect();
+        discovered.push(RemoteService {
+            id: BleId::from(remote_service.uuid()),
+            characteristics,
+        });
+    }
+    Ok(discovered)
 }
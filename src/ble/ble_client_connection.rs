@@ -0,0 +1,389 @@
+use super::{
+    ble_client::{discover_services, get_remote_characteristic, get_remote_characteristics},
+    utils::{
+        BleAdvertisedDevice, BleError, BleId, RemoteCharacteristic, RemoteService, TxPower,
+        TxPowerTarget,
+    },
+};
+use crate::utils::{
+    auxiliary::{SharableRef, SharableRefExt},
+    notification::Notifier,
+};
+use esp32_nimble::{BLEAddress, BLEClient};
+use esp_idf_svc::hal::task::block_on;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// State of a BleClientConnection handled on the update loop of its BleClient:
+/// - `remote_characteristics`: The characteristics gotten through the connection, whose
+///   notifications are handled.
+/// - `link_lost`: Set by the BLE task when the connection drops.
+/// - `on_disconnect`: Callback executed when the connection drops.
+pub(crate) struct ConnectionUpdater {
+    remote_characteristics: HashMap<BleId, RemoteCharacteristic>,
+    link_lost: Arc<AtomicBool>,
+    on_disconnect: Option<Box<dyn FnMut()>>,
+}
+
+/// A connection of a [crate::ble::BleClient] to a peripheral, besides the one the BleClient itself
+/// handles. Each connection is created with [crate::ble::BleClient::open_connection] and has its
+/// own characteristics and disconnect callback, so a client can talk to several peripherals at
+/// the same time. The amount of simultaneous connections is limited by the
+/// `CONFIG_BT_NIMBLE_MAX_CONNECTIONS` option of the sdkconfig, counting the one of the BleClient.
+///
+/// The notifications and the disconnect callback are handled by the update loop of the BleClient.
+/// The connection is closed when dropped.
+pub struct BleClientConnection {
+    ble_client: BLEClient,
+    address: BLEAddress,
+    discovered_services: Option<Vec<RemoteService>>,
+    notifier: Notifier,
+    updater: SharableRef<ConnectionUpdater>,
+}
+
+impl ConnectionUpdater {
+    /// Executes the callbacks of the notified characteristics, and the disconnect callback if the
+    /// connection dropped
+    pub(crate) fn handle_events(&mut self) {
+        for characteristic in self.remote_characteristics.values_mut() {
+            characteristic.execute_if_notified()
+        }
+        if self.link_lost.swap(false, Ordering::SeqCst) {
+            if let Some(callback) = self.on_disconnect.as_mut() {
+                callback();
+            }
+        }
+    }
+}
+
+impl BleClientConnection {
+    /// Connects to a device with a new BLEClient
+    ///
+    /// # Arguments
+    ///
+    /// - `device`: The device to connect to
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] after a
+    ///   notification or a disconnection
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new BleClientConnection and the state handled by the update loop, or a
+    /// `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::DeviceNotConnectable`: if the device does not accept connections
+    /// - `BleError::DeviceNotFound`: if the device was not found when trying to connect to it
+    /// - `BleError::Code`: on other errors, such as reaching the maximum amount of connections
+    pub(crate) async fn connect(
+        device: &BleAdvertisedDevice,
+        notifier: Notifier,
+    ) -> Result<(Self, SharableRef<ConnectionUpdater>), BleError> {
+        if !device.is_connectable() {
+            return Err(BleError::DeviceNotConnectable);
+        }
+        let mut ble_client = BLEClient::new();
+        let link_lost = Arc::new(AtomicBool::new(false));
+        let lost_flag = link_lost.clone();
+        let disconnect_notifier = notifier.clone();
+        ble_client.on_disconnect(move |_| {
+            lost_flag.store(true, Ordering::SeqCst);
+            disconnect_notifier.notify();
+        });
+        ble_client
+            .connect(device.addr())
+            .await
+            .map_err(BleError::from_connection_context)?;
+
+        let updater = SharableRef::new_sharable(ConnectionUpdater {
+            remote_characteristics: HashMap::new(),
+            link_lost,
+            on_disconnect: None,
+        });
+        let connection = BleClientConnection {
+            ble_client,
+            address: *device.addr(),
+            discovered_services: None,
+            notifier,
+            updater: updater.clone(),
+        };
+        Ok((connection, updater))
+    }
+
+    /// Gets the address of the connected peripheral
+    ///
+    /// # Returns
+    ///
+    /// The BLEAddress of the peripheral
+    pub fn address(&self) -> BLEAddress {
+        self.address
+    }
+
+    /// Checks whether the peripheral is still connected
+    ///
+    /// # Returns
+    ///
+    /// True if the connection is open, False if it dropped or was closed
+    pub fn is_connected(&self) -> bool {
+        self.ble_client.connected()
+    }
+
+    /// Sets the callback executed when the connection drops or is closed with [Self::disconnect].
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure executed after the disconnection
+    pub fn on_disconnect<C: FnMut() + 'static>(&mut self, callback: C) {
+        self.updater.deref_mut().on_disconnect = Some(Box::new(callback));
+    }
+
+    /// Blocking method that attempts to get a characteristic from a service of the peripheral.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The id of the service which owns the characteristic.
+    /// - `characteristic_id`: The id of the desired characterisitc, of the given service.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `RemoteCharacteristic`, or `BleError` if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if the connection dropped
+    /// - `BleError::ServiceNotFound`: if the device does not have a service of the specified id
+    /// - `BleError::CharacteristicNotFound`: if the devices's service does not have a characteristic of the
+    ///    specified id
+    /// - `BleError::Code`: on other errors
+    pub fn get_characteristic(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<RemoteCharacteristic, BleError> {
+        block_on(self.get_characteristic_async(service_id, characteristic_id))
+    }
+
+    /// Non blocking async version of [Self::get_characteristic]
+    pub async fn get_characteristic_async(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<RemoteCharacteristic, BleError> {
+        self.check_connected()?;
+        let characteristic = get_remote_characteristic(
+            &mut self.ble_client,
+            self.discovered_services.as_deref(),
+            &self.notifier,
+            service_id,
+            characteristic_id,
+        )
+        .await?;
+        self.add_characteristics(std::slice::from_ref(&characteristic));
+        Ok(characteristic)
+    }
+
+    /// Blocking method that attempts to get all characteristics of a service of the peripheral
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The id of the service.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec<RemoteCharacteristic>` if it was able to find the specified service or
+    /// `BleError` if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if the connection dropped
+    /// - `BleError::ServiceNotFound`: if the device does not have a service of the specified id
+    /// - `BleError::Code`: on other errors
+    pub fn get_all_characteristics(
+        &mut self,
+        service_id: &BleId,
+    ) -> Result<Vec<RemoteCharacteristic>, BleError> {
+        block_on(self.get_all_characteristics_async(service_id))
+    }
+
+    /// Non blocking async version of [Self::get_all_characteristics]
+    pub async fn get_all_characteristics_async(
+        &mut self,
+        service_id: &BleId,
+    ) -> Result<Vec<RemoteCharacteristic>, BleError> {
+        self.check_connected()?;
+        let characteristics = get_remote_characteristics(
+            &mut self.ble_client,
+            self.discovered_services.as_deref(),
+            &self.notifier,
+            service_id,
+        )
+        .await?;
+        self.add_characteristics(&characteristics);
+        Ok(characteristics)
+    }
+
+    /// Blocking method that discovers every service of the peripheral with all of its
+    /// characteristics, as [crate::ble::BleClient::get_services] does. The result is cached while
+    /// the connection is open.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing a `Vec<RemoteService>` with the services of the device, or `BleError`
+    /// if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if the connection dropped
+    /// - `BleError::Code`: on other errors
+    pub fn get_services(&mut self) -> Result<Vec<RemoteService>, BleError> {
+        block_on(self.get_services_async())
+    }
+
+    /// Non blocking async version of [Self::get_services]
+    pub async fn get_services_async(&mut self) -> Result<Vec<RemoteService>, BleError> {
+        self.check_connected()?;
+        if self.discovered_services.is_none() {
+            let discovered = discover_services(&mut self.ble_client, &self.notifier).await?;
+            for service in &discovered {
+                self.add_characteristics(&service.characteristics);
+            }
+            self.discovered_services = Some(discovered);
+        }
+        Ok(self.discovered_services.clone().unwrap_or_default())
+    }
+
+    /// Blocking method that gets a characteristic from the discovered services, running the
+    /// discovery of [Self::get_services] first if it was not run yet.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The id of the service which owns the characteristic.
+    /// - `characteristic_id`: The id of the desired characterisitc, of the given service.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `RemoteCharacteristic`, or `BleError` if a failure occured.
+    ///
+    /// # Errors
+    /// - `BleError::Disconnected`: if the connection dropped
+    /// - `BleError::ServiceNotFound`: if the device does not have a service of the specified id
+    /// - `BleError::CharacteristicNotFound`: if the devices's service does not have a characteristic of the
+    ///    specified id
+    /// - `BleError::Code`: on other errors
+    pub fn find_characteristic(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<RemoteCharacteristic, BleError> {
+        block_on(self.find_characteristic_async(service_id, characteristic_id))
+    }
+
+    /// Non blocking async version of [Self::find_characteristic]
+    pub async fn find_characteristic_async(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<RemoteCharacteristic, BleError> {
+        self.get_services_async().await?;
+        self.get_characteristic_async(service_id, characteristic_id)
+            .await
+    }
+
+    /// Reads the signal strength of the connection
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the RSSI in dBm, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: if the connection dropped
+    /// - `BleError::Code`: on other errors
+    pub fn read_rssi(&mut self) -> Result<i8, BleError> {
+        self.check_connected()?;
+        self.ble_client.get_rssi().map_err(BleError::from)
+    }
+
+    /// Sets the transmission power of the connection
+    ///
+    /// # Arguments
+    ///
+    /// - `tx_power`: The TxPower to use
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the power was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: if the connection dropped
+    /// - `BleError::Code`: if the controller rejects the level
+    pub fn set_tx_power(&mut self, tx_power: TxPower) -> Result<(), BleError> {
+        self.check_connected()?;
+        TxPowerTarget::Connection(self.ble_client.conn_handle()).set(tx_power)
+    }
+
+    /// Gets the transmission power of the connection
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the TxPower, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: if the connection dropped
+    /// - `BleError::NotFound`: if the controller could not inform the level
+    pub fn tx_power(&self) -> Result<TxPower, BleError> {
+        self.check_connected()?;
+        TxPowerTarget::Connection(self.ble_client.conn_handle()).get()
+    }
+
+    /// Closes the connection. The characteristics gotten through it stop working.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the connection was closed or was already closed, or a `BleError` if
+    /// it fails.
+    pub fn disconnect(&mut self) -> Result<(), BleError> {
+        self.discovered_services = None;
+        self.updater.deref_mut().remote_characteristics.clear();
+        if !self.ble_client.connected() {
+            return Ok(());
+        }
+        match self.ble_client.disconnect().map_err(BleError::from) {
+            Err(BleError::DeviceNotFound(_)) => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Registers characteristics so their notifications are handled by the update loop
+    fn add_characteristics(&mut self, characteristics: &[RemoteCharacteristic]) {
+        let mut updater = self.updater.deref_mut();
+        for characteristic in characteristics {
+            updater
+                .remote_characteristics
+                .insert(characteristic.id(), characteristic.clone());
+        }
+    }
+
+    fn check_connected(&self) -> Result<(), BleError> {
+        if !self.ble_client.connected() {
+            return Err(BleError::Disconnected(None));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for BleClientConnection {
+    /// Closes the connection and stops handling its callbacks
+    fn drop(&mut self) {
+        self.updater.deref_mut().on_disconnect = None;
+        _ = self.disconnect();
+    }
+}
@@ -1,31 +1,57 @@
 use super::utils::{
-    BleError, BleId, Characteristic, ConnectionInformation, ConnectionMode, DiscoverableMode,
-    Service,
+    own_address, request_connection_params, set_raw_advertising_data, AdjustReason,
+    AdvertisementPayload, AdvertisingFilterPolicy, AdvertisingParams, BatteryService, BleError,
+    BleId, Characteristic, ClientConfiguration, ConnectionInformation, ConnectionMode,
+    CurrentTimeService, DiscoverableMode, IndicationResult, OwnAddressType, PairingCallbacks,
+    Service, TxPower, TxPowerTarget, MAX_ATTRIBUTE_LENGTH,
 };
+use super::{BleHid, BleServerEvent, BleServerEvents, EventChannel, HidDeviceKind, L2capListener};
 use crate::{
+    sensors::DateTime,
     utils::{
         auxiliary::{SharableRef, SharableRefExt},
         esp32_framework_error::Esp32FrameworkError,
         isr_queues::{ISRQueue, ISRQueueTrait},
-        notification::Notifier,
+        notification::{Notification, Notifier},
+        queue_config::{queue_capacities, record_dropped_item},
+        timer_driver::TimerDriver,
     },
     InterruptDriver,
 };
 use esp32_nimble::{
-    utilities::mutex::Mutex, BLEAdvertisementData, BLEAdvertising, BLECharacteristic, BLEDevice,
-    BLEServer, BLEService, NimbleProperties,
+    utilities::mutex::Mutex, BLEAddress, BLEAdvertisementData, BLEAdvertising, BLECharacteristic,
+    BLEConnDesc, BLEDescriptor, BLEDevice, BLEError, BLEServer, BLEService, NimbleProperties,
+    NimbleSub, OnWriteArgs,
+};
+use esp_idf_svc::sys::{
+    ble_addr_t, ble_gap_adv_params, ble_gap_adv_start, ble_gap_conn_desc, ble_gap_conn_find,
+    ble_gap_event, ble_gap_wl_set, BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN, BLE_GAP_CONN_MODE_DIR,
+    BLE_GAP_DISC_MODE_NON, BLE_GAP_EVENT_ADV_COMPLETE, BLE_GAP_EVENT_CONNECT,
+    BLE_GAP_EVENT_DISCONNECT, BLE_HS_ETIMEOUT, BLE_HS_FOREVER,
 };
-use esp_idf_svc::hal::task;
 use sharable_reference_macro::sharable_reference_wrapper;
-use std::sync::{
-    atomic::{AtomicU8, Ordering},
-    Arc,
+use std::{
+    collections::VecDeque,
+    ffi::{c_int, c_void},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
 
 const DEFAULT_MAX_CLIENTS: u8 = 1;
 
 type ConnUserCallback<'a> = dyn FnMut(&mut BleServer<'a>, &ConnectionInformation) + 'a;
 type ConnCountingCallback<'a> = dyn FnMut(&mut BleServer<'a>) + 'a;
+type NotificationReader<'a> = dyn FnMut() -> Vec<Characteristic> + 'a;
+type WriteUserCallback<'a> = dyn FnMut(&mut BleServer<'a>, &ConnectionInformation, Vec<u8>) + 'a;
+type IndicationUserCallback<'a> =
+    dyn FnMut(&mut BleServer<'a>, &ConnectionInformation, IndicationResult) + 'a;
+type DirectedTimeoutCallback<'a> = dyn FnMut(&mut BleServer<'a>, BLEAddress) + 'a;
+type WriteQueue = Arc<Mutex<VecDeque<(ConnectionInformation, Vec<u8>)>>>;
+type IndicationQueues = Arc<Mutex<Vec<ISRQueue<(ConnectionInformation, IndicationResult)>>>>;
+type PendingClients = Arc<Mutex<Vec<u16>>>;
 
 /// Abstraction to create a BLE server, the side that has the information to be used in a connection
 /// oriented relationship. Contains:
@@ -36,6 +62,24 @@ type ConnCountingCallback<'a> = dyn FnMut(&mut BleServer<'a>) + 'a;
 /// * `remaining_connections`: maximum amount of simultaneous clients.
 /// * `user_on_connection`: Callback that will be executed for each client connected.
 /// * `user_on_disconnection`: Callback that will be executed for each client disconnected.
+/// * `periodic_notifications`: Characteristics that are notified to the clients at a fixed period.
+/// * `write_callbacks`: Callbacks that will be executed when a client writes a characteristic.
+/// * `indication_callbacks`: Callbacks that will be executed with the result of each indication.
+/// * `notify_tx_hooks`: Callbacks set on the characteristics to receive the result of each notification and indication.
+/// * `pairing`: Callbacks that will be executed on the pairing events that need the user.
+/// * `whitelist`: Addresses of the devices allowed by the advertising filter policy.
+/// * `subscriptions`: Clients subscribed to the notifications or indications of each characteristic.
+/// * `descriptors`: Descriptors of the characteristics, whose values can be written by the clients.
+/// * `ble_services`: Services created on the attribute table, so they are found without searching NimBLE.
+/// * `ble_characteristics`: Characteristics created on the attribute table, so they are found without
+///   searching NimBLE.
+/// * `advertising_interval`: Advertising interval set by the user, if any.
+/// * `directed`: Configuration of the directed connectable advertising.
+/// * `advertisement_payload`: Custom payload that replaces the generated advertisement data, if any.
+/// * `own_address_type`: Type of the address the server advertises and connects with.
+/// * `appearance`: External appearance advertised by the server, if any.
+/// * `events`: Events waiting to be consumed by the stream returned by [BleServer::events].
+/// * `notifier`: Notifier used to wake up the microcontroller when a client writes a characteristic.
 struct _BleServer<'a> {
     advertising_name: String,
     ble_server: &'a mut BLEServer,
@@ -44,6 +88,23 @@ struct _BleServer<'a> {
     remaining_connections: RemainingConnections,
     user_on_connection: Option<ConnectionCallback<'a>>,
     user_on_disconnection: Option<ConnectionCallback<'a>>,
+    periodic_notifications: Vec<PeriodicNotification<'a>>,
+    write_callbacks: Vec<WriteCallback<'a>>,
+    indication_callbacks: Vec<IndicationCallback<'a>>,
+    notify_tx_hooks: Vec<NotifyTxHook>,
+    pairing: Option<PairingCallbacks<'a>>,
+    whitelist: Vec<BLEAddress>,
+    subscriptions: Vec<CharacteristicSubscriptions>,
+    descriptors: Vec<CharacteristicDescriptor>,
+    ble_services: Vec<ServerService>,
+    ble_characteristics: Vec<ServerCharacteristic>,
+    advertising_interval: Option<(u16, u16)>,
+    directed: DirectedAdvertising<'a>,
+    advertisement_payload: Option<AdvertisementPayload>,
+    own_address_type: OwnAddressType,
+    appearance: Option<u16>,
+    events: Arc<EventChannel>,
+    notifier: Notifier,
 }
 
 /// Abstraction to create a BLE server, the side that has the information to be used in a connection
@@ -60,6 +121,131 @@ struct ConnectionCallback<'a> {
     notifier: Notifier,
 }
 
+/// Wrapper to notify the characteristics returned by the user reader every time the timer driver
+/// sets the notification as due.
+struct PeriodicNotification<'a> {
+    service_id: BleId,
+    _timer_driver: TimerDriver<'a>,
+    due: SharableRef<bool>,
+    reader: Box<NotificationReader<'a>>,
+}
+
+/// Wrapper to execute, on the update loop, a user callback with the data written by a client
+/// on a characteristic, and the information of that client.
+struct WriteCallback<'a> {
+    service_id: BleId,
+    characteristic_id: BleId,
+    user_callback: Box<WriteUserCallback<'a>>,
+    writes: WriteQueue,
+}
+
+/// Wrapper to execute, on the update loop, a user callback with the result of each indication
+/// sent on a characteristic, and the information of the client it was sent to.
+struct IndicationCallback<'a> {
+    service_id: BleId,
+    characteristic_id: BleId,
+    user_callback: Box<IndicationUserCallback<'a>>,
+    result_queue: ISRQueue<(ConnectionInformation, IndicationResult)>,
+}
+
+/// The on_notify_tx callback of a characteristic. esp32-nimble keeps a single callback per
+/// characteristic, so it is set once and forwards the result of each indication to the queue of
+/// every IndicationCallback of the characteristic, and the result of each notification or indication
+/// to the TxWaiters of the characteristic.
+#[derive(Clone)]
+struct NotifyTxHook {
+    service_id: BleId,
+    characteristic_id: BleId,
+    indication_queues: IndicationQueues,
+    waiters: Arc<Mutex<Vec<TxWaiter>>>,
+}
+
+/// A notification sent with [BleServer::notify_value_async], waiting for the result of each client it
+/// was sent to:
+/// * `pending`: Connection handles of the clients whose result was not reported yet.
+/// * `notifier`: Notifier used to wake up the async method on each result.
+struct TxWaiter {
+    pending: PendingClients,
+    notifier: Notifier,
+}
+
+/// Directed connectable advertising, sent as ADV_DIRECT_IND packets addressed to the target device.
+/// The advertising of esp32-nimble cannot be given a peer address, so it is started directly on
+/// NimBLE with its own event callback. Contains:
+/// * `target`: Address of the only device allowed to connect, None if the advertising is not directed.
+/// * `high_duty_cycle`: Whether the advertising uses the high duty cycle.
+/// * `timeout`: Time after which the advertising stops if the target did not connect.
+/// * `on_timeout`: Callback executed when the target did not connect before the timeout.
+/// * `state`: State shared with the NimBLE event callback.
+struct DirectedAdvertising<'a> {
+    target: Option<BLEAddress>,
+    high_duty_cycle: bool,
+    timeout: Option<Duration>,
+    on_timeout: Option<Box<DirectedTimeoutCallback<'a>>>,
+    state: Arc<Mutex<DirectedAdvertisingState>>,
+}
+
+/// Outcome of the last directed advertising:
+/// * `Advertising`: The advertising did not end yet, or was not started.
+/// * `Connected`: The target device connected.
+/// * `TimedOut`: The advertising stopped because the target device did not connect in time.
+#[derive(Debug, Clone, Copy)]
+enum DirectedOutcome {
+    Advertising,
+    Connected(ConnectionInformation),
+    TimedOut,
+}
+
+/// State of the directed advertising updated from the BLE task. NimBLE reports the events of the
+/// connections made through the directed advertising to its callback instead of the one of
+/// esp32-nimble, so they are forwarded to the connection and disconnection callbacks of the server.
+/// Contains:
+/// * `outcome`: Outcome of the last directed advertising.
+/// * `timeout_pending`: Set when the advertising times out, until the user callback is executed.
+/// * `on_connection`: Queue of the connection callback of the server.
+/// * `on_disconnection`: Queue of the disconnection callback of the server.
+/// * `connection_notifier`: Notifier used to wake up the microcontroller on connections and timeouts.
+/// * `disconnection_notifier`: Notifier used to wake up the microcontroller on disconnections.
+/// * `events`: Events waiting to be consumed by the stream returned by [BleServer::events].
+struct DirectedAdvertisingState {
+    outcome: DirectedOutcome,
+    timeout_pending: bool,
+    on_connection: ISRQueue<ConnectionInformation>,
+    on_disconnection: ISRQueue<ConnectionInformation>,
+    connection_notifier: Notifier,
+    disconnection_notifier: Notifier,
+    events: Arc<EventChannel>,
+}
+
+/// Connection handles of the clients that enabled the notifications or indications of a
+/// characteristic, with what each one enabled, updated from the BLE task on every subscription event.
+struct CharacteristicSubscriptions {
+    service_id: BleId,
+    characteristic_id: BleId,
+    clients: Arc<Mutex<Vec<(u16, NimbleSub)>>>,
+}
+
+/// A descriptor created on the attribute table for a characteristic of the server
+struct CharacteristicDescriptor {
+    service_id: BleId,
+    characteristic_id: BleId,
+    descriptor_id: BleId,
+    descriptor: Arc<Mutex<BLEDescriptor>>,
+}
+
+/// A service created on the attribute table of the server
+struct ServerService {
+    service_id: BleId,
+    service: Arc<Mutex<BLEService>>,
+}
+
+/// A characteristic created on the attribute table for a service of the server
+struct ServerCharacteristic {
+    service_id: BleId,
+    characteristic_id: BleId,
+    characteristic: Arc<Mutex<BLECharacteristic>>,
+}
+
 /// Wrapper of the remaining amount of simultaneous clients. The value that it contains
 /// must be greater than one to start s new advertisement automatically on a new connection.
 #[derive(Clone, Debug)]
@@ -136,7 +322,7 @@ impl<'a> ConnectionCallback<'a> {
         Self {
             user_callback: Box::new(|_, _| {}),
             counting_callback: Box::new(|_| {}),
-            info_queue: ISRQueue::new(1000),
+            info_queue: ISRQueue::new(queue_capacities().ble_connection),
             notifier,
         }
     }
@@ -196,6 +382,251 @@ impl<'a> ConnectionCallback<'a> {
     }
 }
 
+impl<'a> PeriodicNotification<'a> {
+    /// If the notification is due, executes the reader and notifies each of the returned characteristics.
+    /// The characteristics that cannot be notified, because they are not notifiable or were not set on
+    /// the server, are logged and skipped, so a mistake in the reader does not stop the update loop.
+    ///
+    /// # Arguments
+    ///
+    /// - `server`: The BleServer used to notify the characteristics
+    fn notify_if_due(&mut self, server: &mut BleServer<'a>) {
+        if !*self.due.deref() {
+            return;
+        }
+        *self.due.deref_mut() = false;
+        for characteristic in (self.reader)() {
+            if let Err(err) = server.notify_value(&self.service_id, &characteristic) {
+                log::warn!(
+                    "The characteristic {:?} could not be notified periodically: {:?}",
+                    characteristic.id,
+                    err
+                );
+            }
+        }
+    }
+}
+
+impl<'a> WriteCallback<'a> {
+    /// Executes the user callback once for each write received since the last call
+    ///
+    /// # Arguments
+    ///
+    /// - `server`: The BleServer that is send as a parameter for the user to use in the callback
+    fn handle_writes(&mut self, server: &mut BleServer<'a>) {
+        while let Some((info, data)) = self.pop_write() {
+            (self.user_callback)(server, &info, data);
+        }
+    }
+
+    /// Takes the oldest write received
+    fn pop_write(&self) -> Option<(ConnectionInformation, Vec<u8>)> {
+        self.writes.lock().pop_front()
+    }
+
+    /// Checks whether the callback belongs to the characteristic
+    fn is_for(&self, service_id: &BleId, characteristic_id: &BleId) -> bool {
+        self.service_id == *service_id && self.characteristic_id == *characteristic_id
+    }
+}
+
+impl<'a> IndicationCallback<'a> {
+    /// Executes the user callback once for each indication result received since the last call
+    ///
+    /// # Arguments
+    ///
+    /// - `server`: The BleServer that is send as a parameter for the user to use in the callback
+    fn handle_results(&mut self, server: &mut BleServer<'a>) {
+        while let Ok((info, result)) = self.result_queue.try_recv() {
+            (self.user_callback)(server, &info, result);
+        }
+    }
+}
+
+impl TxWaiter {
+    /// Removes the client from the pending ones and wakes up the async method
+    ///
+    /// # Arguments
+    ///
+    /// - `conn_handle`: The connection handle of the client whose result was reported, if it is known
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is true while there are clients whose result is pending
+    fn report(&self, conn_handle: Option<u16>) -> bool {
+        let mut pending = self.pending.lock();
+        if let Some(conn_handle) = conn_handle {
+            pending.retain(|handle| *handle != conn_handle);
+        }
+        self.notifier.notify();
+        !pending.is_empty()
+    }
+}
+
+impl<'a> DirectedAdvertising<'a> {
+    /// Creates a new DirectedAdvertising, with the advertising not directed
+    ///
+    /// # Arguments
+    ///
+    /// - `on_connection`: The ConnectionCallback that handles the connections of the server
+    /// - `on_disconnection`: The ConnectionCallback that handles the disconnections of the server
+    /// - `events`: The EventChannel of the server
+    ///
+    /// # Returns
+    ///
+    /// The new DirectedAdvertising
+    fn new(
+        on_connection: &ConnectionCallback<'a>,
+        on_disconnection: &ConnectionCallback<'a>,
+        events: Arc<EventChannel>,
+    ) -> Self {
+        let state = DirectedAdvertisingState {
+            outcome: DirectedOutcome::Advertising,
+            timeout_pending: false,
+            on_connection: on_connection.info_queue.clone(),
+            on_disconnection: on_disconnection.info_queue.clone(),
+            connection_notifier: on_connection.notifier.clone(),
+            disconnection_notifier: on_disconnection.notifier.clone(),
+            events,
+        };
+        DirectedAdvertising {
+            target: None,
+            high_duty_cycle: false,
+            timeout: None,
+            on_timeout: None,
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Starts the ADV_DIRECT_IND advertising addressed to the target. Without a timeout, the high
+    /// duty cycle advertising is stopped by the controller after 1.28s and the low duty cycle
+    /// advertising does not stop.
+    ///
+    /// # Arguments
+    ///
+    /// - `target`: The address of the device allowed to connect
+    /// - `own_addr_type`: The NimBLE code of the address type the server advertises with
+    /// - `interval`: The advertising interval of the low duty cycle, or None to use the default one
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the advertising started, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the timeout is longer than NimBLE allows
+    /// - `BleError::StartingAdvertisementError`: If NimBLE could not start the advertising
+    fn start(
+        &mut self,
+        target: BLEAddress,
+        own_addr_type: u8,
+        interval: Option<(u16, u16)>,
+    ) -> Result<(), BleError> {
+        let duration_ms = match self.timeout {
+            Some(timeout) => {
+                i32::try_from(timeout.as_millis()).map_err(|_| BleError::InvalidParameters(None))?
+            }
+            None => BLE_HS_FOREVER as i32,
+        };
+        let (min_interval, max_interval) = interval.unwrap_or((0, 0));
+        let mut params = ble_gap_adv_params {
+            conn_mode: BLE_GAP_CONN_MODE_DIR as u8,
+            disc_mode: BLE_GAP_DISC_MODE_NON as u8,
+            itvl_min: min_interval,
+            itvl_max: max_interval,
+            ..Default::default()
+        };
+        params.set_high_duty_cycle(self.high_duty_cycle as u8);
+        let peer = ble_addr_t {
+            type_: target.addr_type() as u8,
+            val: target.as_le_bytes(),
+        };
+
+        self.state.lock().outcome = DirectedOutcome::Advertising;
+        let res = unsafe {
+            ble_gap_adv_start(
+                own_addr_type,
+                &peer,
+                duration_ms,
+                &params,
+                Some(directed_advertising_callback),
+                Arc::as_ptr(&self.state) as *mut c_void,
+            )
+        };
+        BLEError::convert(res as u32).map_err(|_| BleError::StartingAdvertisementError)
+    }
+
+    /// Gets the outcome of the last directed advertising
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with Ok and the information of the target if it connected, an Err if the
+    /// advertising timed out, or None if the advertising did not end
+    fn result(&self) -> Option<Result<ConnectionInformation, BleError>> {
+        match self.state.lock().outcome {
+            DirectedOutcome::Advertising => None,
+            DirectedOutcome::Connected(info) => Some(Ok(info)),
+            DirectedOutcome::TimedOut => Some(Err(BleError::TimeOut(None))),
+        }
+    }
+
+    /// Checks whether the advertising timed out since the last call
+    fn take_timeout(&self) -> bool {
+        std::mem::take(&mut self.state.lock().timeout_pending)
+    }
+}
+
+impl DirectedAdvertisingState {
+    /// Handles a NimBLE event of the directed advertising or of a connection made through it
+    ///
+    /// # Arguments
+    ///
+    /// - `event`: The ble_gap_event received
+    fn handle_event(&mut self, event: &ble_gap_event) {
+        match event.type_ as u32 {
+            BLE_GAP_EVENT_CONNECT => {
+                // A failed connection ends the advertising, which is reported with its own event
+                let connect = unsafe { event.__bindgen_anon_1.connect };
+                if connect.status != 0 {
+                    return;
+                }
+                let mut desc = ble_gap_conn_desc::default();
+                if unsafe { ble_gap_conn_find(connect.conn_handle, &mut desc) } != 0 {
+                    return;
+                }
+                let info = connection_information(&desc, true, Ok(()));
+                self.outcome = DirectedOutcome::Connected(info);
+                self.events.push(BleServerEvent::Connected(info));
+                self.connection_notifier.notify();
+                _ = self.on_connection.send_timeout(info, 1_000_000);
+            }
+            BLE_GAP_EVENT_DISCONNECT => {
+                let disconnect = unsafe { event.__bindgen_anon_1.disconnect };
+                let res = BLEError::convert(disconnect.reason as u32);
+                let info = connection_information(&disconnect.conn, false, res);
+                self.events.push(BleServerEvent::Disconnected(info));
+                self.disconnection_notifier.notify();
+                _ = self.on_disconnection.send_timeout(info, 1_000_000);
+            }
+            BLE_GAP_EVENT_ADV_COMPLETE => {
+                let adv_complete = unsafe { event.__bindgen_anon_1.adv_complete };
+                if adv_complete.reason as u32 == BLE_HS_ETIMEOUT {
+                    self.time_out();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets the directed advertising as timed out and wakes up the microcontroller to execute
+    /// the user callback
+    fn time_out(&mut self) {
+        self.outcome = DirectedOutcome::TimedOut;
+        self.timeout_pending = true;
+        self.connection_notifier.notify();
+    }
+}
+
 #[sharable_reference_wrapper]
 impl<'a> _BleServer<'a> {
     /// Creates a new _BleServer. This server will have a one client as maximum default amount
@@ -224,14 +655,36 @@ impl<'a> _BleServer<'a> {
         connection_notifier: Notifier,
         disconnection_notifier: Notifier,
     ) -> Result<Self, BleError> {
+        let user_on_connection = ConnectionCallback::new(connection_notifier.clone());
+        let user_on_disconnection = ConnectionCallback::new(disconnection_notifier);
+        let events = Arc::new(EventChannel::new());
+        let directed =
+            DirectedAdvertising::new(&user_on_connection, &user_on_disconnection, events.clone());
         let mut server = _BleServer {
             advertising_name: name,
             ble_server: ble_device.get_server(),
             services: services.clone(),
             advertisement: ble_device.get_advertising(),
             remaining_connections: RemainingConnections::new(DEFAULT_MAX_CLIENTS),
-            user_on_connection: Some(ConnectionCallback::new(connection_notifier)),
-            user_on_disconnection: Some(ConnectionCallback::new(disconnection_notifier)),
+            user_on_connection: Some(user_on_connection),
+            user_on_disconnection: Some(user_on_disconnection),
+            periodic_notifications: vec![],
+            write_callbacks: vec![],
+            indication_callbacks: vec![],
+            notify_tx_hooks: vec![],
+            pairing: Some(PairingCallbacks::new(connection_notifier.clone())),
+            whitelist: vec![],
+            subscriptions: vec![],
+            descriptors: vec![],
+            ble_services: vec![],
+            ble_characteristics: vec![],
+            advertising_interval: None,
+            directed,
+            advertisement_payload: None,
+            own_address_type: OwnAddressType::Public,
+            appearance: None,
+            events,
+            notifier: connection_notifier,
         };
 
         for service in services {
@@ -246,12 +699,18 @@ impl<'a> _BleServer<'a> {
         let user_on_connection = self.user_on_connection.as_mut().unwrap();
         let notifier_ref = user_on_connection.notifier.clone();
         let mut con_info_ref = user_on_connection.info_queue.clone();
+        let pairing = self.pairing.as_ref().unwrap();
+        let passkey_display = pairing.displays_passkey().then(|| pairing.channel());
+        let events = self.events.clone();
         self.ble_server.on_connect(move |_, info| {
+            if let Some(channel) = &passkey_display {
+                let passkey = channel.display_random_passkey();
+                BLEDevice::take().security().set_passkey(passkey);
+            }
+            let info = ConnectionInformation::from_bleconn_desc(info, true, Ok(()));
+            events.push(BleServerEvent::Connected(info));
             notifier_ref.notify();
-            _ = con_info_ref.send_timeout(
-                ConnectionInformation::from_bleconn_desc(info, true, Ok(())),
-                1_000_000,
-            );
+            _ = con_info_ref.send_timeout(info, 1_000_000);
         });
     }
 
@@ -260,13 +719,13 @@ impl<'a> _BleServer<'a> {
         let user_on_disconnection = self.user_on_disconnection.as_mut().unwrap();
         let notifier_ref = user_on_disconnection.notifier.clone();
         let mut con_info_ref = user_on_disconnection.info_queue.clone();
+        let events = self.events.clone();
 
         self.ble_server.on_disconnect(move |info, res| {
+            let info = ConnectionInformation::from_bleconn_desc(info, false, res);
+            events.push(BleServerEvent::Disconnected(info));
             notifier_ref.notify();
-            _ = con_info_ref.send_timeout(
-                ConnectionInformation::from_bleconn_desc(info, false, res),
-                1_000_000,
-            );
+            _ = con_info_ref.send_timeout(info, 1_000_000);
         });
     }
 
@@ -320,6 +779,88 @@ impl<'a> _BleServer<'a> {
         self
     }
 
+    /// Gets the stream of the events of the server, an alternative to the connection, disconnection
+    /// and write handlers that can be consumed inside [crate::Microcontroller::block_on]. Events
+    /// are only queued after the stream is requested, so it should be done before starting the
+    /// server. Handlers set before or after keep being executed too.
+    ///
+    /// # Returns
+    ///
+    /// The `BleServerEvents` stream of the server
+    pub fn events(&mut self) -> BleServerEvents {
+        self.subscribe_on_connection();
+        self.subscribe_on_disconnection();
+        BleServerEvents::new(self.events.clone())
+    }
+
+    /// Sets the callback executed with the passkey that a client must enter to pair. Once set, a new random
+    /// passkey is generated every time a client connects, replacing the one of the [crate::ble::utils::Security].
+    /// Meant for servers with the `DisplayOnly`, `DisplayYesNo` or `KeyboardDisplay` capabilities.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the passkey to display
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn on_passkey_display<C: FnMut(u32) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.pairing.as_mut().unwrap().set_passkey_display(callback);
+        self.subscribe_on_connection();
+        self
+    }
+
+    /// Sets the callback executed during a numeric comparison pairing, where both devices show the same
+    /// number and the user must confirm that they match. If the callback is not answered in time the
+    /// pairing is rejected. Meant for servers with the `DisplayYesNo` or `KeyboardDisplay` capabilities.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the number to compare and returns true to accept the pairing
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn on_numeric_comparison<C: FnMut(u32) -> bool + 'a>(&mut self, callback: C) -> &mut Self {
+        let pairing = self.pairing.as_mut().unwrap();
+        pairing.set_numeric_comparison(callback);
+        let channel = pairing.channel();
+        self.ble_server
+            .on_confirm_pin(move |number| channel.confirm_number(number));
+        self
+    }
+
+    /// Sets the callback executed when the client displays a passkey that must be entered on the server.
+    /// Meant for servers with the `KeyboardOnly` or `KeyboardDisplay` capabilities.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that returns the passkey entered by the user
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn on_passkey_entry<C: FnMut() -> u32 + 'a>(&mut self, callback: C) -> &mut Self {
+        let pairing = self.pairing.as_mut().unwrap();
+        pairing.set_passkey_entry(callback);
+        let channel = pairing.channel();
+        self.ble_server
+            .on_passkey_request(move || channel.enter_passkey());
+        self
+    }
+
     /// The conn_handle is obtained with the ConnectionInformation inside the closure of
     /// connection_handler
     ///
@@ -361,6 +902,52 @@ impl<'a> _BleServer<'a> {
             .map_err(BleError::from_connection_params_context)
     }
 
+    /// Asks a client to use other connection parameters, with an L2CAP connection parameter
+    /// update request, since as the peripheral the server can not change them by itself. Useful to
+    /// ask for a short interval while transferring data, or for a long interval and some latency
+    /// to save energy while idle. Blocks until the client answers. Once accepted, the client
+    /// applies the parameters on its own, which takes a few connection events.
+    ///
+    /// # Arguments
+    ///
+    /// - `client`: A reference to the `ConnectionInformation` of the client.
+    /// - `min_interval`: The minimum connection interval, time between BLE events. This value
+    ///   must range between 7.5ms and 4000ms in 1.25ms units.
+    /// - `max_interval`: The maximum connection interval, time between BLE events. This value
+    ///   must range between 7.5ms and 4000ms in 1.25ms units.
+    /// - `latency`: The number of connection events the server can skip when it has no data to send.
+    /// - `timeout`: The maximum time to wait after the last packet arrived to consider connection
+    ///   lost, in 10ms units.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the client accepted the parameters, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ConnectionParamsRejected`: If the client rejected the parameters.
+    /// - `BleError::Disconnected`: If the client is no longer connected.
+    /// - `BleError::InvalidParameters`: If the parameters are out of range.
+    /// - `BleError::TimeOut`: If the client did not answer in 30 seconds.
+    /// - `BleError::Code`: on other errors.
+    pub fn request_connection_params(
+        &mut self,
+        client: &ConnectionInformation,
+        min_interval: u16,
+        max_interval: u16,
+        latency: u16,
+        timeout: u16,
+    ) -> Result<(), BleError> {
+        self.check_client_connected(client)?;
+        request_connection_params(
+            client.conn_handle,
+            min_interval,
+            max_interval,
+            latency,
+            timeout,
+        )
+    }
+
     /// Sets the max amount of clients the server can be connected to concurrently at any given time.
     /// After each connection a new advertisement will be made if there are still connections left to be done.
     ///
@@ -390,7 +977,8 @@ impl<'a> _BleServer<'a> {
         Ok(())
     }
 
-    /// Set the advertising time parameters.
+    /// Set the advertising time parameters. The values are not validated, see
+    /// [Self::set_advertising_params] to set them from durations.
     ///
     /// # Arguments
     ///
@@ -403,6 +991,7 @@ impl<'a> _BleServer<'a> {
     ///
     /// The _BleServer itself
     pub fn set_advertising_interval(&mut self, min_interval: u16, max_interval: u16) -> &mut Self {
+        self.advertising_interval = Some((min_interval, max_interval));
         self.advertisement
             .lock()
             .min_interval(min_interval)
@@ -410,87 +999,363 @@ impl<'a> _BleServer<'a> {
         self
     }
 
-    /// Sets a high duty cycle has intervals between advertising packets are
-    /// typically in the range of 20 ms to 100 ms.
-    /// Valid only if advertisement_type is directed-connectable.
+    /// Sets the advertising parameters validated by an [crate::ble::utils::AdvertisingParamsBuilder].
+    /// If the params change the address type and the server is advertising, the advertisement is
+    /// restarted.
+    ///
+    /// # Arguments
+    ///
+    /// - `params`: The AdvertisingParams to use
     ///
     /// # Returns
     ///
-    /// The _BleServer itself
-    pub fn set_high_advertising_duty_cycle(&mut self) -> &mut Self {
-        self.advertisement.lock().high_duty_cycle(true);
-        self
+    /// A `Result` with Ok if the params were set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidChannelMap`: If the params do not use every channel. NimBLE always sends
+    ///   the legacy advertisements of the server on the three primary channels
+    /// - `BleError::InvalidParameters`: If the address type is `NonResolvablePrivate`, or the given
+    ///   random static address is not valid
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    pub fn set_advertising_params(&mut self, params: &AdvertisingParams) -> Result<(), BleError> {
+        if !params.uses_all_channels() {
+            return Err(BleError::InvalidChannelMap);
+        }
+        if let Some(address_type) = params.own_address_type() {
+            if address_type != self.own_address_type {
+                self.set_address_type(address_type)?;
+            }
+        }
+        match params.interval_units() {
+            Some((min_interval, max_interval)) => {
+                self.set_advertising_interval(min_interval, max_interval);
+            }
+            None => {
+                self.advertising_interval = None;
+                self.restore_advertising_interval();
+            }
+        }
+        Ok(())
     }
 
-    /// Sets a low duty cycle has ntervals between advertising packets are
-    /// typically in the range of 1,000 ms to 10,240 ms.
-    /// Valid only if advertisement_type is directed-connectable.
+    /// Replaces the advertisement data generated from the name and services of the server with a
+    /// custom payload, for example to advertise manufacturer data or the appearance. If the payload
+    /// has no flags field, the general discoverable flags are added at its start.
+    ///
+    /// # Arguments
+    ///
+    /// - `payload`: The AdvertisementPayload to advertise
     ///
     /// # Returns
     ///
-    /// The _BleServer itself
-    pub fn set_low_advertising_duty_cycle(&mut self) -> &mut Self {
-        self.advertisement.lock().high_duty_cycle(false);
-        self
+    /// A `Result` with Ok if the payload is being advertised, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceDoesNotFit`: If the payload, including the flags, is longer than 31 bytes
+    /// - `BleError::InvalidParameters`: If a field of the payload is too long
+    /// - `BleError::AdvertisementError`: If the advertisement data could not be set
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    pub fn set_advertisement_payload(
+        &mut self,
+        payload: &AdvertisementPayload,
+    ) -> Result<(), BleError> {
+        let payload = payload.with_default_flags();
+        payload.build()?;
+        self.advertisement_payload = Some(payload);
+        self.refresh_advertisement()
     }
 
-    /// Sets the discoverable mode for the server.
+    /// Goes back to advertising the name and services of the server, after a custom payload was set
+    /// with [Self::set_advertisement_payload].
     ///
-    /// # Arguments
+    /// # Returns
     ///
-    /// - `disc_mode`: A DiscoverableMode that the user decides to set
+    /// A `Result` with Ok if the advertisement data was regenerated, or a `BleError` if it fails.
     ///
-    /// # Returns
+    /// # Errors
     ///
-    /// The _BleServer itself
-    pub fn set_discoverable_mode(&mut self, disc_mode: DiscoverableMode) -> &mut Self {
-        match disc_mode {
-            DiscoverableMode::NonDiscoverable => {
-                self.advertisement.lock().disc_mode(disc_mode.get_code())
-            }
-            DiscoverableMode::GeneralDiscoverable(min_interval, max_interval) => self
-                .advertisement
-                .lock()
-                .disc_mode(disc_mode.get_code())
-                .min_interval(min_interval)
-                .max_interval(max_interval),
-        };
-        self.advertisement.lock().disc_mode(disc_mode.get_code());
-        self
+    /// - `BleError::AdvertisementError`: If the advertisement data could not be set
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    pub fn clear_advertisement_payload(&mut self) -> Result<(), BleError> {
+        self.advertisement_payload = None;
+        self.refresh_advertisement()
     }
 
-    ///Sets the connection mode of the advertisment.
+    /// Sets which requests only the devices on the whitelist can make. By default the whitelist is not used.
+    /// The policy does not apply to the directed advertising, where only the target device can connect.
     ///
     /// # Arguments
     ///
-    /// - `conn_mode`: A ConnectionMode that the user decisdes to set
+    /// - `policy`: The AdvertisingFilterPolicy to use
     ///
     /// # Returns
     ///
     /// The _BleServer itself
-    pub fn set_connection_mode(&mut self, conn_mode: ConnectionMode) -> &mut Self {
-        self.advertisement
-            .lock()
-            .advertisement_type(conn_mode.get_code());
+    pub fn set_advertising_filter_policy(&mut self, policy: AdvertisingFilterPolicy) -> &mut Self {
+        self.advertisement.lock().filter_policy(policy.get_code());
         self
     }
 
-    /// Sets or overwrites a service to the server.
+    /// Adds a device to the whitelist. If the server is advertising, the advertisement is restarted.
     ///
     /// # Arguments
     ///
-    /// - `service`: A Service struct
+    /// - `address`: The BLEAddress of the device
     ///
     /// # Returns
     ///
-    /// A `Result` with Ok if the operation completed successfully, or an `BleError` if it fails.
+    /// A `Result` with Ok if the whitelist was updated, or a `BleError` if it fails.
     ///
     /// # Errors
     ///
-    /// - `BleError::PropertiesError`: If a characteristic on the service has an invalid property.
-    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server.
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    /// - `BleError::Code`: If the controller rejected the whitelist
+    pub fn add_to_whitelist(&mut self, address: BLEAddress) -> Result<(), BleError> {
+        if !self.whitelist.contains(&address) {
+            self.whitelist.push(address);
+        }
+        self.apply_whitelist()
+    }
+
+    /// Removes a device from the whitelist. If the server is advertising, the advertisement is restarted.
+    ///
+    /// # Arguments
+    ///
+    /// - `address`: The BLEAddress of the device
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the whitelist was updated, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::NotFound`: If the device is not on the whitelist
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    /// - `BleError::Code`: If the controller rejected the whitelist
+    pub fn remove_from_whitelist(&mut self, address: BLEAddress) -> Result<(), BleError> {
+        let index = self
+            .whitelist
+            .iter()
+            .position(|whitelisted| *whitelisted == address)
+            .ok_or(BleError::NotFound(None))?;
+        self.whitelist.remove(index);
+        self.apply_whitelist()
+    }
+
+    /// Gets the devices on the whitelist
+    ///
+    /// # Returns
+    ///
+    /// A vector with the BLEAddress of each device
+    pub fn get_whitelist(&self) -> Vec<BLEAddress> {
+        self.whitelist.clone()
+    }
+
+    /// Sets the whitelist on the controller. The controller does not accept changes while advertising,
+    /// so the advertisement is stopped and then restarted.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the whitelist was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    /// - `BleError::Code`: If the controller rejected the whitelist
+    fn apply_whitelist(&mut self) -> Result<(), BleError> {
+        let advertising = self.advertisement.lock().is_advertising();
+        if advertising {
+            self.stop_advertisement()?;
+        }
+        let addresses: Vec<ble_addr_t> = self
+            .whitelist
+            .iter()
+            .map(|address| ble_addr_t {
+                type_: address.addr_type() as u8,
+                val: address.as_le_bytes(),
+            })
+            .collect();
+        let res = BLEError::convert(unsafe {
+            ble_gap_wl_set(addresses.as_ptr(), addresses.len() as u8) as u32
+        });
+        if advertising {
+            self.start()?;
+        }
+        Ok(res?)
+    }
+
+    /// Sets a high duty cycle has intervals between advertising packets are
+    /// typically in the range of 20 ms to 100 ms.
+    /// Valid only if advertisement_type is directed-connectable. Unless a shorter timeout is set with
+    /// [Self::set_directed_advertising_timeout], the controller stops the advertising after 1.28s.
+    /// The mode is applied the next time the advertisement starts.
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn set_high_advertising_duty_cycle(&mut self) -> &mut Self {
+        self.directed.high_duty_cycle = true;
+        self
+    }
+
+    /// Sets a low duty cycle has ntervals between advertising packets are
+    /// typically in the range of 1,000 ms to 10,240 ms.
+    /// Valid only if advertisement_type is directed-connectable. The advertising uses the interval
+    /// set with [Self::set_advertising_interval]. The mode is applied the next time the
+    /// advertisement starts.
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn set_low_advertising_duty_cycle(&mut self) -> &mut Self {
+        self.directed.high_duty_cycle = false;
+        self
+    }
+
+    /// Sets the time after which the directed advertising stops if the target device did not connect,
+    /// executing the callback set with [Self::on_directed_advertising_timeout]. The outcome is also
+    /// returned by [Self::directed_advertising_result].
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: The max duration of the advertising. If None, the high duty cycle advertising
+    ///   stops after 1.28s and the low duty cycle advertising does not stop
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn set_directed_advertising_timeout(&mut self, timeout: Option<Duration>) -> &mut Self {
+        self.directed.timeout = timeout;
+        self
+    }
+
+    /// Sets the callback executed when the directed advertising stops because the target device did
+    /// not connect before the timeout.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the server and the address of the target device
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn on_directed_advertising_timeout<C: FnMut(&mut BleServer<'a>, BLEAddress) + 'a>(
+        &mut self,
+        callback: C,
+    ) -> &mut Self {
+        self.directed.on_timeout = Some(Box::new(callback));
+        self
+    }
+
+    /// Gets the outcome of the last directed advertising started with [Self::start]
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the ConnectionInformation of the target device if it connected, a
+    /// `BleError` if the advertising stopped before, or None if the advertising did not end or
+    /// was never directed
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimeOut`: If the target device did not connect before the timeout
+    pub fn directed_advertising_result(&self) -> Option<Result<ConnectionInformation, BleError>> {
+        self.directed.result()
+    }
+
+    /// Sets on the advertisement the interval set by the user, or the default one if none was set
+    fn restore_advertising_interval(&mut self) {
+        let (min_interval, max_interval) = self.advertising_interval.unwrap_or((0, 0));
+        self.advertisement
+            .lock()
+            .min_interval(min_interval)
+            .max_interval(max_interval);
+    }
+
+    /// Sets the discoverable mode for the server.
+    ///
+    /// # Arguments
+    ///
+    /// - `disc_mode`: A DiscoverableMode that the user decides to set
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn set_discoverable_mode(&mut self, disc_mode: DiscoverableMode) -> &mut Self {
+        match disc_mode {
+            DiscoverableMode::NonDiscoverable => {
+                self.advertisement.lock().disc_mode(disc_mode.get_code())
+            }
+            DiscoverableMode::GeneralDiscoverable(min_interval, max_interval) => self
+                .advertisement
+                .lock()
+                .disc_mode(disc_mode.get_code())
+                .min_interval(min_interval)
+                .max_interval(max_interval),
+        };
+        self.advertisement.lock().disc_mode(disc_mode.get_code());
+        self
+    }
+
+    ///Sets the connection mode of the advertisment. With `ConnectionMode::DirectedConnectable` the
+    /// server sends directed advertisements that only the target device can connect to, with the duty
+    /// cycle set with [Self::set_high_advertising_duty_cycle] or [Self::set_low_advertising_duty_cycle].
+    /// The mode is applied the next time the advertisement starts. The target device is reported to
+    /// the connection and disconnection handlers, but it is not tracked by esp32-nimble, so it is not
+    /// listed by [Self::list_clients].
+    ///
+    /// # Arguments
+    ///
+    /// - `conn_mode`: A ConnectionMode that the user decisdes to set
+    ///
+    /// # Returns
+    ///
+    /// The _BleServer itself
+    pub fn set_connection_mode(&mut self, conn_mode: ConnectionMode) -> &mut Self {
+        self.directed.target = conn_mode.target();
+        if self.directed.target.is_none() {
+            self.advertisement
+                .lock()
+                .advertisement_type(conn_mode.get_code());
+        }
+        self
+    }
+
+    /// Sets or overwrites a service to the server.
+    ///
+    /// # Arguments
+    ///
+    /// - `service`: A Service struct
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::PropertiesError`: If a characteristic on the service has an invalid property.
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server.
     pub fn set_service(&mut self, service: &Service) -> Result<(), BleError> {
-        self.ble_server.create_service(service.id.to_uuid());
+        if self.get_server_service(&service.id).is_err() {
+            let ble_service = self.ble_server.create_service(service.id.to_uuid());
+            self.ble_services.push(ServerService {
+                service_id: service.id.clone(),
+                service: ble_service,
+            });
+        }
+        self.services.retain(|current| current.id != service.id);
+        self.services.push(service.clone());
 
         for characteristic in &service.characteristics {
             self.set_characteristic(&service.id, characteristic)?;
@@ -498,6 +1363,149 @@ impl<'a> _BleServer<'a> {
         Ok(())
     }
 
+    /// Removes a service from the server, regenerating the advertisement data so it is no longer
+    /// advertised. Its characteristics stop answering reads and writes, and every callback, periodic
+    /// notification and subscription of them is dropped.
+    ///
+    /// Note: esp32-nimble cannot unregister the attributes of a started server, so the service stays
+    /// on the attribute table, empty and inert, until the next reboot. Setting it again with
+    /// [Self::set_service] only restores the values of its characteristics.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the service was removed, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service set on the server
+    /// - `BleError::AdvertisementError`: If the advertisement data could not be regenerated
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped to be regenerated
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    pub fn remove_service(&mut self, service_id: &BleId) -> Result<(), BleError> {
+        let index = self
+            .services
+            .iter()
+            .position(|service| service.id == *service_id)
+            .ok_or(BleError::ServiceNotFound(None))?;
+        let service = self.services.remove(index);
+        for characteristic in &service.characteristics {
+            self.detach_characteristic(service_id, &characteristic.id);
+        }
+        self.periodic_notifications
+            .retain(|notification| notification.service_id != *service_id);
+        self.refresh_advertisement()
+    }
+
+    /// Removes a characteristic from a service of the server, regenerating the advertisement data. The
+    /// characteristic stops answering reads and writes, and every callback and subscription of it is
+    /// dropped.
+    ///
+    /// Note: esp32-nimble cannot unregister the attributes of a started server, so the characteristic
+    /// stays on the attribute table, empty and inert, until the next reboot.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the characteristic was removed, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic is not part of the service
+    /// - `BleError::AdvertisementError`: If the advertisement data could not be regenerated
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped to be regenerated
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    pub fn remove_characteristic(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<(), BleError> {
+        let service = self
+            .services
+            .iter_mut()
+            .find(|service| service.id == *service_id)
+            .ok_or(BleError::ServiceNotFound(None))?;
+        let index = service
+            .characteristics
+            .iter()
+            .position(|characteristic| characteristic.id == *characteristic_id)
+            .ok_or(BleError::CharacteristicNotFound(None))?;
+        service.characteristics.remove(index);
+        self.detach_characteristic(service_id, characteristic_id);
+        self.refresh_advertisement()
+    }
+
+    /// Leaves a characteristic of the attribute table inert: its value is cleared, its esp32-nimble
+    /// callbacks replaced by ones that do nothing, and the framework callbacks and subscriptions dropped.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    fn detach_characteristic(&mut self, service_id: &BleId, characteristic_id: &BleId) {
+        if let Ok(characteristic) = self.get_server_characteristic(service_id, characteristic_id) {
+            let mut characteristic = characteristic.lock();
+            characteristic.set_value(&[]);
+            characteristic.on_read(|_, _| {});
+            characteristic.on_write(|_| {});
+            characteristic.on_subscribe(|_, _, _| {});
+            characteristic.on_notify_tx(|_| {});
+        }
+
+        let is_detached = |service: &BleId, characteristic: &BleId| {
+            service == service_id && characteristic == characteristic_id
+        };
+        self.write_callbacks
+            .retain(|callback| !is_detached(&callback.service_id, &callback.characteristic_id));
+        self.indication_callbacks
+            .retain(|callback| !is_detached(&callback.service_id, &callback.characteristic_id));
+        self.notify_tx_hooks.retain(|hook| {
+            if !is_detached(&hook.service_id, &hook.characteristic_id) {
+                return true;
+            }
+            for waiter in hook.waiters.lock().drain(..) {
+                waiter.pending.lock().clear();
+                waiter.notifier.notify();
+            }
+            false
+        });
+        self.subscriptions.retain(|subscription| {
+            !is_detached(&subscription.service_id, &subscription.characteristic_id)
+        });
+        self.descriptors.retain(|descriptor| {
+            !is_detached(&descriptor.service_id, &descriptor.characteristic_id)
+        });
+    }
+
+    /// Regenerates the advertisement data from the services of the server. If the server is
+    /// advertising, the advertisement is restarted with the new data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the advertisement data was regenerated, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::AdvertisementError`: If the advertisement data could not be set
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    fn refresh_advertisement(&mut self) -> Result<(), BleError> {
+        let advertising = self.advertisement.lock().is_advertising();
+        if !advertising {
+            return self.create_advertisement_data();
+        }
+        self.stop_advertisement()?;
+        self.start()
+    }
+
     /// Sets or overwrites multiple services to the server.
     ///
     /// # Arguments
@@ -533,22 +1541,27 @@ impl<'a> _BleServer<'a> {
     ///
     /// - `BleError::PropertiesError`: If a characteristic on the service has an invalid property
     /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::InvalidParameters`: If the max length of the characteristic is longer than 512 bytes
+    /// - `BleError::ValueTooLong`: If the data is longer than the max length of the characteristic
     pub fn set_characteristic(
         &mut self,
         service_id: &BleId,
         characteristic: &Characteristic,
     ) -> Result<(), BleError> {
-        let server_service =
-            task::block_on(async { self.ble_server.get_service(service_id.to_uuid()).await });
-
-        match server_service {
-            Some(service) => {
-                match self.try_to_update_characteristic(service, characteristic, false) {
-                    Ok(_) => Ok(()),
-                    Err(_) => self.create_new_characteristic(characteristic, service),
-                }
-            }
-            None => Err(BleError::ServiceNotFound),
+        characteristic.check_length()?;
+        let server_service = self.get_server_service(service_id);
+
+        if let Some(service) = self.services.iter_mut().find(|s| s.id == *service_id) {
+            service
+                .characteristics
+                .retain(|current| current.id != characteristic.id);
+            service.characteristics.push(characteristic.clone());
+        }
+
+        let service = server_service?;
+        match self.try_to_update_characteristic(service_id, characteristic, false) {
+            Ok(_) => Ok(()),
+            Err(_) => self.create_new_characteristic(service_id, characteristic, &service),
         }
     }
 
@@ -556,9 +1569,10 @@ impl<'a> _BleServer<'a> {
     ///
     /// # Arguments
     ///
-    /// - `service`: The service to which the characteristic will be added
+    /// - `service_id`: A BleId to identify the service
     /// - `characteristic`: A Characteristic struct that will contain all the onformation of the characteristic
     ///   that wants to be set
+    /// - `service`: The service to which the characteristic will be added
     ///
     /// # Returns
     ///  
@@ -568,7 +1582,8 @@ impl<'a> _BleServer<'a> {
     ///
     /// - `BleError::PropertiesError`: If a characteristic on the service has an invalid property
     fn create_new_characteristic(
-        &self,
+        &mut self,
+        service_id: &BleId,
         characteristic: &Characteristic,
         service: &Arc<Mutex<BLEService>>,
     ) -> Result<(), BleError> {
@@ -577,6 +1592,11 @@ impl<'a> _BleServer<'a> {
                 let charac = service
                     .lock()
                     .create_characteristic(characteristic.id.to_uuid(), properties);
+                self.ble_characteristics.push(ServerCharacteristic {
+                    service_id: service_id.clone(),
+                    characteristic_id: characteristic.id.clone(),
+                    characteristic: charac.clone(),
+                });
                 let mut unlocked_char = charac.lock();
                 unlocked_char.set_value(&characteristic.data);
 
@@ -586,11 +1606,65 @@ impl<'a> _BleServer<'a> {
                             let ble_descriptor = unlocked_char
                                 .create_descriptor(descriptor.id.to_uuid(), properties);
                             ble_descriptor.lock().set_value(&descriptor.data);
+                            self.descriptors.push(CharacteristicDescriptor {
+                                service_id: service_id.clone(),
+                                characteristic_id: characteristic.id.clone(),
+                                descriptor_id: descriptor.id.clone(),
+                                descriptor: ble_descriptor,
+                            });
                         }
                         Err(_) => return Err(BleError::PropertiesError),
                     };
                 }
 
+                if characteristic.is_writable() {
+                    let events = self.events.clone();
+                    let max_length = characteristic.max_length;
+                    let (service, characteristic) = (service_id.clone(), characteristic.id.clone());
+                    unlocked_char.on_write(move |args| {
+                        if reject_if_too_long(args, max_length) {
+                            return;
+                        }
+                        events.push(BleServerEvent::CharacteristicWritten {
+                            service_id: service.clone(),
+                            characteristic_id: characteristic.clone(),
+                            info: ConnectionInformation::from_bleconn_desc(
+                                args.desc(),
+                                true,
+                                Ok(()),
+                            ),
+                            data: args.recv_data().to_vec(),
+                        });
+                    });
+                }
+
+                if characteristic.is_notifiable() || characteristic.is_indicatable() {
+                    let clients = Arc::new(Mutex::new(vec![]));
+                    let subscribed = clients.clone();
+                    let events = self.events.clone();
+                    let (service, characteristic_id) =
+                        (service_id.clone(), characteristic.id.clone());
+                    unlocked_char.on_subscribe(move |_, desc, subscription| {
+                        let mut subscribed = subscribed.lock();
+                        subscribed.retain(|(handle, _)| *handle != desc.conn_handle());
+                        if !subscription.is_empty() {
+                            subscribed.push((desc.conn_handle(), subscription));
+                        }
+                        drop(subscribed);
+                        events.push(BleServerEvent::SubscriptionChanged {
+                            service_id: service.clone(),
+                            characteristic_id: characteristic_id.clone(),
+                            info: ConnectionInformation::from_bleconn_desc(desc, true, Ok(())),
+                            notify: subscription.contains(NimbleSub::NOTIFY),
+                            indicate: subscription.contains(NimbleSub::INDICATE),
+                        });
+                    });
+                    self.subscriptions.push(CharacteristicSubscriptions {
+                        service_id: service_id.clone(),
+                        characteristic_id: characteristic.id.clone(),
+                        clients,
+                    });
+                }
                 Ok(())
             }
             None => Err(BleError::PropertiesError),
@@ -602,7 +1676,7 @@ impl<'a> _BleServer<'a> {
     ///
     /// # Arguments
     ///
-    /// - `service`: An `Arc<Mutex<BLEService>>` that contains the service that has the characteristic to update
+    /// - `service_id`: A BleId to identify the service that has the characteristic to update
     /// - `characteristic`: A Characteristic struct that contains the updated information
     /// - `notify`: A boolean that indicates wheter to notify the characteristic or not.
     ///
@@ -612,29 +1686,22 @@ impl<'a> _BleServer<'a> {
     ///
     /// # Errors
     ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
     /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
     fn try_to_update_characteristic(
         &self,
-        service: &Arc<Mutex<BLEService>>,
+        service_id: &BleId,
         characteristic: &Characteristic,
         notify: bool,
     ) -> Result<(), BleError> {
-        // Check if there is a characteristic with characteristic.id in this service
-        let locked_service = service.lock();
-        let server_characteristic = task::block_on(async {
-            locked_service
-                .get_characteristic(characteristic.id.to_uuid())
-                .await
-        });
-        if let Some(server_characteristic) = server_characteristic {
-            let mut res_characteristic = server_characteristic.lock();
-            res_characteristic.set_value(&characteristic.data);
-            if notify {
-                res_characteristic.notify();
-            }
-            return Ok(());
+        let server_characteristic =
+            self.get_server_characteristic(service_id, &characteristic.id)?;
+        let mut res_characteristic = server_characteristic.lock();
+        res_characteristic.set_value(&characteristic.data);
+        if notify {
+            res_characteristic.notify();
         }
-        Err(BleError::CharacteristicNotFound)
+        Ok(())
     }
 
     /// Notifies to the client the value of the characteristic
@@ -652,6 +1719,7 @@ impl<'a> _BleServer<'a> {
     ///
     /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
     /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    /// - `BleError::ValueTooLong`: If the data is longer than the max length the characteristic was set with
     pub fn notify_value(
         &mut self,
         service_id: &BleId,
@@ -660,13 +1728,537 @@ impl<'a> _BleServer<'a> {
         if !characteristic.is_notifiable() {
             return Err(BleError::CharacteristicNotNotifiable);
         }
-        let server_service =
-            task::block_on(async { self.ble_server.get_service(service_id.to_uuid()).await });
-        if let Some(service) = server_service {
-            self.try_to_update_characteristic(service, characteristic, true)?;
-            return Ok(());
+        self.check_value_length(service_id, characteristic)?;
+        self.try_to_update_characteristic(service_id, characteristic, true)
+    }
+
+    /// Notifies the value of the characteristic as in [Self::notify_value], registering a TxWaiter
+    /// for the clients subscribed to it before sending.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `charactersitic`: A Characteristic struct that represents the characteristic to notify.
+    /// - `notifier`: A Notifier used to wake up the async method on each result
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the connection handles of the clients whose result is pending, or an
+    /// `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::CharacteristicNotNotifiable`: If the characteristic does not have the NOTIFY property
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    /// - `BleError::ValueTooLong`: If the data is longer than the max length the characteristic was set with
+    fn notify_with_waiter(
+        &mut self,
+        service_id: &BleId,
+        characteristic: &Characteristic,
+        notifier: Notifier,
+    ) -> Result<PendingClients, BleError> {
+        if !characteristic.is_notifiable() {
+            return Err(BleError::CharacteristicNotNotifiable);
+        }
+        let handles: Vec<u16> = self
+            .client_configurations(service_id, &characteristic.id)?
+            .iter()
+            .filter(|client| client.notify || client.indicate)
+            .map(|client| client.info.conn_handle)
+            .collect();
+        let pending: PendingClients = Arc::new(Mutex::new(handles));
+        if !pending.lock().is_empty() {
+            let hook = self.notify_tx_hook(service_id, &characteristic.id)?;
+            hook.waiters.lock().push(TxWaiter {
+                pending: pending.clone(),
+                notifier,
+            });
+        }
+        if let Err(err) = self.notify_value(service_id, characteristic) {
+            pending.lock().clear();
+            return Err(err);
+        }
+        Ok(pending)
+    }
+
+    /// Indicates to the subscribed clients the value of the characteristic. Unlike notifications, each
+    /// client must confirm the reception of an indication. The outcome for each client is given to the
+    /// callback set with [Self::on_indication_result].
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `charactersitic`: A Characteristic struct that represents the characteristic to indicate.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the indication was sent, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::CharacteristicNotIndicatable`: If the characteristic does not have the INDICATE property
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    /// - `BleError::ValueTooLong`: If the data is longer than the max length the characteristic was set with
+    pub fn indicate_value(
+        &mut self,
+        service_id: &BleId,
+        characteristic: &Characteristic,
+    ) -> Result<(), BleError> {
+        if !characteristic.is_indicatable() {
+            return Err(BleError::CharacteristicNotIndicatable);
+        }
+        self.check_value_length(service_id, characteristic)?;
+        self.try_to_update_characteristic(service_id, characteristic, true)
+    }
+
+    /// Sets a callback that will be executed with the result of each indication sent on the characteristic,
+    /// telling whether the client acknowledged it or it timed out. Setting more than one callback on a
+    /// characteristic keeps all of them, and each one receives every result.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    /// - `callback`: A closure that receives the BleServer, the information of the client and the IndicationResult
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the callback was set successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    pub fn on_indication_result<
+        C: FnMut(&mut BleServer<'a>, &ConnectionInformation, IndicationResult) + 'a,
+    >(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+        callback: C,
+    ) -> Result<(), BleError> {
+        let hook = self.notify_tx_hook(service_id, characteristic_id)?;
+        let result_queue = ISRQueue::new(queue_capacities().ble_indication);
+        hook.indication_queues.lock().push(result_queue.clone());
+
+        self.indication_callbacks.push(IndicationCallback {
+            service_id: service_id.clone(),
+            characteristic_id: characteristic_id.clone(),
+            user_callback: Box::new(callback),
+            result_queue,
+        });
+        Ok(())
+    }
+
+    /// Gets the NotifyTxHook of the characteristic, setting its on_notify_tx callback the first time.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the NotifyTxHook, sharing the queues and waiters of the callback, or an
+    /// `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    fn notify_tx_hook(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<NotifyTxHook, BleError> {
+        let characteristic = self.get_server_characteristic(service_id, characteristic_id)?;
+        if let Some(hook) = self.notify_tx_hooks.iter().find(|hook| {
+            hook.service_id == *service_id && hook.characteristic_id == *characteristic_id
+        }) {
+            return Ok(hook.clone());
+        }
+
+        let hook = NotifyTxHook {
+            service_id: service_id.clone(),
+            characteristic_id: characteristic_id.clone(),
+            indication_queues: Arc::new(Mutex::new(Vec::new())),
+            waiters: Arc::new(Mutex::new(Vec::new())),
+        };
+        let queues_ref = hook.indication_queues.clone();
+        let waiters_ref = hook.waiters.clone();
+        let notifier_ref = self.notifier.clone();
+        characteristic.lock().on_notify_tx(move |args| {
+            let desc = args.desc().ok();
+            let conn_handle = desc.as_ref().map(|desc| desc.conn_handle());
+            waiters_ref
+                .lock()
+                .retain(|waiter| waiter.report(conn_handle));
+
+            let Some(result) = IndicationResult::from_notify_tx_status(args.status()) else {
+                return;
+            };
+            if let Some(desc) = desc {
+                let info = ConnectionInformation::from_bleconn_desc(&desc, true, Ok(()));
+                for queue in queues_ref.lock().iter_mut() {
+                    _ = queue.send_timeout((info, result), 1_000_000);
+                }
+                notifier_ref.notify();
+            }
+        });
+
+        self.notify_tx_hooks.push(hook.clone());
+        Ok(hook)
+    }
+
+    /// Periodically notifies to the clients the characteristics returned by the reader. Every `period` the
+    /// reader is executed and each characteristic it returns is updated and notified as in [Self::notify_value].
+    /// The returned characteristics that cannot be notified are logged and skipped.
+    ///
+    /// Note: For the reader to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitics are part of.
+    /// - `timer_driver`: A TimerDriver used to keep track of the period.
+    /// - `period`: Time between each notification.
+    /// - `reader`: A closure that returns the characteristics with their updated data.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the notifications were scheduled successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimerDriverError`: If the timer driver could not be enabled.
+    pub fn notify_periodically<F: FnMut() -> Vec<Characteristic> + 'a>(
+        &mut self,
+        service_id: &BleId,
+        mut timer_driver: TimerDriver<'a>,
+        period: Duration,
+        reader: F,
+    ) -> Result<(), BleError> {
+        let due = SharableRef::new_sharable(false);
+        let mut due_ref = due.clone();
+        timer_driver.interrupt_after_n_times(
+            period.as_micros().try_into().unwrap_or(u64::MAX),
+            None,
+            true,
+            move || *due_ref.deref_mut() = true,
+        );
+        timer_driver.enable().map_err(BleError::TimerDriverError)?;
+
+        self.periodic_notifications.push(PeriodicNotification {
+            service_id: service_id.clone(),
+            _timer_driver: timer_driver,
+            due,
+            reader: Box::new(reader),
+        });
+        Ok(())
+    }
+
+    /// Gets the BLECharacteristic of the server with the corresponding ids
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the characteristic if it was found, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    fn get_server_characteristic(
+        &self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<Arc<Mutex<BLECharacteristic>>, BleError> {
+        self.get_server_service(service_id)?;
+        self.ble_characteristics
+            .iter()
+            .find(|server_characteristic| {
+                server_characteristic.service_id == *service_id
+                    && server_characteristic.characteristic_id == *characteristic_id
+            })
+            .map(|server_characteristic| server_characteristic.characteristic.clone())
+            .ok_or(BleError::CharacteristicNotFound(None))
+    }
+
+    /// Gets the BLEService created on the attribute table for the service with the given id
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the service if it was found, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    fn get_server_service(&self, service_id: &BleId) -> Result<Arc<Mutex<BLEService>>, BleError> {
+        self.ble_services
+            .iter()
+            .find(|server_service| server_service.service_id == *service_id)
+            .map(|server_service| server_service.service.clone())
+            .ok_or(BleError::ServiceNotFound(None))
+    }
+
+    /// Sets a callback that will be executed each time a client writes on the characteristic. The callback
+    /// receives the server, the information of the client that wrote and the written data. Setting a new
+    /// callback on a characteristic replaces the previous one. The characteristic must be writable for
+    /// clients to be able to write on it. Prepared writes are received once executed, with the whole
+    /// value, and writes longer than the max length of the characteristic are rejected.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    /// - `callback`: A closure that will be executed with the written data
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the callback was set successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    pub fn on_write<C: FnMut(&mut BleServer<'a>, &ConnectionInformation, Vec<u8>) + 'a>(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+        callback: C,
+    ) -> Result<(), BleError> {
+        let characteristic = self.get_server_characteristic(service_id, characteristic_id)?;
+        let notifier_ref = self.notifier.clone();
+        let max_length = self.max_length_of(service_id, characteristic_id);
+        let writes: WriteQueue = Arc::new(Mutex::new(VecDeque::new()));
+        let writes_ref = writes.clone();
+        let capacity = queue_capacities().ble_write;
+        let events = self.events.clone();
+        let (service, characteristic_ref) = (service_id.clone(), characteristic_id.clone());
+
+        characteristic.lock().on_write(move |args| {
+            if reject_if_too_long(args, max_length) {
+                return;
+            }
+            let info = ConnectionInformation::from_bleconn_desc(args.desc(), true, Ok(()));
+            events.push(BleServerEvent::CharacteristicWritten {
+                service_id: service.clone(),
+                characteristic_id: characteristic_ref.clone(),
+                info,
+                data: args.recv_data().to_vec(),
+            });
+            let mut writes = writes_ref.lock();
+            if writes.len() < capacity {
+                writes.push_back((info, args.recv_data().to_vec()));
+            } else {
+                record_dropped_item();
+            }
+            drop(writes);
+            notifier_ref.notify();
+        });
+
+        let write_callback = WriteCallback {
+            service_id: service_id.clone(),
+            characteristic_id: characteristic_id.clone(),
+            user_callback: Box::new(callback),
+            writes,
+        };
+        match self
+            .write_callbacks
+            .iter_mut()
+            .find(|callback| callback.is_for(service_id, characteristic_id))
+        {
+            Some(previous) => *previous = write_callback,
+            None => self.write_callbacks.push(write_callback),
+        }
+        Ok(())
+    }
+
+    /// Sets a callback that computes the value of the characteristic each time a client reads it. The
+    /// returned data is sent to the client and also stored as the new value of the characteristic. This
+    /// allows exposing values such as the latest sensor sample without having to update the characteristic
+    /// periodically. The characteristic must be readable for clients to be able to read it.
+    ///
+    /// Note: Since the value must be answered inmediatly, the callback is executed on the BLE task instead of
+    /// the update loop of the [crate::Microcontroller]. It should be short, and can only capture values that
+    /// can be shared between threads, such as an `Arc<Mutex<T>>` or atomics.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    /// - `callback`: A closure that receives the information of the client and returns the value to send
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the callback was set successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    pub fn on_read<C: FnMut(&ConnectionInformation) -> Vec<u8> + Send + Sync + 'static>(
+        &mut self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+        mut callback: C,
+    ) -> Result<(), BleError> {
+        let characteristic = self.get_server_characteristic(service_id, characteristic_id)?;
+        characteristic.lock().on_read(move |value, desc| {
+            let info = ConnectionInformation::from_bleconn_desc(desc, true, Ok(()));
+            value.set_value(&callback(&info));
+        });
+        Ok(())
+    }
+
+    /// Sets the standard Current Time Service on the server, so clients can read and set the time of the device.
+    /// Each time a client writes a valid time, the callback is executed with it, so it can be stored on the
+    /// framework clock (for example a DS3231).
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `date_time`: The current time of the device
+    /// - `on_time_set`: A closure that will be executed with the time written by a client
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the service was set successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::PropertiesError`: If the current time characteristic could not be created
+    pub fn set_current_time_service<C: FnMut(DateTime) + 'a>(
+        &mut self,
+        date_time: &DateTime,
+        mut on_time_set: C,
+    ) -> Result<(), BleError> {
+        self.set_service(&CurrentTimeService::service(date_time))?;
+        self.on_write(
+            &CurrentTimeService::id(),
+            &CurrentTimeService::characteristic_id(),
+            move |_, _, data| {
+                if let Ok(date_time) = CurrentTimeService::from_bytes(&data) {
+                    on_time_set(date_time)
+                }
+            },
+        )
+    }
+
+    /// Updates the time of the Current Time Service and notifies it to the clients
+    ///
+    /// # Arguments
+    ///
+    /// - `date_time`: The current time of the device
+    /// - `reason`: The reason why the time changed
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the notify operation completed successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the Current Time Service was not set with [Self::set_current_time_service]
+    /// - `BleError::CharacteristicNotFound`: If the Current Time characteristic was not found
+    pub fn update_current_time(
+        &mut self,
+        date_time: &DateTime,
+        reason: AdjustReason,
+    ) -> Result<(), BleError> {
+        let characteristic = Characteristic::new(
+            &CurrentTimeService::characteristic_id(),
+            CurrentTimeService::to_bytes(date_time, reason),
+        )
+        .notifiable(true);
+        self.notify_value(&CurrentTimeService::id(), &characteristic)
+    }
+
+    /// Sets the standard Battery Service on the server, so clients can read and subscribe to the
+    /// charge of the battery. It is updated with [Self::update_battery_level].
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The initial charge of the battery in percentage. Levels above 100 are saturated
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the service was set successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::PropertiesError`: If the battery level characteristic could not be created
+    pub fn set_battery_service(&mut self, level: u8) -> Result<(), BleError> {
+        self.set_service(&BatteryService::service(level))
+    }
+
+    /// Updates the charge of the Battery Service and notifies it to the subscribed clients
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The charge of the battery in percentage. Levels above 100 are saturated
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the notify operation completed successfully, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If the Battery Service was not set with [Self::set_battery_service]
+    /// - `BleError::CharacteristicNotFound`: If the Battery Level characteristic was not found
+    pub fn update_battery_level(&mut self, level: u8) -> Result<(), BleError> {
+        self.notify_value(
+            &BatteryService::id(),
+            &BatteryService::characteristic(level),
+        )
+    }
+
+    /// Sets the HID Service on the server, so it acts as a wireless keyboard, mouse or gamepad. The
+    /// server advertises the appearance of the device, so hosts show it with the right icon. Hosts
+    /// only accept HID devices over encrypted connections, so the server should be secure and bond
+    /// with the host.
+    ///
+    /// # Arguments
+    ///
+    /// - `kind`: The HidDeviceKind to act as
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the BleHid used to send the reports, or an `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the HID Service was already set on the server
+    pub fn set_hid_device(&mut self, kind: HidDeviceKind) -> Result<BleHid, BleError> {
+        let service_id = BleHid::service_id();
+        if self.services.iter().any(|service| service.id == service_id) {
+            return Err(BleError::InvalidParameters(None));
         }
-        Err(BleError::ServiceNotFound)
+        let hid = BleHid::new(self.ble_server, kind);
+        self.services.push(Service::new(&service_id, vec![])?);
+        self.appearance = Some(kind.appearance());
+        Ok(hid)
     }
 
     /// Starts the server and its advertisement
@@ -679,12 +2271,24 @@ impl<'a> _BleServer<'a> {
     ///
     /// - `BleError::AdvertisementError`: If the advertising operation failed
     /// - `BleError::StartingAdvertisementError`: If the starting operation failed
+    /// - `BleError::InvalidParameters`: If the timeout of the directed advertising is too long
     pub fn start(&mut self) -> Result<(), BleError> {
         self.create_advertisement_data()?;
-        self.advertisement
-            .lock()
+        let Some(target) = self.directed.target else {
+            return self
+                .advertisement
+                .lock()
+                .start()
+                .map_err(|_| BleError::StartingAdvertisementError);
+        };
+        self.ble_server
             .start()
-            .map_err(|_| BleError::StartingAdvertisementError)
+            .map_err(|_| BleError::StartingAdvertisementError)?;
+        self.directed.start(
+            target,
+            self.own_address_type.code(),
+            self.advertising_interval,
+        )
     }
 
     /// Stop the server advertisement. This function only stop the advertisement,
@@ -733,7 +2337,7 @@ impl<'a> _BleServer<'a> {
         for client in clients {
             self.ble_server
                 .disconnect(client.conn_handle())
-                .map_err(|_| BleError::Disconnected)?;
+                .map_err(|_| BleError::Disconnected(None))?;
         }
         Ok(())
     }
@@ -754,11 +2358,210 @@ impl<'a> _BleServer<'a> {
     pub fn disconnect_client(&mut self, client: &ConnectionInformation) -> Result<(), BleError> {
         self.ble_server
             .disconnect(client.conn_handle)
-            .map_err(|_| BleError::Disconnected)?;
+            .map_err(|_| BleError::Disconnected(None))?;
+
+        Ok(())
+    }
+
+    /// Reads the signal strength of the connection with a client. Unlike the rssi field of the
+    /// ConnectionInformation, which is taken when the event occurs, this reads the current value.
+    ///
+    /// # Arguments
+    ///
+    /// - `client`: A reference to the `ConnectionInformation` of the client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the RSSI in dBm, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the client is no longer connected.
+    /// - `BleError::Code`: on other errors.
+    pub fn read_rssi(&mut self, client: &ConnectionInformation) -> Result<i8, BleError> {
+        let connection = self
+            .ble_server
+            .connections()
+            .find(|connection| connection.conn_handle() == client.conn_handle)
+            .ok_or(BleError::Disconnected(None))?;
+        connection.get_rssi().map_err(BleError::from)
+    }
+
+    /// Sets the transmission power of the connection with a client, for example to reach a distant
+    /// client without raising the power of the advertisements.
+    ///
+    /// # Arguments
+    ///
+    /// - `client`: A reference to the `ConnectionInformation` of the client.
+    /// - `tx_power`: The TxPower to use
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the power was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the client is no longer connected.
+    /// - `BleError::Code`: If the controller rejects the level.
+    pub fn set_client_tx_power(
+        &mut self,
+        client: &ConnectionInformation,
+        tx_power: TxPower,
+    ) -> Result<(), BleError> {
+        self.check_client_connected(client)?;
+        TxPowerTarget::Connection(client.conn_handle).set(tx_power)
+    }
+
+    /// Gets the transmission power of the connection with a client
+    ///
+    /// # Arguments
+    ///
+    /// - `client`: A reference to the `ConnectionInformation` of the client.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the TxPower, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the client is no longer connected.
+    /// - `BleError::NotFound`: If the controller could not inform the level.
+    pub fn client_tx_power(&mut self, client: &ConnectionInformation) -> Result<TxPower, BleError> {
+        self.check_client_connected(client)?;
+        TxPowerTarget::Connection(client.conn_handle).get()
+    }
+
+    /// Gets the max length of a characteristic set on the server
+    ///
+    /// # Returns
+    ///
+    /// The max length of the characteristic, or 512 bytes if it was not set
+    fn max_length_of(&self, service_id: &BleId, characteristic_id: &BleId) -> usize {
+        self.services
+            .iter()
+            .filter(|service| service.id == *service_id)
+            .flat_map(|service| &service.characteristics)
+            .find(|characteristic| characteristic.id == *characteristic_id)
+            .map_or(MAX_ATTRIBUTE_LENGTH, |characteristic| {
+                characteristic.max_length
+            })
+    }
+
+    /// Checks that the data of a characteristic fits in the max length it was set with
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ValueTooLong`: If the data is longer than the max length
+    fn check_value_length(
+        &self,
+        service_id: &BleId,
+        characteristic: &Characteristic,
+    ) -> Result<(), BleError> {
+        if characteristic.data.len() > self.max_length_of(service_id, &characteristic.id) {
+            return Err(BleError::ValueTooLong(None));
+        }
+        Ok(())
+    }
 
+    /// Checks whether a client is still connected
+    fn check_client_connected(&self, client: &ConnectionInformation) -> Result<(), BleError> {
+        if !self
+            .ble_server
+            .connections()
+            .any(|connection| connection.conn_handle() == client.conn_handle)
+        {
+            return Err(BleError::Disconnected(None));
+        }
         Ok(())
     }
 
+    /// Gets the clients that enabled the notifications or indications of a characteristic, for
+    /// example to avoid calling [Self::notify_value] when nobody is listening.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the `ConnectionInformation` of each subscribed client, or a `BleError` if
+    /// it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not set on the server, or is not
+    ///   notifiable nor indicatable.
+    pub fn subscribed_clients(
+        &self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<Vec<ConnectionInformation>, BleError> {
+        let subscriptions = self
+            .subscriptions
+            .iter()
+            .find(|s| &s.service_id == service_id && &s.characteristic_id == characteristic_id)
+            .ok_or(BleError::CharacteristicNotFound(None))?;
+        let conn_handles: Vec<u16> = subscriptions
+            .clients
+            .lock()
+            .iter()
+            .map(|(handle, _)| *handle)
+            .collect();
+        Ok(self
+            .ble_server
+            .connections()
+            .filter(|desc| conn_handles.contains(&desc.conn_handle()))
+            .map(|desc| ConnectionInformation::from_bleconn_desc(&desc, true, Ok(())))
+            .collect())
+    }
+
+    /// Gets the Client Characteristic Configuration that each connected client wrote for a
+    /// characteristic, which tells whether it enabled the notifications and the indications. Unlike
+    /// other descriptors, each client has its own, so it can not be read with
+    /// [Self::get_descriptor_data].
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `characteristic_id`: A BleId to identify the characteristic.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the `ClientConfiguration` of each client that enabled the notifications or
+    /// indications, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not set on the server, or is not
+    ///   notifiable nor indicatable.
+    pub fn client_configurations(
+        &self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+    ) -> Result<Vec<ClientConfiguration>, BleError> {
+        let subscriptions = self
+            .subscriptions
+            .iter()
+            .find(|s| &s.service_id == service_id && &s.characteristic_id == characteristic_id)
+            .ok_or(BleError::CharacteristicNotFound(None))?;
+        let clients = subscriptions.clients.lock().clone();
+        Ok(self
+            .ble_server
+            .connections()
+            .filter_map(|desc| {
+                let (_, subscription) = clients
+                    .iter()
+                    .find(|(handle, _)| *handle == desc.conn_handle())?;
+                Some(ClientConfiguration {
+                    info: ConnectionInformation::from_bleconn_desc(&desc, true, Ok(())),
+                    notify: subscription.contains(NimbleSub::NOTIFY),
+                    indicate: subscription.contains(NimbleSub::INDICATE),
+                })
+            })
+            .collect())
+    }
+
     /// Returns the number of currently connected BLE clients.
     ///
     /// # Returns
@@ -768,7 +2571,88 @@ impl<'a> _BleServer<'a> {
         self.ble_server.connected_count()
     }
 
-    /// Creates the necessary advertisement data with the user settings
+    /// Starts listening for the L2CAP connection oriented channels opened by clients on a PSM.
+    /// Each PSM can only be listened once, since NimBLE does not allow to stop listening.
+    ///
+    /// # Arguments
+    ///
+    /// - `psm`: The Protocol/Service Multiplexer to listen on, from 0x01 to 0xFF
+    /// - `mtu`: The max size of the SDUs the server can receive, at least 23 bytes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the L2capListener used to accept the channels, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the PSM is not a LE PSM or the MTU is smaller than 23
+    /// - `BleError::Code`: If the PSM is already being listened, or L2CAP channels are disabled
+    ///   on the sdkconfig
+    pub fn listen_l2cap(&mut self, psm: u16, mtu: u16) -> Result<L2capListener, BleError> {
+        L2capListener::new(psm, mtu)
+    }
+
+    /// Gets the address of the server. If the address type is `ResolvablePrivate` it is the identity
+    /// address, since the private address advertised changes periodically.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the BLEAddress of the server, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the address could not be read from the controller
+    pub fn address(&self) -> Result<BLEAddress, BleError> {
+        own_address(&self.own_address_type)
+    }
+
+    /// Gets the type of the address the server advertises and connects with
+    ///
+    /// # Returns
+    ///
+    /// The OwnAddressType in use, `Public` unless it was changed
+    pub fn address_type(&self) -> OwnAddressType {
+        self.own_address_type
+    }
+
+    /// Sets the type of the address the server advertises and connects with. If the server is
+    /// advertising, the advertisement is restarted with the new address. The rotation interval of
+    /// the `ResolvablePrivate` address is set with the `CONFIG_BT_NIMBLE_RPA_TIMEOUT` option of
+    /// the sdkconfig.
+    ///
+    /// # Arguments
+    ///
+    /// - `address_type`: The new OwnAddressType
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the address type was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the address type is `NonResolvablePrivate`, since clients
+    ///   cannot connect to it, or if the given random static address is not valid
+    /// - `BleError::StoppingFailure`: If the advertisement could not be stopped
+    /// - `BleError::StartingAdvertisementError`: If the advertisement could not be restarted
+    /// - `BleError::Code`: If the controller rejected the random address
+    pub fn set_address_type(&mut self, address_type: OwnAddressType) -> Result<(), BleError> {
+        if !address_type.is_connectable() {
+            return Err(BleError::InvalidParameters(None));
+        }
+        let advertising = self.advertisement.lock().is_advertising();
+        if advertising {
+            self.stop_advertisement()?;
+        }
+        address_type.apply(BLEDevice::take())?;
+        self.own_address_type = address_type;
+        if advertising {
+            self.start()?;
+        }
+        Ok(())
+    }
+
+    /// Creates the necessary advertisement data with the user settings. If a custom payload was set
+    /// it is advertised instead of the name and services.
     ///
     /// # Returns
     ///
@@ -778,8 +2662,15 @@ impl<'a> _BleServer<'a> {
     ///
     /// - `BleError::AdvertisementError`: If the advertising operation failed
     fn create_advertisement_data(&mut self) -> Result<(), BleError> {
+        if let Some(payload) = &self.advertisement_payload {
+            let payload = payload.build()?;
+            return set_raw_advertising_data(&payload).map_err(|_| BleError::AdvertisementError);
+        }
         let mut adv_data = BLEAdvertisementData::new();
         adv_data.name(&self.advertising_name);
+        if let Some(appearance) = self.appearance {
+            adv_data.appearance(appearance);
+        }
         for service in &self.services {
             adv_data.add_service_uuid(service.id.to_uuid());
         }
@@ -826,30 +2717,44 @@ impl<'a> _BleServer<'a> {
         service_id: &BleId,
         characteristic_id: &BleId,
     ) -> Result<Vec<u8>, BleError> {
-        // Get the service from the BLEServer
-        let service_option =
-            task::block_on(async { self.ble_server.get_service(service_id.to_uuid()).await });
-
-        match service_option {
-            Some(service_arc) => {
-                // If the service is found, we get the desired characteristic
-                let locked_service = service_arc.lock();
-                let server_characteristic = task::block_on(async {
-                    locked_service
-                        .get_characteristic(characteristic_id.to_uuid())
-                        .await
-                });
+        let characteristic = self.get_server_characteristic(service_id, characteristic_id)?;
+        Ok(self.read_characteristic_data(&characteristic))
+    }
 
-                // Onece we have the characteristic, we get its data
-                match server_characteristic {
-                    Some(characteristic_arc) => {
-                        Ok(self.read_characteristic_data(characteristic_arc))
-                    }
-                    None => Err(BleError::CharacteristicNotFound),
-                }
-            }
-            None => Err(BleError::ServiceNotFound),
-        }
+    /// Gets the data of a descriptor of a characteristic, including the changes written by the
+    /// clients, such as a user description set from a phone. The Client Characteristic Configuration
+    /// is read with [Self::client_configurations] instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A `&BleId` that represents the id of the service that has the characteristic
+    /// - `characteristic_id`: A `&BleId` that represents the id of the characteristic
+    /// - `descriptor_id`: A `&BleId` that represents the id of the descriptor
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the data if the operation is succesful, or a `BleError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::DescriptorNotFound`: If the descriptor was not added to the characteristic
+    pub fn get_descriptor_data(
+        &self,
+        service_id: &BleId,
+        characteristic_id: &BleId,
+        descriptor_id: &BleId,
+    ) -> Result<Vec<u8>, BleError> {
+        let descriptor = self
+            .descriptors
+            .iter()
+            .find(|d| {
+                &d.service_id == service_id
+                    && &d.characteristic_id == characteristic_id
+                    && &d.descriptor_id == descriptor_id
+            })
+            .ok_or(BleError::DescriptorNotFound(None))?;
+        let mut descriptor = descriptor.descriptor.lock();
+        Ok(descriptor.value_mut().value().to_vec())
     }
 
     /// Gets the data of all characteristics from a given service
@@ -875,7 +2780,7 @@ impl<'a> _BleServer<'a> {
             .services
             .iter()
             .find(|s| s.id == *service_id)
-            .ok_or(BleError::ServiceNotFound)?;
+            .ok_or(BleError::ServiceNotFound(None))?;
         for c in &services.characteristics {
             data.push((
                 c.id.clone(),
@@ -892,6 +2797,30 @@ impl<'a> InterruptDriver<'a> for BleServer<'a> {
         user_on_connection.handle_connection_changes(self);
         user_on_disconnection.handle_connection_changes(self);
         self.set_connection_callbacks(user_on_connection, user_on_disconnection);
+
+        let mut write_callbacks = self.take_write_callbacks();
+        for write_callback in &mut write_callbacks {
+            write_callback.handle_writes(self);
+        }
+        self.set_write_callbacks(write_callbacks);
+
+        let mut indication_callbacks = self.take_indication_callbacks();
+        for indication_callback in &mut indication_callbacks {
+            indication_callback.handle_results(self);
+        }
+        self.set_indication_callbacks(indication_callbacks);
+
+        self.handle_directed_advertising_timeout();
+
+        let mut pairing = self.take_pairing_callbacks();
+        pairing.handle_events();
+        self.inner.deref_mut().pairing = Some(pairing);
+
+        let mut periodic_notifications = self.take_periodic_notifications();
+        for notification in &mut periodic_notifications {
+            notification.notify_if_due(self);
+        }
+        self.set_periodic_notifications(periodic_notifications);
         Ok(())
     }
 
@@ -900,6 +2829,17 @@ impl<'a> InterruptDriver<'a> for BleServer<'a> {
             inner: self.inner.clone(),
         })
     }
+
+    /// Stops advertising and disconnects every client, without advertising again after the
+    /// disconnections
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner
+            .deref_mut()
+            .ble_server
+            .advertise_on_disconnect(false);
+        self.stop_advertisement()?;
+        Ok(self.disconnect_all_clients()?)
+    }
 }
 
 impl<'a> BleServer<'a> {
@@ -940,6 +2880,60 @@ impl<'a> BleServer<'a> {
         })
     }
 
+    /// Efectibly clones the server, but is only allowed in the crate
+    pub(crate) fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// Async version of [Self::notify_value], that waits until each subscribed client received the
+    /// notification, or acknowledged the indication or timed out if it subscribed to indications.
+    /// The server is not borrowed while waiting, so its callbacks keep being executed.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: A BleId to identify the service the charactersitic is part of.
+    /// - `charactersitic`: A Characteristic struct that represents the characteristic to notify.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok once every subscribed client got the value or disconnected, or an `BleError`
+    /// if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::CharacteristicNotNotifiable`: If the characteristic does not have the NOTIFY property
+    /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server
+    /// - `BleError::CharacteristicNotFound`: If the characteristic was not setted before on the server
+    /// - `BleError::ValueTooLong`: If the data is longer than the max length the characteristic was set with
+    pub async fn notify_value_async(
+        &mut self,
+        service_id: &BleId,
+        characteristic: &Characteristic,
+    ) -> Result<(), BleError> {
+        let notification = Notification::new();
+        let pending = self.inner.deref_mut().notify_with_waiter(
+            service_id,
+            characteristic,
+            notification.notifier(),
+        )?;
+        loop {
+            let connected: Vec<u16> = self
+                .list_clients()
+                .iter()
+                .map(|client| client.conn_handle)
+                .collect();
+            let mut pending_clients = pending.lock();
+            pending_clients.retain(|handle| connected.contains(handle));
+            if pending_clients.is_empty() {
+                return Ok(());
+            }
+            drop(pending_clients);
+            notification.wait().await;
+        }
+    }
+
     /// Takes ownership of both of the connection and disconnection callbacks
     ///
     /// # Returns
@@ -965,4 +2959,142 @@ impl<'a> BleServer<'a> {
         self.inner.deref_mut().user_on_connection = Some(user_on_connection);
         self.inner.deref_mut().user_on_disconnection = Some(user_on_disconnection);
     }
+
+    /// Takes ownership of the pairing callbacks
+    ///
+    /// # Returns
+    ///
+    /// The PairingCallbacks of the server
+    fn take_pairing_callbacks(&mut self) -> PairingCallbacks<'a> {
+        self.inner.deref_mut().pairing.take().unwrap()
+    }
+
+    /// Takes ownership of the write callbacks
+    ///
+    /// # Returns
+    ///
+    /// A vector with every WriteCallback of the server
+    fn take_write_callbacks(&mut self) -> Vec<WriteCallback<'a>> {
+        std::mem::take(&mut self.inner.deref_mut().write_callbacks)
+    }
+
+    /// Sets back the write callbacks, keeping any callback added in the meantime. The taken callbacks
+    /// replaced in the meantime are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// - `write_callbacks`: The write callbacks previously taken
+    fn set_write_callbacks(&mut self, mut write_callbacks: Vec<WriteCallback<'a>>) {
+        let mut inner = self.inner.deref_mut();
+        write_callbacks.retain(|taken| {
+            !inner
+                .write_callbacks
+                .iter()
+                .any(|callback| callback.is_for(&taken.service_id, &taken.characteristic_id))
+        });
+        write_callbacks.append(&mut inner.write_callbacks);
+        inner.write_callbacks = write_callbacks;
+    }
+
+    /// Takes ownership of the indication callbacks
+    ///
+    /// # Returns
+    ///
+    /// A vector with every IndicationCallback of the server
+    fn take_indication_callbacks(&mut self) -> Vec<IndicationCallback<'a>> {
+        std::mem::take(&mut self.inner.deref_mut().indication_callbacks)
+    }
+
+    /// Sets back the indication callbacks, keeping any callback added in the meantime
+    ///
+    /// # Arguments
+    ///
+    /// - `indication_callbacks`: The indication callbacks previously taken
+    fn set_indication_callbacks(&mut self, mut indication_callbacks: Vec<IndicationCallback<'a>>) {
+        let mut inner = self.inner.deref_mut();
+        indication_callbacks.append(&mut inner.indication_callbacks);
+        inner.indication_callbacks = indication_callbacks;
+    }
+
+    /// Executes the user callback if the directed advertising stopped because the target device did
+    /// not connect before the timeout
+    fn handle_directed_advertising_timeout(&mut self) {
+        let (target, mut on_timeout) = {
+            let mut inner = self.inner.deref_mut();
+            if !inner.directed.take_timeout() {
+                return;
+            }
+            let Some(target) = inner.directed.target else {
+                return;
+            };
+            (target, inner.directed.on_timeout.take())
+        };
+
+        if let Some(callback) = on_timeout.as_mut() {
+            callback(self, target);
+        }
+        let mut inner = self.inner.deref_mut();
+        if inner.directed.on_timeout.is_none() {
+            inner.directed.on_timeout = on_timeout;
+        }
+    }
+
+    /// Takes ownership of the periodic notifications
+    ///
+    /// # Returns
+    ///
+    /// A vector with every PeriodicNotification of the server
+    fn take_periodic_notifications(&mut self) -> Vec<PeriodicNotification<'a>> {
+        std::mem::take(&mut self.inner.deref_mut().periodic_notifications)
+    }
+
+    /// Sets back the periodic notifications, keeping any notification added in the meantime
+    ///
+    /// # Arguments
+    ///
+    /// - `periodic_notifications`: The periodic notifications previously taken
+    fn set_periodic_notifications(
+        &mut self,
+        mut periodic_notifications: Vec<PeriodicNotification<'a>>,
+    ) {
+        let mut inner = self.inner.deref_mut();
+        periodic_notifications.append(&mut inner.periodic_notifications);
+        inner.periodic_notifications = periodic_notifications;
+    }
+}
+
+/// Rejects a write longer than the max length of the characteristic with the "Invalid Attribute
+/// Value Length" ATT error. Prepared writes are checked once they are executed, with the whole value.
+///
+/// # Returns
+///
+/// True if the write was rejected
+fn reject_if_too_long(args: &mut OnWriteArgs, max_length: usize) -> bool {
+    if args.recv_data().len() <= max_length {
+        return false;
+    }
+    args.reject_with_error_code(BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN as u8);
+    true
+}
+
+/// Creates a ConnectionInformation from a connection descriptor received from NimBLE
+fn connection_information(
+    desc: &ble_gap_conn_desc,
+    is_connected: bool,
+    desc_res: Result<(), BLEError>,
+) -> ConnectionInformation {
+    // BLEConnDesc is a transparent wrapper of ble_gap_conn_desc
+    let desc = unsafe { &*(desc as *const ble_gap_conn_desc as *const BLEConnDesc) };
+    ConnectionInformation::from_bleconn_desc(desc, is_connected, desc_res)
+}
+
+/// Event callback of the directed advertising and of the connections made through it
+unsafe extern "C" fn directed_advertising_callback(
+    event: *mut ble_gap_event,
+    arg: *mut c_void,
+) -> c_int {
+    (*(arg as *const Mutex<DirectedAdvertisingState>))
+        .lock()
+        .handle_event(&*event);
+    0
 }
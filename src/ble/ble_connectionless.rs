@@ -1,4 +1,7 @@
-use super::utils::{BleError, BleId, Service};
+use super::utils::{
+    set_raw_advertising_data, AdvertisementPayload, BleError, BleId, EddystoneFrame,
+    EddystoneTelemetry, Service, TxPower, TxPowerTarget,
+};
 use crate::utils::{
     auxiliary::{SharableRef, SharableRefExt},
     timer_driver::TimerDriver,
@@ -6,7 +9,29 @@ use crate::utils::{
 use esp32_nimble::{
     utilities::mutex::Mutex, BLEAdvertisementData, BLEAdvertising, BLEDevice, BLEError,
 };
-use std::{cell::RefCell, collections::HashMap, rc::Rc, time::Duration};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// Apple company id (0x004C, little endian), followed by the iBeacon type and length
+const IBEACON_PREFIX: [u8; 4] = [0x4C, 0x00, 0x02, 0x15];
+const IBEACON_DATA_SIZE: usize = 25;
+
+/// Frame of the Apple iBeacon format, advertised as manufacturer data:
+/// - `uuid`: The proximity UUID, identifying the beacons of an organization, in big endian order.
+/// - `major`: Identifies a group of beacons, for example a building.
+/// - `minor`: Identifies a beacon inside the group.
+/// - `measured_power`: The RSSI in dBm measured at 1 meter from the beacon, used to estimate the distance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IBeacon {
+    pub uuid: [u8; 16],
+    pub major: u16,
+    pub minor: u16,
+    pub measured_power: i8,
+}
 
 /// The Beacon advertises information in small packets of data at regular intervals.
 /// The small packets can be detected by other devices and get the information.
@@ -15,8 +40,75 @@ pub struct BleBeacon<'a> {
     ble_device: &'a mut BLEDevice,
     services: SharableRef<HashMap<BleId, Service>>,
     advertisement: SharableRef<BLEAdvertisementData>,
+    advertised_service: SharableRef<Option<BleId>>,
     timer_driver: TimerDriver<'a>,
     time_per_service: Duration,
+    service_periods: SharableRef<HashMap<BleId, Duration>>,
+    ibeacon: Option<IBeacon>,
+    eddystone_telemetry: SharableRef<EddystoneTelemetry>,
+    advertisement_payload: Option<AdvertisementPayload>,
+}
+
+impl IBeacon {
+    /// Creates a new IBeacon frame
+    ///
+    /// # Arguments
+    ///
+    /// - `uuid`: The proximity UUID, in big endian order
+    /// - `major`: The major value
+    /// - `minor`: The minor value
+    /// - `measured_power`: The RSSI in dBm measured at 1 meter from the beacon
+    ///
+    /// # Returns
+    ///
+    /// The new IBeacon
+    pub fn new(uuid: [u8; 16], major: u16, minor: u16, measured_power: i8) -> Self {
+        IBeacon {
+            uuid,
+            major,
+            minor,
+            measured_power,
+        }
+    }
+
+    /// Gets the manufacturer data of the frame: the Apple company id, the iBeacon type and
+    /// length, the uuid, the major, the minor and the measured power.
+    ///
+    /// # Returns
+    ///
+    /// A vector with the 25 bytes of manufacturer data
+    pub fn manufacturer_data(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(IBEACON_DATA_SIZE);
+        data.extend_from_slice(&IBEACON_PREFIX);
+        data.extend_from_slice(&self.uuid);
+        data.extend_from_slice(&self.major.to_be_bytes());
+        data.extend_from_slice(&self.minor.to_be_bytes());
+        data.push(self.measured_power as u8);
+        data
+    }
+
+    /// Parses the manufacturer data of an advertisement, in order to recognize iBeacons while scanning
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The manufacturer data, starting with the company id
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the IBeacon, or None if the data is not an iBeacon frame
+    pub fn from_manufacturer_data(data: &[u8]) -> Option<Self> {
+        if data.len() != IBEACON_DATA_SIZE || data[..IBEACON_PREFIX.len()] != IBEACON_PREFIX {
+            return None;
+        }
+        let mut uuid = [0; 16];
+        uuid.copy_from_slice(&data[4..20]);
+        Some(IBeacon {
+            uuid,
+            major: u16::from_be_bytes([data[20], data[21]]),
+            minor: u16::from_be_bytes([data[22], data[23]]),
+            measured_power: data[24] as i8,
+        })
+    }
 }
 
 impl<'a> BleBeacon<'a> {
@@ -50,8 +142,13 @@ impl<'a> BleBeacon<'a> {
             ble_device,
             services: SharableRef::new_sharable(HashMap::new()),
             advertisement: Rc::new(RefCell::from(advertisement)),
+            advertised_service: SharableRef::new_sharable(None),
             timer_driver,
             time_per_service: Duration::from_secs(1),
+            service_periods: SharableRef::new_sharable(HashMap::new()),
+            ibeacon: None,
+            eddystone_telemetry: SharableRef::new_sharable(EddystoneTelemetry::new()),
+            advertisement_payload: None,
         };
         beacon.set_services(services)?;
         Ok(beacon)
@@ -84,12 +181,16 @@ impl<'a> BleBeacon<'a> {
             service,
             self.services.deref().contains_key(&service.id),
         );
+        if !service.data.is_empty() {
+            self.advertised_service.replace(Some(service.id.clone()));
+        }
         self.services
             .deref_mut()
             .insert(service.id.clone(), service.clone());
     }
 
-    /// Updates the advertising of the beacon. Needed when changing the data of the advertisment
+    /// Updates the advertising of the beacon. Needed when changing the data of the advertisment.
+    /// If a custom payload was set it is advertised instead of the generated data.
     ///
     /// # Returns
     ///
@@ -100,6 +201,14 @@ impl<'a> BleBeacon<'a> {
     /// - `BleError::ServiceDoesNotFit`: if advertising is too big
     /// - `BleError::Code` on other errors
     fn update_advertisement(&mut self) -> Result<(), BleError> {
+        if let Some(payload) = &self.advertisement_payload {
+            let payload = payload.build()?;
+            set_advertising_data(
+                self.ble_device.get_advertising(),
+                &mut BLEAdvertisementData::new(),
+            )?;
+            return set_raw_advertising_data(&payload);
+        }
         set_advertising_data(
             self.ble_device.get_advertising(),
             &mut self.advertisement.deref_mut(),
@@ -159,14 +268,194 @@ impl<'a> BleBeacon<'a> {
     /// - `BleError::Code`: on other errors
     fn reset_advertisement(&mut self) -> Result<(), BleError> {
         let mut advertisement = BLEAdvertisementData::new();
+        self.advertised_service.replace(None);
+        if let Some(ibeacon) = &self.ibeacon {
+            advertisement.manufacturer_data(&ibeacon.manufacturer_data());
+            self.advertisement.replace(advertisement);
+            return self.update_advertisement();
+        }
         for service in self.services.deref().values() {
             add_service_to_advertising(&mut advertisement, service, false);
+            if !service.data.is_empty() {
+                self.advertised_service.replace(Some(service.id.clone()));
+            }
         }
         self.advertisement.replace(advertisement);
         self.set_name(self.advertising_name.clone());
         self.update_advertisement()
     }
 
+    /// Makes the beacon advertise an iBeacon frame. The frame takes almost the whole advertisement,
+    /// so the name and services of the beacon stop being advertised until [Self::clear_ibeacon] is called.
+    ///
+    /// # Arguments
+    ///
+    /// - `ibeacon`: The IBeacon frame to advertise
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BleBeacon` itself, or a `BleError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the advertising data could not be set
+    pub fn set_ibeacon(&mut self, ibeacon: &IBeacon) -> Result<&mut Self, BleError> {
+        self.ibeacon = Some(*ibeacon);
+        self.reset_advertisement()?;
+        Ok(self)
+    }
+
+    /// Stops advertising the iBeacon frame, going back to advertising the name and services of the beacon
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BleBeacon` itself, or a `BleError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceDoesNotFit`: if advertising is too big
+    /// - `BleError::Code`: on other errors
+    pub fn clear_ibeacon(&mut self) -> Result<&mut Self, BleError> {
+        self.ibeacon = None;
+        self.reset_advertisement()?;
+        Ok(self)
+    }
+
+    /// Sets the telemetry advertised on the Eddystone TLM frames. The amount of frames advertised
+    /// and the time since the beacon was created are counted automatically.
+    ///
+    /// # Arguments
+    ///
+    /// - `battery_mv`: The battery voltage in millivolts, 0 if the beacon is not battery powered
+    /// - `temperature`: The temperature in degrees Celsius, None if the beacon does not measure it
+    pub fn set_eddystone_telemetry(&mut self, battery_mv: u16, temperature: Option<f32>) {
+        self.eddystone_telemetry
+            .deref_mut()
+            .set(battery_mv, temperature);
+    }
+
+    /// The beacon advertises each Eddystone frame every fixed duration, interleaving them. The time
+    /// per frame can be set with [Self::set_time_per_service]. While advertising Eddystone frames
+    /// the name and services of the beacon are not advertised, until [Self::stop_eddystone] is called.
+    ///
+    /// Note: For the advertised frame to change, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `frames`: The frames to advertise
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the advertise operation completed successfully, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If no frames were received, or an url is not valid
+    /// - `BleError::ServiceTooBig`: If a compressed url is longer than 17 bytes
+    /// - `BleError::TimerDriverError`: If the enabling of the TimerDriver fails
+    /// - `BleError::Code`: If the advertising data could not be set
+    pub fn advertise_eddystone_frames(
+        &mut self,
+        frames: Vec<EddystoneFrame>,
+    ) -> Result<(), BleError> {
+        if frames.is_empty() {
+            return Err(BleError::InvalidParameters(None));
+        }
+        for frame in &frames {
+            frame.encode(&self.eddystone_telemetry.deref())?;
+        }
+        self.stop_looping_data()?;
+        self.advertised_service.replace(None);
+
+        let advertising = self.ble_device.get_advertising();
+        let telemetry = self.eddystone_telemetry.clone();
+        set_eddystone_frame(advertising, &frames[0], &mut telemetry.deref_mut())?;
+        let mut i = 0;
+
+        let callback = move || {
+            i = (i + 1) % frames.len();
+            _ = set_eddystone_frame(advertising, &frames[i], &mut telemetry.deref_mut());
+        };
+
+        self.timer_driver.interrupt_after_n_times(
+            self.time_per_service
+                .as_micros()
+                .try_into()
+                .unwrap_or(u64::MAX),
+            None,
+            true,
+            callback,
+        );
+        self.timer_driver
+            .enable()
+            .map_err(BleError::TimerDriverError)
+    }
+
+    /// Stops advertising the Eddystone frames, going back to advertising the name and services of the beacon
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimerDriverError`: If the underlying timer_driver fails
+    /// - `BleError::ServiceDoesNotFit`: if advertising is too big
+    /// - `BleError::Code`: on other errors
+    pub fn stop_eddystone(&mut self) -> Result<(), BleError> {
+        self.stop_looping_data()?;
+        self.reset_advertisement()
+    }
+
+    /// Replaces the advertisement data of the beacon with a custom payload, for example to advertise
+    /// manufacturer data or the TX power. The name, services and iBeacon frame of the beacon stop
+    /// being advertised, and the service data rotation is stopped, until
+    /// [Self::clear_advertisement_payload] is called.
+    ///
+    /// # Arguments
+    ///
+    /// - `payload`: The AdvertisementPayload to advertise
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BleBeacon` itself, or a `BleError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceDoesNotFit`: If the payload is longer than 31 bytes
+    /// - `BleError::InvalidParameters`: If a field of the payload is too long
+    /// - `BleError::TimerDriverError`: If the service data rotation could not be stopped
+    /// - `BleError::Code`: on other errors
+    pub fn set_advertisement_payload(
+        &mut self,
+        payload: &AdvertisementPayload,
+    ) -> Result<&mut Self, BleError> {
+        payload.build()?;
+        self.stop_looping_data()?;
+        self.advertisement_payload = Some(payload.clone());
+        self.update_advertisement()?;
+        Ok(self)
+    }
+
+    /// Goes back to advertising the name and services of the beacon, or its iBeacon frame, after a
+    /// custom payload was set with [Self::set_advertisement_payload].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the `BleBeacon` itself, or a `BleError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceDoesNotFit`: if advertising is too big
+    /// - `BleError::Code`: on other errors
+    pub fn clear_advertisement_payload(&mut self) -> Result<&mut Self, BleError> {
+        self.advertisement_payload = None;
+        self.reset_advertisement()?;
+        Ok(self)
+    }
+
     /// Removes the specified service from the beacon
     ///
     /// # Arguments
@@ -182,6 +471,7 @@ impl<'a> BleBeacon<'a> {
     /// - `BleError::Code` on other errors
     pub fn remove_service(&mut self, service_id: &BleId) -> Result<&mut Self, BleError> {
         self.services.deref_mut().remove(service_id);
+        self.service_periods.deref_mut().remove(service_id);
         self.reset_advertisement()?;
         Ok(self)
     }
@@ -202,10 +492,63 @@ impl<'a> BleBeacon<'a> {
     pub fn remove_services(&mut self, service_ids: &Vec<BleId>) -> Result<(), BleError> {
         for service_id in service_ids {
             self.services.deref_mut().remove(service_id);
+            self.service_periods.deref_mut().remove(service_id);
         }
         self.reset_advertisement()
     }
 
+    /// Updates the data of a service, swapping it on the air without stopping the advertising, so
+    /// sensor beacons can refresh their readings at a high rate without gaps. If the data of the
+    /// service is being advertised the change is immediate, otherwise it is advertised the next
+    /// time the service is, for example on the rotation of [Self::advertise_all_service_data].
+    /// Updates with the same data as before are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The BleId of the service
+    /// - `data`: The new data of the service
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the data was updated, or a `BleError` if it fails. On failure the
+    /// previous data is kept.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceUnknown`: If the service was not set on the beacon
+    /// - `BleError::ServiceDoesNotFit`: if the advertising data is too big
+    /// - `BleError::Code`: on other errors
+    pub fn update_service_data(
+        &mut self,
+        service_id: &BleId,
+        data: Vec<u8>,
+    ) -> Result<(), BleError> {
+        let previous = match self.services.deref().get(service_id) {
+            Some(service) if service.data == data => return Ok(()),
+            Some(service) => service.data.clone(),
+            None => return Err(BleError::ServiceUnknown),
+        };
+        let on_air = self.ibeacon.is_none()
+            && self.advertisement_payload.is_none()
+            && self.advertised_service.deref().as_ref() == Some(service_id);
+        if on_air {
+            let uuid = service_id.to_uuid();
+            self.advertisement.deref_mut().service_data(uuid, &data);
+            let result = set_advertising_data(
+                self.ble_device.get_advertising(),
+                &mut self.advertisement.deref_mut(),
+            );
+            if let Err(err) = result {
+                self.advertisement.deref_mut().service_data(uuid, &previous);
+                return Err(err);
+            }
+        }
+        if let Some(service) = self.services.deref_mut().get_mut(service_id) {
+            service.data = data;
+        }
+        Ok(())
+    }
+
     /// Start advertising one particular service data
     ///
     /// # Arguments
@@ -232,6 +575,8 @@ impl<'a> BleBeacon<'a> {
                     self.ble_device.get_advertising(),
                     &mut self.advertisement.deref_mut(),
                 )?;
+                self.advertised_service
+                    .replace(Some(request_service.id.clone()));
                 self.start()
             }
             None => Err(BleError::ServiceUnknown),
@@ -286,8 +631,70 @@ impl<'a> BleBeacon<'a> {
         self.time_per_service = dur
     }
 
-    /// The beacon advertises the data of each service every fixed duration. If services are added or
-    /// removed this is reflected. The time per service can be set with [Self::set_time_per_service]
+    /// Sets the time the beacon will advertise the data of a particular service if
+    /// [Self::advertise_all_service_data] was called, overriding the time per service. Changes are
+    /// applied the next time the rotation is started.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The BleId of the service
+    /// - `dur`: The Duration wanted for the service to be on advertising
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the period was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceUnknown`: If the service was not set on the beacon
+    pub fn set_service_period(
+        &mut self,
+        service_id: &BleId,
+        dur: Duration,
+    ) -> Result<(), BleError> {
+        if !self.services.deref().contains_key(service_id) {
+            return Err(BleError::ServiceUnknown);
+        }
+        self.service_periods
+            .deref_mut()
+            .insert(service_id.clone(), dur);
+        Ok(())
+    }
+
+    /// Pauses the rotation started with [Self::advertise_all_service_data], leaving the current
+    /// service data on advertising.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the rotation was paused, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimerDriverError`: If the underlying timer_driver fails
+    pub fn pause_rotation(&mut self) -> Result<(), BleError> {
+        self.timer_driver
+            .disable()
+            .map_err(BleError::TimerDriverError)
+    }
+
+    /// Resumes the rotation paused with [Self::pause_rotation]
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the rotation was resumed, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimerDriverError`: If the underlying timer_driver fails
+    pub fn resume_rotation(&mut self) -> Result<(), BleError> {
+        self.timer_driver
+            .enable()
+            .map_err(BleError::TimerDriverError)
+    }
+
+    /// The beacon advertises the data of each service for its period, one after the other. If services
+    /// are added or removed this is reflected. The time per service can be set with [Self::set_time_per_service],
+    /// and the time of a particular service with [Self::set_service_period].
     ///
     /// Note: For the advertised data to change, the method [crate::Microcontroller::wait_for_updates] must
     /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
@@ -306,26 +713,54 @@ impl<'a> BleBeacon<'a> {
     /// It may panic if the setting of the advertising data fails
     pub fn advertise_all_service_data(&mut self) -> Result<(), BleError> {
         let services = self.services.clone();
+        let service_periods = self.service_periods.clone();
+        let time_per_service = self.time_per_service;
         let advertising = self.ble_device.get_advertising();
         let advertisement = self.advertisement.clone();
+        let advertised_service = self.advertised_service.clone();
         let mut i = 0;
+        let mut advertised_since: Option<Instant> = None;
+
+        // The timer ticks at the shortest period, and each tick checks if the current service
+        // was advertised long enough
+        let tick = self
+            .service_periods
+            .deref()
+            .values()
+            .copied()
+            .chain([time_per_service])
+            .min()
+            .unwrap_or(time_per_service);
 
         let callback = move || {
             let services = services.deref();
+            if services.is_empty() {
+                return;
+            }
             i %= services.len();
+            if let Some(since) = advertised_since {
+                let current = services.keys().nth(i).unwrap();
+                let period = service_periods
+                    .deref()
+                    .get(current)
+                    .copied()
+                    .unwrap_or(time_per_service);
+                if since.elapsed() + tick / 2 < period {
+                    return;
+                }
+                i = (i + 1) % services.len();
+            }
             let service = services.values().collect::<Vec<&Service>>()[i];
             advertisement
                 .borrow_mut()
                 .service_data(service.id.to_uuid(), &service.data);
             set_advertising_data(advertising, &mut advertisement.borrow_mut()).unwrap();
-            i += 1
+            advertised_service.replace(Some(service.id.clone()));
+            advertised_since = Some(Instant::now());
         };
 
         self.timer_driver.interrupt_after_n_times(
-            self.time_per_service
-                .as_micros()
-                .try_into()
-                .unwrap_or(u64::MAX),
+            tick.as_micros().try_into().unwrap_or(u64::MAX),
             None,
             true,
             callback,
@@ -335,6 +770,37 @@ impl<'a> BleBeacon<'a> {
             .map_err(BleError::TimerDriverError)
     }
 
+    /// Sets the transmission power of the advertisements of the beacon. Lowering it reduces the
+    /// consumption and the range, which also changes the RSSI measured by the scanners.
+    ///
+    /// # Arguments
+    ///
+    /// - `tx_power`: The TxPower to use
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the power was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the controller rejects the level
+    pub fn set_tx_power(&mut self, tx_power: TxPower) -> Result<(), BleError> {
+        TxPowerTarget::Advertising(0).set(tx_power)
+    }
+
+    /// Gets the transmission power of the advertisements of the beacon
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the TxPower, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::NotFound`: If the controller could not inform the level
+    pub fn tx_power(&self) -> Result<TxPower, BleError> {
+        TxPowerTarget::Advertising(0).get()
+    }
+
     /// Start advertising set services of the beacon
     ///
     /// # Returns
@@ -392,6 +858,27 @@ fn set_advertising_data(
     }
 }
 
+/// Sets an Eddystone frame as the advertising data, counting it on the telemetry
+///
+/// # Errors
+///
+/// - `BleError::InvalidParameters`: If an url is not valid
+/// - `BleError::ServiceTooBig`: If a compressed url is longer than 17 bytes
+/// - `BleError::Code`: If the advertising data could not be set
+fn set_eddystone_frame(
+    ble_adv: &Mutex<BLEAdvertising>,
+    frame: &EddystoneFrame,
+    telemetry: &mut EddystoneTelemetry,
+) -> Result<(), BleError> {
+    let service_id = EddystoneFrame::service_id().to_uuid();
+    let mut advertisement = BLEAdvertisementData::new();
+    advertisement.add_service_uuid(service_id);
+    advertisement.service_data(service_id, &frame.encode(telemetry)?);
+    set_advertising_data(ble_adv, &mut advertisement)?;
+    telemetry.count_frame();
+    Ok(())
+}
+
 fn add_service_to_advertising(data: &mut BLEAdvertisementData, service: &Service, only_data: bool) {
     if !only_data {
         data.add_service_uuid(service.id.to_uuid());
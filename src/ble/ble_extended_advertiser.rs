@@ -0,0 +1,391 @@
+use super::utils::{BleError, Service, TxPower, TxPowerTarget};
+use esp32_nimble::{
+    enums::{PrimPhy, SecPhy},
+    utilities::mutex::Mutex,
+    BLEDevice, BLEExtAdvertisement, BLEExtAdvertising,
+};
+use std::collections::HashMap;
+
+const LEGACY_MAX_PAYLOAD_SIZE: usize = 31;
+const EXTENDED_MAX_PAYLOAD_SIZE: usize = 1650;
+const AD_STRUCTURE_HEADER_SIZE: usize = 2;
+const FLAGS_SIZE: usize = 3;
+
+/// Enums the posible advertising modes of an advertising set:
+/// * `Legacy`: Uses the legacy advertising PDUs, so any device can receive it, but the payload is
+///   limited to 31 bytes.
+/// * `Extended`: Uses the BLE 5 extended advertising PDUs, allowing payloads of up to 1650 bytes,
+///   but only BLE 5 scanners can receive it. An extended set can not be both connectable and scannable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdvertisingMode {
+    Legacy,
+    Extended,
+}
+
+/// Payload and properties of one of the advertisements sent by a [BleExtendedAdvertiser].
+#[derive(Debug, Clone)]
+pub struct AdvertisingSet {
+    mode: AdvertisingMode,
+    name: Option<String>,
+    services: Vec<Service>,
+    manufacturer_data: Option<Vec<u8>>,
+    connectable: bool,
+    scannable: bool,
+}
+
+/// Advertiser capable of sending multiple advertising sets at the same time, each one with its own
+/// payload and with either legacy or extended advertising.
+///
+/// Note: Extended advertising requires `CONFIG_BT_NIMBLE_EXT_ADV=y` on the sdkconfig. With this
+/// option NimBLE only offers the extended advertising api, so this advertiser must be used instead
+/// of the advertising of the [crate::ble::BleBeacon] and [crate::ble::BleServer].
+pub struct BleExtendedAdvertiser<'a> {
+    advertising: &'a Mutex<BLEExtAdvertising>,
+    sets: HashMap<u8, AdvertisingSet>,
+}
+
+impl AdvertisingSet {
+    /// Creates a new empty AdvertisingSet, that is neither connectable nor scannable
+    ///
+    /// # Arguments
+    ///
+    /// - `mode`: The AdvertisingMode of the set
+    ///
+    /// # Returns
+    ///
+    /// The new AdvertisingSet
+    pub fn new(mode: AdvertisingMode) -> Self {
+        AdvertisingSet {
+            mode,
+            name: None,
+            services: vec![],
+            manufacturer_data: None,
+            connectable: false,
+            scannable: false,
+        }
+    }
+
+    /// Sets the name advertised
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name to advertise
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingSet itself
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Adds the data of a service to the payload
+    ///
+    /// # Arguments
+    ///
+    /// - `service`: The Service whose id and data are advertised
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingSet itself
+    pub fn service(mut self, service: &Service) -> Self {
+        self.services.push(service.clone());
+        self
+    }
+
+    /// Sets the manufacturer data advertised
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The manufacturer data, starting with the company identifier
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingSet itself
+    pub fn manufacturer_data(mut self, data: &[u8]) -> Self {
+        self.manufacturer_data = Some(data.to_vec());
+        self
+    }
+
+    /// Sets whether clients can connect through this set
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: If true the set is connectable
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingSet itself
+    pub fn connectable(mut self, value: bool) -> Self {
+        self.connectable = value;
+        self
+    }
+
+    /// Sets whether scanners can make scan requests to this set
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: If true the set is scannable
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingSet itself
+    pub fn scannable(mut self, value: bool) -> Self {
+        self.scannable = value;
+        self
+    }
+
+    /// Gets the size of the payload of the set
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes of every AD structure of the payload
+    pub fn payload_size(&self) -> usize {
+        let name_size = self
+            .name
+            .as_ref()
+            .map_or(0, |name| AD_STRUCTURE_HEADER_SIZE + name.len());
+        let services_size: usize = self
+            .services
+            .iter()
+            .map(|service| AD_STRUCTURE_HEADER_SIZE + service.id.byte_size() + service.data.len())
+            .sum();
+        let manufacturer_size = self
+            .manufacturer_data
+            .as_ref()
+            .map_or(0, |data| AD_STRUCTURE_HEADER_SIZE + data.len());
+        let flags_size = if self.connectable { FLAGS_SIZE } else { 0 };
+        name_size + services_size + manufacturer_size + flags_size
+    }
+
+    /// Gets the maximum payload size of the mode of the set
+    ///
+    /// # Returns
+    ///
+    /// The maximum size in bytes
+    pub fn max_payload_size(&self) -> usize {
+        match self.mode {
+            AdvertisingMode::Legacy => LEGACY_MAX_PAYLOAD_SIZE,
+            AdvertisingMode::Extended => EXTENDED_MAX_PAYLOAD_SIZE,
+        }
+    }
+
+    /// Creates the BLEExtAdvertisement of the set
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the BLEExtAdvertisement, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceTooBig`: If the payload does not fit in the mode of the set
+    /// - `BleError::InvalidParameters`: If an extended set is both connectable and scannable
+    fn to_advertisement(&self) -> Result<BLEExtAdvertisement, BleError> {
+        if self.payload_size() > self.max_payload_size() {
+            return Err(BleError::ServiceTooBig);
+        }
+        if self.mode == AdvertisingMode::Extended && self.connectable && self.scannable {
+            return Err(BleError::InvalidParameters(None));
+        }
+
+        let mut advertisement = BLEExtAdvertisement::new(PrimPhy::Phy1M, SecPhy::Phy1M);
+        advertisement.legacy_advertising(self.mode == AdvertisingMode::Legacy);
+        advertisement.connectable(self.connectable);
+        advertisement.scannable(self.scannable);
+        if let Some(name) = &self.name {
+            advertisement.name(name);
+        }
+        for service in &self.services {
+            advertisement.service_data(service.id.to_uuid(), &service.data);
+        }
+        if let Some(data) = &self.manufacturer_data {
+            advertisement.manufacturer_data(data);
+        }
+        Ok(advertisement)
+    }
+}
+
+impl<'a> BleExtendedAdvertiser<'a> {
+    /// Creates a new BleExtendedAdvertiser without advertising sets
+    ///
+    /// # Arguments
+    ///
+    /// - `ble_device`: A BLEDevice needed to get the BLEExtAdvertising
+    ///
+    /// # Returns
+    ///
+    /// The new BleExtendedAdvertiser
+    pub(crate) fn new(ble_device: &'a mut BLEDevice) -> Self {
+        BleExtendedAdvertiser {
+            advertising: ble_device.get_advertising(),
+            sets: HashMap::new(),
+        }
+    }
+
+    /// Sets the payload of an advertising set, replacing the previous one. If the set was being
+    /// advertised, the new payload is advertised.
+    ///
+    /// # Arguments
+    ///
+    /// - `instance`: The id of the set. The amount of sets is limited by `CONFIG_BT_NIMBLE_MAX_EXT_ADV_INSTANCES`.
+    /// - `set`: The AdvertisingSet
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the set was configured, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceTooBig`: If the payload does not fit in the mode of the set
+    /// - `BleError::InvalidParameters`: If an extended set is both connectable and scannable, or the
+    ///   instance is not valid
+    /// - `BleError::Code`: on other errors
+    pub fn set_advertising_set(
+        &mut self,
+        instance: u8,
+        set: AdvertisingSet,
+    ) -> Result<(), BleError> {
+        let mut advertisement = set.to_advertisement()?;
+        self.advertising
+            .lock()
+            .set_instance_data(instance, &mut advertisement)?;
+        self.sets.insert(instance, set);
+        Ok(())
+    }
+
+    /// Gets an advertising set
+    ///
+    /// # Arguments
+    ///
+    /// - `instance`: The id of the set
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the AdvertisingSet, or None if there is no set with the id
+    pub fn get_advertising_set(&self, instance: u8) -> Option<&AdvertisingSet> {
+        self.sets.get(&instance)
+    }
+
+    /// Starts advertising a set
+    ///
+    /// # Arguments
+    ///
+    /// - `instance`: The id of the set
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the advertising started, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If there is no set with the id
+    /// - `BleError::StartingAdvertisementError`: If the advertising could not be started
+    pub fn start(&mut self, instance: u8) -> Result<(), BleError> {
+        if !self.sets.contains_key(&instance) {
+            return Err(BleError::ServiceNotFound(None));
+        }
+        self.advertising
+            .lock()
+            .start(instance)
+            .map_err(|_| BleError::StartingAdvertisementError)
+    }
+
+    /// Stops advertising a set, keeping its payload
+    ///
+    /// # Arguments
+    ///
+    /// - `instance`: The id of the set
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the advertising stopped, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If there is no set with the id
+    /// - `BleError::StoppingFailure`: If the advertising could not be stopped
+    pub fn stop(&mut self, instance: u8) -> Result<(), BleError> {
+        if !self.sets.contains_key(&instance) {
+            return Err(BleError::ServiceNotFound(None));
+        }
+        self.advertising
+            .lock()
+            .stop(instance)
+            .map_err(|_| BleError::StoppingFailure)
+    }
+
+    /// Stops advertising a set and removes it
+    ///
+    /// # Arguments
+    ///
+    /// - `instance`: The id of the set
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the set was removed, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If there is no set with the id
+    /// - `BleError::Code`: If the set could not be removed
+    pub fn remove_advertising_set(&mut self, instance: u8) -> Result<(), BleError> {
+        self.sets
+            .remove(&instance)
+            .ok_or(BleError::ServiceNotFound(None))?;
+        self.advertising.lock().remove_instance(instance)?;
+        Ok(())
+    }
+
+    /// Sets the transmission power of an advertising set. Sets without their own power use the
+    /// default one, see [crate::ble::set_default_tx_power].
+    ///
+    /// # Arguments
+    ///
+    /// - `instance`: The id of the set
+    /// - `tx_power`: The TxPower to use
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the power was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If there is no set with the id
+    /// - `BleError::Code`: If the controller rejects the level
+    pub fn set_tx_power(&mut self, instance: u8, tx_power: TxPower) -> Result<(), BleError> {
+        if !self.sets.contains_key(&instance) {
+            return Err(BleError::ServiceNotFound(None));
+        }
+        TxPowerTarget::Advertising(instance).set(tx_power)
+    }
+
+    /// Gets the transmission power of an advertising set
+    ///
+    /// # Arguments
+    ///
+    /// - `instance`: The id of the set
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the TxPower, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceNotFound`: If there is no set with the id
+    /// - `BleError::NotFound`: If the controller could not inform the level
+    pub fn tx_power(&self, instance: u8) -> Result<TxPower, BleError> {
+        if !self.sets.contains_key(&instance) {
+            return Err(BleError::ServiceNotFound(None));
+        }
+        TxPowerTarget::Advertising(instance).get()
+    }
+
+    /// Gets the ids of every advertising set
+    ///
+    /// # Returns
+    ///
+    /// A vector with the id of each set
+    pub fn advertising_sets(&self) -> Vec<u8> {
+        self.sets.keys().copied().collect()
+    }
+}
@@ -0,0 +1,542 @@
+use super::utils::{
+    ble_standard_uuids::{StandardCharacteristicId, StandardDescriptorId, StandardServiceId},
+    BleError, BleId,
+};
+use esp32_nimble::{
+    utilities::mutex::Mutex, BLECharacteristic, BLEServer, BLEService, DescriptorProperties,
+    NimbleProperties,
+};
+use std::{
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// HID version 1.11, no country code, remote wake up and normally connectable
+const HID_INFORMATION: [u8; 4] = [0x11, 0x01, 0x00, 0x03];
+const BOOT_PROTOCOL_MODE: u8 = 0;
+const REPORT_PROTOCOL_MODE: u8 = 1;
+const INPUT_REPORT_TYPE: u8 = 1;
+const OUTPUT_REPORT_TYPE: u8 = 2;
+const KEYBOARD_REPORT_ID: u8 = 1;
+const MOUSE_REPORT_ID: u8 = 2;
+const GAMEPAD_REPORT_ID: u8 = 3;
+const KEYBOARD_APPEARANCE: u16 = 0x03C1;
+const MOUSE_APPEARANCE: u16 = 0x03C2;
+const GAMEPAD_APPEARANCE: u16 = 0x03C4;
+const LEFT_SHIFT: u8 = 0x02;
+const KEY_RELEASE_DELAY: Duration = Duration::from_millis(8);
+
+/// Keyboard with 8 modifiers, 6 simultaneous keys and 5 LEDs
+#[rustfmt::skip]
+const KEYBOARD_REPORT_MAP: [u8; 65] = [
+    0x05, 0x01, 0x09, 0x06, 0xA1, 0x01, 0x85, KEYBOARD_REPORT_ID, // Keyboard application
+    0x05, 0x07, 0x19, 0xE0, 0x29, 0xE7, 0x15, 0x00, 0x25, 0x01, // Modifiers
+    0x75, 0x01, 0x95, 0x08, 0x81, 0x02, //
+    0x95, 0x01, 0x75, 0x08, 0x81, 0x01, // Reserved byte
+    0x95, 0x05, 0x75, 0x01, 0x05, 0x08, 0x19, 0x01, 0x29, 0x05, 0x91, 0x02, // LEDs
+    0x95, 0x01, 0x75, 0x03, 0x91, 0x01, // LEDs padding
+    0x95, 0x06, 0x75, 0x08, 0x15, 0x00, 0x25, 0x65, // Keys
+    0x05, 0x07, 0x19, 0x00, 0x29, 0x65, 0x81, 0x00, //
+    0xC0,
+];
+
+/// Mouse with 5 buttons, relative X and Y movement and a wheel
+#[rustfmt::skip]
+const MOUSE_REPORT_MAP: [u8; 54] = [
+    0x05, 0x01, 0x09, 0x02, 0xA1, 0x01, 0x85, MOUSE_REPORT_ID, // Mouse application
+    0x09, 0x01, 0xA1, 0x00, // Pointer
+    0x05, 0x09, 0x19, 0x01, 0x29, 0x05, 0x15, 0x00, 0x25, 0x01, // Buttons
+    0x95, 0x05, 0x75, 0x01, 0x81, 0x02, //
+    0x95, 0x01, 0x75, 0x03, 0x81, 0x01, // Buttons padding
+    0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x38, // X, Y and wheel
+    0x15, 0x81, 0x25, 0x7F, 0x75, 0x08, 0x95, 0x03, 0x81, 0x06, //
+    0xC0, 0xC0,
+];
+
+/// Gamepad with 16 buttons and 4 axes
+#[rustfmt::skip]
+const GAMEPAD_REPORT_MAP: [u8; 45] = [
+    0x05, 0x01, 0x09, 0x05, 0xA1, 0x01, 0x85, GAMEPAD_REPORT_ID, // Gamepad application
+    0x05, 0x09, 0x19, 0x01, 0x29, 0x10, 0x15, 0x00, 0x25, 0x01, // Buttons
+    0x75, 0x01, 0x95, 0x10, 0x81, 0x02, //
+    0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x09, 0x32, 0x09, 0x35, // X, Y, Z and Rz
+    0x15, 0x81, 0x25, 0x7F, 0x75, 0x08, 0x95, 0x04, 0x81, 0x02, //
+    0xC0,
+];
+
+/// Enums the input devices a BleHid can act as:
+/// - `Keyboard`: Sends KeyboardReports and receives the state of its LEDs. Supports boot mode.
+/// - `Mouse`: Sends MouseReports. Supports boot mode.
+/// - `Gamepad`: Sends GamepadReports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HidDeviceKind {
+    Keyboard,
+    Mouse,
+    Gamepad,
+}
+
+/// State of a keyboard:
+/// - `modifiers`: Bitmask of the pressed modifiers, from the least significant bit: left ctrl,
+///   left shift, left alt, left gui, right ctrl, right shift, right alt and right gui.
+/// - `keys`: Usage ids of up to 6 pressed keys, 0 for none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyboardReport {
+    pub modifiers: u8,
+    pub keys: [u8; 6],
+}
+
+/// Movement of a mouse since the last report:
+/// - `buttons`: Bitmask of the pressed buttons, from the least significant bit: left, right,
+///   middle, back and forward.
+/// - `x`: Horizontal movement, positive to the right.
+/// - `y`: Vertical movement, positive downwards.
+/// - `wheel`: Wheel movement, positive upwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MouseReport {
+    pub buttons: u8,
+    pub x: i8,
+    pub y: i8,
+    pub wheel: i8,
+}
+
+/// State of a gamepad:
+/// - `buttons`: Bitmask of the 16 buttons, the least significant bit being button 1.
+/// - `axes`: Position of the X, Y, Z and Rz axes, from -127 to 127.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct GamepadReport {
+    pub buttons: u16,
+    pub axes: [i8; 4],
+}
+
+/// State of the LEDs of a keyboard, as set by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct KeyboardLeds {
+    pub num_lock: bool,
+    pub caps_lock: bool,
+    pub scroll_lock: bool,
+}
+
+/// Human Interface Device over GATT, which makes the microcontroller a wireless keyboard, mouse
+/// or gamepad for computers and phones. It is created with [crate::ble::BleServer::set_hid_device]
+/// and its reports are notified to the connected host.
+///
+/// Hosts only accept HID devices over encrypted connections, so the server should be created with
+/// [crate::Microcontroller::ble_secure_server] and bonding enabled.
+#[derive(Clone)]
+pub struct BleHid {
+    kind: HidDeviceKind,
+    input: Arc<Mutex<BLECharacteristic>>,
+    boot_input: Option<Arc<Mutex<BLECharacteristic>>>,
+    protocol_mode: Arc<AtomicU8>,
+    leds: Arc<AtomicU8>,
+}
+
+impl HidDeviceKind {
+    /// Gets the appearance advertised by the device
+    ///
+    /// # Returns
+    ///
+    /// The appearance code, as specified by the standard
+    pub fn appearance(&self) -> u16 {
+        match self {
+            HidDeviceKind::Keyboard => KEYBOARD_APPEARANCE,
+            HidDeviceKind::Mouse => MOUSE_APPEARANCE,
+            HidDeviceKind::Gamepad => GAMEPAD_APPEARANCE,
+        }
+    }
+
+    /// Gets the report map that describes the reports of the device
+    fn report_map(&self) -> &'static [u8] {
+        match self {
+            HidDeviceKind::Keyboard => &KEYBOARD_REPORT_MAP,
+            HidDeviceKind::Mouse => &MOUSE_REPORT_MAP,
+            HidDeviceKind::Gamepad => &GAMEPAD_REPORT_MAP,
+        }
+    }
+
+    /// Gets the id of the input report of the device
+    fn report_id(&self) -> u8 {
+        match self {
+            HidDeviceKind::Keyboard => KEYBOARD_REPORT_ID,
+            HidDeviceKind::Mouse => MOUSE_REPORT_ID,
+            HidDeviceKind::Gamepad => GAMEPAD_REPORT_ID,
+        }
+    }
+
+    /// Gets the id of the boot input report characteristic, if the device supports boot mode
+    fn boot_input_id(&self) -> Option<StandardCharacteristicId> {
+        match self {
+            HidDeviceKind::Keyboard => Some(StandardCharacteristicId::BootKeyboardInputReport),
+            HidDeviceKind::Mouse => Some(StandardCharacteristicId::BootMouseInputReport),
+            HidDeviceKind::Gamepad => None,
+        }
+    }
+}
+
+impl KeyboardReport {
+    /// Creates the report of a single ASCII character being typed, adding shift if needed
+    ///
+    /// # Arguments
+    ///
+    /// - `c`: The character
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the KeyboardReport, or None if the character has no key on a US keyboard
+    pub fn from_char(c: char) -> Option<Self> {
+        let (key, shift) = ascii_to_usage(c)?;
+        let mut report = KeyboardReport {
+            modifiers: 0,
+            keys: [key, 0, 0, 0, 0, 0],
+        };
+        if shift {
+            report.modifiers = LEFT_SHIFT;
+        }
+        Some(report)
+    }
+
+    /// Encodes the report, which is the same in report and boot mode
+    fn to_bytes(self) -> Vec<u8> {
+        let mut data = vec![self.modifiers, 0];
+        data.extend_from_slice(&self.keys);
+        data
+    }
+}
+
+impl MouseReport {
+    /// Encodes the report in report mode
+    fn to_bytes(self) -> Vec<u8> {
+        vec![self.buttons, self.x as u8, self.y as u8, self.wheel as u8]
+    }
+
+    /// Encodes the report in boot mode, which has no wheel
+    fn to_boot_bytes(self) -> Vec<u8> {
+        vec![self.buttons, self.x as u8, self.y as u8]
+    }
+}
+
+impl GamepadReport {
+    /// Encodes the report
+    fn to_bytes(self) -> Vec<u8> {
+        let mut data = self.buttons.to_le_bytes().to_vec();
+        data.extend(self.axes.iter().map(|axis| *axis as u8));
+        data
+    }
+}
+
+impl BleHid {
+    /// Creates the HID Service on the server, with the report map and reports of the device
+    ///
+    /// # Arguments
+    ///
+    /// - `server`: The BLEServer of the BleServer
+    /// - `kind`: The HidDeviceKind to act as
+    ///
+    /// # Returns
+    ///
+    /// The new BleHid
+    pub(crate) fn new(server: &mut BLEServer, kind: HidDeviceKind) -> Self {
+        let service = server.create_service(Self::service_id().to_uuid());
+        let protocol_mode = Arc::new(AtomicU8::new(REPORT_PROTOCOL_MODE));
+        let leds = Arc::new(AtomicU8::new(0));
+
+        create_characteristic(
+            &service,
+            StandardCharacteristicId::HIDInformation,
+            NimbleProperties::READ,
+            &HID_INFORMATION,
+        );
+        create_characteristic(
+            &service,
+            StandardCharacteristicId::ReportMap,
+            NimbleProperties::READ,
+            kind.report_map(),
+        );
+        create_characteristic(
+            &service,
+            StandardCharacteristicId::HIDControlPoint,
+            NimbleProperties::WRITE_NO_RSP,
+            &[0],
+        );
+        let mode_characteristic = create_characteristic(
+            &service,
+            StandardCharacteristicId::ProtocolMode,
+            NimbleProperties::READ | NimbleProperties::WRITE_NO_RSP,
+            &[REPORT_PROTOCOL_MODE],
+        );
+        let mode = protocol_mode.clone();
+        mode_characteristic.lock().on_write(move |args| {
+            if let Some(new_mode @ (BOOT_PROTOCOL_MODE | REPORT_PROTOCOL_MODE)) =
+                args.recv_data().first()
+            {
+                mode.store(*new_mode, Ordering::Release);
+            }
+        });
+
+        let input = create_report(
+            &service,
+            kind.report_id(),
+            INPUT_REPORT_TYPE,
+            NimbleProperties::READ | NimbleProperties::NOTIFY,
+        );
+        let boot_input = kind.boot_input_id().map(|id| {
+            create_characteristic(
+                &service,
+                id,
+                NimbleProperties::READ | NimbleProperties::NOTIFY,
+                &[],
+            )
+        });
+
+        if kind == HidDeviceKind::Keyboard {
+            let output_properties =
+                NimbleProperties::READ | NimbleProperties::WRITE | NimbleProperties::WRITE_NO_RSP;
+            let output = create_report(
+                &service,
+                kind.report_id(),
+                OUTPUT_REPORT_TYPE,
+                output_properties,
+            );
+            let boot_output = create_characteristic(
+                &service,
+                StandardCharacteristicId::BootKeyboardOutputReport,
+                output_properties,
+                &[0],
+            );
+            for characteristic in [output, boot_output] {
+                let leds = leds.clone();
+                characteristic.lock().on_write(move |args| {
+                    if let Some(state) = args.recv_data().first() {
+                        leds.store(*state, Ordering::Release);
+                    }
+                });
+            }
+        }
+
+        BleHid {
+            kind,
+            input,
+            boot_input,
+            protocol_mode,
+            leds,
+        }
+    }
+
+    /// Gets the id of the HID Service
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the service
+    pub fn service_id() -> BleId {
+        BleId::from_standard_service(StandardServiceId::HumanInterfaceDevice)
+    }
+
+    /// Gets the kind of device
+    ///
+    /// # Returns
+    ///
+    /// The HidDeviceKind of the device
+    pub fn kind(&self) -> HidDeviceKind {
+        self.kind
+    }
+
+    /// Checks whether the host switched the device to boot mode, used by BIOS and other simple
+    /// hosts. The reports are encoded accordingly.
+    ///
+    /// # Returns
+    ///
+    /// True if the device is in boot mode, false if it is in report mode
+    pub fn is_boot_mode(&self) -> bool {
+        self.protocol_mode.load(Ordering::Acquire) == BOOT_PROTOCOL_MODE
+    }
+
+    /// Sends the state of the keyboard to the host. Keys stay pressed until a report without them
+    /// is sent.
+    ///
+    /// # Arguments
+    ///
+    /// - `report`: The KeyboardReport with the pressed keys
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the report was notified, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the device is not a keyboard
+    pub fn send_keyboard(&self, report: &KeyboardReport) -> Result<(), BleError> {
+        if self.kind != HidDeviceKind::Keyboard {
+            return Err(BleError::InvalidParameters(None));
+        }
+        self.notify(&report.to_bytes(), &report.to_bytes());
+        Ok(())
+    }
+
+    /// Types a text, pressing and releasing the key of each character. Characters without a key
+    /// on a US keyboard layout are skipped. It blocks a few milliseconds per character.
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The ASCII text to type
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the text was typed, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the device is not a keyboard
+    pub fn type_text(&self, text: &str) -> Result<(), BleError> {
+        for report in text.chars().filter_map(KeyboardReport::from_char) {
+            self.send_keyboard(&report)?;
+            thread::sleep(KEY_RELEASE_DELAY);
+            self.send_keyboard(&KeyboardReport::default())?;
+            thread::sleep(KEY_RELEASE_DELAY);
+        }
+        Ok(())
+    }
+
+    /// Gets the state of the keyboard LEDs, as last written by the host
+    ///
+    /// # Returns
+    ///
+    /// The KeyboardLeds
+    pub fn keyboard_leds(&self) -> KeyboardLeds {
+        let state = self.leds.load(Ordering::Acquire);
+        KeyboardLeds {
+            num_lock: state & 0x01 != 0,
+            caps_lock: state & 0x02 != 0,
+            scroll_lock: state & 0x04 != 0,
+        }
+    }
+
+    /// Sends a movement of the mouse to the host
+    ///
+    /// # Arguments
+    ///
+    /// - `report`: The MouseReport with the movement and the pressed buttons
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the report was notified, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the device is not a mouse
+    pub fn send_mouse(&self, report: &MouseReport) -> Result<(), BleError> {
+        if self.kind != HidDeviceKind::Mouse {
+            return Err(BleError::InvalidParameters(None));
+        }
+        self.notify(&report.to_bytes(), &report.to_boot_bytes());
+        Ok(())
+    }
+
+    /// Sends the state of the gamepad to the host
+    ///
+    /// # Arguments
+    ///
+    /// - `report`: The GamepadReport with the pressed buttons and the position of the axes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the report was notified, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the device is not a gamepad
+    pub fn send_gamepad(&self, report: &GamepadReport) -> Result<(), BleError> {
+        if self.kind != HidDeviceKind::Gamepad {
+            return Err(BleError::InvalidParameters(None));
+        }
+        self.notify(&report.to_bytes(), &[]);
+        Ok(())
+    }
+
+    /// Notifies a report on the characteristic of the current protocol mode
+    fn notify(&self, report: &[u8], boot_report: &[u8]) {
+        let (characteristic, data) = match &self.boot_input {
+            Some(boot_input) if self.is_boot_mode() => (boot_input, boot_report),
+            _ => (&self.input, report),
+        };
+        let mut characteristic = characteristic.lock();
+        characteristic.set_value(data);
+        characteristic.notify();
+    }
+}
+
+/// Creates a characteristic of the HID Service with its initial value
+fn create_characteristic(
+    service: &Arc<Mutex<BLEService>>,
+    id: StandardCharacteristicId,
+    properties: NimbleProperties,
+    value: &[u8],
+) -> Arc<Mutex<BLECharacteristic>> {
+    let characteristic = service.lock().create_characteristic(
+        BleId::from_standard_characteristic(id).to_uuid(),
+        properties,
+    );
+    characteristic.lock().set_value(value);
+    characteristic
+}
+
+/// Creates a Report characteristic with its Report Reference descriptor. Since every report uses
+/// the same uuid, the descriptor tells the host which report it is.
+fn create_report(
+    service: &Arc<Mutex<BLEService>>,
+    report_id: u8,
+    report_type: u8,
+    properties: NimbleProperties,
+) -> Arc<Mutex<BLECharacteristic>> {
+    let characteristic =
+        create_characteristic(service, StandardCharacteristicId::Report, properties, &[]);
+    characteristic
+        .lock()
+        .create_descriptor(
+            BleId::from_standard_descriptor(StandardDescriptorId::ReportReference).to_uuid(),
+            DescriptorProperties::READ,
+        )
+        .lock()
+        .set_value(&[report_id, report_type]);
+    characteristic
+}
+
+/// Gets the usage id of the key of an ASCII character on a US keyboard layout, and whether shift
+/// must be pressed
+fn ascii_to_usage(c: char) -> Option<(u8, bool)> {
+    let usage = match c {
+        'a'..='z' => (0x04 + (c as u8 - b'a'), false),
+        'A'..='Z' => (0x04 + (c as u8 - b'A'), true),
+        '1'..='9' => (0x1E + (c as u8 - b'1'), false),
+        '0' => (0x27, false),
+        '\n' => (0x28, false),
+        '\t' => (0x2B, false),
+        ' ' => (0x2C, false),
+        '!' => (0x1E, true),
+        '@' => (0x1F, true),
+        '#' => (0x20, true),
+        '$' => (0x21, true),
+        '%' => (0x22, true),
+        '^' => (0x23, true),
+        '&' => (0x24, true),
+        '*' => (0x25, true),
+        '(' => (0x26, true),
+        ')' => (0x27, true),
+        '-' | '_' => (0x2D, c == '_'),
+        '=' | '+' => (0x2E, c == '+'),
+        '[' | '{' => (0x2F, c == '{'),
+        ']' | '}' => (0x30, c == '}'),
+        '\\' | '|' => (0x31, c == '|'),
+        ';' | ':' => (0x33, c == ':'),
+        '\'' | '"' => (0x34, c == '"'),
+        '`' | '~' => (0x35, c == '~'),
+        ',' | '<' => (0x36, c == '<'),
+        '.' | '>' => (0x37, c == '>'),
+        '/' | '?' => (0x38, c == '?'),
+        _ => return None,
+    };
+    Some(usage)
+}
@@ -0,0 +1,670 @@
+use super::utils::BleError;
+use esp32_nimble::BLEError;
+use esp_idf_svc::sys::{
+    ble_l2cap_chan, ble_l2cap_chan_info, ble_l2cap_connect, ble_l2cap_create_server,
+    ble_l2cap_disconnect, ble_l2cap_event, ble_l2cap_get_chan_info, ble_l2cap_recv_ready,
+    ble_l2cap_send, os_mbuf, os_mbuf_append, os_mbuf_copydata, os_mbuf_free_chain, os_mbuf_len,
+    os_msys_get_pkthdr, BLE_HS_ENOMEM, BLE_HS_ESTALLED, BLE_L2CAP_EVENT_COC_ACCEPT,
+    BLE_L2CAP_EVENT_COC_CONNECTED, BLE_L2CAP_EVENT_COC_DATA_RECEIVED,
+    BLE_L2CAP_EVENT_COC_DISCONNECTED, BLE_L2CAP_EVENT_COC_TX_UNSTALLED,
+};
+use std::{
+    collections::VecDeque,
+    ffi::{c_int, c_void},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+/// Smallest MTU allowed for a connection oriented channel by the BLE specification
+const MIN_COC_MTU: u16 = 23;
+/// Max amount of received SDUs kept before the peer stops receiving credits
+const RX_QUEUE_SIZE: usize = 8;
+const MAX_LE_PSM: u16 = 0xFF;
+
+/// Pointer to a NimBLE channel. It is only read or written while holding the lock of the
+/// channel state.
+#[derive(Clone, Copy, PartialEq)]
+struct ChanPtr(*mut ble_l2cap_chan);
+
+unsafe impl Send for ChanPtr {}
+
+/// State of a channel, updated from the BLE task on every event. Contains:
+/// - `chan`: The NimBLE channel, null until it is connected.
+/// - `conn_handle`: The connection the channel belongs to.
+/// - `connected`: Whether the channel is connected.
+/// - `connect_status`: The status of the connection procedure, None while it is in progress.
+/// - `received`: The SDUs received that were not read yet.
+/// - `rx_paused`: Whether the peer was left without credits because the queue was full.
+/// - `tx_stalled`: Whether the peer ran out of credits for the SDUs sent.
+struct ChannelInner {
+    chan: ChanPtr,
+    conn_handle: u16,
+    connected: bool,
+    connect_status: Option<i32>,
+    received: VecDeque<Vec<u8>>,
+    rx_paused: bool,
+    tx_stalled: bool,
+}
+
+/// State of a channel shared with the BLE task, with a Condvar signaled on every change
+struct ChannelState {
+    inner: Mutex<ChannelInner>,
+    changed: Condvar,
+    mtu: u16,
+}
+
+/// Receiver of the events of the channels of a PSM, passed to NimBLE as the argument of the
+/// event callback. Contains:
+/// - `mtu`: The MTU of the SDUs received on the channels.
+/// - `is_server`: Whether the channels are accepted, instead of opened by this device.
+/// - `channels`: The channels connected or being connected.
+/// - `accepted`: The channels accepted that were not returned to the user yet.
+/// - `accepted_changed`: Condvar signaled when a channel is accepted.
+struct Endpoint {
+    mtu: u16,
+    is_server: bool,
+    channels: Mutex<Vec<Arc<ChannelState>>>,
+    accepted: Mutex<VecDeque<Arc<ChannelState>>>,
+    accepted_changed: Condvar,
+}
+
+/// An L2CAP connection oriented channel, used to transfer raw data with a connected device
+/// outside of GATT, at a higher throughput than characteristics. Data is sent and received as
+/// SDUs (service data units) of up to the MTU of the receiver. Flow control is based on
+/// credits: the peer can only send while the channel has room for the received SDUs, so reading
+/// them is what allows it to keep sending.
+///
+/// L2CAP channels need the `CONFIG_BT_NIMBLE_L2CAP_COC_MAX_NUM` option of the sdkconfig to be
+/// set to the max amount of simultaneous channels.
+pub struct L2capChannel {
+    state: Arc<ChannelState>,
+}
+
+/// Listener of the L2CAP channels opened by connected devices on a PSM.
+pub struct L2capListener {
+    endpoint: &'static Endpoint,
+}
+
+impl ChannelState {
+    /// Creates a new ChannelState
+    fn new(chan: *mut ble_l2cap_chan, conn_handle: u16, mtu: u16) -> Arc<Self> {
+        Arc::new(ChannelState {
+            inner: Mutex::new(ChannelInner {
+                chan: ChanPtr(chan),
+                conn_handle,
+                connected: false,
+                connect_status: None,
+                received: VecDeque::new(),
+                rx_paused: false,
+                tx_stalled: false,
+            }),
+            changed: Condvar::new(),
+            mtu,
+        })
+    }
+
+    /// Locks the inner state, even if a thread panicked while holding it
+    fn lock(&self) -> MutexGuard<'_, ChannelInner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Waits until the condition is met or the timeout ends
+    ///
+    /// # Returns
+    ///
+    /// The guard of the inner state, and whether the condition was met
+    fn wait_while<'g, F: FnMut(&mut ChannelInner) -> bool>(
+        &self,
+        guard: MutexGuard<'g, ChannelInner>,
+        timeout: Option<Duration>,
+        condition: F,
+    ) -> (MutexGuard<'g, ChannelInner>, bool) {
+        match timeout {
+            Some(timeout) => {
+                let (guard, res) = self
+                    .changed
+                    .wait_timeout_while(guard, timeout, condition)
+                    .unwrap_or_else(|err| err.into_inner());
+                (guard, !res.timed_out())
+            }
+            None => (
+                self.changed
+                    .wait_while(guard, condition)
+                    .unwrap_or_else(|err| err.into_inner()),
+                true,
+            ),
+        }
+    }
+
+    /// Gives the peer a new buffer to send an SDU, which grants it the credits to do it
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the buffer could not be allocated or NimBLE rejected it
+    fn give_rx_buffer(&self, chan: *mut ble_l2cap_chan) -> Result<(), BleError> {
+        let sdu = new_sdu_buffer(self.mtu)?;
+        let res = unsafe { ble_l2cap_recv_ready(chan, sdu) };
+        if res != 0 {
+            unsafe { os_mbuf_free_chain(sdu) };
+        }
+        Ok(BLEError::convert(res as u32)?)
+    }
+}
+
+impl Endpoint {
+    /// Creates a new Endpoint
+    fn new(mtu: u16, is_server: bool) -> Arc<Self> {
+        Arc::new(Endpoint {
+            mtu,
+            is_server,
+            channels: Mutex::new(vec![]),
+            accepted: Mutex::new(VecDeque::new()),
+            accepted_changed: Condvar::new(),
+        })
+    }
+
+    /// Finds the state of a channel. Channels opened by this device are found by their connection,
+    /// since NimBLE informs the channel only once it is connected.
+    fn find(&self, chan: *mut ble_l2cap_chan, conn_handle: u16) -> Option<Arc<ChannelState>> {
+        let channels = self.channels.lock().unwrap_or_else(|err| err.into_inner());
+        channels
+            .iter()
+            .find(|state| {
+                let inner = state.lock();
+                inner.chan.0 == chan || (inner.chan.0.is_null() && inner.conn_handle == conn_handle)
+            })
+            .cloned()
+    }
+
+    /// Stops tracking a channel
+    fn remove(&self, state: &Arc<ChannelState>) {
+        let mut channels = self.channels.lock().unwrap_or_else(|err| err.into_inner());
+        channels.retain(|channel| !Arc::ptr_eq(channel, state));
+    }
+
+    /// Handles an event of NimBLE
+    ///
+    /// # Returns
+    ///
+    /// The code returned to NimBLE, 0 unless an incoming channel is rejected
+    ///
+    /// # Safety
+    ///
+    /// The event must be a valid event received on the callback
+    unsafe fn handle_event(&self, event: &ble_l2cap_event) -> c_int {
+        let data = &event.__bindgen_anon_1;
+        match event.type_ as u32 {
+            BLE_L2CAP_EVENT_COC_ACCEPT => {
+                let accept = data.accept;
+                let state = ChannelState::new(accept.chan, accept.conn_handle, self.mtu);
+                if state.give_rx_buffer(accept.chan).is_err() {
+                    return BLE_HS_ENOMEM as c_int;
+                }
+                self.channels
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .push(state);
+            }
+            BLE_L2CAP_EVENT_COC_CONNECTED => {
+                let connect = data.connect;
+                let Some(state) = self.find(connect.chan, connect.conn_handle) else {
+                    return 0;
+                };
+                {
+                    let mut inner = state.lock();
+                    inner.chan = ChanPtr(connect.chan);
+                    inner.connected = connect.status == 0;
+                    inner.connect_status = Some(connect.status);
+                }
+                state.changed.notify_all();
+                if connect.status != 0 {
+                    self.remove(&state);
+                } else if self.is_server {
+                    self.accepted
+                        .lock()
+                        .unwrap_or_else(|err| err.into_inner())
+                        .push_back(state);
+                    self.accepted_changed.notify_all();
+                }
+            }
+            BLE_L2CAP_EVENT_COC_DISCONNECTED => {
+                let disconnect = data.disconnect;
+                if let Some(state) = self.find(disconnect.chan, disconnect.conn_handle) {
+                    state.lock().connected = false;
+                    state.changed.notify_all();
+                    self.remove(&state);
+                }
+            }
+            BLE_L2CAP_EVENT_COC_DATA_RECEIVED => {
+                let receive = data.receive;
+                let sdu = read_sdu(receive.sdu_rx);
+                let Some(state) = self.find(receive.chan, receive.conn_handle) else {
+                    return 0;
+                };
+                let queue_full = {
+                    let mut inner = state.lock();
+                    inner.received.push_back(sdu);
+                    inner.rx_paused = inner.received.len() >= RX_QUEUE_SIZE;
+                    inner.rx_paused
+                };
+                if !queue_full && state.give_rx_buffer(receive.chan).is_err() {
+                    state.lock().rx_paused = true;
+                }
+                state.changed.notify_all();
+            }
+            BLE_L2CAP_EVENT_COC_TX_UNSTALLED => {
+                let unstalled = data.tx_unstalled;
+                if let Some(state) = self.find(unstalled.chan, unstalled.conn_handle) {
+                    state.lock().tx_stalled = false;
+                    state.changed.notify_all();
+                }
+            }
+            _ => {}
+        }
+        0
+    }
+
+    /// Checks whether no more events will be received for the channels of a client endpoint
+    fn is_finished(&self) -> bool {
+        !self.is_server
+            && self
+                .channels
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .is_empty()
+    }
+}
+
+impl L2capChannel {
+    /// Opens a channel with a connected device, blocking until the device accepts or rejects it
+    ///
+    /// # Arguments
+    ///
+    /// - `conn_handle`: The connection with the device
+    /// - `psm`: The Protocol/Service Multiplexer the device listens on
+    /// - `mtu`: The max size of the SDUs this device can receive
+    /// - `timeout`: Max time to wait for the device to answer, or None to wait indefinitely
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new L2capChannel, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the PSM is not a LE PSM or the MTU is smaller than 23
+    /// - `BleError::TimeOut`: If the device did not answer in time
+    /// - `BleError::Code`: If the device rejected the channel or NimBLE could not open it
+    pub(crate) fn open(
+        conn_handle: u16,
+        psm: u16,
+        mtu: u16,
+        timeout: Option<Duration>,
+    ) -> Result<Self, BleError> {
+        validate_psm_and_mtu(psm, mtu)?;
+        let endpoint = Endpoint::new(mtu, false);
+        let state = ChannelState::new(std::ptr::null_mut(), conn_handle, mtu);
+        endpoint
+            .channels
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push(state.clone());
+
+        let sdu = new_sdu_buffer(mtu)?;
+        let arg = Arc::into_raw(endpoint) as *mut c_void;
+        let res = unsafe {
+            ble_l2cap_connect(conn_handle, psm, mtu, sdu, Some(client_event_callback), arg)
+        };
+        if res != 0 {
+            unsafe {
+                os_mbuf_free_chain(sdu);
+                drop(Arc::from_raw(arg as *const Endpoint));
+            }
+            BLEError::convert(res as u32)?;
+        }
+
+        let inner = state.lock();
+        let (inner, _) = state.wait_while(inner, timeout, |inner| inner.connect_status.is_none());
+        match inner.connect_status {
+            Some(0) => {}
+            Some(status) => BLEError::convert(status as u32)?,
+            None => return Err(BleError::TimeOut(None)),
+        }
+        drop(inner);
+        Ok(L2capChannel { state })
+    }
+
+    /// Sends an SDU to the peer. If the peer has no credits left, it blocks until the previous
+    /// SDU is sent.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The data to send, of up to the MTU of the peer
+    /// - `timeout`: Max time to wait for the peer to give credits, or None to wait indefinitely
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the SDU was queued to be sent, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the channel is not connected
+    /// - `BleError::InvalidParameters`: If the data is bigger than the MTU of the peer
+    /// - `BleError::TimeOut`: If the peer did not give credits in time
+    /// - `BleError::Code`: If NimBLE could not send the SDU
+    pub fn send(&self, data: &[u8], timeout: Option<Duration>) -> Result<(), BleError> {
+        if data.len() > self.peer_mtu()? as usize {
+            return Err(BleError::InvalidParameters(None));
+        }
+        let inner = self.state.lock();
+        let (mut inner, unstalled) = self
+            .state
+            .wait_while(inner, timeout, |inner| inner.connected && inner.tx_stalled);
+        if !inner.connected {
+            return Err(BleError::Disconnected(None));
+        }
+        if !unstalled {
+            return Err(BleError::TimeOut(None));
+        }
+
+        let sdu = new_sdu_buffer(data.len() as u16)?;
+        let res = unsafe {
+            match os_mbuf_append(sdu, data.as_ptr() as *const c_void, data.len() as u16) {
+                0 => ble_l2cap_send(inner.chan.0, sdu),
+                res => res,
+            }
+        };
+        if res == BLE_HS_ESTALLED as c_int {
+            inner.tx_stalled = true;
+            return Ok(());
+        }
+        if res != 0 {
+            unsafe { os_mbuf_free_chain(sdu) };
+        }
+        Ok(BLEError::convert(res as u32)?)
+    }
+
+    /// Sends a stream of bytes, split in SDUs of the MTU of the peer
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The data to send
+    /// - `timeout`: Max time to wait for the peer to give credits for each SDU, or None to wait
+    ///   indefinitely
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if every SDU was queued to be sent, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the channel is not connected
+    /// - `BleError::TimeOut`: If the peer did not give credits in time
+    /// - `BleError::Code`: If NimBLE could not send an SDU
+    pub fn send_all(&self, data: &[u8], timeout: Option<Duration>) -> Result<(), BleError> {
+        let peer_mtu = self.peer_mtu()?.max(1) as usize;
+        data.chunks(peer_mtu)
+            .try_for_each(|chunk| self.send(chunk, timeout))
+    }
+
+    /// Receives the next SDU sent by the peer, blocking until it arrives
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: Max time to wait for an SDU, or None to wait indefinitely
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the data of the SDU, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the channel was disconnected and every SDU was read
+    /// - `BleError::TimeOut`: If no SDU arrived in time
+    pub fn receive(&self, timeout: Option<Duration>) -> Result<Vec<u8>, BleError> {
+        let inner = self.state.lock();
+        let (inner, _) = self.state.wait_while(inner, timeout, |inner| {
+            inner.connected && inner.received.is_empty()
+        });
+        let disconnected = !inner.connected;
+        drop(inner);
+        match self.try_receive() {
+            Some(sdu) => Ok(sdu),
+            None if disconnected => Err(BleError::Disconnected(None)),
+            None => Err(BleError::TimeOut(None)),
+        }
+    }
+
+    /// Receives the next SDU sent by the peer, if there is any. Reading the SDUs gives the peer
+    /// the credits to keep sending.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the data of the SDU, or None if no SDU was received
+    pub fn try_receive(&self) -> Option<Vec<u8>> {
+        let mut inner = self.state.lock();
+        let sdu = inner.received.pop_front()?;
+        if inner.rx_paused && inner.connected && inner.received.len() < RX_QUEUE_SIZE {
+            inner.rx_paused = self.state.give_rx_buffer(inner.chan.0).is_err();
+        }
+        Some(sdu)
+    }
+
+    /// Gets the amount of SDUs received that were not read yet
+    ///
+    /// # Returns
+    ///
+    /// The amount of SDUs waiting to be read
+    pub fn available(&self) -> usize {
+        self.state.lock().received.len()
+    }
+
+    /// Checks whether the channel is connected
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is true while the channel is connected
+    pub fn is_connected(&self) -> bool {
+        self.state.lock().connected
+    }
+
+    /// Gets the max size of the SDUs this device can receive
+    ///
+    /// # Returns
+    ///
+    /// The MTU in bytes
+    pub fn mtu(&self) -> u16 {
+        self.state.mtu
+    }
+
+    /// Gets the max size of the SDUs the peer can receive
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the MTU in bytes, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Disconnected`: If the channel is not connected
+    /// - `BleError::Code`: If NimBLE could not get the information of the channel
+    pub fn peer_mtu(&self) -> Result<u16, BleError> {
+        let inner = self.state.lock();
+        if !inner.connected {
+            return Err(BleError::Disconnected(None));
+        }
+        let mut info: ble_l2cap_chan_info = unsafe { std::mem::zeroed() };
+        BLEError::convert(unsafe { ble_l2cap_get_chan_info(inner.chan.0, &mut info) } as u32)?;
+        Ok(info.peer_coc_mtu)
+    }
+
+    /// Disconnects the channel. The connection with the device remains open.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the channel is disconnecting or was already disconnected, or a
+    /// `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If NimBLE could not disconnect the channel
+    pub fn disconnect(&self) -> Result<(), BleError> {
+        let inner = self.state.lock();
+        if !inner.connected || inner.chan.0.is_null() {
+            return Ok(());
+        }
+        let chan = inner.chan.0;
+        drop(inner);
+        Ok(BLEError::convert(
+            unsafe { ble_l2cap_disconnect(chan) } as u32
+        )?)
+    }
+}
+
+impl Drop for L2capChannel {
+    fn drop(&mut self) {
+        _ = self.disconnect();
+    }
+}
+
+impl L2capListener {
+    /// Starts listening for channels on a PSM. Since NimBLE does not allow to stop listening,
+    /// each PSM can only be listened once.
+    ///
+    /// # Arguments
+    ///
+    /// - `psm`: The Protocol/Service Multiplexer to listen on
+    /// - `mtu`: The max size of the SDUs this device can receive
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new L2capListener, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the PSM is not a LE PSM or the MTU is smaller than 23
+    /// - `BleError::Code`: If the PSM is already being listened, or L2CAP channels are disabled
+    ///   on the sdkconfig
+    pub(crate) fn new(psm: u16, mtu: u16) -> Result<Self, BleError> {
+        validate_psm_and_mtu(psm, mtu)?;
+        let endpoint = Arc::into_raw(Endpoint::new(mtu, true));
+        let res = unsafe {
+            ble_l2cap_create_server(
+                psm,
+                mtu,
+                Some(server_event_callback),
+                endpoint as *mut c_void,
+            )
+        };
+        if res != 0 {
+            unsafe { drop(Arc::from_raw(endpoint)) };
+            BLEError::convert(res as u32)?;
+        }
+        Ok(L2capListener {
+            endpoint: unsafe { &*endpoint },
+        })
+    }
+
+    /// Waits for a connected device to open a channel
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: Max time to wait, or None to wait indefinitely
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new L2capChannel, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimeOut`: If no channel was opened in time
+    pub fn accept(&self, timeout: Option<Duration>) -> Result<L2capChannel, BleError> {
+        let accepted = self
+            .endpoint
+            .accepted
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let condition = |accepted: &mut VecDeque<Arc<ChannelState>>| accepted.is_empty();
+        let mut accepted = match timeout {
+            Some(timeout) => {
+                self.endpoint
+                    .accepted_changed
+                    .wait_timeout_while(accepted, timeout, condition)
+                    .unwrap_or_else(|err| err.into_inner())
+                    .0
+            }
+            None => self
+                .endpoint
+                .accepted_changed
+                .wait_while(accepted, condition)
+                .unwrap_or_else(|err| err.into_inner()),
+        };
+        accepted
+            .pop_front()
+            .map(|state| L2capChannel { state })
+            .ok_or(BleError::TimeOut(None))
+    }
+
+    /// Gets the next channel opened by a connected device, if there is any
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the new L2capChannel, or None if no channel was opened
+    pub fn try_accept(&self) -> Option<L2capChannel> {
+        self.endpoint
+            .accepted
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .pop_front()
+            .map(|state| L2capChannel { state })
+    }
+}
+
+/// Event callback of the channels opened by this device. The endpoint is released once its
+/// channel is disconnected or could not be connected.
+unsafe extern "C" fn client_event_callback(event: *mut ble_l2cap_event, arg: *mut c_void) -> c_int {
+    let endpoint = arg as *const Endpoint;
+    let res = (*endpoint).handle_event(&*event);
+    if (*endpoint).is_finished() {
+        drop(Arc::from_raw(endpoint));
+    }
+    res
+}
+
+/// Event callback of the channels accepted by a listener
+unsafe extern "C" fn server_event_callback(event: *mut ble_l2cap_event, arg: *mut c_void) -> c_int {
+    (*(arg as *const Endpoint)).handle_event(&*event)
+}
+
+/// Checks that the PSM is in the LE range and the MTU is big enough
+fn validate_psm_and_mtu(psm: u16, mtu: u16) -> Result<(), BleError> {
+    if psm == 0 || psm > MAX_LE_PSM || mtu < MIN_COC_MTU {
+        return Err(BleError::InvalidParameters(None));
+    }
+    Ok(())
+}
+
+/// Allocates a buffer for an SDU from the NimBLE memory pool
+///
+/// # Errors
+///
+/// - `BleError::Code`: If there is no memory left on the pool
+fn new_sdu_buffer(size: u16) -> Result<*mut os_mbuf, BleError> {
+    let sdu = unsafe { os_msys_get_pkthdr(size, 0) };
+    if sdu.is_null() {
+        return Err(BleError::Code(
+            BLE_HS_ENOMEM,
+            "No memory left for the L2CAP buffer".to_string(),
+        ));
+    }
+    Ok(sdu)
+}
+
+/// Copies the data of a received SDU and releases its buffer
+fn read_sdu(sdu: *mut os_mbuf) -> Vec<u8> {
+    unsafe {
+        let len = os_mbuf_len(sdu);
+        let mut data = vec![0u8; len as usize];
+        os_mbuf_copydata(sdu, 0, len as c_int, data.as_mut_ptr() as *mut c_void);
+        os_mbuf_free_chain(sdu);
+        data
+    }
+}
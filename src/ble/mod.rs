@@ -1,9 +1,23 @@
 mod ble_client;
+mod ble_client_connection;
 mod ble_connection_oriented;
 mod ble_connectionless;
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+mod ble_extended_advertiser;
+mod hid;
+mod l2cap_channel;
+mod presence_monitor;
+mod server_events;
 pub mod utils;
 
 pub use ble_client::*;
+pub use ble_client_connection::*;
 pub use ble_connection_oriented::*;
 pub use ble_connectionless::*;
+#[cfg(esp_idf_bt_nimble_ext_adv)]
+pub use ble_extended_advertiser::*;
+pub use hid::*;
+pub use l2cap_channel::*;
+pub use presence_monitor::*;
+pub use server_events::*;
 pub use utils::{BleError, BleId};
@@ -0,0 +1,431 @@
+use super::utils::BleError;
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        isr_queues::{ISRQueue, ISRQueueTrait},
+        notification::Notifier,
+        queue_config::queue_capacities,
+        timer_driver::TimerDriver,
+    },
+    InterruptDriver,
+};
+use esp32_nimble::{BLEAddress, BLEDevice, BLEScan};
+use esp_idf_svc::sys::{
+    mbedtls_aes_context, mbedtls_aes_crypt_ecb, mbedtls_aes_free, mbedtls_aes_init,
+    mbedtls_aes_setkey_enc, MBEDTLS_AES_ENCRYPT,
+};
+use futures::FutureExt;
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::time::{Duration, Instant};
+
+const BLOCK: i32 = i32::MAX;
+const CHECK_PERIOD_US: u64 = 1_000_000;
+const DEFAULT_LEAVE_TIMEOUT: Duration = Duration::from_secs(30);
+const RESOLVABLE_ADDRESS_MASK: u8 = 0xC0;
+const RESOLVABLE_ADDRESS_BITS: u8 = 0x40;
+
+type EnterCallback<'a> = dyn FnMut(&PresenceTarget, i32) + 'a;
+type LeaveCallback<'a> = dyn FnMut(&PresenceTarget) + 'a;
+
+/// Enums the ways a device can be identified by the PresenceMonitor:
+/// - `Address`: The device always advertises with the same address.
+/// - `Irk`: The device uses resolvable private addresses that can be resolved with its Identity
+///   Resolving Key. The key must be given with its most significant byte first.
+#[derive(Debug, Clone, Copy)]
+pub enum PresenceTarget {
+    Address(BLEAddress),
+    Irk([u8; 16]),
+}
+
+/// Sighting of a target, sent from the scan callback to the update loop
+#[derive(Clone, Copy)]
+struct Sighting {
+    target: usize,
+    rssi: i32,
+}
+
+/// State of each of the targets being monitored
+struct TrackedTarget {
+    target: PresenceTarget,
+    first_seen: Option<Instant>,
+    last_seen: Option<Instant>,
+    last_rssi: i32,
+    present: bool,
+}
+
+/// Continuously scans for a set of devices, firing a callback when one of them enters or leaves the area.
+/// Contains:
+/// - `ble_scan`: The BLEScan used to look for the targets.
+/// - `timer_driver`: A TimerDriver used to check periodically if any target left.
+/// - `targets`: The devices being monitored.
+/// - `sightings`: Queue where the scan informs the targets that were seen.
+/// - `enter_delay`: Time a target must keep being seen before being considered present.
+/// - `leave_timeout`: Time without seeing a present target before considering it gone.
+/// - `on_enter`: Callback executed when a target is considered present.
+/// - `on_leave`: Callback executed when a target is considered gone.
+/// - `notifier`: Notifier used to wake up the microcontroller when a target is seen.
+struct _PresenceMonitor<'a> {
+    ble_scan: &'static mut BLEScan,
+    timer_driver: TimerDriver<'a>,
+    targets: Vec<TrackedTarget>,
+    sightings: ISRQueue<Sighting>,
+    enter_delay: Duration,
+    leave_timeout: Duration,
+    on_enter: Option<Box<EnterCallback<'a>>>,
+    on_leave: Option<Box<LeaveCallback<'a>>>,
+    notifier: Notifier,
+}
+
+/// Continuously scans for a set of devices, firing a callback when one of them enters or leaves the area.
+/// Useful for occupancy or "phone nearby" automations.
+pub struct PresenceMonitor<'a> {
+    inner: SharableRef<_PresenceMonitor<'a>>,
+}
+
+impl PresenceTarget {
+    /// Checks if an advertised address belongs to the target
+    fn matches(&self, address: &BLEAddress) -> bool {
+        match self {
+            PresenceTarget::Address(target) => target == address,
+            PresenceTarget::Irk(irk) => resolves_private_address(irk, address),
+        }
+    }
+}
+
+impl TrackedTarget {
+    /// Creates a new TrackedTarget that has not been seen yet
+    fn new(target: PresenceTarget) -> Self {
+        TrackedTarget {
+            target,
+            first_seen: None,
+            last_seen: None,
+            last_rssi: 0,
+            present: false,
+        }
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _PresenceMonitor<'a> {
+    /// Creates a new _PresenceMonitor without targets
+    ///
+    /// # Arguments
+    ///
+    /// - `ble_device`: A BLEDevice needed to get the BLEScan
+    /// - `timer_driver`: A TimerDriver used to check periodically if any target left
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a target is seen
+    ///
+    /// # Returns
+    ///
+    /// The new _PresenceMonitor
+    fn new(ble_device: &mut BLEDevice, timer_driver: TimerDriver<'a>, notifier: Notifier) -> Self {
+        _PresenceMonitor {
+            ble_scan: ble_device.get_scan(),
+            timer_driver,
+            targets: vec![],
+            sightings: ISRQueue::new(queue_capacities().ble_sightings),
+            enter_delay: Duration::ZERO,
+            leave_timeout: DEFAULT_LEAVE_TIMEOUT,
+            on_enter: None,
+            on_leave: None,
+            notifier,
+        }
+    }
+
+    /// Adds a device to monitor. Targets added after [Self::start] will be monitored after the
+    /// next call to [Self::start].
+    ///
+    /// # Arguments
+    ///
+    /// - `target`: The PresenceTarget that identifies the device
+    ///
+    /// # Returns
+    ///
+    /// The _PresenceMonitor itself
+    pub fn add_target(&mut self, target: PresenceTarget) -> &mut Self {
+        self.targets.push(TrackedTarget::new(target));
+        self
+    }
+
+    /// Sets the time a target must keep being seen before being considered present. This avoids firing
+    /// the enter callback for devices that just pass by. By default it is 0.
+    ///
+    /// # Arguments
+    ///
+    /// - `delay`: Duration of the delay
+    ///
+    /// # Returns
+    ///
+    /// The _PresenceMonitor itself
+    pub fn set_enter_delay(&mut self, delay: Duration) -> &mut Self {
+        self.enter_delay = delay;
+        self
+    }
+
+    /// Sets the time without seeing a present target before considering it gone. By default it is 30 seconds.
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: Duration of the timeout
+    ///
+    /// # Returns
+    ///
+    /// The _PresenceMonitor itself
+    pub fn set_leave_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.leave_timeout = timeout;
+        self
+    }
+
+    /// Sets the callback that will be executed when a target is considered present. The callback
+    /// receives the target and the rssi of the last sighting.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that will be executed when a target enters
+    ///
+    /// # Returns
+    ///
+    /// The _PresenceMonitor itself
+    pub fn on_enter<C: FnMut(&PresenceTarget, i32) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.on_enter = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the callback that will be executed when a target is considered gone.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that will be executed when a target leaves
+    ///
+    /// # Returns
+    ///
+    /// The _PresenceMonitor itself
+    pub fn on_leave<C: FnMut(&PresenceTarget) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.on_leave = Some(Box::new(callback));
+        self
+    }
+
+    /// Starts scanning continuously for the targets.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the scan started successfully, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimerDriverError`: If the timer driver could not be enabled.
+    /// - `BleError::Code`: If the scan could not be started.
+    pub fn start(&mut self) -> Result<(), BleError> {
+        let targets: Vec<PresenceTarget> = self.targets.iter().map(|t| t.target).collect();
+        let notifier_ref = self.notifier.clone();
+        let mut sightings_ref = self.sightings.clone();
+
+        self.ble_scan
+            .active_scan(false)
+            .filter_duplicates(false)
+            .on_result(move |_, device| {
+                if let Some(target) = targets.iter().position(|t| t.matches(device.addr())) {
+                    notifier_ref.notify();
+                    _ = sightings_ref.try_send(Sighting {
+                        target,
+                        rssi: device.rssi(),
+                    });
+                }
+            });
+
+        // The scan procedure begins the first time the future is polled, and then the future only waits
+        // for the procedure to end. Since it never ends, polling it once leaves the scan running.
+        if let Some(res) = self.ble_scan.start(BLOCK).now_or_never() {
+            res?;
+        }
+
+        self.timer_driver
+            .interrupt_after_n_times(CHECK_PERIOD_US, None, true, || {});
+        self.timer_driver
+            .enable()
+            .map_err(BleError::TimerDriverError)
+    }
+
+    /// Stops scanning for the targets. Targets that were present will remain present until [Self::start]
+    /// is called again.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the scan stopped successfully, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::TimerDriverError`: If the timer driver could not be disabled.
+    /// - `BleError::Code`: If the scan could not be stopped.
+    pub fn stop(&mut self) -> Result<(), BleError> {
+        self.ble_scan.stop()?;
+        self.timer_driver
+            .disable()
+            .map_err(BleError::TimerDriverError)
+    }
+
+    /// Gets the targets that are currently considered present
+    ///
+    /// # Returns
+    ///
+    /// A vector with every present target
+    pub fn present_targets(&self) -> Vec<PresenceTarget> {
+        self.targets
+            .iter()
+            .filter(|t| t.present)
+            .map(|t| t.target)
+            .collect()
+    }
+
+    /// Registers the sightings received from the scan and fires the enter and leave callbacks
+    /// of the targets whose state changed.
+    fn update_presence(&mut self) {
+        let now = Instant::now();
+        while let Ok(sighting) = self.sightings.try_recv() {
+            if let Some(tracked) = self.targets.get_mut(sighting.target) {
+                let gone = tracked
+                    .last_seen
+                    .map_or(true, |last| now.duration_since(last) >= self.leave_timeout);
+                if !tracked.present && gone {
+                    tracked.first_seen = Some(now);
+                }
+                tracked.last_seen = Some(now);
+                tracked.last_rssi = sighting.rssi;
+            }
+        }
+
+        for tracked in &mut self.targets {
+            let (first_seen, last_seen) = match (tracked.first_seen, tracked.last_seen) {
+                (Some(first_seen), Some(last_seen)) => (first_seen, last_seen),
+                _ => continue,
+            };
+            let gone = now.duration_since(last_seen) >= self.leave_timeout;
+
+            if !tracked.present && !gone && last_seen.duration_since(first_seen) >= self.enter_delay
+            {
+                tracked.present = true;
+                if let Some(on_enter) = self.on_enter.as_mut() {
+                    on_enter(&tracked.target, tracked.last_rssi);
+                }
+            } else if tracked.present && gone {
+                tracked.present = false;
+                tracked.first_seen = None;
+                if let Some(on_leave) = self.on_leave.as_mut() {
+                    on_leave(&tracked.target);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> PresenceMonitor<'a> {
+    /// Creates a new PresenceMonitor without targets
+    ///
+    /// # Arguments
+    ///
+    /// - `ble_device`: A BLEDevice needed to get the BLEScan
+    /// - `timer_driver`: A TimerDriver used to check periodically if any target left
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a target is seen
+    ///
+    /// # Returns
+    ///
+    /// The new PresenceMonitor
+    pub(crate) fn new(
+        ble_device: &mut BLEDevice,
+        timer_driver: TimerDriver<'a>,
+        notifier: Notifier,
+    ) -> Self {
+        Self {
+            inner: SharableRef::new_sharable(_PresenceMonitor::new(
+                ble_device,
+                timer_driver,
+                notifier,
+            )),
+        }
+    }
+}
+
+impl<'a> InterruptDriver<'a> for PresenceMonitor<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().update_presence();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// Stops scanning for the targets
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        Ok(self.stop()?)
+    }
+}
+
+/// Checks if a resolvable private address was generated with the given Identity Resolving Key.
+/// The hash on the lower half of the address must match the result of encrypting the random
+/// part of the address with the key.
+///
+/// # Arguments
+///
+/// - `irk`: Identity Resolving Key, most significant byte first
+/// - `address`: The address to resolve
+///
+/// # Returns
+///
+/// True if the address was generated with the key, False if not
+fn resolves_private_address(irk: &[u8; 16], address: &BLEAddress) -> bool {
+    let addr = address.as_le_bytes();
+    if addr[5] & RESOLVABLE_ADDRESS_MASK != RESOLVABLE_ADDRESS_BITS {
+        return false;
+    }
+    let mut plaintext = [0_u8; 16];
+    plaintext[13] = addr[5];
+    plaintext[14] = addr[4];
+    plaintext[15] = addr[3];
+
+    match aes_128_encrypt(irk, &plaintext) {
+        Some(hash) => hash[15] == addr[0] && hash[14] == addr[1] && hash[13] == addr[2],
+        None => false,
+    }
+}
+
+/// Encrypts a single block with AES-128
+///
+/// # Arguments
+///
+/// - `key`: The key, most significant byte first
+/// - `block`: The block to encrypt, most significant byte first
+///
+/// # Returns
+///
+/// An `Option` with the encrypted block, or `None` if the encryption failed
+fn aes_128_encrypt(key: &[u8; 16], block: &[u8; 16]) -> Option<[u8; 16]> {
+    let mut output = [0_u8; 16];
+    let res = unsafe {
+        let mut ctx: mbedtls_aes_context = std::mem::zeroed();
+        mbedtls_aes_init(&mut ctx);
+        let mut res = mbedtls_aes_setkey_enc(&mut ctx, key.as_ptr(), 128);
+        if res == 0 {
+            res = mbedtls_aes_crypt_ecb(
+                &mut ctx,
+                MBEDTLS_AES_ENCRYPT as i32,
+                block.as_ptr(),
+                output.as_mut_ptr(),
+            );
+        }
+        mbedtls_aes_free(&mut ctx);
+        res
+    };
+    (res == 0).then_some(output)
+}
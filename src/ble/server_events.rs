@@ -0,0 +1,177 @@
+use super::utils::{BleId, ConnectionInformation};
+use crate::utils::{
+    notification::Notification,
+    queue_config::{queue_capacities, record_dropped_item},
+};
+use esp32_nimble::utilities::mutex::Mutex;
+use futures::Stream;
+use std::{
+    collections::VecDeque,
+    future::Future,
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+/// Enums the events of a [crate::ble::BleServer] yielded by its [BleServerEvents]:
+/// - `Connected`: A client connected to the server.
+/// - `Disconnected`: A client disconnected from the server.
+/// - `CharacteristicWritten`: A client wrote data on a characteristic.
+/// - `SubscriptionChanged`: A client enabled or disabled the notifications or indications of a
+///   characteristic.
+#[derive(Debug, Clone)]
+pub enum BleServerEvent {
+    Connected(ConnectionInformation),
+    Disconnected(ConnectionInformation),
+    CharacteristicWritten {
+        service_id: BleId,
+        characteristic_id: BleId,
+        info: ConnectionInformation,
+        data: Vec<u8>,
+    },
+    SubscriptionChanged {
+        service_id: BleId,
+        characteristic_id: BleId,
+        info: ConnectionInformation,
+        notify: bool,
+        indicate: bool,
+    },
+}
+
+/// Events of a server waiting to be consumed, pushed from the BLE task:
+/// - `enabled`: Whether a BleServerEvents stream was requested. Until then, events are dropped.
+/// - `events`: The queued events, oldest first.
+/// - `capacity`: Maximum amount of queued events.
+/// - `notification`: Notification used to wake up the stream when an event is pushed.
+pub(crate) struct EventChannel {
+    enabled: AtomicBool,
+    events: Mutex<VecDeque<BleServerEvent>>,
+    capacity: usize,
+    notification: Notification,
+}
+
+/// Stream of the events of a [crate::ble::BleServer], an alternative to setting callbacks for
+/// each event. It is obtained with [crate::ble::BleServer::events] and is meant to be consumed in
+/// a future run with [crate::Microcontroller::block_on]:
+///
+/// ```
+/// let mut events = server.events();
+/// micro.block_on(async {
+///     loop {
+///         match events.next().await {
+///             BleServerEvent::CharacteristicWritten { data, .. } => println!("{:?}", data),
+///             _ => {}
+///         }
+///     }
+/// });
+/// ```
+///
+/// Up to [crate::utils::queue_config::QueueCapacities::ble_server_events] events are kept while
+/// they are not consumed, newer events are dropped.
+pub struct BleServerEvents {
+    channel: Arc<EventChannel>,
+}
+
+impl EventChannel {
+    /// Creates a new disabled EventChannel
+    ///
+    /// # Returns
+    ///
+    /// The new EventChannel
+    pub(crate) fn new() -> Self {
+        EventChannel {
+            enabled: AtomicBool::new(false),
+            events: Mutex::new(VecDeque::new()),
+            capacity: queue_capacities().ble_server_events,
+            notification: Notification::new(),
+        }
+    }
+
+    /// Enables the channel, so pushed events are queued
+    pub(crate) fn enable(&self) {
+        self.enabled.store(true, Ordering::Release);
+    }
+
+    /// Queues an event and wakes up the stream. The event is dropped if the channel is disabled or
+    /// full.
+    ///
+    /// # Arguments
+    ///
+    /// - `event`: The BleServerEvent to queue
+    pub(crate) fn push(&self, event: BleServerEvent) {
+        if !self.enabled.load(Ordering::Acquire) {
+            return;
+        }
+        let mut events = self.events.lock();
+        if events.len() < self.capacity {
+            events.push_back(event);
+        } else {
+            record_dropped_item();
+        }
+        drop(events);
+        self.notification.notifier().notify();
+    }
+
+    /// Takes the oldest queued event
+    fn pop(&self) -> Option<BleServerEvent> {
+        self.events.lock().pop_front()
+    }
+}
+
+impl BleServerEvents {
+    /// Creates a new BleServerEvents that consumes the events of a channel
+    ///
+    /// # Arguments
+    ///
+    /// - `channel`: The EventChannel of the server
+    ///
+    /// # Returns
+    ///
+    /// The new BleServerEvents
+    pub(crate) fn new(channel: Arc<EventChannel>) -> Self {
+        channel.enable();
+        BleServerEvents { channel }
+    }
+
+    /// Waits for the next event of the server
+    ///
+    /// # Returns
+    ///
+    /// The oldest event not consumed yet
+    pub async fn next(&mut self) -> BleServerEvent {
+        loop {
+            if let Some(event) = self.channel.pop() {
+                return event;
+            }
+            self.channel.notification.wait().await;
+        }
+    }
+
+    /// Takes the next event of the server without waiting
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the oldest event not consumed yet, or None if there are none
+    pub fn try_next(&mut self) -> Option<BleServerEvent> {
+        self.channel.pop()
+    }
+}
+
+impl Stream for BleServerEvents {
+    type Item = BleServerEvent;
+
+    /// Polls the next event of the server. The stream never ends.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.channel.pop() {
+                return Poll::Ready(Some(event));
+            }
+            if pin!(self.channel.notification.wait()).poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use esp32_nimble::BLEError;
+use esp_idf_svc::sys::ble_gap_adv_set_data;
+
+use super::{service::MAX_ADV_PAYLOAD_SIZE, BleError, BleId};
+
+const AD_HEADER_SIZE: usize = 2;
+const AD_TYPE_FLAGS: u8 = 0x01;
+const AD_TYPE_COMPLETE_UUID16_LIST: u8 = 0x03;
+const AD_TYPE_COMPLETE_UUID128_LIST: u8 = 0x07;
+const AD_TYPE_COMPLETE_NAME: u8 = 0x09;
+const AD_TYPE_TX_POWER: u8 = 0x0A;
+const AD_TYPE_SERVICE_DATA_UUID16: u8 = 0x16;
+const AD_TYPE_APPEARANCE: u8 = 0x19;
+const AD_TYPE_SERVICE_DATA_UUID128: u8 = 0x21;
+const AD_TYPE_MANUFACTURER_DATA: u8 = 0xFF;
+
+/// Flags of a discoverable device that does not support Bluetooth classic: LE General
+/// Discoverable Mode and BR/EDR Not Supported.
+pub const GENERAL_DISCOVERABLE_FLAGS: u8 = 0x06;
+
+/// Builder of the raw payload of an advertisement, made of AD structures (length, type and data).
+/// It allows advertising fields that the BleServer and BleBeacon do not generate on their own,
+/// such as manufacturer data, the appearance or the TX power. Fields are advertised in the order
+/// they are added.
+///
+/// The payload is validated against the 31 bytes of a legacy advertisement when it is built.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertisementPayload {
+    structures: Vec<(u8, Vec<u8>)>,
+}
+
+impl AdvertisementPayload {
+    /// Creates a new empty AdvertisementPayload
+    ///
+    /// # Returns
+    ///
+    /// The new AdvertisementPayload
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the flags field. It should be the first field of a discoverable device, usually with
+    /// [GENERAL_DISCOVERABLE_FLAGS].
+    ///
+    /// # Arguments
+    ///
+    /// - `flags`: The flags bitmask
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn flags(self, flags: u8) -> Self {
+        self.ad_structure(AD_TYPE_FLAGS, &[flags])
+    }
+
+    /// Adds the complete local name field
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the device
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn name(self, name: &str) -> Self {
+        self.ad_structure(AD_TYPE_COMPLETE_NAME, name.as_bytes())
+    }
+
+    /// Adds a manufacturer specific data field
+    ///
+    /// # Arguments
+    ///
+    /// - `company_id`: The company identifier assigned by the Bluetooth SIG. 0xFFFF is reserved
+    ///   for tests
+    /// - `data`: The data that follows the company id
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn manufacturer_data(self, company_id: u16, data: &[u8]) -> Self {
+        let mut field = company_id.to_le_bytes().to_vec();
+        field.extend_from_slice(data);
+        self.ad_structure(AD_TYPE_MANUFACTURER_DATA, &field)
+    }
+
+    /// Adds the appearance field, that tells scanners the kind of device (for example 0x00C1 is a
+    /// sports watch).
+    ///
+    /// # Arguments
+    ///
+    /// - `appearance`: The appearance value assigned by the Bluetooth SIG
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn appearance(self, appearance: u16) -> Self {
+        self.ad_structure(AD_TYPE_APPEARANCE, &appearance.to_le_bytes())
+    }
+
+    /// Adds the TX power level field, that scanners use together with the RSSI to estimate the
+    /// path loss.
+    ///
+    /// # Arguments
+    ///
+    /// - `tx_power`: The transmitted power in dBm
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn tx_power(self, tx_power: i8) -> Self {
+        self.ad_structure(AD_TYPE_TX_POWER, &[tx_power as u8])
+    }
+
+    /// Adds a field with the complete list of a service uuid
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The BleId of the service
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn service_uuid(self, service_id: &BleId) -> Self {
+        match service_id {
+            BleId::FromUuid16(_) => {
+                self.ad_structure(AD_TYPE_COMPLETE_UUID16_LIST, &uuid_bytes(service_id))
+            }
+            BleId::FromUuid128(_) => {
+                self.ad_structure(AD_TYPE_COMPLETE_UUID128_LIST, &uuid_bytes(service_id))
+            }
+        }
+    }
+
+    /// Adds a service data field
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The BleId of the service
+    /// - `data`: The data of the service
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn service_data(self, service_id: &BleId, data: &[u8]) -> Self {
+        let ad_type = match service_id {
+            BleId::FromUuid16(_) => AD_TYPE_SERVICE_DATA_UUID16,
+            BleId::FromUuid128(_) => AD_TYPE_SERVICE_DATA_UUID128,
+        };
+        let mut field = uuid_bytes(service_id);
+        field.extend_from_slice(data);
+        self.ad_structure(ad_type, &field)
+    }
+
+    /// Adds an arbitrary AD structure, for fields without a dedicated method
+    ///
+    /// # Arguments
+    ///
+    /// - `ad_type`: The AD type assigned by the Bluetooth SIG
+    /// - `data`: The data of the field
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisementPayload itself
+    pub fn ad_structure(mut self, ad_type: u8, data: &[u8]) -> Self {
+        self.structures.push((ad_type, data.to_vec()));
+        self
+    }
+
+    /// Checks whether the payload contains a field of the given AD type
+    ///
+    /// # Arguments
+    ///
+    /// - `ad_type`: The AD type to look for
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is true if the payload contains a field of the type
+    pub fn contains(&self, ad_type: u8) -> bool {
+        self.structures.iter().any(|(t, _)| *t == ad_type)
+    }
+
+    /// Gets the size the payload takes on the advertisement
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes, including the length and type of every field
+    pub fn len(&self) -> usize {
+        self.structures
+            .iter()
+            .map(|(_, data)| data.len() + AD_HEADER_SIZE)
+            .sum()
+    }
+
+    /// Checks whether the payload has no fields
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is true if the payload is empty
+    pub fn is_empty(&self) -> bool {
+        self.structures.is_empty()
+    }
+
+    /// Encodes the payload as it is sent on air
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the bytes of the payload, or a `BleError` if it is not valid
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::ServiceDoesNotFit`: If the payload is longer than 31 bytes
+    /// - `BleError::InvalidParameters`: If a field is longer than 254 bytes
+    pub fn build(&self) -> Result<Vec<u8>, BleError> {
+        if self.len() > MAX_ADV_PAYLOAD_SIZE {
+            return Err(BleError::ServiceDoesNotFit(None));
+        }
+        let mut payload = Vec::with_capacity(self.len());
+        for (ad_type, data) in &self.structures {
+            let len =
+                u8::try_from(data.len() + 1).map_err(|_| BleError::InvalidParameters(None))?;
+            payload.push(len);
+            payload.push(*ad_type);
+            payload.extend_from_slice(data);
+        }
+        Ok(payload)
+    }
+
+    /// Adds the [GENERAL_DISCOVERABLE_FLAGS] at the start of the payload, unless it already has
+    /// a flags field.
+    pub(crate) fn with_default_flags(&self) -> Self {
+        if self.contains(AD_TYPE_FLAGS) {
+            return self.clone();
+        }
+        let mut structures = vec![(AD_TYPE_FLAGS, vec![GENERAL_DISCOVERABLE_FLAGS])];
+        structures.extend(self.structures.iter().cloned());
+        AdvertisementPayload { structures }
+    }
+}
+
+/// Sets an already built payload as the advertising data of the controller, replacing the one set
+/// through esp32-nimble.
+///
+/// # Errors
+///
+/// - `BleError::ServiceDoesNotFit`: If the payload is too big
+/// - `BleError::Code`: On other errors
+pub(crate) fn set_raw_advertising_data(payload: &[u8]) -> Result<(), BleError> {
+    BLEError::convert(unsafe {
+        ble_gap_adv_set_data(payload.as_ptr(), payload.len() as i32) as u32
+    })
+    .map_err(BleError::from)
+}
+
+/// Gets the bytes of the uuid of a BleId, in the little endian order used on air
+fn uuid_bytes(id: &BleId) -> Vec<u8> {
+    match id {
+        BleId::FromUuid16(uuid) => uuid.to_le_bytes().to_vec(),
+        BleId::FromUuid128(uuid) => {
+            let mut bytes = uuid.to_vec();
+            bytes.reverse();
+            bytes
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Nordic UART service, 6E400001-B5A3-F393-E0A9-E50E24DCCA9E
+    const NUS_UUID: [u8; 16] = [
+        0x6E, 0x40, 0x00, 0x01, 0xB5, 0xA3, 0xF3, 0x93, 0xE0, 0xA9, 0xE5, 0x0E, 0x24, 0xDC, 0xCA,
+        0x9E,
+    ];
+
+    #[test]
+    fn advertisement_payload_01_flags_name_and_manufacturer_data() {
+        let payload = AdvertisementPayload::new()
+            .flags(GENERAL_DISCOVERABLE_FLAGS)
+            .name("esp32")
+            .manufacturer_data(0x02E5, &[0x01, 0x02]);
+        assert_eq!(
+            payload.build().unwrap(),
+            vec![
+                0x02, 0x01, 0x06, 0x06, 0x09, b'e', b's', b'p', b'3', b'2', 0x05, 0xFF, 0xE5, 0x02,
+                0x01, 0x02,
+            ]
+        );
+        assert_eq!(payload.len(), 16);
+    }
+
+    #[test]
+    fn advertisement_payload_02_appearance_and_tx_power() {
+        let payload = AdvertisementPayload::new().appearance(0x00C1).tx_power(-4);
+        assert_eq!(
+            payload.build().unwrap(),
+            vec![0x03, 0x19, 0xC1, 0x00, 0x02, 0x0A, 0xFC]
+        );
+    }
+
+    #[test]
+    fn advertisement_payload_03_uuids_are_little_endian() {
+        let payload = AdvertisementPayload::new()
+            .service_uuid(&BleId::FromUuid16(0x180F))
+            .service_data(&BleId::FromUuid16(0xFEAA), &[0x10]);
+        assert_eq!(
+            payload.build().unwrap(),
+            vec![0x03, 0x03, 0x0F, 0x18, 0x04, 0x16, 0xAA, 0xFE, 0x10]
+        );
+
+        let payload = AdvertisementPayload::new().service_uuid(&BleId::FromUuid128(NUS_UUID));
+        assert_eq!(
+            payload.build().unwrap(),
+            vec![
+                0x11, 0x07, 0x9E, 0xCA, 0xDC, 0x24, 0x0E, 0xE5, 0xA9, 0xE0, 0x93, 0xF3, 0xA3, 0xB5,
+                0x01, 0x00, 0x40, 0x6E,
+            ]
+        );
+
+        let payload = AdvertisementPayload::new().service_data(&BleId::FromUuid128(NUS_UUID), &[]);
+        assert_eq!(&payload.build().unwrap()[..3], &[0x11, 0x21, 0x9E]);
+    }
+
+    #[test]
+    fn advertisement_payload_04_legacy_size_limit() {
+        let fits = AdvertisementPayload::new().name(&"a".repeat(29));
+        assert_eq!(fits.len(), 31);
+        assert_eq!(fits.build().unwrap().len(), 31);
+
+        let too_long = AdvertisementPayload::new().name(&"a".repeat(30));
+        assert!(matches!(
+            too_long.build(),
+            Err(BleError::ServiceDoesNotFit(None))
+        ));
+    }
+
+    #[test]
+    fn advertisement_payload_05_default_flags_go_first() {
+        let payload = AdvertisementPayload::new().tx_power(0);
+        assert!(!payload.contains(AD_TYPE_FLAGS));
+        assert_eq!(
+            payload.with_default_flags().build().unwrap(),
+            vec![0x02, 0x01, 0x06, 0x02, 0x0A, 0x00]
+        );
+
+        let payload = payload.flags(0x04);
+        assert_eq!(payload.with_default_flags(), payload);
+        assert!(AdvertisementPayload::new().is_empty());
+    }
+}
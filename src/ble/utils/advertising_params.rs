@@ -0,0 +1,180 @@
+use super::{BleError, OwnAddressType};
+use std::time::Duration;
+
+const MIN_ADVERTISING_INTERVAL: Duration = Duration::from_millis(20);
+const MAX_ADVERTISING_INTERVAL: Duration = Duration::from_millis(10240);
+/// Advertising intervals are set in units of 0.625 ms
+const INTERVAL_UNIT_US: u128 = 625;
+const CHANNEL_37: u8 = 0b001;
+const CHANNEL_38: u8 = 0b010;
+const CHANNEL_39: u8 = 0b100;
+const ALL_CHANNELS: u8 = CHANNEL_37 | CHANNEL_38 | CHANNEL_39;
+
+/// Validated advertising parameters, created with an [AdvertisingParamsBuilder]:
+/// - `interval`: The minimum and maximum advertising interval in 0.625 ms units, or None to use
+///   the default of the controller.
+/// - `channel_map`: The primary advertising channels used, as a bitmap where channel 37 is the
+///   least significant bit.
+/// - `own_address_type`: The type of address to advertise with, or None to keep the current one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdvertisingParams {
+    interval: Option<(u16, u16)>,
+    channel_map: u8,
+    own_address_type: Option<OwnAddressType>,
+}
+
+/// Builds [AdvertisingParams], validating them together at the end. By default the interval of
+/// the controller and every advertising channel are used, and the address type is not changed.
+#[derive(Debug, Clone, Copy)]
+pub struct AdvertisingParamsBuilder {
+    interval: Option<(Duration, Duration)>,
+    channel_map: u8,
+    own_address_type: Option<OwnAddressType>,
+}
+
+impl AdvertisingParams {
+    /// Creates an AdvertisingParamsBuilder with the default parameters
+    ///
+    /// # Returns
+    ///
+    /// A new AdvertisingParamsBuilder
+    pub fn builder() -> AdvertisingParamsBuilder {
+        AdvertisingParamsBuilder::default()
+    }
+
+    /// Gets the advertising interval in the units used by the controller
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the minimum and maximum interval in 0.625 ms units, or None if the default
+    /// of the controller is used
+    pub fn interval_units(&self) -> Option<(u16, u16)> {
+        self.interval
+    }
+
+    /// Gets the primary advertising channels used
+    ///
+    /// # Returns
+    ///
+    /// A bitmap of the channels, where channel 37 is the least significant bit
+    pub fn channel_map(&self) -> u8 {
+        self.channel_map
+    }
+
+    /// Checks whether every primary advertising channel is used
+    ///
+    /// # Returns
+    ///
+    /// True if the channels 37, 38 and 39 are used, False if not
+    pub fn uses_all_channels(&self) -> bool {
+        self.channel_map == ALL_CHANNELS
+    }
+
+    /// Gets the type of address to advertise with
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the OwnAddressType, or None if the current one is kept
+    pub fn own_address_type(&self) -> Option<OwnAddressType> {
+        self.own_address_type
+    }
+}
+
+impl Default for AdvertisingParamsBuilder {
+    fn default() -> Self {
+        AdvertisingParamsBuilder {
+            interval: None,
+            channel_map: ALL_CHANNELS,
+            own_address_type: None,
+        }
+    }
+}
+
+impl AdvertisingParamsBuilder {
+    /// Sets the time between advertisements. The controller picks an interval between both
+    /// values.
+    ///
+    /// # Arguments
+    ///
+    /// - `min_interval`: The minimum advertising interval, between 20 ms and 10.24 s
+    /// - `max_interval`: The maximum advertising interval, between `min_interval` and 10.24 s
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingParamsBuilder with the interval set
+    pub fn interval(mut self, min_interval: Duration, max_interval: Duration) -> Self {
+        self.interval = Some((min_interval, max_interval));
+        self
+    }
+
+    /// Sets the primary advertising channels used. Using less channels reduces the consumption,
+    /// but makes the device harder to discover.
+    ///
+    /// # Arguments
+    ///
+    /// - `ch37`: Whether to advertise on channel 37
+    /// - `ch38`: Whether to advertise on channel 38
+    /// - `ch39`: Whether to advertise on channel 39
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingParamsBuilder with the channels set
+    pub fn channels(mut self, ch37: bool, ch38: bool, ch39: bool) -> Self {
+        self.channel_map = [(ch37, CHANNEL_37), (ch38, CHANNEL_38), (ch39, CHANNEL_39)]
+            .iter()
+            .filter(|(used, _)| *used)
+            .fold(0, |map, (_, channel)| map | channel);
+        self
+    }
+
+    /// Sets the type of address to advertise with, as in [crate::ble::BleServer::set_address_type]
+    ///
+    /// # Arguments
+    ///
+    /// - `address_type`: The OwnAddressType to use
+    ///
+    /// # Returns
+    ///
+    /// The AdvertisingParamsBuilder with the address type set
+    pub fn own_address_type(mut self, address_type: OwnAddressType) -> Self {
+        self.own_address_type = Some(address_type);
+        self
+    }
+
+    /// Validates the parameters and converts the interval to the units used by the controller
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the AdvertisingParams, or a `BleError` if they are not valid
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidAdvertisingInterval`: If an interval is out of the 20 ms to 10.24 s
+    ///   range, or the minimum is greater than the maximum
+    /// - `BleError::InvalidChannelMap`: If no channel is used
+    pub fn build(self) -> Result<AdvertisingParams, BleError> {
+        let interval = match self.interval {
+            Some((min, max)) => {
+                let valid_range = MIN_ADVERTISING_INTERVAL..=MAX_ADVERTISING_INTERVAL;
+                if !valid_range.contains(&min) || !valid_range.contains(&max) || min > max {
+                    return Err(BleError::InvalidAdvertisingInterval);
+                }
+                Some((to_interval_units(min), to_interval_units(max)))
+            }
+            None => None,
+        };
+        if self.channel_map == 0 {
+            return Err(BleError::InvalidChannelMap);
+        }
+        Ok(AdvertisingParams {
+            interval,
+            channel_map: self.channel_map,
+            own_address_type: self.own_address_type,
+        })
+    }
+}
+
+/// Converts a duration in the valid range to 0.625 ms units, rounding to the nearest unit
+fn to_interval_units(interval: Duration) -> u16 {
+    ((interval.as_micros() + INTERVAL_UNIT_US / 2) / INTERVAL_UNIT_US) as u16
+}
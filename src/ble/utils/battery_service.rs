@@ -0,0 +1,66 @@
+use super::{
+    ble_standard_uuids::{StandardCharacteristicId, StandardServiceId},
+    BleId, Characteristic, Service,
+};
+
+const MAX_BATTERY_LEVEL: u8 = 100;
+
+/// Helper for the standard Battery Service (0x180F), which exposes the charge of the battery
+/// as a percentage on the Battery Level characteristic (0x2A19).
+pub struct BatteryService;
+
+impl BatteryService {
+    /// Gets the id of the Battery Service
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the service
+    pub fn id() -> BleId {
+        BleId::from_standard_service(StandardServiceId::Battery)
+    }
+
+    /// Gets the id of the Battery Level characteristic
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the characteristic
+    pub fn characteristic_id() -> BleId {
+        BleId::from_standard_characteristic(StandardCharacteristicId::BatteryLevel)
+    }
+
+    /// Creates a readable and notifiable Battery Level characteristic. Levels above 100 are
+    /// saturated.
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The charge of the battery in percentage
+    ///
+    /// # Returns
+    ///
+    /// The new Characteristic
+    pub fn characteristic(level: u8) -> Characteristic {
+        Characteristic::new(
+            &Self::characteristic_id(),
+            vec![level.min(MAX_BATTERY_LEVEL)],
+        )
+        .readable(true)
+        .notifiable(true)
+    }
+
+    /// Creates the Battery Service with its Battery Level characteristic
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The initial charge of the battery in percentage
+    ///
+    /// # Returns
+    ///
+    /// The Battery Service ready to be set on a BleServer
+    pub fn service(level: u8) -> Service {
+        Service {
+            id: Self::id(),
+            data: vec![],
+            characteristics: vec![Self::characteristic(level)],
+        }
+    }
+}
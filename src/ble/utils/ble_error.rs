@@ -1,46 +1,59 @@
 use esp32_nimble::BLEError;
+use std::{error::Error, fmt};
 
 use crate::{microcontroller_src::peripherals::PeripheralError, timer_driver::TimerDriverError};
 
 const ATTRIBUTE_CANNOT_BE_READ: u32 = 258;
 const ATTRIBUTE_CANNOT_BE_WRITTEN: u32 = 259;
+const ATTRIBUTE_INVALID_OFFSET: u32 = 263;
+const ATTRIBUTE_INVALID_VALUE_LENGTH: u32 = 269;
+const HCI_AUTHENTICATION_FAILURE: u32 = esp_idf_svc::sys::BLE_HS_ERR_HCI_BASE + 0x05;
+const HCI_PIN_OR_KEY_MISSING: u32 = esp_idf_svc::sys::BLE_HS_ERR_HCI_BASE + 0x06;
 
-/// Enums the different errors possible when working with BLE  
+/// Enums the different errors possible when working with BLE. The variants that NimBLE return codes
+/// are mapped to keep the code, or None if the error was detected by the framework.
 #[derive(Debug)]
 pub enum BleError {
     AdvertisementError,
-    AlreadyConnected,
+    AlreadyConnected(Option<u32>),
     CanOnlyBeOneBleDriver,
-    CharacteristicNotFound,
+    CharacteristicNotFound(Option<u32>),
+    CharacteristicNotIndicatable,
     CharacteristicNotNotifiable,
-    CharacteristicNotReadable,
-    CharacteristicNotWritable,
+    CharacteristicNotReadable(Option<u32>),
+    CharacteristicNotWritable(Option<u32>),
     Code(u32, String),
-    ConnectionError,
-    CouldNotConnectToDevice,
+    ConnectionError(Option<u32>),
+    ConnectionParamsRejected,
+    CouldNotConnectToDevice(Option<u32>),
     DeviceNotConnectable,
-    DescriptorNotFound,
-    DescriptorNotReadable,
-    DescriptorNotWritable,
-    DeviceNotFound,
-    Disconnected,
+    DescriptorNotFound(Option<u32>),
+    DescriptorNotReadable(Option<u32>),
+    DescriptorNotWritable(Option<u32>),
+    DeviceNotFound(Option<u32>),
+    Disconnected(Option<u32>),
     IncorrectHandle,
+    InvalidAdvertisingInterval,
+    InvalidChannelMap,
     InvalidPasskey,
-    InvalidParameters,
-    NotFound,
-    NotReadable,
-    NotWritable,
+    InvalidParameters(Option<u32>),
+    MitmWithoutIOCapabilities,
+    NotFound(Option<u32>),
+    NotReadable(Option<u32>),
+    NotWritable(Option<u32>),
+    PairingFailed(Option<u32>),
     PeripheralError(PeripheralError),
     PropertiesError,
-    ServiceDoesNotFit,
-    ServiceNotFound,
+    ServiceDoesNotFit(Option<u32>),
+    ServiceNotFound(Option<u32>),
     ServiceTooBig,
     ServiceUnknown,
     StartingAdvertisementError,
     StartingFailure,
     StoppingFailure,
-    TimeOut,
+    TimeOut(Option<u32>),
     TimerDriverError(TimerDriverError),
+    ValueTooLong(Option<u32>),
 }
 
 impl From<BLEError> for BleError {
@@ -54,15 +67,18 @@ impl From<BLEError> for BleError {
     ///
     /// The new BleError
     fn from(value: BLEError) -> Self {
+        let code = Some(value.code());
         match value.code() {
-            ATTRIBUTE_CANNOT_BE_READ => BleError::NotReadable,
-            ATTRIBUTE_CANNOT_BE_WRITTEN => BleError::NotWritable,
-            esp_idf_svc::sys::BLE_HS_CONN_HANDLE_NONE => BleError::NotFound,
-            esp_idf_svc::sys::BLE_HS_EDONE => BleError::AlreadyConnected,
-            esp_idf_svc::sys::BLE_HS_EINVAL => BleError::InvalidParameters,
-            esp_idf_svc::sys::BLE_HS_EMSGSIZE => BleError::ServiceDoesNotFit,
-            esp_idf_svc::sys::BLE_HS_ENOTCONN => BleError::DeviceNotFound,
-            esp_idf_svc::sys::BLE_HS_ETIMEOUT => BleError::TimeOut,
+            ATTRIBUTE_CANNOT_BE_READ => BleError::NotReadable(code),
+            ATTRIBUTE_CANNOT_BE_WRITTEN => BleError::NotWritable(code),
+            ATTRIBUTE_INVALID_OFFSET => BleError::InvalidParameters(code),
+            ATTRIBUTE_INVALID_VALUE_LENGTH => BleError::ValueTooLong(code),
+            esp_idf_svc::sys::BLE_HS_CONN_HANDLE_NONE => BleError::NotFound(code),
+            esp_idf_svc::sys::BLE_HS_EDONE => BleError::AlreadyConnected(code),
+            esp_idf_svc::sys::BLE_HS_EINVAL => BleError::InvalidParameters(code),
+            esp_idf_svc::sys::BLE_HS_EMSGSIZE => BleError::ServiceDoesNotFit(code),
+            esp_idf_svc::sys::BLE_HS_ENOTCONN => BleError::DeviceNotFound(code),
+            esp_idf_svc::sys::BLE_HS_ETIMEOUT => BleError::TimeOut(code),
             _ => BleError::Code(value.code(), value.to_string()),
         }
     }
@@ -133,6 +149,31 @@ impl BleError {
         Self::from(err).connection_context()
     }
 
+    /// Creates a more specif BleError from a BLEError, taking into acount its in a pairing context.
+    /// Errors of the Security Manager of either device, and encryption failures, are taken as a
+    /// failed pairing.
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: The BLEError to transform
+    ///
+    /// # Returns
+    ///
+    /// The new BleError
+    pub(crate) fn from_pairing_context(err: BLEError) -> Self {
+        let code = err.code();
+        let security_manager_error = (esp_idf_svc::sys::BLE_HS_ERR_SM_US_BASE
+            ..esp_idf_svc::sys::BLE_HS_ERR_HW_BASE)
+            .contains(&code);
+        if security_manager_error
+            || code == HCI_AUTHENTICATION_FAILURE
+            || code == HCI_PIN_OR_KEY_MISSING
+        {
+            return BleError::PairingFailed(Some(code));
+        }
+        Self::from(err).connection_context()
+    }
+
     /// Creates a more specif BleError from a BLEError, taking into acount its in a descriptors context
     ///
     /// # Arguments
@@ -146,52 +187,182 @@ impl BleError {
         Self::from(err).descriptor_context()
     }
 
-    /// Makes a BleError more specific in the context of services
-    fn service_context(self) -> Self {
+    /// Gets the NimBLE return code the error comes from. Errors detected by the framework have no
+    /// code.
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the code, or None if the error has no code
+    pub fn code(&self) -> Option<u32> {
+        match self {
+            BleError::Code(code, _) => Some(*code),
+            BleError::NotReadable(code)
+            | BleError::NotWritable(code)
+            | BleError::InvalidParameters(code)
+            | BleError::ValueTooLong(code)
+            | BleError::NotFound(code)
+            | BleError::AlreadyConnected(code)
+            | BleError::ServiceDoesNotFit(code)
+            | BleError::DeviceNotFound(code)
+            | BleError::TimeOut(code)
+            | BleError::Disconnected(code)
+            | BleError::ServiceNotFound(code)
+            | BleError::CharacteristicNotFound(code)
+            | BleError::CharacteristicNotReadable(code)
+            | BleError::CharacteristicNotWritable(code)
+            | BleError::DescriptorNotFound(code)
+            | BleError::DescriptorNotReadable(code)
+            | BleError::DescriptorNotWritable(code)
+            | BleError::CouldNotConnectToDevice(code)
+            | BleError::ConnectionError(code)
+            | BleError::PairingFailed(code) => *code,
+            _ => None,
+        }
+    }
+
+    /// Makes a BleError more specific in the context of services, for example to propagate the
+    /// error of an operation on a service
+    ///
+    /// # Returns
+    ///
+    /// The more specific BleError, or the same one if there is none
+    pub fn service_context(self) -> Self {
         match self {
-            BleError::DeviceNotFound => BleError::Disconnected,
-            BleError::NotFound => BleError::ServiceNotFound,
+            BleError::DeviceNotFound(code) => BleError::Disconnected(code),
+            BleError::NotFound(code) => BleError::ServiceNotFound(code),
             _ => self,
         }
     }
 
-    /// Makes a BleError more specific in the context of characteristic
-    fn characteristic_context(self) -> Self {
+    /// Makes a BleError more specific in the context of characteristics, for example to propagate
+    /// the error of an operation on a characteristic
+    ///
+    /// # Returns
+    ///
+    /// The more specific BleError, or the same one if there is none
+    pub fn characteristic_context(self) -> Self {
         match self {
-            BleError::DeviceNotFound => BleError::Disconnected,
-            BleError::NotFound => BleError::CharacteristicNotFound,
-            BleError::NotReadable => BleError::CharacteristicNotReadable,
-            BleError::NotWritable => BleError::CharacteristicNotWritable,
+            BleError::DeviceNotFound(code) => BleError::Disconnected(code),
+            BleError::NotFound(code) => BleError::CharacteristicNotFound(code),
+            BleError::NotReadable(code) => BleError::CharacteristicNotReadable(code),
+            BleError::NotWritable(code) => BleError::CharacteristicNotWritable(code),
             _ => self,
         }
     }
 
-    /// Makes a BleError more specific in the context of descriptors
-    fn descriptor_context(self) -> Self {
+    /// Makes a BleError more specific in the context of descriptors, for example to propagate the
+    /// error of an operation on a descriptor
+    ///
+    /// # Returns
+    ///
+    /// The more specific BleError, or the same one if there is none
+    pub fn descriptor_context(self) -> Self {
         match self {
-            BleError::DeviceNotFound => BleError::Disconnected,
-            BleError::NotFound => BleError::DescriptorNotFound,
-            BleError::NotReadable => BleError::DescriptorNotReadable,
-            BleError::NotWritable => BleError::DescriptorNotWritable,
+            BleError::DeviceNotFound(code) => BleError::Disconnected(code),
+            BleError::NotFound(code) => BleError::DescriptorNotFound(code),
+            BleError::NotReadable(code) => BleError::DescriptorNotReadable(code),
+            BleError::NotWritable(code) => BleError::DescriptorNotWritable(code),
             _ => self,
         }
     }
 
-    /// Makes a BleError more specific in the context of descriptors
-    fn connection_context(self) -> Self {
+    /// Makes a BleError more specific in the context of connections, for example to propagate the
+    /// error of connecting to a device
+    ///
+    /// # Returns
+    ///
+    /// The more specific BleError, or the same one if there is none
+    pub fn connection_context(self) -> Self {
         match self {
-            BleError::TimeOut => BleError::CouldNotConnectToDevice,
-            BleError::DeviceNotFound => BleError::Disconnected,
+            BleError::TimeOut(code) => BleError::CouldNotConnectToDevice(code),
+            BleError::DeviceNotFound(code) => BleError::Disconnected(code),
             _ => self,
         }
     }
 
-    /// Makes a BleError more specific in the context of descriptors
-    fn connection_params_context(self) -> Self {
+    /// Makes a BleError more specific in the context of connection parameters, for example to
+    /// propagate the error of updating them
+    ///
+    /// # Returns
+    ///
+    /// The more specific BleError, or the same one if there is none
+    pub fn connection_params_context(self) -> Self {
         match self {
-            BleError::DeviceNotFound => BleError::Disconnected,
-            BleError::TimeOut => BleError::ConnectionError,
+            BleError::DeviceNotFound(code) => BleError::Disconnected(code),
+            BleError::TimeOut(code) => BleError::ConnectionError(code),
             _ => self,
         }
     }
 }
+
+impl fmt::Display for BleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BleError::AdvertisementError => write!(f, "the advertisement could not be set"),
+            BleError::AlreadyConnected(_) => write!(f, "already connected"),
+            BleError::CanOnlyBeOneBleDriver => write!(f, "there can only be one BLE driver"),
+            BleError::CharacteristicNotFound(_) => write!(f, "characteristic not found"),
+            BleError::CharacteristicNotIndicatable => {
+                write!(f, "the characteristic does not allow indications")
+            }
+            BleError::CharacteristicNotNotifiable => {
+                write!(f, "the characteristic does not allow notifications")
+            }
+            BleError::CharacteristicNotReadable(_) => {
+                write!(f, "the characteristic is not readable")
+            }
+            BleError::CharacteristicNotWritable(_) => {
+                write!(f, "the characteristic is not writable")
+            }
+            BleError::Code(code, message) => write!(f, "NimBLE error {code}: {message}"),
+            BleError::ConnectionError(_) => write!(f, "the connection could not be updated"),
+            BleError::ConnectionParamsRejected => {
+                write!(f, "the central rejected the connection parameters")
+            }
+            BleError::CouldNotConnectToDevice(_) => write!(f, "could not connect to the device"),
+            BleError::DeviceNotConnectable => write!(f, "the device does not accept connections"),
+            BleError::DescriptorNotFound(_) => write!(f, "descriptor not found"),
+            BleError::DescriptorNotReadable(_) => write!(f, "the descriptor is not readable"),
+            BleError::DescriptorNotWritable(_) => write!(f, "the descriptor is not writable"),
+            BleError::DeviceNotFound(_) => write!(f, "device not found"),
+            BleError::Disconnected(_) => write!(f, "not connected"),
+            BleError::IncorrectHandle => write!(f, "incorrect attribute handle"),
+            BleError::InvalidAdvertisingInterval => write!(f, "invalid advertising interval"),
+            BleError::InvalidChannelMap => write!(f, "invalid channel map"),
+            BleError::InvalidPasskey => write!(f, "the passkey has more than 6 digits"),
+            BleError::InvalidParameters(_) => write!(f, "invalid parameters"),
+            BleError::MitmWithoutIOCapabilities => write!(
+                f,
+                "man in the middle protection requires input or output capabilities"
+            ),
+            BleError::NotFound(_) => write!(f, "not found"),
+            BleError::NotReadable(_) => write!(f, "the attribute is not readable"),
+            BleError::NotWritable(_) => write!(f, "the attribute is not writable"),
+            BleError::PairingFailed(_) => write!(f, "the pairing failed"),
+            BleError::PeripheralError(_) => write!(f, "peripheral error"),
+            BleError::PropertiesError => write!(f, "invalid characteristic properties"),
+            BleError::ServiceDoesNotFit(_) => {
+                write!(f, "the service does not fit in the advertisement")
+            }
+            BleError::ServiceNotFound(_) => write!(f, "service not found"),
+            BleError::ServiceTooBig => write!(f, "the service is too big"),
+            BleError::ServiceUnknown => write!(f, "unknown service"),
+            BleError::StartingAdvertisementError => write!(f, "the advertisement could not start"),
+            BleError::StartingFailure => write!(f, "could not start"),
+            BleError::StoppingFailure => write!(f, "could not stop"),
+            BleError::TimeOut(_) => write!(f, "timed out"),
+            BleError::TimerDriverError(_) => write!(f, "timer driver error"),
+            BleError::ValueTooLong(_) => write!(f, "the value is too long"),
+        }
+    }
+}
+
+impl Error for BleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BleError::PeripheralError(err) => Some(err),
+            BleError::TimerDriverError(err) => Some(err),
+            _ => None,
+        }
+    }
+}
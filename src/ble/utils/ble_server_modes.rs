@@ -1,4 +1,7 @@
-use esp32_nimble::enums::{ConnMode, DiscMode};
+use esp32_nimble::{
+    enums::{AdvFilterPolicy, ConnMode, DiscMode, ScanFilterPolicy},
+    BLEAddress,
+};
 
 /// Enums the posible discoverable modes:
 /// * `Non-Discoverable Mode`: The device does not advertise itself. Other devices will connect only if they know the specific address.
@@ -32,10 +35,14 @@ impl DiscoverableMode {
 /// Enums the posible connection modes:
 /// * `NonConnectable`: The device does not allow connections.
 /// * `UndirectedConnectable`: The divice allows connections from any device.
-#[derive(Debug)]
+/// * `DirectedConnectable`: The device only allows the connection of the device with the given
+///   address. The advertisement can use a high or low duty cycle, and stops after a timeout if
+///   the device did not connect.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ConnectionMode {
     NonConnectable,
     UndirectedConnectable,
+    DirectedConnectable(BLEAddress),
 }
 
 impl ConnectionMode {
@@ -48,6 +55,72 @@ impl ConnectionMode {
         match self {
             ConnectionMode::NonConnectable => ConnMode::Non,
             ConnectionMode::UndirectedConnectable => ConnMode::Und,
+            ConnectionMode::DirectedConnectable(_) => ConnMode::Dir,
+        }
+    }
+
+    /// Gets the address of the only device allowed to connect
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the BLEAddress of the target device, or None if the mode is not directed
+    pub fn target(&self) -> Option<BLEAddress> {
+        match self {
+            ConnectionMode::DirectedConnectable(address) => Some(*address),
+            _ => None,
+        }
+    }
+}
+
+/// Enums the posible advertising filter policies, which decide the requests that only the devices
+/// on the whitelist can make:
+/// * `AllowAll`: The whitelist is not used, any device can scan and connect.
+/// * `ScanRequests`: Only whitelisted devices can make scan requests, any device can connect.
+/// * `Connections`: Only whitelisted devices can connect, any device can make scan requests.
+/// * `ScanRequestsAndConnections`: Only whitelisted devices can make scan requests and connect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdvertisingFilterPolicy {
+    AllowAll,
+    ScanRequests,
+    Connections,
+    ScanRequestsAndConnections,
+}
+
+impl AdvertisingFilterPolicy {
+    /// Gets the AdvFilterPolicy from an AdvertisingFilterPolicy
+    ///
+    /// # Returns
+    ///
+    /// The corresponding AdvFilterPolicy
+    pub fn get_code(&self) -> AdvFilterPolicy {
+        match self {
+            AdvertisingFilterPolicy::AllowAll => AdvFilterPolicy::None,
+            AdvertisingFilterPolicy::ScanRequests => AdvFilterPolicy::Scan,
+            AdvertisingFilterPolicy::Connections => AdvFilterPolicy::Connect,
+            AdvertisingFilterPolicy::ScanRequestsAndConnections => AdvFilterPolicy::Both,
+        }
+    }
+}
+
+/// Enums the posible scan filter policies:
+/// * `AllowAll`: The advertisements of every device are processed.
+/// * `WhitelistOnly`: Only the advertisements of the devices on the whitelist are processed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScanPolicy {
+    AllowAll,
+    WhitelistOnly,
+}
+
+impl ScanPolicy {
+    /// Gets the ScanFilterPolicy from a ScanPolicy
+    ///
+    /// # Returns
+    ///
+    /// The corresponding ScanFilterPolicy
+    pub fn get_code(&self) -> ScanFilterPolicy {
+        match self {
+            ScanPolicy::AllowAll => ScanFilterPolicy::NoWl,
+            ScanPolicy::WhitelistOnly => ScanFilterPolicy::UseWl,
         }
     }
 }
@@ -1,4 +1,4 @@
-use esp32_nimble::{BLEAddress, BLEConnDesc, BLEError};
+use esp32_nimble::{BLEAddress, BLEConnDesc, BLEError, NotifyTxStatus};
 
 /// Contains information about the new client connected that can be user on
 /// connection or disconnection callbacks.
@@ -33,6 +33,66 @@ pub struct ConnectionInformation {
     pub disconnection_result: Option<u32>,
 }
 
+/// The Client Characteristic Configuration a client wrote for a characteristic of the server:
+/// - `info`: The ConnectionInformation of the client.
+/// - `notify`: Whether the client enabled the notifications.
+/// - `indicate`: Whether the client enabled the indications.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientConfiguration {
+    pub info: ConnectionInformation,
+    pub notify: bool,
+    pub indicate: bool,
+}
+
+/// Enums the outcomes of an indication sent to a client:
+/// - `Acknowledged`: The client confirmed the reception.
+/// - `TimedOut`: The client did not confirm the reception in time (30 seconds), after which no
+///   more indications can be sent to it on this connection.
+/// - `NotSubscribed`: The client did not enable the indications of the characteristic.
+/// - `Failed`: The indication could not be sent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IndicationResult {
+    Acknowledged,
+    TimedOut,
+    NotSubscribed,
+    Failed,
+}
+
+impl IndicationResult {
+    /// Creates an IndicationResult from the status of a sent notification or indication
+    ///
+    /// # Arguments
+    ///
+    /// - `status`: The NotifyTxStatus reported by the BLE stack
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the IndicationResult, or None if the status belongs to a notification
+    pub(crate) fn from_notify_tx_status(status: NotifyTxStatus) -> Option<Self> {
+        match status {
+            NotifyTxStatus::SuccessNotify | NotifyTxStatus::ErrorNotifyDisabled => None,
+            NotifyTxStatus::SuccessIndicate => Some(IndicationResult::Acknowledged),
+            NotifyTxStatus::ErrorIndicateTimeout => Some(IndicationResult::TimedOut),
+            NotifyTxStatus::ErrorIndicateDisabled | NotifyTxStatus::ErrorNoClient => {
+                Some(IndicationResult::NotSubscribed)
+            }
+            _ => Some(IndicationResult::Failed),
+        }
+    }
+}
+
+impl ClientConfiguration {
+    /// Gets the value of the descriptor as the client wrote it, with the bit 0 set if the
+    /// notifications are enabled and the bit 1 set if the indications are enabled
+    ///
+    /// # Returns
+    ///
+    /// The 2 bytes of the descriptor, in little endian
+    pub fn value(&self) -> [u8; 2] {
+        [self.notify as u8 | (self.indicate as u8) << 1, 0]
+    }
+}
+
 impl ConnectionInformation {
     /// Creates a ConnectionInformation from a BLEConnDesc
     ///
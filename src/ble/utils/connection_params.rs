@@ -0,0 +1,90 @@
+use super::BleError;
+use esp32_nimble::BLEError;
+use esp_idf_svc::sys::{ble_l2cap_sig_update, ble_l2cap_sig_update_params, BLE_HS_EREJECT};
+use std::{
+    ffi::{c_int, c_void},
+    sync::{Arc, Condvar, Mutex},
+};
+
+/// State of a connection parameter update request shared with the BLE task, with the status the
+/// central answered with, None while waiting for the answer
+struct UpdateRequest {
+    status: Mutex<Option<c_int>>,
+    answered: Condvar,
+}
+
+/// Asks the central of a connection to use other connection parameters, with an L2CAP Connection
+/// Parameter Update request. Blocks until the central answers, or the request times out after 30
+/// seconds. An accepted request only means the central will start a connection update with the
+/// requested parameters, which may take a few connection events to apply.
+///
+/// # Arguments
+///
+/// - `conn_handle`: The connection with the central
+/// - `min_interval`: The minimum connection interval, in 1.25ms units
+/// - `max_interval`: The maximum connection interval, in 1.25ms units
+/// - `latency`: The number of connection events the peripheral can skip
+/// - `timeout`: The supervision timeout, in 10ms units
+///
+/// # Returns
+///
+/// A `Result` with Ok if the central accepted the parameters, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::ConnectionParamsRejected`: If the central rejected the parameters
+/// - `BleError::Disconnected`: If the connection dropped
+/// - `BleError::InvalidParameters`: If the parameters are out of range, or this device is the
+///   central of the connection
+/// - `BleError::TimeOut`: If the central did not answer
+/// - `BleError::Code`: on other errors
+pub(crate) fn request_connection_params(
+    conn_handle: u16,
+    min_interval: u16,
+    max_interval: u16,
+    latency: u16,
+    timeout: u16,
+) -> Result<(), BleError> {
+    let mut params = ble_l2cap_sig_update_params {
+        itvl_min: min_interval,
+        itvl_max: max_interval,
+        slave_latency: latency,
+        timeout_multiplier: timeout,
+    };
+    let request = Arc::new(UpdateRequest {
+        status: Mutex::new(None),
+        answered: Condvar::new(),
+    });
+    let arg = Arc::into_raw(request.clone()) as *mut c_void;
+    let res = unsafe {
+        ble_l2cap_sig_update(conn_handle, &mut params, Some(update_request_callback), arg)
+    };
+    let status = if res != 0 {
+        unsafe { drop(Arc::from_raw(arg as *const UpdateRequest)) };
+        res
+    } else {
+        let status = request
+            .answered
+            .wait_while(
+                request.status.lock().unwrap_or_else(|err| err.into_inner()),
+                |status| status.is_none(),
+            )
+            .unwrap_or_else(|err| err.into_inner());
+        status.unwrap_or_default()
+    };
+    if status == BLE_HS_EREJECT as c_int {
+        return Err(BleError::ConnectionParamsRejected);
+    }
+    BLEError::convert(status as u32).map_err(|err| match BleError::from(err) {
+        BleError::DeviceNotFound(code) => BleError::Disconnected(code),
+        err => err,
+    })
+}
+
+/// Callback executed by NimBLE when the central answers the request, or when it times out. The
+/// request is released.
+unsafe extern "C" fn update_request_callback(_conn_handle: u16, status: c_int, arg: *mut c_void) {
+    let request = Arc::from_raw(arg as *const UpdateRequest);
+    *request.status.lock().unwrap_or_else(|err| err.into_inner()) = Some(status);
+    request.answered.notify_all();
+}
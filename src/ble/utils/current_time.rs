@@ -0,0 +1,152 @@
+use super::{
+    ble_standard_uuids::{StandardCharacteristicId, StandardServiceId},
+    BleError, BleId, Characteristic, Service,
+};
+use crate::sensors::DateTime;
+
+const CURRENT_TIME_SIZE: usize = 10;
+const BASE_YEAR: u16 = 2000;
+const MAX_YEAR_OFFSET: u16 = 99;
+const SUNDAY_DS3231: u8 = 1;
+const SUNDAY_CTS: u8 = 7;
+
+/// Enums the reasons informed when the current time changes:
+/// - `ManualTimeUpdate`: The time was set by the user.
+/// - `ExternalReferenceTimeUpdate`: The time was synchronized with an external source (SNTP, RTC, etc).
+/// - `ChangeOfTimeZone`: The time zone was changed.
+/// - `ChangeOfDst`: The daylight saving time was changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdjustReason {
+    ManualTimeUpdate,
+    ExternalReferenceTimeUpdate,
+    ChangeOfTimeZone,
+    ChangeOfDst,
+}
+
+/// Helper for the standard Current Time Service (0x1805). It handles the encoding between the
+/// framework `DateTime` and the Current Time characteristic (0x2A2B) format.
+pub struct CurrentTimeService;
+
+impl AdjustReason {
+    /// Gets the flag of the adjust reason as specified by the standard
+    fn get_code(&self) -> u8 {
+        match self {
+            AdjustReason::ManualTimeUpdate => 0x01,
+            AdjustReason::ExternalReferenceTimeUpdate => 0x02,
+            AdjustReason::ChangeOfTimeZone => 0x04,
+            AdjustReason::ChangeOfDst => 0x08,
+        }
+    }
+}
+
+impl CurrentTimeService {
+    /// Gets the id of the Current Time Service
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the service
+    pub fn id() -> BleId {
+        BleId::from_standard_service(StandardServiceId::CurrentTime)
+    }
+
+    /// Gets the id of the Current Time characteristic
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the characteristic
+    pub fn characteristic_id() -> BleId {
+        BleId::from_standard_characteristic(StandardCharacteristicId::CurrentTime)
+    }
+
+    /// Creates the Current Time Service with a readable, writable and notifiable Current Time characteristic.
+    ///
+    /// # Arguments
+    ///
+    /// - `date_time`: The initial time of the characteristic
+    ///
+    /// # Returns
+    ///
+    /// The Current Time Service ready to be set on a BleServer
+    pub fn service(date_time: &DateTime) -> Service {
+        let characteristic = Characteristic::new(
+            &Self::characteristic_id(),
+            Self::to_bytes(date_time, AdjustReason::ManualTimeUpdate),
+        )
+        .readable(true)
+        .writable(true)
+        .notifiable(true);
+
+        Service {
+            id: Self::id(),
+            data: vec![],
+            characteristics: vec![characteristic],
+        }
+    }
+
+    /// Encodes a DateTime in the Current Time characteristic format. The fractions of a second are
+    /// always informed as 0.
+    ///
+    /// # Arguments
+    ///
+    /// - `date_time`: The time to encode
+    /// - `reason`: The reason why the time is being informed
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<u8>` with the encoded time
+    pub fn to_bytes(date_time: &DateTime, reason: AdjustReason) -> Vec<u8> {
+        let week_day = match date_time.week_day {
+            SUNDAY_DS3231 => SUNDAY_CTS,
+            2..=7 => date_time.week_day - 1,
+            _ => 0,
+        };
+        let mut data = (BASE_YEAR + date_time.year as u16).to_le_bytes().to_vec();
+        data.extend_from_slice(&[
+            date_time.month,
+            date_time.date,
+            date_time.hour,
+            date_time.minute,
+            date_time.second,
+            week_day,
+            0,
+            reason.get_code(),
+        ]);
+        data
+    }
+
+    /// Decodes the data of a Current Time characteristic into a DateTime
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes of the characteristic
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the decoded `DateTime`, or a `BleError` if the data is not valid
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the data has an invalid length or the year is outside 2000-2099
+    pub fn from_bytes(data: &[u8]) -> Result<DateTime, BleError> {
+        if data.len() < CURRENT_TIME_SIZE {
+            return Err(BleError::InvalidParameters(None));
+        }
+        let year = u16::from_le_bytes([data[0], data[1]]);
+        if year < BASE_YEAR || year > BASE_YEAR + MAX_YEAR_OFFSET {
+            return Err(BleError::InvalidParameters(None));
+        }
+        let week_day = match data[7] {
+            SUNDAY_CTS => SUNDAY_DS3231,
+            day => day + 1,
+        };
+        Ok(DateTime {
+            second: data[6],
+            minute: data[5],
+            hour: data[4],
+            week_day,
+            date: data[3],
+            month: data[2],
+            year: (year - BASE_YEAR) as u8,
+        })
+    }
+}
@@ -0,0 +1,141 @@
+use super::{
+    ble_standard_uuids::{StandardCharacteristicId, StandardServiceId},
+    BleId, Characteristic, Service,
+};
+
+/// Builder of the standard Device Information Service (0x180A). Each string set on the builder is
+/// exposed on its standard read only characteristic, and the ones not set are left out.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceInformationService {
+    fields: Vec<(StandardCharacteristicId, String)>,
+}
+
+impl DeviceInformationService {
+    /// Creates a new DeviceInformationService without characteristics
+    ///
+    /// # Returns
+    ///
+    /// The new DeviceInformationService
+    pub fn new() -> Self {
+        DeviceInformationService { fields: vec![] }
+    }
+
+    /// Gets the id of the Device Information Service
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the service
+    pub fn id() -> BleId {
+        BleId::from_standard_service(StandardServiceId::DeviceInformation)
+    }
+
+    /// Adds or replaces a string characteristic on the service
+    fn set_field(mut self, id: StandardCharacteristicId, value: &str) -> Self {
+        self.fields.retain(|(field_id, _)| *field_id != id);
+        self.fields.push((id, value.to_string()));
+        self
+    }
+
+    /// Adds the Manufacturer Name String characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the manufacturer of the device
+    ///
+    /// # Returns
+    ///
+    /// The DeviceInformationService itself
+    pub fn manufacturer_name(self, name: &str) -> Self {
+        self.set_field(StandardCharacteristicId::ManufacturerNameString, name)
+    }
+
+    /// Adds the Model Number String characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `model`: The model number assigned by the manufacturer
+    ///
+    /// # Returns
+    ///
+    /// The DeviceInformationService itself
+    pub fn model_number(self, model: &str) -> Self {
+        self.set_field(StandardCharacteristicId::ModelNumberString, model)
+    }
+
+    /// Adds the Serial Number String characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `serial`: The serial number of this particular device
+    ///
+    /// # Returns
+    ///
+    /// The DeviceInformationService itself
+    pub fn serial_number(self, serial: &str) -> Self {
+        self.set_field(StandardCharacteristicId::SerialNumberString, serial)
+    }
+
+    /// Adds the Hardware Revision String characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `revision`: The revision of the hardware of the device
+    ///
+    /// # Returns
+    ///
+    /// The DeviceInformationService itself
+    pub fn hardware_revision(self, revision: &str) -> Self {
+        self.set_field(StandardCharacteristicId::HardwareRevisionString, revision)
+    }
+
+    /// Adds the Firmware Revision String characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `revision`: The revision of the firmware of the device
+    ///
+    /// # Returns
+    ///
+    /// The DeviceInformationService itself
+    pub fn firmware_revision(self, revision: &str) -> Self {
+        self.set_field(StandardCharacteristicId::FirmwareRevisionString, revision)
+    }
+
+    /// Adds the Software Revision String characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `revision`: The revision of the software of the device
+    ///
+    /// # Returns
+    ///
+    /// The DeviceInformationService itself
+    pub fn software_revision(self, revision: &str) -> Self {
+        self.set_field(StandardCharacteristicId::SoftwareRevisionString, revision)
+    }
+
+    /// Creates the Service with one readable characteristic per string set
+    ///
+    /// # Returns
+    ///
+    /// The Device Information Service ready to be set on a BleServer
+    pub fn build(&self) -> Service {
+        let characteristics = self
+            .fields
+            .iter()
+            .map(|(id, value)| {
+                Characteristic::new(
+                    &BleId::from_standard_characteristic(*id),
+                    value.as_bytes().to_vec(),
+                )
+                .readable(true)
+            })
+            .collect();
+
+        Service {
+            id: Self::id(),
+            data: vec![],
+            characteristics,
+        }
+    }
+}
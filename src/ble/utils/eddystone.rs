@@ -0,0 +1,300 @@
+use super::{BleError, BleId};
+use std::time::Instant;
+
+const EDDYSTONE_SERVICE_UUID: u16 = 0xFEAA;
+const UID_FRAME_TYPE: u8 = 0x00;
+const URL_FRAME_TYPE: u8 = 0x10;
+const TLM_FRAME_TYPE: u8 = 0x20;
+const TLM_VERSION: u8 = 0x00;
+const MAX_ENCODED_URL_SIZE: usize = 17;
+/// Temperature sent when the beacon does not measure it, as defined by the specification
+const TLM_NO_TEMPERATURE: [u8; 2] = [0x80, 0x00];
+
+/// Url schemes that are sent as a single byte
+const URL_SCHEMES: [&str; 4] = ["http://www.", "https://www.", "http://", "https://"];
+
+/// Url expansions that are sent as a single byte, their index being the code. The ones ending
+/// with a slash must be checked first.
+const URL_EXPANSIONS: [&str; 14] = [
+    ".com/", ".org/", ".edu/", ".net/", ".info/", ".biz/", ".gov/", ".com", ".org", ".edu", ".net",
+    ".info", ".biz", ".gov",
+];
+
+/// Enums the Eddystone frames a [crate::ble::BleBeacon] can advertise:
+/// - `Uid`: Identifies the beacon with a 10 bytes namespace and a 6 bytes instance.
+/// - `Url`: Advertises an url, compressed so it fits in 17 bytes.
+/// - `Tlm`: Advertises the telemetry of the beacon: battery voltage, temperature, amount of
+///   frames advertised and time since it started.
+///
+/// The `tx_power` is the RSSI in dBm measured at 0 meters from the beacon.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EddystoneFrame {
+    Uid {
+        namespace: [u8; 10],
+        instance: [u8; 6],
+        tx_power: i8,
+    },
+    Url {
+        url: String,
+        tx_power: i8,
+    },
+    Tlm,
+}
+
+/// Telemetry advertised on the TLM frames of a beacon
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct EddystoneTelemetry {
+    battery_mv: u16,
+    temperature: Option<f32>,
+    started: Instant,
+    frames_advertised: u32,
+}
+
+impl EddystoneFrame {
+    /// Gets the BleId of the Eddystone service, under which every frame is advertised
+    ///
+    /// # Returns
+    ///
+    /// The BleId of the service
+    pub fn service_id() -> BleId {
+        BleId::FromUuid16(EDDYSTONE_SERVICE_UUID)
+    }
+
+    /// Encodes the frame as the service data of the Eddystone service
+    ///
+    /// # Arguments
+    ///
+    /// - `telemetry`: The telemetry used on TLM frames
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the service data, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the url does not start with a valid scheme or has non printable characters
+    /// - `BleError::ServiceTooBig`: If the compressed url is longer than 17 bytes
+    pub(crate) fn encode(&self, telemetry: &EddystoneTelemetry) -> Result<Vec<u8>, BleError> {
+        let mut data = vec![];
+        match self {
+            EddystoneFrame::Uid {
+                namespace,
+                instance,
+                tx_power,
+            } => {
+                data.push(UID_FRAME_TYPE);
+                data.push(*tx_power as u8);
+                data.extend_from_slice(namespace);
+                data.extend_from_slice(instance);
+                data.extend_from_slice(&[0, 0]);
+            }
+            EddystoneFrame::Url { url, tx_power } => {
+                data.push(URL_FRAME_TYPE);
+                data.push(*tx_power as u8);
+                data.extend(compress_url(url)?);
+            }
+            EddystoneFrame::Tlm => {
+                data.push(TLM_FRAME_TYPE);
+                data.push(TLM_VERSION);
+                data.extend(telemetry.encode());
+            }
+        }
+        Ok(data)
+    }
+}
+
+impl EddystoneTelemetry {
+    /// Creates a new EddystoneTelemetry without battery nor temperature, starting its counters
+    ///
+    /// # Returns
+    ///
+    /// The new EddystoneTelemetry
+    pub(crate) fn new() -> Self {
+        EddystoneTelemetry {
+            battery_mv: 0,
+            temperature: None,
+            started: Instant::now(),
+            frames_advertised: 0,
+        }
+    }
+
+    /// Sets the battery voltage and temperature advertised
+    ///
+    /// # Arguments
+    ///
+    /// - `battery_mv`: The battery voltage in millivolts, 0 if the beacon is not battery powered
+    /// - `temperature`: The temperature in degrees Celsius, None if the beacon does not measure it
+    pub(crate) fn set(&mut self, battery_mv: u16, temperature: Option<f32>) {
+        self.battery_mv = battery_mv;
+        self.temperature = temperature;
+    }
+
+    /// Counts a new frame put on air
+    pub(crate) fn count_frame(&mut self) {
+        self.frames_advertised = self.frames_advertised.wrapping_add(1);
+    }
+
+    /// Encodes the telemetry: battery voltage, temperature in 8.8 fixed point, amount of frames
+    /// advertised and time since the start in tenths of a second. Every value is big endian.
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(12);
+        data.extend_from_slice(&self.battery_mv.to_be_bytes());
+        match self.temperature {
+            Some(temperature) => {
+                let fixed_point = (temperature * 256.0).clamp(i16::MIN as f32, i16::MAX as f32);
+                data.extend_from_slice(&(fixed_point as i16).to_be_bytes());
+            }
+            None => data.extend_from_slice(&TLM_NO_TEMPERATURE),
+        }
+        data.extend_from_slice(&self.frames_advertised.to_be_bytes());
+        let deciseconds = (self.started.elapsed().as_millis() / 100) as u32;
+        data.extend_from_slice(&deciseconds.to_be_bytes());
+        data
+    }
+}
+
+/// Compresses an url as defined by the Eddystone-URL specification: the scheme and the common
+/// domain endings are replaced by a single byte.
+///
+/// # Arguments
+///
+/// - `url`: The url to compress
+///
+/// # Returns
+///
+/// A `Result` with the compressed url, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::InvalidParameters`: If the url does not start with a valid scheme or has non printable characters
+/// - `BleError::ServiceTooBig`: If the compressed url is longer than 17 bytes
+fn compress_url(url: &str) -> Result<Vec<u8>, BleError> {
+    let (scheme, mut rest) = URL_SCHEMES
+        .iter()
+        .enumerate()
+        .find_map(|(code, scheme)| url.strip_prefix(scheme).map(|rest| (code as u8, rest)))
+        .ok_or(BleError::InvalidParameters(None))?;
+
+    let mut data = vec![scheme];
+    while let Some(c) = rest.chars().next() {
+        let expansion = URL_EXPANSIONS
+            .iter()
+            .position(|expansion| rest.starts_with(expansion));
+        match expansion {
+            Some(code) => {
+                data.push(code as u8);
+                rest = &rest[URL_EXPANSIONS[code].len()..];
+            }
+            None => {
+                if !c.is_ascii_graphic() {
+                    return Err(BleError::InvalidParameters(None));
+                }
+                data.push(c as u8);
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    if data.len() > MAX_ENCODED_URL_SIZE + 1 {
+        return Err(BleError::ServiceTooBig);
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn telemetry(battery_mv: u16, temperature: Option<f32>, frames: u32) -> EddystoneTelemetry {
+        let mut telemetry = EddystoneTelemetry::new();
+        telemetry.set(battery_mv, temperature);
+        for _ in 0..frames {
+            telemetry.count_frame();
+        }
+        telemetry
+    }
+
+    #[test]
+    fn eddystone_01_uid_frame() {
+        let frame = EddystoneFrame::Uid {
+            namespace: [0x8B, 0x0C, 0xA7, 0x50, 0xE7, 0xA7, 0x4E, 0x14, 0xBD, 0x99],
+            instance: [0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+            tx_power: -59,
+        };
+        assert_eq!(
+            frame.encode(&EddystoneTelemetry::new()).unwrap(),
+            vec![
+                0x00, 0xC5, 0x8B, 0x0C, 0xA7, 0x50, 0xE7, 0xA7, 0x4E, 0x14, 0xBD, 0x99, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn eddystone_02_url_frame() {
+        let frame = EddystoneFrame::Url {
+            url: String::from("https://www.google.com/"),
+            tx_power: -20,
+        };
+        assert_eq!(
+            frame.encode(&EddystoneTelemetry::new()).unwrap(),
+            vec![0x10, 0xEC, 0x01, b'g', b'o', b'o', b'g', b'l', b'e', 0x00]
+        );
+    }
+
+    #[test]
+    fn eddystone_03_url_compression() {
+        assert_eq!(
+            compress_url("https://goo.gl/S6zT6P").unwrap(),
+            b"\x03goo.gl/S6zT6P".to_vec()
+        );
+        assert_eq!(
+            compress_url("http://www.example.org/a.info").unwrap(),
+            b"\x00example\x01a\x0b".to_vec()
+        );
+        assert_eq!(
+            compress_url("http://mit.edu.gov").unwrap(),
+            b"\x02mit\x09\x0d".to_vec()
+        );
+    }
+
+    #[test]
+    fn eddystone_04_invalid_urls() {
+        assert!(matches!(
+            compress_url("ftp://example.com"),
+            Err(BleError::InvalidParameters(None))
+        ));
+        assert!(matches!(
+            compress_url("https://exa mple.com"),
+            Err(BleError::InvalidParameters(None))
+        ));
+        assert!(matches!(
+            compress_url("https://0123456789abcdefgh"),
+            Err(BleError::ServiceTooBig)
+        ));
+        assert_eq!(compress_url("https://0123456789abcdefg").unwrap().len(), 18);
+    }
+
+    #[test]
+    fn eddystone_05_tlm_frame() {
+        let frame = EddystoneFrame::Tlm
+            .encode(&telemetry(3000, Some(25.5), 2))
+            .unwrap();
+        assert_eq!(frame.len(), 14);
+        assert_eq!(
+            &frame[..10],
+            &[0x20, 0x00, 0x0B, 0xB8, 0x19, 0x80, 0x00, 0x00, 0x00, 0x02]
+        );
+        assert_eq!(&frame[10..], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn eddystone_06_tlm_temperature_is_fixed_point() {
+        let negative = telemetry(0, Some(-1.25), 0).encode();
+        assert_eq!(&negative[2..4], &[0xFE, 0xC0]);
+        let unknown = telemetry(0, None, 0).encode();
+        assert_eq!(&unknown[2..4], &[0x80, 0x00]);
+        let too_hot = telemetry(0, Some(200.0), 0).encode();
+        assert_eq!(&too_hot[2..4], &[0x7F, 0xFF]);
+    }
+}
@@ -0,0 +1,308 @@
+use super::{
+    ble_standard_uuids::{StandardCharacteristicId, StandardDescriptorId, StandardServiceId},
+    BleId, Characteristic, Descriptor, Service,
+};
+
+const MAX_UINT24: u32 = 0x00FF_FFFF;
+
+/// Enums the sampling functions an Environmental Sensing Measurement descriptor can report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SamplingFunction {
+    Unspecified,
+    Instantaneous,
+    ArithmeticMean,
+    Rms,
+    Maximum,
+    Minimum,
+    Accumulated,
+    Count,
+}
+
+/// Enums the applications an Environmental Sensing Measurement descriptor can report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeasurementApplication {
+    Unspecified,
+    Air,
+    Water,
+    Barometric,
+    Soil,
+}
+
+/// Enums the readings that can be exposed through the Environmental Sensing Service:
+/// - `Temperature`: Temperature in degrees Celsius. Sent with a resolution of 0.01 °C.
+/// - `Humidity`: Relative humidity in percentage. Sent with a resolution of 0.01 %.
+/// - `Pressure`: Pressure in pascals. Sent with a resolution of 0.1 Pa.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnvironmentalMeasurement {
+    Temperature(f32),
+    Humidity(f32),
+    Pressure(f32),
+}
+
+/// Builder of the standard Environmental Sensing Service (0x181A). Each added reading is
+/// mapped into its standard characteristic, readable and notifiable, with an Environmental
+/// Sensing Measurement descriptor describing how the value was sampled.
+#[derive(Debug, Clone)]
+pub struct EnvironmentalSensingService {
+    measurements: Vec<EnvironmentalMeasurement>,
+    sampling_function: SamplingFunction,
+    measurement_period: u32,
+    update_interval: u32,
+    application: MeasurementApplication,
+}
+
+impl SamplingFunction {
+    /// Gets the code of the sampling function as specified by the standard
+    fn get_code(&self) -> u8 {
+        match self {
+            SamplingFunction::Unspecified => 0x00,
+            SamplingFunction::Instantaneous => 0x01,
+            SamplingFunction::ArithmeticMean => 0x02,
+            SamplingFunction::Rms => 0x03,
+            SamplingFunction::Maximum => 0x04,
+            SamplingFunction::Minimum => 0x05,
+            SamplingFunction::Accumulated => 0x06,
+            SamplingFunction::Count => 0x07,
+        }
+    }
+}
+
+impl MeasurementApplication {
+    /// Gets the code of the application as specified by the standard
+    fn get_code(&self) -> u8 {
+        match self {
+            MeasurementApplication::Unspecified => 0x00,
+            MeasurementApplication::Air => 0x01,
+            MeasurementApplication::Water => 0x02,
+            MeasurementApplication::Barometric => 0x03,
+            MeasurementApplication::Soil => 0x04,
+        }
+    }
+}
+
+impl EnvironmentalMeasurement {
+    /// Gets the id of the standard characteristic that represents the measurement
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the characteristic
+    pub fn characteristic_id(&self) -> BleId {
+        match self {
+            EnvironmentalMeasurement::Temperature(_) => {
+                BleId::from_standard_characteristic(StandardCharacteristicId::Temperature)
+            }
+            EnvironmentalMeasurement::Humidity(_) => {
+                BleId::from_standard_characteristic(StandardCharacteristicId::Humidity)
+            }
+            EnvironmentalMeasurement::Pressure(_) => {
+                BleId::from_standard_characteristic(StandardCharacteristicId::Pressure)
+            }
+        }
+    }
+
+    /// Encodes the measurement in the little endian format expected by the standard characteristic.
+    /// Values outside the range of the format are saturated.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<u8>` with the encoded value
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            EnvironmentalMeasurement::Temperature(celsius) => {
+                ((celsius * 100.0).round() as i16).to_le_bytes().to_vec()
+            }
+            EnvironmentalMeasurement::Humidity(percentage) => {
+                ((percentage * 100.0).round() as u16).to_le_bytes().to_vec()
+            }
+            EnvironmentalMeasurement::Pressure(pascals) => {
+                ((pascals * 10.0).round() as u32).to_le_bytes().to_vec()
+            }
+        }
+    }
+
+    /// Creates a readable and notifiable characteristic with the encoded measurement as its data.
+    ///
+    /// # Returns
+    ///
+    /// The new Characteristic
+    pub fn to_characteristic(&self) -> Characteristic {
+        Characteristic::new(&self.characteristic_id(), self.to_bytes())
+            .readable(true)
+            .notifiable(true)
+    }
+}
+
+impl EnvironmentalSensingService {
+    /// Creates a new EnvironmentalSensingService without readings. The measurement descriptors
+    /// will have instantaneous sampling, unspecified application and no period nor update interval.
+    ///
+    /// # Returns
+    ///
+    /// The new EnvironmentalSensingService
+    pub fn new() -> Self {
+        EnvironmentalSensingService {
+            measurements: vec![],
+            sampling_function: SamplingFunction::Instantaneous,
+            measurement_period: 0,
+            update_interval: 0,
+            application: MeasurementApplication::Unspecified,
+        }
+    }
+
+    /// Gets the id of the Environmental Sensing Service
+    ///
+    /// # Returns
+    ///
+    /// The `BleId` of the service
+    pub fn id() -> BleId {
+        BleId::from_standard_service(StandardServiceId::EnvironmentalSensing)
+    }
+
+    /// Adds or replaces a measurement on the service
+    fn set_measurement(mut self, measurement: EnvironmentalMeasurement) -> Self {
+        let id = measurement.characteristic_id();
+        self.measurements.retain(|m| m.characteristic_id() != id);
+        self.measurements.push(measurement);
+        self
+    }
+
+    /// Adds a temperature characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `celsius`: Initial temperature in degrees Celsius
+    ///
+    /// # Returns
+    ///
+    /// The EnvironmentalSensingService itself
+    pub fn temperature(self, celsius: f32) -> Self {
+        self.set_measurement(EnvironmentalMeasurement::Temperature(celsius))
+    }
+
+    /// Adds a humidity characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `percentage`: Initial relative humidity in percentage
+    ///
+    /// # Returns
+    ///
+    /// The EnvironmentalSensingService itself
+    pub fn humidity(self, percentage: f32) -> Self {
+        self.set_measurement(EnvironmentalMeasurement::Humidity(percentage))
+    }
+
+    /// Adds a pressure characteristic to the service
+    ///
+    /// # Arguments
+    ///
+    /// - `pascals`: Initial pressure in pascals
+    ///
+    /// # Returns
+    ///
+    /// The EnvironmentalSensingService itself
+    pub fn pressure(self, pascals: f32) -> Self {
+        self.set_measurement(EnvironmentalMeasurement::Pressure(pascals))
+    }
+
+    /// Sets the sampling function informed in the measurement descriptors
+    ///
+    /// # Arguments
+    ///
+    /// - `sampling_function`: The SamplingFunction used to obtain the readings
+    ///
+    /// # Returns
+    ///
+    /// The EnvironmentalSensingService itself
+    pub fn sampling_function(mut self, sampling_function: SamplingFunction) -> Self {
+        self.sampling_function = sampling_function;
+        self
+    }
+
+    /// Sets the measurement period informed in the measurement descriptors. Values greater
+    /// than 16777215 are saturated.
+    ///
+    /// # Arguments
+    ///
+    /// - `seconds`: Period of time over which the readings are sampled
+    ///
+    /// # Returns
+    ///
+    /// The EnvironmentalSensingService itself
+    pub fn measurement_period(mut self, seconds: u32) -> Self {
+        self.measurement_period = seconds.min(MAX_UINT24);
+        self
+    }
+
+    /// Sets the update interval informed in the measurement descriptors. This should match
+    /// the period used to notify the readings. Values greater than 16777215 are saturated.
+    ///
+    /// # Arguments
+    ///
+    /// - `seconds`: Interval between updates of the readings
+    ///
+    /// # Returns
+    ///
+    /// The EnvironmentalSensingService itself
+    pub fn update_interval(mut self, seconds: u32) -> Self {
+        self.update_interval = seconds.min(MAX_UINT24);
+        self
+    }
+
+    /// Sets the application informed in the measurement descriptors
+    ///
+    /// # Arguments
+    ///
+    /// - `application`: The MeasurementApplication of the readings
+    ///
+    /// # Returns
+    ///
+    /// The EnvironmentalSensingService itself
+    pub fn application(mut self, application: MeasurementApplication) -> Self {
+        self.application = application;
+        self
+    }
+
+    /// Creates the data of the Environmental Sensing Measurement descriptor. It is made of
+    /// 2 bytes of flags, 1 byte of sampling function, 3 bytes of measurement period,
+    /// 3 bytes of update interval, 1 byte of application and 1 byte of uncertainty.
+    fn measurement_descriptor_data(&self) -> Vec<u8> {
+        let mut data = vec![0, 0, self.sampling_function.get_code()];
+        data.extend_from_slice(&self.measurement_period.to_le_bytes()[..3]);
+        data.extend_from_slice(&self.update_interval.to_le_bytes()[..3]);
+        data.push(self.application.get_code());
+        data.push(0);
+        data
+    }
+
+    /// Creates the Service with one characteristic per reading, each one with its measurement descriptor
+    ///
+    /// # Returns
+    ///
+    /// The Environmental Sensing Service ready to be set on a BleServer
+    pub fn build(&self) -> Service {
+        let descriptor = Descriptor::new(
+            BleId::from_standard_descriptor(StandardDescriptorId::EnvironmentalSensingMeasurement),
+            self.measurement_descriptor_data(),
+        )
+        .readable(true);
+
+        let characteristics = self
+            .measurements
+            .iter()
+            .map(|m| m.to_characteristic().add_descriptor(&descriptor))
+            .collect();
+
+        Service {
+            id: Self::id(),
+            data: vec![],
+            characteristics,
+        }
+    }
+}
+
+impl Default for EnvironmentalSensingService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,200 @@
+use super::BleError;
+use esp32_nimble::BLEError;
+use esp_idf_svc::sys::{
+    ble_gatt_attr, ble_gatt_error, ble_gattc_read_long, ble_gattc_write_long,
+    ble_hs_mbuf_from_flat, os_mbuf_copydata, os_mbuf_len, BLE_HS_EDONE, BLE_HS_ENOMEM,
+};
+use std::{
+    ffi::{c_int, c_void},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+/// State of a GATT procedure shared with the BLE task. Contains:
+/// - `data`: The chunks of the value received so far.
+/// - `status`: The status the procedure ended with, None while it is in progress.
+struct ProcedureInner {
+    data: Vec<u8>,
+    status: Option<c_int>,
+}
+
+/// State of a GATT procedure, with a Condvar signaled when it ends
+struct Procedure {
+    inner: Mutex<ProcedureInner>,
+    finished: Condvar,
+}
+
+impl Procedure {
+    fn new() -> Arc<Self> {
+        Arc::new(Procedure {
+            inner: Mutex::new(ProcedureInner {
+                data: vec![],
+                status: None,
+            }),
+            finished: Condvar::new(),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ProcedureInner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Marks the procedure as ended and wakes up the waiting task
+    fn finish(&self, status: c_int) {
+        self.lock().status = Some(status);
+        self.finished.notify_all();
+    }
+
+    /// Blocks until the procedure ends. NimBLE always ends a procedure, either when the peer
+    /// answers, when the connection drops or when the ATT timeout of 30 seconds expires.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the data received, or a `BleError` if the procedure failed.
+    fn wait(&self) -> Result<Vec<u8>, BleError> {
+        let mut inner = self
+            .finished
+            .wait_while(self.lock(), |inner| inner.status.is_none())
+            .unwrap_or_else(|err| err.into_inner());
+        let status = inner.status.unwrap_or_default();
+        BLEError::convert(status as u32).map_err(BleError::from_characteristic_context)?;
+        Ok(std::mem::take(&mut inner.data))
+    }
+}
+
+/// Reads the value of an attribute starting at an offset, with a Read Blob request for each chunk
+/// of the MTU of the connection. Blocks until the whole value is read.
+///
+/// # Arguments
+///
+/// - `conn_handle`: The connection with the peer
+/// - `attr_handle`: The handle of the attribute on the peer
+/// - `offset`: The first byte of the value to read
+///
+/// # Returns
+///
+/// A `Result` with the bytes from the offset to the end of the value, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::Disconnected`: If the connection dropped
+/// - `BleError::InvalidParameters`: If the offset is past the end of the value
+/// - `BleError::CharacteristicNotReadable`: If the attribute can not be read
+/// - `BleError::Code`: on other errors
+pub(crate) fn read_long(
+    conn_handle: u16,
+    attr_handle: u16,
+    offset: u16,
+) -> Result<Vec<u8>, BleError> {
+    let procedure = Procedure::new();
+    let arg = Arc::into_raw(procedure.clone()) as *mut c_void;
+    let res = unsafe {
+        ble_gattc_read_long(
+            conn_handle,
+            attr_handle,
+            offset,
+            Some(read_long_callback),
+            arg,
+        )
+    };
+    if res != 0 {
+        unsafe { drop(Arc::from_raw(arg as *const Procedure)) };
+        BLEError::convert(res as u32).map_err(BleError::from_characteristic_context)?;
+    }
+    procedure.wait()
+}
+
+/// Writes the value of an attribute starting at an offset, queuing it on the peer with Prepare
+/// Write requests of the MTU of the connection and applying it with an Execute Write request, so
+/// the peer gets the whole value at once. Blocks until the peer confirms the write.
+///
+/// # Arguments
+///
+/// - `conn_handle`: The connection with the peer
+/// - `attr_handle`: The handle of the attribute on the peer
+/// - `offset`: The first byte of the value to write
+/// - `data`: The bytes to write from the offset
+///
+/// # Returns
+///
+/// A `Result` with Ok if the value was written, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::Disconnected`: If the connection dropped
+/// - `BleError::InvalidParameters`: If the offset is past the end of the value
+/// - `BleError::ValueTooLong`: If the value does not fit in the max length of the attribute
+/// - `BleError::CharacteristicNotWritable`: If the attribute can not be written
+/// - `BleError::Code`: on other errors, such as running out of buffers for the data
+pub(crate) fn write_long(
+    conn_handle: u16,
+    attr_handle: u16,
+    offset: u16,
+    data: &[u8],
+) -> Result<(), BleError> {
+    let txom = unsafe { ble_hs_mbuf_from_flat(data.as_ptr() as *const c_void, data.len() as u16) };
+    if txom.is_null() {
+        return Err(BleError::Code(
+            BLE_HS_ENOMEM,
+            "No memory left for the written value".to_string(),
+        ));
+    }
+    let procedure = Procedure::new();
+    let arg = Arc::into_raw(procedure.clone()) as *mut c_void;
+    let res = unsafe {
+        ble_gattc_write_long(
+            conn_handle,
+            attr_handle,
+            offset,
+            txom,
+            Some(write_long_callback),
+            arg,
+        )
+    };
+    if res != 0 {
+        unsafe { drop(Arc::from_raw(arg as *const Procedure)) };
+        BLEError::convert(res as u32).map_err(BleError::from_characteristic_context)?;
+    }
+    procedure.wait().map(|_| ())
+}
+
+/// Callback executed by NimBLE for each chunk read, and once more with `BLE_HS_EDONE` when the
+/// whole value was read. The procedure is released once it ends.
+unsafe extern "C" fn read_long_callback(
+    _conn_handle: u16,
+    error: *const ble_gatt_error,
+    attr: *mut ble_gatt_attr,
+    arg: *mut c_void,
+) -> c_int {
+    let procedure = arg as *const Procedure;
+    let status = (*error).status as c_int;
+    if status == 0 && !attr.is_null() {
+        let om = (*attr).om;
+        let len = os_mbuf_len(om);
+        let mut chunk = vec![0u8; len as usize];
+        os_mbuf_copydata(om, 0, len as c_int, chunk.as_mut_ptr() as *mut c_void);
+        (*procedure).lock().data.extend_from_slice(&chunk);
+        return 0;
+    }
+    let status = if status == BLE_HS_EDONE as c_int {
+        0
+    } else {
+        status
+    };
+    (*procedure).finish(status);
+    drop(Arc::from_raw(procedure));
+    0
+}
+
+/// Callback executed by NimBLE once the Execute Write request is answered or the procedure fails.
+/// The procedure is released.
+unsafe extern "C" fn write_long_callback(
+    _conn_handle: u16,
+    error: *const ble_gatt_error,
+    _attr: *mut ble_gatt_attr,
+    arg: *mut c_void,
+) -> c_int {
+    let procedure = arg as *const Procedure;
+    (*procedure).finish((*error).status as c_int);
+    drop(Arc::from_raw(procedure));
+    0
+}
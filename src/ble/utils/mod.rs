@@ -1,18 +1,46 @@
 mod advertised_device;
+mod advertisement_payload;
+mod advertising_params;
+mod battery_service;
 mod ble_error;
 mod ble_id;
 mod ble_server_modes;
 pub mod ble_standard_uuids;
 mod connection_information;
+mod connection_params;
+mod current_time;
+mod device_information;
+mod eddystone;
+mod environmental_sensing;
+mod gatt_procedure;
+mod own_address;
+mod pairing;
+mod reconnect_policy;
 mod remote_service;
+mod scan_filter;
 mod security;
 mod service;
+mod tx_power;
 
 pub use advertised_device::*;
+pub use advertisement_payload::*;
+pub use advertising_params::*;
+pub use battery_service::*;
 pub use ble_error::*;
 pub use ble_id::*;
 pub use ble_server_modes::*;
 pub use connection_information::*;
+pub(crate) use connection_params::*;
+pub use current_time::*;
+pub use device_information::*;
+pub use eddystone::*;
+pub use environmental_sensing::*;
+pub(crate) use gatt_procedure::*;
+pub use own_address::*;
+pub use pairing::*;
+pub use reconnect_policy::*;
 pub use remote_service::*;
+pub use scan_filter::*;
 pub use security::*;
 pub use service::*;
+pub use tx_power::*;
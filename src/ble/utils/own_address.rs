@@ -0,0 +1,187 @@
+use esp32_nimble::{
+    enums::{BLEAddressType, OwnAddrType},
+    BLEAddress, BLEDevice, BLEError,
+};
+use esp_idf_svc::sys::{
+    ble_addr_t, ble_hs_id_copy_addr, ble_hs_id_gen_rnd, ble_hs_id_set_rnd,
+    esp_mac_type_t_ESP_MAC_BT, esp_read_mac, BLE_OWN_ADDR_PUBLIC, BLE_OWN_ADDR_RANDOM,
+    BLE_OWN_ADDR_RPA_PUBLIC_DEFAULT, ESP_OK,
+};
+
+use super::BleError;
+
+const BLE_ADDR_PUBLIC: u8 = 0;
+const BLE_ADDR_RANDOM: u8 = 1;
+/// The two most significant bits of a random static address must be set
+const RANDOM_STATIC_MARK: u8 = 0b1100_0000;
+
+/// Enums the address types the device can use on its advertisements, scans and connections:
+/// - `Public`: The public address burned in the chip. It never changes.
+/// - `RandomStatic`: A random address that stays the same until the device reboots or it is
+///   changed. If no address is given, one is derived from the public address so it is the same
+///   on every boot.
+/// - `ResolvablePrivate`: A private address that changes periodically, but that bonded peers can
+///   resolve to the identity of the device. The rotation interval is set with the
+///   `CONFIG_BT_NIMBLE_RPA_TIMEOUT` option of the sdkconfig, since NimBLE does not allow to
+///   change it at runtime.
+/// - `NonResolvablePrivate`: A private address that nobody can relate to the device. It can only
+///   be used to advertise and scan, not to connect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OwnAddressType {
+    Public,
+    RandomStatic(Option<[u8; 6]>),
+    ResolvablePrivate,
+    NonResolvablePrivate,
+}
+
+impl OwnAddressType {
+    /// Sets the address type on the BLEDevice, generating and setting the random address first
+    /// if the type needs one
+    ///
+    /// # Arguments
+    ///
+    /// - `ble_device`: The BLEDevice used by the ble driver
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the address type was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the given random static address is not a valid one
+    /// - `BleError::Code`: If the controller rejected the random address
+    pub(crate) fn apply(&self, ble_device: &mut BLEDevice) -> Result<(), BleError> {
+        let own_addr_type = match self {
+            OwnAddressType::Public => OwnAddrType::Public,
+            OwnAddressType::RandomStatic(address) => {
+                let address = match address {
+                    Some(address) => validate_random_static_address(*address)?,
+                    None => derived_random_static_address()?,
+                };
+                set_random_address(&address)?;
+                OwnAddrType::Random
+            }
+            OwnAddressType::ResolvablePrivate => OwnAddrType::RpaPublicDefault,
+            OwnAddressType::NonResolvablePrivate => {
+                regenerate_non_resolvable_address()?;
+                OwnAddrType::Random
+            }
+        };
+        ble_device.set_own_addr_type(own_addr_type);
+        Ok(())
+    }
+
+    /// Gets the NimBLE code of the address type, used when starting NimBLE procedures directly
+    ///
+    /// # Returns
+    ///
+    /// A `u8` with the own address type code
+    pub(crate) fn code(&self) -> u8 {
+        let own_addr_type = match self {
+            OwnAddressType::Public => BLE_OWN_ADDR_PUBLIC,
+            OwnAddressType::RandomStatic(_) | OwnAddressType::NonResolvablePrivate => {
+                BLE_OWN_ADDR_RANDOM
+            }
+            OwnAddressType::ResolvablePrivate => BLE_OWN_ADDR_RPA_PUBLIC_DEFAULT,
+        };
+        own_addr_type as u8
+    }
+
+    /// Checks whether the address type allows connections
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is true unless the type is `NonResolvablePrivate`
+    pub fn is_connectable(&self) -> bool {
+        !matches!(self, OwnAddressType::NonResolvablePrivate)
+    }
+}
+
+/// Gets the address the device uses for the address type. For the `ResolvablePrivate` type it
+/// is the identity address of the device, since the private address is generated by the
+/// controller each time it rotates.
+///
+/// # Arguments
+///
+/// - `address_type`: The address type in use
+///
+/// # Returns
+///
+/// A `Result` with the BLEAddress, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::Code`: If the device has no address of the type
+pub(crate) fn own_address(address_type: &OwnAddressType) -> Result<BLEAddress, BleError> {
+    let (ble_type, address_type) = match address_type {
+        OwnAddressType::Public | OwnAddressType::ResolvablePrivate => {
+            (BLE_ADDR_PUBLIC, BLEAddressType::Public)
+        }
+        _ => (BLE_ADDR_RANDOM, BLEAddressType::Random),
+    };
+    let mut address = [0u8; 6];
+    BLEError::convert(unsafe {
+        ble_hs_id_copy_addr(ble_type, address.as_mut_ptr(), std::ptr::null_mut()) as u32
+    })?;
+    Ok(BLEAddress::from_le_bytes(address, address_type))
+}
+
+/// Generates a new non resolvable private address and sets it as the random address of the
+/// device
+///
+/// # Errors
+///
+/// - `BleError::Code`: If the address could not be generated or set
+fn regenerate_non_resolvable_address() -> Result<(), BleError> {
+    let mut address = ble_addr_t {
+        type_: BLE_ADDR_RANDOM,
+        val: [0; 6],
+    };
+    BLEError::convert(unsafe { ble_hs_id_gen_rnd(1, &mut address) as u32 })?;
+    set_random_address(&address.val)
+}
+
+/// Sets the random address of the device, in little endian order
+fn set_random_address(address: &[u8; 6]) -> Result<(), BleError> {
+    Ok(BLEError::convert(unsafe {
+        ble_hs_id_set_rnd(address.as_ptr()) as u32
+    })?)
+}
+
+/// Checks that an address given in the order it is displayed is a valid random static address,
+/// with its two most significant bits set and the rest not all zeros nor all ones
+///
+/// # Returns
+///
+/// A `Result` with the address in little endian order, or `BleError::InvalidParameters` if it is
+/// not valid
+fn validate_random_static_address(address: [u8; 6]) -> Result<[u8; 6], BleError> {
+    let random_part = address[0] & !RANDOM_STATIC_MARK;
+    let all_zeros = random_part == 0 && address[1..].iter().all(|byte| *byte == 0);
+    let all_ones = random_part == !RANDOM_STATIC_MARK && address[1..].iter().all(|b| *b == 0xFF);
+    if address[0] & RANDOM_STATIC_MARK != RANDOM_STATIC_MARK || all_zeros || all_ones {
+        return Err(BleError::InvalidParameters(None));
+    }
+    let mut address = address;
+    address.reverse();
+    Ok(address)
+}
+
+/// Derives a random static address from the bluetooth MAC of the chip, so the device keeps the
+/// same random address between reboots
+///
+/// # Returns
+///
+/// A `Result` with the address in little endian order, or a `BleError` if the MAC could not be read
+fn derived_random_static_address() -> Result<[u8; 6], BleError> {
+    let mut mac = [0u8; 6];
+    let res = unsafe { esp_read_mac(mac.as_mut_ptr(), esp_mac_type_t_ESP_MAC_BT) };
+    if res != ESP_OK {
+        return Err(BleError::Code(
+            res as u32,
+            "Could not read the bluetooth MAC".to_string(),
+        ));
+    }
+    mac[0] |= RANDOM_STATIC_MARK;
+    validate_random_static_address(mac)
+}
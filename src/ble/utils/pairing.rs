@@ -0,0 +1,210 @@
+use crate::utils::{
+    isr_queues::{ISRQueue, ISRQueueTrait},
+    notification::Notifier,
+    queue_config::queue_capacities,
+};
+
+/// Time the BLE task waits for an answer of the user. Pairing fails on its own after 30 seconds.
+const PAIRING_RESPONSE_TIMEOUT_US: u32 = 30_000_000;
+const MAX_PASSKEY: u32 = 999999;
+
+type PasskeyDisplayCallback<'a> = dyn FnMut(u32) + 'a;
+type NumericComparisonCallback<'a> = dyn FnMut(u32) -> bool + 'a;
+type PasskeyEntryCallback<'a> = dyn FnMut() -> u32 + 'a;
+
+/// Events of the pairing process that need the user to take part
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PairingEvent {
+    PasskeyDisplay(u32),
+    NumericComparison(u32),
+    PasskeyEntry,
+}
+
+/// Channel used by the BLE task to send the pairing events to the update loop and wait for
+/// the answer of the user.
+#[derive(Clone)]
+pub(crate) struct PairingChannel {
+    events: ISRQueue<PairingEvent>,
+    responses: ISRQueue<u32>,
+    notifier: Notifier,
+}
+
+/// Callbacks of the user for the pairing events, executed on the update loop.
+pub(crate) struct PairingCallbacks<'a> {
+    channel: PairingChannel,
+    passkey_display: Option<Box<PasskeyDisplayCallback<'a>>>,
+    numeric_comparison: Option<Box<NumericComparisonCallback<'a>>>,
+    passkey_entry: Option<Box<PasskeyEntryCallback<'a>>>,
+}
+
+impl PairingChannel {
+    /// Creates a new PairingChannel
+    ///
+    /// # Arguments
+    ///
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when an event is sent
+    ///
+    /// # Returns
+    ///
+    /// The new PairingChannel
+    fn new(notifier: Notifier) -> Self {
+        let size = queue_capacities().ble_pairing;
+        PairingChannel {
+            events: ISRQueue::new(size),
+            responses: ISRQueue::new(size),
+            notifier,
+        }
+    }
+
+    /// Sends an event to the update loop without waiting for an answer
+    ///
+    /// # Arguments
+    ///
+    /// - `event`: The event to send
+    fn send(&self, event: PairingEvent) {
+        let mut events = self.events.clone();
+        _ = events.try_send(event);
+        self.notifier.notify();
+    }
+
+    /// Sends an event to the update loop and waits for the answer of the user. Answers left
+    /// from previous events that timed out are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// - `event`: The event to send
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the answer, or None if the user did not answer in time
+    fn request(&self, event: PairingEvent) -> Option<u32> {
+        let mut responses = self.responses.clone();
+        while responses.try_recv().is_ok() {}
+        self.send(event);
+        responses.receive_timeout(PAIRING_RESPONSE_TIMEOUT_US).ok()
+    }
+
+    /// Generates a new random passkey and informs it to the update loop, so it can be displayed
+    ///
+    /// # Returns
+    ///
+    /// The new passkey
+    pub(crate) fn display_random_passkey(&self) -> u32 {
+        let passkey = unsafe { esp_idf_svc::sys::esp_random() } % (MAX_PASSKEY + 1);
+        self.send(PairingEvent::PasskeyDisplay(passkey));
+        passkey
+    }
+
+    /// Asks the user whether the number shown on both devices is the same
+    ///
+    /// # Arguments
+    ///
+    /// - `number`: The number to compare
+    ///
+    /// # Returns
+    ///
+    /// True if the user confirmed the number, False if not or if the user did not answer in time
+    pub(crate) fn confirm_number(&self, number: u32) -> bool {
+        self.request(PairingEvent::NumericComparison(number))
+            .map_or(false, |response| response != 0)
+    }
+
+    /// Asks the user for the passkey displayed on the other device
+    ///
+    /// # Returns
+    ///
+    /// The passkey entered by the user, or 0 if the user did not answer in time
+    pub(crate) fn enter_passkey(&self) -> u32 {
+        self.request(PairingEvent::PasskeyEntry).unwrap_or(0)
+    }
+}
+
+impl<'a> PairingCallbacks<'a> {
+    /// Creates a new PairingCallbacks without any callback
+    ///
+    /// # Arguments
+    ///
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when an event is sent
+    ///
+    /// # Returns
+    ///
+    /// The new PairingCallbacks
+    pub(crate) fn new(notifier: Notifier) -> Self {
+        PairingCallbacks {
+            channel: PairingChannel::new(notifier),
+            passkey_display: None,
+            numeric_comparison: None,
+            passkey_entry: None,
+        }
+    }
+
+    /// Gets a channel for the BLE task
+    ///
+    /// # Returns
+    ///
+    /// A clone of the PairingChannel
+    pub(crate) fn channel(&self) -> PairingChannel {
+        self.channel.clone()
+    }
+
+    /// Checks if there is a callback for displaying the passkey
+    ///
+    /// # Returns
+    ///
+    /// True if there is a callback, False if not
+    pub(crate) fn displays_passkey(&self) -> bool {
+        self.passkey_display.is_some()
+    }
+
+    /// Sets the callback executed with the passkey that the other device must enter
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the passkey to display
+    pub(crate) fn set_passkey_display<C: FnMut(u32) + 'a>(&mut self, callback: C) {
+        self.passkey_display = Some(Box::new(callback));
+    }
+
+    /// Sets the callback executed to confirm the number shown on both devices
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the number and returns whether it matches
+    pub(crate) fn set_numeric_comparison<C: FnMut(u32) -> bool + 'a>(&mut self, callback: C) {
+        self.numeric_comparison = Some(Box::new(callback));
+    }
+
+    /// Sets the callback executed to get the passkey displayed on the other device
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that returns the passkey entered by the user
+    pub(crate) fn set_passkey_entry<C: FnMut() -> u32 + 'a>(&mut self, callback: C) {
+        self.passkey_entry = Some(Box::new(callback));
+    }
+
+    /// Executes the user callback for each event received since the last call, sending back
+    /// the answers to the BLE task.
+    pub(crate) fn handle_events(&mut self) {
+        while let Ok(event) = self.channel.events.try_recv() {
+            let response = match event {
+                PairingEvent::PasskeyDisplay(passkey) => {
+                    if let Some(callback) = self.passkey_display.as_mut() {
+                        callback(passkey)
+                    }
+                    continue;
+                }
+                PairingEvent::NumericComparison(number) => {
+                    self.numeric_comparison
+                        .as_mut()
+                        .map_or(false, |callback| callback(number)) as u32
+                }
+                PairingEvent::PasskeyEntry => self
+                    .passkey_entry
+                    .as_mut()
+                    .map_or(0, |callback| callback().min(MAX_PASSKEY)),
+            };
+            _ = self.channel.responses.try_send(response);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+const DEFAULT_MULTIPLIER: u32 = 2;
+
+/// Policy used by a [crate::ble::BleClient] to reconnect when the link to the server drops.
+/// Before each attempt the client waits a backoff, which starts at the initial backoff and is
+/// multiplied after every failed attempt, up to the max backoff.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconnectPolicy {
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            multiplier: DEFAULT_MULTIPLIER,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Creates a new ReconnectPolicy. By default the backoff starts at 500ms, doubles after every
+    /// attempt and is at most 30s.
+    ///
+    /// # Arguments
+    ///
+    /// - `max_attempts`: Amount of attempts before giving up
+    ///
+    /// # Returns
+    ///
+    /// The new ReconnectPolicy
+    pub fn new(max_attempts: u32) -> Self {
+        ReconnectPolicy {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    /// Sets the time waited before the first attempt
+    ///
+    /// # Arguments
+    ///
+    /// - `backoff`: The initial backoff
+    ///
+    /// # Returns
+    ///
+    /// The ReconnectPolicy itself
+    pub fn initial_backoff(mut self, backoff: Duration) -> Self {
+        self.initial_backoff = backoff;
+        self
+    }
+
+    /// Sets the maximum time waited between two attempts
+    ///
+    /// # Arguments
+    ///
+    /// - `backoff`: The max backoff
+    ///
+    /// # Returns
+    ///
+    /// The ReconnectPolicy itself
+    pub fn max_backoff(mut self, backoff: Duration) -> Self {
+        self.max_backoff = backoff;
+        self
+    }
+
+    /// Sets the factor by which the backoff is multiplied after each failed attempt
+    ///
+    /// # Arguments
+    ///
+    /// - `multiplier`: The factor, 1 for a constant backoff
+    ///
+    /// # Returns
+    ///
+    /// The ReconnectPolicy itself
+    pub fn multiplier(mut self, multiplier: u32) -> Self {
+        self.multiplier = multiplier.max(1);
+        self
+    }
+
+    /// Gets the amount of attempts before giving up
+    ///
+    /// # Returns
+    ///
+    /// The max amount of attempts
+    pub fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    /// Gets the time to wait before an attempt
+    ///
+    /// # Arguments
+    ///
+    /// - `attempt`: The number of the attempt, starting from 0
+    ///
+    /// # Returns
+    ///
+    /// The backoff of the attempt
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self.multiplier.saturating_pow(attempt);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
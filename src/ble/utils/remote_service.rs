@@ -1,5 +1,9 @@
 use esp32_nimble::{BLERemoteCharacteristic, BLERemoteDescriptor};
-use esp_idf_svc::hal::task::block_on;
+use esp_idf_svc::{
+    hal::task::block_on,
+    sys::{ble_att_mtu, BLE_HS_ENOMEM},
+};
+use std::{thread, time::Duration};
 
 use crate::utils::{
     auxiliary::{SharableRef, SharableRefExt},
@@ -7,7 +11,11 @@ use crate::utils::{
     notification::Notifier,
 };
 
-use super::{BleError, BleId};
+use super::{read_long, write_long, BleError, BleId};
+
+const ATT_WRITE_HEADER_SIZE: usize = 3;
+const BUFFER_RETRY_DELAY: Duration = Duration::from_millis(1);
+const MAX_BUFFER_RETRIES: u32 = 1000;
 
 /// A remote characteristic representing an available characteristic of a given service of a
 /// ble connection. Can be used to read, write and notify.
@@ -29,6 +37,8 @@ struct RemoteCharacteristicUpdater {
 struct _RemoteCharacteristic {
     characteristic: BLERemoteCharacteristic,
     notifier: Option<Notifier>,
+    conn_handle: u16,
+    write_queue: Vec<u8>,
 }
 
 impl RemoteCharacteristicUpdater {
@@ -63,12 +73,21 @@ impl RemoteCharacteristic {
     ///
     /// - `characteristic`: the remote characteristic to be wrapped
     /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller]
+    /// - `conn_handle`: The handle of the connection the characteristic belongs to
     ///
     /// # Returns
     /// A [RemoteCharacteristic]
-    pub(crate) fn new(characteristic: &mut BLERemoteCharacteristic, notifier: Notifier) -> Self {
+    pub(crate) fn new(
+        characteristic: &mut BLERemoteCharacteristic,
+        notifier: Notifier,
+        conn_handle: u16,
+    ) -> Self {
         Self {
-            inner: SharableRef::new_sharable(_RemoteCharacteristic::new(characteristic, notifier)),
+            inner: SharableRef::new_sharable(_RemoteCharacteristic::new(
+                characteristic,
+                notifier,
+                conn_handle,
+            )),
             updater: SharableRef::new_sharable(RemoteCharacteristicUpdater::default()),
         }
     }
@@ -123,13 +142,20 @@ impl _RemoteCharacteristic {
     ///
     /// - `characteristic`: the remote characteristic to be wrapped
     /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller]
+    /// - `conn_handle`: The handle of the connection the characteristic belongs to
     ///
     /// # Returns
     /// A [_RemoteCharacteristic]
-    fn new(characteristic: &mut BLERemoteCharacteristic, notifier: Notifier) -> Self {
+    fn new(
+        characteristic: &mut BLERemoteCharacteristic,
+        notifier: Notifier,
+        conn_handle: u16,
+    ) -> Self {
         Self {
             characteristic: characteristic.clone(),
             notifier: Some(notifier),
+            conn_handle,
+            write_queue: vec![],
         }
     }
 
@@ -166,7 +192,7 @@ impl _RemoteCharacteristic {
     /// Non blocking async version of [Self::read]
     pub async fn read_async(&mut self) -> Result<Vec<u8>, BleError> {
         if !self.is_readable() {
-            return Err(BleError::CharacteristicNotReadable);
+            return Err(BleError::CharacteristicNotReadable(None));
         }
         self.characteristic
             .read_value()
@@ -174,7 +200,8 @@ impl _RemoteCharacteristic {
             .map_err(BleError::from_characteristic_context)
     }
 
-    /// Attempts to read the characteristics value
+    /// Attempts to read the characteristics value. Values longer than the MTU of the connection are
+    /// read in chunks with Read Blob requests.
     ///
     /// # Returns     
     ///
@@ -192,7 +219,7 @@ impl _RemoteCharacteristic {
     /// Non blocking async version of [Self::write]
     pub async fn write_async(&mut self, data: &[u8]) -> Result<(), BleError> {
         if !self.is_writable() && !self.is_writable_no_resp() {
-            return Err(BleError::CharacteristicNotWritable);
+            return Err(BleError::CharacteristicNotWritable(None));
         }
         self.characteristic
             .write_value(data, !self.is_writable_no_resp())
@@ -201,7 +228,8 @@ impl _RemoteCharacteristic {
     }
 
     /// Attempts to write the characteristic value. The write will wait for a response or not depending if the characterisitc
-    /// is [Self::is_writable_no_resp], or not.
+    /// is [Self::is_writable_no_resp], or not. Values longer than [Self::max_write_size] are sent with
+    /// prepared writes when waiting for a response, and fail otherwise.
     ///
     /// # Returns
     ///
@@ -215,6 +243,149 @@ impl _RemoteCharacteristic {
         block_on(self.write_async(data))
     }
 
+    /// Gets the longest value that can be written with a single request on the current MTU of the
+    /// connection. Longer values need prepared writes.
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes, or 0 if the connection dropped
+    pub fn max_write_size(&self) -> usize {
+        let mtu = unsafe { ble_att_mtu(self.conn_handle) } as usize;
+        mtu.saturating_sub(ATT_WRITE_HEADER_SIZE)
+    }
+
+    /// Blocking method that reads the value of the characteristic starting at an offset, with a
+    /// Read Blob request for each chunk of the MTU of the connection. Useful to resume the read
+    /// of a long value, or to read only its tail.
+    ///
+    /// # Arguments
+    ///
+    /// - `offset`: The first byte of the value to read
+    ///
+    /// # Returns
+    ///
+    /// A `Result` contanting the bytes from the offset to the end of the value, or a `BleError`
+    ///
+    /// # Errors
+    ///
+    /// `BleError::CharacteristicNotReadable`: If the characteristic is not readable
+    /// `BleError::InvalidParameters`: If the offset is past the end of the value
+    /// `BleError::Disconnected`: If connection to the ble server is lost
+    /// `BleError::Code`: On other errors
+    pub fn read_long(&mut self, offset: u16) -> Result<Vec<u8>, BleError> {
+        if !self.is_readable() {
+            return Err(BleError::CharacteristicNotReadable(None));
+        }
+        read_long(self.conn_handle, self.characteristic.handle(), offset)
+    }
+
+    /// Blocking method that writes part of the value of the characteristic starting at an offset,
+    /// with prepared writes. The chunks are queued on the server and applied together once all of
+    /// them were received, so the server never sees a partially written value.
+    ///
+    /// # Arguments
+    ///
+    /// - `offset`: The first byte of the value to write
+    /// - `data`: The bytes to write from the offset
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` if the server applied the write or BleError on failure
+    ///
+    /// # Errors
+    ///
+    /// `BleError::CharacteristicNotWritable`: If the characteristic is not writable with response
+    /// `BleError::InvalidParameters`: If the offset is past the end of the value
+    /// `BleError::ValueTooLong`: If the value does not fit in the max length of the characteristic
+    /// `BleError::Disconnected`: If connection to the ble server is lost
+    /// `BleError::Code`: On other errors
+    pub fn write_long(&mut self, offset: u16, data: &[u8]) -> Result<(), BleError> {
+        if !self.is_writable() {
+            return Err(BleError::CharacteristicNotWritable(None));
+        }
+        write_long(self.conn_handle, self.characteristic.handle(), offset, data)
+    }
+
+    /// Queues data to be written without response by [Self::flush]. Consecutive queued writes are
+    /// joined and sent in packets as big as the MTU of the connection allows, which is much faster
+    /// than writing small values one by one, for example when uploading a firmware. The boundaries
+    /// between the queued writes are not kept, so the server must handle the data as a stream.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The data to queue
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` if the data was queued or BleError on failure
+    ///
+    /// # Errors
+    ///
+    /// `BleError::CharacteristicNotWritable`: If the characteristic is not writable without response
+    pub fn queue_write(&mut self, data: &[u8]) -> Result<(), BleError> {
+        if !self.is_writable_no_resp() {
+            return Err(BleError::CharacteristicNotWritable(None));
+        }
+        self.write_queue.extend_from_slice(data);
+        Ok(())
+    }
+
+    /// Returns the amount of bytes queued with [Self::queue_write] that were not sent yet
+    pub fn queued_bytes(&self) -> usize {
+        self.write_queue.len()
+    }
+
+    /// Non blocking async version of [Self::flush]
+    pub async fn flush_async(&mut self) -> Result<(), BleError> {
+        let mtu = unsafe { ble_att_mtu(self.conn_handle) } as usize;
+        if mtu <= ATT_WRITE_HEADER_SIZE {
+            return Err(BleError::Disconnected(None));
+        }
+        let packet_size = mtu - ATT_WRITE_HEADER_SIZE;
+        while !self.write_queue.is_empty() {
+            let len = packet_size.min(self.write_queue.len());
+            let packet: Vec<u8> = self.write_queue[..len].to_vec();
+            self.write_packet(&packet).await?;
+            self.write_queue.drain(..len);
+        }
+        Ok(())
+    }
+
+    /// Sends the data queued with [Self::queue_write] without response, in packets of the MTU of
+    /// the connection. When the controller runs out of buffers, it waits for them to be freed
+    /// before sending the next packet. If a packet fails, it and the following ones stay queued.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing `()` if every packet was sent or BleError on failure
+    ///
+    /// # Errors
+    ///
+    /// `BleError::Disconnected`: If connection to the ble server is lost
+    /// `BleError::TimeOut`: If the controller did not free its buffers in time
+    /// `BleError::Code`: On other errors
+    pub fn flush(&mut self) -> Result<(), BleError> {
+        block_on(self.flush_async())
+    }
+
+    /// Writes a packet without response, retrying while the controller has no buffers available
+    async fn write_packet(&mut self, packet: &[u8]) -> Result<(), BleError> {
+        let mut retries = 0;
+        loop {
+            match self.characteristic.write_value(packet, false).await {
+                Ok(()) => return Ok(()),
+                Err(err) if err.code() == BLE_HS_ENOMEM => {
+                    if retries == MAX_BUFFER_RETRIES {
+                        return Err(BleError::TimeOut(None));
+                    }
+                    retries += 1;
+                    thread::sleep(BUFFER_RETRY_DELAY);
+                }
+                Err(err) => return Err(BleError::from_characteristic_context(err)),
+            }
+        }
+    }
+
     /// Documented on [RemoteCharacteristic::on_notify]
     fn set_notification_on_notify(&mut self, mut queue: ISRByteArrayQueue) -> Result<(), BleError> {
         if !self.is_notifiable() {
@@ -349,3 +520,40 @@ impl From<&mut BLERemoteDescriptor> for RemoteDescriptor {
         }
     }
 }
+
+/// A service of the connected device, with all of its characteristics, as returned by
+/// [crate::ble::BleClient::get_services]
+pub struct RemoteService {
+    pub id: BleId,
+    pub characteristics: Vec<RemoteCharacteristic>,
+}
+
+impl RemoteService {
+    /// Efectibly clones the remote service, but is only allowed in the crate
+    pub(crate) fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            characteristics: self
+                .characteristics
+                .iter()
+                .map(|characteristic| characteristic.clone())
+                .collect(),
+        }
+    }
+
+    /// Gets a characteristic of the service
+    ///
+    /// # Arguments
+    ///
+    /// - `id`: The id of the characteristic
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the RemoteCharacteristic, or None if the service does not have it
+    pub fn characteristic(&self, id: &BleId) -> Option<RemoteCharacteristic> {
+        self.characteristics
+            .iter()
+            .find(|characteristic| characteristic.id() == *id)
+            .map(|characteristic| characteristic.clone())
+    }
+}
@@ -0,0 +1,127 @@
+use esp32_nimble::BLEAddress;
+
+use super::{BleAdvertisedDevice, BleId};
+
+/// Filters used to select the devices found while scanning. A device must fulfill every filter set
+/// to be selected. A filter without conditions selects every device.
+#[derive(Debug, Clone, Default)]
+pub struct ScanFilter {
+    name_contains: Option<String>,
+    service: Option<BleId>,
+    min_rssi: Option<i32>,
+    address: Option<BLEAddress>,
+    stop_on_first_match: bool,
+}
+
+impl ScanFilter {
+    /// Creates a new ScanFilter without conditions
+    ///
+    /// # Returns
+    ///
+    /// The new ScanFilter
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only selects devices whose name contains the text
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text the name must contain
+    ///
+    /// # Returns
+    ///
+    /// The ScanFilter itself
+    pub fn name_contains(mut self, text: &str) -> Self {
+        self.name_contains = Some(text.to_string());
+        self
+    }
+
+    /// Only selects devices that advertise the service
+    ///
+    /// # Arguments
+    ///
+    /// - `service_id`: The BleId of the service
+    ///
+    /// # Returns
+    ///
+    /// The ScanFilter itself
+    pub fn service(mut self, service_id: &BleId) -> Self {
+        self.service = Some(service_id.clone());
+        self
+    }
+
+    /// Only selects devices received with at least the rssi
+    ///
+    /// # Arguments
+    ///
+    /// - `rssi`: The minimum rssi in dBm
+    ///
+    /// # Returns
+    ///
+    /// The ScanFilter itself
+    pub fn min_rssi(mut self, rssi: i32) -> Self {
+        self.min_rssi = Some(rssi);
+        self
+    }
+
+    /// Only selects the device with the address
+    ///
+    /// # Arguments
+    ///
+    /// - `address`: The BLEAddress of the device
+    ///
+    /// # Returns
+    ///
+    /// The ScanFilter itself
+    pub fn address(mut self, address: BLEAddress) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Sets whether the scan stops as soon as a device is selected
+    ///
+    /// # Arguments
+    ///
+    /// - `stop`: If true the scan stops on the first match
+    ///
+    /// # Returns
+    ///
+    /// The ScanFilter itself
+    pub fn stop_on_first_match(mut self, stop: bool) -> Self {
+        self.stop_on_first_match = stop;
+        self
+    }
+
+    /// Gets whether the scan stops as soon as a device is selected
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates if the scan stops on the first match
+    pub fn stops_on_first_match(&self) -> bool {
+        self.stop_on_first_match
+    }
+
+    /// Checks whether a device fulfills every filter
+    ///
+    /// # Arguments
+    ///
+    /// - `device`: The device to check
+    ///
+    /// # Returns
+    ///
+    /// True if the device fulfills every filter, False if not
+    pub fn matches(&self, device: &BleAdvertisedDevice) -> bool {
+        self.name_contains
+            .as_ref()
+            .map_or(true, |text| device.name().contains(text.as_str()))
+            && self
+                .service
+                .as_ref()
+                .map_or(true, |service| device.is_advertising_service(service))
+            && self.min_rssi.map_or(true, |rssi| device.rssi() >= rssi)
+            && self
+                .address
+                .map_or(true, |address| *device.addr() == address)
+    }
+}
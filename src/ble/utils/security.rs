@@ -1,5 +1,8 @@
 use super::BleError;
-use esp32_nimble::enums::{AuthReq, SecurityIOCap};
+use esp32_nimble::{
+    enums::{AuthReq, SecurityIOCap},
+    BLEDevice,
+};
 
 const MAX_PASKEY: u32 = 999999;
 
@@ -41,6 +44,17 @@ impl IOCapabilities {
         }
     }
 }
+/// Builds a [Security] step by step, validating the whole configuration at the end:
+/// - `passkey`: A 6-digit u32. If it is not set, a random one is used.
+/// - `auth_mode`: An u8 representing the combination of authorization modes
+/// - `io_capabilities`: An IOCapabilities instance
+#[derive(Debug)]
+pub struct SecurityBuilder {
+    passkey: Option<u32>,
+    auth_mode: u8,
+    io_capabilities: IOCapabilities,
+}
+
 /// Contains the necessary to have a secure BLE server.
 /// This includes a passkey, the I/O capabilities and the
 /// authorization requirements.
@@ -67,15 +81,84 @@ impl Security {
     /// # Returns
     ///
     /// A new Security instance
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidPasskey`: If the passkey has more than 6 digits
     pub fn new(passkey: u32, io_capabilities: IOCapabilities) -> Result<Self, BleError> {
-        if passkey > MAX_PASKEY {
-            return Err(BleError::InvalidPasskey);
-        }
-        Ok(Security {
+        let security = Security {
             passkey,
             auth_mode: 0,
             io_capabilities,
-        })
+        };
+        security.validate()?;
+        Ok(security)
+    }
+
+    /// Creates a SecurityBuilder, which sets every requirement before validating them together
+    ///
+    /// # Arguments
+    ///
+    /// - `io_capabilities`: An IOCapabilities instance
+    ///
+    /// # Returns
+    ///
+    /// A new SecurityBuilder
+    pub fn builder(io_capabilities: IOCapabilities) -> SecurityBuilder {
+        SecurityBuilder::new(io_capabilities)
+    }
+
+    /// Checks that the passkey and the combination of authorization requirements and I/O
+    /// capabilities can be used for pairing. It is also checked when the security is applied by
+    /// [crate::Microcontroller::ble_secure_server] or [crate::ble::BleClient::secure_connection].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the configuration is valid, or a `BleError` if it is not
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidPasskey`: If the passkey has more than 6 digits
+    /// - `BleError::MitmWithoutIOCapabilities`: If the Man in the Middle requirement is set while
+    ///   having no input nor output, since pairing without them can not authenticate the devices
+    pub fn validate(&self) -> Result<(), BleError> {
+        if self.passkey > MAX_PASKEY {
+            return Err(BleError::InvalidPasskey);
+        }
+        let mitm = self.auth_mode & AuthReq::Mitm.bits() != 0;
+        if mitm && matches!(self.io_capabilities, IOCapabilities::NoInputNoOutput) {
+            return Err(BleError::MitmWithoutIOCapabilities);
+        }
+        Ok(())
+    }
+
+    /// Validates the security and sets it as the one used by the BLEDevice for every pairing
+    ///
+    /// # Arguments
+    ///
+    /// - `ble_device`: The BLEDevice to configure
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the security was set, or a `BleError` if it is not valid
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the authorization requirements are not valid
+    /// - `BleError::InvalidPasskey` or `BleError::MitmWithoutIOCapabilities`: If the security is
+    ///   not valid, see [Security::validate].
+    pub(crate) fn apply(&self, ble_device: &mut BLEDevice) -> Result<(), BleError> {
+        self.validate()?;
+        ble_device
+            .security()
+            .set_auth(
+                AuthReq::from_bits(self.auth_mode.to_le())
+                    .ok_or(BleError::InvalidParameters(None))?,
+            )
+            .set_passkey(self.passkey)
+            .set_io_cap(self.io_capabilities.get_code())
+            .resolve_rpa();
+        Ok(())
     }
 
     /// Adds or removes a authorization requirement to the security instance
@@ -149,3 +232,109 @@ impl Security {
         self
     }
 }
+
+impl SecurityBuilder {
+    /// Creates a SecurityBuilder without authorization requirements nor passkey
+    ///
+    /// # Arguments
+    ///
+    /// - `io_capabilities`: An IOCapabilities instance
+    ///
+    /// # Returns
+    ///
+    /// A new SecurityBuilder
+    fn new(io_capabilities: IOCapabilities) -> Self {
+        SecurityBuilder {
+            passkey: None,
+            auth_mode: 0,
+            io_capabilities,
+        }
+    }
+
+    /// Sets the passkey used for pairing
+    ///
+    /// # Arguments
+    ///
+    /// - `passkey`: A 6-digit u32
+    ///
+    /// # Returns
+    ///
+    /// The SecurityBuilder with the passkey set
+    pub fn passkey(mut self, passkey: u32) -> Self {
+        self.passkey = Some(passkey);
+        self
+    }
+
+    /// Sets the Allow Bonding authorization requirement, as in [Security::allow_bonding]
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: A bool. When True the requirement is added. When False the requirement is removed
+    ///
+    /// # Returns
+    ///
+    /// The SecurityBuilder with the requirement set
+    pub fn allow_bonding(self, value: bool) -> Self {
+        self.toggle(value, AuthReq::Bond)
+    }
+
+    /// Sets the Man in the Middle authorization requirement, as in [Security::man_in_the_middle]
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: A bool. When True the requirement is added. When False the requirement is removed
+    ///
+    /// # Returns
+    ///
+    /// The SecurityBuilder with the requirement set
+    pub fn man_in_the_middle(self, value: bool) -> Self {
+        self.toggle(value, AuthReq::Mitm)
+    }
+
+    /// Sets the Secure Connection authorization requirement, as in [Security::secure_connection]
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: A bool. When True the requirement is added. When False the requirement is removed
+    ///
+    /// # Returns
+    ///
+    /// The SecurityBuilder with the requirement set
+    pub fn secure_connection(self, value: bool) -> Self {
+        self.toggle(value, AuthReq::Sc)
+    }
+
+    /// Adds or removes a authorization requirement
+    fn toggle(mut self, value: bool, flag: AuthReq) -> Self {
+        if value {
+            self.auth_mode |= flag.bits();
+        } else {
+            self.auth_mode &= !flag.bits();
+        }
+        self
+    }
+
+    /// Creates the Security, validating its configuration as in [Security::validate]
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new Security, or a `BleError` if the configuration is invalid
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidPasskey`: If the passkey has more than 6 digits
+    /// - `BleError::MitmWithoutIOCapabilities`: If the Man in the Middle requirement is set while
+    ///   having no input nor output
+    pub fn build(self) -> Result<Security, BleError> {
+        let passkey = self
+            .passkey
+            .unwrap_or_else(|| unsafe { esp_idf_svc::sys::esp_random() } % (MAX_PASKEY + 1));
+        let security = Security {
+            passkey,
+            auth_mode: self.auth_mode,
+            io_capabilities: self.io_capabilities,
+        };
+        security.validate()?;
+        Ok(security)
+    }
+}
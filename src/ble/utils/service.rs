@@ -2,7 +2,9 @@ use esp32_nimble::{DescriptorProperties, NimbleProperties};
 
 use super::{BleError, BleId};
 
-const MAX_ADV_PAYLOAD_SIZE: usize = 31;
+pub(crate) const MAX_ADV_PAYLOAD_SIZE: usize = 31;
+/// Max length of an attribute value allowed by the ATT protocol
+pub(crate) const MAX_ATTRIBUTE_LENGTH: usize = 512;
 const PAYLOAD_FIELD_IDENTIFIER_SIZE: usize = 2;
 
 /// A struct representing a Bluetooth Low Energy (BLE) service.
@@ -90,12 +92,14 @@ impl Service {
 /// - `id`: The id lets clients identified each service characteristic.
 /// - `properties`: Properties especify how the clients will be able to interact with the characteristic.
 /// - `data`: The value that the clients will be able to see or write (depending on the properties).
+/// - `max_length`: The max length of the value, 512 bytes unless set with [Self::max_length].
 #[derive(Clone, Debug)]
 pub struct Characteristic {
     pub id: BleId,
     pub(crate) properties: u16,
     pub data: Vec<u8>,
     pub descriptors: Vec<Descriptor>,
+    pub(crate) max_length: usize,
 }
 
 impl Characteristic {
@@ -116,6 +120,7 @@ impl Characteristic {
             properties: 0,
             data,
             descriptors: vec![],
+            max_length: MAX_ATTRIBUTE_LENGTH,
         }
     }
 
@@ -133,6 +138,39 @@ impl Characteristic {
         self
     }
 
+    /// Sets the max length of the value of the characteristic. Values longer than the MTU of the
+    /// connection are read by the clients in chunks with Read Blob requests, and written with
+    /// prepared writes that the server receives as a single write. Writes longer than the max
+    /// length are rejected with the "Invalid Attribute Value Length" ATT error.
+    ///
+    /// # Arguments
+    ///
+    /// - `max_length`: The max length in bytes, of up to 512 bytes
+    ///
+    /// # Returns
+    ///
+    /// The Characteristic itself
+    pub fn max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Checks that the max length is allowed by the ATT protocol and that the data fits in it
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::InvalidParameters`: If the max length is longer than 512 bytes
+    /// - `BleError::ValueTooLong`: If the data is longer than the max length
+    pub(crate) fn check_length(&self) -> Result<(), BleError> {
+        if self.max_length > MAX_ATTRIBUTE_LENGTH {
+            return Err(BleError::InvalidParameters(None));
+        }
+        if self.data.len() > self.max_length {
+            return Err(BleError::ValueTooLong(None));
+        }
+        Ok(())
+    }
+
     /// Adds or removes a property to the characteristic
     ///
     /// # Arguments
@@ -0,0 +1,205 @@
+use super::BleError;
+use esp_idf_svc::sys::{
+    esp_ble_enhanced_power_type_t, esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_ADV,
+    esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_CONN,
+    esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_DEFAULT, esp_ble_tx_power_get_enhanced,
+    esp_ble_tx_power_set_enhanced, esp_power_level_t, esp_power_level_t_ESP_PWR_LVL_N0,
+    esp_power_level_t_ESP_PWR_LVL_N12, esp_power_level_t_ESP_PWR_LVL_N15,
+    esp_power_level_t_ESP_PWR_LVL_N3, esp_power_level_t_ESP_PWR_LVL_N6,
+    esp_power_level_t_ESP_PWR_LVL_N9, esp_power_level_t_ESP_PWR_LVL_P12,
+    esp_power_level_t_ESP_PWR_LVL_P15, esp_power_level_t_ESP_PWR_LVL_P18,
+    esp_power_level_t_ESP_PWR_LVL_P20, esp_power_level_t_ESP_PWR_LVL_P3,
+    esp_power_level_t_ESP_PWR_LVL_P6, esp_power_level_t_ESP_PWR_LVL_P9, EspError,
+};
+
+const LEVELS: [(TxPower, esp_power_level_t); 13] = [
+    (TxPower::N15, esp_power_level_t_ESP_PWR_LVL_N15),
+    (TxPower::N12, esp_power_level_t_ESP_PWR_LVL_N12),
+    (TxPower::N9, esp_power_level_t_ESP_PWR_LVL_N9),
+    (TxPower::N6, esp_power_level_t_ESP_PWR_LVL_N6),
+    (TxPower::N3, esp_power_level_t_ESP_PWR_LVL_N3),
+    (TxPower::N0, esp_power_level_t_ESP_PWR_LVL_N0),
+    (TxPower::P3, esp_power_level_t_ESP_PWR_LVL_P3),
+    (TxPower::P6, esp_power_level_t_ESP_PWR_LVL_P6),
+    (TxPower::P9, esp_power_level_t_ESP_PWR_LVL_P9),
+    (TxPower::P12, esp_power_level_t_ESP_PWR_LVL_P12),
+    (TxPower::P15, esp_power_level_t_ESP_PWR_LVL_P15),
+    (TxPower::P18, esp_power_level_t_ESP_PWR_LVL_P18),
+    (TxPower::P20, esp_power_level_t_ESP_PWR_LVL_P20),
+];
+
+/// Enums the transmission power levels supported by the radio of the ESP32-C6, from -15 dBm
+/// (`N15`) to +20 dBm (`P20`). Lower levels reduce the consumption and the range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TxPower {
+    N15,
+    N12,
+    N9,
+    N6,
+    N3,
+    N0,
+    P3,
+    P6,
+    P9,
+    P12,
+    P15,
+    P18,
+    P20,
+}
+
+/// Enums the radio activities whose transmission power can be set:
+/// - `Default`: Every activity without its own level.
+/// - `Advertising`: The advertising set of the instance. Legacy advertising uses the instance 0.
+/// - `Connection`: The connection of the handle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum TxPowerTarget {
+    Default,
+    Advertising(u8),
+    Connection(u16),
+}
+
+impl TxPower {
+    /// Gets the highest level that does not exceed a power
+    ///
+    /// # Arguments
+    ///
+    /// - `dbm`: The desired power in dBm
+    ///
+    /// # Returns
+    ///
+    /// The highest TxPower that does not exceed `dbm`, or `TxPower::N15` if every level exceeds it
+    pub fn from_dbm(dbm: i8) -> Self {
+        LEVELS
+            .iter()
+            .rev()
+            .map(|(level, _)| *level)
+            .find(|level| level.dbm() <= dbm)
+            .unwrap_or(TxPower::N15)
+    }
+
+    /// Gets the power of the level
+    ///
+    /// # Returns
+    ///
+    /// The power in dBm
+    pub fn dbm(&self) -> i8 {
+        match self {
+            TxPower::N15 => -15,
+            TxPower::N12 => -12,
+            TxPower::N9 => -9,
+            TxPower::N6 => -6,
+            TxPower::N3 => -3,
+            TxPower::N0 => 0,
+            TxPower::P3 => 3,
+            TxPower::P6 => 6,
+            TxPower::P9 => 9,
+            TxPower::P12 => 12,
+            TxPower::P15 => 15,
+            TxPower::P18 => 18,
+            TxPower::P20 => 20,
+        }
+    }
+
+    fn to_power_level(self) -> esp_power_level_t {
+        LEVELS[self as usize].1
+    }
+
+    fn from_power_level(power_level: esp_power_level_t) -> Option<Self> {
+        LEVELS
+            .iter()
+            .find(|(_, level)| *level == power_level)
+            .map(|(tx_power, _)| *tx_power)
+    }
+}
+
+impl TxPowerTarget {
+    /// Gets the power type and handle used by the controller for the target
+    fn to_type_and_handle(self) -> (esp_ble_enhanced_power_type_t, u16) {
+        match self {
+            TxPowerTarget::Default => (
+                esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_DEFAULT,
+                0,
+            ),
+            TxPowerTarget::Advertising(instance) => (
+                esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_ADV,
+                instance as u16,
+            ),
+            TxPowerTarget::Connection(conn_handle) => (
+                esp_ble_enhanced_power_type_t_ESP_BLE_ENHANCED_PWR_TYPE_CONN,
+                conn_handle,
+            ),
+        }
+    }
+
+    /// Sets the transmission power of the target
+    ///
+    /// # Arguments
+    ///
+    /// - `tx_power`: The TxPower to use
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the power was set, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::Code`: If the controller rejects the level, for example because the handle does
+    ///   not exist
+    pub(crate) fn set(self, tx_power: TxPower) -> Result<(), BleError> {
+        let (power_type, handle) = self.to_type_and_handle();
+        EspError::convert(unsafe {
+            esp_ble_tx_power_set_enhanced(power_type, handle, tx_power.to_power_level())
+        })
+        .map_err(|err| BleError::Code(err.code() as u32, err.to_string()))
+    }
+
+    /// Gets the transmission power of the target
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the TxPower, or a `BleError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::NotFound`: If the controller has no level for the target, for example because
+    ///   the handle does not exist
+    pub(crate) fn get(self) -> Result<TxPower, BleError> {
+        let (power_type, handle) = self.to_type_and_handle();
+        let power_level = unsafe { esp_ble_tx_power_get_enhanced(power_type, handle) };
+        TxPower::from_power_level(power_level).ok_or(BleError::NotFound(None))
+    }
+}
+
+/// Sets the transmission power used by every advertisement, scan and connection without its own
+/// level. Levels set for a specific advertising set or connection are kept.
+///
+/// # Arguments
+///
+/// - `tx_power`: The TxPower to use
+///
+/// # Returns
+///
+/// A `Result` with Ok if the power was set, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::Code`: If the controller rejects the level, for example because the BLE stack was
+///   not started yet
+pub fn set_default_tx_power(tx_power: TxPower) -> Result<(), BleError> {
+    TxPowerTarget::Default.set(tx_power)
+}
+
+/// Gets the transmission power used by every advertisement, scan and connection without its own
+/// level
+///
+/// # Returns
+///
+/// A `Result` with the TxPower, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::NotFound`: If the controller could not inform the level, for example because the
+///   BLE stack was not started yet
+pub fn default_tx_power() -> Result<TxPower, BleError> {
+    TxPowerTarget::Default.get()
+}
@@ -0,0 +1,303 @@
+use super::AnalogIn;
+use crate::{
+    microcontroller_src::interrupt_driver::InterruptDriver,
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        timer_driver::{TimerDriver, TimerDriverError},
+    },
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const DEFAULT_SAMPLE_PERIOD: Duration = Duration::from_millis(10);
+/// Value stored while a pin has not been read yet
+const NO_READING: u32 = u32::MAX;
+
+type ChangeCallback<'a> = dyn FnMut(u16) + 'a;
+
+/// Enums the different errors possible when working with the AdcScanner
+#[derive(Debug)]
+pub enum AdcScannerError {
+    InvalidPin,
+    NoPins,
+    TimerDriverError(TimerDriverError),
+}
+
+/// Handle to the latest value read by an [AdcScanner] from one of its pins. Reading it does not
+/// touch the ADC nor take any lock, so it can be cloned and read from anywhere.
+#[derive(Debug, Clone)]
+pub struct AdcReading {
+    latest: Arc<AtomicU32>,
+}
+
+/// A pin scanned by the AdcScanner, with its latest value and change callback
+struct ScannedPin<'a> {
+    input: AnalogIn<'a>,
+    latest: Arc<AtomicU32>,
+    threshold: u16,
+    last_reported: Option<u16>,
+    user_callback: Option<Box<ChangeCallback<'a>>>,
+}
+
+/// Shares the single ADC between several analog pins by reading them one after the other at a
+/// fixed rate. The latest value of each pin is stored, and a callback can be executed when it
+/// changes, through the update loop.
+struct _AdcScanner<'a> {
+    timer_driver: TimerDriver<'a>,
+    sample_period: Duration,
+    sample_due: Arc<AtomicBool>,
+    pins: Vec<ScannedPin<'a>>,
+    next_pin: usize,
+}
+
+/// Shares the single ADC between several analog pins by reading them one after the other at a
+/// fixed rate. The latest value of each pin is stored, and a callback can be executed when it
+/// changes, through the update loop.
+pub struct AdcScanner<'a> {
+    inner: SharableRef<_AdcScanner<'a>>,
+}
+
+impl AdcReading {
+    /// Gets the latest value read from the pin
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the value, or None if the pin was not read yet
+    pub fn value(&self) -> Option<u16> {
+        match self.latest.load(Ordering::Relaxed) {
+            NO_READING => None,
+            value => Some(value as u16),
+        }
+    }
+}
+
+impl<'a> ScannedPin<'a> {
+    /// Reads the pin, storing the value and executing the callback if it changed by at least
+    /// the threshold since the last time it was reported. Failed reads are discarded.
+    fn sample(&mut self) {
+        let Ok(value) = self.input.read() else {
+            return;
+        };
+        self.latest.store(value as u32, Ordering::Relaxed);
+
+        let changed = self
+            .last_reported
+            .map_or(true, |last| last.abs_diff(value) >= self.threshold);
+        if let (true, Some(callback)) = (changed, self.user_callback.as_mut()) {
+            self.last_reported = Some(value);
+            callback(value);
+        }
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _AdcScanner<'a> {
+    /// Creates a new _AdcScanner without pins
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used to pace the readings
+    ///
+    /// # Returns
+    ///
+    /// The new _AdcScanner
+    fn new(timer_driver: TimerDriver<'a>) -> Self {
+        _AdcScanner {
+            timer_driver,
+            sample_period: DEFAULT_SAMPLE_PERIOD,
+            sample_due: Arc::new(AtomicBool::new(false)),
+            pins: vec![],
+            next_pin: 0,
+        }
+    }
+
+    /// Adds a pin to be scanned.
+    ///
+    /// # Arguments
+    ///
+    /// - `input`: The AnalogIn of the pin, with its attenuation already set
+    ///
+    /// # Returns
+    ///
+    /// The id of the pin, used to get its readings and set its callback
+    pub fn add_pin(&mut self, input: AnalogIn<'a>) -> usize {
+        self.pins.push(ScannedPin {
+            input,
+            latest: Arc::new(AtomicU32::new(NO_READING)),
+            threshold: 0,
+            last_reported: None,
+            user_callback: None,
+        });
+        self.pins.len() - 1
+    }
+
+    /// Sets the time between two readings. Pins are read one per period, so each pin is updated
+    /// every `period * amount of pins`. By default a pin is read every 10 ms. Changes are applied
+    /// the next time the scanner is started.
+    ///
+    /// # Arguments
+    ///
+    /// - `period`: Time between two readings
+    ///
+    /// # Returns
+    ///
+    /// The _AdcScanner itself
+    pub fn set_sample_period(&mut self, period: Duration) -> &mut Self {
+        self.sample_period = period;
+        self
+    }
+
+    /// Sets the callback executed when the value of a pin changes by at least the threshold
+    /// since the last value reported. It is also executed with the first value read.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `pin`: The id of the pin
+    /// - `threshold`: Minimum change needed to execute the callback
+    /// - `callback`: A closure that receives the new value
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the callback was set, or an `AdcScannerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AdcScannerError::InvalidPin`: If the id was not added to the scanner.
+    pub fn on_change<C: FnMut(u16) + 'a>(
+        &mut self,
+        pin: usize,
+        threshold: u16,
+        callback: C,
+    ) -> Result<(), AdcScannerError> {
+        let scanned_pin = self.pins.get_mut(pin).ok_or(AdcScannerError::InvalidPin)?;
+        scanned_pin.threshold = threshold;
+        scanned_pin.last_reported = None;
+        scanned_pin.user_callback = Some(Box::new(callback));
+        Ok(())
+    }
+
+    /// Gets a handle to the latest value of a pin
+    ///
+    /// # Arguments
+    ///
+    /// - `pin`: The id of the pin
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the AdcReading, or None if the id was not added to the scanner
+    pub fn reading(&self, pin: usize) -> Option<AdcReading> {
+        self.pins.get(pin).map(|scanned_pin| AdcReading {
+            latest: scanned_pin.latest.clone(),
+        })
+    }
+
+    /// Gets the latest value read from a pin
+    ///
+    /// # Arguments
+    ///
+    /// - `pin`: The id of the pin
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the value, or None if the id is invalid or the pin was not read yet
+    pub fn latest(&self, pin: usize) -> Option<u16> {
+        self.reading(pin)?.value()
+    }
+
+    /// Starts scanning the pins
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the scanning started, or an `AdcScannerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AdcScannerError::NoPins`: If no pins were added.
+    /// - `AdcScannerError::TimerDriverError`: If the timer driver could not be enabled.
+    pub fn start(&mut self) -> Result<(), AdcScannerError> {
+        if self.pins.is_empty() {
+            return Err(AdcScannerError::NoPins);
+        }
+        let sample_due = self.sample_due.clone();
+        self.timer_driver.interrupt_after_n_times(
+            self.sample_period
+                .as_micros()
+                .try_into()
+                .unwrap_or(u64::MAX),
+            None,
+            true,
+            move || sample_due.store(true, Ordering::SeqCst),
+        );
+        Ok(self.timer_driver.enable()?)
+    }
+
+    /// Stops scanning the pins, keeping their latest values
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the scanning stopped, or an `AdcScannerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AdcScannerError::TimerDriverError`: If the timer driver could not be disabled.
+    pub fn stop(&mut self) -> Result<(), AdcScannerError> {
+        Ok(self.timer_driver.disable()?)
+    }
+
+    /// Reads the next pin when it is due
+    fn _update_interrupt(&mut self) {
+        if !self.sample_due.swap(false, Ordering::SeqCst) || self.pins.is_empty() {
+            return;
+        }
+        self.next_pin %= self.pins.len();
+        self.pins[self.next_pin].sample();
+        self.next_pin += 1;
+    }
+}
+
+impl<'a> AdcScanner<'a> {
+    /// Creates a new AdcScanner without pins
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used to pace the readings
+    ///
+    /// # Returns
+    ///
+    /// The new AdcScanner
+    pub(crate) fn new(timer_driver: TimerDriver<'a>) -> Self {
+        AdcScanner {
+            inner: SharableRef::new_sharable(_AdcScanner::new(timer_driver)),
+        }
+    }
+}
+
+impl<'a> InterruptDriver<'a> for AdcScanner<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut()._update_interrupt();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+impl From<TimerDriverError> for AdcScannerError {
+    fn from(value: TimerDriverError) -> Self {
+        AdcScannerError::TimerDriverError(value)
+    }
+}
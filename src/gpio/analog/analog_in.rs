@@ -1,15 +1,37 @@
 use crate::{
     microcontroller_src::{
+        interrupt_driver::InterruptDriver,
         microcontroller::SharableAdcDriver,
         peripherals::{Peripheral, PeripheralError},
     },
-    utils::esp32_framework_error::AdcDriverError,
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::{AdcDriverError, Esp32FrameworkError},
+        notification::Notifier,
+        units::Millivolts,
+    },
+};
+use esp_idf_svc::{
+    hal::{adc::attenuation::adc_atten_t, adc::*, gpio::*},
+    timer::{EspTaskTimerService, EspTimer},
 };
-use esp_idf_svc::hal::{adc::attenuation::adc_atten_t, adc::*, gpio::*};
 use oneshot::{config::AdcChannelConfig, AdcChannelDriver, AdcDriver};
-use std::{rc::Rc, time::Duration, time::Instant};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    ops::RangeInclusive,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+    time::Instant,
+};
 
 const MAX_DIGITAL_VAL: u16 = 4095;
+const DEFAULT_ALERT_INTERVAL: Duration = Duration::from_millis(100);
+
+type AlertCallback<'a> = dyn FnMut(u16) + 'a;
 
 /// Enums the different errors possible when working with the analog in
 #[derive(Debug)]
@@ -19,12 +41,50 @@ pub enum AnalogInError {
     ErrorReading,
     InvalidPeripheral(PeripheralError),
     InvalidPin,
+    InvalidWindow,
+    TimerError,
+}
+
+/// Enums the conditions that trigger an alert of an AnalogIn:
+/// - `Above`: The value read is greater than the threshold.
+/// - `Below`: The value read is less than the threshold.
+/// - `Outside`: The value read is out of the window.
+enum AlertCondition {
+    Above(u16),
+    Below(u16),
+    Outside(RangeInclusive<u16>),
+}
+
+/// An alert checked on every background sample of an AnalogIn. The callback is executed once
+/// when the condition starts being met, and the alert is rearmed when the value goes back past
+/// the limit by at least the hysteresis.
+struct Alert<'a> {
+    condition: AlertCondition,
+    triggered: bool,
+    callback: Box<AlertCallback<'a>>,
 }
 
 /// Driver for receiving analog inputs from a particular pin
 /// - `adc_channel_driver`: Instance of AnalogChannels
-pub struct AnalogIn<'a> {
+/// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a background sample is due
+/// - `timer`: The timer that sets when the next background sample is due, created with the first alert
+/// - `alert_interval`: Time between two background samples
+/// - `alert_hysteresis`: Amount the value must go back past the limit of an alert to rearm it
+/// - `sample_due`: Set by the timer when the next background sample must be taken
+/// - `alerts`: The alerts checked on each background sample
+struct _AnalogIn<'a> {
     adc_channel_driver: AnalogChannels<'a>,
+    notifier: Notifier,
+    timer: Option<EspTimer<'static>>,
+    alert_interval: Duration,
+    alert_hysteresis: u16,
+    sample_due: Arc<AtomicBool>,
+    alerts: Vec<Alert<'a>>,
+}
+
+/// Driver for receiving analog inputs from a particular pin
+pub struct AnalogIn<'a> {
+    inner: SharableRef<_AnalogIn<'a>>,
 }
 
 /// Enums the possible channels from the ADC. In the ESP32-C6 the
@@ -40,30 +100,71 @@ enum AnalogChannels<'a> {
     Channel6(AdcChannelDriver<'a, Gpio6, Rc<AdcDriver<'a, ADC1>>>),
 }
 
-impl<'a> AnalogIn<'a> {
+impl<'a> Alert<'a> {
+    /// Checks the condition with a new value, executing the callback if it started being met
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: The value read
+    /// - `hysteresis`: Amount the value must go back past the limit to rearm the alert
+    fn check(&mut self, value: u16, hysteresis: u16) {
+        let (met, rearmed) = match &self.condition {
+            AlertCondition::Above(threshold) => (
+                value > *threshold,
+                value.saturating_add(hysteresis) <= *threshold,
+            ),
+            AlertCondition::Below(threshold) => (
+                value < *threshold,
+                value >= threshold.saturating_add(hysteresis),
+            ),
+            AlertCondition::Outside(window) => (
+                !window.contains(&value),
+                value >= window.start().saturating_add(hysteresis)
+                    && value.saturating_add(hysteresis) <= *window.end(),
+            ),
+        };
+        if !self.triggered && met {
+            self.triggered = true;
+            (self.callback)(value);
+        } else if self.triggered && rearmed {
+            self.triggered = false;
+        }
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _AnalogIn<'a> {
     /// Create a new _AnalogIn for a specific pin.
     ///
     /// # Arguments
     ///
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a background sample is due
     /// - `pin`: A Peripheral of type Pin
     /// - `adc_driver`: An instance of a SharableAdcDriver
     /// - `attenuation`: An adc_atten_t representing the desired attenuation
     ///
     /// # Returns
     ///
-    /// A `Result` containing the new `AnalogIn` instance, or an `AnalogInError` if the
+    /// A `Result` containing the new `_AnalogIn` instance, or an `AnalogInError` if the
     /// initialization fails.
     ///
     /// # Errors
     ///
     /// - `AnalogInError::InvalidPin`: If the pin Peripheral is not valid
-    pub(crate) fn new(
+    fn new(
+        notifier: Notifier,
         pin: Peripheral,
         adc_driver: SharableAdcDriver<'a>,
         attenuation: adc_atten_t,
     ) -> Result<Self, AnalogInError> {
-        Ok(AnalogIn {
-            adc_channel_driver: AnalogIn::new_channel(pin, adc_driver, attenuation)?,
+        Ok(_AnalogIn {
+            adc_channel_driver: _AnalogIn::new_channel(pin, adc_driver, attenuation)?,
+            notifier,
+            timer: None,
+            alert_interval: DEFAULT_ALERT_INTERVAL,
+            alert_hysteresis: 0,
+            sample_due: Arc::new(AtomicBool::new(false)),
+            alerts: vec![],
         })
     }
 
@@ -127,7 +228,7 @@ impl<'a> AnalogIn<'a> {
             },
             Peripheral::None => {
                 return Err(AnalogInError::InvalidPeripheral(
-                    PeripheralError::AlreadyTaken,
+                    PeripheralError::AlreadyTaken { owner: None },
                 ))
             }
             _ => return Err(AnalogInError::InvalidPeripheral(PeripheralError::NotAPin)),
@@ -225,6 +326,282 @@ impl<'a> AnalogIn<'a> {
         let result = smooth_val / amount_of_samples as u64;
         Ok(result as u16)
     }
+
+    /// Reads the voltage on the analog pin. The range possible depends on the attenuation set.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the Millivolts read, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::ErrorReading`: If the read operation failed
+    pub fn read_voltage(&mut self) -> Result<Millivolts, AnalogInError> {
+        Ok(Millivolts(self.read()? as u32))
+    }
+
+    /// Reads the voltage on the analog pin multiple times and returns the average, in order to get
+    /// a more stable value.
+    ///
+    /// # Arguments
+    ///
+    /// - `amount_of_samples`: The number of times to read from the analog pin
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the average Millivolts, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::ErrorReading`: If the read operation failed
+    pub fn smooth_read_voltage(
+        &mut self,
+        amount_of_samples: u16,
+    ) -> Result<Millivolts, AnalogInError> {
+        Ok(Millivolts(self.smooth_read(amount_of_samples)? as u32))
+    }
+
+    /// Sets the callback executed when the value read goes above the threshold. The pin is
+    /// sampled in the background every alert interval, see [Self::set_alert_interval], and the
+    /// callback is executed once each time the threshold is crossed.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `threshold`: The value, in the units of [Self::read], the reading must exceed
+    /// - `callback`: A closure that receives the value read
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the alert was set, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::TimerError`: If the background sampling could not be started
+    pub fn on_above<C: FnMut(u16) + 'a>(
+        &mut self,
+        threshold: u16,
+        callback: C,
+    ) -> Result<(), AnalogInError> {
+        self.add_alert(AlertCondition::Above(threshold), Box::new(callback))
+    }
+
+    /// Sets the callback executed when the value read goes below the threshold. The pin is
+    /// sampled in the background every alert interval, see [Self::set_alert_interval], and the
+    /// callback is executed once each time the threshold is crossed.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `threshold`: The value, in the units of [Self::read], the reading must fall under
+    /// - `callback`: A closure that receives the value read
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the alert was set, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::TimerError`: If the background sampling could not be started
+    pub fn on_below<C: FnMut(u16) + 'a>(
+        &mut self,
+        threshold: u16,
+        callback: C,
+    ) -> Result<(), AnalogInError> {
+        self.add_alert(AlertCondition::Below(threshold), Box::new(callback))
+    }
+
+    /// Sets the callback executed when the value read leaves the window. The pin is sampled in
+    /// the background every alert interval, see [Self::set_alert_interval], and the callback is
+    /// executed once each time the value leaves the window.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `window`: The range of values, in the units of [Self::read], considered normal
+    /// - `callback`: A closure that receives the value read
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the alert was set, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::InvalidWindow`: If the window is empty
+    /// - `AnalogInError::TimerError`: If the background sampling could not be started
+    pub fn on_outside<C: FnMut(u16) + 'a>(
+        &mut self,
+        window: RangeInclusive<u16>,
+        callback: C,
+    ) -> Result<(), AnalogInError> {
+        if window.is_empty() {
+            return Err(AnalogInError::InvalidWindow);
+        }
+        self.add_alert(AlertCondition::Outside(window), Box::new(callback))
+    }
+
+    /// Sets the time between two background samples of the alerts. By default the pin is sampled
+    /// every 100 ms.
+    ///
+    /// # Arguments
+    ///
+    /// - `interval`: Time between two samples
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the interval was set, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::TimerError`: If the background sampling could not be restarted
+    pub fn set_alert_interval(&mut self, interval: Duration) -> Result<(), AnalogInError> {
+        self.alert_interval = interval;
+        if self.alerts.is_empty() {
+            return Ok(());
+        }
+        self.start_alert_sampling()
+    }
+
+    /// Sets the amount the value must go back past the limit of an alert before it can be
+    /// triggered again, so a noisy reading near the limit does not trigger it repeatedly. By
+    /// default it is 0.
+    ///
+    /// # Arguments
+    ///
+    /// - `hysteresis`: The amount, in the units of [Self::read]
+    pub fn set_alert_hysteresis(&mut self, hysteresis: u16) {
+        self.alert_hysteresis = hysteresis;
+    }
+
+    /// Removes every alert and stops the background sampling
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the alerts were removed, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::TimerError`: If the background sampling could not be stopped
+    pub fn clear_alerts(&mut self) -> Result<(), AnalogInError> {
+        self.alerts.clear();
+        match self.timer.as_ref() {
+            Some(timer) => timer
+                .cancel()
+                .map(|_| ())
+                .map_err(|_| AnalogInError::TimerError),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds an alert, starting the background sampling if it is the first one
+    fn add_alert(
+        &mut self,
+        condition: AlertCondition,
+        callback: Box<AlertCallback<'a>>,
+    ) -> Result<(), AnalogInError> {
+        self.alerts.push(Alert {
+            condition,
+            triggered: false,
+            callback,
+        });
+        if self.alerts.len() > 1 {
+            return Ok(());
+        }
+        self.start_alert_sampling()
+    }
+
+    /// Starts the timer that sets when the next background sample is due. The timer is created the
+    /// first time, so an AnalogIn without alerts does not use one.
+    fn start_alert_sampling(&mut self) -> Result<(), AnalogInError> {
+        if self.timer.is_none() {
+            let sample_due = self.sample_due.clone();
+            let notifier = self.notifier.clone();
+            let timer = EspTaskTimerService::new()
+                .and_then(|service| {
+                    service.timer(move || {
+                        sample_due.store(true, Ordering::SeqCst);
+                        notifier.notify();
+                    })
+                })
+                .map_err(|_| AnalogInError::TimerError)?;
+            self.timer = Some(timer);
+        }
+        let timer = self.timer.as_ref().ok_or(AnalogInError::TimerError)?;
+        timer
+            .every(self.alert_interval)
+            .map_err(|_| AnalogInError::TimerError)
+    }
+
+    /// Samples the pin when it is due and checks the alerts. Failed reads are discarded.
+    fn _update_interrupt(&mut self) {
+        if !self.sample_due.swap(false, Ordering::SeqCst) || self.alerts.is_empty() {
+            return;
+        }
+        let Ok(value) = self.read() else {
+            return;
+        };
+        let hysteresis = self.alert_hysteresis;
+        for alert in &mut self.alerts {
+            alert.check(value, hysteresis);
+        }
+    }
+}
+
+impl<'a> AnalogIn<'a> {
+    /// Create a new AnalogIn for a specific pin.
+    ///
+    /// # Arguments
+    ///
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a background sample is due
+    /// - `pin`: A Peripheral of type Pin
+    /// - `adc_driver`: An instance of a SharableAdcDriver
+    /// - `attenuation`: An adc_atten_t representing the desired attenuation
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `AnalogIn` instance, or an `AnalogInError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::InvalidPin`: If the pin Peripheral is not valid
+    pub(crate) fn new(
+        notifier: Notifier,
+        pin: Peripheral,
+        adc_driver: SharableAdcDriver<'a>,
+        attenuation: adc_atten_t,
+    ) -> Result<Self, AnalogInError> {
+        Ok(AnalogIn {
+            inner: SharableRef::new_sharable(_AnalogIn::new(
+                notifier,
+                pin,
+                adc_driver,
+                attenuation,
+            )?),
+        })
+    }
+}
+
+impl<'a> InterruptDriver<'a> for AnalogIn<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut()._update_interrupt();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
 }
 
 impl From<AdcDriverError> for AnalogInError {
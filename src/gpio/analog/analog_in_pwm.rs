@@ -2,7 +2,7 @@ use crate::{
     gpio::digital::{DigitalIn, DigitalInError},
     microcontroller_src::peripherals::Peripheral,
     timer_driver::TimerDriverError,
-    utils::timer_driver::TimerDriver,
+    utils::{timer_driver::TimerDriver, units::Hertz},
 };
 use esp_idf_svc::hal::ledc::config::TimerConfig;
 
@@ -85,6 +85,15 @@ impl<'a> AnalogInPwm<'a> {
         self.sampling = FREQUENCY_TO_SAMPLING_RATIO * frequency_hz
     }
 
+    /// Changes the frequency between each read, like [Self::set_sampling_frequency].
+    ///
+    /// # Arguments
+    ///
+    /// - `frequency`: The Hertz of the signal to be read.
+    pub fn set_signal_frequency(&mut self, frequency: Hertz) {
+        self.set_sampling_frequency(frequency.0)
+    }
+
     /// Returns the intensity value [0 , 1] obtained dividing the amount
     /// of Highs read by the amount of samples taken.
     ///
@@ -1,3 +1,4 @@
+use super::Waveform;
 use crate::{
     microcontroller_src::{
         interrupt_driver::InterruptDriver,
@@ -6,6 +7,9 @@ use crate::{
     utils::{
         auxiliary::{SharableRef, SharableRefExt},
         esp32_framework_error::Esp32FrameworkError,
+        isr_queues::{ISRQueue, ISRQueueTrait},
+        notification::Notifier,
+        queue_config::queue_capacities,
         timer_driver::{TimerDriver, TimerDriverError},
     },
 };
@@ -23,9 +27,16 @@ use std::{
     },
 };
 
+/// Minimum time between the samples of a waveform. Samples are applied by the update loop, so
+/// shorter periods can not be followed reliably.
+const MIN_WAVEFORM_SAMPLE_PERIOD_US: u64 = 1000;
+/// Minimum amount of samples in each period of a waveform
+const MIN_WAVEFORM_SAMPLES: usize = 2;
+
 /// Enums the different errors possible when working with the analog out
 #[derive(Debug)]
 pub enum AnalogOutError {
+    CommandQueueFull,
     ErrorSettingOutput,
     InvalidArg,
     InvalidPeripheral(PeripheralError),
@@ -48,6 +59,7 @@ enum FixedChangeType {
     Decrease(ExtremeDutyPolicy),
     Increase(ExtremeDutyPolicy),
     None,
+    Waveform,
 }
 
 /// Driver to handle an analog output for a particular pin
@@ -58,6 +70,7 @@ enum FixedChangeType {
 /// - `fixed_change_increasing`: `Arc<AtomicBool>` that indicates if a fixed change on the duty is needed
 /// - `fixed_change_type`: An instance of `FixedChangeType` that indicates the type of duty change
 /// - `amount_of_cycles`: An Option containing an `u32` thath indicates the amount of desired cycles
+/// - `commands`: Queue of the high ratios sent by the AnalogOutHandles, created with the first handle
 struct _AnalogOut<'a> {
     driver: LedcDriver<'a>,
     timer_driver: TimerDriver<'a>,
@@ -66,6 +79,7 @@ struct _AnalogOut<'a> {
     fixed_change_increasing: Arc<AtomicBool>,
     fixed_change_type: FixedChangeType,
     amount_of_cycles: Option<u32>,
+    commands: Option<ISRQueue<f32>>,
 }
 
 /// Driver to handle an analog output for a particular pin.
@@ -73,6 +87,19 @@ pub struct AnalogOut<'a> {
     inner: SharableRef<_AnalogOut<'a>>,
 }
 
+/// Handle to control an AnalogOut from other threads. It can be cloned and sent to other threads,
+/// while the AnalogOut stays on the thread that owns the [crate::Microcontroller]. Commands are
+/// queued and applied in order by the AnalogOut on the next update.
+///
+/// Note: For the commands to be applied, the method [crate::Microcontroller::wait_for_updates] must
+/// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+/// must be used.
+#[derive(Clone)]
+pub struct AnalogOutHandle {
+    commands: ISRQueue<f32>,
+    notifier: Notifier,
+}
+
 /// Wrapper for simple use of an `Arc<AtomicBool>`
 /// in the context of the changinf of the drivers duty
 #[derive(Clone, Debug)]
@@ -213,7 +240,7 @@ impl<'a> _AnalogOut<'a> {
                 config,
             ),
             Peripheral::None => Err(AnalogOutError::InvalidPeripheral(
-                PeripheralError::AlreadyTaken,
+                PeripheralError::AlreadyTaken { owner: None },
             )),
             _ => Err(AnalogOutError::InvalidPeripheral(
                 PeripheralError::NotAPwmTimer,
@@ -228,9 +255,34 @@ impl<'a> _AnalogOut<'a> {
             fixed_change_increasing: Arc::new(AtomicBool::new(false)),
             fixed_change_type: FixedChangeType::None,
             amount_of_cycles: None,
+            commands: None,
         })
     }
 
+    /// Creates a new _AnalogOut from an already configured LedcDriver, keeping its current duty.
+    ///
+    /// # Arguments
+    ///
+    /// - `driver`: The LedcDriver to wrap
+    /// - `timer_driver`: An instance of a TimerDriver
+    ///
+    /// # Returns
+    ///
+    /// The new `_AnalogOut` instance
+    fn from_raw(driver: LedcDriver<'a>, timer_driver: TimerDriver<'a>) -> _AnalogOut<'a> {
+        let duty = driver.get_duty();
+        _AnalogOut {
+            driver,
+            timer_driver,
+            duty: Arc::new(AtomicU32::new(duty)),
+            change_duty_update: ChangeDutyUpdate::new(),
+            fixed_change_increasing: Arc::new(AtomicBool::new(false)),
+            fixed_change_type: FixedChangeType::None,
+            amount_of_cycles: None,
+            commands: None,
+        }
+    }
+
     /// Creates a new _AnalogOut with a default frecuency of 1000Hz and a resolution of 8bits.
     ///
     /// # Arguments
@@ -344,7 +396,7 @@ impl<'a> _AnalogOut<'a> {
             }
             Peripheral::None => {
                 return Err(AnalogOutError::InvalidPeripheral(
-                    PeripheralError::AlreadyTaken,
+                    PeripheralError::AlreadyTaken { owner: None },
                 ))
             }
             _ => {
@@ -736,6 +788,82 @@ impl<'a> _AnalogOut<'a> {
         true
     }
 
+    /// Starts generating a periodic waveform, changing the high level ratio on each sample. The
+    /// output is a PWM signal, since the microcontroller has no DAC, so an RC low pass filter with a
+    /// cutoff well below the PWM frequency and above the waveform frequency is needed to get an
+    /// analog signal. Calling [Self::set_high_level_output_ratio] or starting another automatic
+    /// change stops the waveform.
+    ///
+    /// Note: The samples are applied by the update loop, so the method
+    /// [crate::Microcontroller::wait_for_updates] must be called periodicly, unless using an async
+    /// aproach in which case [crate::Microcontroller::block_on] must be used. This limits the time
+    /// between samples to at least 1 ms, so `frequency_hz * samples_per_period` can be at most 1000.
+    ///
+    /// # Arguments
+    ///
+    /// - `waveform`: The shape of the signal
+    /// - `frequency_hz`: The frequency of the waveform, in Hz
+    /// - `samples_per_period`: The amount of samples in each period, at least 2. Custom tables are
+    ///   resampled to this length.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation is successful, or an `AnalogOutError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogOutError::InvalidArg`: If the frequency is not positive, there are less than 2
+    ///   samples, the time between samples is less than 1 ms or the custom table is not valid
+    /// - `AnalogOutError::ErrorSettingOutput`: If setting the first sample fails
+    /// - `AnalogOutError::TimerDriverError`: If the timer driver cannot be enabled.
+    pub fn start_waveform(
+        &mut self,
+        waveform: &Waveform,
+        frequency_hz: f32,
+        samples_per_period: usize,
+    ) -> Result<(), AnalogOutError> {
+        if !frequency_hz.is_finite()
+            || frequency_hz <= 0.0
+            || samples_per_period < MIN_WAVEFORM_SAMPLES
+        {
+            return Err(AnalogOutError::InvalidArg);
+        }
+        let sample_period_us = 1_000_000.0 / (frequency_hz * samples_per_period as f32);
+        if sample_period_us < MIN_WAVEFORM_SAMPLE_PERIOD_US as f32 {
+            return Err(AnalogOutError::InvalidArg);
+        }
+        let max_duty = self.driver.get_max_duty();
+        let duties: Vec<u32> = waveform
+            .samples(samples_per_period)?
+            .into_iter()
+            .map(|ratio| duty_from_high_ratio(max_duty, ratio))
+            .collect();
+
+        self.fixed_change_type = FixedChangeType::None;
+        self.timer_driver.remove_interrupt()?;
+        self.duty.store(duties[0], Ordering::SeqCst);
+        self.driver
+            .set_duty(duties[0])
+            .map_err(|_| AnalogOutError::ErrorSettingOutput)?;
+
+        let mut change_duty_update_ref = self.change_duty_update.clone();
+        let duty_ref = self.duty.clone();
+        let mut index = 0;
+        let callback = move || {
+            index = (index + 1) % duties.len();
+            duty_ref.store(duties[index], Ordering::SeqCst);
+            change_duty_update_ref.change_duty();
+        };
+
+        self.timer_driver
+            .interrupt_after_n_times(sample_period_us as u64, None, true, callback);
+        self.timer_driver
+            .enable()
+            .map_err(AnalogOutError::TimerDriverError)?;
+        self.fixed_change_type = FixedChangeType::Waveform;
+        Ok(())
+    }
+
     /// Handler for InterruptUpdate::ChangeDuty, depending on the ExtremeDutyPolicy
     ///
     /// # Returns
@@ -765,6 +893,7 @@ impl<'a> _AnalogOut<'a> {
                     self.driver.get_duty() < self.driver.get_max_duty()
                 }
                 FixedChangeType::Decrease(ExtremeDutyPolicy::None) => self.driver.get_duty() > 0,
+                FixedChangeType::Waveform => true,
                 _ => false,
             }
         }
@@ -781,7 +910,23 @@ impl<'a> _AnalogOut<'a> {
         Ok(())
     }
 
-    /// Handles the diferent type of interrupts.
+    /// Creates a handle to control the signal from other threads
+    ///
+    /// # Returns
+    ///
+    /// A new `AnalogOutHandle` of the pin
+    pub fn handle(&mut self) -> AnalogOutHandle {
+        let commands = self
+            .commands
+            .get_or_insert_with(|| ISRQueue::new(queue_capacities().output_handle))
+            .clone();
+        AnalogOutHandle {
+            commands,
+            notifier: self.timer_driver.notifier(),
+        }
+    }
+
+    /// Handles the diferent type of interrupts, and then applies the high ratios sent by the handles.
     ///
     /// Returns a Result containing an AnalogOutError if an error ocurred.
     ///
@@ -792,6 +937,9 @@ impl<'a> _AnalogOut<'a> {
         if self.change_duty_update.handle_change_duty() {
             self.change_duty_on_cycle()?
         }
+        while let Some(high_ratio) = self.commands.as_mut().and_then(|q| q.try_recv().ok()) {
+            self.set_high_level_output_ratio(high_ratio)?;
+        }
         Ok(())
     }
 }
@@ -867,6 +1015,83 @@ impl<'a> AnalogOut<'a> {
             )?)),
         })
     }
+
+    /// Creates a new AnalogOut from an already configured LedcDriver
+    ///
+    /// # Arguments
+    /// - `driver`: The LedcDriver to wrap
+    /// - `timer_driver`: The TimerDriver instance that will handle the interrupts
+    ///
+    /// # Returns
+    /// The new AnalogOut instance
+    pub(crate) fn from_raw(driver: LedcDriver<'a>, timer_driver: TimerDriver<'a>) -> AnalogOut<'a> {
+        AnalogOut {
+            inner: SharableRef::new_sharable(_AnalogOut::from_raw(driver, timer_driver)),
+        }
+    }
+
+    /// Gives access to the underlying LedcDriver, in order to use esp-idf-hal features not covered
+    /// by the framework. Changing the duty through the driver while a fixed change is running may
+    /// be overwritten by it.
+    ///
+    /// # Arguments
+    /// - `f`: A closure that receives the LedcDriver
+    ///
+    /// # Returns
+    /// The value returned by the closure
+    pub fn with_driver<R, F: FnOnce(&mut LedcDriver<'a>) -> R>(&mut self, f: F) -> R {
+        f(&mut self.inner.deref_mut().driver)
+    }
+}
+
+impl AnalogOutHandle {
+    /// Changes the output signal to be at it maximun.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was sent, or an `AnalogOutError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogOutError::CommandQueueFull`: If the AnalogOut has too many commands to apply
+    pub fn set_high(&mut self) -> Result<(), AnalogOutError> {
+        self.set_high_level_output_ratio(1.0)
+    }
+
+    /// Changes the output signal to be at it minimum.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was sent, or an `AnalogOutError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogOutError::CommandQueueFull`: If the AnalogOut has too many commands to apply
+    pub fn set_low(&mut self) -> Result<(), AnalogOutError> {
+        self.set_high_level_output_ratio(0.0)
+    }
+
+    /// Changes the intensity of the signal using the High-Low level ratio, as
+    /// [AnalogOut::set_high_level_output_ratio] does.
+    ///
+    /// # Arguments
+    ///
+    /// - `high_ratio`: An `f32` representinf the desired high level ratio
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was sent, or an `AnalogOutError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogOutError::CommandQueueFull`: If the AnalogOut has too many commands to apply
+    pub fn set_high_level_output_ratio(&mut self, high_ratio: f32) -> Result<(), AnalogOutError> {
+        self.commands
+            .try_send(high_ratio)
+            .map_err(|_| AnalogOutError::CommandQueueFull)?;
+        self.notifier.notify();
+        Ok(())
+    }
 }
 
 impl<'a> InterruptDriver<'a> for AnalogOut<'a> {
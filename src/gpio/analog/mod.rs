@@ -1,4 +1,9 @@
+mod adc_scanner;
 mod analog_in;
 mod analog_in_pwm;
 mod analog_out;
-pub use {analog_in::*, analog_in_pwm::*, analog_out::*};
+mod ratiometric;
+mod waveform;
+pub use {
+    adc_scanner::*, analog_in::*, analog_in_pwm::*, analog_out::*, ratiometric::*, waveform::*,
+};
@@ -0,0 +1,171 @@
+use super::{AnalogIn, AnalogInError};
+
+/// Enums the references a RatiometricAnalogIn can compare its readings against:
+/// - `Channel`: An AnalogIn that measures the supply of the sensor, through a voltage divider of
+///   the given ratio (1.0 if connected directly). Compensates for the sag of the supply.
+/// - `Fixed`: A fixed supply voltage in millivolts. Readings are calibrated against the internal
+///   reference of the ADC, so only a known regulated supply should be used this way.
+pub enum AdcReference<'a> {
+    Channel { input: AnalogIn<'a>, divider: f32 },
+    Fixed(f32),
+}
+
+/// Combines an AnalogIn with a reference in order to read ratiometric values, that is, the
+/// voltage of the signal as a fraction of the voltage supplying the sensor. Resistive sensors
+/// (thermistors, LDRs, potentiometers) fed from the same supply give the same ratio no matter
+/// the supply voltage, which is key on battery powered boards.
+pub struct RatiometricAnalogIn<'a> {
+    signal: AnalogIn<'a>,
+    reference: AdcReference<'a>,
+    temperature_coefficient: f32,
+    reference_temperature: f32,
+}
+
+impl<'a> AdcReference<'a> {
+    /// Reads the voltage of the reference
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the voltage in millivolts, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::ErrorReading`: If the reference channel could not be read
+    fn read_mv(&mut self) -> Result<f32, AnalogInError> {
+        match self {
+            AdcReference::Channel { input, divider } => Ok(input.read()? as f32 / *divider),
+            AdcReference::Fixed(supply_mv) => Ok(*supply_mv),
+        }
+    }
+}
+
+impl<'a> RatiometricAnalogIn<'a> {
+    /// Creates a new RatiometricAnalogIn without temperature compensation
+    ///
+    /// # Arguments
+    ///
+    /// - `signal`: The AnalogIn connected to the output of the sensor
+    /// - `reference`: The AdcReference of the supply of the sensor
+    ///
+    /// # Returns
+    ///
+    /// The new RatiometricAnalogIn
+    pub fn new(signal: AnalogIn<'a>, reference: AdcReference<'a>) -> Self {
+        RatiometricAnalogIn {
+            signal,
+            reference,
+            temperature_coefficient: 0.0,
+            reference_temperature: 25.0,
+        }
+    }
+
+    /// Sets the temperature coefficient of the sensor, used by [Self::read_compensated_ratio].
+    ///
+    /// # Arguments
+    ///
+    /// - `ppm_per_celsius`: Relative change of the ratio per degree Celsius, in parts per million
+    /// - `reference_celsius`: Temperature at which the sensor has no deviation
+    pub fn set_temperature_coefficient(&mut self, ppm_per_celsius: f32, reference_celsius: f32) {
+        self.temperature_coefficient = ppm_per_celsius / 1_000_000.0;
+        self.reference_temperature = reference_celsius;
+    }
+
+    /// Reads the signal as a fraction of the reference
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the ratio, from 0 to 1, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::ErrorReading`: If a channel could not be read, or the reference is 0
+    pub fn read_ratio(&mut self) -> Result<f32, AnalogInError> {
+        let reference = self.reference.read_mv()?;
+        if reference <= 0.0 {
+            return Err(AnalogInError::ErrorReading);
+        }
+        let signal = self.signal.read()? as f32;
+        Ok((signal / reference).clamp(0.0, 1.0))
+    }
+
+    /// Reads the ratio multiple times and returns the average value, in order to get a more
+    /// stable value.
+    ///
+    /// # Arguments
+    ///
+    /// - `amount_of_samples`: The number of times to read the ratio
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the average ratio, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::ErrorReading`: If a channel could not be read, or the reference is 0
+    pub fn smooth_read_ratio(&mut self, amount_of_samples: u16) -> Result<f32, AnalogInError> {
+        let samples = amount_of_samples.max(1);
+        let mut total = 0.0;
+        for _ in 0..samples {
+            total += self.read_ratio()?;
+        }
+        Ok(total / samples as f32)
+    }
+
+    /// Reads the ratio, removing the deviation caused by the temperature as set with
+    /// [Self::set_temperature_coefficient].
+    ///
+    /// # Arguments
+    ///
+    /// - `temperature_celsius`: The current temperature of the sensor
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the compensated ratio, or an `AnalogInError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::ErrorReading`: If a channel could not be read, or the reference is 0
+    pub fn read_compensated_ratio(
+        &mut self,
+        temperature_celsius: f32,
+    ) -> Result<f32, AnalogInError> {
+        let ratio = self.read_ratio()?;
+        let deviation =
+            1.0 + self.temperature_coefficient * (temperature_celsius - self.reference_temperature);
+        Ok(ratio / deviation)
+    }
+
+    /// Reads the resistance of a sensor that forms a voltage divider with a fixed resistor, the
+    /// signal being the middle point of the divider.
+    ///
+    /// # Arguments
+    ///
+    /// - `fixed_resistor_ohms`: The resistance of the fixed resistor
+    /// - `sensor_on_high_side`: True if the sensor is between the supply and the signal, False if
+    ///   it is between the signal and ground
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the resistance of the sensor in ohms, or an `AnalogInError` if it fails.
+    /// The resistance is infinite if the divider is open.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogInError::ErrorReading`: If a channel could not be read, or the reference is 0
+    pub fn read_resistance(
+        &mut self,
+        fixed_resistor_ohms: f32,
+        sensor_on_high_side: bool,
+    ) -> Result<f32, AnalogInError> {
+        let ratio = self.read_ratio()?;
+        let (numerator, denominator) = if sensor_on_high_side {
+            (1.0 - ratio, ratio)
+        } else {
+            (ratio, 1.0 - ratio)
+        };
+        if denominator <= 0.0 {
+            return Ok(f32::INFINITY);
+        }
+        Ok(fixed_resistor_ohms * numerator / denominator)
+    }
+}
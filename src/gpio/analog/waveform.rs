@@ -0,0 +1,61 @@
+use super::AnalogOutError;
+use std::f32::consts::PI;
+
+/// Enums the shapes of the signals generated with [crate::gpio::analog::AnalogOut::start_waveform].
+/// Each shape goes from a high level ratio of 0 to 1 once per period:
+/// - `Sine`: A sine wave centered on a high level ratio of 0.5.
+/// - `Triangle`: Rises linearly during the first half of the period and falls during the second.
+/// - `Sawtooth`: Rises linearly during the whole period and then drops back to 0.
+/// - `Square`: Stays high during the first half of the period and low during the second.
+/// - `Custom`: A user provided lookup table of high level ratios, from 0 to 1, that is resampled
+///   to the amount of samples per period.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Sawtooth,
+    Square,
+    Custom(Vec<f32>),
+}
+
+impl Waveform {
+    /// Calculates the high level ratios of one period of the waveform
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: The amount of samples of the period
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the high level ratio of each sample, or an `AnalogOutError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogOutError::InvalidArg`: If there are no samples, or the custom table is empty or
+    ///   has values out of the 0 to 1 range
+    pub fn samples(&self, samples: usize) -> Result<Vec<f32>, AnalogOutError> {
+        if samples == 0 {
+            return Err(AnalogOutError::InvalidArg);
+        }
+        if let Waveform::Custom(table) = self {
+            if table.is_empty() || table.iter().any(|ratio| !(0.0..=1.0).contains(ratio)) {
+                return Err(AnalogOutError::InvalidArg);
+            }
+        }
+        Ok((0..samples).map(|i| self.sample_at(i, samples)).collect())
+    }
+
+    /// Calculates the high level ratio of a sample of the period
+    fn sample_at(&self, index: usize, samples: usize) -> f32 {
+        let phase = index as f32 / samples as f32;
+        match self {
+            Waveform::Sine => 0.5 + 0.5 * (2.0 * PI * phase).sin(),
+            Waveform::Triangle if phase < 0.5 => 2.0 * phase,
+            Waveform::Triangle => 2.0 - 2.0 * phase,
+            Waveform::Sawtooth => index as f32 / (samples - 1).max(1) as f32,
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => 0.0,
+            Waveform::Custom(table) => table[index * table.len() / samples],
+        }
+    }
+}
@@ -0,0 +1,476 @@
+use super::DigitalOut;
+use crate::microcontroller_src::peripherals::{Peripheral, PeripheralError};
+use esp_idf_svc::{
+    hal::gpio::{AnyIOPin, Input, InterruptType, PinDriver, Pull},
+    sys::{
+        esp_timer, esp_timer_create, esp_timer_create_args_t, esp_timer_delete,
+        esp_timer_dispatch_t_ESP_TIMER_TASK, esp_timer_get_time, esp_timer_start_once,
+        esp_timer_stop, gpio_intr_enable, gpio_set_level, ESP_OK,
+    },
+};
+use std::{
+    ffi::c_void,
+    ptr,
+    sync::{
+        atomic::{AtomicI32, AtomicPtr, AtomicU32, AtomicU8, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Maximum amount of channels driven by an AcDimmer
+pub const MAX_DIMMER_CHANNELS: usize = 4;
+const MIN_MAINS_FREQUENCY_HZ: u32 = 40;
+const MAX_MAINS_FREQUENCY_HZ: u32 = 70;
+const DEFAULT_GATE_PULSE_US: u32 = 100;
+/// Levels are kept in thousandths, so they can be stored in atomics
+const MAX_LEVEL: u32 = 1000;
+/// Time kept between the end of a gate pulse and the next zero cross, so the triac is never
+/// triggered on the wrong half cycle
+const GATE_MARGIN_US: u32 = 200;
+const UNUSED_GATE: i32 = -1;
+const GATE_IDLE: u8 = 0;
+const GATE_ON: u8 = 1;
+const GATE_DONE: u8 = 2;
+
+/// Enums the different errors possible when working with the AcDimmer
+#[derive(Debug)]
+pub enum AcDimmerError {
+    CannotSetPinAsInput,
+    InterruptError,
+    InvalidChannel,
+    InvalidFrequency,
+    InvalidLevel,
+    InvalidPeripheral(PeripheralError),
+    TimerError(i32),
+    TooManyChannels,
+}
+
+/// A channel of the dimmer, shared with the ISR and the timer:
+/// - `gpio`: Number of the pin of the triac gate, or -1 if the channel is not used.
+/// - `level`: Thousandths of each half cycle the triac conducts.
+/// - `gate`: Whether the gate pulse of the current half cycle is pending, on or done.
+struct DimmerChannel {
+    gpio: AtomicI32,
+    level: AtomicU32,
+    gate: AtomicU8,
+}
+
+/// State of the dimmer, shared with the zero cross ISR and the timer that fires the gates:
+/// - `timer`: The esp_timer that fires the gate pulses.
+/// - `channels`: The channels of the dimmer.
+/// - `nominal_half_period_us`: Half period of the mains frequency set by the user.
+/// - `half_period_us`: Half period measured between the last zero crosses.
+/// - `last_zero_cross_us`: Lower 32 bits of the time of the last zero cross, in microseconds.
+/// - `gate_pulse_us`: Duration of each gate pulse.
+/// - `zero_crosses`: Amount of zero crosses detected, used to know if the mains is present.
+struct DimmerState {
+    timer: AtomicPtr<esp_timer>,
+    channels: [DimmerChannel; MAX_DIMMER_CHANNELS],
+    nominal_half_period_us: u32,
+    half_period_us: AtomicU32,
+    last_zero_cross_us: AtomicU32,
+    gate_pulse_us: AtomicU32,
+    zero_crosses: AtomicU32,
+}
+
+/// Phase control dimmer for AC loads, driving the gates of triacs synchronized with the zero
+/// crosses of the mains. On each zero cross the ISR of the zero cross detector restarts a high
+/// resolution timer, which then fires a short pulse on the gate of each channel, later the lower
+/// the level is. Since the timing is done by the ISR and the esp_timer task, it does not depend on
+/// the update loop of the [crate::Microcontroller].
+///
+/// The level of each channel is the percentage of each half cycle the triac conducts, which is
+/// not linear with the power delivered to the load. Every gate is set low and the timer is
+/// deleted when the AcDimmer is dropped.
+///
+/// Warning: Mains voltage is dangerous. The zero cross detector and the triac gates must be
+/// isolated from the mains, for example with optocouplers.
+pub struct AcDimmer<'a> {
+    zero_cross: PinDriver<'a, AnyIOPin, Input>,
+    gates: Vec<DigitalOut<'a>>,
+    state: Arc<DimmerState>,
+}
+
+impl DimmerChannel {
+    /// Creates a new unused DimmerChannel
+    fn new() -> Self {
+        DimmerChannel {
+            gpio: AtomicI32::new(UNUSED_GATE),
+            level: AtomicU32::new(0),
+            gate: AtomicU8::new(GATE_IDLE),
+        }
+    }
+
+    /// Sets the level of the gate pin, if the channel is used
+    fn set_gate(&self, high: bool) {
+        let gpio = self.gpio.load(Ordering::Acquire);
+        if gpio != UNUSED_GATE {
+            unsafe { gpio_set_level(gpio, high as u32) };
+        }
+    }
+}
+
+impl DimmerState {
+    /// Creates a new DimmerState without channels
+    ///
+    /// # Arguments
+    ///
+    /// - `mains_frequency_hz`: The frequency of the mains
+    ///
+    /// # Returns
+    ///
+    /// The new DimmerState
+    fn new(mains_frequency_hz: u32) -> Self {
+        let half_period_us = 1_000_000 / (2 * mains_frequency_hz);
+        DimmerState {
+            timer: AtomicPtr::new(ptr::null_mut()),
+            channels: std::array::from_fn(|_| DimmerChannel::new()),
+            nominal_half_period_us: half_period_us,
+            half_period_us: AtomicU32::new(half_period_us),
+            last_zero_cross_us: AtomicU32::new(0),
+            gate_pulse_us: AtomicU32::new(DEFAULT_GATE_PULSE_US),
+            zero_crosses: AtomicU32::new(0),
+        }
+    }
+
+    /// Handles a zero cross on the ISR. Crosses closer than half the nominal period to the
+    /// previous one are considered noise and ignored. Otherwise the half period is measured, the
+    /// gates are released and the timer is restarted.
+    fn on_zero_cross(&self) {
+        let now = now_us();
+        let elapsed = now.wrapping_sub(self.last_zero_cross_us.load(Ordering::Acquire));
+        if elapsed < self.nominal_half_period_us / 2 {
+            return;
+        }
+        if elapsed < self.nominal_half_period_us * 3 / 2 {
+            self.half_period_us.store(elapsed, Ordering::Release);
+        }
+        self.last_zero_cross_us.store(now, Ordering::Release);
+        self.zero_crosses.fetch_add(1, Ordering::Relaxed);
+        for channel in &self.channels {
+            if channel.gate.swap(GATE_IDLE, Ordering::AcqRel) == GATE_ON {
+                channel.set_gate(false);
+            }
+        }
+        self.start_timer(1);
+    }
+
+    /// Handles the timer, firing and releasing the gates whose time has come, and restarting the
+    /// timer for the next of them
+    fn on_timer(&self) {
+        let since_zero_cross =
+            now_us().wrapping_sub(self.last_zero_cross_us.load(Ordering::Acquire));
+        let half_period = self.half_period_us.load(Ordering::Acquire);
+        let pulse = self.gate_pulse_us.load(Ordering::Relaxed);
+        let mut next_event: Option<u32> = None;
+        for channel in &self.channels {
+            let level = channel.level.load(Ordering::Acquire);
+            let Some(fire_at) = fire_delay(level, half_period, pulse) else {
+                continue;
+            };
+            let event = match channel.gate.load(Ordering::Acquire) {
+                GATE_IDLE if since_zero_cross >= fire_at => {
+                    channel.set_gate(true);
+                    channel.gate.store(GATE_ON, Ordering::Release);
+                    fire_at + pulse
+                }
+                GATE_IDLE => fire_at,
+                GATE_ON if since_zero_cross >= fire_at + pulse => {
+                    channel.set_gate(false);
+                    channel.gate.store(GATE_DONE, Ordering::Release);
+                    continue;
+                }
+                GATE_ON => fire_at + pulse,
+                _ => continue,
+            };
+            next_event = Some(next_event.map_or(event, |next| next.min(event)));
+        }
+        if let Some(event) = next_event {
+            self.start_timer(event.saturating_sub(since_zero_cross).max(1));
+        }
+    }
+
+    /// Restarts the timer so it fires after `delay_us` microseconds
+    fn start_timer(&self, delay_us: u32) {
+        let timer = self.timer.load(Ordering::Acquire);
+        if timer.is_null() {
+            return;
+        }
+        unsafe {
+            esp_timer_stop(timer);
+            esp_timer_start_once(timer, delay_us as u64);
+        }
+    }
+}
+
+impl<'a> AcDimmer<'a> {
+    /// Creates a new AcDimmer, without channels and stopped
+    ///
+    /// # Arguments
+    ///
+    /// - `zero_cross_per`: The Peripheral of the pin of the zero cross detector, which must have a
+    ///   rising edge on each zero cross
+    /// - `mains_frequency_hz`: The nominal frequency of the mains, usually 50 or 60 Hz
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new AcDimmer, or an `AcDimmerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AcDimmerError::InvalidFrequency`: If the frequency is not between 40 and 70 Hz
+    /// - `AcDimmerError::InvalidPeripheral`: If the peripheral is not a pin, or was already taken
+    /// - `AcDimmerError::CannotSetPinAsInput`: If the pin can not be set as an input with a pull up
+    /// - `AcDimmerError::InterruptError`: If the zero cross interrupt can not be set
+    /// - `AcDimmerError::TimerError`: If the timer of the gates can not be created
+    pub(crate) fn new(
+        zero_cross_per: Peripheral,
+        mains_frequency_hz: u32,
+    ) -> Result<AcDimmer<'a>, AcDimmerError> {
+        if !(MIN_MAINS_FREQUENCY_HZ..=MAX_MAINS_FREQUENCY_HZ).contains(&mains_frequency_hz) {
+            return Err(AcDimmerError::InvalidFrequency);
+        }
+        let gpio = zero_cross_per
+            .into_any_io_pin()
+            .map_err(AcDimmerError::InvalidPeripheral)?;
+        let mut zero_cross =
+            PinDriver::input(gpio).map_err(|_| AcDimmerError::CannotSetPinAsInput)?;
+        zero_cross
+            .set_pull(Pull::Up)
+            .map_err(|_| AcDimmerError::CannotSetPinAsInput)?;
+        zero_cross
+            .set_interrupt_type(InterruptType::PosEdge)
+            .map_err(|_| AcDimmerError::InterruptError)?;
+
+        let state = Arc::new(DimmerState::new(mains_frequency_hz));
+        let args = esp_timer_create_args_t {
+            callback: Some(gate_timer_callback),
+            arg: Arc::as_ptr(&state) as *mut c_void,
+            dispatch_method: esp_timer_dispatch_t_ESP_TIMER_TASK,
+            name: b"ac_dimmer\0".as_ptr() as *const _,
+            skip_unhandled_events: true,
+        };
+        let mut timer = ptr::null_mut();
+        let result = unsafe { esp_timer_create(&args, &mut timer) };
+        if result != ESP_OK {
+            return Err(AcDimmerError::TimerError(result));
+        }
+        state.timer.store(timer, Ordering::Release);
+
+        let isr_state = state.clone();
+        let zero_cross_gpio = zero_cross.pin();
+        // esp-idf-hal disables the interrupt before running the callback, so it is enabled
+        // again from the ISR itself instead of waiting for the update loop
+        let callback = move || {
+            isr_state.on_zero_cross();
+            unsafe { gpio_intr_enable(zero_cross_gpio) };
+        };
+        unsafe { zero_cross.subscribe(callback) }.map_err(|_| AcDimmerError::InterruptError)?;
+
+        Ok(AcDimmer {
+            zero_cross,
+            gates: Vec::new(),
+            state,
+        })
+    }
+
+    /// Adds a channel driven by a triac, starting off
+    ///
+    /// # Arguments
+    ///
+    /// - `gate`: The DigitalOut connected to the gate of the triac, through its optocoupler
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the index of the channel, or an `AcDimmerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AcDimmerError::TooManyChannels`: If the dimmer already has [MAX_DIMMER_CHANNELS] channels
+    pub fn add_channel(&mut self, mut gate: DigitalOut<'a>) -> Result<usize, AcDimmerError> {
+        let index = self.gates.len();
+        let channel = self
+            .state
+            .channels
+            .get(index)
+            .ok_or(AcDimmerError::TooManyChannels)?;
+        let gpio = gate.with_driver(|driver| {
+            _ = driver.set_low();
+            driver.pin()
+        });
+        channel.level.store(0, Ordering::Release);
+        channel.gpio.store(gpio, Ordering::Release);
+        self.gates.push(gate);
+        Ok(index)
+    }
+
+    /// Sets the level of a channel. The change is applied on the next half cycle.
+    ///
+    /// # Arguments
+    ///
+    /// - `channel`: The index of the channel, as returned by [Self::add_channel]
+    /// - `percentage`: The percentage of each half cycle the triac conducts, from 0 to 100
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the level was set, or an `AcDimmerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AcDimmerError::InvalidChannel`: If there is no channel with that index
+    /// - `AcDimmerError::InvalidLevel`: If the percentage is not between 0 and 100
+    pub fn set_level(&mut self, channel: usize, percentage: f32) -> Result<(), AcDimmerError> {
+        if !(0.0..=100.0).contains(&percentage) {
+            return Err(AcDimmerError::InvalidLevel);
+        }
+        let level = (percentage * MAX_LEVEL as f32 / 100.0).round() as u32;
+        self.channel(channel)?.level.store(level, Ordering::Release);
+        Ok(())
+    }
+
+    /// Gets the level of a channel
+    ///
+    /// # Arguments
+    ///
+    /// - `channel`: The index of the channel, as returned by [Self::add_channel]
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the percentage of each half cycle the triac conducts, or an `AcDimmerError`
+    /// if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AcDimmerError::InvalidChannel`: If there is no channel with that index
+    pub fn level(&self, channel: usize) -> Result<f32, AcDimmerError> {
+        let level = self.channel(channel)?.level.load(Ordering::Acquire);
+        Ok(level as f32 * 100.0 / MAX_LEVEL as f32)
+    }
+
+    /// Sets the duration of the gate pulses. Longer pulses are needed by some triacs and
+    /// inductive loads. By default pulses last 100 us.
+    ///
+    /// # Arguments
+    ///
+    /// - `pulse`: The duration of each gate pulse
+    pub fn set_gate_pulse(&mut self, pulse: Duration) {
+        let pulse_us = pulse
+            .as_micros()
+            .min(self.state.nominal_half_period_us as u128) as u32;
+        self.state
+            .gate_pulse_us
+            .store(pulse_us.max(1), Ordering::Relaxed);
+    }
+
+    /// Gets the frequency of the mains measured between the last zero crosses
+    ///
+    /// # Returns
+    ///
+    /// The frequency in Hz, or the nominal one if no zero crosses were measured yet
+    pub fn mains_frequency(&self) -> f32 {
+        500_000.0 / self.state.half_period_us.load(Ordering::Acquire) as f32
+    }
+
+    /// Gets the amount of zero crosses detected since the dimmer was created. If it stops
+    /// increasing while the dimmer is started, the mains or the detector are not working.
+    ///
+    /// # Returns
+    ///
+    /// The amount of zero crosses, which wraps around on overflow
+    pub fn zero_crosses(&self) -> u32 {
+        self.state.zero_crosses.load(Ordering::Relaxed)
+    }
+
+    /// Starts detecting zero crosses and firing the gates
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the dimmer started, or an `AcDimmerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AcDimmerError::InterruptError`: If the zero cross interrupt can not be enabled
+    pub fn start(&mut self) -> Result<(), AcDimmerError> {
+        self.zero_cross
+            .enable_interrupt()
+            .map_err(|_| AcDimmerError::InterruptError)
+    }
+
+    /// Stops detecting zero crosses and sets every gate low, so every channel is off until the
+    /// dimmer is started again
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the dimmer stopped, or an `AcDimmerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AcDimmerError::InterruptError`: If the zero cross interrupt can not be disabled
+    pub fn stop(&mut self) -> Result<(), AcDimmerError> {
+        self.zero_cross
+            .disable_interrupt()
+            .map_err(|_| AcDimmerError::InterruptError)?;
+        unsafe { esp_timer_stop(self.state.timer.load(Ordering::Acquire)) };
+        for channel in &self.state.channels {
+            channel.gate.store(GATE_DONE, Ordering::Release);
+            channel.set_gate(false);
+        }
+        Ok(())
+    }
+
+    /// Gets a used channel
+    fn channel(&self, channel: usize) -> Result<&DimmerChannel, AcDimmerError> {
+        if channel >= self.gates.len() {
+            return Err(AcDimmerError::InvalidChannel);
+        }
+        Ok(&self.state.channels[channel])
+    }
+}
+
+impl Drop for AcDimmer<'_> {
+    /// Removes the zero cross interrupt, deletes the timer and sets every gate low
+    fn drop(&mut self) {
+        _ = self.zero_cross.unsubscribe();
+        let timer = self.state.timer.swap(ptr::null_mut(), Ordering::AcqRel);
+        unsafe {
+            esp_timer_stop(timer);
+            esp_timer_delete(timer);
+        }
+        for channel in &self.state.channels {
+            channel.set_gate(false);
+        }
+    }
+}
+
+/// Callback of the esp_timer of the gates, which receives the DimmerState as argument
+unsafe extern "C" fn gate_timer_callback(arg: *mut c_void) {
+    let state = unsafe { &*(arg as *const DimmerState) };
+    state.on_timer();
+}
+
+/// Calculates when the gate of a channel is fired, counted from the zero cross. The pulse always
+/// ends before the next zero cross.
+///
+/// # Arguments
+///
+/// - `level`: Thousandths of the half cycle the triac conducts
+/// - `half_period`: The half period of the mains, in microseconds
+/// - `pulse`: The duration of the gate pulse, in microseconds
+///
+/// # Returns
+///
+/// An `Option` with the microseconds after the zero cross, or None if the channel is off
+fn fire_delay(level: u32, half_period: u32, pulse: u32) -> Option<u32> {
+    if level == 0 {
+        return None;
+    }
+    let delay =
+        (half_period as u64 * (MAX_LEVEL - level.min(MAX_LEVEL)) as u64 / MAX_LEVEL as u64) as u32;
+    Some(delay.min(half_period.saturating_sub(pulse + GATE_MARGIN_US)))
+}
+
+/// Gets the lower 32 bits of the time since boot, in microseconds
+fn now_us() -> u32 {
+    unsafe { esp_timer_get_time() as u32 }
+}
@@ -0,0 +1,416 @@
+use super::DigitalIn;
+use crate::{
+    microcontroller_src::interrupt_driver::InterruptDriver,
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        timer_driver::{TimerDriver, TimerDriverError},
+    },
+};
+use esp_idf_svc::hal::gpio::Level;
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+const DEFAULT_POLL_PERIOD: Duration = Duration::from_millis(10);
+const DEFAULT_DEBOUNCE_SAMPLES: u8 = 3;
+
+/// Enums the different errors possible when working with the ButtonManager
+#[derive(Debug)]
+pub enum ButtonManagerError {
+    EmptyCombination,
+    InvalidButton,
+    TimerDriverError(TimerDriverError),
+}
+
+/// High level events detected by the ButtonManager. Buttons, chords and sequences are
+/// identified by the id returned when they were added:
+/// - `Pressed`: A button was pressed.
+/// - `Released`: A button was released.
+/// - `Chord`: Every button of a chord was held down together for the chord hold time.
+/// - `Sequence`: The buttons of a sequence were pressed in order, within the max gap between presses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Pressed(usize),
+    Released(usize),
+    Chord(usize),
+    Sequence(usize),
+}
+
+/// A button tracked by the ButtonManager, with its debounced state
+struct TrackedButton<'a> {
+    input: DigitalIn<'a>,
+    pressed_level: Level,
+    pressed: bool,
+    stable_samples: u8,
+}
+
+/// A combination of buttons that must be held down together
+struct Chord {
+    buttons: Vec<usize>,
+    hold: Duration,
+    held_since: Option<Instant>,
+    fired: bool,
+}
+
+/// An ordered combination of buttons that must be pressed one after the other
+struct Sequence {
+    buttons: Vec<usize>,
+    max_gap: Duration,
+}
+
+/// Tracks several buttons, debouncing them by polling, and detects chords (buttons held down
+/// together) and sequences (buttons pressed in order), firing an InputEvent for each of them
+/// through the update loop.
+struct _ButtonManager<'a> {
+    timer_driver: TimerDriver<'a>,
+    poll_period: Duration,
+    poll_due: Arc<AtomicBool>,
+    debounce_samples: u8,
+    buttons: Vec<TrackedButton<'a>>,
+    chords: Vec<Chord>,
+    sequences: Vec<Sequence>,
+    presses: VecDeque<(usize, Instant)>,
+    user_callback: Box<dyn FnMut(InputEvent) + 'a>,
+}
+
+/// Tracks several buttons, debouncing them by polling, and detects chords (buttons held down
+/// together) and sequences (buttons pressed in order), firing an InputEvent for each of them
+/// through the update loop.
+pub struct ButtonManager<'a> {
+    inner: SharableRef<_ButtonManager<'a>>,
+}
+
+impl Chord {
+    /// Checks if the chord fires, given the buttons currently pressed
+    fn update(&mut self, buttons: &[TrackedButton], now: Instant) -> bool {
+        if !self.buttons.iter().all(|b| buttons[*b].pressed) {
+            self.held_since = None;
+            self.fired = false;
+            return false;
+        }
+        let held_since = *self.held_since.get_or_insert(now);
+        if !self.fired && now.duration_since(held_since) >= self.hold {
+            self.fired = true;
+            return true;
+        }
+        false
+    }
+}
+
+impl Sequence {
+    /// Checks if the last presses complete the sequence
+    fn matches(&self, presses: &VecDeque<(usize, Instant)>) -> bool {
+        if presses.len() < self.buttons.len() {
+            return false;
+        }
+        let last_presses = presses.range(presses.len() - self.buttons.len()..);
+        let mut previous: Option<Instant> = None;
+        for ((button, pressed_at), expected) in last_presses.zip(&self.buttons) {
+            if button != expected {
+                return false;
+            }
+            if previous.is_some_and(|p| pressed_at.duration_since(p) > self.max_gap) {
+                return false;
+            }
+            previous = Some(*pressed_at);
+        }
+        true
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _ButtonManager<'a> {
+    /// Creates a new _ButtonManager without buttons
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used to poll the buttons
+    ///
+    /// # Returns
+    ///
+    /// The new _ButtonManager
+    fn new(timer_driver: TimerDriver<'a>) -> Self {
+        _ButtonManager {
+            timer_driver,
+            poll_period: DEFAULT_POLL_PERIOD,
+            poll_due: Arc::new(AtomicBool::new(false)),
+            debounce_samples: DEFAULT_DEBOUNCE_SAMPLES,
+            buttons: vec![],
+            chords: vec![],
+            sequences: vec![],
+            presses: VecDeque::new(),
+            user_callback: Box::new(|_| {}),
+        }
+    }
+
+    /// Adds a button to be tracked.
+    ///
+    /// # Arguments
+    ///
+    /// - `input`: The DigitalIn of the button, with its pull already set
+    /// - `pressed_level`: The level of the pin while the button is pressed
+    ///
+    /// # Returns
+    ///
+    /// The id of the button, used in chords, sequences and events
+    pub fn add_button(&mut self, input: DigitalIn<'a>, pressed_level: Level) -> usize {
+        let pressed = input.get_level() == pressed_level;
+        self.buttons.push(TrackedButton {
+            input,
+            pressed_level,
+            pressed,
+            stable_samples: 0,
+        });
+        self.buttons.len() - 1
+    }
+
+    /// Adds a chord, fired once every time all of its buttons are held down together for the hold time.
+    ///
+    /// # Arguments
+    ///
+    /// - `buttons`: The ids of the buttons of the chord
+    /// - `hold`: Time the buttons must be held down together. Use `Duration::ZERO` to fire as soon as they are
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the id of the chord, or a `ButtonManagerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ButtonManagerError::EmptyCombination`: If no buttons were received.
+    /// - `ButtonManagerError::InvalidButton`: If any button id was not added to the manager.
+    pub fn add_chord(
+        &mut self,
+        buttons: &[usize],
+        hold: Duration,
+    ) -> Result<usize, ButtonManagerError> {
+        self.validate_buttons(buttons)?;
+        self.chords.push(Chord {
+            buttons: buttons.to_vec(),
+            hold,
+            held_since: None,
+            fired: false,
+        });
+        Ok(self.chords.len() - 1)
+    }
+
+    /// Adds a sequence, fired when its buttons are pressed in order.
+    ///
+    /// # Arguments
+    ///
+    /// - `buttons`: The ids of the buttons of the sequence, in order
+    /// - `max_gap`: Max time between two consecutive presses of the sequence
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the id of the sequence, or a `ButtonManagerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ButtonManagerError::EmptyCombination`: If no buttons were received.
+    /// - `ButtonManagerError::InvalidButton`: If any button id was not added to the manager.
+    pub fn add_sequence(
+        &mut self,
+        buttons: &[usize],
+        max_gap: Duration,
+    ) -> Result<usize, ButtonManagerError> {
+        self.validate_buttons(buttons)?;
+        self.sequences.push(Sequence {
+            buttons: buttons.to_vec(),
+            max_gap,
+        });
+        Ok(self.sequences.len() - 1)
+    }
+
+    /// Sets the callback executed for each InputEvent.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives each InputEvent
+    ///
+    /// # Returns
+    ///
+    /// The _ButtonManager itself
+    pub fn on_event<C: FnMut(InputEvent) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.user_callback = Box::new(callback);
+        self
+    }
+
+    /// Sets how often the buttons are polled and how many equal samples are needed for a change
+    /// to be accepted. By default buttons are polled every 10 ms and need 3 samples. Changes are
+    /// applied the next time the manager is started.
+    ///
+    /// # Arguments
+    ///
+    /// - `poll_period`: Time between polls
+    /// - `samples`: Amount of consecutive equal samples needed to accept a change
+    ///
+    /// # Returns
+    ///
+    /// The _ButtonManager itself
+    pub fn set_debounce(&mut self, poll_period: Duration, samples: u8) -> &mut Self {
+        self.poll_period = poll_period;
+        self.debounce_samples = samples.max(1);
+        self
+    }
+
+    /// Starts polling the buttons
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the polling started, or a `ButtonManagerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ButtonManagerError::TimerDriverError`: If the timer driver could not be enabled.
+    pub fn start(&mut self) -> Result<(), ButtonManagerError> {
+        let poll_due = self.poll_due.clone();
+        self.timer_driver.interrupt_after_n_times(
+            self.poll_period.as_micros().try_into().unwrap_or(u64::MAX),
+            None,
+            true,
+            move || poll_due.store(true, Ordering::SeqCst),
+        );
+        Ok(self.timer_driver.enable()?)
+    }
+
+    /// Stops polling the buttons
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the polling stopped, or a `ButtonManagerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ButtonManagerError::TimerDriverError`: If the timer driver could not be disabled.
+    pub fn stop(&mut self) -> Result<(), ButtonManagerError> {
+        Ok(self.timer_driver.disable()?)
+    }
+
+    /// Checks whether a button is currently pressed, after debouncing
+    ///
+    /// # Arguments
+    ///
+    /// - `button`: The id of the button
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the button is pressed, false if the id is invalid
+    pub fn is_pressed(&self, button: usize) -> bool {
+        self.buttons.get(button).is_some_and(|b| b.pressed)
+    }
+
+    /// Checks that every id belongs to a button of the manager
+    fn validate_buttons(&self, buttons: &[usize]) -> Result<(), ButtonManagerError> {
+        if buttons.is_empty() {
+            return Err(ButtonManagerError::EmptyCombination);
+        }
+        if buttons.iter().any(|b| *b >= self.buttons.len()) {
+            return Err(ButtonManagerError::InvalidButton);
+        }
+        Ok(())
+    }
+
+    /// Polls every button, and detects the chords and sequences completed since the last poll
+    fn poll(&mut self) {
+        let now = Instant::now();
+        let mut events = vec![];
+        for (id, button) in self.buttons.iter_mut().enumerate() {
+            let pressed = button.input.get_level() == button.pressed_level;
+            if pressed == button.pressed {
+                button.stable_samples = 0;
+                continue;
+            }
+            button.stable_samples += 1;
+            if button.stable_samples < self.debounce_samples {
+                continue;
+            }
+            button.stable_samples = 0;
+            button.pressed = pressed;
+            if pressed {
+                events.push(InputEvent::Pressed(id));
+            } else {
+                events.push(InputEvent::Released(id));
+            }
+        }
+
+        let max_sequence_len = self.sequences.iter().map(|s| s.buttons.len()).max();
+        for event in events.clone() {
+            if let (InputEvent::Pressed(id), Some(max_len)) = (event, max_sequence_len) {
+                self.presses.push_back((id, now));
+                while self.presses.len() > max_len {
+                    self.presses.pop_front();
+                }
+                let completed = self.sequences.iter().position(|s| s.matches(&self.presses));
+                if let Some(sequence) = completed {
+                    self.presses.clear();
+                    events.push(InputEvent::Sequence(sequence));
+                }
+            }
+        }
+
+        for (id, chord) in self.chords.iter_mut().enumerate() {
+            if chord.update(&self.buttons, now) {
+                events.push(InputEvent::Chord(id));
+            }
+        }
+
+        for event in events {
+            (self.user_callback)(event);
+        }
+    }
+
+    /// Polls the buttons when it is due
+    fn _update_interrupt(&mut self) {
+        if self.poll_due.swap(false, Ordering::SeqCst) {
+            self.poll();
+        }
+    }
+}
+
+impl<'a> ButtonManager<'a> {
+    /// Creates a new ButtonManager without buttons
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used to poll the buttons
+    ///
+    /// # Returns
+    ///
+    /// The new ButtonManager
+    pub(crate) fn new(timer_driver: TimerDriver<'a>) -> Self {
+        ButtonManager {
+            inner: SharableRef::new_sharable(_ButtonManager::new(timer_driver)),
+        }
+    }
+}
+
+impl<'a> InterruptDriver<'a> for ButtonManager<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut()._update_interrupt();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+impl From<TimerDriverError> for ButtonManagerError {
+    fn from(value: TimerDriverError) -> Self {
+        ButtonManagerError::TimerDriverError(value)
+    }
+}
@@ -0,0 +1,461 @@
+use super::{DigitalIn, DigitalInError, DigitalOut};
+use crate::{
+    microcontroller_src::interrupt_driver::InterruptDriver,
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        timer_driver::{TimerDriver, TimerDriverError},
+    },
+};
+use esp_idf_svc::hal::gpio::Pull;
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const DEFAULT_SAMPLE_PERIOD: Duration = Duration::from_millis(20);
+const DEFAULT_SAMPLES: u16 = 16;
+const DEFAULT_THRESHOLD: f32 = 0.2;
+/// Weight of each new reading on the baseline, so it follows slow drifts of temperature and
+/// humidity but not touches
+const BASELINE_WEIGHT: f32 = 0.02;
+/// Maximum amount of polls waiting for the receive pin to change, after which the pad is
+/// considered disconnected
+const MAX_POLLS: u32 = 100_000;
+
+type PadCallback<'a> = dyn FnMut() + 'a;
+
+/// Enums the different errors possible when working with the CapacitiveSensor
+#[derive(Debug)]
+pub enum CapacitiveSensorError {
+    DigitalInError(DigitalInError),
+    InvalidArg,
+    InvalidPad,
+    NoPads,
+    TimerDriverError(TimerDriverError),
+}
+
+/// A pad measured by the CapacitiveSensor:
+/// - `send`: The pin that charges and discharges the pad through the resistor.
+/// - `receive`: The pin connected to the pad, which follows the charge.
+/// - `latest`: The latest reading, or None if the pad was not read yet or did not respond.
+/// - `baseline`: The reading of the pad while not touched.
+/// - `threshold`: Increase over the baseline, as a ratio, needed to consider the pad touched.
+/// - `touched`: Whether the pad is being touched.
+struct CapacitivePad<'a> {
+    send: DigitalOut<'a>,
+    receive: DigitalIn<'a>,
+    latest: Option<u32>,
+    baseline: Option<f32>,
+    threshold: f32,
+    touched: bool,
+    on_touch: Option<Box<PadCallback<'a>>>,
+    on_release: Option<Box<PadCallback<'a>>>,
+}
+
+/// Software capacitive sensing for chips without touch pads, such as the ESP32-C6. Each pad uses
+/// a pair of pins: the send pin is connected to the pad through a resistor of 1 to 10 MOhm, and
+/// the receive pin directly to the pad. The time the pad takes to charge and discharge through
+/// the resistor grows when it is touched. The readings are compared against a baseline that
+/// slowly follows the environment, and callbacks are executed when a pad is touched or released,
+/// through the update loop.
+struct _CapacitiveSensor<'a> {
+    timer_driver: TimerDriver<'a>,
+    sample_period: Duration,
+    samples: u16,
+    sample_due: Arc<AtomicBool>,
+    pads: Vec<CapacitivePad<'a>>,
+}
+
+/// Software capacitive sensing for chips without touch pads, such as the ESP32-C6. Each pad uses
+/// a pair of pins: the send pin is connected to the pad through a resistor of 1 to 10 MOhm, and
+/// the receive pin directly to the pad. The time the pad takes to charge and discharge through
+/// the resistor grows when it is touched. The readings are compared against a baseline that
+/// slowly follows the environment, and callbacks are executed when a pad is touched or released,
+/// through the update loop.
+pub struct CapacitiveSensor<'a> {
+    inner: SharableRef<_CapacitiveSensor<'a>>,
+}
+
+impl<'a> CapacitivePad<'a> {
+    /// Measures the pad, charging and discharging it `samples` times
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the sum of the polls every charge and discharge took, or None if the
+    /// receive pin did not follow the send pin
+    fn measure(&mut self, samples: u16) -> Option<u32> {
+        self.send.set_low().ok()?;
+        self.wait_for_level(false)?;
+        let mut total: u32 = 0;
+        for _ in 0..samples {
+            self.send.set_high().ok()?;
+            total = total.saturating_add(self.wait_for_level(true)?);
+            self.send.set_low().ok()?;
+            total = total.saturating_add(self.wait_for_level(false)?);
+        }
+        Some(total)
+    }
+
+    /// Polls the receive pin until it reaches a level
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the amount of polls, or None if the level was not reached in time
+    fn wait_for_level(&self, high: bool) -> Option<u32> {
+        (0..MAX_POLLS).find(|_| self.receive.is_high() == high)
+    }
+
+    /// Measures the pad, updates its baseline while it is not touched, and executes the callbacks
+    /// when it is touched or released. Released uses half the threshold, so noise near the
+    /// threshold does not toggle the state.
+    fn sample(&mut self, samples: u16) {
+        self.latest = self.measure(samples);
+        let Some(reading) = self.latest.map(|reading| reading as f32) else {
+            return;
+        };
+        let baseline = *self.baseline.get_or_insert(reading);
+        let increase = (reading - baseline) / baseline.max(1.0);
+
+        if !self.touched && increase >= self.threshold {
+            self.touched = true;
+            if let Some(callback) = self.on_touch.as_mut() {
+                callback();
+            }
+        } else if self.touched && increase < self.threshold / 2.0 {
+            self.touched = false;
+            if let Some(callback) = self.on_release.as_mut() {
+                callback();
+            }
+        }
+        if !self.touched {
+            self.baseline = Some(baseline + (reading - baseline) * BASELINE_WEIGHT);
+        }
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _CapacitiveSensor<'a> {
+    /// Creates a new _CapacitiveSensor without pads
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used to pace the readings
+    ///
+    /// # Returns
+    ///
+    /// The new _CapacitiveSensor
+    fn new(timer_driver: TimerDriver<'a>) -> Self {
+        _CapacitiveSensor {
+            timer_driver,
+            sample_period: DEFAULT_SAMPLE_PERIOD,
+            samples: DEFAULT_SAMPLES,
+            sample_due: Arc::new(AtomicBool::new(false)),
+            pads: vec![],
+        }
+    }
+
+    /// Adds a pad to be measured. The pull of the receive pin is removed, and its baseline is
+    /// taken from the first reading, so the pad must not be touched when the sensor starts.
+    ///
+    /// # Arguments
+    ///
+    /// - `send`: The DigitalOut connected to the pad through the resistor
+    /// - `receive`: The DigitalIn connected directly to the pad
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the id of the pad, used to set its callbacks, or a `CapacitiveSensorError`
+    /// if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::DigitalInError`: If the pull of the receive pin can not be removed
+    pub fn add_pad(
+        &mut self,
+        send: DigitalOut<'a>,
+        mut receive: DigitalIn<'a>,
+    ) -> Result<usize, CapacitiveSensorError> {
+        receive
+            .set_pull(Pull::Floating)
+            .map_err(CapacitiveSensorError::DigitalInError)?;
+        self.pads.push(CapacitivePad {
+            send,
+            receive,
+            latest: None,
+            baseline: None,
+            threshold: DEFAULT_THRESHOLD,
+            touched: false,
+            on_touch: None,
+            on_release: None,
+        });
+        Ok(self.pads.len() - 1)
+    }
+
+    /// Sets the time between two readings of every pad. By default pads are read every 20 ms.
+    /// Changes are applied the next time the sensor is started.
+    ///
+    /// # Arguments
+    ///
+    /// - `period`: Time between two readings
+    ///
+    /// # Returns
+    ///
+    /// The _CapacitiveSensor itself
+    pub fn set_sample_period(&mut self, period: Duration) -> &mut Self {
+        self.sample_period = period;
+        self
+    }
+
+    /// Sets the amount of times each pad is charged and discharged on each reading. More samples
+    /// reduce the noise, but block the update loop for longer. By default 16 samples are taken.
+    ///
+    /// # Arguments
+    ///
+    /// - `samples`: The amount of samples, at least 1
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the amount was set, or a `CapacitiveSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::InvalidArg`: If the amount is 0
+    pub fn set_samples(&mut self, samples: u16) -> Result<(), CapacitiveSensorError> {
+        if samples == 0 {
+            return Err(CapacitiveSensorError::InvalidArg);
+        }
+        self.samples = samples;
+        Ok(())
+    }
+
+    /// Sets how much a reading must grow over the baseline to consider the pad touched. The pad
+    /// is released when the increase falls under half the threshold. By default it is 0.2, a 20%
+    /// increase.
+    ///
+    /// # Arguments
+    ///
+    /// - `pad`: The id of the pad
+    /// - `threshold`: The increase over the baseline, as a ratio greater than 0
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the threshold was set, or a `CapacitiveSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::InvalidPad`: If the id was not added to the sensor
+    /// - `CapacitiveSensorError::InvalidArg`: If the threshold is not greater than 0
+    pub fn set_threshold(
+        &mut self,
+        pad: usize,
+        threshold: f32,
+    ) -> Result<(), CapacitiveSensorError> {
+        if !threshold.is_finite() || threshold <= 0.0 {
+            return Err(CapacitiveSensorError::InvalidArg);
+        }
+        self.get_pad(pad)?.threshold = threshold;
+        Ok(())
+    }
+
+    /// Sets the callback executed when a pad is touched.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `pad`: The id of the pad
+    /// - `callback`: A closure executed when the pad is touched
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the callback was set, or a `CapacitiveSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::InvalidPad`: If the id was not added to the sensor
+    pub fn on_touch<C: FnMut() + 'a>(
+        &mut self,
+        pad: usize,
+        callback: C,
+    ) -> Result<(), CapacitiveSensorError> {
+        self.get_pad(pad)?.on_touch = Some(Box::new(callback));
+        Ok(())
+    }
+
+    /// Sets the callback executed when a pad is released.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `pad`: The id of the pad
+    /// - `callback`: A closure executed when the pad is released
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the callback was set, or a `CapacitiveSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::InvalidPad`: If the id was not added to the sensor
+    pub fn on_release<C: FnMut() + 'a>(
+        &mut self,
+        pad: usize,
+        callback: C,
+    ) -> Result<(), CapacitiveSensorError> {
+        self.get_pad(pad)?.on_release = Some(Box::new(callback));
+        Ok(())
+    }
+
+    /// Checks whether a pad is being touched
+    ///
+    /// # Arguments
+    ///
+    /// - `pad`: The id of the pad
+    ///
+    /// # Returns
+    ///
+    /// True if the pad is touched, False if it is not or the id is invalid
+    pub fn is_touched(&self, pad: usize) -> bool {
+        self.pads.get(pad).map_or(false, |pad| pad.touched)
+    }
+
+    /// Gets the latest reading and the baseline of a pad, useful to choose its threshold
+    ///
+    /// # Arguments
+    ///
+    /// - `pad`: The id of the pad
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the latest reading and the baseline, or None if the id is invalid, the
+    /// pad was not read yet, or it did not respond on the last reading
+    pub fn reading(&self, pad: usize) -> Option<(u32, f32)> {
+        let pad = self.pads.get(pad)?;
+        Some((pad.latest?, pad.baseline?))
+    }
+
+    /// Discards the baseline of a pad, so it is taken again from the next reading. Useful when
+    /// the surroundings of the pad change suddenly.
+    ///
+    /// # Arguments
+    ///
+    /// - `pad`: The id of the pad
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the baseline was discarded, or a `CapacitiveSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::InvalidPad`: If the id was not added to the sensor
+    pub fn recalibrate(&mut self, pad: usize) -> Result<(), CapacitiveSensorError> {
+        let pad = self.get_pad(pad)?;
+        pad.baseline = None;
+        pad.touched = false;
+        Ok(())
+    }
+
+    /// Starts measuring the pads
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the measuring started, or a `CapacitiveSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::NoPads`: If no pads were added.
+    /// - `CapacitiveSensorError::TimerDriverError`: If the timer driver could not be enabled.
+    pub fn start(&mut self) -> Result<(), CapacitiveSensorError> {
+        if self.pads.is_empty() {
+            return Err(CapacitiveSensorError::NoPads);
+        }
+        let sample_due = self.sample_due.clone();
+        self.timer_driver.interrupt_after_n_times(
+            self.sample_period
+                .as_micros()
+                .try_into()
+                .unwrap_or(u64::MAX),
+            None,
+            true,
+            move || sample_due.store(true, Ordering::SeqCst),
+        );
+        Ok(self.timer_driver.enable()?)
+    }
+
+    /// Stops measuring the pads, keeping their baselines
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the measuring stopped, or a `CapacitiveSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::TimerDriverError`: If the timer driver could not be disabled.
+    pub fn stop(&mut self) -> Result<(), CapacitiveSensorError> {
+        Ok(self.timer_driver.disable()?)
+    }
+
+    /// Gets a pad of the sensor
+    fn get_pad(&mut self, pad: usize) -> Result<&mut CapacitivePad<'a>, CapacitiveSensorError> {
+        self.pads
+            .get_mut(pad)
+            .ok_or(CapacitiveSensorError::InvalidPad)
+    }
+
+    /// Measures every pad when it is due
+    fn _update_interrupt(&mut self) {
+        if !self.sample_due.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        let samples = self.samples;
+        for pad in &mut self.pads {
+            pad.sample(samples);
+        }
+    }
+}
+
+impl<'a> CapacitiveSensor<'a> {
+    /// Creates a new CapacitiveSensor without pads
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used to pace the readings
+    ///
+    /// # Returns
+    ///
+    /// The new CapacitiveSensor
+    pub(crate) fn new(timer_driver: TimerDriver<'a>) -> Self {
+        CapacitiveSensor {
+            inner: SharableRef::new_sharable(_CapacitiveSensor::new(timer_driver)),
+        }
+    }
+}
+
+impl<'a> InterruptDriver<'a> for CapacitiveSensor<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut()._update_interrupt();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+impl From<TimerDriverError> for CapacitiveSensorError {
+    fn from(value: TimerDriverError) -> Self {
+        CapacitiveSensorError::TimerDriverError(value)
+    }
+}
@@ -605,6 +605,16 @@ impl<'a> InterruptDriver<'a> for DigitalIn<'a> {
             inner: self.inner.clone(),
         })
     }
+
+    /// Unsubscribes the interrupt of the pin, so the callback is no longer executed
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner
+            .deref_mut()
+            .pin_driver
+            .unsubscribe()
+            .map_err(DigitalInError::from_enable_disable_errors)?;
+        Ok(())
+    }
 }
 
 impl From<TimerDriverError> for DigitalInError {
@@ -4,6 +4,9 @@ use crate::{
     utils::{
         auxiliary::{SharableRef, SharableRefExt},
         esp32_framework_error::Esp32FrameworkError,
+        isr_queues::{ISRQueue, ISRQueueTrait},
+        notification::Notifier,
+        queue_config::queue_capacities,
         timer_driver::{TimerDriver, TimerDriverError},
     },
 };
@@ -20,6 +23,7 @@ type AtomicInterruptUpdateCode = AtomicU8;
 #[derive(Debug)]
 pub enum DigitalOutError {
     CannotSetPinAsOutput,
+    CommandQueueFull,
     InvalidPin,
     InvalidPeripheral(PeripheralError),
     TimerDriverError(TimerDriverError),
@@ -29,10 +33,12 @@ pub enum DigitalOutError {
 /// - `pin_driver`: A PinDriver instance that handles the output signals
 /// - `timer_driver`: A TimerDriver instance
 /// - `interrupt_update_code`: An `Arc<AtomicInterruptUpdateCode>` to handle interrupts
+/// - `commands`: Queue of the commands sent by the DigitalOutHandles, created with the first handle
 struct _DigitalOut<'a> {
     pin_driver: PinDriver<'a, AnyIOPin, Output>,
     timer_driver: TimerDriver<'a>,
     interrupt_update_code: Arc<AtomicInterruptUpdateCode>,
+    commands: Option<ISRQueue<DigitalOutCommand>>,
 }
 
 /// Driver to handle a digital output for a particular Pin
@@ -40,6 +46,27 @@ pub struct DigitalOut<'a> {
     inner: SharableRef<_DigitalOut<'a>>,
 }
 
+/// Handle to control a DigitalOut from other threads. It can be cloned and sent to other threads,
+/// while the DigitalOut stays on the thread that owns the [crate::Microcontroller]. Commands are
+/// queued and applied in order by the DigitalOut on the next update.
+///
+/// Note: For the commands to be applied, the method [crate::Microcontroller::wait_for_updates] must
+/// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+/// must be used.
+#[derive(Clone)]
+pub struct DigitalOutHandle {
+    commands: ISRQueue<DigitalOutCommand>,
+    notifier: Notifier,
+}
+
+/// Commands sent by a DigitalOutHandle to its DigitalOut
+#[derive(Clone, Copy)]
+enum DigitalOutCommand {
+    SetLevel(Level),
+    Toggle,
+    Blink(u32, u64),
+}
+
 /// After an interrupt is triggered an InterruptUpdate will be set and handled
 enum InterruptUpdate {
     Blink,
@@ -126,9 +153,32 @@ impl<'a> _DigitalOut<'a> {
             pin_driver,
             timer_driver,
             interrupt_update_code: Arc::from(InterruptUpdate::None.get_atomic_code()),
+            commands: None,
         })
     }
 
+    /// Creates a new `_DigitalOut` from an already configured PinDriver.
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A `TimerDriver<'a>` instance to manage the timing operations.
+    /// - `pin_driver`: The output PinDriver to wrap.
+    ///
+    /// # Returns
+    ///
+    /// The new `_DigitalOut` instance.
+    fn from_raw(
+        timer_driver: TimerDriver<'a>,
+        pin_driver: PinDriver<'a, AnyIOPin, Output>,
+    ) -> _DigitalOut<'a> {
+        _DigitalOut {
+            pin_driver,
+            timer_driver,
+            interrupt_update_code: Arc::from(InterruptUpdate::None.get_atomic_code()),
+            commands: None,
+        }
+    }
+
     /// Sets the pin level to either `High` or `Low`.
     ///
     /// # Arguments
@@ -247,7 +297,24 @@ impl<'a> _DigitalOut<'a> {
             .map_err(DigitalOutError::TimerDriverError)
     }
 
-    /// Handles the diferent type of interrupts and reenabling the interrupt when necesary
+    /// Creates a handle to control the pin from other threads
+    ///
+    /// # Returns
+    ///
+    /// A new `DigitalOutHandle` of the pin
+    pub fn handle(&mut self) -> DigitalOutHandle {
+        let commands = self
+            .commands
+            .get_or_insert_with(|| ISRQueue::new(queue_capacities().output_handle))
+            .clone();
+        DigitalOutHandle {
+            commands,
+            notifier: self.timer_driver.notifier(),
+        }
+    }
+
+    /// Handles the diferent type of interrupts and reenabling the interrupt when necesary. Then
+    /// applies the commands sent by the handles.
     ///
     /// # Returns
     ///
@@ -256,19 +323,29 @@ impl<'a> _DigitalOut<'a> {
     /// # Errors
     ///
     /// - `DigitalOutError::InvalidPin`: If the pin level cannot be toggled.
+    /// - `DigitalOutError::TimerDriverError`: If a blink sent by a handle could not be started.
     fn _update_interrupt(&mut self) -> Result<(), DigitalOutError> {
         let interrupt_update = InterruptUpdate::from_atomic_code(&self.interrupt_update_code);
         self.interrupt_update_code
             .store(InterruptUpdate::None.get_code(), Ordering::SeqCst);
 
         match interrupt_update {
-            InterruptUpdate::Blink => self.toggle(),
-            InterruptUpdate::None => Ok(()),
+            InterruptUpdate::Blink => self.toggle()?,
+            InterruptUpdate::None => {}
         }
+
+        while let Some(command) = self.commands.as_mut().and_then(|q| q.try_recv().ok()) {
+            match command {
+                DigitalOutCommand::SetLevel(level) => self.set_level(level)?,
+                DigitalOutCommand::Toggle => self.toggle()?,
+                DigitalOutCommand::Blink(amount, micro) => self.blink(amount, micro)?,
+            }
+        }
+        Ok(())
     }
 }
 
-impl DigitalOut<'_> {
+impl<'a> DigitalOut<'a> {
     /// Creates a new `DigitalOut` for a specified pin.
     ///
     /// # Arguments
@@ -285,13 +362,141 @@ impl DigitalOut<'_> {
     /// - `DigitalOutError::InvalidPeripheral`: If the peripheral cannot be converted into an AnyIOPin.
     /// - `DigitalOutError::CannotSetPinAsOutput`: If the pin cannot be set as an output.
     pub(crate) fn new(
-        timer_driver: TimerDriver,
+        timer_driver: TimerDriver<'a>,
         per: Peripheral,
-    ) -> Result<DigitalOut, DigitalOutError> {
+    ) -> Result<DigitalOut<'a>, DigitalOutError> {
         Ok(DigitalOut {
             inner: SharableRef::new_sharable(_DigitalOut::new(timer_driver, per)?),
         })
     }
+
+    /// Creates a new `DigitalOut` from an already configured PinDriver.
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver<'a> instance to manage the timing operations.
+    /// - `pin_driver`: The output PinDriver to wrap.
+    ///
+    /// # Returns
+    ///
+    /// The new `DigitalOut` instance.
+    pub(crate) fn from_raw(
+        timer_driver: TimerDriver<'a>,
+        pin_driver: PinDriver<'a, AnyIOPin, Output>,
+    ) -> DigitalOut<'a> {
+        DigitalOut {
+            inner: SharableRef::new_sharable(_DigitalOut::from_raw(timer_driver, pin_driver)),
+        }
+    }
+
+    /// Gives access to the underlying PinDriver, in order to use esp-idf-hal features not covered
+    /// by the framework, such as the drive strength.
+    ///
+    /// # Arguments
+    ///
+    /// - `f`: A closure that receives the PinDriver.
+    ///
+    /// # Returns
+    ///
+    /// The value returned by the closure.
+    pub fn with_driver<R, F: FnOnce(&mut PinDriver<'a, AnyIOPin, Output>) -> R>(
+        &mut self,
+        f: F,
+    ) -> R {
+        f(&mut self.inner.deref_mut().pin_driver)
+    }
+}
+
+impl DigitalOutHandle {
+    /// Sets the pin level to either `High` or `Low`.
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: A Level value to set the pin to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `DigitalOutError` if the operation fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DigitalOutError::CommandQueueFull`: If the DigitalOut has too many commands to apply.
+    pub fn set_level(&mut self, level: Level) -> Result<(), DigitalOutError> {
+        self.send(DigitalOutCommand::SetLevel(level))
+    }
+
+    /// Sets the pin level to `High`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `DigitalOutError` if the operation fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DigitalOutError::CommandQueueFull`: If the DigitalOut has too many commands to apply.
+    pub fn set_high(&mut self) -> Result<(), DigitalOutError> {
+        self.set_level(Level::High)
+    }
+
+    /// Sets the pin level to `Low`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `DigitalOutError` if the operation fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DigitalOutError::CommandQueueFull`: If the DigitalOut has too many commands to apply.
+    pub fn set_low(&mut self) -> Result<(), DigitalOutError> {
+        self.set_level(Level::Low)
+    }
+
+    /// Changes the pin level.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `DigitalOutError` if the operation fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DigitalOutError::CommandQueueFull`: If the DigitalOut has too many commands to apply.
+    pub fn toggle(&mut self) -> Result<(), DigitalOutError> {
+        self.send(DigitalOutCommand::Toggle)
+    }
+
+    /// Makes the pin blink, as [DigitalOut::blink] does.
+    ///
+    /// # Arguments
+    ///
+    /// * `amount_of_blinks` - Amount of times the pin will blink
+    /// * `time_between_states_micro` - Time between each state change in micro seconds
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or a `DigitalOutError` if the operation fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DigitalOutError::CommandQueueFull`: If the DigitalOut has too many commands to apply.
+    pub fn blink(
+        &mut self,
+        amount_of_blinks: u32,
+        time_between_states_micro: u64,
+    ) -> Result<(), DigitalOutError> {
+        self.send(DigitalOutCommand::Blink(
+            amount_of_blinks,
+            time_between_states_micro,
+        ))
+    }
+
+    /// Queues a command and wakes up the [crate::Microcontroller] to apply it
+    fn send(&mut self, command: DigitalOutCommand) -> Result<(), DigitalOutError> {
+        self.commands
+            .try_send(command)
+            .map_err(|_| DigitalOutError::CommandQueueFull)?;
+        self.notifier.notify();
+        Ok(())
+    }
 }
 
 impl<'a> InterruptDriver<'a> for DigitalOut<'a> {
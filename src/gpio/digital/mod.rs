@@ -1,3 +1,6 @@
+mod ac_dimmer;
+mod button_manager;
+mod capacitive_sensor;
 mod digital_in;
 mod digital_out;
-pub use {digital_in::*, digital_out::*};
+pub use {ac_dimmer::*, button_manager::*, capacitive_sensor::*, digital_in::*, digital_out::*};
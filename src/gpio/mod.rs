@@ -1,2 +1,3 @@
 pub mod analog;
 pub mod digital;
+pub mod status_led;
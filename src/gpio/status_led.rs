@@ -0,0 +1,463 @@
+use super::{
+    analog::{AnalogOut, AnalogOutError},
+    digital::{DigitalOut, DigitalOutError},
+};
+use crate::{
+    ble::BleServer,
+    microcontroller_src::interrupt_driver::InterruptDriver,
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        timer_driver::{TimerDriver, TimerDriverError},
+    },
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const ERROR_CODE_PULSE: Duration = Duration::from_millis(200);
+const ERROR_CODE_PAUSE: Duration = Duration::from_millis(1500);
+
+/// Enums the different errors possible when working with a StatusLed
+#[derive(Debug)]
+pub enum StatusLedError {
+    AnalogOutError(AnalogOutError),
+    DigitalOutError(DigitalOutError),
+    OutputError,
+    TimerDriverError(TimerDriverError),
+}
+
+/// A color shown by a StatusLed. Outputs that can not show colors use the brightest
+/// component as their brightness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+/// Named states of the device, each one shown with its own LedPattern:
+/// - `Off`: The led is turned off.
+/// - `Idle`: The device is working but has nothing to report.
+/// - `Provisioning`: The device is waiting to be configured.
+/// - `Connecting`: The device is connecting to a network.
+/// - `Connected`: The device is connected.
+/// - `Advertising`: The device is advertising and waiting for connections.
+/// - `Updating`: The device is updating its firmware.
+/// - `Error`: An error code, by default shown as that amount of red pulses.
+/// - `Custom`: A state defined by the user, turned off unless a pattern is set for it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceState {
+    Off,
+    Idle,
+    Provisioning,
+    Connecting,
+    Connected,
+    Advertising,
+    Updating,
+    Error(u8),
+    Custom(String),
+}
+
+/// A sequence of colors, each one shown for a duration, that repeats while its state is active
+#[derive(Debug, Clone, PartialEq)]
+pub struct LedPattern {
+    steps: Vec<(Color, Duration)>,
+}
+
+/// An output capable of showing the colors of a StatusLed. It is implemented for `DigitalOut`
+/// and `AnalogOut`, and can be implemented for other leds such as a NeoPixel.
+pub trait StatusLedOutput {
+    /// Shows a color on the output
+    ///
+    /// # Arguments
+    ///
+    /// - `color`: The color to show
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the color was shown, or a `StatusLedError` if it fails.
+    fn show(&mut self, color: Color) -> Result<(), StatusLedError>;
+}
+
+/// Driver that owns a led and shows the state of the device through blinking and color patterns
+struct _StatusLed<'a> {
+    output: Box<dyn StatusLedOutput + 'a>,
+    timer_driver: TimerDriver<'a>,
+    patterns: HashMap<DeviceState, LedPattern>,
+    state: DeviceState,
+    step: usize,
+    step_due: Arc<AtomicBool>,
+}
+
+/// Driver that owns a led and shows the state of the device through blinking and color patterns
+pub struct StatusLed<'a> {
+    inner: SharableRef<_StatusLed<'a>>,
+}
+
+impl Color {
+    pub const OFF: Color = Color::new(0, 0, 0);
+    pub const WHITE: Color = Color::new(255, 255, 255);
+    pub const RED: Color = Color::new(255, 0, 0);
+    pub const GREEN: Color = Color::new(0, 255, 0);
+    pub const BLUE: Color = Color::new(0, 0, 255);
+    pub const YELLOW: Color = Color::new(255, 255, 0);
+    pub const CYAN: Color = Color::new(0, 255, 255);
+    pub const MAGENTA: Color = Color::new(255, 0, 255);
+
+    /// Creates a new Color
+    ///
+    /// # Arguments
+    ///
+    /// - `red`: The red component
+    /// - `green`: The green component
+    /// - `blue`: The blue component
+    ///
+    /// # Returns
+    ///
+    /// The new Color
+    pub const fn new(red: u8, green: u8, blue: u8) -> Self {
+        Color { red, green, blue }
+    }
+
+    /// Gets the brightness of the color, given by its brightest component
+    ///
+    /// # Returns
+    ///
+    /// A `f32` between 0 and 1
+    pub fn brightness(&self) -> f32 {
+        self.red.max(self.green).max(self.blue) as f32 / u8::MAX as f32
+    }
+}
+
+impl LedPattern {
+    /// Creates a pattern that keeps the led turned off
+    ///
+    /// # Returns
+    ///
+    /// The new LedPattern
+    pub fn off() -> Self {
+        Self::solid(Color::OFF)
+    }
+
+    /// Creates a pattern that shows a color without blinking
+    ///
+    /// # Arguments
+    ///
+    /// - `color`: The color to show
+    ///
+    /// # Returns
+    ///
+    /// The new LedPattern
+    pub fn solid(color: Color) -> Self {
+        LedPattern {
+            steps: vec![(color, Duration::ZERO)],
+        }
+    }
+
+    /// Creates a pattern that blinks a color
+    ///
+    /// # Arguments
+    ///
+    /// - `color`: The color to show while on
+    /// - `on`: Time the led stays on
+    /// - `off`: Time the led stays off
+    ///
+    /// # Returns
+    ///
+    /// The new LedPattern
+    pub fn blink(color: Color, on: Duration, off: Duration) -> Self {
+        LedPattern {
+            steps: vec![(color, on), (Color::OFF, off)],
+        }
+    }
+
+    /// Creates a pattern that shows an error code as an amount of pulses followed by a pause
+    ///
+    /// # Arguments
+    ///
+    /// - `color`: The color of the pulses
+    /// - `code`: The amount of pulses
+    ///
+    /// # Returns
+    ///
+    /// The new LedPattern
+    pub fn error_code(color: Color, code: u8) -> Self {
+        if code == 0 {
+            return Self::solid(color);
+        }
+        let mut steps = vec![];
+        for _ in 0..code {
+            steps.push((color, ERROR_CODE_PULSE));
+            steps.push((Color::OFF, ERROR_CODE_PULSE));
+        }
+        if let Some(last) = steps.last_mut() {
+            last.1 = ERROR_CODE_PAUSE;
+        }
+        LedPattern { steps }
+    }
+
+    /// Creates a pattern from a sequence of colors and the time each one is shown
+    ///
+    /// # Arguments
+    ///
+    /// - `steps`: The colors and their durations. An empty sequence turns the led off
+    ///
+    /// # Returns
+    ///
+    /// The new LedPattern
+    pub fn sequence(steps: Vec<(Color, Duration)>) -> Self {
+        if steps.is_empty() {
+            return Self::off();
+        }
+        LedPattern { steps }
+    }
+
+    /// Gets the default pattern of a state
+    fn default_for(state: &DeviceState) -> Self {
+        match state {
+            DeviceState::Off | DeviceState::Custom(_) => Self::off(),
+            DeviceState::Idle => Self::blink(
+                Color::GREEN,
+                Duration::from_millis(100),
+                Duration::from_millis(1900),
+            ),
+            DeviceState::Provisioning => Self::blink(
+                Color::BLUE,
+                Duration::from_millis(500),
+                Duration::from_millis(500),
+            ),
+            DeviceState::Connecting => Self::blink(
+                Color::YELLOW,
+                Duration::from_millis(100),
+                Duration::from_millis(100),
+            ),
+            DeviceState::Connected => Self::solid(Color::GREEN),
+            DeviceState::Advertising => Self::blink(
+                Color::BLUE,
+                Duration::from_millis(100),
+                Duration::from_millis(900),
+            ),
+            DeviceState::Updating => Self::blink(
+                Color::MAGENTA,
+                Duration::from_millis(250),
+                Duration::from_millis(250),
+            ),
+            DeviceState::Error(code) => Self::error_code(Color::RED, *code),
+        }
+    }
+}
+
+impl StatusLedOutput for DigitalOut<'_> {
+    fn show(&mut self, color: Color) -> Result<(), StatusLedError> {
+        if color.brightness() > 0.0 {
+            Ok(self.set_high()?)
+        } else {
+            Ok(self.set_low()?)
+        }
+    }
+}
+
+impl StatusLedOutput for AnalogOut<'_> {
+    fn show(&mut self, color: Color) -> Result<(), StatusLedError> {
+        Ok(self.set_high_level_output_ratio(color.brightness())?)
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _StatusLed<'a> {
+    /// Creates a new _StatusLed in the `Off` state
+    ///
+    /// # Arguments
+    ///
+    /// - `output`: The led used to show the state
+    /// - `timer_driver`: A TimerDriver used to change between the steps of the patterns
+    ///
+    /// # Returns
+    ///
+    /// The new _StatusLed
+    fn new<O: StatusLedOutput + 'a>(output: O, timer_driver: TimerDriver<'a>) -> Self {
+        _StatusLed {
+            output: Box::new(output),
+            timer_driver,
+            patterns: HashMap::new(),
+            state: DeviceState::Off,
+            step: 0,
+            step_due: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Sets the pattern shown on a state, replacing its default pattern. If the state is
+    /// the current one, the new pattern is shown the next time the state is set.
+    ///
+    /// # Arguments
+    ///
+    /// - `state`: The DeviceState
+    /// - `pattern`: The LedPattern to show while on that state
+    ///
+    /// # Returns
+    ///
+    /// The _StatusLed itself
+    pub fn set_pattern(&mut self, state: DeviceState, pattern: LedPattern) -> &mut Self {
+        self.patterns.insert(state, pattern);
+        self
+    }
+
+    /// Changes the state of the device, starting its pattern from the beginning.
+    ///
+    /// Note: For the pattern to advance, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `state`: The new DeviceState
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the state was shown, or a `StatusLedError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `StatusLedError::TimerDriverError`: If the timer driver could not be set.
+    /// - Any error returned by the output while showing the first color of the pattern.
+    pub fn set_state(&mut self, state: DeviceState) -> Result<(), StatusLedError> {
+        self.timer_driver.disable()?;
+        self.step_due.store(false, Ordering::SeqCst);
+        self.state = state;
+        self.step = 0;
+        self.show_step()
+    }
+
+    /// Gets the current state of the device
+    ///
+    /// # Returns
+    ///
+    /// The current DeviceState
+    pub fn get_state(&self) -> DeviceState {
+        self.state.clone()
+    }
+
+    /// Shows the current step of the pattern of the state, and schedules the next one
+    fn show_step(&mut self) -> Result<(), StatusLedError> {
+        let pattern = self
+            .patterns
+            .get(&self.state)
+            .cloned()
+            .unwrap_or_else(|| LedPattern::default_for(&self.state));
+        self.step %= pattern.steps.len();
+        let (color, duration) = pattern.steps[self.step];
+        self.output.show(color)?;
+
+        if pattern.steps.len() > 1 {
+            let step_due = self.step_due.clone();
+            self.timer_driver.interrupt_after(
+                duration.as_micros().try_into().unwrap_or(u64::MAX),
+                move || step_due.store(true, Ordering::SeqCst),
+            );
+            self.timer_driver.enable()?;
+        }
+        Ok(())
+    }
+
+    /// Advances the pattern to the next step when its time has come
+    fn _update_interrupt(&mut self) -> Result<(), StatusLedError> {
+        if !self.step_due.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.step += 1;
+        self.show_step()
+    }
+}
+
+impl<'a> StatusLed<'a> {
+    /// Creates a new StatusLed in the `Off` state
+    ///
+    /// # Arguments
+    ///
+    /// - `output`: The led used to show the state
+    /// - `timer_driver`: A TimerDriver used to change between the steps of the patterns
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new StatusLed, or a `StatusLedError` if the led could not be turned off.
+    pub(crate) fn new<O: StatusLedOutput + 'a>(
+        output: O,
+        timer_driver: TimerDriver<'a>,
+    ) -> Result<Self, StatusLedError> {
+        let mut status_led = StatusLed {
+            inner: SharableRef::new_sharable(_StatusLed::new(output, timer_driver)),
+        };
+        status_led.set_state(DeviceState::Off)?;
+        Ok(status_led)
+    }
+
+    /// Makes the led follow the connections of a BleServer. It shows `Connected` while there is
+    /// at least one client, and `Advertising` otherwise. This replaces the connection and
+    /// disconnection handlers of the server.
+    ///
+    /// # Arguments
+    ///
+    /// - `server`: The BleServer to follow
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the led shows the current state of the server, or a `StatusLedError` if it fails.
+    pub fn follow_ble_server(&mut self, server: &mut BleServer<'a>) -> Result<(), StatusLedError> {
+        let mut connected_led = StatusLed {
+            inner: self.inner.clone(),
+        };
+        server.connection_handler(move |_, _| {
+            _ = connected_led.set_state(DeviceState::Connected);
+        });
+        let mut disconnected_led = StatusLed {
+            inner: self.inner.clone(),
+        };
+        server.disconnect_handler(move |server, _| {
+            if server.amount_of_clients() == 0 {
+                _ = disconnected_led.set_state(DeviceState::Advertising);
+            }
+        });
+        match server.amount_of_clients() {
+            0 => self.set_state(DeviceState::Advertising),
+            _ => self.set_state(DeviceState::Connected),
+        }
+    }
+}
+
+impl<'a> InterruptDriver<'a> for StatusLed<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut()._update_interrupt()?;
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+impl From<AnalogOutError> for StatusLedError {
+    fn from(value: AnalogOutError) -> Self {
+        StatusLedError::AnalogOutError(value)
+    }
+}
+
+impl From<DigitalOutError> for StatusLedError {
+    fn from(value: DigitalOutError) -> Self {
+        StatusLedError::DigitalOutError(value)
+    }
+}
+
+impl From<TimerDriverError> for StatusLedError {
+    fn from(value: TimerDriverError) -> Self {
+        StatusLedError::TimerDriverError(value)
+    }
+}
@@ -5,22 +5,30 @@
 #![test_runner(test_runner_mod::esp_test_runner)]
 esp32_testing_macro::use_esp32_tests!(crate::esp_test);
 
+pub mod audio;
 pub mod ble;
 pub mod gpio;
 mod microcontroller_src;
+pub mod peer;
 pub mod sensors;
 pub mod serial;
+pub mod storage;
+pub mod touch;
+pub mod ui;
 pub mod utils; //TODO private this
 pub mod wifi;
 pub mod external_peripheral {
     pub use super::microcontroller_src::external_peripheral::UseOfExternalPeripheralsExt;
-    pub use super::microcontroller_src::peripherals::Peripheral;
+    pub use super::microcontroller_src::peripherals::{
+        Peripheral, PeripheralError, PeripheralUsage,
+    };
 }
 
 pub(crate) use microcontroller_src::interrupt_driver::InterruptDriver;
 
-pub use microcontroller_src::Microcontroller;
+pub use microcontroller_src::{Microcontroller, ShutdownMode};
 pub use utils::esp32_framework_error;
+pub use utils::system_clock;
 pub use utils::timer_driver;
 
 mod esp_test_runner;
@@ -15,4 +15,15 @@ pub(crate) trait InterruptDriver<'a> {
     /// This function returns an updater of the Interrupt driver. This may be a reference to the original
     /// driver or a completly diferent struct that implements the `InterruptDriver` trait
     fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a>;
+
+    /// This function will be called by [crate::Microcontroller::shutdown] in order to stop the work the driver
+    /// does on its own, such as interrupts, scans or advertisements. By default the driver has nothing to stop.
+    ///
+    /// #Returns
+    ///
+    /// If the driver stopped `Ok(())` is returned. If not `Err(Esp32FrameworkError)` with the corresponding
+    /// driver error type is returned.
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        Ok(())
+    }
 }
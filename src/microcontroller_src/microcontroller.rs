@@ -1,36 +1,62 @@
+#[cfg(feature = "virtual-time")]
+use crate::utils::{timer_driver::advance_virtual_time_step, virtual_time};
+#[cfg(feature = "mdns")]
+use crate::wifi::{Mdns, MdnsError};
 use crate::{
+    audio::{I2SError, I2SInput, I2SOutput},
     ble::{
         utils::{Security, Service},
-        BleBeacon, BleClient, BleError, BleServer,
+        BleBeacon, BleClient, BleError, BleServer, PresenceMonitor,
+    },
+    gpio::{
+        analog::*,
+        digital::*,
+        status_led::{StatusLed, StatusLedError, StatusLedOutput},
     },
-    gpio::{analog::*, digital::*},
     microcontroller_src::{interrupt_driver::InterruptDriver, peripherals::*},
+    peer::{EspNow, EspNowError, PeerError, PeerLink},
     serial::{i2c::*, uart::*},
+    storage::{SdCard, SdCardError},
     timer_driver::TimerDriverError,
+    touch::{TouchController, TouchError, Touchscreen},
     utils::{
         auxiliary::{SharableRef, SharableRefExt},
         esp32_framework_error::{AdcDriverError, Esp32FrameworkError},
+        isr_log,
         notification::{Notification, Notifier},
         timer_driver::TimerDriver,
     },
-    wifi::{WifiDriver, WifiError},
+    wifi::{
+        HttpServer, HttpServerError, MqttClient, MqttError, MqttOptions, SmartConfig,
+        SmartConfigType, WifiDriver, WifiError, WifiSniffer,
+    },
 };
 use attenuation::adc_atten_t;
-use esp32_nimble::{enums::AuthReq, BLEDevice};
+use esp32_nimble::BLEDevice;
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
-    hal::{adc::*, task::block_on},
+    hal::{
+        adc::*,
+        gpio::{AnyIOPin, Output, PinDriver},
+        ledc::LedcDriver,
+        task::block_on,
+    },
 };
 use futures::future::{join, Future};
 use oneshot::AdcDriver;
 use std::{
+    future::poll_fn,
+    io::Write,
+    pin::pin,
     rc::Rc,
     sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
 };
 
 use super::external_peripheral::UseOfExternalPeripheralsExt;
 
 const TIMER_GROUPS: usize = 2;
+const DEFAULT_STARVATION_THRESHOLD: Duration = Duration::from_millis(500);
 
 pub(crate) type SharableAdcDriver<'a> = Rc<AdcDriver<'a, ADC1>>;
 static TAKEN: AtomicBool = AtomicBool::new(false);
@@ -43,6 +69,8 @@ static TAKEN: AtomicBool = AtomicBool::new(false);
 /// - `interrupt_drivers`: A vector of boxed `InterruptDriver` trait objects, representing the drivers responsible for handling hardware interrupts.
 /// - `adc_driver`: An optional shared instance of `SharableAdcDriver`, providing access to the ADC (Analog-to-Digital Converter) for analog input processing.
 /// - `notification`: An instance of `Notification`, used for managing notifications or signaling events within the microcontroller's operation.
+/// - `starvation`: Detector of the user futures that keep the drivers from being updated in [Self::block_on].
+/// - `on_shutdown`: Callback of the user executed first by [Self::shutdown].
 pub struct Microcontroller<'a> {
     peripherals: Peripherals,
     timer_drivers: Vec<TimerDriver<'a>>,
@@ -50,6 +78,29 @@ pub struct Microcontroller<'a> {
     adc_driver: Option<SharableAdcDriver<'a>>,
     notification: Notification,
     event_loop: EspSystemEventLoop,
+    starvation: StarvationDetector<'a>,
+    on_shutdown: Option<Box<dyn FnMut() + 'a>>,
+}
+
+/// Enums what the microcontroller does once [Microcontroller::shutdown] stopped every driver:
+/// - `Halt`: Stays awake, returning from the shutdown.
+/// - `DeepSleep`: Enters deep sleep, waking up after the given time, or only on a reset or on a
+///   wake up source set by the user if None. Waking up from deep sleep restarts the program.
+/// - `Restart`: Restarts the microcontroller.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShutdownMode {
+    Halt,
+    DeepSleep(Option<Duration>),
+    Restart,
+}
+
+/// Measures how long the future of [Microcontroller::block_on] runs each time it is polled. While it
+/// runs the drivers cannot be updated, so polls longer than the threshold are reported.
+/// - `threshold`: Longest poll allowed before reporting it.
+/// - `callback`: Callback executed with the duration of each long poll. If None, a warning is logged.
+struct StarvationDetector<'a> {
+    threshold: Duration,
+    callback: Option<Box<dyn FnMut(Duration) + 'a>>,
 }
 
 impl<'a> Microcontroller<'a> {
@@ -69,6 +120,7 @@ impl<'a> Microcontroller<'a> {
         esp_idf_svc::sys::link_patches();
         let mut peripherals = Peripherals::new();
         let notification = Notification::new();
+        isr_log::init(notification.notifier());
         let timer_drivers =
             Microcontroller::initialize_timer_drivers(&mut peripherals, &notification).unwrap();
 
@@ -79,6 +131,8 @@ impl<'a> Microcontroller<'a> {
             adc_driver: None,
             notification,
             event_loop: EspSystemEventLoop::take().expect("Error creating microcontroller"),
+            starvation: StarvationDetector::default(),
+            on_shutdown: None,
         }
     }
 
@@ -120,7 +174,11 @@ impl<'a> Microcontroller<'a> {
     ) -> Result<Vec<TimerDriver<'a>>, TimerDriverError> {
         let mut timer_drivers = Vec::new();
         for i in 0..TIMER_GROUPS {
-            let timer = peripherals.get_timer(i);
+            let timer = peripherals
+                .take_for("TimerDriver", &Peripheral::Timer(i as u8), |p| {
+                    p.get_timer(i)
+                })
+                .map_err(TimerDriverError::InvalidPeripheral)?;
             timer_drivers.push(TimerDriver::new(timer, notification.notifier())?);
         }
         Ok(timer_drivers)
@@ -144,6 +202,17 @@ impl<'a> Microcontroller<'a> {
         Ok(timer_driver_copy)
     }
 
+    /// Lists the pins, timers, PWM channels, UARTs, buses and radios that were taken, with the type of
+    /// the driver that took each one. The driver that holds a peripheral is also named by the
+    /// `PeripheralError::AlreadyTaken` returned when it is requested again.
+    ///
+    /// # Returns
+    ///
+    /// A vector with a PeripheralUsage for each peripheral taken
+    pub fn peripheral_report(&self) -> Vec<PeripheralUsage> {
+        self.peripherals.usage()
+    }
+
     /// Creates a DigitalIn on the ESP pin with number 'pin_num' to read digital inputs.
     ///
     /// # Arguments
@@ -168,7 +237,12 @@ impl<'a> Microcontroller<'a> {
         &mut self,
         pin_num: usize,
     ) -> Result<DigitalIn<'a>, DigitalInError> {
-        let pin_peripheral = self.peripherals.get_digital_pin(pin_num);
+        let pin_peripheral = self
+            .peripherals
+            .take_for("DigitalIn", &Peripheral::Pin(pin_num as u8), |p| {
+                p.get_digital_pin(pin_num)
+            })
+            .map_err(DigitalInError::InvalidPeripheral)?;
         let dgin = DigitalIn::new(
             self.get_timer_driver()?,
             pin_peripheral,
@@ -197,11 +271,148 @@ impl<'a> Microcontroller<'a> {
         &mut self,
         pin_num: usize,
     ) -> Result<DigitalOut<'a>, DigitalOutError> {
-        let pin_peripheral = self.peripherals.get_digital_pin(pin_num);
+        let pin_peripheral = self
+            .peripherals
+            .take_for("DigitalOut", &Peripheral::Pin(pin_num as u8), |p| {
+                p.get_digital_pin(pin_num)
+            })
+            .map_err(DigitalOutError::InvalidPeripheral)?;
         let dgout = DigitalOut::new(self.get_timer_driver()?, pin_peripheral)?;
         Ok(self.keep_updater(dgout))
     }
 
+    /// Wraps an output PinDriver created through esp-idf-hal into a DigitalOut, so it can be used
+    /// with the framework. The pin of the driver is not tracked by the Microcontroller, so it must
+    /// not be requested again through it.
+    ///
+    /// # Arguments
+    ///
+    /// - `pin_driver`: The PinDriver to wrap
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `DigitalOut` instance, or a `DigitalOutError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DigitalOutError::TimerDriverError`: If the TimerDriver could not be created
+    pub fn digital_out_from_raw(
+        &mut self,
+        pin_driver: PinDriver<'a, AnyIOPin, Output>,
+    ) -> Result<DigitalOut<'a>, DigitalOutError> {
+        let dgout = DigitalOut::from_raw(self.get_timer_driver()?, pin_driver);
+        Ok(self.keep_updater(dgout))
+    }
+
+    /// Creates an AcDimmer, which controls the power of AC loads with triacs synchronized with a
+    /// zero cross detector. Channels are added as DigitalOut through [AcDimmer::add_channel], and
+    /// the dimmer must be started with [AcDimmer::start].
+    ///
+    /// # Arguments
+    ///
+    /// - `zero_cross_pin`: The number of the pin of the zero cross detector
+    /// - `mains_frequency_hz`: The nominal frequency of the mains, usually 50 or 60 Hz
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `AcDimmer` instance, or an `AcDimmerError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AcDimmerError::InvalidFrequency`: If the frequency is not between 40 and 70 Hz
+    /// - `AcDimmerError::InvalidPeripheral`: If the pin is not valid or was already taken
+    /// - `AcDimmerError::CannotSetPinAsInput`: If the pin can not be set as an input
+    /// - `AcDimmerError::InterruptError`: If the zero cross interrupt can not be set
+    /// - `AcDimmerError::TimerError`: If the timer of the gates can not be created
+    pub fn ac_dimmer(
+        &mut self,
+        zero_cross_pin: usize,
+        mains_frequency_hz: u32,
+    ) -> Result<AcDimmer<'a>, AcDimmerError> {
+        let pin_peripheral = self
+            .peripherals
+            .take_for("AcDimmer", &Peripheral::Pin(zero_cross_pin as u8), |p| {
+                p.get_digital_pin(zero_cross_pin)
+            })
+            .map_err(AcDimmerError::InvalidPeripheral)?;
+        AcDimmer::new(pin_peripheral, mains_frequency_hz)
+    }
+
+    /// Creates a ButtonManager, which tracks several buttons in order to detect chords and
+    /// sequences. Buttons are added as DigitalIn through [ButtonManager::add_button].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `ButtonManager` instance, or a `ButtonManagerError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ButtonManagerError::TimerDriverError`: This error is returned if an issue occurs while initializing the TimerDriver.
+    pub fn button_manager(&mut self) -> Result<ButtonManager<'a>, ButtonManagerError> {
+        let button_manager = ButtonManager::new(self.get_timer_driver()?);
+        Ok(self.keep_updater(button_manager))
+    }
+
+    /// Creates a CapacitiveSensor, which detects touches on pads by timing how long they take to
+    /// charge through a resistor, for chips without touch pads. Pads are added as a DigitalOut and
+    /// a DigitalIn through [CapacitiveSensor::add_pad].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `CapacitiveSensor` instance, or a `CapacitiveSensorError` if
+    /// the initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `CapacitiveSensorError::TimerDriverError`: This error is returned if an issue occurs while initializing the TimerDriver.
+    pub fn capacitive_sensor(&mut self) -> Result<CapacitiveSensor<'a>, CapacitiveSensorError> {
+        let capacitive_sensor = CapacitiveSensor::new(self.get_timer_driver()?);
+        Ok(self.keep_updater(capacitive_sensor))
+    }
+
+    /// Creates a Touchscreen, which polls a touch controller and fires the touch events of the
+    /// panel through the update loop. The controller can be an [crate::touch::Ft6236] or a
+    /// [crate::touch::Gt911] created from an I2CMaster, or an [crate::touch::Xpt2046] created
+    /// from digital pins.
+    ///
+    /// # Arguments
+    ///
+    /// - `controller`: The touch controller of the panel
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `Touchscreen` instance, or a `TouchError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::TimerDriverError`: This error is returned if an issue occurs while initializing the TimerDriver.
+    pub fn touchscreen<T: TouchController + 'a>(
+        &mut self,
+        controller: T,
+    ) -> Result<Touchscreen<'a>, TouchError> {
+        let touchscreen = Touchscreen::new(controller, self.get_timer_driver()?);
+        Ok(self.keep_updater(touchscreen))
+    }
+
+    /// Creates an AdcScanner, which shares the ADC between several analog pins by reading them
+    /// one after the other. Pins are added as AnalogIn through [AdcScanner::add_pin].
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `AdcScanner` instance, or an `AdcScannerError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AdcScannerError::TimerDriverError`: This error is returned if an issue occurs while initializing the TimerDriver.
+    pub fn adc_scanner(&mut self) -> Result<AdcScanner<'a>, AdcScannerError> {
+        let adc_scanner = AdcScanner::new(self.get_timer_driver()?);
+        Ok(self.keep_updater(adc_scanner))
+    }
+
     /// Starts an adc driver if no other was started before. Bitwidth is always set to 12, since
     /// the ESP32-C6 only allows that width
     ///
@@ -214,7 +425,10 @@ impl<'a> Microcontroller<'a> {
     /// - `AdcDriverError::Code`: To represent other errors.
     fn start_adc_driver(&mut self) -> Result<(), AdcDriverError> {
         if self.adc_driver.is_none() {
-            let adc1 = self.peripherals.get_adc().into_adc1()?;
+            let adc1 = self
+                .peripherals
+                .take_for("AdcDriver", &Peripheral::Adc, |p| p.get_adc())?
+                .into_adc1()?;
             let driver = AdcDriver::new(adc1)?;
             self.adc_driver.replace(Rc::new(driver));
         };
@@ -242,12 +456,23 @@ impl<'a> Microcontroller<'a> {
         attenuation: adc_atten_t,
     ) -> Result<AnalogIn<'a>, AnalogInError> {
         self.start_adc_driver()?;
-        let pin_peripheral = self.peripherals.get_analog_pin(pin_num);
+        let pin_peripheral = self
+            .peripherals
+            .take_for("AnalogIn", &Peripheral::Pin(pin_num as u8), |p| {
+                p.get_analog_pin(pin_num)
+            })
+            .map_err(AnalogInError::InvalidPeripheral)?;
         let adc_driver = self
             .adc_driver
             .clone()
             .ok_or(AnalogInError::AdcDriverError(AdcDriverError::AlreadyTaken))?;
-        AnalogIn::new(pin_peripheral, adc_driver, attenuation)
+        let analog_in = AnalogIn::new(
+            self.notification.notifier(),
+            pin_peripheral,
+            adc_driver,
+            attenuation,
+        )?;
+        Ok(self.keep_updater(analog_in))
     }
 
     /// Sets pin as analog input with attenuation set to 2.5dB
@@ -360,7 +585,14 @@ impl<'a> Microcontroller<'a> {
         resolution: u32,
     ) -> Result<AnalogOut<'a>, AnalogOutError> {
         let (pwm_channel, pwm_timer) = self.peripherals.get_next_pwm();
-        let pin_peripheral = self.peripherals.get_pwm_pin(pin_num);
+        self.peripherals.record_owner(&pwm_channel, "AnalogOut");
+        self.peripherals.record_owner(&pwm_timer, "AnalogOut");
+        let pin_peripheral = self
+            .peripherals
+            .take_for("AnalogOut", &Peripheral::Pin(pin_num as u8), |p| {
+                p.get_pwm_pin(pin_num)
+            })
+            .map_err(AnalogOutError::InvalidPeripheral)?;
         let analog_out = AnalogOut::new(
             pwm_channel,
             pwm_timer,
@@ -394,7 +626,14 @@ impl<'a> Microcontroller<'a> {
         pin_num: usize,
     ) -> Result<AnalogOut<'a>, AnalogOutError> {
         let (pwm_channel, pwm_timer) = self.peripherals.get_next_pwm();
-        let pin_peripheral = self.peripherals.get_pwm_pin(pin_num);
+        self.peripherals.record_owner(&pwm_channel, "AnalogOut");
+        self.peripherals.record_owner(&pwm_timer, "AnalogOut");
+        let pin_peripheral = self
+            .peripherals
+            .take_for("AnalogOut", &Peripheral::Pin(pin_num as u8), |p| {
+                p.get_pwm_pin(pin_num)
+            })
+            .map_err(AnalogOutError::InvalidPeripheral)?;
         let analog_out = AnalogOut::default(
             pwm_channel,
             pwm_timer,
@@ -404,6 +643,29 @@ impl<'a> Microcontroller<'a> {
         Ok(self.keep_updater(analog_out))
     }
 
+    /// Wraps a LedcDriver created through esp-idf-hal into an AnalogOut, so it can be used with the
+    /// framework. The channel, timer and pin of the driver are not tracked by the Microcontroller,
+    /// so they must not be requested again through it.
+    ///
+    /// # Arguments
+    ///
+    /// - `driver`: The LedcDriver to wrap
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `AnalogOut` instance, or an `AnalogOutError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AnalogOutError::TimerDriver`: This error is returned if an issue occurs while initializing the TimerDriver.
+    pub fn analog_out_from_raw(
+        &mut self,
+        driver: LedcDriver<'a>,
+    ) -> Result<AnalogOut<'a>, AnalogOutError> {
+        let analog_out = AnalogOut::from_raw(driver, self.get_timer_driver()?);
+        Ok(self.keep_updater(analog_out))
+    }
+
     /// Sets pin as analog input of PWM signals, with default signal frequency of 1000 Hertz
     ///
     /// # Arguments
@@ -422,11 +684,41 @@ impl<'a> Microcontroller<'a> {
         &mut self,
         pin_num: usize,
     ) -> Result<AnalogInPwm<'a>, AnalogInPwmError> {
-        let pin_peripheral = self.peripherals.get_digital_pin(pin_num);
+        let pin_peripheral = self
+            .peripherals
+            .take_for("AnalogInPwm", &Peripheral::Pin(pin_num as u8), |p| {
+                p.get_digital_pin(pin_num)
+            })
+            .map_err(|err| {
+                AnalogInPwmError::DigitalDriverError(DigitalInError::InvalidPeripheral(err))
+            })?;
         let timer_driver = self.get_timer_driver()?;
         AnalogInPwm::default(timer_driver, pin_peripheral)
     }
 
+    /// Creates a StatusLed that shows the state of the device on a led, starting turned off.
+    ///
+    /// # Arguments
+    ///
+    /// - `output`: The led used to show the state, for example a `DigitalOut` or an `AnalogOut`.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `StatusLed` instance, or a `StatusLedError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `StatusLedError::TimerDriverError`: This error is returned if an issue occurs while initializing the TimerDriver.
+    /// - Any error returned by the output while turning the led off.
+    pub fn status_led<O: StatusLedOutput + 'a>(
+        &mut self,
+        output: O,
+    ) -> Result<StatusLed<'a>, StatusLedError> {
+        let status_led = StatusLed::new(output, self.get_timer_driver()?)?;
+        Ok(self.keep_updater(status_led))
+    }
+
     /// Configures the specified pins for I2C master mode.
     ///
     /// # Arguments
@@ -449,10 +741,26 @@ impl<'a> Microcontroller<'a> {
         sda_pin: usize,
         scl_pin: usize,
     ) -> Result<I2CMaster<'a>, I2CError> {
-        let sda_peripheral = self.peripherals.get_digital_pin(sda_pin);
-        let scl_peripheral = self.peripherals.get_digital_pin(scl_pin);
+        let sda_peripheral = self
+            .peripherals
+            .take_for("I2CMaster", &Peripheral::Pin(sda_pin as u8), |p| {
+                p.get_digital_pin(sda_pin)
+            })
+            .map_err(I2CError::PeripheralError)?;
+        let scl_peripheral = self
+            .peripherals
+            .take_for("I2CMaster", &Peripheral::Pin(scl_pin as u8), |p| {
+                p.get_digital_pin(scl_pin)
+            })
+            .map_err(I2CError::PeripheralError)?;
 
-        I2CMaster::new(sda_peripheral, scl_peripheral, self.peripherals.get_i2c())
+        I2CMaster::new(
+            sda_peripheral,
+            scl_peripheral,
+            self.peripherals
+                .take_for("I2CMaster", &Peripheral::I2C, |p| p.get_i2c())
+                .map_err(I2CError::PeripheralError)?,
+        )
     }
 
     /// Configures the specified pins for I2C slave mode and sets the slave address.
@@ -477,17 +785,199 @@ impl<'a> Microcontroller<'a> {
         scl_pin: usize,
         slave_addr: u8,
     ) -> Result<I2CSlave<'a>, I2CError> {
-        let sda_peripheral = self.peripherals.get_digital_pin(sda_pin);
-        let scl_peripheral = self.peripherals.get_digital_pin(scl_pin);
+        let sda_peripheral = self
+            .peripherals
+            .take_for("I2CSlave", &Peripheral::Pin(sda_pin as u8), |p| {
+                p.get_digital_pin(sda_pin)
+            })
+            .map_err(I2CError::PeripheralError)?;
+        let scl_peripheral = self
+            .peripherals
+            .take_for("I2CSlave", &Peripheral::Pin(scl_pin as u8), |p| {
+                p.get_digital_pin(scl_pin)
+            })
+            .map_err(I2CError::PeripheralError)?;
 
         I2CSlave::new(
             sda_peripheral,
             scl_peripheral,
-            self.peripherals.get_i2c(),
+            self.peripherals
+                .take_for("I2CSlave", &Peripheral::I2C, |p| p.get_i2c())
+                .map_err(I2CError::PeripheralError)?,
             slave_addr,
         )
     }
 
+    /// Configures the specified pins for an I2S output, to send audio to an external DAC or amplifier.
+    /// The samples are 16 bit stereo in the standard (Philips) format.
+    ///
+    /// # Arguments
+    ///
+    /// - `bclk_pin`: The pin number to be used as the BCLK (bit clock) line.
+    /// - `ws_pin`: The pin number to be used as the WS (word select) line.
+    /// - `dout_pin`: The pin number to be used as the data output line.
+    /// - `sample_rate`: The sample rate in Hz, for example 16_000 or 44_100.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `I2SOutput` instance, or an `I2SError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `I2SError::PeripheralError`: If a pin or the I2S peripheral is not available.
+    /// - `I2SError::InvalidArg`: If the sample rate is not supported.
+    /// - `I2SError::DriverError`: If there is an error initializing the driver.
+    pub fn set_pins_for_i2s_output(
+        &mut self,
+        bclk_pin: usize,
+        ws_pin: usize,
+        dout_pin: usize,
+        sample_rate: u32,
+    ) -> Result<I2SOutput<'a>, I2SError> {
+        let bclk_peripheral = self
+            .peripherals
+            .take_for("I2SOutput", &Peripheral::Pin(bclk_pin as u8), |p| {
+                p.get_digital_pin(bclk_pin)
+            })
+            .map_err(I2SError::PeripheralError)?;
+        let ws_peripheral = self
+            .peripherals
+            .take_for("I2SOutput", &Peripheral::Pin(ws_pin as u8), |p| {
+                p.get_digital_pin(ws_pin)
+            })
+            .map_err(I2SError::PeripheralError)?;
+        let dout_peripheral = self
+            .peripherals
+            .take_for("I2SOutput", &Peripheral::Pin(dout_pin as u8), |p| {
+                p.get_digital_pin(dout_pin)
+            })
+            .map_err(I2SError::PeripheralError)?;
+
+        I2SOutput::new(
+            bclk_peripheral,
+            ws_peripheral,
+            dout_peripheral,
+            self.peripherals
+                .take_for("I2SOutput", &Peripheral::I2S, |p| p.get_i2s())
+                .map_err(I2SError::PeripheralError)?,
+            sample_rate,
+        )
+    }
+
+    /// Configures the specified pins for an I2S input, to receive audio from a digital microphone.
+    /// The microphone must send its samples on the left slot.
+    ///
+    /// # Arguments
+    ///
+    /// - `bclk_pin`: The pin number to be used as the BCLK (bit clock) line.
+    /// - `ws_pin`: The pin number to be used as the WS (word select) line.
+    /// - `din_pin`: The pin number to be used as the data input line.
+    /// - `sample_rate`: The sample rate in Hz, for example 16_000.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `I2SInput` instance, or an `I2SError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `I2SError::PeripheralError`: If a pin or the I2S peripheral is not available.
+    /// - `I2SError::InvalidArg`: If the sample rate is not supported.
+    /// - `I2SError::DriverError`: If there is an error initializing the driver.
+    pub fn set_pins_for_i2s_input(
+        &mut self,
+        bclk_pin: usize,
+        ws_pin: usize,
+        din_pin: usize,
+        sample_rate: u32,
+    ) -> Result<I2SInput<'a>, I2SError> {
+        let bclk_peripheral = self
+            .peripherals
+            .take_for("I2SInput", &Peripheral::Pin(bclk_pin as u8), |p| {
+                p.get_digital_pin(bclk_pin)
+            })
+            .map_err(I2SError::PeripheralError)?;
+        let ws_peripheral = self
+            .peripherals
+            .take_for("I2SInput", &Peripheral::Pin(ws_pin as u8), |p| {
+                p.get_digital_pin(ws_pin)
+            })
+            .map_err(I2SError::PeripheralError)?;
+        let din_peripheral = self
+            .peripherals
+            .take_for("I2SInput", &Peripheral::Pin(din_pin as u8), |p| {
+                p.get_digital_pin(din_pin)
+            })
+            .map_err(I2SError::PeripheralError)?;
+
+        I2SInput::new(
+            bclk_peripheral,
+            ws_peripheral,
+            din_peripheral,
+            self.peripherals
+                .take_for("I2SInput", &Peripheral::I2S, |p| p.get_i2s())
+                .map_err(I2SError::PeripheralError)?,
+            sample_rate,
+        )
+    }
+
+    /// Mounts the FAT filesystem of a SD card connected in SPI mode, so its files can be accessed
+    /// with `std::fs`, for example to apply a [crate::storage::FieldUpdate] at boot.
+    ///
+    /// # Arguments
+    ///
+    /// - `sclk_pin`: The pin number to be used as the SPI clock line.
+    /// - `mosi_pin`: The pin number to be used as the MOSI line, the DI pin of the card.
+    /// - `miso_pin`: The pin number to be used as the MISO line, the DO pin of the card.
+    /// - `cs_pin`: The pin number to be used as the chip select line.
+    /// - `mount_point`: The path where the filesystem is mounted, for example "/sdcard".
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `SdCard` instance, or a `SdCardError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `SdCardError::PeripheralError`: If any of the pins cannot be converted to an IO pin.
+    /// - `SdCardError::InvalidMountPoint`: If the mount point does not start with '/'.
+    /// - `SdCardError::AlreadyMounted`: If a SD card is already mounted.
+    /// - `SdCardError::SpiBusError`: If the SPI bus could not be initialized.
+    /// - `SdCardError::MountError`: If there is no card, or it does not have a FAT filesystem.
+    pub fn mount_sd_card(
+        &mut self,
+        sclk_pin: usize,
+        mosi_pin: usize,
+        miso_pin: usize,
+        cs_pin: usize,
+        mount_point: &str,
+    ) -> Result<SdCard, SdCardError> {
+        SdCard::new(
+            self.peripherals
+                .take_for("SdCard", &Peripheral::Pin(sclk_pin as u8), |p| {
+                    p.get_digital_pin(sclk_pin)
+                })
+                .map_err(SdCardError::PeripheralError)?,
+            self.peripherals
+                .take_for("SdCard", &Peripheral::Pin(mosi_pin as u8), |p| {
+                    p.get_digital_pin(mosi_pin)
+                })
+                .map_err(SdCardError::PeripheralError)?,
+            self.peripherals
+                .take_for("SdCard", &Peripheral::Pin(miso_pin as u8), |p| {
+                    p.get_digital_pin(miso_pin)
+                })
+                .map_err(SdCardError::PeripheralError)?,
+            self.peripherals
+                .take_for("SdCard", &Peripheral::Pin(cs_pin as u8), |p| {
+                    p.get_digital_pin(cs_pin)
+                })
+                .map_err(SdCardError::PeripheralError)?,
+            mount_point,
+        )
+    }
+
     /// Configures the specified pins for a default UART configuration.
     /// The default configuration is:
     /// - `baudrate`: 115_200 Hz.
@@ -516,9 +1006,24 @@ impl<'a> Microcontroller<'a> {
         rx_pin: usize,
         uart_num: usize,
     ) -> Result<UART<'a>, UARTError> {
-        let tx_peripheral = self.peripherals.get_digital_pin(tx_pin);
-        let rx_peripheral = self.peripherals.get_digital_pin(rx_pin);
-        let uart_peripheral = self.peripherals.get_uart(uart_num);
+        let tx_peripheral = self
+            .peripherals
+            .take_for("UART", &Peripheral::Pin(tx_pin as u8), |p| {
+                p.get_digital_pin(tx_pin)
+            })
+            .map_err(UARTError::InvalidPeripheral)?;
+        let rx_peripheral = self
+            .peripherals
+            .take_for("UART", &Peripheral::Pin(rx_pin as u8), |p| {
+                p.get_digital_pin(rx_pin)
+            })
+            .map_err(UARTError::InvalidPeripheral)?;
+        let uart_peripheral = self
+            .peripherals
+            .take_for("UART", &Peripheral::Uart(uart_num as u8), |p| {
+                p.get_uart(uart_num)
+            })
+            .map_err(UARTError::InvalidPeripheral)?;
 
         UART::default(tx_peripheral, rx_peripheral, uart_peripheral)
     }
@@ -553,9 +1058,24 @@ impl<'a> Microcontroller<'a> {
         parity: Parity,
         stopbit: StopBit,
     ) -> Result<UART<'a>, UARTError> {
-        let tx_peripheral = self.peripherals.get_digital_pin(tx_pin);
-        let rx_peripheral = self.peripherals.get_digital_pin(rx_pin);
-        let uart_peripheral = self.peripherals.get_uart(uart_num);
+        let tx_peripheral = self
+            .peripherals
+            .take_for("UART", &Peripheral::Pin(tx_pin as u8), |p| {
+                p.get_digital_pin(tx_pin)
+            })
+            .map_err(UARTError::InvalidPeripheral)?;
+        let rx_peripheral = self
+            .peripherals
+            .take_for("UART", &Peripheral::Pin(rx_pin as u8), |p| {
+                p.get_digital_pin(rx_pin)
+            })
+            .map_err(UARTError::InvalidPeripheral)?;
+        let uart_peripheral = self
+            .peripherals
+            .take_for("UART", &Peripheral::Uart(uart_num as u8), |p| {
+                p.get_uart(uart_num)
+            })
+            .map_err(UARTError::InvalidPeripheral)?;
 
         UART::new(
             tx_peripheral,
@@ -589,7 +1109,12 @@ impl<'a> Microcontroller<'a> {
         advertising_name: String,
         services: &Vec<Service>,
     ) -> Result<BleBeacon<'a>, BleError> {
-        let ble_device = self.peripherals.get_ble_peripheral().into_ble_device()?;
+        let ble_device = self
+            .peripherals
+            .take_for("BleBeacon", &Peripheral::BleDevice, |p| {
+                p.get_ble_peripheral()
+            })?
+            .into_ble_device()?;
 
         BleBeacon::new(
             ble_device,
@@ -621,7 +1146,12 @@ impl<'a> Microcontroller<'a> {
         advertising_name: String,
         services: &Vec<Service>,
     ) -> Result<BleServer<'a>, BleError> {
-        let ble_device = self.peripherals.get_ble_peripheral().into_ble_device()?;
+        let ble_device = self
+            .peripherals
+            .take_for("BleServer", &Peripheral::BleDevice, |p| {
+                p.get_ble_peripheral()
+            })?
+            .into_ble_device()?;
         let ble_server = BleServer::new(
             advertising_name,
             ble_device,
@@ -646,21 +1176,14 @@ impl<'a> Microcontroller<'a> {
     /// # Errors
     ///
     /// - `BleError::InvalidParameters`: This error is returned if there is an error in the `security_config` argument.
+    /// - `BleError::InvalidPasskey` or `BleError::MitmWithoutIOCapabilities`: If the `security_config` is not
+    ///   valid, see [Security::validate].
     fn config_bluetooth_security(
         &mut self,
         ble_device: &mut BLEDevice,
         security_config: Security,
     ) -> Result<(), BleError> {
-        ble_device
-            .security()
-            .set_auth(
-                AuthReq::from_bits(security_config.auth_mode.to_le())
-                    .ok_or(BleError::InvalidParameters)?,
-            )
-            .set_passkey(security_config.passkey)
-            .set_io_cap(security_config.io_capabilities.get_code())
-            .resolve_rpa();
-        Ok(())
+        security_config.apply(ble_device)
     }
 
     /// Configures a secure BLE server with the specified name, services, and security settings.
@@ -680,6 +1203,8 @@ impl<'a> Microcontroller<'a> {
     ///
     /// - `BleError::PeripheralError`: This error is returned if an issue occurs while initializing the BleDevice.
     /// - `BleError::InvalidParameters`: This error is returned if there is an error in the `security_config` argument.
+    /// - `BleError::InvalidPasskey` or `BleError::MitmWithoutIOCapabilities`: If the `security_config` is not
+    ///   valid, see [Security::validate].
     /// - `BleError::PropertiesError`: If a characteristic on the service has an invalid property.
     /// - `BleError::ServiceNotFound`: If the service_id doesnt match with the id of a service already set on the server.
     pub fn ble_secure_server(
@@ -688,7 +1213,12 @@ impl<'a> Microcontroller<'a> {
         services: &Vec<Service>,
         security_config: Security,
     ) -> Result<BleServer<'a>, BleError> {
-        let ble_device = self.peripherals.get_ble_peripheral().into_ble_device()?;
+        let ble_device = self
+            .peripherals
+            .take_for("BleSecureServer", &Peripheral::BleDevice, |p| {
+                p.get_ble_peripheral()
+            })?
+            .into_ble_device()?;
         self.config_bluetooth_security(ble_device, security_config)?;
         let ble_server = BleServer::new(
             advertising_name,
@@ -700,6 +1230,32 @@ impl<'a> Microcontroller<'a> {
         Ok(self.keep_updater(ble_server))
     }
 
+    /// Configures a BLE extended advertiser, capable of advertising multiple sets with legacy or
+    /// extended advertising. Only available after adding `CONFIG_BT_NIMBLE_EXT_ADV=y` to the
+    /// `sdkconfig.defaults` of the project. With it, the advertising of the BleBeacon and BleServer
+    /// is not available.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `BleExtendedAdvertiser` instance, or an `BleError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::PeripheralError`: This error is returned if an issue occurs while initializing the BleDevice.
+    #[cfg(esp_idf_bt_nimble_ext_adv)]
+    pub fn ble_extended_advertiser(
+        &mut self,
+    ) -> Result<crate::ble::BleExtendedAdvertiser<'a>, BleError> {
+        let ble_device = self
+            .peripherals
+            .take_for("BleExtendedAdvertiser", &Peripheral::BleDevice, |p| {
+                p.get_ble_peripheral()
+            })?
+            .into_ble_device()?;
+        Ok(crate::ble::BleExtendedAdvertiser::new(ble_device))
+    }
+
     /// Configures a BLE client.
     /// # Returns
     ///
@@ -710,15 +1266,47 @@ impl<'a> Microcontroller<'a> {
     ///
     /// - `BleError::PeripheralError`: This error is returned if an issue occurs while initializing the BleDevice.
     pub fn ble_client(&mut self) -> Result<BleClient, BleError> {
-        let ble_device = self.peripherals.get_ble_peripheral().into_ble_device()?;
+        let ble_device = self
+            .peripherals
+            .take_for("BleClient", &Peripheral::BleDevice, |p| {
+                p.get_ble_peripheral()
+            })?
+            .into_ble_device()?;
         let ble_client = BleClient::new(ble_device, self.notification.notifier());
         Ok(self.keep_updater(ble_client))
     }
 
+    /// Configures a BLE presence monitor, that continuously scans for a set of devices.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `PresenceMonitor` instance, or an `BleError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BleError::PeripheralError`: This error is returned if an issue occurs while initializing the BleDevice.
+    /// - `BleError::TimerDriverError`: This error is returned if an issue occurs while initializing the TimerDriver.
+    pub fn ble_presence_monitor(&mut self) -> Result<PresenceMonitor<'a>, BleError> {
+        let ble_device = self
+            .peripherals
+            .take_for("PresenceMonitor", &Peripheral::BleDevice, |p| {
+                p.get_ble_peripheral()
+            })?
+            .into_ble_device()?;
+        let presence_monitor = PresenceMonitor::new(
+            ble_device,
+            self.get_timer_driver()?,
+            self.notification.notifier(),
+        );
+        Ok(self.keep_updater(presence_monitor))
+    }
+
     /// Configures a WIFIDriver. This driver uses the
     /// By default this function takes the Non-Volatile Storage of the ESP in order to save
     /// wifi configuration. This is to improve connection times for future connections
-    /// to the same network.
+    /// to the same network. The callbacks of the wifi events set on the driver are executed
+    /// by [Self::wait_for_updates] and [Self::block_on].
     ///
     /// # Returns
     ///
@@ -729,11 +1317,195 @@ impl<'a> Microcontroller<'a> {
     ///
     /// - `WifiError::PeripheralError`: This error is returned if an issue occurs while initializing the WifiModem.
     pub fn get_wifi_driver(&mut self) -> Result<WifiDriver<'a>, WifiError> {
-        let modem = self.peripherals.get_wifi_peripheral().into_modem()?;
-        WifiDriver::new(self.event_loop.clone(), modem)
+        let modem = self
+            .peripherals
+            .take_for("WifiDriver", &Peripheral::Modem, |p| {
+                p.get_wifi_peripheral()
+            })?
+            .into_modem()?;
+        let wifi_driver =
+            WifiDriver::new(self.event_loop.clone(), modem, self.notification.notifier())?;
+        Ok(self.keep_updater(wifi_driver))
+    }
+
+    /// Creates a WifiSniffer in order to capture 802.11 frames in promiscuous mode. The
+    /// wifi driver is started if needed, but it does not need to be connected to a network.
+    ///
+    /// # Arguments
+    ///
+    /// - `wifi_driver`: The WifiDriver whose radio will be used to capture frames.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `WifiSniffer` instance, or a `WifiError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::StartingError`: This error is returned if the wifi driver could not be started.
+    /// - `WifiError::TimerDriverError`: This error is returned if an issue occurs while initializing the TimerDriver.
+    pub fn get_wifi_sniffer(
+        &mut self,
+        wifi_driver: &mut WifiDriver<'a>,
+    ) -> Result<WifiSniffer<'a>, WifiError> {
+        self.block_on(wifi_driver.start_if_needed_async())?;
+        let sniffer = WifiSniffer::new(self.get_timer_driver()?, self.notification.notifier());
+        Ok(self.keep_updater(sniffer))
+    }
+
+    /// Creates an HttpServer listening on the port, whose route handlers are executed in the update
+    /// loop, so they can use the other drivers. The device must be connected to a network, or
+    /// hosting one as an access point, for the server to be reached.
+    ///
+    /// # Arguments
+    ///
+    /// - `port`: The port to listen on, usually 80.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `HttpServer` instance, or an `HttpServerError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpServerError::StartingError`: This error is returned if the server could not be started,
+    ///   for example if the wifi driver was not created.
+    pub fn http_server(&mut self, port: u16) -> Result<HttpServer<'a>, HttpServerError> {
+        let server = HttpServer::new(port, self.notification.notifier())?;
+        Ok(self.keep_updater(server))
+    }
+
+    /// Starts SmartConfig provisioning, so a phone app such as ESP-Touch can send the credentials
+    /// of a network to the device. The wifi driver is started in station mode if needed.
+    ///
+    /// # Arguments
+    ///
+    /// - `wifi_driver`: The WifiDriver whose radio will be used.
+    /// - `smart_config_type`: The protocol used by the phone app.
+    /// - `timeout`: The maximum time to wait for the credentials.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `SmartConfig` instance, or a `WifiError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: This error is returned if the station mode could not be
+    ///   set.
+    /// - `WifiError::StartingError`: This error is returned if the wifi driver or SmartConfig
+    ///   could not be started, for example if SmartConfig is already running.
+    pub fn smart_config(
+        &mut self,
+        wifi_driver: &mut WifiDriver<'a>,
+        smart_config_type: SmartConfigType,
+        timeout: Duration,
+    ) -> Result<SmartConfig<'a>, WifiError> {
+        self.block_on(wifi_driver.start_station_async())?;
+        let smart_config =
+            SmartConfig::new(smart_config_type, timeout, self.notification.notifier())?;
+        Ok(self.keep_updater(smart_config))
+    }
+
+    /// Creates an MqttClient, which connects to the broker on its own task, reconnecting whenever
+    /// the connection drops. The device must be connected to a network for the client to reach
+    /// the broker.
+    ///
+    /// # Arguments
+    ///
+    /// - `broker_url`: The url of the broker, such as `mqtt://broker.local:1883`.
+    /// - `options`: The MqttOptions with the client id and credentials of the connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `MqttClient` instance, or an `MqttError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MqttError::ConnectionError`: This error is returned if the client could not be created,
+    ///   for example if the url is not valid.
+    pub fn mqtt_client(
+        &mut self,
+        broker_url: &str,
+        options: MqttOptions,
+    ) -> Result<MqttClient<'a>, MqttError> {
+        let client = MqttClient::new(broker_url, &options, self.notification.notifier())?;
+        Ok(self.keep_updater(client))
+    }
+
+    /// Creates the mDNS responder, in order to advertise the device as `hostname.local` and the
+    /// services it offers on the local network. It answers once the device joins a network, or
+    /// hosts one as an access point. Requires the `mdns` feature.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `Mdns` instance, or a `MdnsError` if the initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MdnsError::AlreadyTaken`: This error is returned if the responder was already created.
+    #[cfg(feature = "mdns")]
+    pub fn mdns(&mut self) -> Result<Mdns, MdnsError> {
+        Mdns::new()
+    }
+
+    /// Creates a PeerLink in order to pair with another framework device and exchange messages
+    /// over ESP-NOW. The wifi driver is started if needed, but it does not need to be connected
+    /// to a network. If a peer was stored on a previous run, the device starts already paired.
+    ///
+    /// # Arguments
+    ///
+    /// - `wifi_driver`: The WifiDriver whose radio and Non-Volatile Storage will be used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `PeerLink` instance, or a `PeerError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeerError::WifiError`: This error is returned if the wifi driver could not be started.
+    /// - `PeerError::NvsError`: This error is returned if the stored peer could not be read.
+    /// - `PeerError::EspNowError`: This error is returned if ESP-NOW could not be initialized.
+    pub fn get_peer_link(
+        &mut self,
+        wifi_driver: &mut WifiDriver<'a>,
+    ) -> Result<PeerLink<'a>, PeerError> {
+        self.block_on(wifi_driver.start_if_needed_async())?;
+        let peer_link = PeerLink::new(wifi_driver.nvs_partition(), self.notification.notifier())?;
+        Ok(self.keep_updater(peer_link))
+    }
+
+    /// Creates an EspNow driver in order to exchange datagrams with other devices over ESP-NOW,
+    /// without an access point. The wifi driver is started if needed, but it does not need to be
+    /// connected to a network. It can not be used together with a PeerLink.
+    ///
+    /// # Arguments
+    ///
+    /// - `wifi_driver`: The WifiDriver whose radio will be used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `EspNow` instance, or an `EspNowError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EspNowError::WifiError`: This error is returned if the wifi driver could not be started.
+    /// - `EspNowError::InitializationError`: This error is returned if ESP-NOW could not be
+    ///   initialized.
+    pub fn get_esp_now(
+        &mut self,
+        wifi_driver: &mut WifiDriver<'a>,
+    ) -> Result<EspNow<'a>, EspNowError> {
+        self.block_on(wifi_driver.start_if_needed_async())?;
+        let esp_now = EspNow::new(self.notification.notifier())?;
+        Ok(self.keep_updater(esp_now))
     }
 
     /// Updates all assigned drivers of the microcontroller, handling interrupts and alarms as needed.
+    /// Records logged with [crate::isr_log] are printed first.
     ///
     /// # Returns
     ///
@@ -745,6 +1517,7 @@ impl<'a> Microcontroller<'a> {
     /// For example if a `TimerDriver` failed while updating the variant `Esp32FrameworkError::TimerDriverError` will be
     /// returned
     pub fn update(&mut self) -> Result<(), Esp32FrameworkError> {
+        isr_log::flush();
         //timer_drivers must be updated before other drivers since this may efect the other drivers updates
         for timer_driver in &mut self.timer_drivers {
             timer_driver.update_interrupt()?;
@@ -764,6 +1537,7 @@ impl<'a> Microcontroller<'a> {
     }
 
     /// Limited blocking version of [Self::wait_for_updates]
+    #[cfg(not(feature = "virtual-time"))]
     fn wait_for_updates_until(&mut self, miliseconds: u32) {
         let timer_driver = self.timer_drivers.first_mut().unwrap();
 
@@ -782,6 +1556,41 @@ impl<'a> Microcontroller<'a> {
         }
     }
 
+    /// Virtual time version of [Self::wait_for_updates], which advances the time instead of blocking
+    #[cfg(feature = "virtual-time")]
+    fn wait_for_updates_until(&mut self, miliseconds: u32) {
+        self.advance_time(miliseconds).unwrap();
+    }
+
+    /// Advances the virtual time, firing in order every alarm of the [TimerDriver]s that is due
+    /// meanwhile and updating the drivers after each one, as [Self::wait_for_updates] would do if
+    /// the time really passed. This allows testing debounces, schedules and ramps without waiting.
+    /// While the feature is enabled, [Self::wait_for_updates] with a limit also advances the
+    /// virtual time instead of blocking.
+    ///
+    /// Note: Only available with the `virtual-time` feature. Time measured with `Instant` is not
+    /// affected, use [crate::utils::virtual_time::now] instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `miliseconds`: Amount of miliseconds to advance
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if all driver updates completed successfully, or an `Esp32FrameworkError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// If an error occurs `Esp32FrameworkError` variant is returned which corresponds to the failing update driver type.
+    #[cfg(feature = "virtual-time")]
+    pub fn advance_time(&mut self, miliseconds: u32) -> Result<(), Esp32FrameworkError> {
+        let until = virtual_time::now_us() + miliseconds as u64 * 1000;
+        while advance_virtual_time_step(&mut self.timer_drivers, until)? {
+            self.update()?;
+        }
+        self.update()
+    }
+
     /// Blocking function that will block for a specified time while keeping updated the microcontroller and other drivers.
     /// It is necesary to call this function from time to time, so that any interrupt that was set on any driver can be
     /// executed properly. Another way to avoid calling this function is to use an asynchronouse aproach, see [Self::block_on].
@@ -838,8 +1647,141 @@ impl<'a> Microcontroller<'a> {
     /// and will stop execution inmidiatly by panicking
     pub fn block_on<F: Future>(&mut self, fut: F) -> F::Output {
         let finished = SharableRef::new_sharable(false);
+        let mut starvation = std::mem::take(&mut self.starvation);
+        let fut = watch_starvation(fut, &mut starvation);
         let fut = wrap_user_future(self.notification.notifier(), finished.clone(), fut);
-        block_on(join(fut, self.wait_for_updates_until_finished(finished))).0
+        let output = block_on(join(fut, self.wait_for_updates_until_finished(finished))).0;
+        self.starvation = starvation;
+        output
+    }
+
+    /// Sets the longest time the future of [Self::block_on] can run without awaiting. While it runs
+    /// no interrupt callback is executed, so longer runs are reported as starvation of the drivers.
+    /// By default it is 500 ms.
+    ///
+    /// # Arguments
+    ///
+    /// - `threshold`: The longest time the future can run without awaiting
+    pub fn set_starvation_threshold(&mut self, threshold: Duration) {
+        self.starvation.threshold = threshold;
+    }
+
+    /// Sets the callback executed when the future of [Self::block_on] runs longer than the starvation
+    /// threshold without awaiting, instead of logging a warning. Long computations can be split with
+    /// [crate::utils::auxiliary::yield_now].
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives how long the future ran without awaiting
+    pub fn on_starvation<C: FnMut(Duration) + 'a>(&mut self, callback: C) {
+        self.starvation.callback = Some(Box::new(callback));
+    }
+
+    /// Sets the callback executed first by [Self::shutdown], while every driver still works. Meant to
+    /// save the state and close the files of the [SdCard], which are owned by the user.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure executed before stopping the drivers
+    pub fn on_shutdown<C: FnMut() + 'a>(&mut self, callback: C) {
+        self.on_shutdown = Some(Box::new(callback));
+    }
+
+    /// Powers down the microcontroller cleanly, for example from a button or a remote command. The
+    /// steps are taken in this order:
+    /// 1. The callback of [Self::on_shutdown] is executed.
+    /// 2. Every driver with work of its own is stopped, the newest first: BLE servers stop advertising
+    ///    and disconnect their clients, BLE clients stop scanning and disconnect without reconnecting,
+    ///    and digital inputs, sniffers and presence monitors stop their interrupts.
+    /// 3. Any BLE advertisement, such as the one of a [BleBeacon], and the wifi are stopped.
+    /// 4. The logs, including the ones of [crate::isr_log], are flushed.
+    /// 5. The timers are stopped, removing every interrupt set on them.
+    /// 6. The microcontroller halts, sleeps or restarts according to the `mode`.
+    ///
+    /// A step that fails does not keep the next ones from being taken. The drivers should not be
+    /// used after a shutdown with [ShutdownMode::Halt]. Outputs keep their level, so they must be
+    /// set beforehand if needed, for example on the callback of [Self::on_shutdown].
+    ///
+    /// # Arguments
+    ///
+    /// - `mode`: The ShutdownMode that tells what to do once the drivers are stopped
+    ///
+    /// # Returns
+    ///
+    /// With [ShutdownMode::Halt], a `Result` with Ok if every driver stopped, or the first
+    /// `Esp32FrameworkError` that occurred. With the other modes it does not return, and the errors
+    /// are discarded.
+    ///
+    /// # Errors
+    ///
+    /// If an error occurs `Esp32FrameworkError` variant is returned which corresponds to the failing driver type.
+    pub fn shutdown(&mut self, mode: ShutdownMode) -> Result<(), Esp32FrameworkError> {
+        if let Some(callback) = self.on_shutdown.as_mut() {
+            callback();
+        }
+
+        let mut result = Ok(());
+        for driver in self.interrupt_drivers.iter_mut().rev() {
+            result = result.and(driver.teardown());
+        }
+        self.stop_radios();
+
+        isr_log::flush();
+        log::logger().flush();
+        _ = std::io::stdout().flush();
+
+        for timer_driver in &mut self.timer_drivers {
+            result = result.and(timer_driver.teardown());
+        }
+
+        match mode {
+            ShutdownMode::Halt => result,
+            ShutdownMode::DeepSleep(Some(duration)) => unsafe {
+                esp_idf_svc::sys::esp_deep_sleep(duration.as_micros() as u64)
+            },
+            ShutdownMode::DeepSleep(None) => unsafe { esp_idf_svc::sys::esp_deep_sleep_start() },
+            ShutdownMode::Restart => unsafe { esp_idf_svc::sys::esp_restart() },
+        }
+    }
+
+    /// Stops the BLE advertisement and the wifi, if a driver took them. Errors are ignored, since
+    /// they were already stopped or their driver was dropped.
+    fn stop_radios(&self) {
+        if self.peripherals.is_ble_taken() {
+            _ = BLEDevice::take().get_advertising().lock().stop();
+        }
+        if self.peripherals.is_modem_taken() {
+            unsafe { esp_idf_svc::sys::esp_wifi_stop() };
+        }
+    }
+}
+
+impl Default for StarvationDetector<'_> {
+    fn default() -> Self {
+        StarvationDetector {
+            threshold: DEFAULT_STARVATION_THRESHOLD,
+            callback: None,
+        }
+    }
+}
+
+impl StarvationDetector<'_> {
+    /// Reports a poll of the user future if it took longer than the threshold
+    ///
+    /// # Arguments
+    ///
+    /// - `elapsed`: How long the poll took
+    fn check(&mut self, elapsed: Duration) {
+        if elapsed <= self.threshold {
+            return;
+        }
+        match self.callback.as_mut() {
+            Some(callback) => callback(elapsed),
+            None => log::warn!(
+                "block_on future ran for {} ms without awaiting, drivers were not updated",
+                elapsed.as_millis()
+            ),
+        }
     }
 }
 
@@ -882,6 +1824,27 @@ async fn wrap_user_future<F: Future>(
     res
 }
 
+/// Wraps fut into a new future that measures the duration of each of its polls, reporting the long
+/// ones to the StarvationDetector.
+///
+/// # Arguments
+/// - fut: `Future` to wrap
+/// - detector: The `StarvationDetector` that checks each poll
+///
+/// # Returns
+///
+/// The original future output
+async fn watch_starvation<F: Future>(fut: F, detector: &mut StarvationDetector<'_>) -> F::Output {
+    let mut fut = pin!(fut);
+    poll_fn(|cx| {
+        let start = Instant::now();
+        let poll = fut.as_mut().poll(cx);
+        detector.check(start.elapsed());
+        poll
+    })
+    .await
+}
+
 impl UseOfExternalPeripheralsExt for Microcontroller<'_> {
     fn register_external_peripherals_use(
         &mut self,
@@ -894,6 +1857,8 @@ impl UseOfExternalPeripheralsExt for Microcontroller<'_> {
     }
 
     fn register_external_peripheral_use(&mut self, peripheral: Peripheral) -> Peripheral {
-        self.peripherals.remove(peripheral)
+        let peripheral = self.peripherals.remove(peripheral);
+        self.peripherals.record_owner(&peripheral, "External");
+        peripheral
     }
 }
@@ -2,4 +2,4 @@ pub mod external_peripheral;
 pub(crate) mod interrupt_driver;
 pub mod microcontroller;
 pub mod peripherals;
-pub use self::microcontroller::Microcontroller;
+pub use self::microcontroller::{Microcontroller, ShutdownMode};
@@ -1,6 +1,6 @@
 use esp32_nimble::BLEDevice;
-use esp_idf_svc::hal::{adc::ADC1, gpio::*, i2c::I2C0, modem};
-use std::mem;
+use esp_idf_svc::hal::{adc::ADC1, gpio::*, i2c::I2C0, i2s::I2S0, modem};
+use std::{error::Error, fmt, mem};
 
 const PIN_COUNT: usize = 24;
 const TIMERS_COUNT: usize = 2;
@@ -12,12 +12,14 @@ const TIMER_BOUND: (usize, usize) = (0, 1);
 const UART_COUNT: usize = 2;
 const UART_BOUNDS: (usize, usize) = (0, 1);
 
-/// Error types related to microcontroller peripheral operations.
+/// Error types related to microcontroller peripheral operations. `AlreadyTaken` contains the type
+/// of the driver that took the peripheral, if it is known.
 #[derive(Debug, PartialEq)]
 pub enum PeripheralError {
-    AlreadyTaken,
+    AlreadyTaken { owner: Option<&'static str> },
     NotABleDevicePeripheral,
     NotAnI2CPeripheral,
+    NotAnI2SPeripheral,
     NotAModemPeripheral,
     NotAPin,
     NotAPwmTimer,
@@ -35,6 +37,7 @@ pub enum Peripheral {
     PWMTimer(u8),
     Adc,
     I2C,
+    I2S,
     Uart(u8),
     BleDevice,
     Modem,
@@ -42,6 +45,15 @@ pub enum Peripheral {
     None,
 }
 
+/// A peripheral in use, listed by [crate::Microcontroller::peripheral_report]:
+/// - `peripheral`: The name of the peripheral, such as `GPIO4` or `UART1`.
+/// - `owner`: The type of the driver that took it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeripheralUsage {
+    pub peripheral: String,
+    pub owner: &'static str,
+}
+
 impl Peripheral {
     /// Takes the Peripheral instance and changes to a Peripheral::None instance.
     ///
@@ -93,7 +105,7 @@ impl Peripheral {
                 23 => unsafe { Gpio23::new().downgrade() },
                 _ => return Err(PeripheralError::NotAPin),
             },
-            Peripheral::None => return Err(PeripheralError::AlreadyTaken),
+            Peripheral::None => return Err(PeripheralError::AlreadyTaken { owner: None }),
             _ => return Err(PeripheralError::NotAPin),
         };
         Ok(pin)
@@ -117,11 +129,34 @@ impl Peripheral {
     pub fn into_i2c0(self) -> Result<I2C0, PeripheralError> {
         match self {
             Peripheral::I2C => Ok(unsafe { I2C0::new() }),
-            Peripheral::None => Err(PeripheralError::AlreadyTaken),
+            Peripheral::None => Err(PeripheralError::AlreadyTaken { owner: None }),
             _ => Err(PeripheralError::NotAnI2CPeripheral),
         }
     }
 
+    /// Transforms the Peripheral instance into a I2S0
+    ///
+    /// If the Peripheral is a I2S returns the corresponding I2S0.
+    /// If its a None it returns PeripheralError::AlreadyTaken
+    /// Otherwise it returns PeripheralError::NotAnI2SPeripheral
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `I2S0` instance, or an `PeripheralError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeripheralError::AlreadyTaken`: If the I2S was already taken.
+    /// - `PeripheralError::NotAnI2SPeripheral`: Peripheral can not be transform into a I2S.
+    pub fn into_i2s0(self) -> Result<I2S0, PeripheralError> {
+        match self {
+            Peripheral::I2S => Ok(unsafe { I2S0::new() }),
+            Peripheral::None => Err(PeripheralError::AlreadyTaken { owner: None }),
+            _ => Err(PeripheralError::NotAnI2SPeripheral),
+        }
+    }
+
     /// Transforms the Peripheral instance into a BleDevice.
     ///
     /// # Returns
@@ -136,7 +171,7 @@ impl Peripheral {
     pub fn into_ble_device(self) -> Result<&'static mut BLEDevice, PeripheralError> {
         match self {
             Peripheral::BleDevice => Ok(BLEDevice::take()),
-            Peripheral::None => Err(PeripheralError::AlreadyTaken),
+            Peripheral::None => Err(PeripheralError::AlreadyTaken { owner: None }),
             _ => Err(PeripheralError::NotABleDevicePeripheral),
         }
     }
@@ -155,7 +190,7 @@ impl Peripheral {
     pub fn into_modem(self) -> Result<modem::Modem, PeripheralError> {
         match self {
             Peripheral::Modem => Ok(unsafe { modem::Modem::new() }),
-            Peripheral::None => Err(PeripheralError::AlreadyTaken),
+            Peripheral::None => Err(PeripheralError::AlreadyTaken { owner: None }),
             _ => Err(PeripheralError::NotAModemPeripheral),
         }
     }
@@ -174,7 +209,7 @@ impl Peripheral {
     pub fn into_adc1(self) -> Result<ADC1, PeripheralError> {
         match self {
             Peripheral::Adc => Ok(unsafe { ADC1::new() }),
-            Peripheral::None => Err(PeripheralError::AlreadyTaken),
+            Peripheral::None => Err(PeripheralError::AlreadyTaken { owner: None }),
             _ => Err(PeripheralError::NotAnAdc),
         }
     }
@@ -182,12 +217,34 @@ impl Peripheral {
     pub fn is_none(&self) -> bool {
         matches!(self, Peripheral::None)
     }
+
+    /// Gets the name of the Peripheral shown in the peripheral usage report
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the name of the Peripheral, or None if it is a `Peripheral::None`
+    fn name(&self) -> Option<String> {
+        let name = match self {
+            Peripheral::Pin(num) => format!("GPIO{num}"),
+            Peripheral::Timer(num) => format!("Timer group {num}"),
+            Peripheral::PWMChannel(num) => format!("PWM channel {num}"),
+            Peripheral::PWMTimer(num) => format!("PWM timer {num}"),
+            Peripheral::Adc => "ADC1".to_string(),
+            Peripheral::I2C => "I2C0".to_string(),
+            Peripheral::I2S => "I2S0".to_string(),
+            Peripheral::Uart(num) => format!("UART{num}"),
+            Peripheral::BleDevice => "BLE".to_string(),
+            Peripheral::Modem => "Modem".to_string(),
+            Peripheral::None => return None,
+        };
+        Some(name)
+    }
 }
 
 /// Represents the available peripherals in the esp32C6 and provides a way to get each particular
 /// peripheral. Subsequent gets of the same peripheral will return Peripheral::None. On some cases
 /// the same peripheral can be obatained by different getters, but only the first one will return the
-/// pin.
+/// pin. The driver that took each peripheral is recorded for the peripheral usage report.
 pub struct Peripherals {
     pins: [Peripheral; PIN_COUNT],
     timers: [Peripheral; TIMERS_COUNT],
@@ -195,9 +252,11 @@ pub struct Peripherals {
     pwm_timers: [Peripheral; PWM_COUNT],
     adc: Peripheral,
     i2c: Peripheral,
+    i2s: Peripheral,
     uart: [Peripheral; UART_COUNT],
     ble_device: Peripheral,
     modem: Peripheral,
+    usage: Vec<PeripheralUsage>,
 }
 
 impl Peripherals {
@@ -213,6 +272,7 @@ impl Peripherals {
         let pwm_timers = Self::new_pwm_timers();
         let adc: Peripheral = Peripheral::Adc;
         let i2c: Peripheral = Peripheral::I2C;
+        let i2s: Peripheral = Peripheral::I2S;
         let uart: [Peripheral; UART_COUNT] = [Peripheral::Uart(0), Peripheral::Uart(1)];
         let ble_device = Peripheral::BleDevice;
         let modem = Peripheral::Modem;
@@ -223,9 +283,11 @@ impl Peripherals {
             pwm_timers,
             adc,
             i2c,
+            i2s,
             uart,
             ble_device,
             modem,
+            usage: Vec::new(),
         }
     }
 
@@ -391,6 +453,15 @@ impl Peripherals {
         self.i2c.take()
     }
 
+    /// Gets the only I2S peripheral available
+    ///
+    /// # Returns
+    ///
+    /// A `Peripheral::I2S` if it was not taken before, otherwise a `Peripheral::None`
+    pub fn get_i2s(&mut self) -> Peripheral {
+        self.i2s.take()
+    }
+
     /// Gets the desired uart Peripheral
     ///
     /// # Arguments
@@ -454,4 +525,128 @@ impl Peripherals {
             _ => Peripheral::None,
         }
     }
+
+    /// Gets a peripheral with one of the getters and records the driver that took it
+    ///
+    /// # Arguments
+    ///
+    /// - `owner`: The type of the driver taking the peripheral
+    /// - `requested`: The peripheral being requested, used to find who took it on a conflict
+    /// - `get`: The getter of the peripheral, such as `|p| p.get_digital_pin(4)`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the `Peripheral` returned by the getter, or a `PeripheralError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeripheralError::AlreadyTaken`: If the getter returns a `Peripheral::None`, with the
+    ///   driver that took the requested peripheral if it is known
+    pub(crate) fn take_for<F>(
+        &mut self,
+        owner: &'static str,
+        requested: &Peripheral,
+        get: F,
+    ) -> Result<Peripheral, PeripheralError>
+    where
+        F: FnOnce(&mut Self) -> Peripheral,
+    {
+        let peripheral = get(self);
+        if peripheral.is_none() {
+            return Err(PeripheralError::AlreadyTaken {
+                owner: self.owner_of(requested),
+            });
+        }
+        self.record_owner(&peripheral, owner);
+        Ok(peripheral)
+    }
+
+    /// Gets the driver that took a peripheral
+    ///
+    /// # Arguments
+    ///
+    /// - `peripheral`: The peripheral to look for
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the type of the driver, or None if the peripheral was not taken by a driver
+    fn owner_of(&self, peripheral: &Peripheral) -> Option<&'static str> {
+        let name = peripheral.name()?;
+        self.usage
+            .iter()
+            .find(|usage| usage.peripheral == name)
+            .map(|usage| usage.owner)
+    }
+
+    /// Records the driver that took a peripheral. Nothing is recorded for a `Peripheral::None`.
+    ///
+    /// # Arguments
+    ///
+    /// - `peripheral`: The peripheral that was taken
+    /// - `owner`: The type of the driver that took it
+    pub(crate) fn record_owner(&mut self, peripheral: &Peripheral, owner: &'static str) {
+        if let Some(name) = peripheral.name() {
+            self.usage.push(PeripheralUsage {
+                peripheral: name,
+                owner,
+            });
+        }
+    }
+
+    /// Checks whether a driver took the BleDevice peripheral
+    ///
+    /// # Returns
+    ///
+    /// True if the BleDevice was taken, False if not
+    pub(crate) fn is_ble_taken(&self) -> bool {
+        self.ble_device.is_none()
+    }
+
+    /// Checks whether a driver took the Modem peripheral
+    ///
+    /// # Returns
+    ///
+    /// True if the Modem was taken, False if not
+    pub(crate) fn is_modem_taken(&self) -> bool {
+        self.modem.is_none()
+    }
+
+    /// Lists the peripherals that were taken and the driver that took each one
+    ///
+    /// # Returns
+    ///
+    /// A vector with a PeripheralUsage for each peripheral taken, in the order they were taken
+    pub(crate) fn usage(&self) -> Vec<PeripheralUsage> {
+        self.usage.clone()
+    }
 }
+
+impl fmt::Display for PeripheralUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.peripheral, self.owner)
+    }
+}
+
+impl fmt::Display for PeripheralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeripheralError::AlreadyTaken { owner: Some(owner) } => {
+                write!(f, "the peripheral was already taken by {owner}")
+            }
+            PeripheralError::AlreadyTaken { owner: None } => {
+                write!(f, "the peripheral was already taken")
+            }
+            PeripheralError::NotABleDevicePeripheral => write!(f, "not a BLE device peripheral"),
+            PeripheralError::NotAnI2CPeripheral => write!(f, "not an I2C peripheral"),
+            PeripheralError::NotAnI2SPeripheral => write!(f, "not an I2S peripheral"),
+            PeripheralError::NotAModemPeripheral => write!(f, "not a modem peripheral"),
+            PeripheralError::NotAPin => write!(f, "not a pin"),
+            PeripheralError::NotAPwmTimer => write!(f, "not a PWM timer"),
+            PeripheralError::NotAPwmChannel => write!(f, "not a PWM channel"),
+            PeripheralError::NotATimerGroup => write!(f, "not a timer group"),
+            PeripheralError::NotAnAdc => write!(f, "not an ADC"),
+        }
+    }
+}
+
+impl Error for PeripheralError {}
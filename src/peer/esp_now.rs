@@ -0,0 +1,382 @@
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        isr_queues::{ISRByteArrayQueue, ISRQueueTrait},
+        notification::Notifier,
+        queue_config::queue_capacities,
+    },
+    wifi::WifiError,
+    InterruptDriver,
+};
+use esp_idf_svc::sys::{
+    esp_now_add_peer, esp_now_del_peer, esp_now_init, esp_now_is_peer_exist, esp_now_peer_info_t,
+    esp_now_recv_info_t, esp_now_register_recv_cb, esp_now_send, wifi_interface_t_WIFI_IF_STA,
+    ESP_NOW_MAX_DATA_LEN, ESP_NOW_MAX_TOTAL_PEER_NUM, ESP_OK,
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{ffi::c_int, sync::Mutex};
+
+const MAC_SIZE: usize = 6;
+/// Every queued message starts with the MAC address of the sender and the rssi
+const MESSAGE_HEADER_SIZE: usize = MAC_SIZE + 1;
+pub const ESP_NOW_BROADCAST_ADDRESS: [u8; MAC_SIZE] = [0xFF; MAC_SIZE];
+
+/// Queue and notifier used by the ESP-NOW receive callback, which does not receive any user data
+static ESP_NOW_CHANNEL: Mutex<Option<(ISRByteArrayQueue, Notifier)>> = Mutex::new(None);
+
+type ReceiveCallback<'a> = dyn FnMut(&EspNowMessage) + 'a;
+
+/// Enums the different errors possible when working with EspNow
+#[derive(Debug)]
+pub enum EspNowError {
+    InitializationError,
+    MessageTooLong,
+    PeerError,
+    SendError,
+    WifiError(WifiError),
+}
+
+/// A message received through ESP-NOW:
+/// - `source`: The MAC address of the sender.
+/// - `rssi`: The signal strength of the message.
+/// - `data`: The bytes of the message.
+#[derive(Debug, Clone)]
+pub struct EspNowMessage {
+    pub source: [u8; MAC_SIZE],
+    pub rssi: i8,
+    pub data: Vec<u8>,
+}
+
+/// Driver to exchange datagrams with other devices over ESP-NOW, without an access point.
+/// Messages can be sent to the registered peers, or broadcasted to every device on the channel.
+/// ESP-NOW does not confirm that messages arrive, so for acknowledged messages between two paired
+/// framework devices a [crate::peer::PeerLink] can be used instead. Both use the same receive
+/// callback of ESP-NOW, so only one of them can be created.
+struct _EspNow<'a> {
+    message_queue: ISRByteArrayQueue,
+    peers: Vec<[u8; MAC_SIZE]>,
+    receive_callback: Option<Box<ReceiveCallback<'a>>>,
+}
+
+/// Driver to exchange datagrams with other devices over ESP-NOW, without an access point.
+/// Messages can be sent to the registered peers, or broadcasted to every device on the channel.
+/// ESP-NOW does not confirm that messages arrive, so for acknowledged messages between two paired
+/// framework devices a [crate::peer::PeerLink] can be used instead. Both use the same receive
+/// callback of ESP-NOW, so only one of them can be created.
+pub struct EspNow<'a> {
+    inner: SharableRef<_EspNow<'a>>,
+}
+
+impl EspNowMessage {
+    /// Decodes a message received through the queue, prefixed with the MAC address of the
+    /// sender and the rssi.
+    fn from_queue_bytes(bytes: Vec<u8>) -> Option<Self> {
+        if bytes.len() < MESSAGE_HEADER_SIZE {
+            return None;
+        }
+        let mut source = [0; MAC_SIZE];
+        source.copy_from_slice(&bytes[..MAC_SIZE]);
+        Some(EspNowMessage {
+            source,
+            rssi: bytes[MAC_SIZE] as i8,
+            data: bytes[MESSAGE_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _EspNow<'a> {
+    /// Creates a new _EspNow. The wifi must already be started.
+    ///
+    /// # Arguments
+    ///
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a message is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new _EspNow, or an `EspNowError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EspNowError::InitializationError`: If ESP-NOW could not be initialized
+    fn new(notifier: Notifier) -> Result<Self, EspNowError> {
+        let message_queue = ISRByteArrayQueue::new(queue_capacities().peer_frames);
+        *ESP_NOW_CHANNEL.lock().unwrap() = Some((message_queue.clone(), notifier));
+        unsafe {
+            if esp_now_init() != ESP_OK
+                || esp_now_register_recv_cb(Some(receive_callback)) != ESP_OK
+            {
+                return Err(EspNowError::InitializationError);
+            }
+        }
+        Ok(_EspNow {
+            message_queue,
+            peers: vec![],
+            receive_callback: None,
+        })
+    }
+
+    /// Registers a device as a peer, so messages can be sent to it. Adding a peer that is
+    /// already registered does nothing.
+    ///
+    /// # Arguments
+    ///
+    /// - `mac`: The MAC address of the device
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the device is registered, or an `EspNowError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EspNowError::PeerError`: If the device could not be registered, for example if there
+    ///   are already 20 peers
+    pub fn add_peer(&mut self, mac: [u8; MAC_SIZE]) -> Result<(), EspNowError> {
+        if self.peers.len() >= ESP_NOW_MAX_TOTAL_PEER_NUM as usize && !self.peers.contains(&mac) {
+            return Err(EspNowError::PeerError);
+        }
+        add_esp_now_peer(&mac)?;
+        if !self.peers.contains(&mac) {
+            self.peers.push(mac);
+        }
+        Ok(())
+    }
+
+    /// Removes a peer registered with [Self::add_peer]
+    ///
+    /// # Arguments
+    ///
+    /// - `mac`: The MAC address of the device
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the peer was removed, or an `EspNowError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EspNowError::PeerError`: If the device is not a peer
+    pub fn remove_peer(&mut self, mac: [u8; MAC_SIZE]) -> Result<(), EspNowError> {
+        if !self.peers.contains(&mac) {
+            return Err(EspNowError::PeerError);
+        }
+        unsafe { esp_now_del_peer(mac.as_ptr()) };
+        self.peers.retain(|peer| *peer != mac);
+        Ok(())
+    }
+
+    /// Gets the MAC addresses of the registered peers
+    ///
+    /// # Returns
+    ///
+    /// A Vec with the MAC address of each peer
+    pub fn peers(&self) -> Vec<[u8; MAC_SIZE]> {
+        self.peers.clone()
+    }
+
+    /// Sends a message to a peer. The message is only queued for transmission, ESP-NOW does not
+    /// confirm that it arrives.
+    ///
+    /// # Arguments
+    ///
+    /// - `destination`: The MAC address of a peer added with [Self::add_peer]
+    /// - `data`: The bytes to send, up to 250 bytes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the message was queued, or an `EspNowError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EspNowError::PeerError`: If the destination is not a peer
+    /// - `EspNowError::MessageTooLong`: If the message is longer than 250 bytes
+    /// - `EspNowError::SendError`: If the message could not be sent
+    pub fn send(&mut self, destination: [u8; MAC_SIZE], data: &[u8]) -> Result<(), EspNowError> {
+        if !self.peers.contains(&destination) {
+            return Err(EspNowError::PeerError);
+        }
+        send_message(&destination, data)
+    }
+
+    /// Sends a message to every device listening on the channel, registering the broadcast
+    /// address as a peer if needed.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes to send, up to 250 bytes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the message was queued, or an `EspNowError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EspNowError::PeerError`: If the broadcast address could not be registered
+    /// - `EspNowError::MessageTooLong`: If the message is longer than 250 bytes
+    /// - `EspNowError::SendError`: If the message could not be sent
+    pub fn broadcast(&mut self, data: &[u8]) -> Result<(), EspNowError> {
+        add_esp_now_peer(&ESP_NOW_BROADCAST_ADDRESS)?;
+        send_message(&ESP_NOW_BROADCAST_ADDRESS, data)
+    }
+
+    /// Sets the callback that will be executed for each message received, from any device.
+    /// Messages received while the amount of pending messages is at the `peer_frames` capacity of
+    /// [crate::utils::queue_config::QueueCapacities] are dropped.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives each message
+    ///
+    /// # Returns
+    ///
+    /// The _EspNow itself
+    pub fn on_receive<C: FnMut(&EspNowMessage) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.receive_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Handles every message received since the last call, executing the user callback
+    fn handle_messages(&mut self) {
+        while let Ok(bytes) = self.message_queue.try_recv() {
+            let Some(message) = EspNowMessage::from_queue_bytes(bytes) else {
+                continue;
+            };
+            if let Some(callback) = self.receive_callback.as_mut() {
+                callback(&message);
+            }
+        }
+    }
+}
+
+impl<'a> EspNow<'a> {
+    /// Creates a new EspNow. The wifi must already be started.
+    ///
+    /// # Arguments
+    ///
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a message is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new EspNow, or an `EspNowError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EspNowError::InitializationError`: If ESP-NOW could not be initialized
+    pub(crate) fn new(notifier: Notifier) -> Result<Self, EspNowError> {
+        Ok(Self {
+            inner: SharableRef::new_sharable(_EspNow::new(notifier)?),
+        })
+    }
+}
+
+impl<'a> InterruptDriver<'a> for EspNow<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().handle_messages();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+/// Registers a device as an ESP-NOW peer on the current channel, if it is not already registered
+///
+/// # Arguments
+///
+/// - `mac`: The MAC address of the device
+///
+/// # Returns
+///
+/// A `Result` with Ok if the device is registered, or an `EspNowError` if it fails.
+///
+/// # Errors
+///
+/// - `EspNowError::PeerError`: If the device could not be registered
+fn add_esp_now_peer(mac: &[u8; MAC_SIZE]) -> Result<(), EspNowError> {
+    if unsafe { esp_now_is_peer_exist(mac.as_ptr()) } {
+        return Ok(());
+    }
+    let peer_info = esp_now_peer_info_t {
+        peer_addr: *mac,
+        channel: 0,
+        ifidx: wifi_interface_t_WIFI_IF_STA,
+        encrypt: false,
+        ..Default::default()
+    };
+    match unsafe { esp_now_add_peer(&peer_info) } {
+        ESP_OK => Ok(()),
+        _ => Err(EspNowError::PeerError),
+    }
+}
+
+/// Sends a message through ESP-NOW
+///
+/// # Arguments
+///
+/// - `destination`: The MAC address of the receiver
+/// - `data`: The bytes to send
+///
+/// # Returns
+///
+/// A `Result` with Ok if the message was queued for transmission, or an `EspNowError` if it fails.
+///
+/// # Errors
+///
+/// - `EspNowError::MessageTooLong`: If the message is longer than 250 bytes
+/// - `EspNowError::SendError`: If the message could not be sent
+fn send_message(destination: &[u8; MAC_SIZE], data: &[u8]) -> Result<(), EspNowError> {
+    if data.len() > ESP_NOW_MAX_DATA_LEN as usize {
+        return Err(EspNowError::MessageTooLong);
+    }
+    match unsafe { esp_now_send(destination.as_ptr(), data.as_ptr(), data.len()) } {
+        ESP_OK => Ok(()),
+        _ => Err(EspNowError::SendError),
+    }
+}
+
+/// Callback executed by the wifi task for each ESP-NOW message received. It sends the message
+/// through the queue, prefixed with the MAC address of the sender and the rssi, and wakes up the
+/// microcontroller.
+unsafe extern "C" fn receive_callback(
+    info: *const esp_now_recv_info_t,
+    data: *const u8,
+    len: c_int,
+) {
+    let Ok(mut channel) = ESP_NOW_CHANNEL.try_lock() else {
+        return;
+    };
+    let Some((queue, notifier)) = channel.as_mut() else {
+        return;
+    };
+    if info.is_null() || data.is_null() || len <= 0 {
+        return;
+    }
+    let source = std::slice::from_raw_parts((*info).src_addr, MAC_SIZE);
+    let rssi = match (*info).rx_ctrl.as_ref() {
+        Some(rx_ctrl) => rx_ctrl.rssi() as i8,
+        None => 0,
+    };
+    let payload = std::slice::from_raw_parts(data, len as usize);
+
+    let mut bytes = Vec::with_capacity(MESSAGE_HEADER_SIZE + payload.len());
+    bytes.extend_from_slice(source);
+    bytes.push(rssi as u8);
+    bytes.extend_from_slice(payload);
+
+    if queue.try_send(bytes).is_ok() {
+        notifier.notify();
+    }
+}
+
+impl From<WifiError> for EspNowError {
+    fn from(value: WifiError) -> Self {
+        EspNowError::WifiError(value)
+    }
+}
@@ -0,0 +1,5 @@
+mod esp_now;
+mod peer_link;
+
+pub use esp_now::*;
+pub use peer_link::*;
@@ -0,0 +1,609 @@
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        isr_queues::{ISRByteArrayQueue, ISRQueueTrait},
+        notification::Notifier,
+        queue_config::queue_capacities,
+    },
+    wifi::WifiError,
+    InterruptDriver,
+};
+use esp_idf_svc::{
+    nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault},
+    sys::{
+        esp_now_add_peer, esp_now_del_peer, esp_now_init, esp_now_is_peer_exist,
+        esp_now_peer_info_t, esp_now_recv_info_t, esp_now_register_recv_cb, esp_now_send,
+        wifi_interface_t_WIFI_IF_STA, ESP_NOW_MAX_DATA_LEN, ESP_OK,
+    },
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    collections::VecDeque,
+    ffi::c_int,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const MAC_SIZE: usize = 6;
+const BROADCAST_ADDRESS: [u8; MAC_SIZE] = [0xFF; MAC_SIZE];
+/// Every frame starts with the magic bytes, the frame type and the sequence number
+const FRAME_HEADER_SIZE: usize = 5;
+const FRAME_MAGIC: [u8; 2] = *b"EF";
+const MAX_MESSAGE_SIZE: usize = ESP_NOW_MAX_DATA_LEN as usize - FRAME_HEADER_SIZE;
+const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_millis(100);
+const DEFAULT_RETRIES: u8 = 5;
+const NVS_NAMESPACE: &str = "esp32fw_peer";
+const NVS_PEER_KEY: &str = "peer_mac";
+
+/// Queue and notifier used by the ESP-NOW receive callback, which does not receive any user data
+static PEER_CHANNEL: Mutex<Option<(ISRByteArrayQueue, Notifier)>> = Mutex::new(None);
+
+type MessageCallback<'a> = dyn FnMut(&[u8]) + 'a;
+type PairedCallback<'a> = dyn FnMut([u8; MAC_SIZE]) + 'a;
+
+/// Enums the different errors possible when working with a PeerLink
+#[derive(Debug)]
+pub enum PeerError {
+    EspNowError,
+    MessageTooLong,
+    NoAcknowledgement,
+    NotPaired,
+    NvsError,
+    WifiError(WifiError),
+}
+
+/// Enums the types of frames exchanged between two peers
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FrameType {
+    PairRequest,
+    PairAccept,
+    Data,
+    Ack,
+}
+
+/// A frame received from another device:
+/// - `source`: The MAC address of the sender.
+/// - `frame_type`: The type of the frame.
+/// - `seq`: The sequence number of the frame.
+/// - `payload`: The message carried by a data frame.
+struct PeerFrame {
+    source: [u8; MAC_SIZE],
+    frame_type: FrameType,
+    seq: u16,
+    payload: Vec<u8>,
+}
+
+/// Link between two framework devices over ESP-NOW. Devices are paired by calling
+/// [PeerLink::start_pairing] on both of them within the pairing window (for example when a button
+/// is pressed). The binding is stored in the Non-Volatile Storage, so it survives reboots.
+/// Once paired, messages are acknowledged and retransmitted until they are received.
+struct _PeerLink<'a> {
+    nvs: EspNvs<NvsDefault>,
+    peer: Option<[u8; MAC_SIZE]>,
+    pairing_deadline: Option<Instant>,
+    frame_queue: ISRByteArrayQueue,
+    next_seq: u16,
+    last_received_seq: Option<u16>,
+    pending_messages: VecDeque<Vec<u8>>,
+    newly_paired: Option<[u8; MAC_SIZE]>,
+    ack_timeout: Duration,
+    retries: u8,
+    message_callback: Option<Box<MessageCallback<'a>>>,
+    paired_callback: Option<Box<PairedCallback<'a>>>,
+}
+
+/// Link between two framework devices over ESP-NOW. Devices are paired by calling
+/// [PeerLink::start_pairing] on both of them within the pairing window (for example when a button
+/// is pressed). The binding is stored in the Non-Volatile Storage, so it survives reboots.
+/// Once paired, messages are acknowledged and retransmitted until they are received.
+pub struct PeerLink<'a> {
+    inner: SharableRef<_PeerLink<'a>>,
+}
+
+impl FrameType {
+    /// Gets the code used to send the frame type
+    fn get_code(&self) -> u8 {
+        match self {
+            FrameType::PairRequest => 0,
+            FrameType::PairAccept => 1,
+            FrameType::Data => 2,
+            FrameType::Ack => 3,
+        }
+    }
+
+    /// Creates a FrameType from its code
+    fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(FrameType::PairRequest),
+            1 => Some(FrameType::PairAccept),
+            2 => Some(FrameType::Data),
+            3 => Some(FrameType::Ack),
+            _ => None,
+        }
+    }
+}
+
+impl PeerFrame {
+    /// Decodes a frame received through the queue. The first bytes are the MAC address of the
+    /// sender, the rest is the frame itself.
+    fn from_queue_bytes(bytes: Vec<u8>) -> Option<Self> {
+        if bytes.len() < MAC_SIZE + FRAME_HEADER_SIZE
+            || bytes[MAC_SIZE..MAC_SIZE + 2] != FRAME_MAGIC
+        {
+            return None;
+        }
+        let mut source = [0; MAC_SIZE];
+        source.copy_from_slice(&bytes[..MAC_SIZE]);
+        Some(PeerFrame {
+            source,
+            frame_type: FrameType::from_code(bytes[MAC_SIZE + 2])?,
+            seq: u16::from_le_bytes([bytes[MAC_SIZE + 3], bytes[MAC_SIZE + 4]]),
+            payload: bytes[MAC_SIZE + FRAME_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _PeerLink<'a> {
+    /// Creates a new _PeerLink, restoring the peer stored in the Non-Volatile Storage if any.
+    /// The wifi must already be started.
+    ///
+    /// # Arguments
+    ///
+    /// - `nvs_partition`: The EspDefaultNvsPartition where the binding is stored
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a frame is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new _PeerLink, or a `PeerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeerError::NvsError`: If the Non-Volatile Storage could not be read
+    /// - `PeerError::EspNowError`: If ESP-NOW could not be initialized
+    fn new(nvs_partition: EspDefaultNvsPartition, notifier: Notifier) -> Result<Self, PeerError> {
+        let nvs =
+            EspNvs::new(nvs_partition, NVS_NAMESPACE, true).map_err(|_| PeerError::NvsError)?;
+        let mut buf = [0; MAC_SIZE];
+        let stored = nvs
+            .get_raw(NVS_PEER_KEY, &mut buf)
+            .map_err(|_| PeerError::NvsError)?
+            .map_or(false, |stored| stored.len() == MAC_SIZE);
+        let peer = stored.then_some(buf);
+
+        let frame_queue = ISRByteArrayQueue::new(queue_capacities().peer_frames);
+        *PEER_CHANNEL.lock().unwrap() = Some((frame_queue.clone(), notifier));
+        unsafe {
+            if esp_now_init() != ESP_OK
+                || esp_now_register_recv_cb(Some(receive_callback)) != ESP_OK
+            {
+                return Err(PeerError::EspNowError);
+            }
+        }
+        if let Some(mac) = peer {
+            add_esp_now_peer(&mac)?;
+        }
+
+        Ok(_PeerLink {
+            nvs,
+            peer,
+            pairing_deadline: None,
+            frame_queue,
+            next_seq: 0,
+            last_received_seq: None,
+            pending_messages: VecDeque::new(),
+            newly_paired: None,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            message_callback: None,
+            paired_callback: None,
+        })
+    }
+
+    /// Opens the pairing window and announces this device. If the other device opens its
+    /// pairing window before this one closes, both devices get paired, replacing any
+    /// previous binding. Meant to be called when the user presses the pairing button.
+    ///
+    /// # Arguments
+    ///
+    /// - `window`: Time during which this device accepts a pairing
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the pairing request was sent, or a `PeerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeerError::EspNowError`: If the pairing request could not be sent
+    pub fn start_pairing(&mut self, window: Duration) -> Result<(), PeerError> {
+        self.pairing_deadline = Some(Instant::now() + window);
+        add_esp_now_peer(&BROADCAST_ADDRESS)?;
+        send_frame(&BROADCAST_ADDRESS, FrameType::PairRequest, 0, &[])
+    }
+
+    /// Closes the pairing window, keeping the current binding
+    pub fn stop_pairing(&mut self) {
+        self.pairing_deadline = None;
+    }
+
+    /// Checks if the pairing window is open
+    ///
+    /// # Returns
+    ///
+    /// True if this device accepts a pairing, False if not
+    pub fn is_pairing(&self) -> bool {
+        self.pairing_deadline
+            .map_or(false, |deadline| Instant::now() < deadline)
+    }
+
+    /// Checks if this device is paired with another one
+    ///
+    /// # Returns
+    ///
+    /// True if there is a peer, False if not
+    pub fn is_paired(&self) -> bool {
+        self.peer.is_some()
+    }
+
+    /// Gets the MAC address of the peer
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the MAC address, or None if the device is not paired
+    pub fn peer_address(&self) -> Option<[u8; MAC_SIZE]> {
+        self.peer
+    }
+
+    /// Forgets the peer, removing the binding from the Non-Volatile Storage
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the binding was removed, or a `PeerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeerError::NvsError`: If the binding could not be removed from the Non-Volatile Storage
+    pub fn unpair(&mut self) -> Result<(), PeerError> {
+        if let Some(mac) = self.peer.take() {
+            unsafe { esp_now_del_peer(mac.as_ptr()) };
+        }
+        self.last_received_seq = None;
+        self.nvs
+            .remove(NVS_PEER_KEY)
+            .map_err(|_| PeerError::NvsError)?;
+        Ok(())
+    }
+
+    /// Sets the time waited for each acknowledgement and the amount of retransmissions made
+    /// before a message is considered lost. By default 100ms and 5 retries are used.
+    ///
+    /// # Arguments
+    ///
+    /// - `ack_timeout`: Time waited for the acknowledgement of each transmission
+    /// - `retries`: Amount of retransmissions after the first one
+    pub fn set_retransmission(&mut self, ack_timeout: Duration, retries: u8) {
+        self.ack_timeout = ack_timeout;
+        self.retries = retries;
+    }
+
+    /// Sends a message to the peer, blocking until it is acknowledged. Messages received
+    /// meanwhile are kept and delivered on the next update.
+    ///
+    /// # Arguments
+    ///
+    /// - `message`: The bytes to send, up to 245 bytes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the peer acknowledged the message, or a `PeerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeerError::NotPaired`: If the device is not paired
+    /// - `PeerError::MessageTooLong`: If the message is longer than 245 bytes
+    /// - `PeerError::EspNowError`: If the message could not be sent
+    /// - `PeerError::NoAcknowledgement`: If the peer did not acknowledge any retransmission
+    pub fn send(&mut self, message: &[u8]) -> Result<(), PeerError> {
+        let peer = self.peer.ok_or(PeerError::NotPaired)?;
+        if message.len() > MAX_MESSAGE_SIZE {
+            return Err(PeerError::MessageTooLong);
+        }
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        for _ in 0..=self.retries {
+            send_frame(&peer, FrameType::Data, seq, message)?;
+            if self.wait_for_ack(seq) {
+                return Ok(());
+            }
+        }
+        Err(PeerError::NoAcknowledgement)
+    }
+
+    /// Sets the callback that will be executed for each message received from the peer.
+    /// Retransmissions of an already received message are discarded.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives each message
+    ///
+    /// # Returns
+    ///
+    /// The _PeerLink itself
+    pub fn on_message<C: FnMut(&[u8]) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.message_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the callback that will be executed when the device gets paired.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the MAC address of the new peer
+    ///
+    /// # Returns
+    ///
+    /// The _PeerLink itself
+    pub fn on_paired<C: FnMut([u8; MAC_SIZE]) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.paired_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Waits for the acknowledgement of a message, handling every other frame received meanwhile
+    ///
+    /// # Arguments
+    ///
+    /// - `seq`: The sequence number of the message
+    ///
+    /// # Returns
+    ///
+    /// True if the message was acknowledged, False if the time ran out
+    fn wait_for_ack(&mut self, seq: u16) -> bool {
+        let deadline = Instant::now() + self.ack_timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            let micros = remaining.as_micros().try_into().unwrap_or(u32::MAX);
+            let Ok(bytes) = self.frame_queue.receive_timeout(micros) else {
+                return false;
+            };
+            if let Some(frame) = PeerFrame::from_queue_bytes(bytes) {
+                if self.handle_frame(frame) == Some(seq) {
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Handles a frame according to its type
+    ///
+    /// # Arguments
+    ///
+    /// - `frame`: The frame received
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the sequence number acknowledged, or None if the frame is not an
+    /// acknowledgement from the peer
+    fn handle_frame(&mut self, frame: PeerFrame) -> Option<u16> {
+        match frame.frame_type {
+            FrameType::PairRequest if self.is_pairing() => {
+                if self.pair_with(frame.source).is_ok() {
+                    _ = send_frame(&frame.source, FrameType::PairAccept, 0, &[]);
+                }
+                None
+            }
+            FrameType::PairAccept if self.is_pairing() => {
+                _ = self.pair_with(frame.source);
+                None
+            }
+            FrameType::Data if self.peer == Some(frame.source) => {
+                _ = send_frame(&frame.source, FrameType::Ack, frame.seq, &[]);
+                if self.last_received_seq != Some(frame.seq) {
+                    self.last_received_seq = Some(frame.seq);
+                    self.pending_messages.push_back(frame.payload);
+                }
+                None
+            }
+            FrameType::Ack if self.peer == Some(frame.source) => Some(frame.seq),
+            _ => None,
+        }
+    }
+
+    /// Binds this device to another one, storing the binding in the Non-Volatile Storage
+    /// and closing the pairing window.
+    ///
+    /// # Arguments
+    ///
+    /// - `mac`: The MAC address of the new peer
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the devices got paired, or a `PeerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeerError::EspNowError`: If the peer could not be registered
+    /// - `PeerError::NvsError`: If the binding could not be stored
+    fn pair_with(&mut self, mac: [u8; MAC_SIZE]) -> Result<(), PeerError> {
+        if let Some(old) = self.peer.filter(|old| *old != mac) {
+            unsafe { esp_now_del_peer(old.as_ptr()) };
+        }
+        add_esp_now_peer(&mac)?;
+        self.nvs
+            .set_raw(NVS_PEER_KEY, &mac)
+            .map_err(|_| PeerError::NvsError)?;
+        self.peer = Some(mac);
+        self.last_received_seq = None;
+        self.pairing_deadline = None;
+        self.newly_paired = Some(mac);
+        Ok(())
+    }
+
+    /// Handles every frame received since the last call and executes the user callbacks
+    fn handle_frames(&mut self) {
+        while let Ok(bytes) = self.frame_queue.try_recv() {
+            if let Some(frame) = PeerFrame::from_queue_bytes(bytes) {
+                self.handle_frame(frame);
+            }
+        }
+        if let Some(mac) = self.newly_paired.take() {
+            if let Some(callback) = self.paired_callback.as_mut() {
+                callback(mac);
+            }
+        }
+        while let Some(message) = self.pending_messages.pop_front() {
+            if let Some(callback) = self.message_callback.as_mut() {
+                callback(&message);
+            }
+        }
+    }
+}
+
+impl<'a> PeerLink<'a> {
+    /// Creates a new PeerLink, restoring the peer stored in the Non-Volatile Storage if any.
+    /// The wifi must already be started.
+    ///
+    /// # Arguments
+    ///
+    /// - `nvs_partition`: The EspDefaultNvsPartition where the binding is stored
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a frame is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new PeerLink, or a `PeerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `PeerError::NvsError`: If the Non-Volatile Storage could not be read
+    /// - `PeerError::EspNowError`: If ESP-NOW could not be initialized
+    pub(crate) fn new(
+        nvs_partition: EspDefaultNvsPartition,
+        notifier: Notifier,
+    ) -> Result<Self, PeerError> {
+        Ok(Self {
+            inner: SharableRef::new_sharable(_PeerLink::new(nvs_partition, notifier)?),
+        })
+    }
+}
+
+impl<'a> InterruptDriver<'a> for PeerLink<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().handle_frames();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+/// Registers a device as an ESP-NOW peer if it is not already registered
+///
+/// # Arguments
+///
+/// - `mac`: The MAC address of the device
+///
+/// # Returns
+///
+/// A `Result` with Ok if the device is registered, or a `PeerError` if it fails.
+///
+/// # Errors
+///
+/// - `PeerError::EspNowError`: If the device could not be registered
+fn add_esp_now_peer(mac: &[u8; MAC_SIZE]) -> Result<(), PeerError> {
+    if unsafe { esp_now_is_peer_exist(mac.as_ptr()) } {
+        return Ok(());
+    }
+    let peer_info = esp_now_peer_info_t {
+        peer_addr: *mac,
+        channel: 0,
+        ifidx: wifi_interface_t_WIFI_IF_STA,
+        encrypt: false,
+        ..Default::default()
+    };
+    match unsafe { esp_now_add_peer(&peer_info) } {
+        ESP_OK => Ok(()),
+        _ => Err(PeerError::EspNowError),
+    }
+}
+
+/// Sends a frame through ESP-NOW
+///
+/// # Arguments
+///
+/// - `destination`: The MAC address of the receiver
+/// - `frame_type`: The type of the frame
+/// - `seq`: The sequence number of the frame
+/// - `payload`: The message carried by the frame
+///
+/// # Returns
+///
+/// A `Result` with Ok if the frame was queued for transmission, or a `PeerError` if it fails.
+///
+/// # Errors
+///
+/// - `PeerError::EspNowError`: If the frame could not be sent
+fn send_frame(
+    destination: &[u8; MAC_SIZE],
+    frame_type: FrameType,
+    seq: u16,
+    payload: &[u8],
+) -> Result<(), PeerError> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    frame.extend_from_slice(&FRAME_MAGIC);
+    frame.push(frame_type.get_code());
+    frame.extend_from_slice(&seq.to_le_bytes());
+    frame.extend_from_slice(payload);
+    match unsafe { esp_now_send(destination.as_ptr(), frame.as_ptr(), frame.len()) } {
+        ESP_OK => Ok(()),
+        _ => Err(PeerError::EspNowError),
+    }
+}
+
+/// Callback executed by the wifi task for each ESP-NOW frame received. It sends the frame
+/// through the queue, prefixed with the MAC address of the sender, and wakes up the microcontroller.
+unsafe extern "C" fn receive_callback(
+    info: *const esp_now_recv_info_t,
+    data: *const u8,
+    len: c_int,
+) {
+    let Ok(mut channel) = PEER_CHANNEL.try_lock() else {
+        return;
+    };
+    let Some((queue, notifier)) = channel.as_mut() else {
+        return;
+    };
+    if info.is_null() || data.is_null() || len <= 0 {
+        return;
+    }
+    let source = std::slice::from_raw_parts((*info).src_addr, MAC_SIZE);
+    let payload = std::slice::from_raw_parts(data, len as usize);
+
+    let mut bytes = Vec::with_capacity(MAC_SIZE + payload.len());
+    bytes.extend_from_slice(source);
+    bytes.extend_from_slice(payload);
+
+    if queue.try_send(bytes).is_ok() {
+        notifier.notify();
+    }
+}
+
+impl From<WifiError> for PeerError {
+    fn from(value: WifiError) -> Self {
+        PeerError::WifiError(value)
+    }
+}
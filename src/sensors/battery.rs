@@ -0,0 +1,339 @@
+use crate::{
+    ble::{
+        utils::{
+            ble_standard_uuids::{StandardCharacteristicId, StandardServiceId},
+            Characteristic, Service,
+        },
+        BleError, BleId, BleServer,
+    },
+    gpio::{analog::AnalogIn, analog::AnalogInError, digital::DigitalIn},
+    serial::i2c::{I2CError, I2CMaster},
+    utils::units::Millivolts,
+};
+use esp_idf_svc::hal::{delay::BLOCK, gpio::Level};
+
+const MAX17048_ADDR: u8 = 0x36;
+const MAX17048_VCELL_ADDR: u8 = 0x02;
+const MAX17048_SOC_ADDR: u8 = 0x04;
+/// Each bit of the VCELL register is 78.125 uV
+const MAX17048_UV_PER_BIT: u32 = 78125;
+const MAX_PERCENTAGE: u8 = 100;
+/// Margin above the low battery threshold needed to report a low battery again
+const LOW_BATTERY_HYSTERESIS: u8 = 5;
+
+/// Discharge curve of a typical single cell LiPo battery, as (millivolts, percentage)
+const DEFAULT_DISCHARGE_CURVE: [(u16, u8); 9] = [
+    (3300, 0),
+    (3500, 5),
+    (3600, 10),
+    (3700, 20),
+    (3800, 40),
+    (3900, 60),
+    (4000, 80),
+    (4100, 90),
+    (4200, 100),
+];
+
+type LowBatteryCallback<'a> = dyn FnMut(&BatteryStatus) + 'a;
+
+/// Enums the different errors possible when working with the Battery
+#[derive(Debug)]
+pub enum BatteryError {
+    AnalogInError(AnalogInError),
+    BleError(BleError),
+    I2CError(I2CError),
+    InvalidCurve,
+}
+
+/// Enums the ways the battery can be measured:
+/// - `Divider`: An AnalogIn connected to the battery through a voltage divider. The `ratio` is the
+///   battery voltage over the voltage on the pin, for example 2.0 for two equal resistors.
+/// - `Max17048`: A MAX17048 fuel gauge connected through I2C, which estimates the charge itself.
+pub enum BatterySource<'a> {
+    Divider { input: AnalogIn<'a>, ratio: f32 },
+    Max17048(I2CMaster<'a>),
+}
+
+/// State of the battery:
+/// - `voltage_mv`: The voltage of the battery in millivolts.
+/// - `percentage`: The estimated charge of the battery, from 0 to 100.
+/// - `charging`: Whether the battery is charging, None if there is no charging pin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryStatus {
+    pub voltage_mv: u16,
+    pub percentage: u8,
+    pub charging: Option<bool>,
+}
+
+/// Monitors a battery, estimating its charge from the voltage, and reporting when it runs low.
+/// The level can be published through the BLE Battery Service.
+pub struct Battery<'a> {
+    source: BatterySource<'a>,
+    discharge_curve: Vec<(u16, u8)>,
+    charging_pin: Option<(DigitalIn<'a>, Level)>,
+    low_threshold: u8,
+    low_reported: bool,
+    low_callback: Option<Box<LowBatteryCallback<'a>>>,
+}
+
+impl<'a> Battery<'a> {
+    /// Creates a new Battery with the discharge curve of a single cell LiPo battery
+    ///
+    /// # Arguments
+    ///
+    /// - `source`: The BatterySource used to measure the battery
+    ///
+    /// # Returns
+    ///
+    /// The new Battery
+    pub fn new(source: BatterySource<'a>) -> Self {
+        Battery {
+            source,
+            discharge_curve: DEFAULT_DISCHARGE_CURVE.to_vec(),
+            charging_pin: None,
+            low_threshold: 0,
+            low_reported: false,
+            low_callback: None,
+        }
+    }
+
+    /// Sets the discharge curve used to estimate the charge from the voltage. The charge is
+    /// interpolated between the points of the curve. Not used with a fuel gauge.
+    ///
+    /// # Arguments
+    ///
+    /// - `curve`: Points of the curve as (millivolts, percentage), in any order
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the curve was set, or a `BatteryError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BatteryError::InvalidCurve`: If the curve has less than 2 points or a percentage above 100
+    pub fn set_discharge_curve(&mut self, mut curve: Vec<(u16, u8)>) -> Result<(), BatteryError> {
+        if curve.len() < 2 || curve.iter().any(|(_, p)| *p > MAX_PERCENTAGE) {
+            return Err(BatteryError::InvalidCurve);
+        }
+        curve.sort_by_key(|(mv, _)| *mv);
+        self.discharge_curve = curve;
+        Ok(())
+    }
+
+    /// Sets the pin of the charger that indicates whether the battery is charging
+    ///
+    /// # Arguments
+    ///
+    /// - `pin`: The DigitalIn connected to the charger
+    /// - `charging_level`: The level of the pin while charging
+    pub fn set_charging_pin(&mut self, pin: DigitalIn<'a>, charging_level: Level) {
+        self.charging_pin = Some((pin, charging_level));
+    }
+
+    /// Sets the callback executed by [Self::update] when the charge drops to the threshold. It is
+    /// executed once, and again only after the charge goes 5% above the threshold.
+    ///
+    /// # Arguments
+    ///
+    /// - `threshold`: The percentage considered low
+    /// - `callback`: A closure that receives the status of the battery
+    pub fn on_low_battery<C: FnMut(&BatteryStatus) + 'a>(&mut self, threshold: u8, callback: C) {
+        self.low_threshold = threshold.min(MAX_PERCENTAGE);
+        self.low_reported = false;
+        self.low_callback = Some(Box::new(callback));
+    }
+
+    /// Reads the voltage of the battery
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the voltage in millivolts, or a `BatteryError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BatteryError::AnalogInError`: If the divider could not be read
+    /// - `BatteryError::I2CError`: If the fuel gauge could not be read
+    pub fn voltage_mv(&mut self) -> Result<u16, BatteryError> {
+        match &mut self.source {
+            BatterySource::Divider { input, ratio } => {
+                Ok((input.smooth_read(10)? as f32 * *ratio) as u16)
+            }
+            BatterySource::Max17048(i2c) => {
+                let vcell = read_max17048_register(i2c, MAX17048_VCELL_ADDR)? as u32;
+                Ok((vcell * MAX17048_UV_PER_BIT / 1_000_000) as u16)
+            }
+        }
+    }
+
+    /// Reads the voltage of the battery
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the Millivolts of the battery, or a `BatteryError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BatteryError::AnalogInError`: If the divider could not be read
+    /// - `BatteryError::I2CError`: If the fuel gauge could not be read
+    pub fn voltage(&mut self) -> Result<Millivolts, BatteryError> {
+        Ok(Millivolts(self.voltage_mv()? as u32))
+    }
+
+    /// Estimates the charge of the battery
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the percentage, from 0 to 100, or a `BatteryError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BatteryError::AnalogInError`: If the divider could not be read
+    /// - `BatteryError::I2CError`: If the fuel gauge could not be read
+    pub fn percentage(&mut self) -> Result<u8, BatteryError> {
+        if let BatterySource::Max17048(i2c) = &mut self.source {
+            let soc = read_max17048_register(i2c, MAX17048_SOC_ADDR)?;
+            return Ok(((soc >> 8) as u8).min(MAX_PERCENTAGE));
+        }
+        let voltage = self.voltage_mv()?;
+        Ok(interpolate_percentage(&self.discharge_curve, voltage))
+    }
+
+    /// Checks whether the battery is charging
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with true if charging, or None if no charging pin was set
+    pub fn is_charging(&self) -> Option<bool> {
+        self.charging_pin
+            .as_ref()
+            .map(|(pin, charging_level)| pin.get_level() == *charging_level)
+    }
+
+    /// Reads the state of the battery and executes the low battery callback if the charge
+    /// dropped to the threshold. Meant to be called periodically.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the BatteryStatus, or a `BatteryError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BatteryError::AnalogInError`: If the divider could not be read
+    /// - `BatteryError::I2CError`: If the fuel gauge could not be read
+    pub fn update(&mut self) -> Result<BatteryStatus, BatteryError> {
+        let status = BatteryStatus {
+            voltage_mv: self.voltage_mv()?,
+            percentage: self.percentage()?,
+            charging: self.is_charging(),
+        };
+
+        if status.percentage > self.low_threshold.saturating_add(LOW_BATTERY_HYSTERESIS) {
+            self.low_reported = false;
+        } else if status.percentage <= self.low_threshold && !self.low_reported {
+            if let Some(callback) = self.low_callback.as_mut() {
+                self.low_reported = true;
+                callback(&status);
+            }
+        }
+        Ok(status)
+    }
+
+    /// Creates the BLE Battery Service with a readable and notifiable Battery Level characteristic
+    /// holding the current charge.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the Battery Service ready to be set on a BleServer, or a `BatteryError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BatteryError::AnalogInError`: If the divider could not be read
+    /// - `BatteryError::I2CError`: If the fuel gauge could not be read
+    pub fn battery_service(&mut self) -> Result<Service, BatteryError> {
+        Ok(Service {
+            id: BleId::from_standard_service(StandardServiceId::Battery),
+            data: vec![],
+            characteristics: vec![self.battery_level_characteristic()?],
+        })
+    }
+
+    /// Notifies the current charge through the Battery Service of the server, which must have been
+    /// set with [Self::battery_service].
+    ///
+    /// # Arguments
+    ///
+    /// - `server`: The BleServer with the Battery Service
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the level was notified, or a `BatteryError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BatteryError::AnalogInError`: If the divider could not be read
+    /// - `BatteryError::I2CError`: If the fuel gauge could not be read
+    /// - `BatteryError::BleError`: If the Battery Service was not set on the server
+    pub fn publish(&mut self, server: &mut BleServer) -> Result<(), BatteryError> {
+        let characteristic = self.battery_level_characteristic()?;
+        server.notify_value(
+            &BleId::from_standard_service(StandardServiceId::Battery),
+            &characteristic,
+        )?;
+        Ok(())
+    }
+
+    /// Creates the Battery Level characteristic with the current charge
+    fn battery_level_characteristic(&mut self) -> Result<Characteristic, BatteryError> {
+        Ok(Characteristic::new(
+            &BleId::from_standard_characteristic(StandardCharacteristicId::BatteryLevel),
+            vec![self.percentage()?],
+        )
+        .readable(true)
+        .notifiable(true))
+    }
+}
+
+/// Reads a 16 bits big endian register of the MAX17048
+fn read_max17048_register(i2c: &mut I2CMaster, register: u8) -> Result<u16, I2CError> {
+    let mut buffer = [0; 2];
+    i2c.write_read(MAX17048_ADDR, &[register], &mut buffer, BLOCK)?;
+    Ok(u16::from_be_bytes(buffer))
+}
+
+/// Estimates the charge from the voltage, interpolating between the points of a discharge curve
+/// sorted by voltage.
+fn interpolate_percentage(curve: &[(u16, u8)], voltage_mv: u16) -> u8 {
+    let (first, last) = (curve[0], curve[curve.len() - 1]);
+    if voltage_mv <= first.0 {
+        return first.1;
+    }
+    if voltage_mv >= last.0 {
+        return last.1;
+    }
+    for window in curve.windows(2) {
+        let ((low_mv, low_p), (high_mv, high_p)) = (window[0], window[1]);
+        if voltage_mv <= high_mv && high_mv > low_mv {
+            let progress = (voltage_mv - low_mv) as f32 / (high_mv - low_mv) as f32;
+            return (low_p as f32 + progress * (high_p as f32 - low_p as f32)).round() as u8;
+        }
+    }
+    last.1
+}
+
+impl From<AnalogInError> for BatteryError {
+    fn from(value: AnalogInError) -> Self {
+        BatteryError::AnalogInError(value)
+    }
+}
+
+impl From<BleError> for BatteryError {
+    fn from(value: BleError) -> Self {
+        BatteryError::BleError(value)
+    }
+}
+
+impl From<I2CError> for BatteryError {
+    fn from(value: I2CError) -> Self {
+        BatteryError::I2CError(value)
+    }
+}
@@ -1,6 +1,9 @@
-use crate::serial::{
-    i2c::{I2CError, I2CMaster},
-    READER,
+use crate::{
+    serial::{
+        i2c::{I2CError, I2CMaster},
+        READER,
+    },
+    utils::units::Celsius,
 };
 use esp_idf_svc::hal::delay::BLOCK;
 use std::collections::HashMap;
@@ -673,6 +676,21 @@ impl<'a> DS3231<'a> {
 
         Ok(temp_integer + temp_fractional)
     }
+
+    /// Reads the temperature of the internal sensor of the DS3231, with a resolution of 0.25 °C.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the Celsius read, or an `I2CError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `I2CError::InvalidArg`: If an invalid argument is passed.
+    /// - `I2CError::BufferTooSmall`: If the buffer is too small.
+    /// - `I2CError::NoMoreHeapMemory`: If there isn't enough heap memory to perform the operation.
+    pub fn temperature(&mut self) -> Result<Celsius, I2CError> {
+        self.get_temperature().map(Celsius)
+    }
 }
 
 impl READER for DS3231<'_> {
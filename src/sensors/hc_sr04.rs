@@ -1,4 +1,7 @@
-use crate::gpio::digital::{DigitalIn, DigitalOut, DigitalOutError};
+use crate::{
+    gpio::digital::{DigitalIn, DigitalOut, DigitalOutError},
+    utils::units::Microseconds,
+};
 use esp_idf_svc::{hal::delay::Delay, sys::esp_timer_get_time};
 use std::sync::{atomic::AtomicU32, Arc};
 
@@ -40,6 +43,25 @@ impl<'a> HCSR04<'a> {
     ///
     /// - `DigitalOutError::InvalidPin`: If the trigger pin level cannot be set.
     pub fn get_distance(&mut self) -> Result<f64, DigitalOutError> {
+        let travel_time = self.echo_time()?;
+        let cm: f64 = SOUND_SPEED_CM_US * travel_time.0 as f64;
+        Ok(cm / 2.0) // We divide by 2 because if not we get the distance of the roundtrip
+    }
+
+    /// Sends an ultrasound burst and measures how long its echo takes to get back, which is the
+    /// time of the roundtrip to the object in front of the sensor.
+    ///
+    /// This function is blocking, since it has to wait for the echo of the ultrasound to get back.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the Microseconds of the roundtrip, or a `DigitalOutError` if the
+    /// reading fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DigitalOutError::InvalidPin`: If the trigger pin level cannot be set.
+    pub fn echo_time(&mut self) -> Result<Microseconds, DigitalOutError> {
         let delay = Delay::new_default();
 
         // First set the trigger to Low for a few micro-seconds to get a clean signal
@@ -60,8 +82,6 @@ impl<'a> HCSR04<'a> {
         }
         let rec_echo_time = unsafe { esp_timer_get_time() };
 
-        let travel_time = rec_echo_time - send_echo_time;
-        let cm: f64 = SOUND_SPEED_CM_US * travel_time as f64;
-        Ok(cm / 2.0) // We divide by 2 because if not we get the distance of the roundtrip
+        Ok(Microseconds((rec_echo_time - send_echo_time) as u64))
     }
 }
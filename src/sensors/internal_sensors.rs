@@ -0,0 +1,210 @@
+#[cfg(all(esp32, esp_idf_version_major = "4"))]
+use esp_idf_svc::sys::{adc1_config_width, adc_bits_width_t_ADC_WIDTH_BIT_12, hall_sensor_read};
+#[cfg(any(esp32s2, esp32s3))]
+use esp_idf_svc::sys::{
+    touch_pad_denoise_cap_t, touch_pad_denoise_disable, touch_pad_denoise_enable,
+    touch_pad_denoise_grade_t, touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT10,
+    touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT12,
+    touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT4,
+    touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT8, touch_pad_denoise_read_data,
+    touch_pad_denoise_set_config, touch_pad_denoise_t, touch_pad_fsm_start, touch_pad_init, ESP_OK,
+};
+
+const MAX_DENOISE_CAP_LEVEL: u8 = 7;
+
+/// Enums the different errors possible when working with the sensors built into the chip. On
+/// targets without the sensor, such as the ESP32-C6, every constructor returns `NotSupported`.
+#[derive(Debug)]
+pub enum InternalSensorError {
+    ConfigurationError,
+    InvalidArgument,
+    NotSupported,
+    ReadError,
+}
+
+/// Enums the resolution of the touch denoise channel. The lower the resolution, the stronger
+/// the noise reduction, but small touches may be filtered out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DenoiseGrade {
+    Bit12,
+    Bit10,
+    Bit8,
+    Bit4,
+}
+
+/// Hall effect sensor built into the classic ESP32. It measures the magnetic field
+/// perpendicular to the chip.
+///
+/// Note: Only available on the classic ESP32 with ESP-IDF v4, since ESP-IDF v5 removed the
+/// driver. The sensor uses the ADC1 channels 0 and 3 (GPIO36 and GPIO39), which must not be used
+/// as AnalogIn while reading it.
+pub struct HallSensor {
+    _private: (),
+}
+
+/// Denoise channel of the touch sensor of the ESP32-S2 and ESP32-S3. It measures the noise
+/// coupled to every touch pad, which the touch hardware substracts from each reading.
+pub struct TouchDenoise {
+    _private: (),
+}
+
+impl DenoiseGrade {
+    /// Gets the touch_pad_denoise_grade_t of the grade
+    #[cfg(any(esp32s2, esp32s3))]
+    fn get_code(&self) -> touch_pad_denoise_grade_t {
+        match self {
+            DenoiseGrade::Bit12 => touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT12,
+            DenoiseGrade::Bit10 => touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT10,
+            DenoiseGrade::Bit8 => touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT8,
+            DenoiseGrade::Bit4 => touch_pad_denoise_grade_t_TOUCH_PAD_DENOISE_BIT4,
+        }
+    }
+}
+
+impl HallSensor {
+    /// Creates a new HallSensor, setting the ADC1 width to 12 bits.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new HallSensor, or an `InternalSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `InternalSensorError::NotSupported`: If the target has no hall sensor
+    /// - `InternalSensorError::ConfigurationError`: If the ADC could not be configured
+    pub fn new() -> Result<Self, InternalSensorError> {
+        #[cfg(all(esp32, esp_idf_version_major = "4"))]
+        {
+            if unsafe { adc1_config_width(adc_bits_width_t_ADC_WIDTH_BIT_12) } != 0 {
+                return Err(InternalSensorError::ConfigurationError);
+            }
+            Ok(HallSensor { _private: () })
+        }
+        #[cfg(not(all(esp32, esp_idf_version_major = "4")))]
+        Err(InternalSensorError::NotSupported)
+    }
+
+    /// Reads the hall sensor. The value is not calibrated, it increases or decreases depending
+    /// on the polarity of the magnetic field.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the raw value read, or an `InternalSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `InternalSensorError::NotSupported`: If the target has no hall sensor
+    pub fn read(&mut self) -> Result<i32, InternalSensorError> {
+        #[cfg(all(esp32, esp_idf_version_major = "4"))]
+        {
+            Ok(unsafe { hall_sensor_read() })
+        }
+        #[cfg(not(all(esp32, esp_idf_version_major = "4")))]
+        Err(InternalSensorError::NotSupported)
+    }
+
+    /// Reads the hall sensor multiple times and returns the average value, in order to get a
+    /// more stable value.
+    ///
+    /// # Arguments
+    ///
+    /// - `amount_of_samples`: The number of times to read the sensor
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the average value, or an `InternalSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `InternalSensorError::InvalidArgument`: If the amount of samples is 0
+    /// - `InternalSensorError::NotSupported`: If the target has no hall sensor
+    pub fn smooth_read(&mut self, amount_of_samples: u16) -> Result<i32, InternalSensorError> {
+        if amount_of_samples == 0 {
+            return Err(InternalSensorError::InvalidArgument);
+        }
+        let mut total: i64 = 0;
+        for _ in 0..amount_of_samples {
+            total += self.read()? as i64;
+        }
+        Ok((total / amount_of_samples as i64) as i32)
+    }
+}
+
+impl TouchDenoise {
+    /// Initializes the touch sensor and enables its denoise channel.
+    ///
+    /// # Arguments
+    ///
+    /// - `grade`: The resolution of the denoise channel
+    /// - `cap_level`: The capacitance of the denoise channel, from 0 to 7. It should be the
+    ///   closest to the capacitance of the touch pads used.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TouchDenoise, or an `InternalSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `InternalSensorError::NotSupported`: If the target has no touch denoise channel
+    /// - `InternalSensorError::InvalidArgument`: If the cap level is greater than 7
+    /// - `InternalSensorError::ConfigurationError`: If the touch sensor could not be configured
+    pub fn new(grade: DenoiseGrade, cap_level: u8) -> Result<Self, InternalSensorError> {
+        if cap_level > MAX_DENOISE_CAP_LEVEL {
+            return Err(InternalSensorError::InvalidArgument);
+        }
+        #[cfg(any(esp32s2, esp32s3))]
+        {
+            let config = touch_pad_denoise_t {
+                grade: grade.get_code(),
+                cap_level: cap_level as touch_pad_denoise_cap_t,
+            };
+            unsafe {
+                if touch_pad_init() != ESP_OK
+                    || touch_pad_denoise_set_config(&config) != ESP_OK
+                    || touch_pad_denoise_enable() != ESP_OK
+                    || touch_pad_fsm_start() != ESP_OK
+                {
+                    return Err(InternalSensorError::ConfigurationError);
+                }
+            }
+            Ok(TouchDenoise { _private: () })
+        }
+        #[cfg(not(any(esp32s2, esp32s3)))]
+        {
+            _ = grade;
+            Err(InternalSensorError::NotSupported)
+        }
+    }
+
+    /// Reads the noise measured by the denoise channel
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the noise measured, or an `InternalSensorError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `InternalSensorError::NotSupported`: If the target has no touch denoise channel
+    /// - `InternalSensorError::ReadError`: If the denoise channel could not be read
+    pub fn read(&mut self) -> Result<u32, InternalSensorError> {
+        #[cfg(any(esp32s2, esp32s3))]
+        {
+            let mut noise = 0;
+            match unsafe { touch_pad_denoise_read_data(&mut noise) } {
+                ESP_OK => Ok(noise),
+                _ => Err(InternalSensorError::ReadError),
+            }
+        }
+        #[cfg(not(any(esp32s2, esp32s3)))]
+        Err(InternalSensorError::NotSupported)
+    }
+}
+
+impl Drop for TouchDenoise {
+    fn drop(&mut self) {
+        #[cfg(any(esp32s2, esp32s3))]
+        unsafe {
+            touch_pad_denoise_disable();
+        }
+    }
+}
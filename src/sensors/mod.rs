@@ -1,5 +1,9 @@
+mod battery;
 mod ds3231;
 mod hc_sr04;
+mod internal_sensors;
 
+pub use battery::*;
 pub use ds3231::*;
 pub use hc_sr04::*;
+pub use internal_sensors::*;
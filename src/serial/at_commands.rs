@@ -0,0 +1,582 @@
+use crate::{
+    ble::{utils::Service, BleBeacon, BleError, BleId},
+    serial::uart::{UARTError, UART},
+    wifi::{WifiDriver, WifiError},
+};
+use esp_idf_svc::hal::reset::restart;
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+
+const READ_BUFFER_SIZE: usize = 128;
+const MAX_LINE_LENGTH: usize = 256;
+const MAX_SEND_LENGTH: usize = 2048;
+const DEFAULT_JOIN_TIMEOUT: Duration = Duration::from_secs(15);
+const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Error types related to AT commands.
+#[derive(Debug)]
+pub enum AtCommandError {
+    BleError(BleError),
+    ConnectionError,
+    InvalidParameters,
+    NotAvailable,
+    NotConnected,
+    UartError(UARTError),
+    UnknownCommand,
+    WifiError(WifiError),
+}
+
+/// Lets the microcontroller be used as a wireless co-processor by another MCU, receiving AT commands
+/// over an UART in the style of the Espressif AT firmware. Each command is answered with `OK` or
+/// `ERROR`. The supported commands are:
+/// - `AT`: Checks the communication.
+/// - `ATE0` / `ATE1`: Disables or enables the echo of the received commands.
+/// - `AT+RST`: Restarts the microcontroller.
+/// - `AT+GMR`: Shows the version of the framework.
+/// - `AT+CWJAP="<ssid>","<password>"[,<timeout s>]`: Joins a wifi network.
+/// - `AT+CWJAP?`: Shows whether the wifi is connected, as `+CWJAP:<0|1>`.
+/// - `AT+CWLAP`: Lists the access points, as `+CWLAP:("<ssid>","<auth>",<rssi>)`.
+/// - `AT+CIFSR`: Shows the ip address, as `+CIFSR:STAIP,"<ip>"`.
+/// - `AT+CIPSTART="TCP","<host>",<port>`: Opens a TCP connection.
+/// - `AT+CIPSEND=<length>`: Answers `>` and sends the next `<length>` bytes through the TCP connection,
+///   answering `SEND OK` once sent.
+/// - `AT+CIPCLOSE`: Closes the TCP connection.
+/// - `AT+BLENAME="<name>"`: Sets the advertising name.
+/// - `AT+BLEADVDATA=<uuid>,<data>`: Advertises the service data, both in hexadecimal.
+/// - `AT+BLEADVSTART` / `AT+BLEADVSTOP`: Starts or stops advertising.
+///
+/// Data received on the TCP connection is written as `+IPD,<length>:<data>`, and `CLOSED` is written
+/// when the remote end closes it.
+pub struct AtCommandMode<'a> {
+    uart: UART<'a>,
+    wifi: Option<WifiDriver<'a>>,
+    beacon: Option<BleBeacon<'a>>,
+    tcp: Option<TcpStream>,
+    line: Vec<u8>,
+    pending_send: Option<(usize, Vec<u8>)>,
+    echo: bool,
+}
+
+impl<'a> AtCommandMode<'a> {
+    /// Creates a new AtCommandMode over an UART. Wifi and BLE commands answer `ERROR` until their
+    /// drivers are given with [Self::with_wifi] and [Self::with_ble_beacon].
+    ///
+    /// # Arguments
+    ///
+    /// - `uart`: The UART connected to the host MCU
+    ///
+    /// # Returns
+    ///
+    /// The new AtCommandMode
+    pub fn new(uart: UART<'a>) -> Self {
+        AtCommandMode {
+            uart,
+            wifi: None,
+            beacon: None,
+            tcp: None,
+            line: vec![],
+            pending_send: None,
+            echo: true,
+        }
+    }
+
+    /// Enables the wifi and TCP commands
+    ///
+    /// # Arguments
+    ///
+    /// - `wifi`: The WifiDriver used by the commands
+    ///
+    /// # Returns
+    ///
+    /// The AtCommandMode itself
+    pub fn with_wifi(mut self, wifi: WifiDriver<'a>) -> Self {
+        self.wifi = Some(wifi);
+        self
+    }
+
+    /// Enables the BLE commands
+    ///
+    /// # Arguments
+    ///
+    /// - `beacon`: The BleBeacon used to advertise
+    ///
+    /// # Returns
+    ///
+    /// The AtCommandMode itself
+    pub fn with_ble_beacon(mut self, beacon: BleBeacon<'a>) -> Self {
+        self.beacon = Some(beacon);
+        self
+    }
+
+    /// Reads the commands received by the UART and executes them, and forwards the data received
+    /// on the TCP connection. Must be called periodically.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or an `AtCommandError` if it fails.
+    /// Errors of the commands are answered with `ERROR` instead of being returned.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::UartError`: If reading or writing the UART failed
+    pub fn update(&mut self) -> Result<(), AtCommandError> {
+        let mut buffer = [0_u8; READ_BUFFER_SIZE];
+        loop {
+            let read = self.uart.read_with_timeout(&mut buffer, 0)?;
+            if read == 0 {
+                break;
+            }
+            for byte in &buffer[..read] {
+                self.handle_byte(*byte)?;
+            }
+        }
+        self.forward_tcp_data()
+    }
+
+    /// Handles a received byte, either as part of the data of an `AT+CIPSEND` or of a command.
+    ///
+    /// # Arguments
+    ///
+    /// - `byte`: The received byte
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::UartError`: If writing the UART failed
+    fn handle_byte(&mut self, byte: u8) -> Result<(), AtCommandError> {
+        if let Some((length, data)) = self.pending_send.as_mut() {
+            data.push(byte);
+            if data.len() == *length {
+                let data = std::mem::take(data);
+                self.pending_send = None;
+                let response = match self.send_tcp(&data) {
+                    Ok(()) => "SEND OK",
+                    Err(_) => "SEND FAIL",
+                };
+                self.respond(response)?;
+            }
+            return Ok(());
+        }
+
+        if self.echo {
+            self.uart.write(&[byte])?;
+        }
+        match byte {
+            b'\n' => {
+                let line = std::mem::take(&mut self.line);
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                if !line.is_empty() {
+                    self.run_command(line)?;
+                }
+            }
+            b'\r' => {}
+            _ if self.line.len() < MAX_LINE_LENGTH => self.line.push(byte),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Executes a command and writes its answer
+    ///
+    /// # Arguments
+    ///
+    /// - `line`: The command
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::UartError`: If writing the UART failed
+    fn run_command(&mut self, line: &str) -> Result<(), AtCommandError> {
+        let (name, params) = match line.split_once('=') {
+            Some((name, params)) => (name, split_params(params)),
+            None => (line, vec![]),
+        };
+        let result = self.execute(&name.to_ascii_uppercase(), &params);
+        match result {
+            Ok(lines) => {
+                for line in lines {
+                    self.respond(&line)?;
+                }
+                if self.pending_send.is_some() {
+                    self.uart.write(b">")?;
+                    return Ok(());
+                }
+                self.respond("OK")
+            }
+            Err(_) => self.respond("ERROR"),
+        }
+    }
+
+    /// Executes a command
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the command in upper case, including the `AT` prefix
+    /// - `params`: The parameters of the command, without quotes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the lines to answer before the `OK`, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::UnknownCommand`: If the command is not supported
+    /// - `AtCommandError::InvalidParameters`: If the parameters are missing or invalid
+    /// - `AtCommandError::NotAvailable`: If the driver needed by the command was not given
+    /// - Any error of the drivers used by the command
+    fn execute(&mut self, name: &str, params: &[String]) -> Result<Vec<String>, AtCommandError> {
+        match name {
+            "AT" => Ok(vec![]),
+            "ATE0" => {
+                self.echo = false;
+                Ok(vec![])
+            }
+            "ATE1" => {
+                self.echo = true;
+                Ok(vec![])
+            }
+            "AT+RST" => {
+                self.respond("OK")?;
+                restart();
+            }
+            "AT+GMR" => Ok(vec![format!("esp32framework version: {}", VERSION)]),
+            "AT+CWJAP" => self.join_wifi(params),
+            "AT+CWJAP?" => {
+                let connected = self.wifi()?.is_connected()?;
+                Ok(vec![format!("+CWJAP:{}", connected as u8)])
+            }
+            "AT+CWLAP" => Ok(self
+                .wifi()?
+                .scan()?
+                .iter()
+                .map(|ap| {
+                    format!(
+                        "+CWLAP:(\"{}\",\"{}\",{})",
+                        ap.ssid, ap.authentication_method, ap.signal_strength
+                    )
+                })
+                .collect()),
+            "AT+CIFSR" => {
+                let ip = self.wifi()?.get_address_info()?;
+                Ok(vec![format!("+CIFSR:STAIP,\"{}\"", ip)])
+            }
+            "AT+CIPSTART" => self.open_tcp(params),
+            "AT+CIPSEND" => {
+                self.tcp.as_ref().ok_or(AtCommandError::NotConnected)?;
+                let length: usize = param(params, 0)?;
+                if length == 0 || length > MAX_SEND_LENGTH {
+                    return Err(AtCommandError::InvalidParameters);
+                }
+                self.pending_send = Some((length, Vec::with_capacity(length)));
+                Ok(vec![])
+            }
+            "AT+CIPCLOSE" => {
+                self.tcp.take().ok_or(AtCommandError::NotConnected)?;
+                Ok(vec!["CLOSED".to_string()])
+            }
+            "AT+BLENAME" => {
+                let name: String = param(params, 0)?;
+                self.beacon()?.set_name(name);
+                Ok(vec![])
+            }
+            "AT+BLEADVDATA" => {
+                let uuid = u16::from_str_radix(&param::<String>(params, 0)?, 16)
+                    .map_err(|_| AtCommandError::InvalidParameters)?;
+                let data = parse_hex(&param::<String>(params, 1)?)?;
+                let id = BleId::FromUuid16(uuid);
+                let beacon = self.beacon()?;
+                beacon.set_service(&Service::new(&id, data)?)?;
+                beacon.advertise_service_data(&id)?;
+                Ok(vec![])
+            }
+            "AT+BLEADVSTART" => {
+                self.beacon()?.start()?;
+                Ok(vec![])
+            }
+            "AT+BLEADVSTOP" => {
+                self.beacon()?.stop()?;
+                Ok(vec![])
+            }
+            _ => Err(AtCommandError::UnknownCommand),
+        }
+    }
+
+    /// Joins a wifi network with the parameters of an `AT+CWJAP`
+    ///
+    /// # Arguments
+    ///
+    /// - `params`: The ssid, the password and optionally the timeout in seconds
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the lines to answer, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::InvalidParameters`: If the parameters are missing or invalid
+    /// - `AtCommandError::NotAvailable`: If the wifi driver was not given
+    /// - `AtCommandError::WifiError`: If the connection failed
+    fn join_wifi(&mut self, params: &[String]) -> Result<Vec<String>, AtCommandError> {
+        let ssid: String = param(params, 0)?;
+        let password = params
+            .get(1)
+            .filter(|password| !password.is_empty())
+            .cloned();
+        let timeout = match params.get(2) {
+            Some(_) => Duration::from_secs(param(params, 2)?),
+            None => DEFAULT_JOIN_TIMEOUT,
+        };
+        self.wifi()?.connect(&ssid, password, Some(timeout))?;
+        Ok(vec!["WIFI CONNECTED".to_string()])
+    }
+
+    /// Opens a TCP connection with the parameters of an `AT+CIPSTART`
+    ///
+    /// # Arguments
+    ///
+    /// - `params`: The type of connection, which must be "TCP", the host and the port
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the lines to answer, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::InvalidParameters`: If the parameters are missing or invalid
+    /// - `AtCommandError::NotAvailable`: If the wifi driver was not given
+    /// - `AtCommandError::ConnectionError`: If the connection could not be opened
+    fn open_tcp(&mut self, params: &[String]) -> Result<Vec<String>, AtCommandError> {
+        let kind: String = param(params, 0)?;
+        let host: String = param(params, 1)?;
+        let port: u16 = param(params, 2)?;
+        if !kind.eq_ignore_ascii_case("TCP") {
+            return Err(AtCommandError::InvalidParameters);
+        }
+        self.wifi()?;
+        let stream = TcpStream::connect((host.as_str(), port))
+            .map_err(|_| AtCommandError::ConnectionError)?;
+        stream
+            .set_nonblocking(true)
+            .map_err(|_| AtCommandError::ConnectionError)?;
+        _ = stream.set_nodelay(true);
+        self.tcp = Some(stream);
+        Ok(vec!["CONNECT".to_string()])
+    }
+
+    /// Sends data through the TCP connection
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The data to send
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if every byte was sent, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::NotConnected`: If there is no TCP connection
+    /// - `AtCommandError::ConnectionError`: If the data could not be sent in time
+    fn send_tcp(&mut self, data: &[u8]) -> Result<(), AtCommandError> {
+        let stream = self.tcp.as_mut().ok_or(AtCommandError::NotConnected)?;
+        let start = Instant::now();
+        let mut written = 0;
+        while written < data.len() {
+            match stream.write(&data[written..]) {
+                Ok(0) => return Err(AtCommandError::ConnectionError),
+                Ok(n) => written += n,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    if start.elapsed() > TCP_WRITE_TIMEOUT {
+                        return Err(AtCommandError::ConnectionError);
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(_) => return Err(AtCommandError::ConnectionError),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes to the UART the data received on the TCP connection, as `+IPD,<length>:<data>`.
+    /// If the remote end closed the connection `CLOSED` is written.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::UartError`: If writing the UART failed
+    fn forward_tcp_data(&mut self) -> Result<(), AtCommandError> {
+        if self.pending_send.is_some() {
+            return Ok(());
+        }
+        let stream = match self.tcp.as_mut() {
+            Some(stream) => stream,
+            None => return Ok(()),
+        };
+        let mut buffer = [0_u8; READ_BUFFER_SIZE];
+        match stream.read(&mut buffer) {
+            Ok(0) => {
+                self.tcp = None;
+                self.respond("CLOSED")
+            }
+            Ok(read) => {
+                self.uart.write(format!("\r\n+IPD,{}:", read).as_bytes())?;
+                self.uart.write(&buffer[..read])?;
+                Ok(())
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(_) => {
+                self.tcp = None;
+                self.respond("CLOSED")
+            }
+        }
+    }
+
+    /// Writes a line of the answer
+    ///
+    /// # Arguments
+    ///
+    /// - `line`: The line to write, without the line ending
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or an `AtCommandError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::UartError`: If writing the UART failed
+    fn respond(&mut self, line: &str) -> Result<(), AtCommandError> {
+        self.uart.write(format!("\r\n{}\r\n", line).as_bytes())?;
+        Ok(())
+    }
+
+    /// Gets the wifi driver
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the WifiDriver, or an `AtCommandError` if it was not given.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::NotAvailable`: If the wifi driver was not given
+    fn wifi(&mut self) -> Result<&mut WifiDriver<'a>, AtCommandError> {
+        self.wifi.as_mut().ok_or(AtCommandError::NotAvailable)
+    }
+
+    /// Gets the ble beacon
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the BleBeacon, or an `AtCommandError` if it was not given.
+    ///
+    /// # Errors
+    ///
+    /// - `AtCommandError::NotAvailable`: If the ble beacon was not given
+    fn beacon(&mut self) -> Result<&mut BleBeacon<'a>, AtCommandError> {
+        self.beacon.as_mut().ok_or(AtCommandError::NotAvailable)
+    }
+}
+
+/// Splits the parameters of a command, separated by commas. Quotes are removed, and commas
+/// between quotes are kept.
+///
+/// # Arguments
+///
+/// - `params`: The text after the `=` of the command
+///
+/// # Returns
+///
+/// A vector with each parameter
+fn split_params(params: &str) -> Vec<String> {
+    let mut result = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = params.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quoted => current.extend(chars.next()),
+            '"' => quoted = !quoted,
+            ',' if !quoted => result.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    result.push(current);
+    result
+}
+
+/// Parses a parameter of a command
+///
+/// # Arguments
+///
+/// - `params`: Every parameter of the command
+/// - `index`: The position of the parameter
+///
+/// # Returns
+///
+/// A `Result` with the parsed parameter, or an `AtCommandError` if it fails.
+///
+/// # Errors
+///
+/// - `AtCommandError::InvalidParameters`: If the parameter is missing or could not be parsed
+fn param<T: std::str::FromStr>(params: &[String], index: usize) -> Result<T, AtCommandError> {
+    params
+        .get(index)
+        .and_then(|param| param.trim().parse().ok())
+        .ok_or(AtCommandError::InvalidParameters)
+}
+
+/// Parses bytes written in hexadecimal, two characters per byte
+///
+/// # Arguments
+///
+/// - `hex`: The hexadecimal text
+///
+/// # Returns
+///
+/// A `Result` with the bytes, or an `AtCommandError` if it fails.
+///
+/// # Errors
+///
+/// - `AtCommandError::InvalidParameters`: If the text is not valid hexadecimal
+fn parse_hex(hex: &str) -> Result<Vec<u8>, AtCommandError> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(AtCommandError::InvalidParameters);
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| AtCommandError::InvalidParameters)
+        })
+        .collect()
+}
+
+impl From<BleError> for AtCommandError {
+    fn from(value: BleError) -> Self {
+        AtCommandError::BleError(value)
+    }
+}
+
+impl From<UARTError> for AtCommandError {
+    fn from(value: UARTError) -> Self {
+        AtCommandError::UartError(value)
+    }
+}
+
+impl From<WifiError> for AtCommandError {
+    fn from(value: WifiError) -> Self {
+        AtCommandError::WifiError(value)
+    }
+}
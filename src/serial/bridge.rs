@@ -0,0 +1,363 @@
+use crate::{
+    ble::{
+        utils::{Characteristic, Service},
+        BleError, BleId, BleServer,
+    },
+    serial::uart::{UARTError, UART},
+    utils::auxiliary::{SharableRef, SharableRefExt},
+};
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    net::TcpStream,
+};
+
+const READ_BUFFER_SIZE: usize = 128;
+const DEFAULT_MAX_BUFFERED_BYTES: usize = 1024;
+const DEFAULT_BLE_CHUNK_SIZE: usize = 20;
+
+/// Id of the Nordic UART Service
+pub const NUS_SERVICE_ID: BleId = BleId::FromUuid128([
+    0x6E, 0x40, 0x00, 0x01, 0xB5, 0xA3, 0xF3, 0x93, 0xE0, 0xA9, 0xE5, 0x0E, 0x24, 0xDC, 0xCA, 0x9E,
+]);
+/// Id of the Nordic UART Service RX characteristic, where clients write the data to send by UART
+pub const NUS_RX_CHARACTERISTIC_ID: BleId = BleId::FromUuid128([
+    0x6E, 0x40, 0x00, 0x02, 0xB5, 0xA3, 0xF3, 0x93, 0xE0, 0xA9, 0xE5, 0x0E, 0x24, 0xDC, 0xCA, 0x9E,
+]);
+/// Id of the Nordic UART Service TX characteristic, where the data read from the UART is notified
+pub const NUS_TX_CHARACTERISTIC_ID: BleId = BleId::FromUuid128([
+    0x6E, 0x40, 0x00, 0x03, 0xB5, 0xA3, 0xF3, 0x93, 0xE0, 0xA9, 0xE5, 0x0E, 0x24, 0xDC, 0xCA, 0x9E,
+]);
+
+/// Error types related to bridge operations.
+#[derive(Debug)]
+pub enum BridgeError {
+    BleError(BleError),
+    ConnectionError,
+    Disconnected,
+    UartError(UARTError),
+}
+
+/// Statistics of the bytes that went through a bridge:
+/// - `uart_to_remote`: Bytes read from the UART and sent to the remote end.
+/// - `remote_to_uart`: Bytes received from the remote end and written to the UART.
+/// - `dropped`: Bytes received from the remote end that were discarded because the buffer was full.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BridgeStats {
+    pub uart_to_remote: usize,
+    pub remote_to_uart: usize,
+    pub dropped: usize,
+}
+
+/// Bridge that pipes bytes bidirectionally between a UART and the Nordic UART Service of a BleServer.
+/// Data written by clients on the RX characteristic is written to the UART, and data read from the UART
+/// is notified on the TX characteristic.
+pub struct UartBleBridge<'a> {
+    uart: UART<'a>,
+    server: BleServer<'a>,
+    to_uart: SharableRef<VecDeque<u8>>,
+    stats: SharableRef<BridgeStats>,
+    max_buffered_bytes: SharableRef<usize>,
+    chunk_size: usize,
+}
+
+/// Bridge that pipes bytes bidirectionally between a UART and a TCP connection.
+/// When one of the ends is slower, the bridge stops reading from the other one, so the data
+/// is held by the UART driver or the TCP window.
+pub struct UartTcpBridge<'a> {
+    uart: UART<'a>,
+    stream: TcpStream,
+    to_uart: VecDeque<u8>,
+    to_tcp: VecDeque<u8>,
+    stats: BridgeStats,
+    max_buffered_bytes: usize,
+}
+
+impl<'a> UartBleBridge<'a> {
+    /// Creates a new UartBleBridge, setting the Nordic UART Service on the server.
+    ///
+    /// # Arguments
+    ///
+    /// - `uart`: The UART to bridge
+    /// - `server`: The BleServer where the service will be set
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `UartBleBridge`, or a `BridgeError` if the service could not be set.
+    ///
+    /// # Errors
+    ///
+    /// - `BridgeError::BleError`: If the service or the write callback could not be set on the server
+    pub fn new(uart: UART<'a>, server: &mut BleServer<'a>) -> Result<Self, BridgeError> {
+        set_nordic_uart_service(server)?;
+
+        let to_uart = SharableRef::new_sharable(VecDeque::new());
+        let stats = SharableRef::new_sharable(BridgeStats::default());
+        let max_buffered_bytes = SharableRef::new_sharable(DEFAULT_MAX_BUFFERED_BYTES);
+
+        let mut to_uart_ref = to_uart.clone();
+        let mut stats_ref = stats.clone();
+        let max_buffered_bytes_ref = max_buffered_bytes.clone();
+        server.on_write(
+            &NUS_SERVICE_ID,
+            &NUS_RX_CHARACTERISTIC_ID,
+            move |_, _, data| {
+                let mut to_uart = to_uart_ref.deref_mut();
+                if to_uart.len() + data.len() > *max_buffered_bytes_ref.deref() {
+                    stats_ref.deref_mut().dropped += data.len();
+                } else {
+                    to_uart.extend(data);
+                }
+            },
+        )?;
+
+        Ok(UartBleBridge {
+            uart,
+            server: server.clone(),
+            to_uart,
+            stats,
+            max_buffered_bytes,
+            chunk_size: DEFAULT_BLE_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the maximum amount of bytes received from clients that can be waiting to be written to
+    /// the UART. Bytes received when the buffer is full are dropped. By default it is 1024.
+    ///
+    /// # Arguments
+    ///
+    /// - `max_buffered_bytes`: The size of the buffer
+    pub fn set_max_buffered_bytes(&mut self, max_buffered_bytes: usize) {
+        *self.max_buffered_bytes.deref_mut() = max_buffered_bytes;
+    }
+
+    /// Sets the maximum size of each notification. It should be the negotiated MTU minus 3. By default it is 20.
+    ///
+    /// # Arguments
+    ///
+    /// - `chunk_size`: The maximum amount of bytes per notification
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size.max(1);
+    }
+
+    /// Moves the pending bytes between the UART and the server. Must be called periodically, along with
+    /// [crate::Microcontroller::wait_for_updates] so the data written by clients gets received.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `BridgeError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BridgeError::UartError`: If reading or writing the UART failed
+    /// - `BridgeError::BleError`: If the data could not be notified
+    pub fn update(&mut self) -> Result<(), BridgeError> {
+        let mut buffer = [0_u8; READ_BUFFER_SIZE];
+        loop {
+            let read = self.uart.read_with_timeout(&mut buffer, 0)?;
+            if read == 0 {
+                break;
+            }
+            for chunk in buffer[..read].chunks(self.chunk_size) {
+                let characteristic =
+                    Characteristic::new(&NUS_TX_CHARACTERISTIC_ID, chunk.to_vec()).notifiable(true);
+                self.server.notify_value(&NUS_SERVICE_ID, &characteristic)?;
+            }
+            self.stats.deref_mut().uart_to_remote += read;
+        }
+
+        let pending: Vec<u8> = self.to_uart.deref_mut().drain(..).collect();
+        if !pending.is_empty() {
+            let written = self.uart.write(&pending)?;
+            self.stats.deref_mut().remote_to_uart += written;
+            let mut to_uart = self.to_uart.deref_mut();
+            for byte in pending[written..].iter().rev() {
+                to_uart.push_front(*byte);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the statistics of the bridge
+    ///
+    /// # Returns
+    ///
+    /// The `BridgeStats` since the creation of the bridge or the last reset
+    pub fn stats(&self) -> BridgeStats {
+        *self.stats.deref()
+    }
+
+    /// Sets every statistic of the bridge back to 0
+    pub fn reset_stats(&mut self) {
+        *self.stats.deref_mut() = BridgeStats::default();
+    }
+}
+
+impl<'a> UartTcpBridge<'a> {
+    /// Creates a new UartTcpBridge over an already established TCP connection.
+    ///
+    /// # Arguments
+    ///
+    /// - `uart`: The UART to bridge
+    /// - `stream`: The TCP connection to bridge
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `UartTcpBridge`, or a `BridgeError` if the connection could not be configured.
+    ///
+    /// # Errors
+    ///
+    /// - `BridgeError::ConnectionError`: If the connection could not be set as non blocking
+    pub fn new(uart: UART<'a>, stream: TcpStream) -> Result<Self, BridgeError> {
+        stream
+            .set_nonblocking(true)
+            .map_err(|_| BridgeError::ConnectionError)?;
+        _ = stream.set_nodelay(true);
+        Ok(UartTcpBridge {
+            uart,
+            stream,
+            to_uart: VecDeque::new(),
+            to_tcp: VecDeque::new(),
+            stats: BridgeStats::default(),
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+        })
+    }
+
+    /// Connects to a TCP server and creates a new UartTcpBridge over the connection.
+    /// The wifi must be connected before calling this function.
+    ///
+    /// # Arguments
+    ///
+    /// - `uart`: The UART to bridge
+    /// - `address`: The address of the TCP server, for example "192.168.0.10:2000"
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `UartTcpBridge`, or a `BridgeError` if the connection failed.
+    ///
+    /// # Errors
+    ///
+    /// - `BridgeError::ConnectionError`: If the connection could not be established
+    pub fn connect(uart: UART<'a>, address: &str) -> Result<Self, BridgeError> {
+        let stream = TcpStream::connect(address).map_err(|_| BridgeError::ConnectionError)?;
+        Self::new(uart, stream)
+    }
+
+    /// Sets the maximum amount of bytes that can be waiting in each direction. Once reached, the bridge
+    /// stops reading from the faster end until the data is delivered. By default it is 1024.
+    ///
+    /// # Arguments
+    ///
+    /// - `max_buffered_bytes`: The size of the buffers
+    pub fn set_max_buffered_bytes(&mut self, max_buffered_bytes: usize) {
+        self.max_buffered_bytes = max_buffered_bytes;
+    }
+
+    /// Moves the pending bytes between the UART and the TCP connection. Must be called periodically.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `BridgeError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `BridgeError::UartError`: If reading or writing the UART failed
+    /// - `BridgeError::Disconnected`: If the TCP connection was closed
+    /// - `BridgeError::ConnectionError`: On other TCP errors
+    pub fn update(&mut self) -> Result<(), BridgeError> {
+        let mut buffer = [0_u8; READ_BUFFER_SIZE];
+
+        while self.to_tcp.len() < self.max_buffered_bytes {
+            let read = self.uart.read_with_timeout(&mut buffer, 0)?;
+            if read == 0 {
+                break;
+            }
+            self.to_tcp.extend(&buffer[..read]);
+        }
+
+        while !self.to_tcp.is_empty() {
+            let (pending, _) = self.to_tcp.as_slices();
+            match self.stream.write(pending) {
+                Ok(0) => return Err(BridgeError::Disconnected),
+                Ok(written) => {
+                    self.to_tcp.drain(..written);
+                    self.stats.uart_to_remote += written;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return Err(BridgeError::ConnectionError),
+            }
+        }
+
+        while self.to_uart.len() < self.max_buffered_bytes {
+            let available = (self.max_buffered_bytes - self.to_uart.len()).min(READ_BUFFER_SIZE);
+            match self.stream.read(&mut buffer[..available]) {
+                Ok(0) => return Err(BridgeError::Disconnected),
+                Ok(read) => self.to_uart.extend(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => return Err(BridgeError::ConnectionError),
+            }
+        }
+
+        if !self.to_uart.is_empty() {
+            let pending: Vec<u8> = self.to_uart.iter().copied().collect();
+            let written = self.uart.write(&pending)?;
+            self.to_uart.drain(..written);
+            self.stats.remote_to_uart += written;
+        }
+        Ok(())
+    }
+
+    /// Gets the statistics of the bridge
+    ///
+    /// # Returns
+    ///
+    /// The `BridgeStats` since the creation of the bridge or the last reset
+    pub fn stats(&self) -> BridgeStats {
+        self.stats
+    }
+
+    /// Sets every statistic of the bridge back to 0
+    pub fn reset_stats(&mut self) {
+        self.stats = BridgeStats::default();
+    }
+}
+
+/// Sets the Nordic UART Service, with its RX and TX characteristics, on a server.
+///
+/// # Arguments
+///
+/// - `server`: The BleServer where the service will be set
+///
+/// # Returns
+///
+/// A `Result` with Ok if the service was set, or a `BleError` if it fails.
+///
+/// # Errors
+///
+/// - `BleError::PropertiesError`: If a characteristic of the service could not be created
+pub(crate) fn set_nordic_uart_service(server: &mut BleServer) -> Result<(), BleError> {
+    let rx_characteristic = Characteristic::new(&NUS_RX_CHARACTERISTIC_ID, vec![])
+        .writable(true)
+        .writable_no_rsp(true);
+    let tx_characteristic = Characteristic::new(&NUS_TX_CHARACTERISTIC_ID, vec![])
+        .readable(true)
+        .notifiable(true);
+    let service = Service {
+        id: NUS_SERVICE_ID,
+        data: vec![],
+        characteristics: vec![rx_characteristic, tx_characteristic],
+    };
+    server.set_service(&service)
+}
+
+impl From<BleError> for BridgeError {
+    fn from(value: BleError) -> Self {
+        BridgeError::BleError(value)
+    }
+}
+
+impl From<UARTError> for BridgeError {
+    fn from(value: UARTError) -> Self {
+        BridgeError::UartError(value)
+    }
+}
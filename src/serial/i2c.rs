@@ -1,3 +1,4 @@
+use super::traffic::{TrafficError, TrafficMode, Transaction};
 use crate::{
     microcontroller_src::peripherals::{Peripheral, PeripheralError},
     utils::auxiliary::micro_to_ticks,
@@ -25,11 +26,13 @@ pub enum I2CError {
     PeripheralError(PeripheralError),
     Temp,
     TimeoutError,
+    TrafficError(TrafficError),
 }
 
 /// An I2C master driver of an I2C communication.
 pub struct I2CMaster<'a> {
     driver: I2cDriver<'a>,
+    traffic: TrafficMode,
 }
 
 impl<'a> I2CMaster<'a> {
@@ -68,7 +71,80 @@ impl<'a> I2CMaster<'a> {
         let driver =
             I2cDriver::new(i2c, sda, scl, &config).map_err(I2CError::from_driver_context)?;
 
-        Ok(I2CMaster { driver })
+        Ok(I2CMaster {
+            driver,
+            traffic: TrafficMode::Live,
+        })
+    }
+
+    /// Wraps an I2cDriver already created through esp-idf-hal, in order to use it with the framework.
+    ///
+    /// # Arguments
+    ///
+    /// - `driver`: The I2cDriver to wrap
+    ///
+    /// # Returns
+    ///
+    /// The new `I2CMaster` instance
+    pub fn from_raw(driver: I2cDriver<'a>) -> I2CMaster<'a> {
+        I2CMaster {
+            driver,
+            traffic: TrafficMode::Live,
+        }
+    }
+
+    /// Consumes the `I2CMaster`, returning the underlying I2cDriver in order to use esp-idf-hal features
+    /// not covered by the framework.
+    ///
+    /// # Returns
+    ///
+    /// The I2cDriver of the `I2CMaster`
+    pub fn into_inner(self) -> I2cDriver<'a> {
+        self.driver
+    }
+
+    /// Sets how the I2CMaster handles its transactions, in order to record the traffic with a
+    /// real device, or to replay it on tests of a sensor driver without the device. The bus is
+    /// not touched while replaying, so the pins can be left unconnected.
+    ///
+    /// # Arguments
+    ///
+    /// - `mode`: The TrafficMode to use
+    pub fn set_traffic_mode(&mut self, mode: TrafficMode) {
+        self.traffic = mode;
+    }
+
+    /// Adds a successful transaction to the recording, if the I2CMaster is recording
+    fn record<F: FnOnce() -> Transaction>(&self, transaction: F) {
+        if let TrafficMode::Record(recorder) = &self.traffic {
+            recorder.record(transaction())
+        }
+    }
+
+    /// Answers a transaction with the recording, if the I2CMaster is replaying
+    ///
+    /// # Arguments
+    ///
+    /// - `request`: Creates the transaction to answer, with a zeroed buffer to read
+    /// - `buffer`: Where to store the recorded bytes read
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the `Result` of the replay, or None if the I2CMaster is not replaying
+    fn replay<F: FnOnce() -> Transaction>(
+        &self,
+        request: F,
+        buffer: &mut [u8],
+    ) -> Option<Result<(), I2CError>> {
+        let TrafficMode::Replay(replay) = &self.traffic else {
+            return None;
+        };
+        Some(
+            replay
+                .next(&request())
+                .map(|recorded| buffer.copy_from_slice(recorded.read_bytes()))
+                .map_err(I2CError::TrafficError),
+        )
     }
 
     /// Reads data from the specified address into the provided buffer with a timeout in us (microsec). The function
@@ -89,7 +165,16 @@ impl<'a> I2CMaster<'a> {
     /// - `I2CError::InvalidArg`: If an invalid argument is passed.
     /// - `I2CError::BufferTooSmall`: If the buffer is too small.
     /// - `I2CError::NoMoreHeapMemory`: If there isn't enough heap memory to perform the operation.
+    /// - `I2CError::TrafficError`: If replaying, and the next recorded transaction is not this one.
     pub fn read(&mut self, addr: u8, buffer: &mut [u8], timeout_us: u32) -> Result<(), I2CError> {
+        let read_len = buffer.len();
+        let request = || Transaction::I2cRead {
+            addr,
+            read: vec![0; read_len],
+        };
+        if let Some(res) = self.replay(request, buffer) {
+            return res;
+        }
         let timeout: u32 = micro_to_ticks(timeout_us);
         self.driver
             .read(addr, buffer, timeout)
@@ -97,7 +182,12 @@ impl<'a> I2CMaster<'a> {
                 ESP_ERR_INVALID_ARG => I2CError::InvalidArg,
                 ESP_ERR_NO_MEM => I2CError::BufferTooSmall,
                 _ => I2CError::NoMoreHeapMemory,
-            })
+            })?;
+        self.record(|| Transaction::I2cRead {
+            addr,
+            read: buffer.to_vec(),
+        });
+        Ok(())
     }
 
     /// Write multiple bytes from a slice to the specified address with a timeout in us (microsec).
@@ -117,12 +207,20 @@ impl<'a> I2CMaster<'a> {
     /// - `I2CError::InvalidArg`: If an invalid argument is passed.
     /// - `I2CError::BufferTooSmall`: If the buffer is too small.
     /// - `I2CError::NoMoreHeapMemory`: If there isn't enough heap memory to perform the operation.
+    /// - `I2CError::TrafficError`: If replaying, and the next recorded transaction is not this one.
     pub fn write(
         &mut self,
         addr: u8,
         bytes_to_write: &[u8],
         timeout_us: u32,
     ) -> Result<(), I2CError> {
+        let request = || Transaction::I2cWrite {
+            addr,
+            written: bytes_to_write.to_vec(),
+        };
+        if let Some(res) = self.replay(request, &mut []) {
+            return res;
+        }
         let timeout: u32 = micro_to_ticks(timeout_us);
         self.driver
             .write(addr, bytes_to_write, timeout)
@@ -130,7 +228,12 @@ impl<'a> I2CMaster<'a> {
                 ESP_ERR_INVALID_ARG => I2CError::InvalidArg,
                 ESP_ERR_NO_MEM => I2CError::BufferTooSmall,
                 _ => I2CError::NoMoreHeapMemory,
-            })
+            })?;
+        self.record(|| Transaction::I2cWrite {
+            addr,
+            written: bytes_to_write.to_vec(),
+        });
+        Ok(())
     }
 
     /// Writes multiple bytes from a slice to the specified address and then reads the answer and stores it into the
@@ -151,6 +254,7 @@ impl<'a> I2CMaster<'a> {
     /// - `I2CError::InvalidArg`: If an invalid argument is passed.
     /// - `I2CError::BufferTooSmall`: If the buffer is too small.
     /// - `I2CError::NoMoreHeapMemory`: If there isn't enough heap memory to perform the operation.
+    /// - `I2CError::TrafficError`: If replaying, and the next recorded transaction is not this one.
     pub fn write_read(
         &mut self,
         addr: u8,
@@ -158,6 +262,15 @@ impl<'a> I2CMaster<'a> {
         buffer: &mut [u8],
         timeout_us: u32,
     ) -> Result<(), I2CError> {
+        let read_len = buffer.len();
+        let request = || Transaction::I2cWriteRead {
+            addr,
+            written: bytes_to_write.to_vec(),
+            read: vec![0; read_len],
+        };
+        if let Some(res) = self.replay(request, buffer) {
+            return res;
+        }
         let timeout: u32 = micro_to_ticks(timeout_us);
         self.driver
             .write_read(addr, bytes_to_write, buffer, timeout)
@@ -165,7 +278,13 @@ impl<'a> I2CMaster<'a> {
                 ESP_ERR_INVALID_ARG => I2CError::InvalidArg,
                 ESP_ERR_NO_MEM => I2CError::BufferTooSmall,
                 _ => I2CError::NoMoreHeapMemory,
-            })
+            })?;
+        self.record(|| Transaction::I2cWriteRead {
+            addr,
+            written: bytes_to_write.to_vec(),
+            read: buffer.to_vec(),
+        });
+        Ok(())
     }
 }
 
@@ -214,6 +333,29 @@ impl<'a> I2CSlave<'a> {
         Ok(I2CSlave { driver })
     }
 
+    /// Wraps an I2cSlaveDriver already created through esp-idf-hal, in order to use it with the framework.
+    ///
+    /// # Arguments
+    ///
+    /// - `driver`: The I2cSlaveDriver to wrap
+    ///
+    /// # Returns
+    ///
+    /// The new `I2CSlave` instance
+    pub fn from_raw(driver: I2cSlaveDriver<'a>) -> I2CSlave<'a> {
+        I2CSlave { driver }
+    }
+
+    /// Consumes the `I2CSlave`, returning the underlying I2cSlaveDriver in order to use esp-idf-hal features
+    /// not covered by the framework.
+    ///
+    /// # Returns
+    ///
+    /// The I2cSlaveDriver of the `I2CSlave`
+    pub fn into_inner(self) -> I2cSlaveDriver<'a> {
+        self.driver
+    }
+
     /// Reads data from the specified address into the provided buffer with a timeout in us (microsec). The function
     /// will return once the timeout is reached or the buffer is full.
     ///
@@ -1,5 +1,9 @@
+pub mod at_commands;
+pub mod bridge;
 pub mod i2c;
 mod serial_operations;
+pub mod shell;
+pub mod traffic;
 pub mod uart;
 
 pub use serial_operations::*;
@@ -0,0 +1,1214 @@
+use crate::{
+    ble::{utils::Characteristic, BleError, BleServer},
+    gpio::digital::DigitalOut,
+    serial::{
+        bridge::{
+            set_nordic_uart_service, NUS_RX_CHARACTERISTIC_ID, NUS_SERVICE_ID,
+            NUS_TX_CHARACTERISTIC_ID,
+        },
+        i2c::I2CMaster,
+        uart::{UARTError, UART},
+    },
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        event_log::EventLog,
+        watch::WatchRegistry,
+    },
+};
+use esp_idf_svc::{
+    hal::{gpio::Level, reset::restart},
+    sys::{
+        esp_get_free_heap_size, esp_get_minimum_free_heap_size, esp_netif_get_handle_from_ifkey,
+        esp_netif_get_ip_info, esp_netif_ip_info_t, esp_wifi_sta_get_ap_info, fcntl,
+        heap_caps_get_largest_free_block, wifi_ap_record_t, ESP_OK, F_SETFL, MALLOC_CAP_DEFAULT,
+        O_NONBLOCK,
+    },
+};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::Write as _,
+    io::{ErrorKind, Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+const READ_BUFFER_SIZE: usize = 64;
+const MAX_LINE_LENGTH: usize = 256;
+const DEFAULT_PROMPT: &str = "> ";
+const PASSWORD_PROMPT: &str = "password: ";
+const DEFAULT_BLE_CHUNK_SIZE: usize = 20;
+const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+const I2C_SCAN_TIMEOUT_US: u32 = 10_000;
+const I2C_FIRST_ADDRESS: u8 = 0x08;
+const I2C_LAST_ADDRESS: u8 = 0x77;
+const DEFAULT_EVENT_COUNT: usize = 20;
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+/// Error types related to shell operations.
+#[derive(Debug)]
+pub enum ShellError {
+    BleError(BleError),
+    CommandAlreadyExists(String),
+    CommandError(String),
+    ConnectionError,
+    InvalidArgument(String),
+    MissingArgument(String),
+    ReadError,
+    UartError(UARTError),
+    UnknownCommand(String),
+    UnterminatedQuote,
+    WriteError,
+}
+
+/// Byte stream over which a shell receives commands and sends their output.
+pub trait ShellTransport {
+    /// Reads the available bytes without blocking.
+    ///
+    /// # Arguments
+    ///
+    /// - `buffer`: The buffer where the bytes are stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of bytes read, which can be 0, or a `ShellError` if it fails.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, ShellError>;
+
+    /// Writes every byte.
+    ///
+    /// # Arguments
+    ///
+    /// - `bytes`: The bytes to write
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if every byte was written, or a `ShellError` if it fails.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ShellError>;
+
+    /// Checks if a new remote session started since the last call, in which case the shell
+    /// asks for the password again. Local transports never start new sessions.
+    ///
+    /// # Returns
+    ///
+    /// True if a new session started, False if not
+    fn new_session(&mut self) -> bool {
+        false
+    }
+}
+
+/// Transport over the console of the microcontroller, which is the USB serial port on most boards.
+/// It uses the standard input and output, so anything printed by the program is also shown.
+pub struct ConsoleTransport {}
+
+/// Transport over a TCP socket. It listens on a port and serves one client at a time, starting a
+/// new session for each client. The wifi must be connected before creating it.
+pub struct TcpShellTransport {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    new_client: bool,
+}
+
+/// Transport over the Nordic UART Service of a BleServer, the same service used by the
+/// [crate::serial::bridge::UartBleBridge], so any BLE serial terminal app can be used. A new session
+/// starts every time the amount of connected clients changes.
+pub struct BleShellTransport<'a> {
+    server: BleServer<'a>,
+    received: SharableRef<VecDeque<u8>>,
+    connected_clients: usize,
+    chunk_size: usize,
+}
+
+/// Arguments received by a command. Words starting with `--` are options, either with a value
+/// (`--name=value`) or without one (`--name`). Every other word is a positional argument.
+/// Words can be grouped with single or double quotes and a `\` escapes the next character.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShellArgs {
+    positional: Vec<String>,
+    options: HashMap<String, Option<String>>,
+}
+
+type CommandHandler<'a> = dyn FnMut(&ShellArgs, &mut String) -> Result<(), ShellError> + 'a;
+
+/// A command registered on a shell
+struct ShellCommand<'a> {
+    usage: String,
+    help: String,
+    handler: Box<CommandHandler<'a>>,
+}
+
+/// Interactive shell where commands are registered along with their usage and help text.
+/// Lines received from the transport are parsed and executed, and the output of the commands is
+/// written back. A `help` command listing every command is always available. If a password is set,
+/// it must be entered at the start of each session before any command is accepted.
+pub struct Shell<'a> {
+    transport: Box<dyn ShellTransport + 'a>,
+    commands: BTreeMap<String, ShellCommand<'a>>,
+    line: String,
+    prompt: String,
+    password: Option<String>,
+    authenticated: bool,
+    echo: bool,
+    last_was_cr: bool,
+    prompt_pending: bool,
+    reboot_requested: SharableRef<bool>,
+}
+
+impl ShellTransport for UART<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, ShellError> {
+        Ok(self.read_with_timeout(buffer, 0)?)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ShellError> {
+        let mut written = 0;
+        while written < bytes.len() {
+            written += UART::write(self, &bytes[written..])?;
+        }
+        Ok(())
+    }
+}
+
+impl ConsoleTransport {
+    /// Creates a new ConsoleTransport, setting the standard input as non blocking.
+    ///
+    /// # Returns
+    ///
+    /// The new ConsoleTransport
+    pub fn new() -> Self {
+        unsafe { fcntl(0, F_SETFL as i32, O_NONBLOCK as i32) };
+        ConsoleTransport {}
+    }
+}
+
+impl Default for ConsoleTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShellTransport for ConsoleTransport {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, ShellError> {
+        match std::io::stdin().read(buffer) {
+            Ok(read) => Ok(read),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(0),
+            Err(_) => Err(ShellError::ReadError),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ShellError> {
+        let mut stdout = std::io::stdout();
+        stdout
+            .write_all(bytes)
+            .and_then(|_| stdout.flush())
+            .map_err(|_| ShellError::WriteError)
+    }
+}
+
+impl TcpShellTransport {
+    /// Creates a new TcpShellTransport listening on a port of every interface.
+    ///
+    /// # Arguments
+    ///
+    /// - `port`: The port where clients connect, for example 23
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `TcpShellTransport`, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::ConnectionError`: If the port could not be bound
+    pub fn listen(port: u16) -> Result<Self, ShellError> {
+        let listener =
+            TcpListener::bind(("0.0.0.0", port)).map_err(|_| ShellError::ConnectionError)?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|_| ShellError::ConnectionError)?;
+        Ok(TcpShellTransport {
+            listener,
+            client: None,
+            new_client: false,
+        })
+    }
+
+    /// Accepts a waiting client if there is none connected
+    fn accept_client(&mut self) {
+        if self.client.is_some() {
+            return;
+        }
+        if let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                _ = stream.set_nodelay(true);
+                self.client = Some(stream);
+                self.new_client = true;
+            }
+        }
+    }
+}
+
+impl ShellTransport for TcpShellTransport {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, ShellError> {
+        self.accept_client();
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Ok(0),
+        };
+        match client.read(buffer) {
+            Ok(0) => {
+                self.client = None;
+                Ok(0)
+            }
+            Ok(read) => Ok(read),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(0),
+            Err(_) => {
+                self.client = None;
+                Ok(0)
+            }
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ShellError> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+        let start = Instant::now();
+        let mut written = 0;
+        while written < bytes.len() {
+            match client.write(&bytes[written..]) {
+                Ok(0) => break,
+                Ok(n) => written += n,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                    if start.elapsed() > TCP_WRITE_TIMEOUT {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                Err(_) => break,
+            }
+        }
+        if written < bytes.len() {
+            self.client = None;
+        }
+        Ok(())
+    }
+
+    fn new_session(&mut self) -> bool {
+        self.accept_client();
+        std::mem::take(&mut self.new_client)
+    }
+}
+
+impl<'a> BleShellTransport<'a> {
+    /// Creates a new BleShellTransport, setting the Nordic UART Service on the server.
+    ///
+    /// # Arguments
+    ///
+    /// - `server`: The BleServer where the service will be set
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `BleShellTransport`, or a `ShellError` if the service could not be set.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::BleError`: If the service or the write callback could not be set on the server
+    pub fn new(server: &mut BleServer<'a>) -> Result<Self, ShellError> {
+        set_nordic_uart_service(server)?;
+        let received = SharableRef::new_sharable(VecDeque::new());
+        let mut received_ref = received.clone();
+        server.on_write(
+            &NUS_SERVICE_ID,
+            &NUS_RX_CHARACTERISTIC_ID,
+            move |_, _, data| {
+                let mut received = received_ref.deref_mut();
+                if received.len() + data.len() <= MAX_LINE_LENGTH * 4 {
+                    received.extend(data);
+                }
+            },
+        )?;
+        Ok(BleShellTransport {
+            server: server.clone(),
+            received,
+            connected_clients: 0,
+            chunk_size: DEFAULT_BLE_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the maximum size of each notification. It should be the negotiated MTU minus 3. By default it is 20.
+    ///
+    /// # Arguments
+    ///
+    /// - `chunk_size`: The maximum amount of bytes per notification
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size.max(1);
+    }
+}
+
+impl ShellTransport for BleShellTransport<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, ShellError> {
+        let mut received = self.received.deref_mut();
+        let read = buffer.len().min(received.len());
+        for (byte, value) in buffer.iter_mut().zip(received.drain(..read)) {
+            *byte = value;
+        }
+        Ok(read)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ShellError> {
+        if self.connected_clients == 0 {
+            return Ok(());
+        }
+        for chunk in bytes.chunks(self.chunk_size) {
+            let characteristic =
+                Characteristic::new(&NUS_TX_CHARACTERISTIC_ID, chunk.to_vec()).notifiable(true);
+            self.server.notify_value(&NUS_SERVICE_ID, &characteristic)?;
+        }
+        Ok(())
+    }
+
+    fn new_session(&mut self) -> bool {
+        let connected_clients = self.server.amount_of_clients();
+        if connected_clients == self.connected_clients {
+            return false;
+        }
+        self.connected_clients = connected_clients;
+        self.received.deref_mut().clear();
+        connected_clients > 0
+    }
+}
+
+impl ShellArgs {
+    /// Parses the arguments of a command.
+    ///
+    /// # Arguments
+    ///
+    /// - `words`: The words after the name of the command
+    ///
+    /// # Returns
+    ///
+    /// The parsed `ShellArgs`
+    fn from_words(words: Vec<String>) -> Self {
+        let mut args = ShellArgs::default();
+        let mut only_positional = false;
+        for word in words {
+            match word.strip_prefix("--") {
+                Some("") if !only_positional => only_positional = true,
+                Some(option) if !only_positional => match option.split_once('=') {
+                    Some((name, value)) => {
+                        args.options
+                            .insert(name.to_string(), Some(value.to_string()));
+                    }
+                    None => {
+                        args.options.insert(option.to_string(), None);
+                    }
+                },
+                _ => args.positional.push(word),
+            }
+        }
+        args
+    }
+
+    /// Gets the amount of positional arguments
+    ///
+    /// # Returns
+    ///
+    /// A `usize` with the amount of positional arguments
+    pub fn len(&self) -> usize {
+        self.positional.len()
+    }
+
+    /// Checks if there are no positional arguments
+    ///
+    /// # Returns
+    ///
+    /// True if there are no positional arguments, False if not
+    pub fn is_empty(&self) -> bool {
+        self.positional.is_empty()
+    }
+
+    /// Gets every positional argument
+    ///
+    /// # Returns
+    ///
+    /// A slice with the positional arguments in order
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    /// Gets a positional argument
+    ///
+    /// # Arguments
+    ///
+    /// - `index`: The position of the argument, starting from 0
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the argument, or None if there are not enough arguments
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(|arg| arg.as_str())
+    }
+
+    /// Parses a positional argument into any type that implements FromStr
+    ///
+    /// # Arguments
+    ///
+    /// - `index`: The position of the argument, starting from 0
+    /// - `name`: The name of the argument, used on the errors
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the parsed argument, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::MissingArgument`: If there are not enough arguments
+    /// - `ShellError::InvalidArgument`: If the argument could not be parsed
+    pub fn parse<T: FromStr>(&self, index: usize, name: &str) -> Result<T, ShellError> {
+        let arg = self
+            .get(index)
+            .ok_or(ShellError::MissingArgument(name.to_string()))?;
+        parse_number_or_str(arg).ok_or(ShellError::InvalidArgument(name.to_string()))
+    }
+
+    /// Checks if an option was received, with or without value
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the option without the `--`
+    ///
+    /// # Returns
+    ///
+    /// True if the option was received, False if not
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    /// Gets the value of an option
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the option without the `--`
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the value, or None if the option was not received or has no value
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name)?.as_deref()
+    }
+
+    /// Parses the value of an option into any type that implements FromStr
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the option without the `--`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with an `Option` of the parsed value, being None if the option was not received,
+    /// or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::InvalidArgument`: If the value is missing or could not be parsed
+    pub fn parse_option<T: FromStr>(&self, name: &str) -> Result<Option<T>, ShellError> {
+        match self.options.get(name) {
+            None => Ok(None),
+            Some(value) => value
+                .as_deref()
+                .and_then(parse_number_or_str)
+                .map(Some)
+                .ok_or(ShellError::InvalidArgument(name.to_string())),
+        }
+    }
+}
+
+impl<'a> Shell<'a> {
+    /// Creates a new Shell over a transport, with only the `help` command.
+    ///
+    /// # Arguments
+    ///
+    /// - `transport`: The `ShellTransport` where commands are received, for example an `UART` or
+    /// a `ConsoleTransport`
+    ///
+    /// # Returns
+    ///
+    /// The new Shell
+    pub fn new<T: ShellTransport + 'a>(transport: T) -> Self {
+        Shell {
+            transport: Box::new(transport),
+            commands: BTreeMap::new(),
+            line: String::new(),
+            prompt: DEFAULT_PROMPT.to_string(),
+            password: None,
+            authenticated: true,
+            echo: true,
+            last_was_cr: false,
+            prompt_pending: true,
+            reboot_requested: SharableRef::new_sharable(false),
+        }
+    }
+
+    /// Sets the text written before each line. By default it is "> ".
+    ///
+    /// # Arguments
+    ///
+    /// - `prompt`: The new prompt
+    pub fn set_prompt(&mut self, prompt: &str) {
+        self.prompt = prompt.to_string();
+    }
+
+    /// Sets a password that must be entered at the start of each session before any command is
+    /// accepted. Meant for remote transports such as the `TcpShellTransport` or the `BleShellTransport`.
+    ///
+    /// # Arguments
+    ///
+    /// - `password`: The password. If None no password is asked.
+    pub fn set_password(&mut self, password: Option<&str>) {
+        self.password = password.map(|password| password.to_string());
+        self.authenticated = self.password.is_none();
+        self.line.clear();
+        self.prompt_pending = true;
+    }
+
+    /// Sets whether the received characters are written back. By default it is true, which is what
+    /// serial terminals expect.
+    ///
+    /// # Arguments
+    ///
+    /// - `echo`: If true the received characters are written back
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Registers a command.
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name used to call the command
+    /// - `usage`: The arguments of the command, for example "<pin> [high|low]"
+    /// - `help`: A short description shown by the `help` command
+    /// - `handler`: A closure that receives the `ShellArgs` and a `String` where the output of the
+    /// command is written. If it returns an error, it is written as the output instead.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If there already is a command with the same name
+    pub fn add_command<F>(
+        &mut self,
+        name: &str,
+        usage: &str,
+        help: &str,
+        handler: F,
+    ) -> Result<(), ShellError>
+    where
+        F: FnMut(&ShellArgs, &mut String) -> Result<(), ShellError> + 'a,
+    {
+        if name == "help" || self.commands.contains_key(name) {
+            return Err(ShellError::CommandAlreadyExists(name.to_string()));
+        }
+        self.commands.insert(
+            name.to_string(),
+            ShellCommand {
+                usage: usage.to_string(),
+                help: help.to_string(),
+                handler: Box::new(handler),
+            },
+        );
+        Ok(())
+    }
+
+    /// Removes a command.
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the command
+    ///
+    /// # Returns
+    ///
+    /// True if the command existed, False if not
+    pub fn remove_command(&mut self, name: &str) -> bool {
+        self.commands.remove(name).is_some()
+    }
+
+    /// Registers the `heap`, `reboot` and `wifi` built-in commands, which need no drivers.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the commands were registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If one of the names is already used
+    pub fn add_builtin_commands(&mut self) -> Result<(), ShellError> {
+        self.add_heap_command()?;
+        self.add_reboot_command()?;
+        self.add_wifi_command()
+    }
+
+    /// Registers the `heap` command, which shows the free, minimum free and largest free block
+    /// of the heap.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If the name is already used
+    pub fn add_heap_command(&mut self) -> Result<(), ShellError> {
+        self.add_command("heap", "", "Shows heap statistics", |_, out| {
+            let (free, min_free, largest) = unsafe {
+                (
+                    esp_get_free_heap_size(),
+                    esp_get_minimum_free_heap_size(),
+                    heap_caps_get_largest_free_block(MALLOC_CAP_DEFAULT),
+                )
+            };
+            _ = writeln!(out, "free: {} bytes", free);
+            _ = writeln!(out, "minimum free: {} bytes", min_free);
+            _ = writeln!(out, "largest free block: {} bytes", largest);
+            Ok(())
+        })
+    }
+
+    /// Registers the `reboot` command, which restarts the microcontroller once its output is written.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If the name is already used
+    pub fn add_reboot_command(&mut self) -> Result<(), ShellError> {
+        let mut reboot_requested = self.reboot_requested.clone();
+        self.add_command(
+            "reboot",
+            "",
+            "Restarts the microcontroller",
+            move |_, out| {
+                *reboot_requested.deref_mut() = true;
+                _ = writeln!(out, "rebooting...");
+                Ok(())
+            },
+        )
+    }
+
+    /// Registers the `wifi` command, which shows the access point the station is connected to,
+    /// its signal strength and the ip address of the station.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If the name is already used
+    pub fn add_wifi_command(&mut self) -> Result<(), ShellError> {
+        self.add_command("wifi", "", "Shows the wifi connection status", |_, out| {
+            let mut record = wifi_ap_record_t::default();
+            if unsafe { esp_wifi_sta_get_ap_info(&mut record) } != ESP_OK {
+                _ = writeln!(out, "not connected");
+                return Ok(());
+            }
+            let ssid_len = record
+                .ssid
+                .iter()
+                .position(|byte| *byte == 0)
+                .unwrap_or(record.ssid.len());
+            _ = writeln!(
+                out,
+                "connected to: {}",
+                String::from_utf8_lossy(&record.ssid[..ssid_len])
+            );
+            _ = writeln!(out, "channel: {}", record.primary);
+            _ = writeln!(out, "rssi: {} dBm", record.rssi);
+
+            let mut ip_info = esp_netif_ip_info_t::default();
+            let netif =
+                unsafe { esp_netif_get_handle_from_ifkey(b"WIFI_STA_DEF\0".as_ptr() as *const _) };
+            if !netif.is_null() && unsafe { esp_netif_get_ip_info(netif, &mut ip_info) } == ESP_OK {
+                _ = writeln!(out, "ip: {}", Ipv4Addr::from(ip_info.ip.addr.to_le_bytes()));
+            }
+            Ok(())
+        })
+    }
+
+    /// Registers the `i2cscan` command, which lists the addresses of the devices that answer on the bus.
+    ///
+    /// # Arguments
+    ///
+    /// - `i2c`: The `I2CMaster` of the bus to scan
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If the name is already used
+    pub fn add_i2c_scan_command(&mut self, mut i2c: I2CMaster<'a>) -> Result<(), ShellError> {
+        self.add_command(
+            "i2cscan",
+            "",
+            "Lists the devices on the i2c bus",
+            move |_, out| {
+                let mut found = 0;
+                for addr in I2C_FIRST_ADDRESS..=I2C_LAST_ADDRESS {
+                    if i2c.write(addr, &[], I2C_SCAN_TIMEOUT_US).is_ok() {
+                        _ = writeln!(out, "found device at 0x{:02x}", addr);
+                        found += 1;
+                    }
+                }
+                _ = writeln!(out, "{} devices found", found);
+                Ok(())
+            },
+        )
+    }
+
+    /// Registers the `gpio` command, which reads, sets or toggles the level of the given outputs.
+    ///
+    /// # Arguments
+    ///
+    /// - `outputs`: A vector with the number of each pin and its `DigitalOut`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If the name is already used
+    pub fn add_gpio_command(
+        &mut self,
+        outputs: Vec<(u8, DigitalOut<'a>)>,
+    ) -> Result<(), ShellError> {
+        let mut outputs: HashMap<u8, DigitalOut<'a>> = outputs.into_iter().collect();
+        self.add_command(
+            "gpio",
+            "<pin> [high|low|toggle]",
+            "Reads or changes the level of an output",
+            move |args, out| {
+                let pin: u8 = args.parse(0, "pin")?;
+                let output = outputs
+                    .get_mut(&pin)
+                    .ok_or(ShellError::InvalidArgument("pin".to_string()))?;
+                let result = match args.get(1) {
+                    None => Ok(()),
+                    Some("high") | Some("1") => output.set_high(),
+                    Some("low") | Some("0") => output.set_low(),
+                    Some("toggle") => output.toggle(),
+                    Some(_) => return Err(ShellError::InvalidArgument("level".to_string())),
+                };
+                result.map_err(|err| ShellError::CommandError(format!("{:?}", err)))?;
+                let level = match output.get_level() {
+                    Level::High => "high",
+                    Level::Low => "low",
+                };
+                _ = writeln!(out, "gpio {}: {}", pin, level);
+                Ok(())
+            },
+        )
+    }
+
+    /// Registers the `events` command, which shows the newest events of the EventLog, or erases
+    /// it with `--clear`.
+    ///
+    /// # Arguments
+    ///
+    /// - `log`: The opened `EventLog`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If the name is already used
+    pub fn add_event_log_command(&mut self, log: EventLog) -> Result<(), ShellError> {
+        self.add_command(
+            "events",
+            "[count] [--clear]",
+            "Shows the newest events of the event log",
+            move |args, out| {
+                if args.has_option("clear") {
+                    log.clear()
+                        .map_err(|err| ShellError::CommandError(format!("{:?}", err)))?;
+                    _ = writeln!(out, "event log cleared");
+                    return Ok(());
+                }
+                let count = match args.get(0) {
+                    Some(_) => args.parse(0, "count")?,
+                    None => DEFAULT_EVENT_COUNT,
+                };
+                let events = log
+                    .render_text(Some(count))
+                    .map_err(|err| ShellError::CommandError(format!("{:?}", err)))?;
+                out.push_str(&events);
+                Ok(())
+            },
+        )
+    }
+
+    /// Registers the `watch` command, which lists the variables of the WatchRegistry, reads one of
+    /// them or modifies it.
+    ///
+    /// # Arguments
+    ///
+    /// - `registry`: The `WatchRegistry` with the variables
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the command was registered, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::CommandAlreadyExists`: If the name is already used
+    pub fn add_watch_command(&mut self, registry: WatchRegistry<'a>) -> Result<(), ShellError> {
+        self.add_command(
+            "watch",
+            "[name] [value]",
+            "Lists, reads or modifies the watch variables",
+            move |args, out| {
+                let Some(name) = args.get(0) else {
+                    for (name, value, read_only) in registry.list() {
+                        let mode = if read_only { " (read only)" } else { "" };
+                        _ = writeln!(out, "{} = {}{}", name, value, mode);
+                    }
+                    return Ok(());
+                };
+                if let Some(value) = args.get(1) {
+                    registry
+                        .write(name, value)
+                        .map_err(|err| ShellError::CommandError(format!("{:?}", err)))?;
+                }
+                let value = registry
+                    .read(name)
+                    .map_err(|err| ShellError::CommandError(format!("{:?}", err)))?;
+                _ = writeln!(out, "{} = {}", name, value);
+                Ok(())
+            },
+        )
+    }
+
+    /// Executes a line as if it was received from the transport.
+    ///
+    /// # Arguments
+    ///
+    /// - `line`: The line with the name of the command and its arguments
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the output of the command, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::UnterminatedQuote`: If a quote of the line is never closed
+    /// - `ShellError::UnknownCommand`: If there is no command with the name
+    /// - Any error returned by the command
+    pub fn execute(&mut self, line: &str) -> Result<String, ShellError> {
+        let mut words = split_words(line)?;
+        if words.is_empty() {
+            return Ok(String::new());
+        }
+        let name = words.remove(0);
+        let args = ShellArgs::from_words(words);
+        let mut out = String::new();
+
+        if name == "help" {
+            self.write_help(args.get(0), &mut out)?;
+            return Ok(out);
+        }
+
+        let command = self
+            .commands
+            .get_mut(&name)
+            .ok_or(ShellError::UnknownCommand(name))?;
+        (command.handler)(&args, &mut out)?;
+        Ok(out)
+    }
+
+    /// Reads the bytes received by the transport, and executes every complete line. Must be called
+    /// periodically.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `ShellError` if it fails.
+    /// Errors of the commands are written to the transport instead of being returned.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::ReadError` or `ShellError::UartError`: If reading the transport failed
+    /// - `ShellError::WriteError` or `ShellError::UartError`: If writing the transport failed
+    pub fn update(&mut self) -> Result<(), ShellError> {
+        if self.transport.new_session() {
+            self.line.clear();
+            self.authenticated = self.password.is_none();
+            self.prompt_pending = true;
+        }
+        if self.prompt_pending {
+            self.prompt_pending = false;
+            self.write_prompt()?;
+        }
+
+        let mut buffer = [0_u8; READ_BUFFER_SIZE];
+        loop {
+            let read = self.transport.read(&mut buffer)?;
+            if read == 0 {
+                return Ok(());
+            }
+            for byte in &buffer[..read] {
+                self.handle_byte(*byte)?;
+            }
+        }
+    }
+
+    /// Handles a received byte, editing the current line or executing it when it ends.
+    ///
+    /// # Arguments
+    ///
+    /// - `byte`: The received byte
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::WriteError` or `ShellError::UartError`: If writing the transport failed
+    fn handle_byte(&mut self, byte: u8) -> Result<(), ShellError> {
+        let last_was_cr = self.last_was_cr;
+        self.last_was_cr = byte == b'\r';
+        match byte {
+            b'\n' if last_was_cr => Ok(()),
+            b'\r' | b'\n' => {
+                if self.echo {
+                    self.transport.write(b"\r\n")?;
+                }
+                let line = std::mem::take(&mut self.line);
+                self.run_line(&line)
+            }
+            BACKSPACE | DELETE => {
+                if self.line.pop().is_some() && self.echo {
+                    self.transport.write(b"\x08 \x08")?;
+                }
+                Ok(())
+            }
+            b' '..=b'~' if self.line.len() < MAX_LINE_LENGTH => {
+                self.line.push(byte as char);
+                if self.echo {
+                    let echoed = if self.authenticated { byte } else { b'*' };
+                    self.transport.write(&[echoed])?;
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Executes a line and writes its output, followed by the prompt.
+    ///
+    /// # Arguments
+    ///
+    /// - `line`: The line to execute
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::WriteError` or `ShellError::UartError`: If writing the transport failed
+    fn run_line(&mut self, line: &str) -> Result<(), ShellError> {
+        if !self.authenticated {
+            self.authenticated = self.password.as_deref() == Some(line);
+            if !self.authenticated {
+                self.transport.write(b"wrong password\r\n")?;
+            }
+            return self.write_prompt();
+        }
+
+        let output = match self.execute(line) {
+            Ok(output) => output,
+            Err(err) => format!("error: {}\n", describe_error(&err)),
+        };
+        self.transport
+            .write(output.replace('\n', "\r\n").as_bytes())?;
+
+        if *self.reboot_requested.deref() {
+            restart();
+        }
+        self.write_prompt()
+    }
+
+    /// Writes the prompt, or asks for the password if the session is not authenticated yet.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::WriteError`, `ShellError::UartError` or `ShellError::BleError`: If writing the transport failed
+    fn write_prompt(&mut self) -> Result<(), ShellError> {
+        let prompt = if self.authenticated {
+            self.prompt.clone()
+        } else {
+            PASSWORD_PROMPT.to_string()
+        };
+        self.transport.write(prompt.as_bytes())
+    }
+
+    /// Writes the list of commands, or the help of a single command.
+    ///
+    /// # Arguments
+    ///
+    /// - `command`: An `Option` with the name of the command. If None every command is listed.
+    /// - `out`: The `String` where the help is written
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the operation completed successfully, or a `ShellError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ShellError::UnknownCommand`: If there is no command with the name
+    fn write_help(&self, command: Option<&str>, out: &mut String) -> Result<(), ShellError> {
+        match command {
+            Some(name) => {
+                let command = self
+                    .commands
+                    .get(name)
+                    .ok_or(ShellError::UnknownCommand(name.to_string()))?;
+                _ = writeln!(out, "usage: {} {}", name, command.usage);
+                _ = writeln!(out, "{}", command.help);
+            }
+            None => {
+                _ = writeln!(
+                    out,
+                    "help        Lists the commands, or shows the help of one"
+                );
+                for (name, command) in &self.commands {
+                    _ = writeln!(out, "{:<12}{}", name, command.help);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses an argument, accepting numbers in hexadecimal (`0x`) or binary (`0b`) notation
+/// when the type supports them.
+///
+/// # Arguments
+///
+/// - `arg`: The argument to parse
+///
+/// # Returns
+///
+/// An `Option` with the parsed argument, or None if it could not be parsed
+fn parse_number_or_str<T: FromStr>(arg: &str) -> Option<T> {
+    if let Ok(value) = arg.parse() {
+        return Some(value);
+    }
+    let (digits, radix) = if let Some(hex) = arg.strip_prefix("0x") {
+        (hex, 16)
+    } else if let Some(bin) = arg.strip_prefix("0b") {
+        (bin, 2)
+    } else {
+        return None;
+    };
+    let number = i128::from_str_radix(digits, radix).ok()?;
+    number.to_string().parse().ok()
+}
+
+/// Splits a line into words, grouping the text between quotes and unescaping characters
+/// preceded by `\`.
+///
+/// # Arguments
+///
+/// - `line`: The line to split
+///
+/// # Returns
+///
+/// A `Result` with the words, or a `ShellError` if it fails.
+///
+/// # Errors
+///
+/// - `ShellError::UnterminatedQuote`: If a quote is never closed
+fn split_words(line: &str) -> Result<Vec<String>, ShellError> {
+    let mut words = vec![];
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                if let Some(escaped) = chars.next() {
+                    current.get_or_insert_with(String::new).push(escaped);
+                }
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => current.get_or_insert_with(String::new).push(c),
+            ('"', None) | ('\'', None) => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (c, None) if c.is_whitespace() => {
+                if let Some(word) = current.take() {
+                    words.push(word);
+                }
+            }
+            (c, None) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return Err(ShellError::UnterminatedQuote);
+    }
+    if let Some(word) = current {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Gets the message shown to the user for an error
+///
+/// # Arguments
+///
+/// - `err`: The error to describe
+///
+/// # Returns
+///
+/// A `String` with the message
+fn describe_error(err: &ShellError) -> String {
+    match err {
+        ShellError::CommandAlreadyExists(name) => format!("command {} already exists", name),
+        ShellError::CommandError(message) => message.clone(),
+        ShellError::InvalidArgument(name) => format!("invalid argument: {}", name),
+        ShellError::MissingArgument(name) => format!("missing argument: {}", name),
+        ShellError::UnknownCommand(name) => {
+            format!("unknown command: {}, use help to list the commands", name)
+        }
+        ShellError::UnterminatedQuote => "unterminated quote".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+impl From<BleError> for ShellError {
+    fn from(value: BleError) -> Self {
+        ShellError::BleError(value)
+    }
+}
+
+impl From<UARTError> for ShellError {
+    fn from(value: UARTError) -> Self {
+        ShellError::UartError(value)
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// Prefix of the lines printed to the host by a printing [TrafficRecorder], so they can be told
+/// apart from the rest of the output of the serial monitor
+pub const TRAFFIC_LINE_PREFIX: &str = "traffic: ";
+const I2C_READ: &str = "i2c_read";
+const I2C_WRITE: &str = "i2c_write";
+const I2C_WRITE_READ: &str = "i2c_write_read";
+const UART_READ: &str = "uart_read";
+const UART_WRITE: &str = "uart_write";
+const EMPTY_DATA: &str = "-";
+
+/// Enums the different errors possible when recording or replaying traffic
+#[derive(Debug)]
+pub enum TrafficError {
+    EndOfRecording,
+    FileError,
+    InvalidFormat(usize),
+    UnexpectedTransaction(Transaction),
+}
+
+/// Enums the transactions of the I2C and UART drivers that can be recorded and replayed:
+/// - `I2cRead`: The master read `read` from the slave at `addr`.
+/// - `I2cWrite`: The master wrote `written` to the slave at `addr`.
+/// - `I2cWriteRead`: The master wrote `written` to the slave at `addr` and read `read` back.
+/// - `UartRead`: The UART received `read`.
+/// - `UartWrite`: The UART sent `written`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transaction {
+    I2cRead {
+        addr: u8,
+        read: Vec<u8>,
+    },
+    I2cWrite {
+        addr: u8,
+        written: Vec<u8>,
+    },
+    I2cWriteRead {
+        addr: u8,
+        written: Vec<u8>,
+        read: Vec<u8>,
+    },
+    UartRead {
+        read: Vec<u8>,
+    },
+    UartWrite {
+        written: Vec<u8>,
+    },
+}
+
+/// Enums the ways a driver can handle its traffic:
+/// - `Live`: Transactions go to the bus and are not recorded. The default mode.
+/// - `Record`: Transactions go to the bus and the successful ones are recorded in the
+///   TrafficRecorder.
+/// - `Replay`: Transactions do not touch the bus. Each one is answered with the next transaction
+///   of the TrafficReplay, failing if it is not the one the driver is doing.
+#[derive(Clone, Default)]
+pub enum TrafficMode {
+    #[default]
+    Live,
+    Record(TrafficRecorder),
+    Replay(TrafficReplay),
+}
+
+/// Records the transactions of one or more I2C or UART drivers, in order to reproduce the traffic
+/// of a real device on tests with a [TrafficReplay]. It is a handle, so clones record on the same
+/// recording, and it can be read while the drivers are owned by a sensor.
+///
+/// The recording can be stored in flash, or in a SD card, with [Self::save]. A printing recorder
+/// also sends each transaction to the host as it happens, on a line starting with
+/// [TRAFFIC_LINE_PREFIX], so nothing is lost if the device crashes. The output of the serial
+/// monitor can then be replayed with [TrafficReplay::from_text].
+#[derive(Clone, Default)]
+pub struct TrafficRecorder {
+    transactions: Arc<Mutex<Vec<Transaction>>>,
+    print: bool,
+}
+
+/// Replays the transactions recorded by a [TrafficRecorder], in the same order. It is a handle,
+/// so it can be checked with [Self::remaining] while the drivers are owned by a sensor.
+#[derive(Clone, Default)]
+pub struct TrafficReplay {
+    transactions: Arc<Mutex<VecDeque<Transaction>>>,
+}
+
+impl Transaction {
+    /// Checks whether the transaction can answer a request of a driver. Written bytes must be the
+    /// same, and read bytes must fit in the buffer of the request: exactly on I2C, where the master
+    /// decides how many bytes to read, or up to its size on UART.
+    ///
+    /// # Arguments
+    ///
+    /// - `request`: The transaction the driver is doing, with a zeroed buffer to read
+    ///
+    /// # Returns
+    ///
+    /// A bool that is true if the transaction answers the request
+    pub(crate) fn answers(&self, request: &Transaction) -> bool {
+        match (self, request) {
+            (
+                Transaction::I2cRead { addr, read },
+                Transaction::I2cRead {
+                    addr: req_addr,
+                    read: req_read,
+                },
+            ) => addr == req_addr && read.len() == req_read.len(),
+            (
+                Transaction::I2cWriteRead {
+                    addr,
+                    written,
+                    read,
+                },
+                Transaction::I2cWriteRead {
+                    addr: req_addr,
+                    written: req_written,
+                    read: req_read,
+                },
+            ) => addr == req_addr && written == req_written && read.len() == req_read.len(),
+            (Transaction::UartRead { read }, Transaction::UartRead { read: req_read }) => {
+                read.len() <= req_read.len()
+            }
+            (recorded, request) => recorded == request,
+        }
+    }
+
+    /// Gets the bytes read in the transaction
+    ///
+    /// # Returns
+    ///
+    /// A slice with the bytes read, empty for writes
+    pub fn read_bytes(&self) -> &[u8] {
+        match self {
+            Transaction::I2cRead { read, .. }
+            | Transaction::I2cWriteRead { read, .. }
+            | Transaction::UartRead { read } => read,
+            Transaction::I2cWrite { .. } | Transaction::UartWrite { .. } => &[],
+        }
+    }
+
+    /// Writes the transaction as a line of text: the kind of transaction, the address in hexadecimal
+    /// for I2C, and the written and read bytes in hexadecimal, with `-` for no bytes. For example
+    /// `i2c_write_read 68 00 2a3b`.
+    ///
+    /// # Returns
+    ///
+    /// A String with the line, without the line break
+    pub fn to_line(&self) -> String {
+        match self {
+            Transaction::I2cRead { addr, read } => {
+                format!("{I2C_READ} {addr:02x} {}", to_hex(read))
+            }
+            Transaction::I2cWrite { addr, written } => {
+                format!("{I2C_WRITE} {addr:02x} {}", to_hex(written))
+            }
+            Transaction::I2cWriteRead {
+                addr,
+                written,
+                read,
+            } => format!(
+                "{I2C_WRITE_READ} {addr:02x} {} {}",
+                to_hex(written),
+                to_hex(read)
+            ),
+            Transaction::UartRead { read } => format!("{UART_READ} {}", to_hex(read)),
+            Transaction::UartWrite { written } => format!("{UART_WRITE} {}", to_hex(written)),
+        }
+    }
+
+    /// Parses a line written by [Self::to_line]
+    ///
+    /// # Arguments
+    ///
+    /// - `line`: The line, with or without the [TRAFFIC_LINE_PREFIX]
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the Transaction, or None if the line is not a valid transaction
+    pub fn from_line(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let line = line.strip_prefix(TRAFFIC_LINE_PREFIX).unwrap_or(line);
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let addr = |field: &str| u8::from_str_radix(field, 16).ok();
+        match fields.as_slice() {
+            [I2C_READ, a, read] => Some(Transaction::I2cRead {
+                addr: addr(a)?,
+                read: from_hex(read)?,
+            }),
+            [I2C_WRITE, a, written] => Some(Transaction::I2cWrite {
+                addr: addr(a)?,
+                written: from_hex(written)?,
+            }),
+            [I2C_WRITE_READ, a, written, read] => Some(Transaction::I2cWriteRead {
+                addr: addr(a)?,
+                written: from_hex(written)?,
+                read: from_hex(read)?,
+            }),
+            [UART_READ, read] => Some(Transaction::UartRead {
+                read: from_hex(read)?,
+            }),
+            [UART_WRITE, written] => Some(Transaction::UartWrite {
+                written: from_hex(written)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl TrafficRecorder {
+    /// Creates a new TrafficRecorder that keeps the transactions in memory
+    ///
+    /// # Returns
+    ///
+    /// The new TrafficRecorder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new TrafficRecorder that keeps the transactions in memory and also prints each
+    /// one to the host, on a line starting with [TRAFFIC_LINE_PREFIX]
+    ///
+    /// # Returns
+    ///
+    /// The new TrafficRecorder
+    pub fn printing() -> Self {
+        TrafficRecorder {
+            transactions: Arc::default(),
+            print: true,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Transaction>> {
+        self.transactions
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Adds a transaction at the end of the recording
+    ///
+    /// # Arguments
+    ///
+    /// - `transaction`: The Transaction done by a driver
+    pub(crate) fn record(&self, transaction: Transaction) {
+        if self.print {
+            println!("{TRAFFIC_LINE_PREFIX}{}", transaction.to_line());
+        }
+        self.lock().push(transaction);
+    }
+
+    /// Gets the transactions recorded so far
+    ///
+    /// # Returns
+    ///
+    /// A Vec with the transactions, in the order they were done
+    pub fn transactions(&self) -> Vec<Transaction> {
+        self.lock().clone()
+    }
+
+    /// Removes every transaction recorded so far
+    pub fn clear(&self) {
+        self.lock().clear()
+    }
+
+    /// Writes the recording as text, with a line for each transaction as described in
+    /// [Transaction::to_line]
+    ///
+    /// # Returns
+    ///
+    /// A String with the recording
+    pub fn to_text(&self) -> String {
+        self.lock()
+            .iter()
+            .fold(String::new(), |mut text, transaction| {
+                let _ = writeln!(text, "{}", transaction.to_line());
+                text
+            })
+    }
+
+    /// Stores the recording as text in a file, replacing it if it exists. The path must be on a
+    /// mounted filesystem, such as a FAT or SPIFFS partition of the flash or a
+    /// [crate::storage::SdCard].
+    ///
+    /// # Arguments
+    ///
+    /// - `path`: The path of the file
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the recording was stored, or a `TrafficError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TrafficError::FileError`: If the file could not be written
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TrafficError> {
+        std::fs::write(path, self.to_text()).map_err(|_| TrafficError::FileError)
+    }
+
+    /// Gets a TrafficReplay of the transactions recorded so far
+    ///
+    /// # Returns
+    ///
+    /// The new TrafficReplay
+    pub fn replay(&self) -> TrafficReplay {
+        TrafficReplay::from_transactions(self.transactions())
+    }
+}
+
+impl TrafficReplay {
+    /// Creates a new TrafficReplay of some transactions
+    ///
+    /// # Arguments
+    ///
+    /// - `transactions`: The transactions to replay, in order
+    ///
+    /// # Returns
+    ///
+    /// The new TrafficReplay
+    pub fn from_transactions(transactions: Vec<Transaction>) -> Self {
+        TrafficReplay {
+            transactions: Arc::new(Mutex::new(transactions.into())),
+        }
+    }
+
+    /// Creates a new TrafficReplay from a recording written as text by
+    /// [TrafficRecorder::to_text], or from the output of the serial monitor with a printing
+    /// [TrafficRecorder]. If any line starts with the [TRAFFIC_LINE_PREFIX], the lines without it
+    /// are skipped, since they are other output of the program.
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The recording
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TrafficReplay, or a `TrafficError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TrafficError::InvalidFormat`: With the number of the first line, starting from 1, that
+    ///   is not a valid transaction
+    pub fn from_text(text: &str) -> Result<Self, TrafficError> {
+        let from_monitor = text.contains(TRAFFIC_LINE_PREFIX);
+        let mut transactions = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || (from_monitor && !line.starts_with(TRAFFIC_LINE_PREFIX)) {
+                continue;
+            }
+            let transaction =
+                Transaction::from_line(line).ok_or(TrafficError::InvalidFormat(i + 1))?;
+            transactions.push(transaction);
+        }
+        Ok(Self::from_transactions(transactions))
+    }
+
+    /// Creates a new TrafficReplay from a file written by [TrafficRecorder::save]
+    ///
+    /// # Arguments
+    ///
+    /// - `path`: The path of the file
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TrafficReplay, or a `TrafficError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TrafficError::FileError`: If the file could not be read
+    /// - `TrafficError::InvalidFormat`: If a line of the file is not a transaction
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TrafficError> {
+        let text = std::fs::read_to_string(path).map_err(|_| TrafficError::FileError)?;
+        Self::from_text(&text)
+    }
+
+    /// Gets the amount of transactions that were not replayed yet. A test can check it is 0 to
+    /// make sure the driver under test did every transaction of the recording.
+    ///
+    /// # Returns
+    ///
+    /// A usize with the amount of transactions left
+    pub fn remaining(&self) -> usize {
+        self.transactions
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .len()
+    }
+
+    /// Takes the next transaction of the recording, if it answers the request of a driver. If it
+    /// does not, the transaction is kept, so the error can be inspected and the replay continued.
+    ///
+    /// # Arguments
+    ///
+    /// - `request`: The transaction the driver is doing, with a zeroed buffer to read
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the recorded Transaction, or a `TrafficError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TrafficError::EndOfRecording`: If every transaction was already replayed
+    /// - `TrafficError::UnexpectedTransaction`: With the next recorded transaction, if it does
+    ///   not answer the request
+    pub(crate) fn next(&self, request: &Transaction) -> Result<Transaction, TrafficError> {
+        let mut transactions = self
+            .transactions
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let recorded = transactions
+            .pop_front()
+            .ok_or(TrafficError::EndOfRecording)?;
+        if !recorded.answers(request) {
+            transactions.push_front(recorded.clone());
+            return Err(TrafficError::UnexpectedTransaction(recorded));
+        }
+        Ok(recorded)
+    }
+}
+
+/// Writes bytes in hexadecimal, two characters per byte, or `-` if there are no bytes
+fn to_hex(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return EMPTY_DATA.to_string();
+    }
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Parses bytes written by [to_hex]
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex == EMPTY_DATA {
+        return Some(vec![]);
+    }
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn every_kind() -> Vec<Transaction> {
+        vec![
+            Transaction::I2cRead {
+                addr: 0x68,
+                read: vec![0x2a, 0x3b],
+            },
+            Transaction::I2cWrite {
+                addr: 0x3c,
+                written: vec![0x00, 0xaf],
+            },
+            Transaction::I2cWriteRead {
+                addr: 0x68,
+                written: vec![0x00],
+                read: vec![0x2a, 0x3b],
+            },
+            Transaction::UartRead {
+                read: b"OK\r\n".to_vec(),
+            },
+            Transaction::UartWrite { written: vec![] },
+        ]
+    }
+
+    #[test]
+    fn traffic_01_lines() {
+        let lines: Vec<String> = every_kind().iter().map(Transaction::to_line).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "i2c_read 68 2a3b",
+                "i2c_write 3c 00af",
+                "i2c_write_read 68 00 2a3b",
+                "uart_read 4f4b0d0a",
+                "uart_write -",
+            ]
+        );
+    }
+
+    #[test]
+    fn traffic_02_line_round_trip() {
+        for transaction in every_kind() {
+            assert_eq!(
+                Transaction::from_line(&transaction.to_line()),
+                Some(transaction)
+            );
+        }
+    }
+
+    #[test]
+    fn traffic_03_invalid_lines() {
+        assert_eq!(Transaction::from_line("i2c_read 68"), None);
+        assert_eq!(Transaction::from_line("i2c_read 680 2a"), None);
+        assert_eq!(Transaction::from_line("uart_write 2a3"), None);
+        assert_eq!(Transaction::from_line("uart_write zz"), None);
+        assert_eq!(Transaction::from_line("spi_write 2a"), None);
+    }
+
+    #[test]
+    fn traffic_04_text_round_trip() {
+        let recorder = TrafficRecorder::new();
+        for transaction in every_kind() {
+            recorder.record(transaction);
+        }
+        let replay = TrafficReplay::from_text(&recorder.to_text()).unwrap();
+        assert_eq!(replay.remaining(), 5);
+        for transaction in every_kind() {
+            assert_eq!(replay.next(&transaction).unwrap(), transaction);
+        }
+        assert!(matches!(
+            replay.next(&every_kind()[0]),
+            Err(TrafficError::EndOfRecording)
+        ));
+    }
+
+    #[test]
+    fn traffic_05_monitor_output_skips_other_lines() {
+        let text =
+            "I (312) boot: started\ntraffic: uart_write 2a\nreading sensor\ntraffic: uart_read -\n";
+        let replay = TrafficReplay::from_text(text).unwrap();
+        assert_eq!(replay.remaining(), 2);
+    }
+
+    #[test]
+    fn traffic_06_invalid_text_reports_the_line() {
+        let text = "uart_write 2a\n\nuart_read 2\n";
+        assert!(matches!(
+            TrafficReplay::from_text(text),
+            Err(TrafficError::InvalidFormat(3))
+        ));
+    }
+
+    #[test]
+    fn traffic_07_replay_answers_requests() {
+        let replay = TrafficReplay::from_transactions(every_kind());
+        let wrong_addr = Transaction::I2cRead {
+            addr: 0x69,
+            read: vec![0; 2],
+        };
+        assert!(matches!(
+            replay.next(&wrong_addr),
+            Err(TrafficError::UnexpectedTransaction(_))
+        ));
+        assert_eq!(replay.remaining(), 5);
+
+        let request = Transaction::I2cRead {
+            addr: 0x68,
+            read: vec![0; 2],
+        };
+        assert_eq!(replay.next(&request).unwrap().read_bytes(), &[0x2a, 0x3b]);
+
+        let uart_request = Transaction::UartRead { read: vec![0; 16] };
+        replay.next(&every_kind()[1]).unwrap();
+        replay.next(&every_kind()[2]).unwrap();
+        assert_eq!(replay.next(&uart_request).unwrap().read_bytes(), b"OK\r\n");
+    }
+}
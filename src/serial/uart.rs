@@ -1,3 +1,4 @@
+use super::traffic::{TrafficError, TrafficMode, Transaction};
 use crate::{
     microcontroller_src::peripherals::{Peripheral, PeripheralError},
     utils::auxiliary::micro_to_ticks,
@@ -20,6 +21,7 @@ pub enum UARTError {
     InvalidPin,
     InvalidUartNumber,
     ReadError,
+    TrafficError(TrafficError),
     WriteError,
 }
 
@@ -42,6 +44,7 @@ pub enum Parity {
 /// A UART (Universal Asynchronous Receiver Transmitter) driver to handle serial communications.
 pub struct UART<'a> {
     driver: UartDriver<'a>,
+    traffic: TrafficMode,
 }
 
 impl<'a> UART<'a> {
@@ -100,7 +103,10 @@ impl<'a> UART<'a> {
             _ => return Err(UARTError::InvalidUartNumber),
         };
 
-        Ok(UART { driver })
+        Ok(UART {
+            driver,
+            traffic: TrafficMode::Live,
+        })
     }
 
     /// Creates a UART driver with default baudrate of 115200 Hz, none parity and one bit stop bit.
@@ -136,6 +142,101 @@ impl<'a> UART<'a> {
         )
     }
 
+    /// Wraps a UartDriver already created through esp-idf-hal, in order to use it with the framework.
+    ///
+    /// # Arguments
+    ///
+    /// - `driver`: The UartDriver to wrap
+    ///
+    /// # Returns
+    ///
+    /// The new `UART` instance
+    pub fn from_raw(driver: UartDriver<'a>) -> UART<'a> {
+        UART {
+            driver,
+            traffic: TrafficMode::Live,
+        }
+    }
+
+    /// Consumes the `UART`, returning the underlying UartDriver in order to use esp-idf-hal features
+    /// not covered by the framework.
+    ///
+    /// # Returns
+    ///
+    /// The UartDriver of the `UART`
+    pub fn into_inner(self) -> UartDriver<'a> {
+        self.driver
+    }
+
+    /// Sets how the UART handles its transactions, in order to record the traffic with a real
+    /// device, or to replay it on tests of a driver without the device. The pins are not touched
+    /// while replaying, so they can be left unconnected.
+    ///
+    /// # Arguments
+    ///
+    /// - `mode`: The TrafficMode to use
+    pub fn set_traffic_mode(&mut self, mode: TrafficMode) {
+        self.traffic = mode;
+    }
+
+    /// Adds a successful transaction to the recording, if the UART is recording
+    fn record<F: FnOnce() -> Transaction>(&self, transaction: F) {
+        if let TrafficMode::Record(recorder) = &self.traffic {
+            recorder.record(transaction())
+        }
+    }
+
+    /// Answers a transaction with the recording, if the UART is replaying
+    ///
+    /// # Arguments
+    ///
+    /// - `request`: Creates the transaction to answer, with a zeroed buffer to read
+    /// - `buffer`: Where to store the recorded bytes read
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the `Result` of the replay, containing the amount of bytes read, or None
+    /// if the UART is not replaying
+    fn replay<F: FnOnce() -> Transaction>(
+        &self,
+        request: F,
+        buffer: &mut [u8],
+    ) -> Option<Result<usize, UARTError>> {
+        let TrafficMode::Replay(replay) = &self.traffic else {
+            return None;
+        };
+        Some(
+            replay
+                .next(&request())
+                .map(|recorded| {
+                    let read = recorded.read_bytes();
+                    buffer[..read.len()].copy_from_slice(read);
+                    read.len()
+                })
+                .map_err(UARTError::TrafficError),
+        )
+    }
+
+    /// Reads from the UART, or from the recording if replaying, and records what was read if
+    /// recording
+    fn read_traffic(&mut self, buffer: &mut [u8], timeout: u32) -> Result<usize, UARTError> {
+        let read_len = buffer.len();
+        let request = || Transaction::UartRead {
+            read: vec![0; read_len],
+        };
+        if let Some(res) = self.replay(request, buffer) {
+            return res;
+        }
+        let read = self
+            .driver
+            .read(buffer, timeout)
+            .map_err(|_| UARTError::ReadError)?;
+        self.record(|| Transaction::UartRead {
+            read: buffer[..read].to_vec(),
+        });
+        Ok(read)
+    }
+
     /// Write multiple bytes from a slice. Returns how many bytes were written or an error
     /// if the write operation fails.
     ///
@@ -151,10 +252,22 @@ impl<'a> UART<'a> {
     /// # Errors
     ///
     /// - `UARTError::WriteError`: If the write operation failed.
+    /// - `UARTError::TrafficError`: If replaying, and the next recorded transaction is not this one.
     pub fn write(&mut self, bytes_to_write: &[u8]) -> Result<usize, UARTError> {
-        self.driver
+        let request = || Transaction::UartWrite {
+            written: bytes_to_write.to_vec(),
+        };
+        if let Some(res) = self.replay(request, &mut []) {
+            return res.map(|_| bytes_to_write.len());
+        }
+        let written = self
+            .driver
             .write(bytes_to_write)
-            .map_err(|_| UARTError::WriteError)
+            .map_err(|_| UARTError::WriteError)?;
+        self.record(|| Transaction::UartWrite {
+            written: bytes_to_write[..written].to_vec(),
+        });
+        Ok(written)
     }
 
     /// Reads from the UART buffer without a timeout. This means that the function will be blocking
@@ -172,10 +285,10 @@ impl<'a> UART<'a> {
     /// # Errors
     ///
     /// - `UARTError::ReadError`: If the read operation failed.
+    /// - `UARTError::TrafficError`: If replaying, and the next recorded transaction is not a read
+    ///   that fits in the buffer.
     pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, UARTError> {
-        self.driver
-            .read(buffer, BLOCK)
-            .map_err(|_| UARTError::ReadError)
+        self.read_traffic(buffer, BLOCK)
     }
 
     /// Reads from the UART buffer with a timeout in us (microsec). The function will
@@ -193,15 +306,15 @@ impl<'a> UART<'a> {
     /// # Errors
     ///
     /// - `UARTError::ReadError`: If the read operation failed.
+    /// - `UARTError::TrafficError`: If replaying, and the next recorded transaction is not a read
+    ///   that fits in the buffer.
     pub fn read_with_timeout(
         &mut self,
         buffer: &mut [u8],
         timeout_us: u32,
     ) -> Result<usize, UARTError> {
         let timeout: u32 = micro_to_ticks(timeout_us);
-        self.driver
-            .read(buffer, timeout)
-            .map_err(|_| UARTError::ReadError)
+        self.read_traffic(buffer, timeout)
     }
 }
 
@@ -0,0 +1,219 @@
+use crate::utils::event_log::{record_event, EventKind};
+use esp_idf_svc::{hal::reset::restart, ota::EspOta};
+use serde_json::Value;
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+const DEFAULT_CONFIG_FILE: &str = "config.json";
+const DEFAULT_FIRMWARE_FILE: &str = "firmware.bin";
+const APPLIED_SUFFIX: &str = ".applied";
+const FAILED_SUFFIX: &str = ".failed";
+const FIRMWARE_CHUNK_SIZE: usize = 4096;
+
+/// Enums the different errors possible when applying a FieldUpdate
+#[derive(Debug)]
+pub enum FieldUpdateError {
+    ConfigRejected,
+    InvalidConfig,
+    OtaError,
+    ReadError,
+    RenameError,
+}
+
+/// The result of a FieldUpdate, for each of its files. A file that was not found is None.
+#[derive(Debug)]
+pub struct FieldUpdateReport {
+    pub config: Option<Result<(), FieldUpdateError>>,
+    pub firmware: Option<Result<(), FieldUpdateError>>,
+}
+
+/// Applies updates dropped on a filesystem, such as a [crate::storage::SdCard], so deployed devices
+/// can be reconfigured or upgraded without a network. It should be run at boot:
+/// - If there is a `config.json`, it is parsed and passed to the config handler.
+/// - If there is a `firmware.bin`, it is written to the next OTA partition, which is booted after
+///   restarting. The partition table must have OTA partitions. If rollback is enabled, as explained
+///   in [crate::wifi::OtaUpdater], the new firmware must confirm it works with
+///   [crate::wifi::OtaUpdater::mark_valid], or the bootloader rolls back to the previous one on the
+///   next restart.
+///
+/// After each file is processed it is renamed, adding `.applied` or `.failed` to its name, so it is
+/// applied only once and the result can be checked by taking the card out. Results are also
+/// recorded on the [crate::utils::event_log::EventLog], if it was opened.
+///
+/// Any mounted filesystem can be used, for example a USB mass storage device on boards that
+/// support it.
+pub struct FieldUpdate<'a> {
+    directory: PathBuf,
+    config_file: String,
+    firmware_file: String,
+    on_config: Option<Box<dyn FnMut(&Value) -> bool + 'a>>,
+    restart_after_firmware: bool,
+}
+
+impl<'a> FieldUpdate<'a> {
+    /// Creates a new FieldUpdate that looks for `config.json` and `firmware.bin` in a directory.
+    /// By default the microcontroller restarts after a firmware is staged.
+    ///
+    /// # Arguments
+    ///
+    /// - `directory`: The directory where the files are dropped, for example the mount point of
+    ///   a SD card
+    ///
+    /// # Returns
+    ///
+    /// The new FieldUpdate
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        FieldUpdate {
+            directory: directory.as_ref().to_path_buf(),
+            config_file: DEFAULT_CONFIG_FILE.to_string(),
+            firmware_file: DEFAULT_FIRMWARE_FILE.to_string(),
+            on_config: None,
+            restart_after_firmware: true,
+        }
+    }
+
+    /// Sets the names of the files looked for
+    ///
+    /// # Arguments
+    ///
+    /// - `config_file`: The name of the configuration file
+    /// - `firmware_file`: The name of the firmware image
+    ///
+    /// # Returns
+    ///
+    /// The FieldUpdate with the new names
+    pub fn file_names(mut self, config_file: &str, firmware_file: &str) -> Self {
+        self.config_file = config_file.to_string();
+        self.firmware_file = firmware_file.to_string();
+        self
+    }
+
+    /// Sets the closure that applies the configuration, for example storing it or passing it to
+    /// [crate::wifi::DeviceTwin::handle_desired]. Without it, configuration files are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// - `handler`: A closure that receives the parsed configuration, and returns whether it was
+    ///   valid and applied
+    ///
+    /// # Returns
+    ///
+    /// The FieldUpdate with the handler set
+    pub fn on_config<F: FnMut(&Value) -> bool + 'a>(mut self, handler: F) -> Self {
+        self.on_config = Some(Box::new(handler));
+        self
+    }
+
+    /// Sets whether the microcontroller restarts, to boot the new firmware, right after staging it
+    ///
+    /// # Arguments
+    ///
+    /// - `restart`: Whether to restart after a firmware is staged
+    ///
+    /// # Returns
+    ///
+    /// The FieldUpdate with the new setting
+    pub fn restart_after_firmware(mut self, restart: bool) -> Self {
+        self.restart_after_firmware = restart;
+        self
+    }
+
+    /// Looks for the files and applies them, the configuration first. If a firmware is staged and
+    /// restarting is enabled, this function does not return.
+    ///
+    /// # Returns
+    ///
+    /// A `FieldUpdateReport` with the result of each file found
+    pub fn run(&mut self) -> FieldUpdateReport {
+        let mut report = FieldUpdateReport {
+            config: None,
+            firmware: None,
+        };
+
+        let config_path = self.directory.join(&self.config_file);
+        if self.on_config.is_some() && config_path.is_file() {
+            let result = self.apply_config(&config_path);
+            report.config = Some(finish(&config_path, result));
+        }
+
+        let firmware_path = self.directory.join(&self.firmware_file);
+        if firmware_path.is_file() {
+            let result = finish(&firmware_path, stage_firmware(&firmware_path));
+            let staged = result.is_ok();
+            report.firmware = Some(result);
+            if staged && self.restart_after_firmware {
+                restart();
+            }
+        }
+        report
+    }
+
+    /// Parses a configuration file and passes it to the handler
+    fn apply_config(&mut self, path: &Path) -> Result<(), FieldUpdateError> {
+        let data = fs::read(path).map_err(|_| FieldUpdateError::ReadError)?;
+        let config: Value =
+            serde_json::from_slice(&data).map_err(|_| FieldUpdateError::InvalidConfig)?;
+        let handler = self
+            .on_config
+            .as_mut()
+            .ok_or(FieldUpdateError::ConfigRejected)?;
+        if !handler(&config) {
+            return Err(FieldUpdateError::ConfigRejected);
+        }
+        Ok(())
+    }
+}
+
+/// Writes a firmware image to the next OTA partition and sets it as the boot partition. The image
+/// is validated by ESP-IDF before it is set.
+fn stage_firmware(path: &Path) -> Result<(), FieldUpdateError> {
+    let mut file = File::open(path).map_err(|_| FieldUpdateError::ReadError)?;
+    let mut ota = EspOta::new().map_err(|_| FieldUpdateError::OtaError)?;
+    let mut update = ota
+        .initiate_update()
+        .map_err(|_| FieldUpdateError::OtaError)?;
+
+    let mut buffer = vec![0; FIRMWARE_CHUNK_SIZE];
+    loop {
+        let read = match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(_) => {
+                _ = update.abort();
+                return Err(FieldUpdateError::ReadError);
+            }
+        };
+        if update.write(&buffer[..read]).is_err() {
+            _ = update.abort();
+            return Err(FieldUpdateError::OtaError);
+        }
+    }
+    update.complete().map_err(|_| FieldUpdateError::OtaError)
+}
+
+/// Renames a processed file to mark the result, and records it on the event log
+fn finish(path: &Path, result: Result<(), FieldUpdateError>) -> Result<(), FieldUpdateError> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match &result {
+        Ok(()) => record_event(EventKind::Update, &format!("{} applied", name)),
+        Err(err) => record_event(EventKind::Update, &format!("{} {:?}", name, err)),
+    }
+
+    let suffix = if result.is_ok() {
+        APPLIED_SUFFIX
+    } else {
+        FAILED_SUFFIX
+    };
+    let mut renamed = path.as_os_str().to_owned();
+    renamed.push(suffix);
+    // FAT does not replace existing files when renaming
+    _ = fs::remove_file(&renamed);
+    fs::rename(path, &renamed).map_err(|_| FieldUpdateError::RenameError)?;
+    result
+}
@@ -0,0 +1,5 @@
+mod field_update;
+mod sd_card;
+
+pub use field_update::*;
+pub use sd_card::*;
@@ -0,0 +1,219 @@
+use crate::microcontroller_src::peripherals::{Peripheral, PeripheralError};
+use esp_idf_svc::{
+    hal::gpio::{AnyIOPin, Pin},
+    sys::{
+        esp_vfs_fat_mount_config_t, esp_vfs_fat_sdcard_unmount, esp_vfs_fat_sdspi_mount,
+        sdmmc_card_t, sdmmc_host_t, sdmmc_host_t__bindgen_ty_1, sdspi_device_config_t,
+        sdspi_host_do_transaction, sdspi_host_get_real_freq, sdspi_host_init,
+        sdspi_host_io_int_enable, sdspi_host_io_int_wait, sdspi_host_remove_device,
+        sdspi_host_set_card_clk, spi_bus_config_t, spi_bus_config_t__bindgen_ty_1,
+        spi_bus_config_t__bindgen_ty_2, spi_bus_config_t__bindgen_ty_3,
+        spi_bus_config_t__bindgen_ty_4, spi_bus_free, spi_bus_initialize,
+        spi_common_dma_t_SPI_DMA_CH_AUTO, spi_host_device_t_SPI2_HOST, ESP_ERR_INVALID_STATE,
+        ESP_OK,
+    },
+};
+use std::{ffi::CString, path::PathBuf, ptr};
+
+const SDMMC_HOST_FLAG_SPI: u32 = 1 << 3;
+const SDMMC_HOST_FLAG_DEINIT_ARG: u32 = 1 << 5;
+const SDMMC_FREQ_DEFAULT_KHZ: i32 = 20_000;
+const MAX_TRANSFER_SIZE: i32 = 4000;
+const MAX_OPEN_FILES: i32 = 5;
+const ALLOCATION_UNIT_SIZE: usize = 16 * 1024;
+const UNUSED_PIN: i32 = -1;
+
+/// Enums the different errors possible when working with the SdCard
+#[derive(Debug)]
+pub enum SdCardError {
+    AlreadyMounted,
+    InvalidMountPoint,
+    MountError(i32),
+    PeripheralError(PeripheralError),
+    SpiBusError(i32),
+}
+
+/// A SD card connected in SPI mode, with its FAT filesystem mounted on the VFS. Once mounted, its
+/// files are accessed with `std::fs`, on paths that start with the mount point. The card uses the
+/// SPI2 bus, so only one card can be mounted at a time. It is unmounted when dropped.
+pub struct SdCard {
+    card: *mut sdmmc_card_t,
+    mount_point: CString,
+    _pins: [AnyIOPin; 4],
+}
+
+impl SdCard {
+    /// Mounts the FAT filesystem of a SD card
+    ///
+    /// # Arguments
+    ///
+    /// - `sclk_per`: The Peripheral of the clock pin
+    /// - `mosi_per`: The Peripheral of the pin the card receives data on
+    /// - `miso_per`: The Peripheral of the pin the card sends data on
+    /// - `cs_per`: The Peripheral of the chip select pin
+    /// - `mount_point`: The path where the filesystem is mounted, for example "/sdcard"
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new SdCard, or a `SdCardError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `SdCardError::PeripheralError`: If any of the pins cannot be converted to an IO pin
+    /// - `SdCardError::InvalidMountPoint`: If the mount point does not start with '/'
+    /// - `SdCardError::AlreadyMounted`: If a SD card is already mounted
+    /// - `SdCardError::SpiBusError`: If the SPI bus could not be initialized
+    /// - `SdCardError::MountError`: If there is no card, or it does not have a FAT filesystem
+    pub(crate) fn new(
+        sclk_per: Peripheral,
+        mosi_per: Peripheral,
+        miso_per: Peripheral,
+        cs_per: Peripheral,
+        mount_point: &str,
+    ) -> Result<Self, SdCardError> {
+        let sclk = sclk_per
+            .into_any_io_pin()
+            .map_err(SdCardError::PeripheralError)?;
+        let mosi = mosi_per
+            .into_any_io_pin()
+            .map_err(SdCardError::PeripheralError)?;
+        let miso = miso_per
+            .into_any_io_pin()
+            .map_err(SdCardError::PeripheralError)?;
+        let cs = cs_per
+            .into_any_io_pin()
+            .map_err(SdCardError::PeripheralError)?;
+        if !mount_point.starts_with('/') {
+            return Err(SdCardError::InvalidMountPoint);
+        }
+        let mount_point = CString::new(mount_point).map_err(|_| SdCardError::InvalidMountPoint)?;
+
+        let bus_config = spi_bus_config_t {
+            __bindgen_anon_1: spi_bus_config_t__bindgen_ty_1 {
+                mosi_io_num: mosi.pin(),
+            },
+            __bindgen_anon_2: spi_bus_config_t__bindgen_ty_2 {
+                miso_io_num: miso.pin(),
+            },
+            sclk_io_num: sclk.pin(),
+            __bindgen_anon_3: spi_bus_config_t__bindgen_ty_3 {
+                quadwp_io_num: UNUSED_PIN,
+            },
+            __bindgen_anon_4: spi_bus_config_t__bindgen_ty_4 {
+                quadhd_io_num: UNUSED_PIN,
+            },
+            max_transfer_sz: MAX_TRANSFER_SIZE,
+            ..Default::default()
+        };
+        let err = unsafe {
+            spi_bus_initialize(
+                spi_host_device_t_SPI2_HOST,
+                &bus_config,
+                spi_common_dma_t_SPI_DMA_CH_AUTO,
+            )
+        };
+        match err {
+            ESP_OK => {}
+            ESP_ERR_INVALID_STATE => return Err(SdCardError::AlreadyMounted),
+            err => return Err(SdCardError::SpiBusError(err)),
+        }
+
+        let slot_config = sdspi_device_config_t {
+            host_id: spi_host_device_t_SPI2_HOST,
+            gpio_cs: cs.pin(),
+            gpio_cd: UNUSED_PIN,
+            gpio_wp: UNUSED_PIN,
+            gpio_int: UNUSED_PIN,
+            ..Default::default()
+        };
+        let mount_config = esp_vfs_fat_mount_config_t {
+            format_if_mount_failed: false,
+            max_files: MAX_OPEN_FILES,
+            allocation_unit_size: ALLOCATION_UNIT_SIZE,
+            ..Default::default()
+        };
+        let mut card = ptr::null_mut();
+        let err = unsafe {
+            esp_vfs_fat_sdspi_mount(
+                mount_point.as_ptr(),
+                &sdspi_host_default(),
+                &slot_config,
+                &mount_config,
+                &mut card,
+            )
+        };
+        if err != ESP_OK {
+            unsafe { spi_bus_free(spi_host_device_t_SPI2_HOST) };
+            return Err(SdCardError::MountError(err));
+        }
+
+        Ok(SdCard {
+            card,
+            mount_point,
+            _pins: [sclk, mosi, miso, cs],
+        })
+    }
+
+    /// Gets the path where the filesystem is mounted
+    ///
+    /// # Returns
+    ///
+    /// The mount point
+    pub fn mount_point(&self) -> PathBuf {
+        PathBuf::from(self.mount_point.to_string_lossy().as_ref())
+    }
+
+    /// Gets the path of a file of the card
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The path of the file relative to the root of the card
+    ///
+    /// # Returns
+    ///
+    /// The absolute path of the file, to be used with `std::fs`
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.mount_point().join(name.trim_start_matches('/'))
+    }
+
+    /// Gets the size of the card
+    ///
+    /// # Returns
+    ///
+    /// The size in bytes
+    pub fn capacity(&self) -> u64 {
+        let csd = unsafe { (*self.card).csd };
+        csd.capacity as u64 * csd.sector_size as u64
+    }
+}
+
+impl Drop for SdCard {
+    /// Unmounts the filesystem and frees the SPI bus
+    fn drop(&mut self) {
+        unsafe {
+            esp_vfs_fat_sdcard_unmount(self.mount_point.as_ptr(), self.card);
+            spi_bus_free(spi_host_device_t_SPI2_HOST);
+        }
+    }
+}
+
+/// Gets the configuration of a SD card host in SPI mode, the same as `SDSPI_HOST_DEFAULT` of
+/// ESP-IDF, which is a macro and not available from Rust
+fn sdspi_host_default() -> sdmmc_host_t {
+    sdmmc_host_t {
+        flags: SDMMC_HOST_FLAG_SPI | SDMMC_HOST_FLAG_DEINIT_ARG,
+        slot: spi_host_device_t_SPI2_HOST as i32,
+        max_freq_khz: SDMMC_FREQ_DEFAULT_KHZ,
+        io_voltage: 3.3,
+        init: Some(sdspi_host_init),
+        set_card_clk: Some(sdspi_host_set_card_clk),
+        do_transaction: Some(sdspi_host_do_transaction),
+        __bindgen_anon_1: sdmmc_host_t__bindgen_ty_1 {
+            deinit_p: Some(sdspi_host_remove_device),
+        },
+        io_int_enable: Some(sdspi_host_io_int_enable),
+        io_int_wait: Some(sdspi_host_io_int_wait),
+        get_real_freq: Some(sdspi_host_get_real_freq),
+        ..Default::default()
+    }
+}
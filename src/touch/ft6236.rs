@@ -0,0 +1,79 @@
+use super::{TouchController, TouchError, TouchPoint};
+use crate::serial::i2c::I2CMaster;
+
+const FT6236_ADDR: u8 = 0x38;
+const TD_STATUS_ADDR: u8 = 0x02;
+const CHIP_ID_ADDR: u8 = 0xA3;
+const TOUCH_COUNT_MASK: u8 = 0x0F;
+const COORDINATE_HIGH_MASK: u8 = 0x0F;
+const MAX_TOUCHES: u8 = 2;
+const TIMEOUT_US: u32 = 10_000;
+
+/// Driver of the FocalTech FT6236/FT6336 capacitive touch controllers, connected through I2C.
+/// The coordinates are reported by the controller already in pixels of the screen.
+pub struct Ft6236<'a> {
+    i2c: I2CMaster<'a>,
+    width: u16,
+    height: u16,
+    swap_xy: bool,
+}
+
+impl<'a> Ft6236<'a> {
+    /// Creates a new Ft6236, checking that the controller answers on the bus
+    ///
+    /// # Arguments
+    ///
+    /// - `i2c`: The I2CMaster the controller is connected to
+    /// - `width`: The width in pixels of the screen
+    /// - `height`: The height in pixels of the screen
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new Ft6236, or a `TouchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::DeviceNotFound`: If no controller answered on the address 0x38
+    pub fn new(mut i2c: I2CMaster<'a>, width: u16, height: u16) -> Result<Self, TouchError> {
+        let mut chip_id = [0u8];
+        i2c.write_read(FT6236_ADDR, &[CHIP_ID_ADDR], &mut chip_id, TIMEOUT_US)
+            .map_err(|_| TouchError::DeviceNotFound)?;
+        Ok(Ft6236 {
+            i2c,
+            width,
+            height,
+            swap_xy: false,
+        })
+    }
+
+    /// Sets whether the axes of the panel are swapped, for screens used in landscape orientation.
+    /// The width and height are kept as given on creation.
+    ///
+    /// # Arguments
+    ///
+    /// - `swap_xy`: Whether the x and y coordinates are swapped
+    pub fn set_swap_xy(&mut self, swap_xy: bool) {
+        self.swap_xy = swap_xy;
+    }
+}
+
+impl TouchController for Ft6236<'_> {
+    fn read_touch(&mut self) -> Result<Option<TouchPoint>, TouchError> {
+        let mut data = [0u8; 5];
+        self.i2c
+            .write_read(FT6236_ADDR, &[TD_STATUS_ADDR], &mut data, TIMEOUT_US)?;
+        let touches = data[0] & TOUCH_COUNT_MASK;
+        if touches == 0 || touches > MAX_TOUCHES {
+            return Ok(None);
+        }
+        let mut x = u16::from_be_bytes([data[1] & COORDINATE_HIGH_MASK, data[2]]);
+        let mut y = u16::from_be_bytes([data[3] & COORDINATE_HIGH_MASK, data[4]]);
+        if self.swap_xy {
+            (x, y) = (y, x);
+        }
+        Ok(Some(TouchPoint {
+            x: x.min(self.width.saturating_sub(1)),
+            y: y.min(self.height.saturating_sub(1)),
+        }))
+    }
+}
@@ -0,0 +1,105 @@
+use super::{TouchController, TouchError, TouchPoint};
+use crate::serial::i2c::I2CMaster;
+
+const GT911_ADDRS: [u8; 2] = [0x5D, 0x14];
+const PRODUCT_ID_ADDR: u16 = 0x8140;
+const STATUS_ADDR: u16 = 0x814E;
+const BUFFER_READY_MASK: u8 = 0x80;
+const TOUCH_COUNT_MASK: u8 = 0x0F;
+const MAX_TOUCHES: u8 = 5;
+const TIMEOUT_US: u32 = 10_000;
+
+/// Driver of the Goodix GT911 capacitive touch controller, connected through I2C. The
+/// coordinates are reported by the controller already in pixels of the screen.
+pub struct Gt911<'a> {
+    i2c: I2CMaster<'a>,
+    addr: u8,
+    width: u16,
+    height: u16,
+    swap_xy: bool,
+    last_point: Option<TouchPoint>,
+}
+
+impl<'a> Gt911<'a> {
+    /// Creates a new Gt911, looking for the controller on both of its possible addresses
+    ///
+    /// # Arguments
+    ///
+    /// - `i2c`: The I2CMaster the controller is connected to
+    /// - `width`: The width in pixels of the screen
+    /// - `height`: The height in pixels of the screen
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new Gt911, or a `TouchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::DeviceNotFound`: If no controller answered on the addresses 0x5D and 0x14
+    pub fn new(mut i2c: I2CMaster<'a>, width: u16, height: u16) -> Result<Self, TouchError> {
+        let mut product_id = [0u8; 4];
+        let addr = GT911_ADDRS
+            .into_iter()
+            .find(|addr| {
+                i2c.write_read(
+                    *addr,
+                    &PRODUCT_ID_ADDR.to_be_bytes(),
+                    &mut product_id,
+                    TIMEOUT_US,
+                )
+                .is_ok()
+            })
+            .ok_or(TouchError::DeviceNotFound)?;
+        Ok(Gt911 {
+            i2c,
+            addr,
+            width,
+            height,
+            swap_xy: false,
+            last_point: None,
+        })
+    }
+
+    /// Sets whether the axes of the panel are swapped, for screens used in landscape orientation.
+    /// The width and height are kept as given on creation.
+    ///
+    /// # Arguments
+    ///
+    /// - `swap_xy`: Whether the x and y coordinates are swapped
+    pub fn set_swap_xy(&mut self, swap_xy: bool) {
+        self.swap_xy = swap_xy;
+    }
+}
+
+impl TouchController for Gt911<'_> {
+    /// Reads the point touched. The controller only updates its buffer at its own report rate,
+    /// so the last point read is returned while there is no new data.
+    fn read_touch(&mut self) -> Result<Option<TouchPoint>, TouchError> {
+        let mut data = [0u8; 6];
+        self.i2c
+            .write_read(self.addr, &STATUS_ADDR.to_be_bytes(), &mut data, TIMEOUT_US)?;
+        let status = data[0];
+        if status & BUFFER_READY_MASK == 0 {
+            return Ok(self.last_point);
+        }
+        let [addr_high, addr_low] = STATUS_ADDR.to_be_bytes();
+        self.i2c
+            .write(self.addr, &[addr_high, addr_low, 0], TIMEOUT_US)?;
+
+        let touches = status & TOUCH_COUNT_MASK;
+        self.last_point = if touches == 0 || touches > MAX_TOUCHES {
+            None
+        } else {
+            let mut x = u16::from_le_bytes([data[2], data[3]]);
+            let mut y = u16::from_le_bytes([data[4], data[5]]);
+            if self.swap_xy {
+                (x, y) = (y, x);
+            }
+            Some(TouchPoint {
+                x: x.min(self.width.saturating_sub(1)),
+                y: y.min(self.height.saturating_sub(1)),
+            })
+        };
+        Ok(self.last_point)
+    }
+}
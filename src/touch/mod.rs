@@ -0,0 +1,9 @@
+mod ft6236;
+mod gt911;
+mod touchscreen;
+mod xpt2046;
+
+pub use ft6236::*;
+pub use gt911::*;
+pub use touchscreen::*;
+pub use xpt2046::*;
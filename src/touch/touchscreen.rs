@@ -0,0 +1,294 @@
+use crate::{
+    gpio::digital::DigitalOutError,
+    microcontroller_src::interrupt_driver::InterruptDriver,
+    serial::i2c::I2CError,
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        timer_driver::{TimerDriver, TimerDriverError},
+    },
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+const DEFAULT_POLL_PERIOD: Duration = Duration::from_millis(20);
+const DEFAULT_MOVE_THRESHOLD: u16 = 2;
+
+/// Enums the different errors possible when working with a touch panel
+#[derive(Debug)]
+pub enum TouchError {
+    DeviceNotFound,
+    DigitalOutError(DigitalOutError),
+    I2CError(I2CError),
+    InvalidCalibration,
+    TimerDriverError(TimerDriverError),
+}
+
+/// A point of the panel, in pixels of the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TouchPoint {
+    pub x: u16,
+    pub y: u16,
+}
+
+/// Events detected by the Touchscreen:
+/// - `Down`: The panel started being touched at the point.
+/// - `Move`: The touch moved to the point.
+/// - `Up`: The panel stopped being touched. It has the last point touched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchEvent {
+    Down(TouchPoint),
+    Move(TouchPoint),
+    Up(TouchPoint),
+}
+
+/// Trait for the controllers of touch panels, that are able to tell where the panel is touched
+pub trait TouchController {
+    /// Reads the point where the panel is touched. If the controller supports multiple touches,
+    /// only the first one is returned.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the TouchPoint, None if the panel is not touched, or a `TouchError` if
+    /// the controller could not be read
+    fn read_touch(&mut self) -> Result<Option<TouchPoint>, TouchError>;
+}
+
+/// Polls a touch controller and fires a TouchEvent through the update loop each time the panel
+/// is touched, the touch moves or it is released. Contains:
+/// - `controller`: The touch controller of the panel.
+/// - `timer_driver`: A TimerDriver used to poll the controller.
+/// - `poll_period`: Time between polls.
+/// - `poll_due`: Set by the timer when the controller must be polled.
+/// - `move_threshold`: Min distance in pixels in any axis for a movement to fire a `Move` event.
+/// - `last_point`: The point touched on the last poll, None if the panel was not touched.
+/// - `user_callback`: Callback executed for each TouchEvent.
+struct _Touchscreen<'a> {
+    controller: Box<dyn TouchController + 'a>,
+    timer_driver: TimerDriver<'a>,
+    poll_period: Duration,
+    poll_due: Arc<AtomicBool>,
+    move_threshold: u16,
+    last_point: Option<TouchPoint>,
+    user_callback: Box<dyn FnMut(TouchEvent) + 'a>,
+}
+
+/// Polls a touch controller and fires a TouchEvent through the update loop each time the panel
+/// is touched, the touch moves or it is released.
+pub struct Touchscreen<'a> {
+    inner: SharableRef<_Touchscreen<'a>>,
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _Touchscreen<'a> {
+    /// Creates a new _Touchscreen
+    ///
+    /// # Arguments
+    ///
+    /// - `controller`: The touch controller of the panel
+    /// - `timer_driver`: A TimerDriver used to poll the controller
+    ///
+    /// # Returns
+    ///
+    /// The new _Touchscreen
+    fn new<T: TouchController + 'a>(controller: T, timer_driver: TimerDriver<'a>) -> Self {
+        _Touchscreen {
+            controller: Box::new(controller),
+            timer_driver,
+            poll_period: DEFAULT_POLL_PERIOD,
+            poll_due: Arc::new(AtomicBool::new(false)),
+            move_threshold: DEFAULT_MOVE_THRESHOLD,
+            last_point: None,
+            user_callback: Box::new(|_| {}),
+        }
+    }
+
+    /// Sets the callback executed for each TouchEvent.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives each TouchEvent
+    ///
+    /// # Returns
+    ///
+    /// The _Touchscreen itself
+    pub fn on_event<C: FnMut(TouchEvent) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.user_callback = Box::new(callback);
+        self
+    }
+
+    /// Sets how often the controller is polled. By default it is polled every 20 ms. Changes are
+    /// applied the next time the touchscreen is started.
+    ///
+    /// # Arguments
+    ///
+    /// - `poll_period`: Time between polls
+    ///
+    /// # Returns
+    ///
+    /// The _Touchscreen itself
+    pub fn set_poll_period(&mut self, poll_period: Duration) -> &mut Self {
+        self.poll_period = poll_period;
+        self
+    }
+
+    /// Sets the min distance the touch must move for a `Move` event to be fired, in order to
+    /// ignore the noise of the panel. By default it is 2 pixels.
+    ///
+    /// # Arguments
+    ///
+    /// - `pixels`: Min distance in pixels in any of the axes
+    ///
+    /// # Returns
+    ///
+    /// The _Touchscreen itself
+    pub fn set_move_threshold(&mut self, pixels: u16) -> &mut Self {
+        self.move_threshold = pixels;
+        self
+    }
+
+    /// Starts polling the controller
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the polling started, or a `TouchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::TimerDriverError`: If the timer driver could not be enabled.
+    pub fn start(&mut self) -> Result<(), TouchError> {
+        let poll_due = self.poll_due.clone();
+        self.timer_driver.interrupt_after_n_times(
+            self.poll_period.as_micros().try_into().unwrap_or(u64::MAX),
+            None,
+            true,
+            move || poll_due.store(true, Ordering::SeqCst),
+        );
+        Ok(self.timer_driver.enable()?)
+    }
+
+    /// Stops polling the controller. If the panel was being touched, no `Up` event is fired.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the polling stopped, or a `TouchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::TimerDriverError`: If the timer driver could not be disabled.
+    pub fn stop(&mut self) -> Result<(), TouchError> {
+        self.last_point = None;
+        Ok(self.timer_driver.disable()?)
+    }
+
+    /// Checks whether the panel was touched on the last poll
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the panel is touched
+    pub fn is_touched(&self) -> bool {
+        self.last_point.is_some()
+    }
+
+    /// Gets the point touched on the last poll
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the TouchPoint, or None if the panel is not touched
+    pub fn last_point(&self) -> Option<TouchPoint> {
+        self.last_point
+    }
+
+    /// Reads the controller and fires the event of the change since the last poll, if any
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the controller was read, or a `TouchError` if it fails.
+    fn poll(&mut self) -> Result<(), TouchError> {
+        let point = self.controller.read_touch()?;
+        let event = match (self.last_point, point) {
+            (None, Some(point)) => TouchEvent::Down(point),
+            (Some(last), None) => TouchEvent::Up(last),
+            (Some(last), Some(point)) if self.has_moved(last, point) => TouchEvent::Move(point),
+            _ => return Ok(()),
+        };
+        self.last_point = point;
+        (self.user_callback)(event);
+        Ok(())
+    }
+
+    /// Checks whether the distance between two points reaches the move threshold
+    fn has_moved(&self, last: TouchPoint, point: TouchPoint) -> bool {
+        last.x.abs_diff(point.x) >= self.move_threshold.max(1)
+            || last.y.abs_diff(point.y) >= self.move_threshold.max(1)
+    }
+
+    /// Polls the controller when it is due
+    fn _update_interrupt(&mut self) -> Result<(), TouchError> {
+        if self.poll_due.swap(false, Ordering::SeqCst) {
+            self.poll()?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Touchscreen<'a> {
+    /// Creates a new Touchscreen
+    ///
+    /// # Arguments
+    ///
+    /// - `controller`: The touch controller of the panel
+    /// - `timer_driver`: A TimerDriver used to poll the controller
+    ///
+    /// # Returns
+    ///
+    /// The new Touchscreen
+    pub(crate) fn new<T: TouchController + 'a>(
+        controller: T,
+        timer_driver: TimerDriver<'a>,
+    ) -> Self {
+        Touchscreen {
+            inner: SharableRef::new_sharable(_Touchscreen::new(controller, timer_driver)),
+        }
+    }
+}
+
+impl<'a> InterruptDriver<'a> for Touchscreen<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        Ok(self.inner.deref_mut()._update_interrupt()?)
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+impl From<TimerDriverError> for TouchError {
+    fn from(value: TimerDriverError) -> Self {
+        TouchError::TimerDriverError(value)
+    }
+}
+
+impl From<I2CError> for TouchError {
+    fn from(value: I2CError) -> Self {
+        TouchError::I2CError(value)
+    }
+}
+
+impl From<DigitalOutError> for TouchError {
+    fn from(value: DigitalOutError) -> Self {
+        TouchError::DigitalOutError(value)
+    }
+}
@@ -0,0 +1,238 @@
+use super::{TouchController, TouchError, TouchPoint};
+use crate::gpio::digital::{DigitalIn, DigitalOut};
+
+const READ_X: u8 = 0xD0;
+const READ_Y: u8 = 0x90;
+const READ_Z1: u8 = 0xB0;
+const READ_Z2: u8 = 0xC0;
+const MAX_RAW_VALUE: u16 = 4095;
+const SAMPLES: usize = 3;
+const DEFAULT_PRESSURE_THRESHOLD: u16 = 400;
+
+/// Calibration of a resistive panel, that maps the raw readings of the XPT2046 to pixels of the
+/// screen. The raw values are the readings on the edges of the screen, and a min greater than
+/// the max inverts the axis. Contains:
+/// - `raw_x_min`: Raw reading of the x axis on the column 0.
+/// - `raw_x_max`: Raw reading of the x axis on the last column.
+/// - `raw_y_min`: Raw reading of the y axis on the row 0.
+/// - `raw_y_max`: Raw reading of the y axis on the last row.
+/// - `width`: Width in pixels of the screen.
+/// - `height`: Height in pixels of the screen.
+/// - `swap_xy`: Whether the axes of the panel are swapped, for screens used in landscape orientation.
+///
+/// The default calibration fits the common 2.8" 240x320 panels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Xpt2046Calibration {
+    pub raw_x_min: u16,
+    pub raw_x_max: u16,
+    pub raw_y_min: u16,
+    pub raw_y_max: u16,
+    pub width: u16,
+    pub height: u16,
+    pub swap_xy: bool,
+}
+
+/// Driver of the XPT2046 resistive touch controller. The SPI bus is driven by software through
+/// digital pins, since the controller works at low clock rates.
+pub struct Xpt2046<'a> {
+    clk: DigitalOut<'a>,
+    mosi: DigitalOut<'a>,
+    miso: DigitalIn<'a>,
+    cs: DigitalOut<'a>,
+    calibration: Xpt2046Calibration,
+    pressure_threshold: u16,
+}
+
+impl Default for Xpt2046Calibration {
+    fn default() -> Self {
+        Xpt2046Calibration {
+            raw_x_min: 200,
+            raw_x_max: 3900,
+            raw_y_min: 200,
+            raw_y_max: 3900,
+            width: 240,
+            height: 320,
+            swap_xy: false,
+        }
+    }
+}
+
+impl Xpt2046Calibration {
+    /// Maps a raw reading to a pixel of an axis
+    fn map(raw: u16, raw_min: u16, raw_max: u16, pixels: u16) -> u16 {
+        let (raw, raw_min, raw_max) = (raw as i32, raw_min as i32, raw_max as i32);
+        let pixel = (raw - raw_min) * (pixels as i32 - 1) / (raw_max - raw_min);
+        pixel.clamp(0, pixels as i32 - 1) as u16
+    }
+
+    /// Checks that the calibration can be used to map readings
+    fn is_valid(&self) -> bool {
+        self.raw_x_min != self.raw_x_max
+            && self.raw_y_min != self.raw_y_max
+            && self.width > 0
+            && self.height > 0
+    }
+}
+
+impl<'a> Xpt2046<'a> {
+    /// Creates a new Xpt2046
+    ///
+    /// # Arguments
+    ///
+    /// - `clk`: The DigitalOut connected to the T_CLK pin
+    /// - `mosi`: The DigitalOut connected to the T_DIN pin
+    /// - `miso`: The DigitalIn connected to the T_DO pin
+    /// - `cs`: The DigitalOut connected to the T_CS pin
+    /// - `calibration`: The Xpt2046Calibration of the panel
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new Xpt2046, or a `TouchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::InvalidCalibration`: If the raw min and max of an axis are equal, or the
+    ///   size of the screen is 0
+    /// - `TouchError::DigitalOutError`: If the levels of the pins could not be set
+    pub fn new(
+        mut clk: DigitalOut<'a>,
+        mosi: DigitalOut<'a>,
+        miso: DigitalIn<'a>,
+        mut cs: DigitalOut<'a>,
+        calibration: Xpt2046Calibration,
+    ) -> Result<Self, TouchError> {
+        if !calibration.is_valid() {
+            return Err(TouchError::InvalidCalibration);
+        }
+        clk.set_low()?;
+        cs.set_high()?;
+        Ok(Xpt2046 {
+            clk,
+            mosi,
+            miso,
+            cs,
+            calibration,
+            pressure_threshold: DEFAULT_PRESSURE_THRESHOLD,
+        })
+    }
+
+    /// Sets the calibration of the panel
+    ///
+    /// # Arguments
+    ///
+    /// - `calibration`: The new Xpt2046Calibration
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the calibration was set, or a `TouchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::InvalidCalibration`: If the raw min and max of an axis are equal, or the
+    ///   size of the screen is 0
+    pub fn set_calibration(&mut self, calibration: Xpt2046Calibration) -> Result<(), TouchError> {
+        if !calibration.is_valid() {
+            return Err(TouchError::InvalidCalibration);
+        }
+        self.calibration = calibration;
+        Ok(())
+    }
+
+    /// Sets the min pressure for the panel to be considered touched. By default it is 400, out
+    /// of 4095.
+    ///
+    /// # Arguments
+    ///
+    /// - `threshold`: The min pressure
+    pub fn set_pressure_threshold(&mut self, threshold: u16) {
+        self.pressure_threshold = threshold;
+    }
+
+    /// Reads the raw values of both axes, without calibration. Useful to find the calibration
+    /// of a panel by touching its edges.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the raw x and y readings, None if the panel is not touched, or a
+    /// `TouchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TouchError::DigitalOutError`: If the levels of the pins could not be set
+    pub fn read_raw(&mut self) -> Result<Option<(u16, u16)>, TouchError> {
+        self.cs.set_low()?;
+        let res = self.read_raw_selected();
+        self.cs.set_high()?;
+        res
+    }
+
+    /// Reads the raw values of both axes while the controller is selected. Each axis is sampled
+    /// a few times and the median is kept, to filter the noise of the panel.
+    fn read_raw_selected(&mut self) -> Result<Option<(u16, u16)>, TouchError> {
+        let z1 = self.read_channel(READ_Z1)?;
+        let z2 = self.read_channel(READ_Z2)?;
+        let pressure = z1 + MAX_RAW_VALUE - z2;
+        if z1 == 0 || pressure < self.pressure_threshold {
+            return Ok(None);
+        }
+        let mut xs = [0u16; SAMPLES];
+        let mut ys = [0u16; SAMPLES];
+        for (x, y) in xs.iter_mut().zip(ys.iter_mut()) {
+            *x = self.read_channel(READ_X)?;
+            *y = self.read_channel(READ_Y)?;
+        }
+        xs.sort_unstable();
+        ys.sort_unstable();
+        Ok(Some((xs[SAMPLES / 2], ys[SAMPLES / 2])))
+    }
+
+    /// Sends a command and reads the 12 bit conversion that follows it
+    fn read_channel(&mut self, command: u8) -> Result<u16, TouchError> {
+        self.transfer(command)?;
+        let high = self.transfer(0)? as u16;
+        let low = self.transfer(0)? as u16;
+        Ok(((high << 8) | low) >> 3)
+    }
+
+    /// Transfers a byte in SPI mode 0, most significant bit first
+    fn transfer(&mut self, byte: u8) -> Result<u8, TouchError> {
+        let mut received = 0u8;
+        for bit in (0..8).rev() {
+            if byte & (1 << bit) != 0 {
+                self.mosi.set_high()?;
+            } else {
+                self.mosi.set_low()?;
+            }
+            self.clk.set_high()?;
+            received = (received << 1) | self.miso.is_high() as u8;
+            self.clk.set_low()?;
+        }
+        Ok(received)
+    }
+}
+
+impl TouchController for Xpt2046<'_> {
+    fn read_touch(&mut self) -> Result<Option<TouchPoint>, TouchError> {
+        let Some((mut raw_x, mut raw_y)) = self.read_raw()? else {
+            return Ok(None);
+        };
+        let calibration = self.calibration;
+        if calibration.swap_xy {
+            (raw_x, raw_y) = (raw_y, raw_x);
+        }
+        Ok(Some(TouchPoint {
+            x: Xpt2046Calibration::map(
+                raw_x,
+                calibration.raw_x_min,
+                calibration.raw_x_max,
+                calibration.width,
+            ),
+            y: Xpt2046Calibration::map(
+                raw_y,
+                calibration.raw_y_min,
+                calibration.raw_y_max,
+                calibration.height,
+            ),
+        }))
+    }
+}
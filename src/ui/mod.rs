@@ -0,0 +1,5 @@
+mod font;
+mod screen;
+mod widgets;
+
+pub use screen::*;
@@ -0,0 +1,418 @@
+use super::widgets::{Label, Menu, ProgressBar, Widget, MENU_ITEM_HEIGHT};
+use crate::{gpio::digital::InputEvent, utils::bitmap::Bitmap};
+
+/// Enums the different errors possible when working with a Screen
+#[derive(Debug, PartialEq)]
+pub enum UiError {
+    InvalidWidget,
+    WrongWidgetType,
+}
+
+/// Events produced by the widgets of a Screen when handling an InputEvent:
+/// - `MenuSelected`: The item of a menu was selected with the select button. Contains the id of
+///   the menu and the index of the item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiEvent {
+    MenuSelected { menu: usize, item: usize },
+}
+
+/// The ids, given by the ButtonManager, of the buttons used to navigate the menus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MenuButtons {
+    pub up: usize,
+    pub down: usize,
+    pub select: usize,
+}
+
+/// A minimal UI for small monochrome displays, made of labels, progress bars and menus navigable
+/// with buttons. The widgets are drawn on a Bitmap that is sent to the display from the main
+/// loop, only when something changed:
+///
+/// ```ignore
+/// if let Some(frame) = screen.render() {
+///     display.write(&frame.to_pages());
+/// }
+/// ```
+///
+/// Widgets are identified by the id returned when they are added. Positions are given in pixels
+/// from the top left corner, and text uses a 5x7 font with 6 pixels per character.
+pub struct Screen {
+    bitmap: Bitmap,
+    widgets: Vec<(Widget, bool)>,
+    focused_menu: Option<usize>,
+    buttons: Option<MenuButtons>,
+    dirty: bool,
+}
+
+impl Screen {
+    /// Creates a new empty Screen
+    ///
+    /// # Arguments
+    ///
+    /// - `width`: The width in pixels of the display
+    /// - `height`: The height in pixels of the display
+    ///
+    /// # Returns
+    ///
+    /// The new Screen
+    pub fn new(width: usize, height: usize) -> Self {
+        Screen {
+            bitmap: Bitmap::new(width, height),
+            widgets: vec![],
+            focused_menu: None,
+            buttons: None,
+            dirty: true,
+        }
+    }
+
+    /// Adds a label
+    ///
+    /// # Arguments
+    ///
+    /// - `x`: The column of the top left corner of the text
+    /// - `y`: The row of the top left corner of the text
+    /// - `text`: The text of the label
+    ///
+    /// # Returns
+    ///
+    /// The id of the label
+    pub fn add_label(&mut self, x: usize, y: usize, text: &str) -> usize {
+        self.add_widget(Widget::Label(Label {
+            x,
+            y,
+            text: text.to_string(),
+            inverted: false,
+        }))
+    }
+
+    /// Adds a progress bar, empty at first
+    ///
+    /// # Arguments
+    ///
+    /// - `x`: The column of the top left corner
+    /// - `y`: The row of the top left corner
+    /// - `width`: The width in pixels, including the outline
+    /// - `height`: The height in pixels, including the outline
+    ///
+    /// # Returns
+    ///
+    /// The id of the progress bar
+    pub fn add_progress_bar(&mut self, x: usize, y: usize, width: usize, height: usize) -> usize {
+        self.add_widget(Widget::ProgressBar(ProgressBar {
+            x,
+            y,
+            width,
+            height,
+            value: 0.0,
+        }))
+    }
+
+    /// Adds a menu with its first item selected. The first menu added gets the focus of the
+    /// buttons.
+    ///
+    /// # Arguments
+    ///
+    /// - `x`: The column of the top left corner
+    /// - `y`: The row of the top left corner
+    /// - `width`: The width in pixels. Items that do not fit are cut
+    /// - `items`: The text of each item
+    /// - `visible_rows`: The amount of items drawn at the same time, each 10 pixels high
+    ///
+    /// # Returns
+    ///
+    /// The id of the menu
+    pub fn add_menu(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        items: &[&str],
+        visible_rows: usize,
+    ) -> usize {
+        let id = self.add_widget(Widget::Menu(Menu {
+            x,
+            y,
+            width,
+            items: items.iter().map(|item| item.to_string()).collect(),
+            visible_rows,
+            selected: 0,
+            first_visible: 0,
+        }));
+        self.focused_menu.get_or_insert(id);
+        id
+    }
+
+    /// Changes the text of a label
+    ///
+    /// # Arguments
+    ///
+    /// - `label`: The id of the label
+    /// - `text`: The new text
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the text changed, or a `UiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UiError::InvalidWidget`: If the id does not belong to a widget of the screen
+    /// - `UiError::WrongWidgetType`: If the widget is not a label
+    pub fn set_text(&mut self, label: usize, text: &str) -> Result<(), UiError> {
+        match self.widget_mut(label)? {
+            Widget::Label(label) if label.text != text => label.text = text.to_string(),
+            Widget::Label(_) => return Ok(()),
+            _ => return Err(UiError::WrongWidgetType),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Sets whether a label is drawn light on a dark background, to highlight it
+    ///
+    /// # Arguments
+    ///
+    /// - `label`: The id of the label
+    /// - `inverted`: Whether the label is inverted
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the label changed, or a `UiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UiError::InvalidWidget`: If the id does not belong to a widget of the screen
+    /// - `UiError::WrongWidgetType`: If the widget is not a label
+    pub fn set_inverted(&mut self, label: usize, inverted: bool) -> Result<(), UiError> {
+        match self.widget_mut(label)? {
+            Widget::Label(label) => label.inverted = inverted,
+            _ => return Err(UiError::WrongWidgetType),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Changes the value of a progress bar
+    ///
+    /// # Arguments
+    ///
+    /// - `bar`: The id of the progress bar
+    /// - `value`: The new value, from 0 (empty) to 1 (full). Values out of range are clamped
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the value changed, or a `UiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UiError::InvalidWidget`: If the id does not belong to a widget of the screen
+    /// - `UiError::WrongWidgetType`: If the widget is not a progress bar
+    pub fn set_progress(&mut self, bar: usize, value: f32) -> Result<(), UiError> {
+        match self.widget_mut(bar)? {
+            Widget::ProgressBar(bar) => bar.value = value.clamp(0.0, 1.0),
+            _ => return Err(UiError::WrongWidgetType),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Selects an item of a menu
+    ///
+    /// # Arguments
+    ///
+    /// - `menu`: The id of the menu
+    /// - `item`: The index of the item. Indexes out of range select the last item
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the item was selected, or a `UiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UiError::InvalidWidget`: If the id does not belong to a widget of the screen
+    /// - `UiError::WrongWidgetType`: If the widget is not a menu
+    pub fn select_item(&mut self, menu: usize, item: usize) -> Result<(), UiError> {
+        match self.widget_mut(menu)? {
+            Widget::Menu(menu) => {
+                menu.selected = item.min(menu.items.len().saturating_sub(1));
+                menu.scroll_to_selected();
+            }
+            _ => return Err(UiError::WrongWidgetType),
+        }
+        self.dirty = true;
+        Ok(())
+    }
+
+    /// Gets the index of the item selected on a menu
+    ///
+    /// # Arguments
+    ///
+    /// - `menu`: The id of the menu
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the index of the selected item, or a `UiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UiError::InvalidWidget`: If the id does not belong to a widget of the screen
+    /// - `UiError::WrongWidgetType`: If the widget is not a menu
+    pub fn selected_item(&self, menu: usize) -> Result<usize, UiError> {
+        match self.widgets.get(menu).ok_or(UiError::InvalidWidget)? {
+            (Widget::Menu(menu), _) => Ok(menu.selected),
+            _ => Err(UiError::WrongWidgetType),
+        }
+    }
+
+    /// Gives the focus of the buttons to a menu
+    ///
+    /// # Arguments
+    ///
+    /// - `menu`: The id of the menu
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the menu got the focus, or a `UiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UiError::InvalidWidget`: If the id does not belong to a widget of the screen
+    /// - `UiError::WrongWidgetType`: If the widget is not a menu
+    pub fn focus_menu(&mut self, menu: usize) -> Result<(), UiError> {
+        match self.widget_mut(menu)? {
+            Widget::Menu(_) => self.focused_menu = Some(menu),
+            _ => return Err(UiError::WrongWidgetType),
+        }
+        Ok(())
+    }
+
+    /// Shows or hides a widget. Hidden menus do not handle the buttons.
+    ///
+    /// # Arguments
+    ///
+    /// - `widget`: The id of the widget
+    /// - `visible`: Whether the widget is drawn
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the visibility changed, or a `UiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UiError::InvalidWidget`: If the id does not belong to a widget of the screen
+    pub fn set_visible(&mut self, widget: usize, visible: bool) -> Result<(), UiError> {
+        let (_, is_visible) = self.widgets.get_mut(widget).ok_or(UiError::InvalidWidget)?;
+        if *is_visible != visible {
+            *is_visible = visible;
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    /// Removes every widget, so the screen can be reused for another view. Ids of the removed
+    /// widgets are no longer valid.
+    pub fn clear(&mut self) {
+        self.widgets.clear();
+        self.focused_menu = None;
+        self.dirty = true;
+    }
+
+    /// Sets the buttons used to navigate the focused menu
+    ///
+    /// # Arguments
+    ///
+    /// - `buttons`: The ids given by the ButtonManager to the up, down and select buttons
+    pub fn set_menu_buttons(&mut self, buttons: MenuButtons) {
+        self.buttons = Some(buttons);
+    }
+
+    /// Handles an event of the ButtonManager, moving the selection of the focused menu or
+    /// selecting its item. Only `Pressed` events of the menu buttons are used.
+    ///
+    /// # Arguments
+    ///
+    /// - `event`: The InputEvent received from the ButtonManager
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the UiEvent produced, or None if the event did not select anything
+    pub fn handle_input(&mut self, event: InputEvent) -> Option<UiEvent> {
+        let (InputEvent::Pressed(button), Some(buttons)) = (event, self.buttons) else {
+            return None;
+        };
+        let id = self.focused_menu?;
+        let (Widget::Menu(menu), true) = self.widgets.get_mut(id)? else {
+            return None;
+        };
+        if button == buttons.up {
+            menu.move_selection(false);
+        } else if button == buttons.down {
+            menu.move_selection(true);
+        } else if button == buttons.select && !menu.items.is_empty() {
+            return Some(UiEvent::MenuSelected {
+                menu: id,
+                item: menu.selected,
+            });
+        } else {
+            return None;
+        }
+        self.dirty = true;
+        None
+    }
+
+    /// Draws the widgets if anything changed since the last call
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the Bitmap to send to the display, or None if nothing changed
+    pub fn render(&mut self) -> Option<&Bitmap> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        let (width, height) = (self.bitmap.width(), self.bitmap.height());
+        self.bitmap.fill_rect(0, 0, width, height, false);
+        for (widget, visible) in &self.widgets {
+            if *visible {
+                widget.draw(&mut self.bitmap);
+            }
+        }
+        Some(&self.bitmap)
+    }
+
+    /// Gets the Bitmap drawn on the last call to [Self::render]
+    ///
+    /// # Returns
+    ///
+    /// A reference to the Bitmap
+    pub fn bitmap(&self) -> &Bitmap {
+        &self.bitmap
+    }
+
+    /// Gets the amount of menu items that fit on a height
+    ///
+    /// # Arguments
+    ///
+    /// - `height`: The height in pixels
+    ///
+    /// # Returns
+    ///
+    /// The amount of items
+    pub fn menu_rows_for_height(height: usize) -> usize {
+        height / MENU_ITEM_HEIGHT
+    }
+
+    /// Adds a visible widget
+    fn add_widget(&mut self, widget: Widget) -> usize {
+        self.widgets.push((widget, true));
+        self.dirty = true;
+        self.widgets.len() - 1
+    }
+
+    /// Gets a mutable reference to a widget
+    fn widget_mut(&mut self, id: usize) -> Result<&mut Widget, UiError> {
+        self.widgets
+            .get_mut(id)
+            .map(|(widget, _)| widget)
+            .ok_or(UiError::InvalidWidget)
+    }
+}
@@ -0,0 +1,160 @@
+use super::font::{glyph, CHAR_ADVANCE, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::utils::bitmap::Bitmap;
+
+/// Height of each item of a menu, including the spacing between items
+pub(crate) const MENU_ITEM_HEIGHT: usize = GLYPH_HEIGHT + 3;
+
+/// A line of text
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Label {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) text: String,
+    pub(crate) inverted: bool,
+}
+
+/// A horizontal bar filled according to a value between 0 and 1
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ProgressBar {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) value: f32,
+}
+
+/// A list of items where one of them is selected. Only the rows that fit are drawn, scrolling to
+/// keep the selected item visible.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Menu {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) width: usize,
+    pub(crate) items: Vec<String>,
+    pub(crate) visible_rows: usize,
+    pub(crate) selected: usize,
+    pub(crate) first_visible: usize,
+}
+
+/// Enums the widgets that can be placed on a Screen
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Widget {
+    Label(Label),
+    ProgressBar(ProgressBar),
+    Menu(Menu),
+}
+
+impl Label {
+    /// Draws the label on a bitmap
+    fn draw(&self, bitmap: &mut Bitmap) {
+        if self.inverted {
+            let width = self.text.chars().count() * CHAR_ADVANCE + 1;
+            bitmap.fill_rect(
+                self.x.saturating_sub(1),
+                self.y.saturating_sub(1),
+                width,
+                GLYPH_HEIGHT + 2,
+                true,
+            );
+        }
+        draw_text(bitmap, self.x, self.y, &self.text, !self.inverted);
+    }
+}
+
+impl ProgressBar {
+    /// Draws the outline of the bar, filled up to its value
+    fn draw(&self, bitmap: &mut Bitmap) {
+        if self.width < 2 || self.height < 2 {
+            return;
+        }
+        let (x, y, width, height) = (self.x, self.y, self.width, self.height);
+        bitmap.fill_rect(x, y, width, 1, true);
+        bitmap.fill_rect(x, y + height - 1, width, 1, true);
+        bitmap.fill_rect(x, y, 1, height, true);
+        bitmap.fill_rect(x + width - 1, y, 1, height, true);
+        let filled = ((width - 2) as f32 * self.value).round() as usize;
+        bitmap.fill_rect(x + 1, y + 1, filled, height - 2, true);
+    }
+}
+
+impl Menu {
+    /// Moves the selection to the next or the previous item, wrapping around the ends of the list
+    pub(crate) fn move_selection(&mut self, forward: bool) {
+        let len = self.items.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+        self.scroll_to_selected();
+    }
+
+    /// Updates the first row drawn so the selected item is visible
+    pub(crate) fn scroll_to_selected(&mut self) {
+        let rows = self.visible_rows.max(1);
+        if self.selected < self.first_visible {
+            self.first_visible = self.selected;
+        } else if self.selected >= self.first_visible + rows {
+            self.first_visible = self.selected + 1 - rows;
+        }
+    }
+
+    /// Draws the visible items, with the selected one inverted
+    fn draw(&self, bitmap: &mut Bitmap) {
+        let visible = self
+            .items
+            .iter()
+            .enumerate()
+            .skip(self.first_visible)
+            .take(self.visible_rows.max(1));
+        for (row, (index, item)) in visible.enumerate() {
+            let y = self.y + row * MENU_ITEM_HEIGHT;
+            let selected = index == self.selected;
+            if selected {
+                bitmap.fill_rect(self.x, y, self.width, MENU_ITEM_HEIGHT - 1, true);
+            }
+            let max_chars = self.width.saturating_sub(2) / CHAR_ADVANCE;
+            let text: String = item.chars().take(max_chars).collect();
+            draw_text(bitmap, self.x + 2, y + 1, &text, !selected);
+        }
+    }
+}
+
+impl Widget {
+    /// Draws the widget on a bitmap
+    pub(crate) fn draw(&self, bitmap: &mut Bitmap) {
+        match self {
+            Widget::Label(label) => label.draw(bitmap),
+            Widget::ProgressBar(bar) => bar.draw(bitmap),
+            Widget::Menu(menu) => menu.draw(bitmap),
+        }
+    }
+}
+
+/// Draws a line of text with the 5x7 font, with its top left corner on the given point
+///
+/// # Arguments
+///
+/// - `bitmap`: The bitmap to draw on
+/// - `x`: The column of the top left corner
+/// - `y`: The row of the top left corner
+/// - `text`: The text to draw
+/// - `dark`: Whether the text is dark or light
+pub(crate) fn draw_text(bitmap: &mut Bitmap, x: usize, y: usize, text: &str, dark: bool) {
+    for (i, c) in text.chars().enumerate() {
+        let char_x = x + i * CHAR_ADVANCE;
+        for (column, bits) in glyph(c).iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if bits & (1 << row) != 0 {
+                    bitmap.fill_rect(char_x + column, y + row, 1, 1, dark);
+                }
+            }
+        }
+        if char_x + GLYPH_WIDTH >= bitmap.width() {
+            break;
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use esp_idf_svc::sys::configTICK_RATE_HZ;
 use std::{
     cell::{Ref, RefCell, RefMut},
+    future::poll_fn,
     rc::Rc,
+    task::Poll,
 };
 
 pub type SharableRef<T> = Rc<RefCell<T>>;
@@ -58,3 +60,30 @@ impl<T> SharableRefExt<T> for SharableRef<T> {
 pub fn micro_to_ticks(time_us: u32) -> u32 {
     ((configTICK_RATE_HZ as u64) * (time_us as u64) / 1_000_000_u64) as u32
 }
+
+/// Yields once to the executor, so the other tasks can run. When using
+/// [crate::Microcontroller::block_on], long computations should call it from time to time, since the
+/// drivers are only updated while the user future is waiting.
+///
+/// # Example
+///
+/// ```
+/// micro.block_on(async {
+///     for sample in samples {
+///         process(sample);
+///         yield_now().await;
+///     }
+/// });
+/// ```
+pub async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
@@ -0,0 +1,184 @@
+use super::bitmap::Bitmap;
+use std::iter;
+
+const START_B: usize = 104;
+const STOP: usize = 106;
+const CHECKSUM_MODULO: usize = 103;
+const FIRST_CHARACTER: u8 = b' ';
+const LAST_CHARACTER: u8 = b'~';
+const QUIET_ZONE_MODULES: usize = 10;
+
+/// Widths of the bars and spaces of each Code 128 symbol, starting with a bar
+const PATTERNS: [&str; 107] = [
+    "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312", "132212",
+    "221213", "221312", "231212", "112232", "122132", "122231", "113222", "123122", "123221",
+    "223211", "221132", "221231", "213212", "223112", "312131", "311222", "321122", "321221",
+    "312212", "322112", "322211", "212123", "212321", "232121", "111323", "131123", "131321",
+    "112313", "132113", "132311", "211313", "231113", "231311", "112133", "112331", "132131",
+    "113123", "113321", "133121", "313121", "211331", "231131", "213113", "213311", "213131",
+    "311123", "311321", "331121", "312113", "312311", "332111", "314111", "221411", "431111",
+    "111224", "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+    "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111", "111242",
+    "121142", "121241", "114212", "124112", "124211", "411212", "421112", "421211", "212141",
+    "214121", "412121", "111143", "111341", "131141", "114113", "114311", "411113", "411311",
+    "113141", "114131", "311141", "411131", "211412", "211214", "211232", "2331112",
+];
+
+/// Enums the different errors possible when generating a barcode
+#[derive(Debug, PartialEq)]
+pub enum BarcodeError {
+    EmptyData,
+    InvalidCharacter(char),
+}
+
+/// A Code 128 barcode, readable by any 1D barcode scanner. It encodes printable ASCII text, such
+/// as a serial number or the id of the device.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Code128 {
+    modules: Vec<bool>,
+}
+
+impl Code128 {
+    /// Generates the barcode of a text, using the code set B of Code 128
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text to encode, made only of printable ASCII characters
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new Code128, or a `BarcodeError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `BarcodeError::EmptyData`: If the text is empty
+    /// - `BarcodeError::InvalidCharacter`: If the text has a character that is not printable ASCII
+    pub fn new(text: &str) -> Result<Self, BarcodeError> {
+        if text.is_empty() {
+            return Err(BarcodeError::EmptyData);
+        }
+        let mut symbols = vec![START_B];
+        for c in text.chars() {
+            if !(FIRST_CHARACTER as char..=LAST_CHARACTER as char).contains(&c) {
+                return Err(BarcodeError::InvalidCharacter(c));
+            }
+            symbols.push((c as u8 - FIRST_CHARACTER) as usize);
+        }
+        let checksum = symbols
+            .iter()
+            .enumerate()
+            .map(|(position, symbol)| position.max(1) * symbol)
+            .sum::<usize>()
+            % CHECKSUM_MODULO;
+        symbols.push(checksum);
+        symbols.push(STOP);
+
+        let mut modules = vec![];
+        for symbol in symbols {
+            for (i, width) in PATTERNS[symbol].bytes().enumerate() {
+                let is_bar = i % 2 == 0;
+                modules.extend(iter::repeat(is_bar).take((width - b'0') as usize));
+            }
+        }
+        Ok(Code128 { modules })
+    }
+
+    /// Gets the modules of the barcode, from left to right, without quiet zones
+    ///
+    /// # Returns
+    ///
+    /// A slice with a `bool` for each module, true for bars and false for spaces
+    pub fn modules(&self) -> &[bool] {
+        &self.modules
+    }
+
+    /// Renders the barcode as a Bitmap, with the quiet zones of 10 modules that scanners need on
+    /// both sides
+    ///
+    /// # Arguments
+    ///
+    /// - `module_width`: The width in pixels of the narrowest bar
+    /// - `height`: The height in pixels of the bars
+    ///
+    /// # Returns
+    ///
+    /// The new Bitmap
+    pub fn render(&self, module_width: usize, height: usize) -> Bitmap {
+        let width = (self.modules.len() + QUIET_ZONE_MODULES * 2) * module_width;
+        let mut bitmap = Bitmap::new(width, height);
+        for (i, is_bar) in self.modules.iter().enumerate() {
+            if *is_bar {
+                let x = (i + QUIET_ZONE_MODULES) * module_width;
+                bitmap.fill_rect(x, 0, module_width, height, true);
+            }
+        }
+        bitmap
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn modules_as_string(barcode: &Code128) -> String {
+        barcode
+            .modules()
+            .iter()
+            .map(|is_bar| if *is_bar { '#' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn barcode_01_modules_with_check_symbol() {
+        let barcode = Code128::new("Wikipedia").unwrap();
+        assert_eq!(
+            modules_as_string(&barcode),
+            concat!(
+                "##.#..#....",
+                "###.#...##.",
+                "#....##.#..",
+                "##....#..#.",
+                "#....##.#..",
+                "#.#..####..",
+                "#.##..#....",
+                "#....#..##.",
+                "#....##.#..",
+                "#..#.##....",
+                "####..#..#.",
+                "##...###.#.##",
+            )
+        );
+    }
+
+    #[test]
+    fn barcode_02_check_symbol_is_weighted_by_position() {
+        let barcode = Code128::new("Wikipedia").unwrap();
+        let modules = barcode.modules();
+        let check_symbol = &modules[modules.len() - 24..modules.len() - 13];
+        let expected: Vec<bool> = "####..#..#.".chars().map(|c| c == '#').collect();
+        assert_eq!(check_symbol, expected);
+    }
+
+    #[test]
+    fn barcode_03_empty_text() {
+        assert_eq!(Code128::new("").unwrap_err(), BarcodeError::EmptyData);
+    }
+
+    #[test]
+    fn barcode_04_character_out_of_code_set_b() {
+        assert_eq!(
+            Code128::new("año").unwrap_err(),
+            BarcodeError::InvalidCharacter('ñ')
+        );
+    }
+
+    #[test]
+    fn barcode_05_render_adds_quiet_zones() {
+        let barcode = Code128::new("Wikipedia").unwrap();
+        let bitmap = barcode.render(2, 10);
+        assert_eq!(bitmap.width(), (barcode.modules().len() + 20) * 2);
+        assert_eq!(bitmap.height(), 10);
+        assert!(!bitmap.is_dark(19, 0));
+        assert!(bitmap.is_dark(20, 9));
+    }
+}
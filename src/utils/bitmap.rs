@@ -0,0 +1,364 @@
+const BMP_HEADERS_SIZE: u32 = 62;
+const BMP_PIXELS_PER_METER: u32 = 2835;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const ZLIB_HEADER: [u8; 2] = [0x78, 0x01];
+const MAX_STORED_BLOCK_SIZE: usize = 0xFFFF;
+
+/// A black and white image, such as a rendered QR code or barcode. It can be drawn on a display
+/// pixel by pixel or with one of its raw formats, or encoded as BMP or PNG to be served over HTTP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bitmap {
+    width: usize,
+    height: usize,
+    pixels: Vec<bool>,
+}
+
+impl Bitmap {
+    /// Creates a new light Bitmap
+    ///
+    /// # Arguments
+    ///
+    /// - `width`: The width in pixels
+    /// - `height`: The height in pixels
+    ///
+    /// # Returns
+    ///
+    /// The new Bitmap
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        Bitmap {
+            width,
+            height,
+            pixels: vec![false; width * height],
+        }
+    }
+
+    /// Paints a rectangle of the bitmap, clipped to its bounds
+    ///
+    /// # Arguments
+    ///
+    /// - `x`: The column of the top left corner
+    /// - `y`: The row of the top left corner
+    /// - `width`: The width of the rectangle
+    /// - `height`: The height of the rectangle
+    /// - `dark`: Whether the rectangle is dark or light
+    pub(crate) fn fill_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        dark: bool,
+    ) {
+        for row in y..(y + height).min(self.height) {
+            for column in x..(x + width).min(self.width) {
+                self.pixels[row * self.width + column] = dark;
+            }
+        }
+    }
+
+    /// Gets the width of the bitmap
+    ///
+    /// # Returns
+    ///
+    /// The width in pixels
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Gets the height of the bitmap
+    ///
+    /// # Returns
+    ///
+    /// The height in pixels
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Checks whether a pixel is dark. Pixels out of bounds are light.
+    ///
+    /// # Arguments
+    ///
+    /// - `x`: The column of the pixel, from left to right
+    /// - `y`: The row of the pixel, from top to bottom
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is true if the pixel is dark
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && self.pixels[y * self.width + x]
+    }
+
+    /// Gets the bitmap as a matrix of bytes, 1 for dark pixels and 0 for light ones
+    ///
+    /// # Returns
+    ///
+    /// A vector with a vector of bytes for each row, from top to bottom
+    pub fn to_byte_matrix(&self) -> Vec<Vec<u8>> {
+        self.pixels
+            .chunks(self.width.max(1))
+            .map(|row| row.iter().map(|dark| *dark as u8).collect())
+            .collect()
+    }
+
+    /// Packs the bitmap with one bit per pixel, the format taken by most monochrome display
+    /// drivers. Each row starts on a new byte and the first pixel is the most significant bit.
+    ///
+    /// # Arguments
+    ///
+    /// - `dark_bit`: The value of the bit of dark pixels. It is usually true on OLED displays,
+    ///   where set bits are lit
+    ///
+    /// # Returns
+    ///
+    /// The packed rows, from top to bottom
+    pub fn to_packed_rows(&self, dark_bit: bool) -> Vec<u8> {
+        self.packed_rows(dark_bit, 1)
+    }
+
+    /// Packs the bitmap in pages of 8 rows, the format of the memory of the SSD1306 and similar
+    /// OLED drivers. Each byte is a column of a page, where the least significant bit is the top
+    /// row, and set bits are dark pixels.
+    ///
+    /// # Returns
+    ///
+    /// The packed pages, from top to bottom, each with a byte per column
+    pub fn to_pages(&self) -> Vec<u8> {
+        let pages = (self.height + 7) / 8;
+        let mut packed = vec![0; pages * self.width];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.is_dark(x, y) {
+                    packed[(y / 8) * self.width + x] |= 1 << (y % 8);
+                }
+            }
+        }
+        packed
+    }
+
+    /// Encodes the bitmap as a 1 bit per pixel BMP file
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the BMP file
+    pub fn to_bmp(&self) -> Vec<u8> {
+        let row_size = (self.width + 31) / 32 * 4;
+        let image_size = (row_size * self.height) as u32;
+        let mut bmp = Vec::with_capacity(BMP_HEADERS_SIZE as usize + image_size as usize);
+
+        bmp.extend_from_slice(b"BM");
+        bmp.extend_from_slice(&(BMP_HEADERS_SIZE + image_size).to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&BMP_HEADERS_SIZE.to_le_bytes());
+
+        bmp.extend_from_slice(&40u32.to_le_bytes());
+        bmp.extend_from_slice(&(self.width as i32).to_le_bytes());
+        bmp.extend_from_slice(&(self.height as i32).to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&1u16.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        bmp.extend_from_slice(&image_size.to_le_bytes());
+        bmp.extend_from_slice(&BMP_PIXELS_PER_METER.to_le_bytes());
+        bmp.extend_from_slice(&BMP_PIXELS_PER_METER.to_le_bytes());
+        bmp.extend_from_slice(&2u32.to_le_bytes());
+        bmp.extend_from_slice(&0u32.to_le_bytes());
+        // Palette: index 0 is black and index 1 is white
+        bmp.extend_from_slice(&[0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0]);
+
+        // Rows are stored from bottom to top
+        let rows = self.packed_rows(false, 4);
+        for row in rows.chunks(row_size).rev() {
+            bmp.extend_from_slice(row);
+        }
+        bmp
+    }
+
+    /// Encodes the bitmap as a 1 bit grayscale PNG file. The image data is stored without
+    /// compression, since the images are small and it avoids the cost of deflate.
+    ///
+    /// # Returns
+    ///
+    /// The bytes of the PNG file
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // Bit depth 1, grayscale, deflate, no filters, no interlace
+        header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+        let row_size = (self.width + 7) / 8;
+        let mut raw = Vec::with_capacity((row_size + 1) * self.height);
+        let rows = self.packed_rows(false, 1);
+        for row in rows.chunks(row_size.max(1)).take(self.height) {
+            raw.push(0);
+            raw.extend_from_slice(row);
+        }
+
+        let mut png = PNG_SIGNATURE.to_vec();
+        write_png_chunk(&mut png, b"IHDR", &header);
+        write_png_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+        write_png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    /// Packs the rows with one bit per pixel, the first pixel being the most significant bit
+    ///
+    /// # Arguments
+    ///
+    /// - `dark_bit`: The value of the bit of dark pixels
+    /// - `alignment`: The amount of bytes every row is padded to a multiple of
+    fn packed_rows(&self, dark_bit: bool, alignment: usize) -> Vec<u8> {
+        let row_size = (self.width + 8 * alignment - 1) / (8 * alignment) * alignment;
+        let mut packed = vec![0; row_size * self.height];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.is_dark(x, y) == dark_bit {
+                    packed[y * row_size + x / 8] |= 0x80 >> (x % 8);
+                }
+            }
+        }
+        packed
+    }
+}
+
+/// Appends a chunk to a PNG file, with its length and CRC
+fn write_png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wraps data in a zlib stream made of stored (uncompressed) deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = ZLIB_HEADER.to_vec();
+    let mut blocks = data.chunks(MAX_STORED_BLOCK_SIZE).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let is_last = blocks.peek().is_none();
+        stream.push(is_last as u8);
+        stream.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        stream.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+/// Computes the CRC-32 checksum used by PNG
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// Computes the Adler-32 checksum used by zlib
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// 3x2 checkerboard: dark, light, dark on top of light, dark, light
+    fn checkerboard() -> Bitmap {
+        let mut bitmap = Bitmap::new(3, 2);
+        bitmap.fill_rect(0, 0, 1, 1, true);
+        bitmap.fill_rect(2, 0, 1, 1, true);
+        bitmap.fill_rect(1, 1, 1, 1, true);
+        bitmap
+    }
+
+    #[test]
+    fn bitmap_01_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn bitmap_02_adler32_check_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn bitmap_03_fill_rect_is_clipped() {
+        let mut bitmap = Bitmap::new(4, 4);
+        bitmap.fill_rect(2, 2, 10, 10, true);
+        assert_eq!(
+            bitmap.to_byte_matrix(),
+            vec![
+                vec![0, 0, 0, 0],
+                vec![0, 0, 0, 0],
+                vec![0, 0, 1, 1],
+                vec![0, 0, 1, 1],
+            ]
+        );
+        assert!(!bitmap.is_dark(4, 3));
+    }
+
+    #[test]
+    fn bitmap_04_packed_rows_start_on_a_new_byte() {
+        let mut bitmap = Bitmap::new(10, 1);
+        bitmap.fill_rect(0, 0, 1, 1, true);
+        bitmap.fill_rect(9, 0, 1, 1, true);
+        assert_eq!(bitmap.to_packed_rows(true), vec![0x80, 0x40]);
+        assert_eq!(bitmap.to_packed_rows(false), vec![0x7F, 0x80]);
+    }
+
+    #[test]
+    fn bitmap_05_pages_have_the_top_row_in_the_lowest_bit() {
+        let mut bitmap = Bitmap::new(2, 9);
+        bitmap.fill_rect(0, 0, 1, 1, true);
+        bitmap.fill_rect(1, 8, 1, 1, true);
+        assert_eq!(bitmap.to_pages(), vec![0x01, 0x00, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn bitmap_06_bmp_rows_are_padded_and_bottom_up() {
+        let bmp = checkerboard().to_bmp();
+        assert_eq!(bmp.len(), 70);
+        assert_eq!(&bmp[0..2], b"BM");
+        assert_eq!(&bmp[2..6], &70u32.to_le_bytes());
+        assert_eq!(&bmp[10..14], &62u32.to_le_bytes());
+        assert_eq!(&bmp[18..22], &3i32.to_le_bytes());
+        assert_eq!(&bmp[22..26], &2i32.to_le_bytes());
+        assert_eq!(&bmp[34..38], &8u32.to_le_bytes());
+        assert_eq!(&bmp[62..], &[0xA0, 0, 0, 0, 0x40, 0, 0, 0]);
+    }
+
+    #[test]
+    fn bitmap_07_png_bytes() {
+        assert_eq!(
+            checkerboard().to_png(),
+            vec![
+                0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48,
+                0x44, 0x52, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x02, 0x01, 0x00, 0x00, 0x00,
+                0x00, 0xB5, 0x0F, 0x5B, 0xB7, 0x00, 0x00, 0x00, 0x0F, 0x49, 0x44, 0x41, 0x54, 0x78,
+                0x01, 0x01, 0x04, 0x00, 0xFB, 0xFF, 0x00, 0x40, 0x00, 0xA0, 0x01, 0x64, 0x00, 0xE1,
+                0x01, 0x04, 0x67, 0xB2, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42,
+                0x60, 0x82,
+            ]
+        );
+    }
+
+    #[test]
+    fn bitmap_08_empty_zlib_stream() {
+        assert_eq!(
+            zlib_stored(&[]),
+            vec![0x78, 0x01, 0x01, 0x00, 0x00, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01]
+        );
+    }
+}
@@ -0,0 +1,333 @@
+use esp_idf_svc::sys::{
+    esp_deep_sleep, esp_light_sleep_start, esp_sleep_enable_timer_wakeup, ESP_OK,
+};
+use std::{
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, Instant},
+};
+
+const PERSISTED_MAGIC: u32 = 0xE4E9_C0DE;
+const SECONDS_PER_HOUR: f32 = 3600.0;
+
+/// Totals kept in RTC memory so the time spent in deep sleep is accounted after waking up
+#[link_section = ".rtc.data"]
+static PERSISTED_VALID: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static PERSISTED_ACTIVE_MS: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static PERSISTED_LIGHT_SLEEP_MS: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static PERSISTED_DEEP_SLEEP_MS: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static PERSISTED_RADIO_MS: AtomicU32 = AtomicU32::new(0);
+
+/// Enums the different errors possible when working with the EnergyProfiler
+#[derive(Debug)]
+pub enum EnergyProfilerError {
+    InvalidModel,
+    SleepError,
+}
+
+/// Enums the power states the EnergyProfiler accounts for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerState {
+    Active,
+    LightSleep,
+    DeepSleep,
+}
+
+/// Current drawn by the board on each state, in milliamperes, usually taken from the datasheets
+/// or measured once with an ammeter:
+/// - `active_ma`: Current while the cpu is running.
+/// - `light_sleep_ma`: Current during light sleep.
+/// - `deep_sleep_ma`: Current during deep sleep.
+/// - `radio_ma`: Extra current while the radio (WiFi or BLE) is on, added to the current state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PowerModel {
+    pub active_ma: f32,
+    pub light_sleep_ma: f32,
+    pub deep_sleep_ma: f32,
+    pub radio_ma: f32,
+}
+
+/// Time spent on each state since the profiler started, and the consumption estimated from it:
+/// - `active`: Time awake.
+/// - `light_sleep`: Time in light sleep.
+/// - `deep_sleep`: Time in deep sleep.
+/// - `radio_on`: Time with the radio on.
+/// - `consumed_mah`: Estimated charge consumed, in milliampere-hours.
+/// - `average_current_ma`: Estimated average current, in milliamperes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnergyReport {
+    pub active: Duration,
+    pub light_sleep: Duration,
+    pub deep_sleep: Duration,
+    pub radio_on: Duration,
+    pub consumed_mah: f32,
+    pub average_current_ma: f32,
+}
+
+/// Tracks the time the microcontroller spends awake, in light and deep sleep, and with the radio
+/// on, in order to estimate its average current from a PowerModel and project the battery life.
+/// Totals survive deep sleep, as long as it is entered through [Self::deep_sleep].
+pub struct EnergyProfiler {
+    model: PowerModel,
+    last_checkpoint: Instant,
+    active: Duration,
+    light_sleep: Duration,
+    deep_sleep: Duration,
+    radio_on: Duration,
+    radio_since: Option<Instant>,
+}
+
+impl PowerModel {
+    /// Creates a new PowerModel
+    ///
+    /// # Arguments
+    ///
+    /// - `active_ma`: Current while the cpu is running
+    /// - `light_sleep_ma`: Current during light sleep
+    /// - `deep_sleep_ma`: Current during deep sleep
+    /// - `radio_ma`: Extra current while the radio is on
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new PowerModel, or an `EnergyProfilerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EnergyProfilerError::InvalidModel`: If any current is negative or not finite
+    pub fn new(
+        active_ma: f32,
+        light_sleep_ma: f32,
+        deep_sleep_ma: f32,
+        radio_ma: f32,
+    ) -> Result<Self, EnergyProfilerError> {
+        let currents = [active_ma, light_sleep_ma, deep_sleep_ma, radio_ma];
+        if currents.iter().any(|c| !c.is_finite() || *c < 0.0) {
+            return Err(EnergyProfilerError::InvalidModel);
+        }
+        Ok(PowerModel {
+            active_ma,
+            light_sleep_ma,
+            deep_sleep_ma,
+            radio_ma,
+        })
+    }
+}
+
+impl EnergyReport {
+    /// Gets the total time accounted
+    ///
+    /// # Returns
+    ///
+    /// The sum of the time spent on every state
+    pub fn total(&self) -> Duration {
+        self.active + self.light_sleep + self.deep_sleep
+    }
+
+    /// Gets the fraction of the time the microcontroller was awake
+    ///
+    /// # Returns
+    ///
+    /// The duty cycle, from 0 to 1
+    pub fn duty_cycle(&self) -> f32 {
+        let total = self.total().as_secs_f32();
+        if total == 0.0 {
+            return 1.0;
+        }
+        self.active.as_secs_f32() / total
+    }
+
+    /// Projects how long a battery would last if the firmware keeps behaving as it did
+    ///
+    /// # Arguments
+    ///
+    /// - `capacity_mah`: The capacity of the battery in milliampere-hours
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the projected battery life, or None if no current was measured
+    pub fn battery_life(&self, capacity_mah: f32) -> Option<Duration> {
+        if self.average_current_ma <= 0.0 {
+            return None;
+        }
+        let hours = capacity_mah / self.average_current_ma;
+        Some(Duration::from_secs_f32(hours * SECONDS_PER_HOUR))
+    }
+}
+
+impl EnergyProfiler {
+    /// Creates a new EnergyProfiler, starting in the active state. If the microcontroller woke
+    /// up from a deep sleep entered through [Self::deep_sleep], the previous totals are restored.
+    ///
+    /// # Arguments
+    ///
+    /// - `model`: The PowerModel of the board
+    ///
+    /// # Returns
+    ///
+    /// The new EnergyProfiler
+    pub fn new(model: PowerModel) -> Self {
+        let mut profiler = EnergyProfiler {
+            model,
+            last_checkpoint: Instant::now(),
+            active: Duration::ZERO,
+            light_sleep: Duration::ZERO,
+            deep_sleep: Duration::ZERO,
+            radio_on: Duration::ZERO,
+            radio_since: None,
+        };
+        if PERSISTED_VALID.swap(0, Ordering::SeqCst) == PERSISTED_MAGIC {
+            profiler.active = load_persisted(&PERSISTED_ACTIVE_MS);
+            profiler.light_sleep = load_persisted(&PERSISTED_LIGHT_SLEEP_MS);
+            profiler.deep_sleep = load_persisted(&PERSISTED_DEEP_SLEEP_MS);
+            profiler.radio_on = load_persisted(&PERSISTED_RADIO_MS);
+        }
+        profiler
+    }
+
+    /// Sets whether the radio is on. Should be called when starting and stopping the WiFi or BLE.
+    ///
+    /// # Arguments
+    ///
+    /// - `on`: True if the radio was turned on, false if it was turned off
+    pub fn set_radio(&mut self, on: bool) {
+        match (on, self.radio_since) {
+            (true, None) => self.radio_since = Some(Instant::now()),
+            (false, Some(since)) => {
+                self.radio_on += since.elapsed();
+                self.radio_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Enters light sleep for the given time, accounting for it. The microcontroller continues
+    /// from this call when it wakes up.
+    ///
+    /// # Arguments
+    ///
+    /// - `duration`: The time to sleep
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the time actually slept, or an `EnergyProfilerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EnergyProfilerError::SleepError`: If the microcontroller could not enter light sleep
+    pub fn light_sleep(&mut self, duration: Duration) -> Result<Duration, EnergyProfilerError> {
+        self.checkpoint();
+        unsafe {
+            if esp_sleep_enable_timer_wakeup(duration.as_micros() as u64) != ESP_OK
+                || esp_light_sleep_start() != ESP_OK
+            {
+                return Err(EnergyProfilerError::SleepError);
+            }
+        }
+        let slept = self.last_checkpoint.elapsed();
+        self.add_time(PowerState::LightSleep, slept);
+        self.last_checkpoint = Instant::now();
+        Ok(slept)
+    }
+
+    /// Enters deep sleep for the given time. The totals are kept in RTC memory and restored by
+    /// [Self::new] after waking up, counting the whole duration as deep sleep. The radio is
+    /// considered off after waking up.
+    ///
+    /// # Arguments
+    ///
+    /// - `duration`: The time to sleep
+    pub fn deep_sleep(&mut self, duration: Duration) -> ! {
+        self.set_radio(false);
+        self.checkpoint();
+        self.add_time(PowerState::DeepSleep, duration);
+
+        store_persisted(&PERSISTED_ACTIVE_MS, self.active);
+        store_persisted(&PERSISTED_LIGHT_SLEEP_MS, self.light_sleep);
+        store_persisted(&PERSISTED_DEEP_SLEEP_MS, self.deep_sleep);
+        store_persisted(&PERSISTED_RADIO_MS, self.radio_on);
+        PERSISTED_VALID.store(PERSISTED_MAGIC, Ordering::SeqCst);
+        unsafe { esp_deep_sleep(duration.as_micros() as u64) }
+    }
+
+    /// Accounts time spent on a state without going through the profiler, for example a sleep
+    /// entered by another library. The time is not substracted from the active time.
+    ///
+    /// # Arguments
+    ///
+    /// - `state`: The PowerState the time was spent on
+    /// - `duration`: The time spent
+    pub fn add_time(&mut self, state: PowerState, duration: Duration) {
+        match state {
+            PowerState::Active => self.active += duration,
+            PowerState::LightSleep => self.light_sleep += duration,
+            PowerState::DeepSleep => self.deep_sleep += duration,
+        }
+    }
+
+    /// Gets the time spent on each state up to now, and the consumption estimated from it
+    ///
+    /// # Returns
+    ///
+    /// The EnergyReport
+    pub fn report(&mut self) -> EnergyReport {
+        self.checkpoint();
+        let radio_on = match self.radio_since {
+            Some(since) => self.radio_on + since.elapsed(),
+            None => self.radio_on,
+        };
+        let hours = |duration: Duration| duration.as_secs_f32() / SECONDS_PER_HOUR;
+
+        let consumed_mah = hours(self.active) * self.model.active_ma
+            + hours(self.light_sleep) * self.model.light_sleep_ma
+            + hours(self.deep_sleep) * self.model.deep_sleep_ma
+            + hours(radio_on) * self.model.radio_ma;
+        let total = hours(self.active + self.light_sleep + self.deep_sleep);
+        let average_current_ma = if total > 0.0 {
+            consumed_mah / total
+        } else {
+            0.0
+        };
+
+        EnergyReport {
+            active: self.active,
+            light_sleep: self.light_sleep,
+            deep_sleep: self.deep_sleep,
+            radio_on,
+            consumed_mah,
+            average_current_ma,
+        }
+    }
+
+    /// Clears every total, starting to account again from now
+    pub fn reset(&mut self) {
+        self.active = Duration::ZERO;
+        self.light_sleep = Duration::ZERO;
+        self.deep_sleep = Duration::ZERO;
+        self.radio_on = Duration::ZERO;
+        self.last_checkpoint = Instant::now();
+        if self.radio_since.is_some() {
+            self.radio_since = Some(Instant::now());
+        }
+    }
+
+    /// Adds the time since the last checkpoint to the active time
+    fn checkpoint(&mut self) {
+        let now = Instant::now();
+        self.active += now - self.last_checkpoint;
+        self.last_checkpoint = now;
+    }
+}
+
+/// Loads a duration persisted in RTC memory as milliseconds
+fn load_persisted(persisted: &AtomicU32) -> Duration {
+    Duration::from_millis(persisted.load(Ordering::SeqCst) as u64)
+}
+
+/// Stores a duration in RTC memory as milliseconds, saturating after about 49 days
+fn store_persisted(persisted: &AtomicU32, duration: Duration) {
+    let millis = duration.as_millis().min(u32::MAX as u128) as u32;
+    persisted.store(millis, Ordering::SeqCst);
+}
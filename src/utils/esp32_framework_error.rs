@@ -5,9 +5,11 @@ use crate::{
     gpio::{
         analog::{AnalogInError, AnalogInPwmError, AnalogOutError},
         digital::{DigitalInError, DigitalOutError},
+        status_led::StatusLedError,
     },
     microcontroller_src::peripherals::PeripheralError,
     serial::{i2c::I2CError, uart::UARTError},
+    touch::TouchError,
     utils::timer_driver::TimerDriverError,
     wifi::{http::HttpError, WifiError},
 };
@@ -25,7 +27,9 @@ pub enum Esp32FrameworkError {
     HttpError(HttpError),
     I2c(I2CError),
     PeripheralError(PeripheralError),
+    StatusLed(StatusLedError),
     TimerDriver(TimerDriverError),
+    Touch(TouchError),
     Uart(UARTError),
     Wifi(WifiError),
 }
@@ -59,7 +63,9 @@ impl_from_for_esp32_error! {
     HttpError => HttpError,
     I2c => I2CError,
     PeripheralError => PeripheralError,
+    StatusLed => StatusLedError,
     TimerDriver => TimerDriverError,
+    Touch => TouchError,
     Uart => UARTError,
     Wifi => WifiError,
 }
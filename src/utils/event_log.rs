@@ -0,0 +1,620 @@
+use crate::{
+    utils::system_clock::system_timestamp,
+    wifi::http::{Http, HttpError},
+};
+use esp_idf_svc::sys::{
+    esp_partition_erase_range, esp_partition_find_first, esp_partition_read,
+    esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY, esp_partition_t,
+    esp_partition_type_t_ESP_PARTITION_TYPE_DATA, esp_partition_write, esp_reset_reason,
+    esp_reset_reason_t, esp_reset_reason_t_ESP_RST_BROWNOUT, esp_reset_reason_t_ESP_RST_DEEPSLEEP,
+    esp_reset_reason_t_ESP_RST_EXT, esp_reset_reason_t_ESP_RST_INT_WDT,
+    esp_reset_reason_t_ESP_RST_PANIC, esp_reset_reason_t_ESP_RST_POWERON,
+    esp_reset_reason_t_ESP_RST_SW, esp_reset_reason_t_ESP_RST_TASK_WDT,
+    esp_reset_reason_t_ESP_RST_WDT, esp_rom_crc32_le, esp_timer_get_time, ESP_OK,
+};
+use std::{
+    cmp::Reverse,
+    ffi::CString,
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+};
+
+const SECTOR_SIZE: usize = 4096;
+const RECORD_SIZE: usize = 64;
+const RECORDS_PER_SECTOR: usize = SECTOR_SIZE / RECORD_SIZE;
+/// Max amount of bytes of the message of a record. Longer messages are cut.
+pub const MAX_EVENT_MESSAGE_LEN: usize = 36;
+const RECORD_MAGIC: u8 = 0xE7;
+const MESSAGE_OFFSET: usize = 24;
+const CRC_OFFSET: usize = MESSAGE_OFFSET + MAX_EVENT_MESSAGE_LEN;
+const RESET_KIND: u8 = 0;
+const WIFI_DROPPED_KIND: u8 = 1;
+const SENSOR_FAULT_KIND: u8 = 2;
+const ERROR_KIND: u8 = 3;
+const CUSTOM_KIND: u8 = 4;
+const UPDATE_KIND: u8 = 5;
+
+static EVENT_LOG: OnceLock<Mutex<EventStorage>> = OnceLock::new();
+
+/// Enums the different errors possible when working with the EventLog
+#[derive(Debug)]
+pub enum EventLogError {
+    AlreadyOpen,
+    HttpError(HttpError),
+    InvalidPartition,
+    PartitionNotFound,
+    StorageError,
+}
+
+/// Enums the types of events that can be recorded:
+/// - `Reset`: The microcontroller started. The message is the reason of the reset.
+/// - `WifiDropped`: The wifi connection was lost or could not be established.
+/// - `SensorFault`: A sensor did not answer or returned invalid data.
+/// - `Error`: Any other error of a driver.
+/// - `Update`: A configuration or firmware update was applied or failed.
+/// - `Custom`: An event of the user, with a code to tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Reset,
+    WifiDropped,
+    SensorFault,
+    Error,
+    Update,
+    Custom(u8),
+}
+
+/// An event read from the log:
+/// - `sequence`: The number of the event, which increases with every event recorded.
+/// - `kind`: The EventKind of the event.
+/// - `timestamp`: Seconds since the unix epoch, if the clock was set when it was recorded.
+/// - `uptime_ms`: Milliseconds since the microcontroller started.
+/// - `message`: A short description of the event.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRecord {
+    pub sequence: u32,
+    pub kind: EventKind,
+    pub timestamp: Option<i64>,
+    pub uptime_ms: u64,
+    pub message: String,
+}
+
+/// Pointer to the partition of the log, which is never freed by ESP-IDF
+struct PartitionPtr(*const esp_partition_t);
+
+unsafe impl Send for PartitionPtr {}
+
+/// The records of the log, as a ring of sectors of a flash partition
+struct EventStorage {
+    partition: PartitionPtr,
+    slots: usize,
+    next_slot: usize,
+    next_sequence: u32,
+}
+
+/// Append only log in flash where drivers and the user record events, such as resets, wifi drops
+/// or sensor faults, to find out what happened to a device after the fact. Records are written
+/// in a ring over the sectors of a data partition, erasing the oldest sector when the log is
+/// full, so every sector wears at the same rate. Records are checked with a CRC, so a record
+/// torn by a power loss is skipped.
+///
+/// The partition must be declared in the partition table, with at least 2 sectors of 4KB:
+///
+/// ```text
+/// eventlog, data, 0x40, , 16K
+/// ```
+///
+/// Only one log can be opened, and once it is, the framework also records its own events. The
+/// log can be read with [Self::records], the `events` command of the shell, or uploaded with
+/// [Self::push_http].
+#[derive(Clone, Copy)]
+pub struct EventLog {
+    storage: &'static Mutex<EventStorage>,
+}
+
+impl EventKind {
+    /// Gets the bytes that identify the kind in a record
+    fn to_bytes(self) -> (u8, u8) {
+        match self {
+            EventKind::Reset => (RESET_KIND, 0),
+            EventKind::WifiDropped => (WIFI_DROPPED_KIND, 0),
+            EventKind::SensorFault => (SENSOR_FAULT_KIND, 0),
+            EventKind::Error => (ERROR_KIND, 0),
+            EventKind::Update => (UPDATE_KIND, 0),
+            EventKind::Custom(code) => (CUSTOM_KIND, code),
+        }
+    }
+
+    /// Parses the bytes that identify the kind in a record
+    fn from_bytes(kind: u8, code: u8) -> Option<Self> {
+        match kind {
+            RESET_KIND => Some(EventKind::Reset),
+            WIFI_DROPPED_KIND => Some(EventKind::WifiDropped),
+            SENSOR_FAULT_KIND => Some(EventKind::SensorFault),
+            ERROR_KIND => Some(EventKind::Error),
+            UPDATE_KIND => Some(EventKind::Update),
+            CUSTOM_KIND => Some(EventKind::Custom(code)),
+            _ => None,
+        }
+    }
+
+    /// Gets the name of the kind, as shown in the exported log
+    ///
+    /// # Returns
+    ///
+    /// The name of the kind
+    pub fn name(&self) -> String {
+        match self {
+            EventKind::Reset => "reset".to_string(),
+            EventKind::WifiDropped => "wifi_dropped".to_string(),
+            EventKind::SensorFault => "sensor_fault".to_string(),
+            EventKind::Error => "error".to_string(),
+            EventKind::Update => "update".to_string(),
+            EventKind::Custom(code) => format!("custom_{}", code),
+        }
+    }
+}
+
+impl EventRecord {
+    /// Encodes the record in the format stored in flash
+    fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut data = [0; RECORD_SIZE];
+        let (kind, code) = self.kind.to_bytes();
+        let message = truncate(&self.message, MAX_EVENT_MESSAGE_LEN);
+        data[0] = RECORD_MAGIC;
+        data[1] = kind;
+        data[2] = code;
+        data[3] = message.len() as u8;
+        data[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        data[8..16].copy_from_slice(&self.timestamp.unwrap_or(0).to_le_bytes());
+        data[16..24].copy_from_slice(&self.uptime_ms.to_le_bytes());
+        data[MESSAGE_OFFSET..MESSAGE_OFFSET + message.len()].copy_from_slice(message.as_bytes());
+        let crc = crc32(&data[..CRC_OFFSET]);
+        data[CRC_OFFSET..].copy_from_slice(&crc.to_le_bytes());
+        data
+    }
+
+    /// Decodes a record stored in flash
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the record, or None if the slot is empty or the record is corrupted
+    fn decode(data: &[u8; RECORD_SIZE]) -> Option<Self> {
+        let crc = u32::from_le_bytes(data[CRC_OFFSET..].try_into().ok()?);
+        if data[0] != RECORD_MAGIC || crc != crc32(&data[..CRC_OFFSET]) {
+            return None;
+        }
+        let message_len = (data[3] as usize).min(MAX_EVENT_MESSAGE_LEN);
+        let timestamp = i64::from_le_bytes(data[8..16].try_into().ok()?);
+        Some(EventRecord {
+            sequence: u32::from_le_bytes(data[4..8].try_into().ok()?),
+            kind: EventKind::from_bytes(data[1], data[2])?,
+            timestamp: (timestamp != 0).then_some(timestamp),
+            uptime_ms: u64::from_le_bytes(data[16..24].try_into().ok()?),
+            message: String::from_utf8_lossy(&data[MESSAGE_OFFSET..MESSAGE_OFFSET + message_len])
+                .to_string(),
+        })
+    }
+
+    /// Formats the record as a line of text
+    fn to_line(&self) -> String {
+        let time = match self.timestamp {
+            Some(timestamp) => timestamp.to_string(),
+            None => "-".to_string(),
+        };
+        format!(
+            "#{} {} +{}ms {} {}",
+            self.sequence,
+            time,
+            self.uptime_ms,
+            self.kind.name(),
+            self.message
+        )
+    }
+}
+
+impl EventStorage {
+    /// Reads the raw bytes of a slot
+    fn read_slot(&self, slot: usize) -> Result<[u8; RECORD_SIZE], EventLogError> {
+        let mut data = [0; RECORD_SIZE];
+        let err = unsafe {
+            esp_partition_read(
+                self.partition.0,
+                slot * RECORD_SIZE,
+                data.as_mut_ptr() as *mut _,
+                RECORD_SIZE,
+            )
+        };
+        if err != ESP_OK {
+            return Err(EventLogError::StorageError);
+        }
+        Ok(data)
+    }
+
+    /// Reads the record of a slot
+    fn read(&self, slot: usize) -> Result<Option<EventRecord>, EventLogError> {
+        Ok(EventRecord::decode(&self.read_slot(slot)?))
+    }
+
+    /// Checks whether a slot was not written since its sector was erased
+    fn is_empty(&self, slot: usize) -> Result<bool, EventLogError> {
+        Ok(self.read_slot(slot)?.iter().all(|byte| *byte == 0xFF))
+    }
+
+    /// Erases the sector that starts on a slot
+    fn erase_sector(&self, slot: usize) -> Result<(), EventLogError> {
+        let err =
+            unsafe { esp_partition_erase_range(self.partition.0, slot * RECORD_SIZE, SECTOR_SIZE) };
+        if err != ESP_OK {
+            return Err(EventLogError::StorageError);
+        }
+        Ok(())
+    }
+
+    /// Finds the slot after the newest record, and the sequence of the next record, by reading
+    /// every slot of the partition
+    fn recover(&mut self) -> Result<(), EventLogError> {
+        let mut newest: Option<(usize, u32)> = None;
+        for slot in 0..self.slots {
+            if let Some(record) = self.read(slot)? {
+                if newest.map_or(true, |(_, sequence)| record.sequence >= sequence) {
+                    newest = Some((slot, record.sequence));
+                }
+            }
+        }
+        if let Some((slot, sequence)) = newest {
+            self.next_slot = (slot + 1) % self.slots;
+            self.next_sequence = sequence.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// Writes a record on the next free slot, erasing the oldest sector when the current one is
+    /// full. Slots that are not empty, such as a record torn by a power loss, are skipped.
+    fn append(&mut self, kind: EventKind, message: &str) -> Result<(), EventLogError> {
+        loop {
+            if self.next_slot % RECORDS_PER_SECTOR == 0 {
+                self.erase_sector(self.next_slot)?;
+                break;
+            }
+            if self.is_empty(self.next_slot)? {
+                break;
+            }
+            self.next_slot = (self.next_slot + 1) % self.slots;
+        }
+
+        let record = EventRecord {
+            sequence: self.next_sequence,
+            kind,
+            timestamp: system_timestamp().ok(),
+            uptime_ms: (unsafe { esp_timer_get_time() } / 1000) as u64,
+            message: message.to_string(),
+        };
+        let data = record.encode();
+        let err = unsafe {
+            esp_partition_write(
+                self.partition.0,
+                self.next_slot * RECORD_SIZE,
+                data.as_ptr() as *const _,
+                RECORD_SIZE,
+            )
+        };
+        self.next_slot = (self.next_slot + 1) % self.slots;
+        if err != ESP_OK {
+            return Err(EventLogError::StorageError);
+        }
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        Ok(())
+    }
+
+    /// Reads every valid record, from the oldest to the newest
+    fn records(&self) -> Result<Vec<EventRecord>, EventLogError> {
+        let mut records = Vec::new();
+        for slot in 0..self.slots {
+            if let Some(record) = self.read(slot)? {
+                records.push(record);
+            }
+        }
+        let newest = self.next_sequence.wrapping_sub(1);
+        records.sort_by_key(|record| Reverse(newest.wrapping_sub(record.sequence)));
+        Ok(records)
+    }
+}
+
+impl EventLog {
+    /// Opens the log stored on a data partition, recovering the records of previous runs, and
+    /// records a `Reset` event with the reason of the last reset.
+    ///
+    /// # Arguments
+    ///
+    /// - `partition_label`: The label of the partition on the partition table
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the EventLog, or an `EventLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::AlreadyOpen`: If a log was already opened
+    /// - `EventLogError::PartitionNotFound`: If there is no data partition with the label
+    /// - `EventLogError::InvalidPartition`: If the partition has less than 2 sectors
+    /// - `EventLogError::StorageError`: If the partition could not be read or written
+    pub fn open(partition_label: &str) -> Result<Self, EventLogError> {
+        if EVENT_LOG.get().is_some() {
+            return Err(EventLogError::AlreadyOpen);
+        }
+        let label = CString::new(partition_label).map_err(|_| EventLogError::PartitionNotFound)?;
+        let partition = unsafe {
+            esp_partition_find_first(
+                esp_partition_type_t_ESP_PARTITION_TYPE_DATA,
+                esp_partition_subtype_t_ESP_PARTITION_SUBTYPE_ANY,
+                label.as_ptr(),
+            )
+        };
+        if partition.is_null() {
+            return Err(EventLogError::PartitionNotFound);
+        }
+        let sectors = unsafe { (*partition).size } as usize / SECTOR_SIZE;
+        if sectors < 2 {
+            return Err(EventLogError::InvalidPartition);
+        }
+
+        let mut storage = EventStorage {
+            partition: PartitionPtr(partition),
+            slots: sectors * RECORDS_PER_SECTOR,
+            next_slot: 0,
+            next_sequence: 0,
+        };
+        storage.recover()?;
+        EVENT_LOG
+            .set(Mutex::new(storage))
+            .map_err(|_| EventLogError::AlreadyOpen)?;
+        let log = EventLog {
+            storage: EVENT_LOG.get().ok_or(EventLogError::AlreadyOpen)?,
+        };
+        log.record(
+            EventKind::Reset,
+            reset_reason_name(unsafe { esp_reset_reason() }),
+        )?;
+        Ok(log)
+    }
+
+    /// Gets the EventLog, if it was opened
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the EventLog, or None if it was not opened
+    pub fn get() -> Option<Self> {
+        EVENT_LOG.get().map(|storage| EventLog { storage })
+    }
+
+    /// Records an event, with the current time if the clock is set
+    ///
+    /// # Arguments
+    ///
+    /// - `kind`: The EventKind of the event
+    /// - `message`: A short description of the event. Only the first 36 bytes are stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the event was recorded, or an `EventLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::StorageError`: If the record could not be written
+    pub fn record(&self, kind: EventKind, message: &str) -> Result<(), EventLogError> {
+        self.storage
+            .lock()
+            .map_err(|_| EventLogError::StorageError)?
+            .append(kind, message)
+    }
+
+    /// Reads every event of the log
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the events, from the oldest to the newest, or an `EventLogError` if it
+    /// fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::StorageError`: If the partition could not be read
+    pub fn records(&self) -> Result<Vec<EventRecord>, EventLogError> {
+        self.storage
+            .lock()
+            .map_err(|_| EventLogError::StorageError)?
+            .records()
+    }
+
+    /// Reads the newest events of the log
+    ///
+    /// # Arguments
+    ///
+    /// - `count`: The max amount of events
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the events, from the oldest to the newest, or an `EventLogError` if it
+    /// fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::StorageError`: If the partition could not be read
+    pub fn last(&self, count: usize) -> Result<Vec<EventRecord>, EventLogError> {
+        let mut records = self.records()?;
+        let skipped = records.len().saturating_sub(count);
+        records.drain(..skipped);
+        Ok(records)
+    }
+
+    /// Gets the max amount of events the log can hold. Once full, the oldest sector is erased,
+    /// discarding 64 events at once.
+    ///
+    /// # Returns
+    ///
+    /// The amount of events
+    pub fn capacity(&self) -> usize {
+        self.storage
+            .lock()
+            .map_or(0, |storage| storage.slots - RECORDS_PER_SECTOR)
+    }
+
+    /// Erases every event of the log. Sequence numbers keep increasing.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the log was erased, or an `EventLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::StorageError`: If the partition could not be erased
+    pub fn clear(&self) -> Result<(), EventLogError> {
+        let mut storage = self
+            .storage
+            .lock()
+            .map_err(|_| EventLogError::StorageError)?;
+        for sector in 0..storage.slots / RECORDS_PER_SECTOR {
+            storage.erase_sector(sector * RECORDS_PER_SECTOR)?;
+        }
+        storage.next_slot = 0;
+        Ok(())
+    }
+
+    /// Renders the events as text, with one event per line in the format
+    /// `#<sequence> <timestamp or -> +<uptime>ms <kind> <message>`
+    ///
+    /// # Arguments
+    ///
+    /// - `count`: The max amount of events, starting from the newest, or None for every event
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the text, or an `EventLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::StorageError`: If the partition could not be read
+    pub fn render_text(&self, count: Option<usize>) -> Result<String, EventLogError> {
+        let records = match count {
+            Some(count) => self.last(count)?,
+            None => self.records()?,
+        };
+        let mut out = String::new();
+        for record in records {
+            out.push_str(&record.to_line());
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    /// Renders every event as a JSON array of objects with the fields of EventRecord
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the JSON, or an `EventLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::StorageError`: If the partition could not be read
+    pub fn render_json(&self) -> Result<String, EventLogError> {
+        let mut out = String::from("[");
+        for (i, record) in self.records()?.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let timestamp = record
+                .timestamp
+                .map_or("null".to_string(), |timestamp| timestamp.to_string());
+            _ = write!(
+                out,
+                "{{\"sequence\":{},\"kind\":\"{}\",\"timestamp\":{},\"uptime_ms\":{},\"message\":\"{}\"}}",
+                record.sequence,
+                record.kind.name(),
+                timestamp,
+                record.uptime_ms,
+                escape_json(&record.message)
+            );
+        }
+        out.push(']');
+        Ok(out)
+    }
+
+    /// Uploads every event as JSON to an HTTP endpoint, waiting for the server to accept them
+    ///
+    /// # Arguments
+    ///
+    /// - `client`: The HttpClient or HttpsClient used to send the events
+    /// - `uri`: The uri the events are posted to
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the server accepted the events, or an `EventLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `EventLogError::StorageError`: If the partition could not be read
+    /// - `EventLogError::HttpError`: If the request fails or the server rejects it
+    pub fn push_http<H: Http>(&self, client: &mut H, uri: &str) -> Result<(), EventLogError> {
+        client
+            .post_and_confirm(uri, self.render_json()?)
+            .map_err(EventLogError::HttpError)
+    }
+}
+
+/// Records an event on the EventLog if it was opened, ignoring any error. It is used by the
+/// drivers of the framework, which must keep working without a log.
+///
+/// # Arguments
+///
+/// - `kind`: The EventKind of the event
+/// - `message`: A short description of the event
+pub(crate) fn record_event(kind: EventKind, message: &str) {
+    if let Some(log) = EventLog::get() {
+        _ = log.record(kind, message);
+    }
+}
+
+/// Gets the name of a reset reason
+fn reset_reason_name(reason: esp_reset_reason_t) -> &'static str {
+    match reason {
+        esp_reset_reason_t_ESP_RST_POWERON => "power on",
+        esp_reset_reason_t_ESP_RST_EXT => "external pin",
+        esp_reset_reason_t_ESP_RST_SW => "software",
+        esp_reset_reason_t_ESP_RST_PANIC => "panic",
+        esp_reset_reason_t_ESP_RST_INT_WDT => "interrupt watchdog",
+        esp_reset_reason_t_ESP_RST_TASK_WDT => "task watchdog",
+        esp_reset_reason_t_ESP_RST_WDT => "watchdog",
+        esp_reset_reason_t_ESP_RST_DEEPSLEEP => "deep sleep wake up",
+        esp_reset_reason_t_ESP_RST_BROWNOUT => "brownout",
+        _ => "unknown",
+    }
+}
+
+/// Cuts a string to a max amount of bytes, without splitting a character
+fn truncate(text: &str, max_len: usize) -> &str {
+    let mut end = text.len().min(max_len);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Escapes the quotes, backslashes and control characters of a JSON string
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => {
+                _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Computes the CRC-32 of a record with the implementation of the ROM
+fn crc32(data: &[u8]) -> u32 {
+    unsafe { esp_rom_crc32_le(0, data.as_ptr(), data.len() as u32) }
+}
@@ -0,0 +1,119 @@
+use super::{
+    isr_queues::{ISRQueue, ISRQueueTrait},
+    notification::Notifier,
+    queue_config::queue_capacities,
+};
+use esp_idf_svc::sys::esp_timer_get_time;
+use std::sync::OnceLock;
+
+/// Maximum amount of arguments kept by each record, further arguments are ignored
+pub const MAX_ISR_LOG_ARGS: usize = 4;
+
+static ISR_LOG: OnceLock<(ISRQueue<IsrLogRecord>, Notifier)> = OnceLock::new();
+
+/// A record logged from an ISR. Formatting is postponed until it is flushed, so only the static
+/// format and the numeric arguments are kept:
+/// - `timestamp_us`: Microseconds since boot when the record was logged.
+/// - `format`: The format string, where each `{}` is replaced by an argument.
+/// - `args`: The arguments of the record.
+/// - `len`: Amount of valid arguments in `args`.
+#[derive(Debug, Clone, Copy)]
+struct IsrLogRecord {
+    timestamp_us: i64,
+    format: &'static str,
+    args: [i64; MAX_ISR_LOG_ARGS],
+    len: usize,
+}
+
+/// Logs a message from an ISR, where `println!` and `log` can not be used. The record is queued
+/// without allocating and logged with `log::info!` the next time the [crate::Microcontroller] is updated, for
+/// example in [crate::Microcontroller::wait_for_updates], with the time it was logged.
+///
+/// The format must be a string literal, where each `{}` is replaced by an argument. Up to
+/// [crate::utils::isr_log::MAX_ISR_LOG_ARGS] integer arguments are supported:
+///
+/// ```ignore
+/// isr_log!("edge on pin {} after {} us", pin, elapsed);
+/// ```
+///
+/// Records logged while the queue is full are dropped and counted in
+/// [crate::utils::queue_config::QueueDiagnostics]. Records logged before the Microcontroller is
+/// taken are dropped too.
+#[macro_export]
+macro_rules! isr_log {
+    ($format:literal $(, $arg:expr)* $(,)?) => {
+        $crate::utils::isr_log::push_record($format, &[$($arg as i64),*])
+    };
+}
+
+/// Queues a record and wakes up the microcontroller so it gets flushed. Used by [crate::isr_log].
+///
+/// # Arguments
+///
+/// - `format`: The format string of the record
+/// - `args`: The arguments of the record
+#[doc(hidden)]
+pub fn push_record(format: &'static str, args: &[i64]) {
+    let Some((queue, notifier)) = ISR_LOG.get() else {
+        return;
+    };
+    let mut record = IsrLogRecord {
+        timestamp_us: unsafe { esp_timer_get_time() },
+        format,
+        args: [0; MAX_ISR_LOG_ARGS],
+        len: args.len().min(MAX_ISR_LOG_ARGS),
+    };
+    record.args[..record.len].copy_from_slice(&args[..record.len]);
+    if queue.clone().try_send(record).is_ok() {
+        notifier.notify();
+    }
+}
+
+/// Creates the queue of the records. Called once when the microcontroller is taken.
+///
+/// # Arguments
+///
+/// - `notifier`: The notifier of the microcontroller, to wake it up when a record is logged
+pub(crate) fn init(notifier: Notifier) {
+    _ = ISR_LOG.set((ISRQueue::new(queue_capacities().isr_log), notifier));
+}
+
+/// Logs every queued record with `log::info!`, oldest first
+pub(crate) fn flush() {
+    let Some((queue, _)) = ISR_LOG.get() else {
+        return;
+    };
+    let mut queue = queue.clone();
+    while let Ok(record) = queue.try_recv() {
+        log::info!(
+            "[isr {} us] {}",
+            record.timestamp_us,
+            record.format_message()
+        );
+    }
+}
+
+impl IsrLogRecord {
+    /// Replaces each `{}` of the format with its argument. Placeholders without an argument are
+    /// kept as they are.
+    ///
+    /// # Returns
+    ///
+    /// The formatted message
+    fn format_message(&self) -> String {
+        let mut message = String::with_capacity(self.format.len());
+        let mut args = self.args[..self.len].iter();
+        let mut parts = self.format.split("{}");
+        if let Some(first) = parts.next() {
+            message.push_str(first);
+        }
+        for part in parts {
+            match args.next() {
+                Some(arg) => message.push_str(&arg.to_string()),
+                None => message.push_str("{}"),
+            }
+            message.push_str(part);
+        }
+        message
+    }
+}
@@ -1,7 +1,7 @@
 use esp_idf_svc::hal::{delay::BLOCK, task::queue::Queue};
 use std::sync::Arc;
 
-use super::auxiliary::micro_to_ticks;
+use super::{auxiliary::micro_to_ticks, queue_config::record_dropped_item};
 
 /// A queue that wraper for a Queue in an `Arc<Queue<T>>` for shared ownership, in order to share data.
 #[derive(Clone)]
@@ -43,7 +43,10 @@ impl<T: Copy> ISRQueueTrait<T> for ISRQueue<T> {
     fn send_timeout(&mut self, item: T, micro: u32) -> Result<(), ISRQueueError> {
         match self.q.send_back(item, micro_to_ticks(micro)) {
             Ok(_) => Ok(()),
-            Err(_) => Err(ISRQueueError::Timeout),
+            Err(_) => {
+                record_dropped_item();
+                Err(ISRQueueError::Timeout)
+            }
         }
     }
 
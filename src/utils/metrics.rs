@@ -0,0 +1,671 @@
+use crate::wifi::http::{Http, HttpError};
+use std::{
+    fmt::Write,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Enums the different errors possible when working with the MetricsRegistry
+#[derive(Debug)]
+pub enum MetricsError {
+    AlreadyRegistered,
+    HttpError(HttpError),
+    InvalidBuckets,
+    InvalidName,
+    SendError,
+}
+
+/// A value that can only increase, such as the amount of requests served. It can be cloned and
+/// updated from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct Counter {
+    value: Arc<AtomicU32>,
+}
+
+/// A value that can go up and down, such as a temperature. It can be cloned and updated from
+/// any thread.
+#[derive(Debug, Clone, Default)]
+pub struct Gauge {
+    bits: Arc<AtomicU32>,
+}
+
+/// Counts observations, such as request durations, in buckets of configurable upper bounds. It
+/// can be cloned and updated from any thread.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: Arc<Vec<f32>>,
+    buckets: Arc<Vec<AtomicU32>>,
+    count: Counter,
+    sum: Gauge,
+}
+
+/// Enums the kinds of metrics of the registry, with the handle to read them
+#[derive(Debug, Clone)]
+enum MetricKind {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+/// A metric of the registry, identified by its name and labels
+#[derive(Debug, Clone)]
+struct Metric {
+    name: String,
+    help: String,
+    labels: Vec<(String, String)>,
+    kind: MetricKind,
+}
+
+/// Registry of the metrics of the firmware. Metrics are registered once, and the returned
+/// handles can be updated from anywhere. The registry renders them in the Prometheus text format,
+/// to be served on a `/metrics` endpoint, or pushes them as InfluxDB line protocol.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsRegistry {
+    metrics: Arc<Mutex<Vec<Metric>>>,
+}
+
+impl Counter {
+    /// Increments the counter by one
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Increments the counter
+    ///
+    /// # Arguments
+    ///
+    /// - `amount`: The amount to add
+    pub fn add(&self, amount: u32) {
+        self.value.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    /// Gets the value of the counter
+    ///
+    /// # Returns
+    ///
+    /// The current value
+    pub fn get(&self) -> u32 {
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Gauge {
+    /// Sets the value of the gauge
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: The new value
+    pub fn set(&self, value: f32) {
+        self.bits.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Adds to the value of the gauge
+    ///
+    /// # Arguments
+    ///
+    /// - `amount`: The amount to add, negative to substract
+    pub fn add(&self, amount: f32) {
+        _ = self
+            .bits
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f32::from_bits(bits) + amount).to_bits())
+            });
+    }
+
+    /// Gets the value of the gauge
+    ///
+    /// # Returns
+    ///
+    /// The current value
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.bits.load(Ordering::Relaxed))
+    }
+}
+
+impl Histogram {
+    /// Creates a new Histogram with the given bucket upper bounds
+    ///
+    /// # Arguments
+    ///
+    /// - `bounds`: The upper bounds of the buckets, in increasing order
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new Histogram, or a `MetricsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MetricsError::InvalidBuckets`: If there are no bounds, or they are not increasing
+    fn new(bounds: &[f32]) -> Result<Self, MetricsError> {
+        let increasing = bounds.windows(2).all(|pair| pair[0] < pair[1]);
+        if bounds.is_empty() || !increasing || bounds.iter().any(|bound| bound.is_nan()) {
+            return Err(MetricsError::InvalidBuckets);
+        }
+        Ok(Histogram {
+            bounds: Arc::new(bounds.to_vec()),
+            buckets: Arc::new(bounds.iter().map(|_| AtomicU32::new(0)).collect()),
+            count: Counter::default(),
+            sum: Gauge::default(),
+        })
+    }
+
+    /// Records an observation
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: The value observed
+    pub fn observe(&self, value: f32) {
+        if let Some(bucket) = self.bounds.iter().position(|bound| value <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.count.inc();
+        self.sum.add(value);
+    }
+
+    /// Gets the amount of observations
+    ///
+    /// # Returns
+    ///
+    /// The amount of observations
+    pub fn count(&self) -> u32 {
+        self.count.get()
+    }
+
+    /// Gets the sum of every observation
+    ///
+    /// # Returns
+    ///
+    /// The sum of the observations
+    pub fn sum(&self) -> f32 {
+        self.sum.get()
+    }
+
+    /// Gets the cumulative amount of observations of each bucket, as (upper bound, count)
+    fn cumulative_buckets(&self) -> Vec<(f32, u32)> {
+        let mut total = 0;
+        self.bounds
+            .iter()
+            .zip(self.buckets.iter())
+            .map(|(bound, bucket)| {
+                total += bucket.load(Ordering::Relaxed);
+                (*bound, total)
+            })
+            .collect()
+    }
+}
+
+impl MetricsRegistry {
+    /// Creates a new empty MetricsRegistry
+    ///
+    /// # Returns
+    ///
+    /// The new MetricsRegistry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new Counter
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the metric, made of letters, digits, underscores and colons
+    /// - `help`: A description of the metric
+    /// - `labels`: The labels of the metric, as (name, value)
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the handle of the Counter, or a `MetricsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MetricsError::InvalidName`: If the name of the metric or of a label is not valid
+    /// - `MetricsError::AlreadyRegistered`: If a metric with the same name and labels exists
+    pub fn counter(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+    ) -> Result<Counter, MetricsError> {
+        let counter = Counter::default();
+        self.register(name, help, labels, MetricKind::Counter(counter.clone()))?;
+        Ok(counter)
+    }
+
+    /// Registers a new Gauge, starting at 0
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the metric, made of letters, digits, underscores and colons
+    /// - `help`: A description of the metric
+    /// - `labels`: The labels of the metric, as (name, value)
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the handle of the Gauge, or a `MetricsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MetricsError::InvalidName`: If the name of the metric or of a label is not valid
+    /// - `MetricsError::AlreadyRegistered`: If a metric with the same name and labels exists
+    pub fn gauge(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+    ) -> Result<Gauge, MetricsError> {
+        let gauge = Gauge::default();
+        self.register(name, help, labels, MetricKind::Gauge(gauge.clone()))?;
+        Ok(gauge)
+    }
+
+    /// Registers a new Histogram
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the metric, made of letters, digits, underscores and colons
+    /// - `help`: A description of the metric
+    /// - `labels`: The labels of the metric, as (name, value)
+    /// - `buckets`: The upper bounds of the buckets, in increasing order
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the handle of the Histogram, or a `MetricsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MetricsError::InvalidName`: If the name of the metric or of a label is not valid
+    /// - `MetricsError::AlreadyRegistered`: If a metric with the same name and labels exists
+    /// - `MetricsError::InvalidBuckets`: If there are no buckets, or they are not increasing
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        buckets: &[f32],
+    ) -> Result<Histogram, MetricsError> {
+        let histogram = Histogram::new(buckets)?;
+        self.register(name, help, labels, MetricKind::Histogram(histogram.clone()))?;
+        Ok(histogram)
+    }
+
+    /// Renders every metric in the Prometheus text format, to be answered on a `/metrics`
+    /// endpoint with the content type `text/plain; version=0.0.4`.
+    ///
+    /// # Returns
+    ///
+    /// The metrics in the Prometheus text format
+    pub fn render_prometheus(&self) -> String {
+        let metrics = self.metrics.lock().unwrap();
+        let mut out = String::new();
+        for (i, metric) in metrics.iter().enumerate() {
+            if !metrics[..i].iter().any(|other| other.name == metric.name) {
+                _ = writeln!(out, "# HELP {} {}", metric.name, escape_help(&metric.help));
+                _ = writeln!(out, "# TYPE {} {}", metric.name, metric.kind.type_name());
+            }
+            let labels = &metric.labels;
+            match &metric.kind {
+                MetricKind::Counter(counter) => {
+                    let rendered = prometheus_labels(labels);
+                    _ = writeln!(out, "{}{} {}", metric.name, rendered, counter.get());
+                }
+                MetricKind::Gauge(gauge) => {
+                    let rendered = prometheus_labels(labels);
+                    _ = writeln!(out, "{}{} {}", metric.name, rendered, gauge.get());
+                }
+                MetricKind::Histogram(histogram) => {
+                    let mut bucket_labels = labels.clone();
+                    bucket_labels.push((String::from("le"), String::new()));
+                    for (bound, count) in histogram.cumulative_buckets() {
+                        bucket_labels.last_mut().unwrap().1 = bound.to_string();
+                        let rendered = prometheus_labels(&bucket_labels);
+                        _ = writeln!(out, "{}_bucket{} {}", metric.name, rendered, count);
+                    }
+                    bucket_labels.last_mut().unwrap().1 = String::from("+Inf");
+                    let rendered = prometheus_labels(&bucket_labels);
+                    _ = writeln!(
+                        out,
+                        "{}_bucket{} {}",
+                        metric.name,
+                        rendered,
+                        histogram.count()
+                    );
+                    let rendered = prometheus_labels(labels);
+                    _ = writeln!(out, "{}_sum{} {}", metric.name, rendered, histogram.sum());
+                    _ = writeln!(
+                        out,
+                        "{}_count{} {}",
+                        metric.name,
+                        rendered,
+                        histogram.count()
+                    );
+                }
+            }
+        }
+        out
+    }
+
+    /// Renders every metric in the InfluxDB line protocol, one line per metric. The labels are
+    /// sent as tags, and the values as fields. No timestamp is added, so the server uses the
+    /// time of reception.
+    ///
+    /// # Returns
+    ///
+    /// The metrics in the InfluxDB line protocol
+    pub fn render_influx(&self) -> String {
+        let metrics = self.metrics.lock().unwrap();
+        let mut out = String::new();
+        for metric in metrics.iter() {
+            out.push_str(&escape_influx(&metric.name, false));
+            for (name, value) in &metric.labels {
+                _ = write!(
+                    out,
+                    ",{}={}",
+                    escape_influx(name, true),
+                    escape_influx(value, true)
+                );
+            }
+            match &metric.kind {
+                MetricKind::Counter(counter) => {
+                    _ = write!(out, " value={}i", counter.get());
+                }
+                MetricKind::Gauge(gauge) => {
+                    _ = write!(out, " value={}", gauge.get());
+                }
+                MetricKind::Histogram(histogram) => {
+                    _ = write!(out, " count={}i,sum={}", histogram.count(), histogram.sum());
+                    for (bound, count) in histogram.cumulative_buckets() {
+                        _ = write!(out, ",le_{}={}i", bound, count);
+                    }
+                }
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Pushes every metric in the InfluxDB line protocol to an UDP listener
+    ///
+    /// # Arguments
+    ///
+    /// - `address`: The address of the listener, for example "192.168.0.10:8089"
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the metrics were sent, or a `MetricsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MetricsError::SendError`: If the socket could not be created or the datagram not sent
+    pub fn push_influx_udp(&self, address: &str) -> Result<(), MetricsError> {
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|_| MetricsError::SendError)?;
+        socket
+            .send_to(self.render_influx().as_bytes(), address)
+            .map_err(|_| MetricsError::SendError)?;
+        Ok(())
+    }
+
+    /// Pushes every metric in the InfluxDB line protocol to the write endpoint of an InfluxDB
+    /// server, waiting for it to accept them.
+    ///
+    /// # Arguments
+    ///
+    /// - `client`: The HttpClient or HttpsClient used to send the metrics
+    /// - `uri`: The uri of the write endpoint, with its query parameters, for example
+    ///   "http://192.168.0.10:8086/api/v2/write?org=home&bucket=sensors"
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the server accepted the metrics, or a `MetricsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MetricsError::HttpError`: If the request fails or the server rejects it
+    pub fn push_influx_http<H: Http>(&self, client: &mut H, uri: &str) -> Result<(), MetricsError> {
+        client
+            .post_and_confirm(uri, self.render_influx())
+            .map_err(MetricsError::HttpError)
+    }
+
+    /// Adds a metric to the registry after validating it
+    fn register(
+        &self,
+        name: &str,
+        help: &str,
+        labels: &[(&str, &str)],
+        kind: MetricKind,
+    ) -> Result<(), MetricsError> {
+        let invalid_label = labels.iter().any(|(label, _)| !is_valid_name(label, false));
+        if !is_valid_name(name, true) || invalid_label {
+            return Err(MetricsError::InvalidName);
+        }
+        let labels: Vec<(String, String)> = labels
+            .iter()
+            .map(|(label, value)| (label.to_string(), value.to_string()))
+            .collect();
+
+        let mut metrics = self.metrics.lock().unwrap();
+        let clashes = metrics.iter().any(|metric| {
+            metric.name == name
+                && (metric.labels == labels || metric.kind.type_name() != kind.type_name())
+        });
+        if clashes {
+            return Err(MetricsError::AlreadyRegistered);
+        }
+        metrics.push(Metric {
+            name: name.to_string(),
+            help: help.to_string(),
+            labels,
+            kind,
+        });
+        Ok(())
+    }
+}
+
+impl MetricKind {
+    /// Gets the name of the kind in the Prometheus text format
+    fn type_name(&self) -> &'static str {
+        match self {
+            MetricKind::Counter(_) => "counter",
+            MetricKind::Gauge(_) => "gauge",
+            MetricKind::Histogram(_) => "histogram",
+        }
+    }
+}
+
+/// Checks whether a metric or label name is valid in Prometheus. Only metric names can have
+/// colons.
+fn is_valid_name(name: &str, allow_colon: bool) -> bool {
+    let valid_char = |c: char| c.is_ascii_alphabetic() || c == '_' || (allow_colon && c == ':');
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if valid_char(first) => chars.all(|c| valid_char(c) || c.is_ascii_digit()),
+        _ => false,
+    }
+}
+
+/// Renders the labels of a metric in the Prometheus text format, empty if there are no labels
+fn prometheus_labels(labels: &[(String, String)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let rendered: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", rendered.join(","))
+}
+
+/// Escapes the help text of a metric in the Prometheus text format
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// Escapes a measurement, tag key or tag value of the InfluxDB line protocol
+fn escape_influx(text: &str, is_tag: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == ',' || c == ' ' || (is_tag && c == '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn metrics_01_prometheus_text() {
+        let registry = MetricsRegistry::new();
+        let root = registry
+            .counter("http_requests_total", "Requests served", &[("path", "/")])
+            .unwrap();
+        let metrics = registry
+            .counter(
+                "http_requests_total",
+                "Requests served",
+                &[("path", "/metrics")],
+            )
+            .unwrap();
+        let temperature = registry
+            .gauge("temperature_celsius", "Temperature\nof the \\ room", &[])
+            .unwrap();
+        let duration = registry
+            .histogram(
+                "request_seconds",
+                "Request duration",
+                &[],
+                &[0.125, 0.5, 1.0],
+            )
+            .unwrap();
+
+        root.inc();
+        root.add(2);
+        metrics.inc();
+        temperature.set(20.0);
+        temperature.add(1.5);
+        for value in [0.0625, 0.25, 0.25, 2.0] {
+            duration.observe(value);
+        }
+
+        assert_eq!(
+            registry.render_prometheus(),
+            concat!(
+                "# HELP http_requests_total Requests served\n",
+                "# TYPE http_requests_total counter\n",
+                "http_requests_total{path=\"/\"} 3\n",
+                "http_requests_total{path=\"/metrics\"} 1\n",
+                "# HELP temperature_celsius Temperature\\nof the \\\\ room\n",
+                "# TYPE temperature_celsius gauge\n",
+                "temperature_celsius 21.5\n",
+                "# HELP request_seconds Request duration\n",
+                "# TYPE request_seconds histogram\n",
+                "request_seconds_bucket{le=\"0.125\"} 1\n",
+                "request_seconds_bucket{le=\"0.5\"} 3\n",
+                "request_seconds_bucket{le=\"1\"} 3\n",
+                "request_seconds_bucket{le=\"+Inf\"} 4\n",
+                "request_seconds_sum 2.5625\n",
+                "request_seconds_count 4\n",
+            )
+        );
+    }
+
+    #[test]
+    fn metrics_02_prometheus_label_values_are_escaped() {
+        let registry = MetricsRegistry::new();
+        registry
+            .gauge("up", "Up", &[("name", "say \"hi\"\\\n"), ("zone", "a")])
+            .unwrap()
+            .set(1.0);
+        assert_eq!(
+            registry.render_prometheus(),
+            concat!(
+                "# HELP up Up\n",
+                "# TYPE up gauge\n",
+                "up{name=\"say \\\"hi\\\"\\\\\\n\",zone=\"a\"} 1\n",
+            )
+        );
+    }
+
+    #[test]
+    fn metrics_03_histogram_labels_come_before_le() {
+        let registry = MetricsRegistry::new();
+        registry
+            .histogram("size_bytes", "Size", &[("kind", "png")], &[256.0])
+            .unwrap()
+            .observe(300.0);
+        assert_eq!(
+            registry.render_prometheus(),
+            concat!(
+                "# HELP size_bytes Size\n",
+                "# TYPE size_bytes histogram\n",
+                "size_bytes_bucket{kind=\"png\",le=\"256\"} 0\n",
+                "size_bytes_bucket{kind=\"png\",le=\"+Inf\"} 1\n",
+                "size_bytes_sum{kind=\"png\"} 300\n",
+                "size_bytes_count{kind=\"png\"} 1\n",
+            )
+        );
+    }
+
+    #[test]
+    fn metrics_04_influx_line_protocol() {
+        let registry = MetricsRegistry::new();
+        registry
+            .counter("boots", "Boots", &[("room", "living room,1=a")])
+            .unwrap()
+            .add(7);
+        registry
+            .histogram("latency", "Latency", &[], &[0.5, 1.0])
+            .unwrap()
+            .observe(0.25);
+        assert_eq!(
+            registry.render_influx(),
+            concat!(
+                "boots,room=living\\ room\\,1\\=a value=7i\n",
+                "latency count=1i,sum=0.25,le_0.5=1i,le_1=1i\n",
+            )
+        );
+    }
+
+    #[test]
+    fn metrics_05_invalid_registrations() {
+        let registry = MetricsRegistry::new();
+        registry.counter("requests", "Requests", &[]).unwrap();
+
+        assert!(matches!(
+            registry.counter("requests", "Requests", &[]),
+            Err(MetricsError::AlreadyRegistered)
+        ));
+        assert!(matches!(
+            registry.gauge("requests", "Requests", &[("path", "/")]),
+            Err(MetricsError::AlreadyRegistered)
+        ));
+        assert!(matches!(
+            registry.counter("1requests", "Requests", &[]),
+            Err(MetricsError::InvalidName)
+        ));
+        assert!(matches!(
+            registry.counter("requests", "Requests", &[("a:b", "c")]),
+            Err(MetricsError::InvalidName)
+        ));
+        assert!(registry.counter("job:requests", "Requests", &[]).is_ok());
+
+        for buckets in [&[][..], &[1.0, 0.5], &[1.0, 1.0], &[f32::NAN]] {
+            assert!(matches!(
+                registry.histogram("latency", "Latency", &[], buckets),
+                Err(MetricsError::InvalidBuckets)
+            ));
+        }
+    }
+}
@@ -1,5 +1,18 @@
 pub mod auxiliary;
+pub mod barcode;
+pub mod bitmap;
+pub mod energy_profiler;
 pub mod esp32_framework_error;
+pub mod event_log;
+pub mod isr_log;
 pub mod isr_queues;
+pub mod metrics;
 pub mod notification;
+pub mod qr_code;
+pub mod queue_config;
+pub mod system_clock;
 pub mod timer_driver;
+pub mod units;
+#[cfg(feature = "virtual-time")]
+pub mod virtual_time;
+pub mod watch;
@@ -1,15 +1,21 @@
+use super::queue_config::record_coalesced_notification;
 use esp_idf_svc::hal::task::{asynch::Notification as AsyncNotif, block_on};
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 /// Used for receiving a notification from an ISR context
 pub struct Notification {
     notif: Arc<AsyncNotif>,
+    pending: Arc<AtomicBool>,
 }
 
 #[derive(Clone)]
 /// Used for sending a notification from an ISR context
 pub struct Notifier {
     notif: Arc<AsyncNotif>,
+    pending: Arc<AtomicBool>,
 }
 
 impl Default for Notification {
@@ -22,12 +28,14 @@ impl Notification {
     pub fn new() -> Self {
         Self {
             notif: Arc::new(AsyncNotif::new()),
+            pending: Arc::new(AtomicBool::new(false)),
         }
     }
 
     /// Async version of [Self::blocking_wait]
     pub async fn wait(&self) {
         self.notif.wait().await;
+        self.pending.store(false, Ordering::Release);
     }
 
     /// Polls for a notification
@@ -41,12 +49,16 @@ impl Notification {
     }
 
     async fn _poll(&self) -> bool {
-        futures::poll!(self.notif.wait()).is_ready()
+        let ready = futures::poll!(self.notif.wait()).is_ready();
+        if ready {
+            self.pending.store(false, Ordering::Release);
+        }
+        ready
     }
 
     /// Blocking waits for a notification sent by any of the notification's notifiers
     pub fn blocking_wait(&self) {
-        block_on(self.notif.wait());
+        block_on(self.wait());
     }
 
     /// Create a notifier for this notification.
@@ -64,14 +76,19 @@ impl From<&Notification> for Notifier {
     fn from(value: &Notification) -> Self {
         Self {
             notif: value.notif.clone(),
+            pending: value.pending.clone(),
         }
     }
 }
 
 impl Notifier {
     /// Send a notification to the associated `Notification`, this will wake the notification if it is
-    /// currently blocked in a wait
+    /// currently blocked in a wait. If a previous notification was not consumed yet, both are merged
+    /// and counted in [crate::utils::queue_config::QueueDiagnostics].
     pub fn notify(&self) -> bool {
+        if self.pending.swap(true, Ordering::AcqRel) {
+            record_coalesced_notification();
+        }
         self.notif.notify_lsb()
     }
 }
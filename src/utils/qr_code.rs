@@ -0,0 +1,941 @@
+use super::bitmap::Bitmap;
+
+const MIN_VERSION: u8 = 1;
+const MAX_VERSION: u8 = 40;
+const ALPHANUMERIC_CHARSET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+const PAD_CODEWORDS: [u8; 2] = [0xEC, 0x11];
+const FORMAT_GENERATOR: u32 = 0x537;
+const FORMAT_MASK: u32 = 0x5412;
+const VERSION_GENERATOR: u32 = 0x1F25;
+const PENALTY_N1: i32 = 3;
+const PENALTY_N2: i32 = 3;
+const PENALTY_N3: i32 = 40;
+const PENALTY_N4: i32 = 10;
+
+/// Error correction codewords per block, indexed by error correction level and version
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 41]; 4] = [
+    [
+        0, 7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28,
+        30, 30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ],
+    [
+        0, 13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30,
+        30, 30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        0, 17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+];
+
+/// Error correction blocks, indexed by error correction level and version
+const ECC_BLOCKS: [[u8; 41]; 4] = [
+    [
+        0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ],
+    [
+        0, 1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21,
+        23, 25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ],
+    [
+        0, 1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ],
+    [
+        0, 1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32,
+        35, 37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ],
+];
+
+/// Enums the different errors possible when generating a QR code
+#[derive(Debug, PartialEq)]
+pub enum QrCodeError {
+    DataTooLong,
+}
+
+/// Enums the error correction levels of a QR code. Higher levels recover from more damage, for
+/// example a logo printed over the code, at the cost of a bigger code.
+///
+/// - `Low`: Recovers about 7% of the codewords.
+/// - `Medium`: Recovers about 15% of the codewords.
+/// - `Quartile`: Recovers about 25% of the codewords.
+/// - `High`: Recovers about 30% of the codewords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EcLevel {
+    Low,
+    Medium,
+    Quartile,
+    High,
+}
+
+/// Enums the encoding modes of the data. The most compact mode able to encode the whole data
+/// is chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Numeric,
+    Alphanumeric,
+    Byte,
+}
+
+/// A QR code, a square matrix of dark and light modules. Useful during onboarding, for example to
+/// show a provisioning url or the id of the device on a display or on a web page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QrCode {
+    version: u8,
+    ec_level: EcLevel,
+    size: usize,
+    modules: Vec<bool>,
+}
+
+/// Matrix used while drawing the code, which tracks the function modules that masks must skip
+struct QrMatrix {
+    size: usize,
+    modules: Vec<bool>,
+    is_function: Vec<bool>,
+}
+
+/// Sequence of bits, used to encode the data before splitting it in codewords
+#[derive(Default)]
+struct BitBuffer {
+    bits: Vec<bool>,
+}
+
+impl EcLevel {
+    /// Gets the index of the level on the tables of error correction codewords and blocks
+    fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// Gets the two bits that encode the level on the format information
+    fn format_bits(&self) -> u32 {
+        match self {
+            EcLevel::Low => 1,
+            EcLevel::Medium => 0,
+            EcLevel::Quartile => 3,
+            EcLevel::High => 2,
+        }
+    }
+
+    /// Gets the next higher level, if any
+    fn higher(&self) -> Option<EcLevel> {
+        match self {
+            EcLevel::Low => Some(EcLevel::Medium),
+            EcLevel::Medium => Some(EcLevel::Quartile),
+            EcLevel::Quartile => Some(EcLevel::High),
+            EcLevel::High => None,
+        }
+    }
+}
+
+impl Mode {
+    /// Gets the most compact mode able to encode all the data
+    fn for_data(data: &[u8]) -> Mode {
+        if data.iter().all(u8::is_ascii_digit) {
+            Mode::Numeric
+        } else if data.iter().all(|byte| ALPHANUMERIC_CHARSET.contains(byte)) {
+            Mode::Alphanumeric
+        } else {
+            Mode::Byte
+        }
+    }
+
+    /// Gets the mode indicator that precedes the data
+    fn indicator(&self) -> u32 {
+        match self {
+            Mode::Numeric => 0x1,
+            Mode::Alphanumeric => 0x2,
+            Mode::Byte => 0x4,
+        }
+    }
+
+    /// Gets the size of the character count field on a version
+    fn count_bits(&self, version: u8) -> usize {
+        let sizes = match self {
+            Mode::Numeric => [10, 12, 14],
+            Mode::Alphanumeric => [9, 11, 13],
+            Mode::Byte => [8, 16, 16],
+        };
+        match version {
+            1..=9 => sizes[0],
+            10..=26 => sizes[1],
+            _ => sizes[2],
+        }
+    }
+
+    /// Gets the amount of bits that the data takes once encoded, without the headers
+    fn data_bits(&self, len: usize) -> usize {
+        match self {
+            Mode::Numeric => len / 3 * 10 + [0, 4, 7][len % 3],
+            Mode::Alphanumeric => len / 2 * 11 + (len % 2) * 6,
+            Mode::Byte => len * 8,
+        }
+    }
+}
+
+impl QrCode {
+    /// Generates the QR code of some data, with the smallest version that fits it. If the data
+    /// still fits, the error correction level is raised above the one asked for.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The data to encode, for example the bytes of an url
+    /// - `ec_level`: The minimum error correction level
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new QrCode, or a `QrCodeError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `QrCodeError::DataTooLong`: If the data does not fit in a version 40 code
+    pub fn new(data: &[u8], ec_level: EcLevel) -> Result<Self, QrCodeError> {
+        let mode = Mode::for_data(data);
+        let (version, data_bits) = (MIN_VERSION..=MAX_VERSION)
+            .find_map(|version| {
+                let bits = encoded_bits(mode, data.len(), version)?;
+                (bits <= data_codewords(version, ec_level) * 8).then_some((version, bits))
+            })
+            .ok_or(QrCodeError::DataTooLong)?;
+
+        let mut ec_level = ec_level;
+        while let Some(higher) = ec_level.higher() {
+            if data_bits > data_codewords(version, higher) * 8 {
+                break;
+            }
+            ec_level = higher;
+        }
+
+        let codewords = encode_data(data, mode, version, ec_level);
+        let codewords = add_error_correction(&codewords, version, ec_level);
+        Ok(QrMatrix::draw(version, ec_level, &codewords))
+    }
+
+    /// Generates the QR code of a text, such as a provisioning url or the id of the device
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The text to encode
+    /// - `ec_level`: The minimum error correction level
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new QrCode, or a `QrCodeError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `QrCodeError::DataTooLong`: If the text does not fit in a version 40 code
+    pub fn from_text(text: &str, ec_level: EcLevel) -> Result<Self, QrCodeError> {
+        Self::new(text.as_bytes(), ec_level)
+    }
+
+    /// Generates a QR code that phone cameras recognize as a WiFi network, to join it without
+    /// typing the credentials. Useful to onboard users to the access point of the device.
+    ///
+    /// # Arguments
+    ///
+    /// - `ssid`: The name of the network
+    /// - `password`: The WPA password of the network, or None if it is open
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new QrCode, or a `QrCodeError` if it fails
+    ///
+    /// # Errors
+    ///
+    /// - `QrCodeError::DataTooLong`: If the credentials do not fit in a version 40 code
+    pub fn wifi_network(ssid: &str, password: Option<&str>) -> Result<Self, QrCodeError> {
+        let text = match password {
+            Some(password) => format!(
+                "WIFI:T:WPA;S:{};P:{};;",
+                escape_wifi_field(ssid),
+                escape_wifi_field(password)
+            ),
+            None => format!("WIFI:T:nopass;S:{};;", escape_wifi_field(ssid)),
+        };
+        Self::from_text(&text, EcLevel::Medium)
+    }
+
+    /// Gets the version of the code, from 1 to 40
+    ///
+    /// # Returns
+    ///
+    /// The version, which defines the size of the code
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Gets the error correction level of the code
+    ///
+    /// # Returns
+    ///
+    /// The `EcLevel` of the code, which may be higher than the one asked for
+    pub fn ec_level(&self) -> EcLevel {
+        self.ec_level
+    }
+
+    /// Gets the size of the code
+    ///
+    /// # Returns
+    ///
+    /// The amount of modules of each side, from 21 to 177
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Checks whether a module is dark. Modules out of bounds are light.
+    ///
+    /// # Arguments
+    ///
+    /// - `x`: The column of the module, from left to right
+    /// - `y`: The row of the module, from top to bottom
+    ///
+    /// # Returns
+    ///
+    /// A `bool` that is true if the module is dark
+    pub fn is_dark(&self, x: usize, y: usize) -> bool {
+        x < self.size && y < self.size && self.modules[y * self.size + x]
+    }
+
+    /// Renders the code as a Bitmap, to draw it on a display or to encode it as an image
+    ///
+    /// # Arguments
+    ///
+    /// - `scale`: The amount of pixels of each side of a module
+    /// - `border`: The amount of light modules around the code. Readers expect at least 4, but
+    ///   less can be used when the code is surrounded by a light background
+    ///
+    /// # Returns
+    ///
+    /// The new Bitmap
+    pub fn render(&self, scale: usize, border: usize) -> Bitmap {
+        let side = (self.size + border * 2) * scale;
+        let mut bitmap = Bitmap::new(side, side);
+        for y in 0..self.size {
+            for x in 0..self.size {
+                if self.is_dark(x, y) {
+                    bitmap.fill_rect(
+                        (x + border) * scale,
+                        (y + border) * scale,
+                        scale,
+                        scale,
+                        true,
+                    );
+                }
+            }
+        }
+        bitmap
+    }
+}
+
+impl QrMatrix {
+    /// Draws the code of the codewords, choosing the mask with the lowest penalty
+    fn draw(version: u8, ec_level: EcLevel, codewords: &[u8]) -> QrCode {
+        let size = version as usize * 4 + 17;
+        let mut matrix = QrMatrix {
+            size,
+            modules: vec![false; size * size],
+            is_function: vec![false; size * size],
+        };
+        matrix.draw_function_patterns(version, ec_level);
+        matrix.draw_codewords(codewords);
+
+        let mask = (0..8)
+            .min_by_key(|mask| {
+                matrix.apply_mask(*mask);
+                matrix.draw_format_bits(ec_level, *mask);
+                let penalty = matrix.penalty_score();
+                matrix.apply_mask(*mask);
+                penalty
+            })
+            .unwrap_or(0);
+        matrix.apply_mask(mask);
+        matrix.draw_format_bits(ec_level, mask);
+
+        QrCode {
+            version,
+            ec_level,
+            size,
+            modules: matrix.modules,
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.modules[y * self.size + x]
+    }
+
+    fn set_function(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y * self.size + x] = dark;
+        self.is_function[y * self.size + x] = true;
+    }
+
+    /// Draws the finder, alignment and timing patterns, and reserves the format and version areas
+    fn draw_function_patterns(&mut self, version: u8, ec_level: EcLevel) {
+        for i in 0..self.size {
+            self.set_function(6, i, i % 2 == 0);
+            self.set_function(i, 6, i % 2 == 0);
+        }
+
+        let far = self.size - 4;
+        for (x, y) in [(3, 3), (far, 3), (3, far)] {
+            self.draw_finder_pattern(x, y);
+        }
+
+        let positions = alignment_pattern_positions(version);
+        let last = positions.len().saturating_sub(1);
+        for (i, x) in positions.iter().enumerate() {
+            for (j, y) in positions.iter().enumerate() {
+                let overlaps_finder =
+                    (i, j) == (0, 0) || (i, j) == (0, last) || (i, j) == (last, 0);
+                if !overlaps_finder {
+                    self.draw_alignment_pattern(*x, *y);
+                }
+            }
+        }
+
+        self.draw_format_bits(ec_level, 0);
+        self.draw_version_bits(version);
+    }
+
+    fn draw_finder_pattern(&mut self, x: usize, y: usize) {
+        for dy in -4i32..=4 {
+            for dx in -4i32..=4 {
+                let distance = dx.abs().max(dy.abs());
+                let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                if (0..self.size as i32).contains(&xx) && (0..self.size as i32).contains(&yy) {
+                    self.set_function(xx as usize, yy as usize, distance != 2 && distance != 4);
+                }
+            }
+        }
+    }
+
+    fn draw_alignment_pattern(&mut self, x: usize, y: usize) {
+        for dy in -2i32..=2 {
+            for dx in -2i32..=2 {
+                let distance = dx.abs().max(dy.abs());
+                self.set_function(
+                    (x as i32 + dx) as usize,
+                    (y as i32 + dy) as usize,
+                    distance != 1,
+                );
+            }
+        }
+    }
+
+    /// Draws both copies of the format information, which encode the error correction level
+    /// and the mask, and the dark module
+    fn draw_format_bits(&mut self, ec_level: EcLevel, mask: u8) {
+        let data = ec_level.format_bits() << 3 | mask as u32;
+        let bits = (data << 10 | bch_remainder(data, FORMAT_GENERATOR, 10)) ^ FORMAT_MASK;
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+
+        for i in 0..6 {
+            self.set_function(8, i, bit(i));
+        }
+        self.set_function(8, 7, bit(6));
+        self.set_function(8, 8, bit(7));
+        self.set_function(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function(14 - i, 8, bit(i));
+        }
+
+        let size = self.size;
+        for i in 0..8 {
+            self.set_function(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function(8, size - 15 + i, bit(i));
+        }
+        self.set_function(8, size - 8, true);
+    }
+
+    /// Draws both copies of the version information, only present from version 7 onwards
+    fn draw_version_bits(&mut self, version: u8) {
+        if version < 7 {
+            return;
+        }
+        let data = version as u32;
+        let bits = data << 12 | bch_remainder(data, VERSION_GENERATOR, 12);
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let (a, b) = (self.size - 11 + i % 3, i / 3);
+            self.set_function(a, b, dark);
+            self.set_function(b, a, dark);
+        }
+    }
+
+    /// Draws the codewords on the modules that are not part of a function pattern, in the zig
+    /// zag order of the specification
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let total_bits = codewords.len() * 8;
+        let mut bit_index = 0;
+        let mut right = self.size as i32 - 1;
+        while right >= 1 {
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..self.size {
+                for column in 0..2 {
+                    let x = (right - column) as usize;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward {
+                        self.size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !self.is_function[y * self.size + x] && bit_index < total_bits {
+                        let byte = codewords[bit_index / 8];
+                        self.modules[y * self.size + x] = (byte >> (7 - bit_index % 8)) & 1 != 0;
+                        bit_index += 1;
+                    }
+                }
+            }
+            right -= 2;
+        }
+    }
+
+    /// Inverts the data modules selected by a mask. Applying it twice undoes it.
+    fn apply_mask(&mut self, mask: u8) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                };
+                let index = y * self.size + x;
+                if invert && !self.is_function[index] {
+                    self.modules[index] = !self.modules[index];
+                }
+            }
+        }
+    }
+
+    /// Computes the penalty of the current modules, using the four rules of the specification
+    fn penalty_score(&self) -> i32 {
+        let mut penalty = 0;
+        let size = self.size;
+
+        for line in 0..size {
+            let row: Vec<bool> = (0..size).map(|x| self.get(x, line)).collect();
+            let column: Vec<bool> = (0..size).map(|y| self.get(line, y)).collect();
+            penalty += line_penalty(&row) + line_penalty(&column);
+        }
+
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.get(x, y);
+                if color == self.get(x + 1, y)
+                    && color == self.get(x, y + 1)
+                    && color == self.get(x + 1, y + 1)
+                {
+                    penalty += PENALTY_N2;
+                }
+            }
+        }
+
+        let total = (size * size) as i32;
+        let dark = self.modules.iter().filter(|dark| **dark).count() as i32;
+        let deviation = ((dark * 20 - total * 10).abs() + total - 1) / total - 1;
+        penalty + deviation * PENALTY_N4
+    }
+}
+
+impl BitBuffer {
+    fn append(&mut self, value: u32, len: usize) {
+        self.bits
+            .extend((0..len).rev().map(|i| (value >> i) & 1 != 0));
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.bits
+            .chunks(8)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (i, bit)| byte | ((*bit as u8) << (7 - i)))
+            })
+            .collect()
+    }
+}
+
+/// Gets the amount of bits of the encoded data including its headers, or None if the amount of
+/// characters does not fit in the count field of the version
+fn encoded_bits(mode: Mode, len: usize, version: u8) -> Option<usize> {
+    let count_bits = mode.count_bits(version);
+    (len < 1 << count_bits).then(|| 4 + count_bits + mode.data_bits(len))
+}
+
+/// Encodes the data with its mode and count headers, padded to the capacity of the version
+fn encode_data(data: &[u8], mode: Mode, version: u8, ec_level: EcLevel) -> Vec<u8> {
+    let mut buffer = BitBuffer::default();
+    buffer.append(mode.indicator(), 4);
+    buffer.append(data.len() as u32, mode.count_bits(version));
+    match mode {
+        Mode::Numeric => {
+            for chunk in data.chunks(3) {
+                let value = chunk
+                    .iter()
+                    .fold(0, |value, digit| value * 10 + (digit - b'0') as u32);
+                buffer.append(value, chunk.len() * 3 + 1);
+            }
+        }
+        Mode::Alphanumeric => {
+            let index = |byte: &u8| {
+                ALPHANUMERIC_CHARSET
+                    .iter()
+                    .position(|c| c == byte)
+                    .unwrap_or(0) as u32
+            };
+            for chunk in data.chunks(2) {
+                match chunk {
+                    [first, second] => buffer.append(index(first) * 45 + index(second), 11),
+                    [single] => buffer.append(index(single), 6),
+                    _ => {}
+                }
+            }
+        }
+        Mode::Byte => {
+            for byte in data {
+                buffer.append(*byte as u32, 8);
+            }
+        }
+    }
+
+    let capacity = data_codewords(version, ec_level) * 8;
+    let terminator = (capacity - buffer.bits.len()).min(4);
+    buffer.append(0, terminator);
+    let padding = (8 - buffer.bits.len() % 8) % 8;
+    buffer.append(0, padding);
+
+    let mut codewords = buffer.to_bytes();
+    for pad in PAD_CODEWORDS.iter().cycle() {
+        if codewords.len() * 8 >= capacity {
+            break;
+        }
+        codewords.push(*pad);
+    }
+    codewords
+}
+
+/// Splits the data codewords in blocks, computes the error correction codewords of each block
+/// and interleaves them as they are drawn on the code
+fn add_error_correction(data: &[u8], version: u8, ec_level: EcLevel) -> Vec<u8> {
+    let blocks_count = ECC_BLOCKS[ec_level.index()][version as usize] as usize;
+    let ecc_len = ECC_CODEWORDS_PER_BLOCK[ec_level.index()][version as usize] as usize;
+    let raw_codewords = raw_data_modules(version) / 8;
+    let short_blocks = blocks_count - raw_codewords % blocks_count;
+    let short_block_len = raw_codewords / blocks_count;
+    let divisor = reed_solomon_divisor(ecc_len);
+
+    let mut blocks: Vec<Vec<u8>> = Vec::with_capacity(blocks_count);
+    let mut start = 0;
+    for i in 0..blocks_count {
+        let data_len = short_block_len - ecc_len + (i >= short_blocks) as usize;
+        let block_data = &data[start..start + data_len];
+        start += data_len;
+        let mut block = block_data.to_vec();
+        if i < short_blocks {
+            // Placeholder so every block has the same length, skipped when interleaving
+            block.push(0);
+        }
+        block.extend(reed_solomon_remainder(block_data, &divisor));
+        blocks.push(block);
+    }
+
+    let mut result = Vec::with_capacity(raw_codewords);
+    for i in 0..blocks[0].len() {
+        for (j, block) in blocks.iter().enumerate() {
+            if i != short_block_len - ecc_len || j >= short_blocks {
+                result.push(block[i]);
+            }
+        }
+    }
+    result
+}
+
+/// Gets the amount of modules available for data and error correction on a version
+fn raw_data_modules(version: u8) -> usize {
+    let version = version as usize;
+    let mut result = (16 * version + 128) * version + 64;
+    if version >= 2 {
+        let alignments = version / 7 + 2;
+        result -= (25 * alignments - 10) * alignments - 55;
+        if version >= 7 {
+            result -= 36;
+        }
+    }
+    result
+}
+
+/// Gets the amount of data codewords of a version and error correction level
+fn data_codewords(version: u8, ec_level: EcLevel) -> usize {
+    let level = ec_level.index();
+    raw_data_modules(version) / 8
+        - ECC_CODEWORDS_PER_BLOCK[level][version as usize] as usize
+            * ECC_BLOCKS[level][version as usize] as usize
+}
+
+/// Gets the coordinates of the centers of the alignment patterns, used both as rows and columns
+fn alignment_pattern_positions(version: u8) -> Vec<usize> {
+    if version == 1 {
+        return vec![];
+    }
+    let version = version as usize;
+    let count = version / 7 + 2;
+    let step = (version * 8 + count * 3 + 5) / (count * 4 - 4) * 2;
+    let size = version * 4 + 17;
+    let mut positions: Vec<usize> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Computes the remainder of the BCH code used by the format and version information
+fn bch_remainder(data: u32, generator: u32, degree: u32) -> u32 {
+    let mut remainder = data;
+    for _ in 0..degree {
+        remainder = (remainder << 1) ^ ((remainder >> (degree - 1)) * generator);
+    }
+    remainder
+}
+
+/// Computes the generator polynomial of a Reed-Solomon code of the given degree, without its
+/// leading term
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+/// Computes the error correction codewords of some data
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (value, coefficient) in result.iter_mut().zip(divisor) {
+            *value ^= gf_multiply(*coefficient, factor);
+        }
+    }
+    result
+}
+
+/// Multiplies two elements of GF(2^8) modulo the polynomial used by QR codes
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+/// Computes the penalty of rules 1 and 3 on a row or column: runs of five or more modules of the
+/// same color, and patterns that look like a finder pattern
+fn line_penalty(line: &[bool]) -> i32 {
+    let mut penalty = 0;
+    let mut run = 1;
+    for i in 1..=line.len() {
+        if i < line.len() && line[i] == line[i - 1] {
+            run += 1;
+            continue;
+        }
+        if run >= 5 {
+            penalty += PENALTY_N1 + (run - 5);
+        }
+        run = 1;
+    }
+
+    const FINDER_LIKE: [bool; 7] = [true, false, true, true, true, false, true];
+    const LIGHT: [bool; 4] = [false; 4];
+    for start in 0..line.len().saturating_sub(10) {
+        let window = &line[start..start + 11];
+        if (window[..7] == FINDER_LIKE && window[7..] == LIGHT)
+            || (window[..4] == LIGHT && window[4..] == FINDER_LIKE)
+        {
+            penalty += PENALTY_N3;
+        }
+    }
+    penalty
+}
+
+/// Escapes the special characters of a field of a WiFi QR code
+fn escape_wifi_field(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Modules of "HELLO WORLD" on a 1-Q code, which uses mask 6. Dark modules are '#'.
+    const HELLO_WORLD_MODULES: [&str; 21] = [
+        "#######....#..#######",
+        "#.....#.##..#.#.....#",
+        "#.###.#..#.##.#.###.#",
+        "#.###.#.#####.#.###.#",
+        "#.###.#.##.#..#.###.#",
+        "#.....#..#..#.#.....#",
+        "#######.#.#.#.#######",
+        "........##.##........",
+        ".#.####.##..###.##.#.",
+        "#.####.#....####.###.",
+        "..#.#.##...#..##.....",
+        "#.##.#...#.##...##...",
+        "##.########.###.#####",
+        "........#...#..#.#...",
+        "#######..##..##..####",
+        "#.....#.#.#..#..#.###",
+        "#.###.#.##.#..#...###",
+        "#.###.#.#.###...#.#..",
+        "#.###.#..#....#....##",
+        "#.....#.###..###..##.",
+        "#######..#.#.......#.",
+    ];
+
+    fn modules_as_rows(code: &QrCode) -> Vec<String> {
+        (0..code.size())
+            .map(|y| {
+                (0..code.size())
+                    .map(|x| if code.is_dark(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn qr_code_01_numeric_codewords_of_the_specification_example() {
+        let data = encode_data(b"01234567", Mode::Numeric, 1, EcLevel::Medium);
+        assert_eq!(
+            data,
+            [
+                0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11,
+                0xEC, 0x11
+            ]
+        );
+        let codewords = add_error_correction(&data, 1, EcLevel::Medium);
+        assert_eq!(
+            codewords[data.len()..],
+            [0xA5, 0x24, 0xD4, 0xC1, 0xED, 0x36, 0xC7, 0x87, 0x2C, 0x55]
+        );
+    }
+
+    #[test]
+    fn qr_code_02_byte_codewords() {
+        let data = encode_data(b"'Twas brillig", Mode::Byte, 1, EcLevel::Medium);
+        assert_eq!(
+            data,
+            [
+                0x40, 0xD2, 0x75, 0x47, 0x76, 0x17, 0x32, 0x06, 0x27, 0x26, 0x96, 0xC6, 0xC6, 0x96,
+                0x70, 0xEC
+            ]
+        );
+        let codewords = add_error_correction(&data, 1, EcLevel::Medium);
+        assert_eq!(
+            codewords[data.len()..],
+            [0xBC, 0x2A, 0x90, 0x13, 0x6B, 0xAF, 0xEF, 0xFD, 0x4B, 0xE0]
+        );
+    }
+
+    #[test]
+    fn qr_code_03_alphanumeric_codewords() {
+        let data = encode_data(b"HELLO WORLD", Mode::Alphanumeric, 1, EcLevel::Quartile);
+        assert_eq!(
+            data,
+            [32, 91, 11, 120, 209, 114, 220, 77, 67, 64, 236, 17, 236]
+        );
+        let codewords = add_error_correction(&data, 1, EcLevel::Quartile);
+        assert_eq!(
+            codewords[data.len()..],
+            [168, 72, 22, 82, 217, 54, 156, 0, 46, 15, 180, 122, 16]
+        );
+    }
+
+    #[test]
+    fn qr_code_04_modules_of_a_text() {
+        let code = QrCode::from_text("HELLO WORLD", EcLevel::Quartile).unwrap();
+        assert_eq!(code.version(), 1);
+        assert_eq!(code.ec_level(), EcLevel::Quartile);
+        assert_eq!(modules_as_rows(&code), HELLO_WORLD_MODULES);
+    }
+
+    #[test]
+    fn qr_code_05_error_correction_is_raised_if_the_data_fits() {
+        let code = QrCode::from_text("01234567", EcLevel::Medium).unwrap();
+        assert_eq!(code.version(), 1);
+        assert_eq!(code.ec_level(), EcLevel::High);
+    }
+
+    #[test]
+    fn qr_code_06_version_grows_with_the_data() {
+        let code = QrCode::from_text(&"a".repeat(100), EcLevel::Low).unwrap();
+        assert_eq!(code.version(), 5);
+        assert_eq!(code.size(), 37);
+    }
+
+    #[test]
+    fn qr_code_07_data_too_long() {
+        let res = QrCode::new(&[0; 3000], EcLevel::Low);
+        assert_eq!(res.unwrap_err(), QrCodeError::DataTooLong);
+    }
+
+    #[test]
+    fn qr_code_08_format_bits_of_the_specification() {
+        let format = |ec_level: EcLevel, mask: u32| {
+            let data = ec_level.format_bits() << 3 | mask;
+            (data << 10 | bch_remainder(data, FORMAT_GENERATOR, 10)) ^ FORMAT_MASK
+        };
+        assert_eq!(format(EcLevel::Low, 0), 0b111011111000100);
+        assert_eq!(format(EcLevel::Medium, 0), 0b101010000010010);
+    }
+
+    #[test]
+    fn qr_code_09_version_bits_of_the_specification() {
+        let bits = 7 << 12 | bch_remainder(7, VERSION_GENERATOR, 12);
+        assert_eq!(bits, 0b000111110010010100);
+    }
+
+    #[test]
+    fn qr_code_10_wifi_fields_are_escaped() {
+        assert_eq!(escape_wifi_field(r#"my;net:"a\b""#), r#"my\;net\:\"a\\b\""#);
+    }
+
+    #[test]
+    fn qr_code_11_render_adds_the_border() {
+        let code = QrCode::from_text("HELLO WORLD", EcLevel::Quartile).unwrap();
+        let bitmap = code.render(2, 4);
+        assert_eq!(bitmap.width(), (21 + 8) * 2);
+        assert!(!bitmap.is_dark(7, 7));
+        assert!(bitmap.is_dark(8, 8));
+        assert!(bitmap.is_dark(9, 9));
+        assert!(!bitmap.is_dark(10, 10));
+    }
+}
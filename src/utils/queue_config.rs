@@ -0,0 +1,120 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Mutex,
+};
+
+static CAPACITIES: Mutex<QueueCapacities> = Mutex::new(QueueCapacities::DEFAULT);
+static DROPPED_ITEMS: AtomicUsize = AtomicUsize::new(0);
+static COALESCED_NOTIFICATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// Capacities of the queues created inside the drivers, used to pass data from ISRs and other tasks
+/// to the update loop. Memory constrained builds can shrink them, and builds with a high rate of
+/// events can grow them so less items are dropped:
+/// - `ble_connection`: Connection and disconnection events of a BleServer.
+/// - `ble_write`: Writes waiting for their callbacks on a BleServer.
+/// - `ble_indication`: Indication results waiting for each of their callbacks on a BleServer.
+/// - `ble_server_events`: Events kept by the stream of [crate::ble::BleServer::events].
+/// - `ble_pairing`: Pairing events and responses of a BleServer.
+/// - `ble_sightings`: Advertisements waiting to be processed by a PresenceMonitor, or by the
+///   background scan of a BleClient.
+/// - `output_handle`: Commands sent through a DigitalOutHandle or AnalogOutHandle.
+/// - `sniffer_frames`: Frames captured by a WifiSniffer.
+/// - `peer_frames`: Frames received by a PeerLink or an EspNow.
+/// - `mqtt_messages`: Messages received by an MqttClient waiting for their callbacks.
+/// - `wifi_events`: Lifecycle events of the wifi waiting for the callbacks of the WifiDriver.
+/// - `isr_log`: Records logged with [crate::isr_log] waiting to be printed. It is created when the
+///   Microcontroller is taken, so it must be set before.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueCapacities {
+    pub ble_connection: usize,
+    pub ble_write: usize,
+    pub ble_indication: usize,
+    pub ble_server_events: usize,
+    pub ble_pairing: usize,
+    pub ble_sightings: usize,
+    pub output_handle: usize,
+    pub sniffer_frames: usize,
+    pub peer_frames: usize,
+    pub mqtt_messages: usize,
+    pub wifi_events: usize,
+    pub isr_log: usize,
+}
+
+/// Counters of the items and notifications lost since the start, or since the last reset:
+/// - `dropped_items`: Items that could not be sent because a queue was full.
+/// - `coalesced_notifications`: Notifications sent while another one was still pending, so they
+///   were merged into a single wake up. A growing value means the update loop falls behind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueueDiagnostics {
+    pub dropped_items: usize,
+    pub coalesced_notifications: usize,
+}
+
+impl QueueCapacities {
+    const DEFAULT: QueueCapacities = QueueCapacities {
+        ble_connection: 1000,
+        ble_write: 50,
+        ble_indication: 20,
+        ble_server_events: 50,
+        ble_pairing: 5,
+        ble_sightings: 100,
+        output_handle: 16,
+        sniffer_frames: 50,
+        peer_frames: 20,
+        mqtt_messages: 20,
+        wifi_events: 16,
+        isr_log: 32,
+    };
+}
+
+impl Default for QueueCapacities {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Sets the capacities of the queues. Only the drivers created afterwards are affected.
+///
+/// # Arguments
+///
+/// - `capacities`: The new QueueCapacities
+pub fn set_queue_capacities(capacities: QueueCapacities) {
+    *CAPACITIES.lock().unwrap() = capacities;
+}
+
+/// Gets the capacities used for the new queues
+///
+/// # Returns
+///
+/// The current QueueCapacities
+pub fn queue_capacities() -> QueueCapacities {
+    *CAPACITIES.lock().unwrap()
+}
+
+/// Gets the counters of lost items and notifications
+///
+/// # Returns
+///
+/// The current QueueDiagnostics
+pub fn queue_diagnostics() -> QueueDiagnostics {
+    QueueDiagnostics {
+        dropped_items: DROPPED_ITEMS.load(Ordering::Relaxed),
+        coalesced_notifications: COALESCED_NOTIFICATIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Sets the counters of lost items and notifications back to 0
+pub fn reset_queue_diagnostics() {
+    DROPPED_ITEMS.store(0, Ordering::Relaxed);
+    COALESCED_NOTIFICATIONS.store(0, Ordering::Relaxed);
+}
+
+/// Counts an item dropped because its queue was full. It is safe to call from an ISR.
+pub(crate) fn record_dropped_item() {
+    DROPPED_ITEMS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counts a notification merged with a pending one. It is safe to call from an ISR.
+pub(crate) fn record_coalesced_notification() {
+    COALESCED_NOTIFICATIONS.fetch_add(1, Ordering::Relaxed);
+}
@@ -0,0 +1,1006 @@
+use crate::{
+    sensors::{DateTime, DS3231},
+    serial::UART,
+};
+use esp_idf_svc::{
+    sntp::{EspSntp, SyncStatus},
+    sys::{settimeofday, timeval},
+};
+use std::{
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SECONDS_PER_MINUTE: i64 = 60;
+const SECONDS_PER_HOUR: i64 = 3600;
+const SECONDS_PER_DAY: i64 = 86400;
+const DEFAULT_TRANSITION_TIME: i32 = 2 * SECONDS_PER_HOUR as i32;
+const DEFAULT_DST_START: &str = "M3.2.0";
+const DEFAULT_DST_END: &str = "M11.1.0";
+const MIN_NAME_LEN: usize = 3;
+/// Timestamps before 2020-01-01 are considered as a clock that was never set
+const MIN_VALID_TIMESTAMP: i64 = 1_577_836_800;
+const BASE_YEAR: i32 = 2000;
+const PERSISTED_MAGIC: u32 = 0x7E5C_10CC;
+const NMEA_MAX_LINE_LEN: usize = 100;
+
+/// Last known time, kept in RTC memory so it survives deep sleep
+#[link_section = ".rtc.data"]
+static PERSISTED_TIMESTAMP: AtomicU32 = AtomicU32::new(0);
+#[link_section = ".rtc.data"]
+static PERSISTED_VALID: AtomicU32 = AtomicU32::new(0);
+
+/// Error types related to the system clock.
+#[derive(Debug)]
+pub enum ClockError {
+    InvalidTimeZone,
+    InvalidTime,
+    NoSourceAvailable,
+    NotSynchronized,
+    SettingTimeError,
+    SntpError,
+}
+
+/// A point in time in a given time zone:
+/// - `year`: The full year, for example 2024.
+/// - `month`: The month (1-12).
+/// - `date`: The day of the month (1-31).
+/// - `hour`: The hour (0-23).
+/// - `minute`: The minute (0-59).
+/// - `second`: The second (0-59).
+/// - `week_day`: The day of the week (1-7, where 1 is Sunday), as used by the DS3231.
+/// - `utc_offset`: Offset from UTC in seconds, positive to the east.
+/// - `is_dst`: Whether daylight saving time is in effect.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClockTime {
+    pub year: i32,
+    pub month: u8,
+    pub date: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+    pub week_day: u8,
+    pub utc_offset: i32,
+    pub is_dst: bool,
+}
+
+/// Day in which a daylight saving time transition happens, as defined by POSIX:
+/// - `MonthWeekDay`: Day `day` (0-6, 0 is Sunday) of week `week` (1-5, 5 is the last) of month `month`.
+/// - `Julian`: Day of the year (1-365), without counting February 29.
+/// - `ZeroBased`: Day of the year (0-365), counting February 29.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TransitionDate {
+    MonthWeekDay { month: u8, week: u8, day: u8 },
+    Julian(u16),
+    ZeroBased(u16),
+}
+
+/// Day and local time, in seconds, of a daylight saving time transition
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct TransitionRule {
+    date: TransitionDate,
+    time: i32,
+}
+
+/// Daylight saving time part of a time zone
+#[derive(Debug, Clone, PartialEq)]
+struct DstRules {
+    name: String,
+    utc_offset: i32,
+    start: TransitionRule,
+    end: TransitionRule,
+}
+
+/// A time zone described by a POSIX TZ string, such as "CET-1CEST,M3.5.0,M10.5.0/3" or "ART3".
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeZone {
+    std_name: String,
+    std_utc_offset: i32,
+    dst: Option<DstRules>,
+}
+
+/// A source the SystemClock can get the current time from
+pub trait TimeSource {
+    /// Gets the current time from the source.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the seconds since the unix epoch, in UTC, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::NotSynchronized`: If the source does not know the time yet.
+    fn get_timestamp(&mut self) -> Result<i64, ClockError>;
+}
+
+/// Time source that synchronizes with SNTP servers. Once started, SNTP keeps disciplining
+/// the system time periodically by itself. Wifi must be connected for it to work.
+pub struct SntpSource {
+    sntp: EspSntp<'static>,
+}
+
+/// Time source that reads NMEA RMC sentences from a GPS receiver connected through UART
+pub struct NmeaGpsSource<'a> {
+    uart: UART<'a>,
+    line: Vec<u8>,
+}
+
+/// Software clock kept by the system timer. It can be synchronized from different sources,
+/// converts the time to a local time zone with its daylight saving time rules, and can
+/// persist the last known time in RTC memory so it is available after deep sleep.
+pub struct SystemClock<'a> {
+    time_zone: TimeZone,
+    sources: Vec<Box<dyn TimeSource + 'a>>,
+}
+
+impl ClockTime {
+    /// Creates a ClockTime from a unix timestamp and an offset
+    fn from_timestamp(timestamp: i64, utc_offset: i32, is_dst: bool) -> Self {
+        let local = timestamp + utc_offset as i64;
+        let days = local.div_euclid(SECONDS_PER_DAY);
+        let seconds = local.rem_euclid(SECONDS_PER_DAY);
+        let (year, month, date) = civil_from_days(days);
+        ClockTime {
+            year,
+            month,
+            date,
+            hour: (seconds / SECONDS_PER_HOUR) as u8,
+            minute: ((seconds % SECONDS_PER_HOUR) / SECONDS_PER_MINUTE) as u8,
+            second: (seconds % SECONDS_PER_MINUTE) as u8,
+            week_day: week_day_from_days(days) + 1,
+            utc_offset,
+            is_dst,
+        }
+    }
+
+    /// Gets the unix timestamp that represents this time
+    ///
+    /// # Returns
+    ///
+    /// The seconds since the unix epoch, in UTC
+    pub fn timestamp(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.date) * SECONDS_PER_DAY
+            + self.hour as i64 * SECONDS_PER_HOUR
+            + self.minute as i64 * SECONDS_PER_MINUTE
+            + self.second as i64
+            - self.utc_offset as i64
+    }
+
+    /// Converts the time into a DateTime, for example to set it on a DS3231
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the DateTime, or a `ClockError` if the year is outside 2000-2099
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::InvalidTime`: If the year can not be represented by a DateTime
+    pub fn to_date_time(&self) -> Result<DateTime, ClockError> {
+        let year = u8::try_from(self.year - BASE_YEAR)
+            .ok()
+            .filter(|year| *year <= 99)
+            .ok_or(ClockError::InvalidTime)?;
+        Ok(DateTime {
+            second: self.second,
+            minute: self.minute,
+            hour: self.hour,
+            week_day: self.week_day,
+            date: self.date,
+            month: self.month,
+            year,
+        })
+    }
+}
+
+impl TransitionRule {
+    /// Gets the local time, in seconds since the unix epoch, when the transition happens on the year
+    fn local_timestamp(&self, year: i32) -> i64 {
+        let day = match self.date {
+            TransitionDate::MonthWeekDay { month, week, day } => {
+                let first_day = days_from_civil(year, month, 1);
+                let first_week_day = week_day_from_days(first_day);
+                let mut date = 1 + (day + 7 - first_week_day) % 7 + (week - 1) * 7;
+                if date > days_in_month(year, month) {
+                    date -= 7;
+                }
+                first_day + date as i64 - 1
+            }
+            TransitionDate::Julian(n) => {
+                let leap_day = (is_leap_year(year) && n >= 60) as i64;
+                days_from_civil(year, 1, 1) + n as i64 - 1 + leap_day
+            }
+            TransitionDate::ZeroBased(n) => days_from_civil(year, 1, 1) + n as i64,
+        };
+        day * SECONDS_PER_DAY + self.time as i64
+    }
+}
+
+impl TimeZone {
+    /// Creates the UTC time zone
+    ///
+    /// # Returns
+    ///
+    /// The UTC TimeZone
+    pub fn utc() -> Self {
+        TimeZone {
+            std_name: String::from("UTC"),
+            std_utc_offset: 0,
+            dst: None,
+        }
+    }
+
+    /// Parses a POSIX TZ string, such as "CET-1CEST,M3.5.0,M10.5.0/3". Note that POSIX offsets
+    /// are positive to the west, so "ART3" is UTC-3. If a daylight saving time name is given
+    /// without rules, the US rules are used.
+    ///
+    /// # Arguments
+    ///
+    /// - `tz`: The POSIX TZ string
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the parsed TimeZone, or a `ClockError` if the string is invalid
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::InvalidTimeZone`: If the string is not a valid POSIX TZ string
+    pub fn parse(tz: &str) -> Result<Self, ClockError> {
+        let mut parser = TzParser::new(tz);
+        let std_name = parser.name()?;
+        let std_utc_offset = -parser.offset()?;
+        if parser.is_finished() {
+            return Ok(TimeZone {
+                std_name,
+                std_utc_offset,
+                dst: None,
+            });
+        }
+
+        let dst_name = parser.name()?;
+        let dst_utc_offset = match parser.peek() {
+            Some(c) if c.is_ascii_digit() || c == b'+' || c == b'-' => -parser.offset()?,
+            _ => std_utc_offset + SECONDS_PER_HOUR as i32,
+        };
+        let (start, end) = if parser.is_finished() {
+            (
+                TzParser::new(DEFAULT_DST_START).rule()?,
+                TzParser::new(DEFAULT_DST_END).rule()?,
+            )
+        } else {
+            parser.expect(b',')?;
+            let start = parser.rule()?;
+            parser.expect(b',')?;
+            (start, parser.rule()?)
+        };
+        if !parser.is_finished() {
+            return Err(ClockError::InvalidTimeZone);
+        }
+
+        Ok(TimeZone {
+            std_name,
+            std_utc_offset,
+            dst: Some(DstRules {
+                name: dst_name,
+                utc_offset: dst_utc_offset,
+                start,
+                end,
+            }),
+        })
+    }
+
+    /// Gets the name of the time zone at a given moment, for example "CET" or "CEST"
+    ///
+    /// # Arguments
+    ///
+    /// - `timestamp`: Seconds since the unix epoch, in UTC
+    ///
+    /// # Returns
+    ///
+    /// The name of the time zone
+    pub fn name_at(&self, timestamp: i64) -> &str {
+        match (&self.dst, self.is_dst_at(timestamp)) {
+            (Some(dst), true) => &dst.name,
+            _ => &self.std_name,
+        }
+    }
+
+    /// Checks whether daylight saving time is in effect at a given moment
+    ///
+    /// # Arguments
+    ///
+    /// - `timestamp`: Seconds since the unix epoch, in UTC
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether daylight saving time is in effect
+    pub fn is_dst_at(&self, timestamp: i64) -> bool {
+        let Some(dst) = &self.dst else {
+            return false;
+        };
+        let local_days = (timestamp + self.std_utc_offset as i64).div_euclid(SECONDS_PER_DAY);
+        let (year, _, _) = civil_from_days(local_days);
+        let start = dst.start.local_timestamp(year) - self.std_utc_offset as i64;
+        let end = dst.end.local_timestamp(year) - dst.utc_offset as i64;
+        if start < end {
+            start <= timestamp && timestamp < end
+        } else {
+            timestamp < end || start <= timestamp
+        }
+    }
+
+    /// Gets the offset from UTC at a given moment
+    ///
+    /// # Arguments
+    ///
+    /// - `timestamp`: Seconds since the unix epoch, in UTC
+    ///
+    /// # Returns
+    ///
+    /// The offset in seconds, positive to the east
+    pub fn utc_offset_at(&self, timestamp: i64) -> i32 {
+        match (&self.dst, self.is_dst_at(timestamp)) {
+            (Some(dst), true) => dst.utc_offset,
+            _ => self.std_utc_offset,
+        }
+    }
+
+    /// Converts a timestamp into the local time of the time zone
+    ///
+    /// # Arguments
+    ///
+    /// - `timestamp`: Seconds since the unix epoch, in UTC
+    ///
+    /// # Returns
+    ///
+    /// The local ClockTime
+    pub fn to_local(&self, timestamp: i64) -> ClockTime {
+        ClockTime::from_timestamp(
+            timestamp,
+            self.utc_offset_at(timestamp),
+            self.is_dst_at(timestamp),
+        )
+    }
+}
+
+/// Parser of the parts of a POSIX TZ string
+struct TzParser<'s> {
+    bytes: &'s [u8],
+    pos: usize,
+}
+
+impl<'s> TzParser<'s> {
+    fn new(tz: &'s str) -> Self {
+        TzParser {
+            bytes: tz.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn is_finished(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), ClockError> {
+        match self.peek() {
+            Some(next) if next == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(ClockError::InvalidTimeZone),
+        }
+    }
+
+    /// Parses a name, either alphabetic or quoted between '<' and '>'
+    fn name(&mut self) -> Result<String, ClockError> {
+        let (start, end) = if self.peek() == Some(b'<') {
+            let start = self.pos + 1;
+            let len = self.bytes[start..]
+                .iter()
+                .position(|c| *c == b'>')
+                .ok_or(ClockError::InvalidTimeZone)?;
+            self.pos = start + len + 1;
+            (start, start + len)
+        } else {
+            let start = self.pos;
+            while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                self.pos += 1;
+            }
+            (start, self.pos)
+        };
+        if end - start < MIN_NAME_LEN {
+            return Err(ClockError::InvalidTimeZone);
+        }
+        String::from_utf8(self.bytes[start..end].to_vec()).map_err(|_| ClockError::InvalidTimeZone)
+    }
+
+    fn number(&mut self) -> Result<i32, ClockError> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.pos])
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .ok_or(ClockError::InvalidTimeZone)
+    }
+
+    /// Parses a signed time of the form [+-]hh[:mm[:ss]] into seconds
+    fn offset(&mut self) -> Result<i32, ClockError> {
+        let sign = match self.peek() {
+            Some(b'-') => {
+                self.pos += 1;
+                -1
+            }
+            Some(b'+') => {
+                self.pos += 1;
+                1
+            }
+            _ => 1,
+        };
+        let mut seconds = self.number()? * SECONDS_PER_HOUR as i32;
+        for unit in [SECONDS_PER_MINUTE as i32, 1] {
+            if self.peek() != Some(b':') {
+                break;
+            }
+            self.pos += 1;
+            seconds += self.number()? * unit;
+        }
+        Ok(sign * seconds)
+    }
+
+    /// Parses a transition rule of the form date[/time]
+    fn rule(&mut self) -> Result<TransitionRule, ClockError> {
+        let date = match self.peek() {
+            Some(b'M') => {
+                self.pos += 1;
+                let month = self.number()?;
+                self.expect(b'.')?;
+                let week = self.number()?;
+                self.expect(b'.')?;
+                let day = self.number()?;
+                if !(1..=12).contains(&month) || !(1..=5).contains(&week) || !(0..=6).contains(&day)
+                {
+                    return Err(ClockError::InvalidTimeZone);
+                }
+                TransitionDate::MonthWeekDay {
+                    month: month as u8,
+                    week: week as u8,
+                    day: day as u8,
+                }
+            }
+            Some(b'J') => {
+                self.pos += 1;
+                match self.number()? {
+                    n @ 1..=365 => TransitionDate::Julian(n as u16),
+                    _ => return Err(ClockError::InvalidTimeZone),
+                }
+            }
+            _ => match self.number()? {
+                n @ 0..=365 => TransitionDate::ZeroBased(n as u16),
+                _ => return Err(ClockError::InvalidTimeZone),
+            },
+        };
+        let time = if self.peek() == Some(b'/') {
+            self.pos += 1;
+            self.offset()?
+        } else {
+            DEFAULT_TRANSITION_TIME
+        };
+        Ok(TransitionRule { date, time })
+    }
+}
+
+impl SntpSource {
+    /// Creates a new SntpSource using the default SNTP servers, and starts synchronizing
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new SntpSource, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::SntpError`: If SNTP could not be started
+    pub fn new() -> Result<Self, ClockError> {
+        let sntp = EspSntp::new_default().map_err(|_| ClockError::SntpError)?;
+        Ok(SntpSource { sntp })
+    }
+
+    /// Checks whether SNTP already synchronized the time
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the time was synchronized
+    pub fn is_synchronized(&self) -> bool {
+        self.sntp.get_sync_status() == SyncStatus::Completed
+    }
+}
+
+impl TimeSource for SntpSource {
+    fn get_timestamp(&mut self) -> Result<i64, ClockError> {
+        if !self.is_synchronized() {
+            return Err(ClockError::NotSynchronized);
+        }
+        system_timestamp()
+    }
+}
+
+/// The DS3231 is expected to be keeping UTC time in 24 hour mode
+impl TimeSource for DS3231<'_> {
+    fn get_timestamp(&mut self) -> Result<i64, ClockError> {
+        let date_time = self.get_date_time();
+        let time = ClockTime {
+            year: BASE_YEAR + date_time.year as i32,
+            month: date_time.month,
+            date: date_time.date,
+            hour: date_time.hour,
+            minute: date_time.minute,
+            second: date_time.second,
+            week_day: date_time.week_day,
+            utc_offset: 0,
+            is_dst: false,
+        };
+        Ok(time.timestamp())
+    }
+}
+
+impl<'a> NmeaGpsSource<'a> {
+    /// Creates a new NmeaGpsSource
+    ///
+    /// # Arguments
+    ///
+    /// - `uart`: The UART connected to the GPS receiver, already configured with its baudrate
+    ///
+    /// # Returns
+    ///
+    /// The new NmeaGpsSource
+    pub fn new(uart: UART<'a>) -> Self {
+        NmeaGpsSource {
+            uart,
+            line: Vec::with_capacity(NMEA_MAX_LINE_LEN),
+        }
+    }
+
+    /// Parses a RMC sentence, such as "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A"
+    fn parse_rmc(line: &str) -> Option<i64> {
+        let line = line.split('*').next()?;
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() < 10 || !fields[0].ends_with("RMC") || fields[2] != "A" {
+            return None;
+        }
+        let (time, date) = (fields[1].as_bytes(), fields[9].as_bytes());
+        if time.len() < 6 || date.len() != 6 {
+            return None;
+        }
+        let two_digits =
+            |bytes: &[u8]| -> Option<u8> { std::str::from_utf8(bytes).ok()?.parse().ok() };
+        let time = ClockTime {
+            year: BASE_YEAR + two_digits(&date[4..6])? as i32,
+            month: two_digits(&date[2..4])?,
+            date: two_digits(&date[0..2])?,
+            hour: two_digits(&time[0..2])?,
+            minute: two_digits(&time[2..4])?,
+            second: two_digits(&time[4..6])?,
+            week_day: 0,
+            utc_offset: 0,
+            is_dst: false,
+        };
+        Some(time.timestamp())
+    }
+}
+
+impl TimeSource for NmeaGpsSource<'_> {
+    /// Reads the bytes already received from the GPS, without blocking, until a valid RMC sentence is found
+    fn get_timestamp(&mut self) -> Result<i64, ClockError> {
+        let mut byte = [0u8];
+        while let Ok(1) = self.uart.read_with_timeout(&mut byte, 0) {
+            match byte[0] {
+                b'\n' | b'\r' => {
+                    let line = std::mem::take(&mut self.line);
+                    if let Some(timestamp) =
+                        std::str::from_utf8(&line).ok().and_then(Self::parse_rmc)
+                    {
+                        return Ok(timestamp);
+                    }
+                }
+                b'$' => self.line = vec![b'$'],
+                c if self.line.len() < NMEA_MAX_LINE_LEN => self.line.push(c),
+                _ => self.line.clear(),
+            }
+        }
+        Err(ClockError::NotSynchronized)
+    }
+}
+
+impl<'a> SystemClock<'a> {
+    /// Creates a new SystemClock. If the system time was never set, and a time was persisted
+    /// in RTC memory before a deep sleep, that time is restored.
+    ///
+    /// # Arguments
+    ///
+    /// - `time_zone`: The time zone used for local times
+    ///
+    /// # Returns
+    ///
+    /// The new SystemClock
+    pub fn new(time_zone: TimeZone) -> Self {
+        let clock = SystemClock {
+            time_zone,
+            sources: vec![],
+        };
+        if !clock.is_set() && PERSISTED_VALID.load(Ordering::Relaxed) == PERSISTED_MAGIC {
+            _ = set_system_timestamp(PERSISTED_TIMESTAMP.load(Ordering::Relaxed) as i64);
+        }
+        clock
+    }
+
+    /// Sets the time zone used for local times
+    ///
+    /// # Arguments
+    ///
+    /// - `time_zone`: The new TimeZone
+    pub fn set_time_zone(&mut self, time_zone: TimeZone) {
+        self.time_zone = time_zone;
+    }
+
+    /// Gets the time zone used for local times
+    ///
+    /// # Returns
+    ///
+    /// A reference to the TimeZone
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.time_zone
+    }
+
+    /// Adds a source to synchronize the clock with. Sources are tried in the order they were added.
+    ///
+    /// # Arguments
+    ///
+    /// - `source`: The TimeSource, for example a SntpSource, a DS3231 or a NmeaGpsSource
+    pub fn add_source<S: TimeSource + 'a>(&mut self, source: S) {
+        self.sources.push(Box::new(source));
+    }
+
+    /// Synchronizes the clock with the first source that knows the current time, and
+    /// persists the new time in RTC memory.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the clock was synchronized, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::NoSourceAvailable`: If no source knows the current time.
+    /// - `ClockError::SettingTimeError`: If the system time could not be set.
+    pub fn sync(&mut self) -> Result<(), ClockError> {
+        let timestamp = self
+            .sources
+            .iter_mut()
+            .find_map(|source| source.get_timestamp().ok())
+            .ok_or(ClockError::NoSourceAvailable)?;
+        self.set_timestamp(timestamp)
+    }
+
+    /// Checks whether the clock has a valid time, either set, synchronized or restored
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the clock is set
+    pub fn is_set(&self) -> bool {
+        system_timestamp().is_ok()
+    }
+
+    /// Sets the time of the clock and persists it in RTC memory
+    ///
+    /// # Arguments
+    ///
+    /// - `timestamp`: Seconds since the unix epoch, in UTC
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the time was set, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::InvalidTime`: If the timestamp is before 2020.
+    /// - `ClockError::SettingTimeError`: If the system time could not be set.
+    pub fn set_timestamp(&mut self, timestamp: i64) -> Result<(), ClockError> {
+        if timestamp < MIN_VALID_TIMESTAMP {
+            return Err(ClockError::InvalidTime);
+        }
+        set_system_timestamp(timestamp)?;
+        self.persist()
+    }
+
+    /// Gets the current time as a unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the seconds since the unix epoch, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::NotSynchronized`: If the clock was never set.
+    pub fn timestamp(&self) -> Result<i64, ClockError> {
+        system_timestamp()
+    }
+
+    /// Gets the current local time, with the daylight saving time rules of the time zone applied
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the local ClockTime, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::NotSynchronized`: If the clock was never set.
+    pub fn now(&self) -> Result<ClockTime, ClockError> {
+        Ok(self.time_zone.to_local(system_timestamp()?))
+    }
+
+    /// Gets the current UTC time
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the UTC ClockTime, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::NotSynchronized`: If the clock was never set.
+    pub fn now_utc(&self) -> Result<ClockTime, ClockError> {
+        Ok(ClockTime::from_timestamp(system_timestamp()?, 0, false))
+    }
+
+    /// Persists the current time in RTC memory. This should be called before entering deep
+    /// sleep so the time can be restored when waking up.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the time was persisted, or a `ClockError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `ClockError::NotSynchronized`: If the clock was never set.
+    pub fn persist(&self) -> Result<(), ClockError> {
+        let timestamp = system_timestamp()?;
+        PERSISTED_TIMESTAMP.store(timestamp as u32, Ordering::Relaxed);
+        PERSISTED_VALID.store(PERSISTED_MAGIC, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Gets the system time as a unix timestamp, failing if it was never set
+pub(crate) fn system_timestamp() -> Result<i64, ClockError> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| ClockError::NotSynchronized)?
+        .as_secs() as i64;
+    if timestamp < MIN_VALID_TIMESTAMP {
+        return Err(ClockError::NotSynchronized);
+    }
+    Ok(timestamp)
+}
+
+/// Sets the system time from a unix timestamp
+fn set_system_timestamp(timestamp: i64) -> Result<(), ClockError> {
+    let time = timeval {
+        tv_sec: timestamp as _,
+        tv_usec: 0,
+    };
+    match unsafe { settimeofday(&time, ptr::null()) } {
+        0 => Ok(()),
+        _ => Err(ClockError::SettingTimeError),
+    }
+}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Gets the day of the week (0-6, 0 is Sunday) of the days since the unix epoch
+fn week_day_from_days(days: i64) -> u8 {
+    (days + 4).rem_euclid(7) as u8
+}
+
+/// Gets the days since the unix epoch of a date in the proleptic gregorian calendar
+fn days_from_civil(year: i32, month: u8, date: u8) -> i64 {
+    let year = year as i64 - (month <= 2) as i64;
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + date as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Gets the date, in the proleptic gregorian calendar, of the days since the unix epoch
+fn civil_from_days(days: i64) -> (i32, u8, u8) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let date = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = (year_of_era + era * 400 + (month <= 2) as i64) as i32;
+    (year, month, date)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CET: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+    /// 2024-03-31 01:00:00 UTC
+    const CET_DST_START_2024: i64 = 1_711_846_800;
+    /// 2024-10-27 01:00:00 UTC
+    const CET_DST_END_2024: i64 = 1_729_990_800;
+
+    fn hms(time: &ClockTime) -> (u8, u8, u8) {
+        (time.hour, time.minute, time.second)
+    }
+
+    #[test]
+    fn system_clock_01_parse_cet() {
+        let time_zone = TimeZone::parse(CET).unwrap();
+        assert_eq!(
+            time_zone,
+            TimeZone {
+                std_name: String::from("CET"),
+                std_utc_offset: 3600,
+                dst: Some(DstRules {
+                    name: String::from("CEST"),
+                    utc_offset: 7200,
+                    start: TransitionRule {
+                        date: TransitionDate::MonthWeekDay {
+                            month: 3,
+                            week: 5,
+                            day: 0
+                        },
+                        time: 7200,
+                    },
+                    end: TransitionRule {
+                        date: TransitionDate::MonthWeekDay {
+                            month: 10,
+                            week: 5,
+                            day: 0
+                        },
+                        time: 10800,
+                    },
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn system_clock_02_cet_spring_forward() {
+        let time_zone = TimeZone::parse(CET).unwrap();
+
+        let before = time_zone.to_local(CET_DST_START_2024 - 1);
+        assert_eq!((before.year, before.month, before.date), (2024, 3, 31));
+        assert_eq!(hms(&before), (1, 59, 59));
+        assert_eq!(before.utc_offset, 3600);
+        assert!(!before.is_dst);
+        assert_eq!(time_zone.name_at(CET_DST_START_2024 - 1), "CET");
+
+        let after = time_zone.to_local(CET_DST_START_2024);
+        assert_eq!(hms(&after), (3, 0, 0));
+        assert_eq!(after.utc_offset, 7200);
+        assert!(after.is_dst);
+        assert_eq!(after.week_day, 1);
+        assert_eq!(time_zone.name_at(CET_DST_START_2024), "CEST");
+    }
+
+    #[test]
+    fn system_clock_03_cet_fall_back() {
+        let time_zone = TimeZone::parse(CET).unwrap();
+
+        let before = time_zone.to_local(CET_DST_END_2024 - 1);
+        assert_eq!((before.year, before.month, before.date), (2024, 10, 27));
+        assert_eq!(hms(&before), (2, 59, 59));
+        assert!(before.is_dst);
+
+        let after = time_zone.to_local(CET_DST_END_2024);
+        assert_eq!(hms(&after), (2, 0, 0));
+        assert_eq!(after.utc_offset, 3600);
+        assert!(!after.is_dst);
+        assert_eq!(after.timestamp(), CET_DST_END_2024);
+    }
+
+    #[test]
+    fn system_clock_04_southern_hemisphere_dst_spans_the_new_year() {
+        let time_zone = TimeZone::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        // 2024-04-06 16:00:00 UTC and 2024-10-05 16:00:00 UTC
+        let (dst_end, dst_start) = (1_712_419_200, 1_728_144_000);
+
+        assert!(time_zone.is_dst_at(dst_end - 1));
+        assert_eq!(hms(&time_zone.to_local(dst_end - 1)), (2, 59, 59));
+        assert!(!time_zone.is_dst_at(dst_end));
+        assert_eq!(hms(&time_zone.to_local(dst_end)), (2, 0, 0));
+
+        assert!(!time_zone.is_dst_at(dst_start - 1));
+        assert!(time_zone.is_dst_at(dst_start));
+        assert_eq!(time_zone.utc_offset_at(dst_start), 11 * 3600);
+    }
+
+    #[test]
+    fn system_clock_05_zone_without_dst_is_west_of_utc() {
+        let time_zone = TimeZone::parse("ART3").unwrap();
+        let local = time_zone.to_local(0);
+        assert_eq!((local.year, local.month, local.date), (1969, 12, 31));
+        assert_eq!(hms(&local), (21, 0, 0));
+        assert_eq!(local.week_day, 4);
+        assert_eq!(local.utc_offset, -3 * 3600);
+        assert!(!time_zone.is_dst_at(CET_DST_START_2024));
+    }
+
+    #[test]
+    fn system_clock_06_invalid_time_zones() {
+        for tz in [
+            "",
+            "CE-1",
+            "CET",
+            "CET-1CEST,M13.1.0,M10.5.0",
+            "CET-1CEST,M3.5.0",
+            "CET-1CEST,M3.5.0,M10.5.0/3x",
+            "CET-1CEST,J0,M10.5.0",
+        ] {
+            assert!(
+                matches!(TimeZone::parse(tz), Err(ClockError::InvalidTimeZone)),
+                "{tz}"
+            );
+        }
+    }
+
+    #[test]
+    fn system_clock_07_date_time_range() {
+        let time = TimeZone::utc().to_local(CET_DST_END_2024);
+        assert_eq!(time.timestamp(), CET_DST_END_2024);
+        let date_time = time.to_date_time().unwrap();
+        assert_eq!(
+            (
+                date_time.year,
+                date_time.month,
+                date_time.date,
+                date_time.hour
+            ),
+            (24, 10, 27, 1)
+        );
+
+        let far = ClockTime { year: 2100, ..time };
+        assert!(matches!(far.to_date_time(), Err(ClockError::InvalidTime)));
+    }
+
+    #[test]
+    fn system_clock_08_parse_rmc() {
+        assert_eq!(
+            NmeaGpsSource::parse_rmc(
+                "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,150624,003.1,W*6A"
+            ),
+            Some(1_718_454_919)
+        );
+        assert_eq!(
+            NmeaGpsSource::parse_rmc("$GPRMC,123519,V,,,,,,,150624,,*6A"),
+            None
+        );
+        assert_eq!(
+            NmeaGpsSource::parse_rmc("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M"),
+            None
+        );
+    }
+}
@@ -1,20 +1,22 @@
-use crate::{
-    microcontroller_src::{
-        interrupt_driver::InterruptDriver,
-        peripherals::{Peripheral, PeripheralError},
-    },
-    utils::timer_driver::timer::TimerConfig,
+use crate::microcontroller_src::{
+    interrupt_driver::InterruptDriver,
+    peripherals::{Peripheral, PeripheralError},
 };
+#[cfg(not(feature = "virtual-time"))]
 use esp_idf_svc::hal::timer;
 use sharable_reference_macro::sharable_reference_wrapper;
 use std::{
     collections::{BinaryHeap, HashMap},
+    error::Error,
+    fmt,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
 };
 
+#[cfg(feature = "virtual-time")]
+use super::virtual_time::{self, VirtualTimer};
 use super::{
     auxiliary::{SharableRef, SharableRefExt},
     esp32_framework_error::Esp32FrameworkError,
@@ -24,6 +26,12 @@ use super::{
 const MICRO_IN_SEC: u64 = 1000000;
 const MAX_CHILDREN: u16 = u8::MAX as u16;
 
+/// The timer of a timer group, emulated on the virtual time when the `virtual-time` feature is enabled
+#[cfg(not(feature = "virtual-time"))]
+type HardwareTimer<'a> = timer::TimerDriver<'a>;
+#[cfg(feature = "virtual-time")]
+type HardwareTimer<'a> = VirtualTimer<'a>;
+
 /// Driver for handling the underlying timer resource. There can be multiple [TimerDriver]s with the same underlying
 /// timer resource, but they will function as if each one had a diferent timer resource.
 pub struct TimerDriver<'a> {
@@ -36,10 +44,11 @@ pub struct TimerDriver<'a> {
 /// Each reference has a unique id and can create one interrupt each. This is the inner of [TimerDriver] which toghether
 /// give the ilution of multiple timer resources when in reality there is only one.
 struct _TimerDriver<'a> {
-    driver: timer::TimerDriver<'a>,
+    driver: HardwareTimer<'a>,
     interrupt_update: InterruptUpdate,
     alarms: BinaryHeap<Alarm>,
     interrupts: HashMap<u16, TimeInterrupt>,
+    notifier: Notifier,
 }
 
 #[derive(Debug, PartialEq)]
@@ -55,6 +64,35 @@ pub enum TimerDriverError {
     TooManyChildren,
 }
 
+impl fmt::Display for TimerDriverError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimerDriverError::CannotSetTimerCounter => {
+                write!(f, "the timer counter could not be set")
+            }
+            TimerDriverError::CouldNotSetTimer => write!(f, "the timer could not be set"),
+            TimerDriverError::ErrorReadingAlarm => write!(f, "the alarm could not be read"),
+            TimerDriverError::ErrorReadingTimer => write!(f, "the timer could not be read"),
+            TimerDriverError::ErrorSettingUpForDelay => write!(f, "the delay could not be set up"),
+            TimerDriverError::InvalidPeripheral(_) => write!(f, "invalid peripheral"),
+            TimerDriverError::OnlyOriginalCopyCanCreateChildren => {
+                write!(f, "only the original timer driver can create children")
+            }
+            TimerDriverError::SubscriptionError => write!(f, "the timer callback could not be set"),
+            TimerDriverError::TooManyChildren => write!(f, "too many timer driver children"),
+        }
+    }
+}
+
+impl Error for TimerDriverError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TimerDriverError::InvalidPeripheral(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
 /// Represents an interrupt to be executed after some time a number of times
 struct TimeInterrupt {
     after: u64,
@@ -211,19 +249,10 @@ impl<'a> _TimerDriver<'a> {
     /// - `TimerDriverError::SubscriptionError`: If it failed while subscribing the base callback
     fn new(timer: Peripheral, notifier: Notifier) -> Result<_TimerDriver<'a>, TimerDriverError> {
         let driver = match timer {
-            Peripheral::Timer(timer_num) => match timer_num {
-                0 => timer::TimerDriver::new(unsafe { timer::TIMER00::new() }, &TimerConfig::new()),
-                1 => timer::TimerDriver::new(unsafe { timer::TIMER10::new() }, &TimerConfig::new()),
-                _ => {
-                    return Err(TimerDriverError::InvalidPeripheral(
-                        PeripheralError::NotATimerGroup,
-                    ))
-                }
-            }
-            .map_err(|_| TimerDriverError::InvalidPeripheral(PeripheralError::NotATimerGroup))?,
+            Peripheral::Timer(timer_num) => new_hardware_timer(timer_num)?,
             Peripheral::None => {
                 return Err(TimerDriverError::InvalidPeripheral(
-                    PeripheralError::AlreadyTaken,
+                    PeripheralError::AlreadyTaken { owner: None },
                 ))
             }
             _ => {
@@ -238,6 +267,7 @@ impl<'a> _TimerDriver<'a> {
             interrupt_update: InterruptUpdate::new(),
             alarms: BinaryHeap::new(),
             interrupts: HashMap::new(),
+            notifier: notifier.clone(),
         };
         timer.set_interrupt_update_callback(notifier).map(|_| timer)
     }
@@ -385,6 +415,27 @@ impl<'a> _TimerDriver<'a> {
         self.alarms = BinaryHeap::new();
     }
 
+    /// Stops the timer and removes every interrupt, of this driver and of its children
+    ///
+    /// # Returns
+    /// A `Result` containing `Ok` if the timer stopped or `Err(TimerDriverError)` if it failed
+    ///
+    /// # Errors
+    ///
+    /// - `TimerDriverError::CouldNotSetTimer`: if it fails trying to stop the timer
+    fn stop(&mut self) -> Result<(), TimerDriverError> {
+        self.reset();
+        self.driver
+            .disable_interrupt()
+            .map_err(|_| TimerDriverError::CouldNotSetTimer)?;
+        self.driver
+            .enable_alarm(false)
+            .map_err(|_| TimerDriverError::CouldNotSetTimer)?;
+        self.driver
+            .enable(false)
+            .map_err(|_| TimerDriverError::CouldNotSetTimer)
+    }
+
     /// Enables or disables the interrupt corresponding to "id". If the interrupt is enabled, and it
     /// is the new lowest time, the soonest alarm is updated. When the first interrupt is enabled, or the last
     /// disabled the timer is started or stoped accordingly
@@ -572,6 +623,106 @@ impl<'a> _TimerDriver<'a> {
     }
 }
 
+#[cfg(feature = "virtual-time")]
+impl<'a> _TimerDriver<'a> {
+    /// Gets the time of the alarm armed on the virtual timer
+    fn pending_virtual_alarm(&self) -> Option<u64> {
+        self.driver.pending_alarm()
+    }
+
+    /// Fires the alarm of the virtual timer if it is due, and handles it as the update loop of the
+    /// [crate::Microcontroller] would, so the next alarm gets armed
+    ///
+    /// # Errors
+    ///
+    /// - `TimerDriverError::CouldNotSetTimer`: if it fails trying to set the next alarm
+    fn fire_due_virtual_alarm(&mut self) -> Result<(), TimerDriverError> {
+        let due = self
+            .pending_virtual_alarm()
+            .map_or(false, |alarm| alarm <= virtual_time::now_us());
+        if due {
+            self.driver.fire();
+            self._update_interrupt()?;
+        }
+        Ok(())
+    }
+}
+
+/// Creates the timer of a timer group
+///
+/// # Errors
+///
+/// - `TimerDriverError::InvalidPeripheral`: If there is no timer group with the number
+#[cfg(not(feature = "virtual-time"))]
+fn new_hardware_timer<'a>(timer_num: u8) -> Result<HardwareTimer<'a>, TimerDriverError> {
+    match timer_num {
+        0 => timer::TimerDriver::new(unsafe { timer::TIMER00::new() }, &timer::TimerConfig::new()),
+        1 => timer::TimerDriver::new(unsafe { timer::TIMER10::new() }, &timer::TimerConfig::new()),
+        _ => {
+            return Err(TimerDriverError::InvalidPeripheral(
+                PeripheralError::NotATimerGroup,
+            ))
+        }
+    }
+    .map_err(|_| TimerDriverError::InvalidPeripheral(PeripheralError::NotATimerGroup))
+}
+
+/// Creates the virtual timer of a timer group
+///
+/// # Errors
+///
+/// - `TimerDriverError::InvalidPeripheral`: If there is no timer group with the number
+#[cfg(feature = "virtual-time")]
+fn new_hardware_timer<'a>(timer_num: u8) -> Result<HardwareTimer<'a>, TimerDriverError> {
+    match timer_num {
+        0 | 1 => Ok(VirtualTimer::new()),
+        _ => Err(TimerDriverError::InvalidPeripheral(
+            PeripheralError::NotATimerGroup,
+        )),
+    }
+}
+
+/// Fires the soonest alarms of the timer drivers that are due before an instant of the virtual time,
+/// moving the virtual time to them. Alarms of different timer groups due at the same time are fired
+/// together. Once no alarm is due, the virtual time is moved to the instant.
+///
+/// # Arguments
+///
+/// - `timer_drivers`: The timer drivers of every timer group in use
+/// - `until`: The instant of the virtual time, in microseconds
+///
+/// # Returns
+///
+/// A `Result` with true if any alarm was fired, false once there are no alarms due, or a
+/// `TimerDriverError` if it fails
+///
+/// # Errors
+///
+/// - `TimerDriverError::CouldNotSetTimer`: if it fails trying to set the next alarm
+#[cfg(feature = "virtual-time")]
+pub(crate) fn advance_virtual_time_step(
+    timer_drivers: &mut [TimerDriver],
+    until: u64,
+) -> Result<bool, TimerDriverError> {
+    let soonest = timer_drivers
+        .iter()
+        .filter_map(|timer_driver| timer_driver.inner.borrow().pending_virtual_alarm())
+        .min();
+    match soonest {
+        Some(alarm) if alarm <= until => {
+            virtual_time::set_now_us(alarm);
+            for timer_driver in timer_drivers {
+                timer_driver.inner.deref_mut().fire_due_virtual_alarm()?;
+            }
+            Ok(true)
+        }
+        _ => {
+            virtual_time::set_now_us(until);
+            Ok(false)
+        }
+    }
+}
+
 impl<'a> InterruptDriver<'a> for TimerDriver<'a> {
     fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
         self.inner.deref_mut()._update_interrupt()?;
@@ -585,6 +736,12 @@ impl<'a> InterruptDriver<'a> for TimerDriver<'a> {
             next_child: self.next_child,
         })
     }
+
+    /// Stops the timer, removing the interrupts of every [TimerDriver] sharing it
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().stop()?;
+        Ok(())
+    }
 }
 
 impl<'a> TimerDriver<'a> {
@@ -626,6 +783,43 @@ impl<'a> TimerDriver<'a> {
         })
     }
 
+    /// Gets the notifier used to wake up the [crate::Microcontroller] after an interrupt, so the
+    /// drivers that own the TimerDriver can be woken up from other threads
+    ///
+    /// # Returns
+    ///
+    /// A clone of the `Notifier` of the timer
+    pub(crate) fn notifier(&self) -> Notifier {
+        self.inner.borrow().notifier.clone()
+    }
+
+    /// Advances the virtual time, firing in order every alarm of the timer group that is due
+    /// meanwhile, including the ones armed again by periodic interrupts. The callbacks are executed
+    /// at the virtual time of their alarm, so debounces, schedules and ramps can be tested without
+    /// waiting. The drivers of other timer groups are not advanced, see
+    /// [crate::Microcontroller::advance_time] for that.
+    ///
+    /// Note: Only available with the `virtual-time` feature. Time measured with `Instant` is not
+    /// affected, use [crate::utils::virtual_time::now] instead.
+    ///
+    /// # Arguments
+    ///
+    /// - `mili_secs`: Amount of miliseconds to advance
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with `Ok` if the alarms were handled or an Err(TimerDriverError) if it failed
+    ///
+    /// # Errors
+    ///
+    /// - `TimerDriverError::CouldNotSetTimer`: if it fails trying to set the next alarm
+    #[cfg(feature = "virtual-time")]
+    pub fn advance(&mut self, mili_secs: u64) -> Result<(), TimerDriverError> {
+        let until = virtual_time::now_us().saturating_add(mili_secs.saturating_mul(1000));
+        while advance_virtual_time_step(std::slice::from_mut(self), until)? {}
+        Ok(())
+    }
+
     /// Async function to sleep on a task
     ///
     /// Note: For the delay to work properly, must be used [crate::Microcontroller::block_on].
@@ -724,7 +918,7 @@ mod test {
         let res = TimerDriver::new(timer_group, notif.notifier());
         assert_timer_driver_error(
             res,
-            TimerDriverError::InvalidPeripheral(PeripheralError::AlreadyTaken),
+            TimerDriverError::InvalidPeripheral(PeripheralError::AlreadyTaken { owner: None }),
         );
     }
 
@@ -893,4 +1087,22 @@ mod test {
 
         assert_eq!(*amount_of_callbacks.deref(), 1);
     }
+
+    #[test]
+    #[cfg(feature = "virtual-time")]
+    fn timer_driver_15_advancing_virtual_time_fires_every_due_alarm() {
+        let (mut timer_driver, _) = get_base_timer_driver();
+        let amount_of_callbacks = SharableRef::new_sharable(0);
+        let mut amount_of_callbacks_ref = amount_of_callbacks.clone();
+
+        timer_driver.interrupt_after_n_times(10_000, None, true, move || {
+            *amount_of_callbacks_ref.deref_mut() += 1
+        });
+        timer_driver.enable().unwrap();
+
+        timer_driver.advance(95).unwrap();
+        assert_eq!(*amount_of_callbacks.deref(), 9);
+        timer_driver.advance(5).unwrap();
+        assert_eq!(*amount_of_callbacks.deref(), 10);
+    }
 }
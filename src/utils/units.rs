@@ -0,0 +1,221 @@
+use esp_idf_svc::hal::units;
+use std::{fmt, time::Duration};
+
+const MICROS_PER_SECOND: u64 = 1_000_000;
+const MILLIVOLTS_PER_VOLT: f32 = 1000.0;
+const KELVIN_OFFSET: f32 = 273.15;
+
+/// A temperature in degrees Celsius
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd)]
+pub struct Celsius(pub f32);
+
+/// A voltage in millivolts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Millivolts(pub u32);
+
+/// A frequency in hertz
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Hertz(pub u32);
+
+/// A span of time in microseconds
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Microseconds(pub u64);
+
+impl Celsius {
+    /// Creates a Celsius from a temperature in degrees Fahrenheit
+    ///
+    /// # Arguments
+    ///
+    /// - `fahrenheit`: The temperature in degrees Fahrenheit
+    ///
+    /// # Returns
+    ///
+    /// The same temperature in degrees Celsius
+    pub fn from_fahrenheit(fahrenheit: f32) -> Self {
+        Celsius((fahrenheit - 32.0) * 5.0 / 9.0)
+    }
+
+    /// Gets the temperature in degrees Fahrenheit
+    pub fn fahrenheit(&self) -> f32 {
+        self.0 * 9.0 / 5.0 + 32.0
+    }
+
+    /// Gets the temperature in kelvin
+    pub fn kelvin(&self) -> f32 {
+        self.0 + KELVIN_OFFSET
+    }
+}
+
+impl Millivolts {
+    /// Creates a Millivolts from a voltage in volts, rounded to the nearest millivolt. Negative
+    /// voltages are taken as 0.
+    ///
+    /// # Arguments
+    ///
+    /// - `volts`: The voltage in volts
+    ///
+    /// # Returns
+    ///
+    /// The same voltage in millivolts
+    pub fn from_volts(volts: f32) -> Self {
+        Millivolts((volts * MILLIVOLTS_PER_VOLT).round().max(0.0) as u32)
+    }
+
+    /// Gets the voltage in volts
+    pub fn volts(&self) -> f32 {
+        self.0 as f32 / MILLIVOLTS_PER_VOLT
+    }
+}
+
+impl Hertz {
+    /// Creates a Hertz from a frequency in kilohertz
+    pub fn from_khz(khz: u32) -> Self {
+        Hertz(khz * 1000)
+    }
+
+    /// Gets the period of the frequency, which is the time between two cycles
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the period, or None if the frequency is 0
+    pub fn period(&self) -> Option<Microseconds> {
+        (self.0 > 0).then(|| Microseconds(MICROS_PER_SECOND / self.0 as u64))
+    }
+}
+
+impl Microseconds {
+    /// Creates a Microseconds from a time in milliseconds
+    pub fn from_millis(millis: u64) -> Self {
+        Microseconds(millis * 1000)
+    }
+
+    /// Gets the time in whole milliseconds
+    pub fn as_millis(&self) -> u64 {
+        self.0 / 1000
+    }
+
+    /// Gets the frequency whose period is this time
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the frequency, or None if the time is 0 or longer than a second
+    pub fn frequency(&self) -> Option<Hertz> {
+        (self.0 > 0 && self.0 <= MICROS_PER_SECOND)
+            .then(|| Hertz((MICROS_PER_SECOND / self.0) as u32))
+    }
+}
+
+impl From<Duration> for Microseconds {
+    fn from(value: Duration) -> Self {
+        Microseconds(value.as_micros() as u64)
+    }
+}
+
+impl From<Microseconds> for Duration {
+    fn from(value: Microseconds) -> Self {
+        Duration::from_micros(value.0)
+    }
+}
+
+impl From<units::Hertz> for Hertz {
+    fn from(value: units::Hertz) -> Self {
+        Hertz(value.0)
+    }
+}
+
+impl From<Hertz> for units::Hertz {
+    fn from(value: Hertz) -> Self {
+        units::Hertz(value.0)
+    }
+}
+
+impl fmt::Display for Celsius {
+    /// Shows the temperature with 2 decimals, unless other precision is given, such as `23.50 °C`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.*} °C", f.precision().unwrap_or(2), self.0)
+    }
+}
+
+impl fmt::Display for Millivolts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} mV", self.0)
+    }
+}
+
+impl fmt::Display for Hertz {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} Hz", self.0)
+    }
+}
+
+impl fmt::Display for Microseconds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} us", self.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn assert_close(value: f32, expected: f32) {
+        assert!((value - expected).abs() < 1e-3, "{value} != {expected}");
+    }
+
+    #[test]
+    fn units_01_celsius_conversions() {
+        assert_close(Celsius::from_fahrenheit(212.0).0, 100.0);
+        assert_close(Celsius::from_fahrenheit(-40.0).0, -40.0);
+        assert_close(Celsius(37.0).fahrenheit(), 98.6);
+        assert_close(Celsius(0.0).kelvin(), 273.15);
+        assert_close(Celsius(-273.15).kelvin(), 0.0);
+    }
+
+    #[test]
+    fn units_02_millivolts_conversions() {
+        assert_eq!(Millivolts::from_volts(3.3), Millivolts(3300));
+        assert_eq!(Millivolts::from_volts(1.2344), Millivolts(1234));
+        assert_eq!(Millivolts::from_volts(0.0006), Millivolts(1));
+        assert_eq!(Millivolts::from_volts(-1.0), Millivolts(0));
+        assert_close(Millivolts(1650).volts(), 1.65);
+    }
+
+    #[test]
+    fn units_03_frequency_and_period() {
+        assert_eq!(Hertz::from_khz(40), Hertz(40_000));
+        assert_eq!(Hertz(1000).period(), Some(Microseconds(1000)));
+        assert_eq!(Hertz(3).period(), Some(Microseconds(333_333)));
+        assert_eq!(Hertz(0).period(), None);
+
+        assert_eq!(Microseconds(20_000).frequency(), Some(Hertz(50)));
+        assert_eq!(Microseconds(1_000_000).frequency(), Some(Hertz(1)));
+        assert_eq!(Microseconds(1_000_001).frequency(), None);
+        assert_eq!(Microseconds(0).frequency(), None);
+    }
+
+    #[test]
+    fn units_04_microseconds_conversions() {
+        assert_eq!(Microseconds::from_millis(250), Microseconds(250_000));
+        assert_eq!(Microseconds(1_999).as_millis(), 1);
+        assert_eq!(
+            Microseconds::from(Duration::from_millis(1500)),
+            Microseconds(1_500_000)
+        );
+        assert_eq!(Duration::from(Microseconds(42)), Duration::from_micros(42));
+    }
+
+    #[test]
+    fn units_05_hal_hertz_conversions() {
+        assert_eq!(Hertz::from(units::Hertz(8_000_000)), Hertz(8_000_000));
+        assert_eq!(units::Hertz::from(Hertz(440)).0, 440);
+    }
+
+    #[test]
+    fn units_06_display() {
+        assert_eq!(Celsius(23.5).to_string(), "23.50 °C");
+        assert_eq!(format!("{:.1}", Celsius(-4.26)), "-4.3 °C");
+        assert_eq!(Millivolts(3300).to_string(), "3300 mV");
+        assert_eq!(Hertz(50).to_string(), "50 Hz");
+        assert_eq!(Microseconds(20).to_string(), "20 us");
+    }
+}
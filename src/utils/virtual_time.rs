@@ -0,0 +1,120 @@
+use std::{cell::Cell, convert::Infallible, marker::PhantomData, time::Duration};
+
+/// Ticks per second of the virtual timers, so their counter is in microseconds
+const VIRTUAL_TICK_HZ: u64 = 1_000_000;
+
+thread_local! {
+    /// Microseconds elapsed since the start of the virtual time
+    static NOW_US: Cell<u64> = Cell::new(0);
+}
+
+/// Stand-in of the hardware timer of a timer group, used by the [crate::timer_driver::TimerDriver]s
+/// when the `virtual-time` feature is enabled. It reads the virtual clock instead of counting, and
+/// its alarm only fires when the time is advanced with [crate::timer_driver::TimerDriver::advance]
+/// or [crate::Microcontroller::advance_time].
+pub(crate) struct VirtualTimer<'a> {
+    alarm: u64,
+    alarm_enabled: bool,
+    interrupt_enabled: bool,
+    running: bool,
+    callback: Option<Box<dyn FnMut() + Send + 'static>>,
+    _lifetime: PhantomData<&'a ()>,
+}
+
+/// Gets the virtual time
+///
+/// # Returns
+///
+/// The time elapsed since the start of the virtual time
+pub fn now() -> Duration {
+    Duration::from_micros(now_us())
+}
+
+/// Gets the virtual time in microseconds
+pub(crate) fn now_us() -> u64 {
+    NOW_US.with(|now| now.get())
+}
+
+/// Moves the virtual time forward to an instant. Instants in the past are ignored, since the time
+/// never goes backwards.
+pub(crate) fn set_now_us(instant: u64) {
+    NOW_US.with(|now| now.set(now.get().max(instant)));
+}
+
+impl<'a> VirtualTimer<'a> {
+    pub(crate) fn new() -> Self {
+        VirtualTimer {
+            alarm: 0,
+            alarm_enabled: false,
+            interrupt_enabled: false,
+            running: false,
+            callback: None,
+            _lifetime: PhantomData,
+        }
+    }
+
+    pub(crate) fn tick_hz(&self) -> u64 {
+        VIRTUAL_TICK_HZ
+    }
+
+    pub(crate) fn counter(&self) -> Result<u64, Infallible> {
+        Ok(now_us())
+    }
+
+    pub(crate) fn alarm(&self) -> Result<u64, Infallible> {
+        Ok(self.alarm)
+    }
+
+    pub(crate) fn set_alarm(&mut self, alarm: u64) -> Result<(), Infallible> {
+        self.alarm = alarm;
+        Ok(())
+    }
+
+    pub(crate) fn enable_alarm(&mut self, enable: bool) -> Result<(), Infallible> {
+        self.alarm_enabled = enable;
+        Ok(())
+    }
+
+    pub(crate) fn enable_interrupt(&mut self) -> Result<(), Infallible> {
+        self.interrupt_enabled = true;
+        Ok(())
+    }
+
+    pub(crate) fn disable_interrupt(&mut self) -> Result<(), Infallible> {
+        self.interrupt_enabled = false;
+        Ok(())
+    }
+
+    pub(crate) fn enable(&mut self, enable: bool) -> Result<(), Infallible> {
+        self.running = enable;
+        Ok(())
+    }
+
+    /// Sets the callback executed when the alarm fires. Unsafe only to match the signature of the
+    /// hardware timer.
+    pub(crate) unsafe fn subscribe<F: FnMut() + Send + 'static>(
+        &mut self,
+        callback: F,
+    ) -> Result<(), Infallible> {
+        self.callback = Some(Box::new(callback));
+        Ok(())
+    }
+
+    /// Gets the time of the alarm if it would fire on the hardware timer
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the time of the alarm in microseconds, or None if the timer is stopped or
+    /// the alarm or interrupt are disabled
+    pub(crate) fn pending_alarm(&self) -> Option<u64> {
+        (self.running && self.alarm_enabled && self.interrupt_enabled).then_some(self.alarm)
+    }
+
+    /// Fires the alarm, disabling it as the hardware does, and executes the callback
+    pub(crate) fn fire(&mut self) {
+        self.alarm_enabled = false;
+        if let Some(callback) = self.callback.as_mut() {
+            callback();
+        }
+    }
+}
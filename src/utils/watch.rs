@@ -0,0 +1,243 @@
+use super::auxiliary::{SharableRef, SharableRefExt};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+/// Enums the different errors possible when working with the WatchRegistry
+#[derive(Debug)]
+pub enum WatchError {
+    AlreadyRegistered,
+    InvalidName,
+    InvalidValue,
+    NotFound,
+    ReadOnly,
+}
+
+/// A value that can be read and modified as text by a [WatchRegistry]. It is implemented for
+/// shared atomics, and for `SharableRef`s of any type that can be parsed and displayed, such as
+/// `SharableRef<f32>` for a PID gain.
+pub trait Watchable {
+    /// Reads the value
+    ///
+    /// # Returns
+    ///
+    /// The current value as text
+    fn read(&self) -> String;
+
+    /// Parses and sets a new value
+    ///
+    /// # Arguments
+    ///
+    /// - `value`: The new value as text
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the value was set, or a `WatchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WatchError::InvalidValue`: If the text can not be parsed as the type of the value
+    fn write(&mut self, value: &str) -> Result<(), WatchError>;
+}
+
+/// A variable of the registry
+struct WatchVariable<'a> {
+    value: Box<dyn Watchable + 'a>,
+    read_only: bool,
+}
+
+/// Registry of named variables that can be listed, read and modified at runtime, to tune
+/// thresholds or gains without reflashing. The user code keeps using the registered atomics or
+/// `SharableRef`s, while the registry is exposed through a debug channel, for example with the
+/// `watch` command of [crate::serial::shell::Shell::add_watch_command], which can be reached over the
+/// console, TCP or BLE. The registry can be cloned, and every clone shares the same variables.
+#[derive(Clone, Default)]
+pub struct WatchRegistry<'a> {
+    variables: SharableRef<BTreeMap<String, WatchVariable<'a>>>,
+}
+
+impl<'a> WatchRegistry<'a> {
+    /// Creates a new empty WatchRegistry
+    ///
+    /// # Returns
+    ///
+    /// The new WatchRegistry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a variable that can be read and modified
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the variable, without whitespaces
+    /// - `value`: The variable, such as an `Arc<AtomicU32>` or a `SharableRef<f32>`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the variable was registered, or a `WatchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WatchError::InvalidName`: If the name is empty or has whitespaces
+    /// - `WatchError::AlreadyRegistered`: If there already is a variable with the same name
+    pub fn watch<W: Watchable + 'a>(&self, name: &str, value: W) -> Result<(), WatchError> {
+        self.register(name, Box::new(value), false)
+    }
+
+    /// Registers a variable that can only be read
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the variable, without whitespaces
+    /// - `value`: The variable, such as an `Arc<AtomicU32>` or a `SharableRef<f32>`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the variable was registered, or a `WatchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WatchError::InvalidName`: If the name is empty or has whitespaces
+    /// - `WatchError::AlreadyRegistered`: If there already is a variable with the same name
+    pub fn watch_read_only<W: Watchable + 'a>(
+        &self,
+        name: &str,
+        value: W,
+    ) -> Result<(), WatchError> {
+        self.register(name, Box::new(value), true)
+    }
+
+    /// Removes a variable from the registry
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the variable
+    ///
+    /// # Returns
+    ///
+    /// True if the variable existed, False if not
+    pub fn unwatch(&self, name: &str) -> bool {
+        self.variables.borrow_mut().remove(name).is_some()
+    }
+
+    /// Lists every variable, sorted by name
+    ///
+    /// # Returns
+    ///
+    /// A `Vec` with the name, value and whether it is read only of each variable
+    pub fn list(&self) -> Vec<(String, String, bool)> {
+        self.variables
+            .borrow()
+            .iter()
+            .map(|(name, variable)| (name.clone(), variable.value.read(), variable.read_only))
+            .collect()
+    }
+
+    /// Reads a variable
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the variable
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the value as text, or a `WatchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WatchError::NotFound`: If there is no variable with the name
+    pub fn read(&self, name: &str) -> Result<String, WatchError> {
+        self.variables
+            .borrow()
+            .get(name)
+            .map(|variable| variable.value.read())
+            .ok_or(WatchError::NotFound)
+    }
+
+    /// Modifies a variable
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the variable
+    /// - `value`: The new value as text
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the variable was modified, or a `WatchError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WatchError::NotFound`: If there is no variable with the name
+    /// - `WatchError::ReadOnly`: If the variable was registered as read only
+    /// - `WatchError::InvalidValue`: If the text can not be parsed as the type of the variable
+    pub fn write(&self, name: &str, value: &str) -> Result<(), WatchError> {
+        let mut variables = self.variables.borrow_mut();
+        let variable = variables.get_mut(name).ok_or(WatchError::NotFound)?;
+        if variable.read_only {
+            return Err(WatchError::ReadOnly);
+        }
+        variable.value.write(value)
+    }
+
+    /// Adds a variable to the registry
+    fn register(
+        &self,
+        name: &str,
+        value: Box<dyn Watchable + 'a>,
+        read_only: bool,
+    ) -> Result<(), WatchError> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(WatchError::InvalidName);
+        }
+        let mut variables = self.variables.borrow_mut();
+        if variables.contains_key(name) {
+            return Err(WatchError::AlreadyRegistered);
+        }
+        variables.insert(name.to_string(), WatchVariable { value, read_only });
+        Ok(())
+    }
+}
+
+impl<T: FromStr + Display> Watchable for SharableRef<T> {
+    fn read(&self) -> String {
+        self.deref().to_string()
+    }
+
+    fn write(&mut self, value: &str) -> Result<(), WatchError> {
+        *self.deref_mut() = value.trim().parse().map_err(|_| WatchError::InvalidValue)?;
+        Ok(())
+    }
+}
+
+/// Implements Watchable for shared atomics, reading and writing them with relaxed ordering
+macro_rules! impl_watchable_for_atomic {
+    ($($atomic:ty => $value:ty),* $(,)?) => {
+        $(
+            impl Watchable for Arc<$atomic> {
+                fn read(&self) -> String {
+                    self.load(Ordering::Relaxed).to_string()
+                }
+
+                fn write(&mut self, value: &str) -> Result<(), WatchError> {
+                    let value: $value = value.trim().parse().map_err(|_| WatchError::InvalidValue)?;
+                    self.store(value, Ordering::Relaxed);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_watchable_for_atomic! {
+    AtomicBool => bool,
+    AtomicI32 => i32,
+    AtomicU32 => u32,
+    AtomicU8 => u8,
+}
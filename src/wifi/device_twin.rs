@@ -0,0 +1,267 @@
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+/// Keys where the cloud services put the desired properties, in the order they are looked for
+const DESIRED_KEYS: [&str; 2] = ["state", "desired"];
+/// Keys where the cloud services put the version of the document
+const VERSION_KEYS: [&str; 2] = ["version", "$version"];
+
+type DesiredCallback<'a> = dyn FnMut(&Value) + 'a;
+
+/// Enums the different errors possible when working with the DeviceTwin
+#[derive(Debug)]
+pub enum DeviceTwinError {
+    InvalidDocument,
+}
+
+/// Local copy of the desired and reported properties of a device, as kept by cloud services
+/// such as the Azure device twins or the AWS device shadows. The cloud sets the desired
+/// properties (configuration) and the device answers with its reported properties (state).
+///
+/// The twin does not depend on a transport: desired documents received, for example on an MQTT
+/// topic, are passed to [Self::handle_desired], and the reported properties are sent through a
+/// closure with [Self::publish_reported].
+pub struct DeviceTwin<'a> {
+    desired: Map<String, Value>,
+    reported: Map<String, Value>,
+    pending_report: Map<String, Value>,
+    version: Option<u64>,
+    acknowledge_desired: bool,
+    callbacks: HashMap<String, Box<DesiredCallback<'a>>>,
+    any_change_callback: Option<Box<dyn FnMut(&str, &Value) + 'a>>,
+}
+
+impl<'a> Default for DeviceTwin<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> DeviceTwin<'a> {
+    /// Creates a new DeviceTwin without properties
+    ///
+    /// # Returns
+    ///
+    /// The new DeviceTwin
+    pub fn new() -> Self {
+        DeviceTwin {
+            desired: Map::new(),
+            reported: Map::new(),
+            pending_report: Map::new(),
+            version: None,
+            acknowledge_desired: false,
+            callbacks: HashMap::new(),
+            any_change_callback: None,
+        }
+    }
+
+    /// Sets whether every accepted desired property is also reported with the same value, so the
+    /// cloud knows the configuration was applied.
+    ///
+    /// # Arguments
+    ///
+    /// - `acknowledge`: True to report the accepted desired properties
+    pub fn acknowledge_desired(&mut self, acknowledge: bool) {
+        self.acknowledge_desired = acknowledge;
+    }
+
+    /// Sets the callback executed when the cloud changes a desired property
+    ///
+    /// # Arguments
+    ///
+    /// - `property`: The name of the top level property
+    /// - `callback`: A closure that receives the new value, Null if the property was removed
+    pub fn on_desired_change<C: FnMut(&Value) + 'a>(&mut self, property: &str, callback: C) {
+        self.callbacks
+            .insert(property.to_string(), Box::new(callback));
+    }
+
+    /// Sets the callback executed when the cloud changes any desired property, after the
+    /// callback of the property itself.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the name of the property and its new value
+    pub fn on_any_desired_change<C: FnMut(&str, &Value) + 'a>(&mut self, callback: C) {
+        self.any_change_callback = Some(Box::new(callback));
+    }
+
+    /// Applies a desired document received from the cloud, executing the callbacks of the
+    /// properties that changed. The document can be the properties themselves, or have them
+    /// under a "state" or "desired" key. Documents with a version older than the last one
+    /// applied are ignored. Properties set to null are removed.
+    ///
+    /// # Arguments
+    ///
+    /// - `payload`: The JSON document
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the names of the properties that changed, or a `DeviceTwinError` if it
+    /// fails.
+    ///
+    /// # Errors
+    ///
+    /// - `DeviceTwinError::InvalidDocument`: If the payload is not a JSON object
+    pub fn handle_desired(&mut self, payload: &[u8]) -> Result<Vec<String>, DeviceTwinError> {
+        let document: Value =
+            serde_json::from_slice(payload).map_err(|_| DeviceTwinError::InvalidDocument)?;
+        let Value::Object(mut document) = document else {
+            return Err(DeviceTwinError::InvalidDocument);
+        };
+
+        let version = VERSION_KEYS
+            .iter()
+            .find_map(|key| document.remove(*key))
+            .and_then(|version| version.as_u64());
+        if let (Some(version), Some(current)) = (version, self.version) {
+            if version <= current {
+                return Ok(vec![]);
+            }
+        }
+        self.version = version.or(self.version);
+
+        let properties = match DESIRED_KEYS.iter().find_map(|key| document.remove(*key)) {
+            Some(Value::Object(properties)) => properties,
+            Some(_) => return Err(DeviceTwinError::InvalidDocument),
+            None => document,
+        };
+
+        let mut changed = vec![];
+        for (property, value) in properties {
+            let previous = self.desired.get(&property).cloned().unwrap_or(Value::Null);
+            let mut updated = previous.clone();
+            merge(&mut updated, value);
+            if updated == previous {
+                continue;
+            }
+            if updated.is_null() {
+                self.desired.remove(&property);
+            } else {
+                self.desired.insert(property.clone(), updated.clone());
+            }
+            if self.acknowledge_desired {
+                self.report(&property, updated.clone());
+            }
+            if let Some(callback) = self.callbacks.get_mut(&property) {
+                callback(&updated);
+            }
+            if let Some(callback) = self.any_change_callback.as_mut() {
+                callback(&property, &updated);
+            }
+            changed.push(property);
+        }
+        Ok(changed)
+    }
+
+    /// Gets the current value of a desired property
+    ///
+    /// # Arguments
+    ///
+    /// - `property`: The name of the top level property
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the value, or None if the cloud did not set it
+    pub fn desired(&self, property: &str) -> Option<&Value> {
+        self.desired.get(property)
+    }
+
+    /// Gets the version of the last desired document applied
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the version, or None if no document had one
+    pub fn version(&self) -> Option<u64> {
+        self.version
+    }
+
+    /// Sets a reported property. It is sent on the next call to [Self::publish_reported], only
+    /// if its value changed.
+    ///
+    /// # Arguments
+    ///
+    /// - `property`: The name of the top level property
+    /// - `value`: The new value, Null to remove it
+    pub fn report<V: Into<Value>>(&mut self, property: &str, value: V) {
+        let value = value.into();
+        if self.reported.get(property).unwrap_or(&Value::Null) == &value {
+            return;
+        }
+        if value.is_null() {
+            self.reported.remove(property);
+        } else {
+            self.reported.insert(property.to_string(), value.clone());
+        }
+        self.pending_report.insert(property.to_string(), value);
+    }
+
+    /// Gets the current value of a reported property
+    ///
+    /// # Arguments
+    ///
+    /// - `property`: The name of the top level property
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the value, or None if it was not reported
+    pub fn reported(&self, property: &str) -> Option<&Value> {
+        self.reported.get(property)
+    }
+
+    /// Sends the reported properties that changed since the last publication, as a JSON object
+    /// with only those properties. Nothing is sent if no property changed. If the closure fails,
+    /// the properties are sent again on the next call.
+    ///
+    /// # Arguments
+    ///
+    /// - `publish`: A closure that sends the JSON document, for example to the MQTT topic of the
+    ///   reported properties
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with true if a document was sent, or the error of the closure.
+    pub fn publish_reported<E, F: FnOnce(&[u8]) -> Result<(), E>>(
+        &mut self,
+        publish: F,
+    ) -> Result<bool, E> {
+        if self.pending_report.is_empty() {
+            return Ok(false);
+        }
+        let document = Value::Object(self.pending_report.clone()).to_string();
+        publish(document.as_bytes())?;
+        self.pending_report.clear();
+        Ok(true)
+    }
+
+    /// Gets every reported property as a JSON document, for example to send the whole state
+    /// after reconnecting.
+    ///
+    /// # Returns
+    ///
+    /// The JSON document
+    pub fn reported_document(&self) -> String {
+        Value::Object(self.reported.clone()).to_string()
+    }
+}
+
+/// Merges a patch into a value as defined by JSON Merge Patch: objects are merged recursively,
+/// null removes a key, and any other value replaces the previous one.
+fn merge(target: &mut Value, patch: Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch;
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(&key);
+            } else {
+                merge(target.entry(key).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use super::WifiError;
+use esp_idf_svc::sys::{
+    esp_eap_client_clear_ca_cert, esp_eap_client_clear_certificate_and_key,
+    esp_eap_client_set_ca_cert, esp_eap_client_set_certificate_and_key,
+    esp_eap_client_set_identity, esp_eap_client_set_password, esp_eap_client_set_username,
+    esp_wifi_sta_enterprise_disable, esp_wifi_sta_enterprise_enable, ESP_OK,
+};
+use std::ffi::c_int;
+
+/// Credentials to join a WPA2-Enterprise network, such as eduroam, which authenticates each user
+/// through a RADIUS server with EAP instead of a shared password:
+/// - `identity`: The outer identity, sent unencrypted before the tunnel is established. Networks
+///   usually accept an anonymous one, such as `anonymous@university.edu`.
+/// - `username`: The username of the account, sent inside the tunnel.
+/// - `password`: The password of the account, sent inside the tunnel.
+/// - `ca_certificate`: The PEM certificate of the authority that signed the certificate of the
+///   server. If it is not set, the server is not verified.
+/// - `client_certificate`: The PEM certificate and private key of the device, for networks that use
+///   EAP-TLS.
+#[derive(Debug, Clone)]
+pub struct EnterpriseCredentials {
+    identity: String,
+    username: String,
+    password: String,
+    ca_certificate: Option<Vec<u8>>,
+    client_certificate: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl EnterpriseCredentials {
+    /// Creates the credentials of an account of a PEAP or TTLS network, without verifying the
+    /// server.
+    ///
+    /// # Arguments
+    ///
+    /// - `identity`: The outer identity
+    /// - `username`: The username of the account
+    /// - `password`: The password of the account
+    ///
+    /// # Returns
+    ///
+    /// The new EnterpriseCredentials
+    pub fn new(identity: &str, username: &str, password: &str) -> Self {
+        EnterpriseCredentials {
+            identity: identity.to_string(),
+            username: username.to_string(),
+            password: password.to_string(),
+            ca_certificate: None,
+            client_certificate: None,
+        }
+    }
+
+    /// Sets the certificate of the authority used to verify the server, which prevents the
+    /// credentials from being sent to a rogue access point with the same ssid.
+    ///
+    /// # Arguments
+    ///
+    /// - `pem`: The PEM encoded certificate
+    ///
+    /// # Returns
+    ///
+    /// The EnterpriseCredentials itself
+    pub fn with_ca_certificate(mut self, pem: &[u8]) -> Self {
+        self.ca_certificate = Some(null_terminated(pem));
+        self
+    }
+
+    /// Sets the certificate and private key of the device, for networks that authenticate the
+    /// devices with EAP-TLS instead of a password.
+    ///
+    /// # Arguments
+    ///
+    /// - `certificate_pem`: The PEM encoded certificate of the device
+    /// - `private_key_pem`: The PEM encoded private key of the certificate, not encrypted
+    ///
+    /// # Returns
+    ///
+    /// The EnterpriseCredentials itself
+    pub fn with_client_certificate(
+        mut self,
+        certificate_pem: &[u8],
+        private_key_pem: &[u8],
+    ) -> Self {
+        self.client_certificate = Some((
+            null_terminated(certificate_pem),
+            null_terminated(private_key_pem),
+        ));
+        self
+    }
+
+    /// Sets the credentials on the wifi driver and enables the enterprise authentication. The
+    /// driver keeps pointers to the certificates, so the credentials must outlive the connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the credentials were set, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If the driver rejects any of the credentials
+    pub(crate) fn apply(&self) -> Result<(), WifiError> {
+        check(unsafe {
+            esp_eap_client_set_identity(self.identity.as_ptr(), self.identity.len() as c_int)
+        })?;
+        check(unsafe {
+            esp_eap_client_set_username(self.username.as_ptr(), self.username.len() as c_int)
+        })?;
+        check(unsafe {
+            esp_eap_client_set_password(self.password.as_ptr(), self.password.len() as c_int)
+        })?;
+        match &self.ca_certificate {
+            Some(pem) => {
+                check(unsafe { esp_eap_client_set_ca_cert(pem.as_ptr(), pem.len() as c_int) })?
+            }
+            None => unsafe { esp_eap_client_clear_ca_cert() },
+        }
+        match &self.client_certificate {
+            Some((certificate, key)) => check(unsafe {
+                esp_eap_client_set_certificate_and_key(
+                    certificate.as_ptr(),
+                    certificate.len() as c_int,
+                    key.as_ptr(),
+                    key.len() as c_int,
+                    std::ptr::null(),
+                    0,
+                )
+            })?,
+            None => unsafe { esp_eap_client_clear_certificate_and_key() },
+        }
+        check(unsafe { esp_wifi_sta_enterprise_enable() })
+    }
+}
+
+/// Disables the enterprise authentication, so the driver can join networks with a shared password.
+pub(crate) fn disable_enterprise() {
+    unsafe { esp_wifi_sta_enterprise_disable() };
+}
+
+/// Maps an error code of the driver to a `WifiError::ConfigurationError`
+fn check(code: i32) -> Result<(), WifiError> {
+    match code {
+        ESP_OK => Ok(()),
+        _ => Err(WifiError::ConfigurationError),
+    }
+}
+
+/// Copies a PEM, adding the null terminator mbedtls requires to parse it
+fn null_terminated(pem: &[u8]) -> Vec<u8> {
+    let mut pem = pem.to_vec();
+    if pem.last() != Some(&0) {
+        pem.push(0);
+    }
+    pem
+}
@@ -1,10 +1,30 @@
-use esp_idf_svc::http::{
-    client::{Configuration, EspHttpConnection},
-    Method,
+use super::{OfflineBuffer, OfflineBufferError, TlsOptions};
+use esp_idf_svc::{
+    http::client::{Configuration, EspHttpConnection},
+    sys::{esp_crt_bundle_attach, esp_tls_set_global_ca_store, ESP_OK},
 };
+use std::{
+    future::poll_fn,
+    sync::{Arc, Mutex},
+    task::{Poll, Waker},
+    thread,
+    time::Duration,
+};
+
+pub use esp_idf_svc::http::Method;
+
+const DRAIN_BUFFER_SIZE: usize = 64;
+const READ_CHUNK_SIZE: usize = 512;
+const HTTP_TIMEOUT_CODE: i32 = -0x7007;
+/// HTTPS handshakes need a bigger stack than the default of the spawned threads
+const REQUEST_THREAD_STACK_SIZE: usize = 10 * 1024;
+
+/// Outcome of a request running on another thread, with the waker of the task awaiting it
+type PendingResponse = Arc<Mutex<(Option<Result<HttpResponse, HttpError>>, Option<Waker>)>>;
 
 #[derive(Debug)]
 pub enum HttpError {
+    BufferError(OfflineBufferError),
     InizializationError,
     ListeningError,
     ReadError,
@@ -73,7 +93,6 @@ pub trait Http {
             .initiate_request(method, uri, &temp)
             .map_err(|_| HttpError::RequestError)?;
         if let Some(body_content) = body {
-            println!("{:?}", body_content.clone());
             connection
                 .write_all(body_content.as_bytes())
                 .map_err(|_| HttpError::RequestError)?;
@@ -81,6 +100,32 @@ pub trait Http {
         Ok(())
     }
 
+    /// Sends an HttpRequest and reads the whole response, including the ones sent in chunks.
+    ///
+    /// # Arguments
+    ///
+    /// - `request`: The HttpRequest to send.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the HttpResponse, or an `HttpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpError::RequestError`: If an error occurs in while creating or sending the request.
+    /// - `HttpError::ListeningError`: If initiating the response phase fails.
+    /// - `HttpError::TimeoutError`: If there is a timeout waiting for the response.
+    /// - `HttpError::ReadError`: If the reading operation fails.
+    fn request(&mut self, request: HttpRequest) -> Result<HttpResponse, HttpError> {
+        self.send_request(request.method, &request.uri, request.headers, request.body)?;
+        let mut body = vec![];
+        self.read_response(|chunk| body.extend_from_slice(chunk))?;
+        Ok(HttpResponse {
+            status: self.response_status(),
+            body,
+        })
+    }
+
     /// Does an HTTP POST on the desired uri with the designated headers
     ///
     /// # Arguments
@@ -105,6 +150,75 @@ pub trait Http {
         self.send_request(Method::Post, uri, headers, body)
     }
 
+    /// Does an HTTP POST and waits for a successful (2xx) status, discarding the response body.
+    ///
+    /// # Arguments
+    ///
+    /// - `uri`: A string slice that holds the URI of the target resource.
+    /// - `body`: The body of the request.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the server accepted the request, or an `HttpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpError::RequestError`: If the request fails or the status is not successful.
+    /// - `HttpError::ListeningError`: If initiating the response phase fails.
+    fn post_and_confirm(&mut self, uri: &str, body: String) -> Result<(), HttpError> {
+        self.send_request(Method::Post, uri, vec![], Some(body))?;
+        let connection = self.get_connection();
+        connection
+            .initiate_response()
+            .map_err(|_| HttpError::ListeningError)?;
+        if !(200..300).contains(&connection.status()) {
+            return Err(HttpError::RequestError);
+        }
+        let mut buffer = [0; DRAIN_BUFFER_SIZE];
+        while matches!(connection.read(&mut buffer), Ok(read) if read > 0) {}
+        Ok(())
+    }
+
+    /// Does an HTTP POST, queuing the body in an OfflineBuffer if it can not be delivered. The
+    /// bodies queued in the buffer are sent first, in order, so no data is lost nor reordered
+    /// while the network is down. The topic of the queued messages is their uri.
+    ///
+    /// # Arguments
+    ///
+    /// - `buffer`: The OfflineBuffer holding the bodies not delivered yet.
+    /// - `uri`: A string slice that holds the URI of the target resource.
+    /// - `body`: The body of the request.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with true if the body was delivered, false if it was queued, or an `HttpError`
+    /// if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpError::BufferError`: If the body could not be queued.
+    fn post_buffered(
+        &mut self,
+        buffer: &mut OfflineBuffer,
+        uri: &str,
+        body: String,
+    ) -> Result<bool, HttpError> {
+        let delivered = buffer
+            .replay(|message| {
+                let queued_body = String::from_utf8_lossy(&message.payload).into_owned();
+                self.post_and_confirm(&message.topic, queued_body)
+            })
+            .is_ok()
+            && self.post_and_confirm(uri, body.clone()).is_ok();
+
+        if !delivered {
+            buffer
+                .push(uri, body.as_bytes())
+                .map_err(HttpError::BufferError)?;
+        }
+        Ok(delivered)
+    }
+
     /// Does an HTTP GET on the desired uri with the designated headers
     ///
     /// # Arguments
@@ -256,6 +370,59 @@ pub trait Http {
         self.get_connection().status_message()
     }
 
+    /// Gets the value of a header of the response of the last done request
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the header, such as `Content-Type`
+    ///
+    /// # Returns
+    ///
+    /// An Option. A Some with an &str if the response has the header. Otherwise a None.
+    fn response_header(&mut self, name: &str) -> Option<&str> {
+        self.get_connection().header(name)
+    }
+
+    /// Blocking read of the whole request response, handing each piece of the body to a callback as
+    /// it arrives. Responses sent in chunks are read until the last chunk, so bodies bigger than the
+    /// memory of the device can be processed, for example written to a file.
+    ///
+    /// # Arguments
+    ///
+    /// - `on_chunk`: A closure called with each piece of the body
+    ///
+    /// # Returns
+    ///
+    /// A Result. An Ok with an usize representing the total bytes read if operation was succesful.
+    /// Otherwise an `HttpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpError::ListeningError`: If initiating the response phase fails.
+    /// - `HttpError::TimeoutError`: If there is a timeout waiting for the response.
+    /// - `HttpError::ReadError`: If the reading operation fails.
+    fn read_response<F: FnMut(&[u8])>(&mut self, mut on_chunk: F) -> Result<usize, HttpError> {
+        let connection = self.get_connection();
+        connection
+            .initiate_response()
+            .map_err(|_| HttpError::ListeningError)?;
+        let mut buffer = [0; READ_CHUNK_SIZE];
+        let mut total = 0;
+        loop {
+            let read = connection
+                .read(&mut buffer)
+                .map_err(|err| match err.code() {
+                    HTTP_TIMEOUT_CODE => HttpError::TimeoutError,
+                    _ => HttpError::ReadError,
+                })?;
+            if read == 0 {
+                return Ok(total);
+            }
+            on_chunk(&buffer[..read]);
+            total += read;
+        }
+    }
+
     /// Blocking wait of the request response
     ///
     /// # Arguments
@@ -279,7 +446,7 @@ pub trait Http {
         self.get_connection()
             .read(buffer)
             .map_err(|err| match err.code() {
-                -0x7007 => HttpError::TimeoutError,
+                HTTP_TIMEOUT_CODE => HttpError::TimeoutError,
                 _ => HttpError::ReadError,
             })
     }
@@ -288,6 +455,50 @@ pub trait Http {
 /// Abstraction to simply make HTTP request as a client
 pub struct HttpClient {
     connection: EspHttpConnection,
+    timeout: Option<Duration>,
+}
+
+impl HttpClient {
+    /// Creates a new HttpClient whose requests fail if the server does not answer in time
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: The maximum time to wait for each network operation
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `HttpClient` instance, or an `HttpError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpError::InizializationError`: If the creation of the Http connection fails
+    pub fn with_timeout(timeout: Duration) -> Result<Self, HttpError> {
+        Self::from_timeout(Some(timeout))
+    }
+
+    fn from_timeout(timeout: Option<Duration>) -> Result<Self, HttpError> {
+        let config = Configuration {
+            timeout,
+            ..Default::default()
+        };
+        let connection =
+            EspHttpConnection::new(&config).map_err(|_| HttpError::InizializationError)?;
+        Ok(HttpClient {
+            connection,
+            timeout,
+        })
+    }
+
+    /// Async version of [Http::request]. The request is done with a new connection on another
+    /// thread, so the other tasks and the drivers keep running while waiting for the server.
+    pub async fn request_async(
+        &self,
+        request: HttpRequest<'static>,
+    ) -> Result<HttpResponse, HttpError> {
+        let timeout = self.timeout;
+        request_on_thread(move || Self::from_timeout(timeout), request).await
+    }
 }
 
 impl Http for HttpClient {
@@ -302,10 +513,7 @@ impl Http for HttpClient {
     ///
     /// - `HttpError::InizializationError`: If the creation of the Http connection fails
     fn new() -> Result<Self, HttpError> {
-        let config: &Configuration = &Default::default();
-        let connection =
-            EspHttpConnection::new(config).map_err(|_| HttpError::InizializationError)?;
-        Ok(HttpClient { connection })
+        Self::from_timeout(None)
     }
 
     /// Gets the EspHttpConnection
@@ -318,9 +526,86 @@ impl Http for HttpClient {
     }
 }
 
-/// Abstraction to simply make HTTPS request as a client
+/// Abstraction to simply make HTTPS request as a client. By default servers are verified with the
+/// certificate bundle, other TlsOptions can be used with [HttpsClient::with_tls].
 pub struct HttpsClient {
     connection: EspHttpConnection,
+    timeout: Option<Duration>,
+    tls: TlsOptions,
+}
+
+impl HttpsClient {
+    /// Creates a new HttpsClient whose requests fail if the server does not answer in time
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: The maximum time to wait for each network operation
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `HttpsClient` instance, or an `HttpError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpError::InizializationError`: If the creation of the Http connection fails
+    pub fn with_timeout(timeout: Duration) -> Result<Self, HttpError> {
+        Self::with_tls(TlsOptions::new(), Some(timeout))
+    }
+
+    /// Creates a new HttpsClient that verifies the servers and authenticates the device with
+    /// the given TlsOptions. The authority certificate is set on the global store shared by
+    /// every HttpsClient, so the last one set is the one used. The name of the server is always
+    /// the host of the uri, the one set on the TlsOptions is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// - `tls`: The TlsOptions of the connections
+    /// - `timeout`: An `Option<Duration>` with the maximum time to wait for each network operation
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `HttpsClient` instance, or an `HttpError` if the
+    /// initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpError::InizializationError`: If the creation of the Http connection fails, or the
+    ///   authority certificate is not valid
+    pub fn with_tls(tls: TlsOptions, timeout: Option<Duration>) -> Result<Self, HttpError> {
+        if let Some(pem) = tls.ca_certificate_pem() {
+            if unsafe { esp_tls_set_global_ca_store(pem.as_ptr(), pem.len() as u32) } != ESP_OK {
+                return Err(HttpError::InizializationError);
+            }
+        }
+        let config = Configuration {
+            use_global_ca_store: true,
+            crt_bundle_attach: tls
+                .uses_certificate_bundle()
+                .then_some(esp_crt_bundle_attach as _),
+            client_certificate: tls.client_certificate(),
+            private_key: tls.private_key(),
+            timeout,
+            ..Default::default()
+        };
+        let connection =
+            EspHttpConnection::new(&config).map_err(|_| HttpError::InizializationError)?;
+        Ok(HttpsClient {
+            connection,
+            timeout,
+            tls,
+        })
+    }
+
+    /// Async version of [Http::request]. The request is done with a new connection on another
+    /// thread, so the other tasks and the drivers keep running while waiting for the server.
+    pub async fn request_async(
+        &self,
+        request: HttpRequest<'static>,
+    ) -> Result<HttpResponse, HttpError> {
+        let (tls, timeout) = (self.tls.clone(), self.timeout);
+        request_on_thread(move || Self::with_tls(tls, timeout), request).await
+    }
 }
 
 impl Http for HttpsClient {
@@ -338,14 +623,7 @@ impl Http for HttpsClient {
     where
         Self: Sized,
     {
-        let config: &Configuration = &Configuration {
-            use_global_ca_store: true,
-            crt_bundle_attach: Some(esp_idf_svc::sys::esp_crt_bundle_attach),
-            ..Default::default()
-        };
-        let connection =
-            EspHttpConnection::new(config).map_err(|_| HttpError::InizializationError)?;
-        Ok(HttpsClient { connection })
+        Self::with_tls(TlsOptions::new(), None)
     }
 
     /// Gets the EspHttpConnection
@@ -358,6 +636,179 @@ impl Http for HttpsClient {
     }
 }
 
+/// Sends a request with a new client created on another thread, waking the awaiting task once the
+/// whole response is read.
+///
+/// # Arguments
+///
+/// - `new_client`: Creates the client on the thread, since connections can not be moved between
+///   threads.
+/// - `request`: The HttpRequest to send.
+///
+/// # Returns
+///
+/// A `Result` with the HttpResponse, or an `HttpError` if it fails.
+///
+/// # Errors
+///
+/// - `HttpError::InizializationError`: If the thread or the client could not be created.
+/// - The errors of [Http::request]
+async fn request_on_thread<H, F>(
+    new_client: F,
+    request: HttpRequest<'static>,
+) -> Result<HttpResponse, HttpError>
+where
+    H: Http,
+    F: FnOnce() -> Result<H, HttpError> + Send + 'static,
+{
+    let pending: PendingResponse = Arc::new(Mutex::new((None, None)));
+    let thread_pending = pending.clone();
+    thread::Builder::new()
+        .stack_size(REQUEST_THREAD_STACK_SIZE)
+        .spawn(move || {
+            let response = new_client().and_then(|mut client| client.request(request));
+            let mut pending = thread_pending.lock().unwrap_or_else(|err| err.into_inner());
+            pending.0 = Some(response);
+            if let Some(waker) = pending.1.take() {
+                waker.wake();
+            }
+        })
+        .map_err(|_| HttpError::InizializationError)?;
+
+    poll_fn(|cx| {
+        let mut pending = pending.lock().unwrap_or_else(|err| err.into_inner());
+        match pending.0.take() {
+            Some(response) => Poll::Ready(response),
+            None => {
+                pending.1 = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    })
+    .await
+}
+
+/// A request to send with [Http::request], built with one of its method constructors:
+/// - `method`: The HTTP method of the request.
+/// - `uri`: The URI of the target resource.
+/// - `headers`: The headers of the request.
+/// - `body`: The body of the request, if any.
+#[derive(Debug)]
+pub struct HttpRequest<'a> {
+    method: Method,
+    uri: String,
+    headers: Vec<HttpHeader<'a>>,
+    body: Option<String>,
+}
+
+impl<'a> HttpRequest<'a> {
+    /// Creates a GET request, without headers
+    ///
+    /// # Arguments
+    ///
+    /// - `uri`: The URI of the target resource
+    ///
+    /// # Returns
+    ///
+    /// The new HttpRequest instance
+    pub fn get(uri: &str) -> Self {
+        Self::new(Method::Get, uri, None)
+    }
+
+    /// Creates a POST request, without headers
+    ///
+    /// # Arguments
+    ///
+    /// - `uri`: The URI of the target resource
+    /// - `body`: The body of the request
+    ///
+    /// # Returns
+    ///
+    /// The new HttpRequest instance
+    pub fn post(uri: &str, body: String) -> Self {
+        Self::new(Method::Post, uri, Some(body))
+    }
+
+    /// Creates a PUT request, without headers
+    ///
+    /// # Arguments
+    ///
+    /// - `uri`: The URI of the target resource
+    /// - `body`: The body of the request
+    ///
+    /// # Returns
+    ///
+    /// The new HttpRequest instance
+    pub fn put(uri: &str, body: String) -> Self {
+        Self::new(Method::Put, uri, Some(body))
+    }
+
+    /// Creates a DELETE request, without headers
+    ///
+    /// # Arguments
+    ///
+    /// - `uri`: The URI of the target resource
+    ///
+    /// # Returns
+    ///
+    /// The new HttpRequest instance
+    pub fn delete(uri: &str) -> Self {
+        Self::new(Method::Delete, uri, None)
+    }
+
+    fn new(method: Method, uri: &str, body: Option<String>) -> Self {
+        HttpRequest {
+            method,
+            uri: uri.to_string(),
+            headers: vec![],
+            body,
+        }
+    }
+
+    /// Adds a header to the request
+    ///
+    /// # Arguments
+    ///
+    /// - `header`: The HttpHeader to add
+    ///
+    /// # Returns
+    ///
+    /// The HttpRequest itself
+    pub fn with_header(mut self, header: HttpHeader<'a>) -> Self {
+        self.headers.push(header);
+        self
+    }
+}
+
+/// The whole response to an HttpRequest:
+/// - `status`: The status code of the response.
+/// - `body`: The body of the response, with every chunk joined.
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    /// Checks if the status of the response is successful (2xx)
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the request succeeded
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// Gets the body of the response as text
+    ///
+    /// # Returns
+    ///
+    /// An Option. A Some with an &str if the body is valid UTF-8. Otherwise a None.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+}
+
 /// Simple abstraction of a header used for HTTP/HTTPS requests. It contains:
 /// - `header_type`: The tyep of header to be used
 /// - `value`: The value associated to the header
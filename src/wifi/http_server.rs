@@ -0,0 +1,482 @@
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        notification::Notifier,
+    },
+    InterruptDriver,
+};
+use esp_idf_svc::{
+    http::{
+        server::{Configuration, EspHttpConnection, EspHttpServer, Request},
+        Method,
+    },
+    io::{Read, Write},
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+const MAX_BODY_SIZE: usize = 8 * 1024;
+const READ_CHUNK_SIZE: usize = 512;
+/// Time the server waits for the update loop to answer a request
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(5);
+const STATUS_PAYLOAD_TOO_LARGE: u16 = 413;
+const STATUS_SERVICE_UNAVAILABLE: u16 = 503;
+
+type RouteHandler<'a> = dyn FnMut(&ServerRequest) -> ServerResponse + 'a;
+
+/// Enums the different errors possible when working with an HttpServer
+#[derive(Debug)]
+pub enum HttpServerError {
+    InvalidRoute,
+    NotStarted,
+    StartingError,
+}
+
+/// A request received by the HttpServer:
+/// - `method`: The HTTP method of the request.
+/// - `path`: The path of the request, without the query.
+/// - `query`: The parameters of the query of the request, decoded.
+/// - `body`: The body of the request.
+#[derive(Debug, Clone)]
+pub struct ServerRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// The response to a ServerRequest:
+/// - `status`: The status code of the response.
+/// - `content_type`: The type of the body, sent in the `Content-Type` header.
+/// - `body`: The body of the response.
+#[derive(Debug, Clone)]
+pub struct ServerResponse {
+    pub status: u16,
+    pub content_type: String,
+    pub body: Vec<u8>,
+}
+
+/// A request waiting for the update loop to answer it, with the route that matched it
+struct PendingRequest {
+    route: usize,
+    request: ServerRequest,
+    response: Arc<(Mutex<Option<ServerResponse>>, Condvar)>,
+}
+
+/// Requests received by the server task, waiting to be answered by the update loop
+type RequestQueue = Arc<Mutex<VecDeque<PendingRequest>>>;
+
+/// Embedded HTTP server. Requests are received on a task of their own, and answered by the handler
+/// of their route in the update loop of the microcontroller, so handlers can use any other
+/// driver, such as pins or a BleServer.
+struct _HttpServer<'a> {
+    server: Option<EspHttpServer<'static>>,
+    routes: Vec<Box<RouteHandler<'a>>>,
+    requests: RequestQueue,
+    notifier: Notifier,
+}
+
+/// Embedded HTTP server. Requests are received on a task of their own, and answered by the handler
+/// of their route in the update loop of the microcontroller, so handlers can use any other
+/// driver, such as pins or a BleServer.
+pub struct HttpServer<'a> {
+    inner: SharableRef<_HttpServer<'a>>,
+}
+
+impl ServerRequest {
+    /// Gets the value of a parameter of the query
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: The name of the parameter
+    ///
+    /// # Returns
+    ///
+    /// An Option. A Some with an &str if the query has the parameter. Otherwise a None.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Gets the body of the request as text
+    ///
+    /// # Returns
+    ///
+    /// An Option. A Some with an &str if the body is valid UTF-8. Otherwise a None.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.body).ok()
+    }
+}
+
+impl ServerResponse {
+    /// Creates a response with a plain text body and a 200 status
+    ///
+    /// # Arguments
+    ///
+    /// - `text`: The body of the response
+    ///
+    /// # Returns
+    ///
+    /// The new ServerResponse
+    pub fn text(text: &str) -> Self {
+        Self::new(200, "text/plain", text.as_bytes().to_vec())
+    }
+
+    /// Creates a response with a JSON body and a 200 status
+    ///
+    /// # Arguments
+    ///
+    /// - `json`: The body of the response
+    ///
+    /// # Returns
+    ///
+    /// The new ServerResponse
+    pub fn json(json: &str) -> Self {
+        Self::new(200, "application/json", json.as_bytes().to_vec())
+    }
+
+    /// Creates a response with an HTML body and a 200 status
+    ///
+    /// # Arguments
+    ///
+    /// - `html`: The body of the response
+    ///
+    /// # Returns
+    ///
+    /// The new ServerResponse
+    pub fn html(html: &str) -> Self {
+        Self::new(200, "text/html", html.as_bytes().to_vec())
+    }
+
+    /// Creates a response without body
+    ///
+    /// # Arguments
+    ///
+    /// - `status`: The status code of the response
+    ///
+    /// # Returns
+    ///
+    /// The new ServerResponse
+    pub fn empty(status: u16) -> Self {
+        Self::new(status, "text/plain", vec![])
+    }
+
+    /// Creates a response
+    ///
+    /// # Arguments
+    ///
+    /// - `status`: The status code of the response
+    /// - `content_type`: The type of the body
+    /// - `body`: The body of the response
+    ///
+    /// # Returns
+    ///
+    /// The new ServerResponse
+    pub fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        ServerResponse {
+            status,
+            content_type: content_type.to_string(),
+            body,
+        }
+    }
+
+    /// Changes the status code of the response
+    ///
+    /// # Arguments
+    ///
+    /// - `status`: The new status code
+    ///
+    /// # Returns
+    ///
+    /// The ServerResponse itself
+    pub fn with_status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _HttpServer<'a> {
+    /// Creates a new _HttpServer listening on the port
+    ///
+    /// # Arguments
+    ///
+    /// - `port`: The port to listen on
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a request is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new _HttpServer, or an `HttpServerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpServerError::StartingError`: If the server could not be started
+    fn new(port: u16, notifier: Notifier) -> Result<Self, HttpServerError> {
+        let config = Configuration {
+            http_port: port,
+            uri_match_wildcard: true,
+            ..Default::default()
+        };
+        Ok(_HttpServer {
+            server: Some(EspHttpServer::new(&config).map_err(|_| HttpServerError::StartingError)?),
+            routes: vec![],
+            requests: Arc::new(Mutex::new(VecDeque::new())),
+            notifier,
+        })
+    }
+
+    /// Sets the handler that answers the requests of a route. Paths can end with a `*` to match
+    /// every path that starts with them, such as `/files/*`. The requests with a body bigger than
+    /// 8KB are answered with a 413 status, and the ones not answered in 5 seconds with a 503 status.
+    ///
+    /// Note: For the handler to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `path`: The path of the route, such as `/led`
+    /// - `method`: The method of the route
+    /// - `handler`: A closure that receives each request of the route and returns its response
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the route was set, or an `HttpServerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpServerError::InvalidRoute`: If the route was already set, or could not be registered
+    /// - `HttpServerError::NotStarted`: If the server was stopped
+    pub fn on<C: FnMut(&ServerRequest) -> ServerResponse + 'a>(
+        &mut self,
+        path: &str,
+        method: Method,
+        handler: C,
+    ) -> Result<(), HttpServerError> {
+        let route = self.routes.len();
+        let requests = self.requests.clone();
+        let notifier = self.notifier.clone();
+        self.server
+            .as_mut()
+            .ok_or(HttpServerError::NotStarted)?
+            .fn_handler(path, method, move |req| {
+                handle_request(req, route, &requests, &notifier)
+            })
+            .map_err(|_| HttpServerError::InvalidRoute)?;
+        self.routes.push(Box::new(handler));
+        Ok(())
+    }
+
+    /// Stops the server, closing its connections. The pending requests are answered with a 503
+    /// status.
+    pub fn stop(&mut self) {
+        let pending_requests: Vec<PendingRequest> = self
+            .requests
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .drain(..)
+            .collect();
+        for pending in pending_requests {
+            respond(&pending, ServerResponse::empty(STATUS_SERVICE_UNAVAILABLE));
+        }
+        self.server = None;
+    }
+
+    /// Checks if the server is listening
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the server is started or not.
+    pub fn is_started(&self) -> bool {
+        self.server.is_some()
+    }
+
+    /// Answers every request received since the last call with the handler of its route
+    fn handle_requests(&mut self) {
+        loop {
+            let pending = self
+                .requests
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .pop_front();
+            let Some(pending) = pending else {
+                return;
+            };
+            let response = match self.routes.get_mut(pending.route) {
+                Some(handler) => handler(&pending.request),
+                None => ServerResponse::empty(STATUS_SERVICE_UNAVAILABLE),
+            };
+            respond(&pending, response);
+        }
+    }
+}
+
+impl<'a> HttpServer<'a> {
+    /// Creates a new HttpServer listening on the port
+    ///
+    /// # Arguments
+    ///
+    /// - `port`: The port to listen on
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a request is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new HttpServer, or an `HttpServerError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `HttpServerError::StartingError`: If the server could not be started
+    pub(crate) fn new(port: u16, notifier: Notifier) -> Result<Self, HttpServerError> {
+        Ok(Self {
+            inner: SharableRef::new_sharable(_HttpServer::new(port, notifier)?),
+        })
+    }
+}
+
+impl<'a> InterruptDriver<'a> for HttpServer<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().handle_requests();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// Stops the server
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.stop();
+        Ok(())
+    }
+}
+
+/// Sends the response of a pending request to the server task waiting for it
+fn respond(pending: &PendingRequest, response: ServerResponse) {
+    let (lock, answered) = &*pending.response;
+    *lock.lock().unwrap_or_else(|err| err.into_inner()) = Some(response);
+    answered.notify_all();
+}
+
+/// Handler executed by the server task for each request of a route. It reads the request, queues
+/// it for the update loop, and waits for its response.
+///
+/// # Arguments
+///
+/// - `req`: The request received
+/// - `route`: The index of the route that matched the request
+/// - `requests`: The queue of requests answered by the update loop
+/// - `notifier`: A notifier in order to wake up the [crate::Microcontroller]
+///
+/// # Returns
+///
+/// A `Result` with Ok if the response was sent, or the error of the connection if it fails.
+fn handle_request(
+    mut req: Request<&mut EspHttpConnection>,
+    route: usize,
+    requests: &RequestQueue,
+    notifier: &Notifier,
+) -> Result<(), esp_idf_svc::io::EspIOError> {
+    let (path, query) = match req.uri().split_once('?') {
+        Some((path, query)) => (path.to_string(), parse_query(query)),
+        None => (req.uri().to_string(), vec![]),
+    };
+    let method = req.method();
+
+    let mut body = vec![];
+    let mut buffer = [0; READ_CHUNK_SIZE];
+    loop {
+        let read = req.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if body.len() + read > MAX_BODY_SIZE {
+            req.into_status_response(STATUS_PAYLOAD_TOO_LARGE)?;
+            return Ok(());
+        }
+        body.extend_from_slice(&buffer[..read]);
+    }
+
+    let response = Arc::new((Mutex::new(None), Condvar::new()));
+    requests
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push_back(PendingRequest {
+            route,
+            request: ServerRequest {
+                method,
+                path,
+                query,
+                body,
+            },
+            response: response.clone(),
+        });
+    notifier.notify();
+
+    let (lock, answered) = &*response;
+    let (mut answer, _) = answered
+        .wait_timeout_while(
+            lock.lock().unwrap_or_else(|err| err.into_inner()),
+            RESPONSE_TIMEOUT,
+            |answer| answer.is_none(),
+        )
+        .unwrap_or_else(|err| err.into_inner());
+    let response = answer
+        .take()
+        .unwrap_or_else(|| ServerResponse::empty(STATUS_SERVICE_UNAVAILABLE));
+    drop(answer);
+
+    let mut connection = req.into_response(
+        response.status,
+        None,
+        &[("Content-Type", response.content_type.as_str())],
+    )?;
+    connection.write_all(&response.body)?;
+    Ok(())
+}
+
+/// Splits a query into its decoded parameters
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            (decode_query_component(key), decode_query_component(value))
+        })
+        .collect()
+}
+
+/// Decodes a component of a query, replacing the `+` with spaces and the `%XX` escapes with their
+/// bytes
+fn decode_query_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let escape = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match escape.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,175 @@
+use esp_idf_svc::mdns::EspMdns;
+
+const MAX_HOSTNAME_LEN: usize = 63;
+
+/// Enums the different errors possible when working with the mDNS responder
+#[derive(Debug)]
+pub enum MdnsError {
+    AlreadyTaken,
+    InvalidHostname,
+    ServiceError,
+    StartingError,
+}
+
+/// A service advertised by the responder, such as `_http._tcp` on port 80:
+/// - `service_type`: The type of the service, starting with an underscore, such as `_http`.
+/// - `protocol`: The protocol of the service, `_tcp` or `_udp`.
+/// - `port`: The port the service listens on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdnsService {
+    pub service_type: String,
+    pub protocol: String,
+    pub port: u16,
+}
+
+/// mDNS responder, which answers the queries of the devices on the local network, so phones and
+/// computers can find the device as `hostname.local`, and discover the services it offers, without
+/// knowing its ip address. It answers on every network the device is connected to, including the
+/// one it hosts as an access point.
+pub struct Mdns {
+    mdns: EspMdns,
+    services: Vec<MdnsService>,
+}
+
+impl Mdns {
+    /// Creates a new Mdns, which does not answer to any hostname until one is set.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the new `Mdns` instance, or a `MdnsError` if the initialization fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MdnsError::AlreadyTaken`: If the responder was already taken
+    pub(crate) fn new() -> Result<Self, MdnsError> {
+        Ok(Mdns {
+            mdns: EspMdns::take().map_err(|_| MdnsError::AlreadyTaken)?,
+            services: vec![],
+        })
+    }
+
+    /// Sets the hostname the device answers to, so it can be reached as `hostname.local`.
+    ///
+    /// # Arguments
+    ///
+    /// - `hostname`: A &str of at most 63 letters, digits and hyphens, without the `.local`
+    ///   suffix, which does not start nor end with a hyphen.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the hostname was set, or a `MdnsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MdnsError::InvalidHostname`: If the hostname is not valid
+    /// - `MdnsError::StartingError`: If the responder could not set the hostname
+    pub fn set_hostname(&mut self, hostname: &str) -> Result<(), MdnsError> {
+        let valid = !hostname.is_empty()
+            && hostname.len() <= MAX_HOSTNAME_LEN
+            && !hostname.starts_with('-')
+            && !hostname.ends_with('-')
+            && hostname
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return Err(MdnsError::InvalidHostname);
+        }
+        self.mdns
+            .set_hostname(hostname)
+            .map_err(|_| MdnsError::StartingError)
+    }
+
+    /// Sets the friendly name shown by the browsers of services, such as `Kitchen sensor`, for
+    /// the services that do not have their own.
+    ///
+    /// # Arguments
+    ///
+    /// - `name`: A &str with the name of the device
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the name was set, or a `MdnsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MdnsError::StartingError`: If the responder could not set the name
+    pub fn set_instance_name(&mut self, name: &str) -> Result<(), MdnsError> {
+        self.mdns
+            .set_instance_name(name)
+            .map_err(|_| MdnsError::StartingError)
+    }
+
+    /// Advertises a service of the device. If the service was already advertised, it is
+    /// replaced.
+    ///
+    /// # Arguments
+    ///
+    /// - `instance_name`: An `Option<&str>` with the friendly name of the service. If it is None,
+    ///   the instance name of the device is used.
+    /// - `service`: The `MdnsService` to advertise.
+    /// - `txt`: Key value pairs with extra information of the service, such as `("path", "/")`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the service is advertised, or a `MdnsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MdnsError::ServiceError`: If the service could not be added
+    pub fn add_service(
+        &mut self,
+        instance_name: Option<&str>,
+        service: MdnsService,
+        txt: &[(&str, &str)],
+    ) -> Result<(), MdnsError> {
+        if self.services.iter().any(|advertised| {
+            advertised.service_type == service.service_type
+                && advertised.protocol == service.protocol
+        }) {
+            self.remove_service(&service.service_type, &service.protocol)?;
+        }
+        self.mdns
+            .add_service(
+                instance_name,
+                &service.service_type,
+                &service.protocol,
+                service.port,
+                txt,
+            )
+            .map_err(|_| MdnsError::ServiceError)?;
+        self.services.push(service);
+        Ok(())
+    }
+
+    /// Stops advertising a service of the device.
+    ///
+    /// # Arguments
+    ///
+    /// - `service_type`: The type of the service, such as `_http`
+    /// - `protocol`: The protocol of the service, `_tcp` or `_udp`
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the service is no longer advertised, or a `MdnsError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MdnsError::ServiceError`: If the service was not advertised
+    pub fn remove_service(&mut self, service_type: &str, protocol: &str) -> Result<(), MdnsError> {
+        self.mdns
+            .remove_service(service_type, protocol)
+            .map_err(|_| MdnsError::ServiceError)?;
+        self.services
+            .retain(|service| service.service_type != service_type || service.protocol != protocol);
+        Ok(())
+    }
+
+    /// Gets the services advertised by the device.
+    ///
+    /// # Returns
+    ///
+    /// A slice with the advertised services
+    pub fn services(&self) -> &[MdnsService] {
+        &self.services
+    }
+}
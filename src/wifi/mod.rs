@@ -1,4 +1,38 @@
+mod device_twin;
+mod enterprise;
 pub mod http;
+mod http_server;
+#[cfg(feature = "mdns")]
+mod mdns;
+mod mqtt;
+mod net_stream;
+mod offline_buffer;
+mod ota;
+mod remote_log;
+mod smart_config;
+mod sniffer;
+mod tcp;
+mod tls;
+mod udp;
+mod vendor_frames;
 mod wifi_driver;
+mod wifi_events;
 
+pub use device_twin::*;
+pub use enterprise::*;
+pub use http_server::*;
+#[cfg(feature = "mdns")]
+pub use mdns::*;
+pub use mqtt::*;
+pub use net_stream::*;
+pub use offline_buffer::*;
+pub use ota::*;
+pub use remote_log::*;
+pub use smart_config::*;
+pub use sniffer::*;
+pub use tcp::*;
+pub use tls::*;
+pub use udp::*;
+pub use vendor_frames::*;
 pub use wifi_driver::*;
+pub use wifi_events::*;
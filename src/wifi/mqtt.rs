@@ -0,0 +1,708 @@
+use super::TlsOptions;
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        notification::Notifier,
+        queue_config::{queue_capacities, record_dropped_item},
+    },
+    InterruptDriver,
+};
+use esp_idf_svc::{
+    mqtt::client::{
+        Details, EspMqttClient, EspMqttEvent, EventPayload, LwtConfiguration,
+        MqttClientConfiguration, QoS,
+    },
+    sys::esp_crt_bundle_attach,
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+type MessageCallback<'a> = dyn FnMut(&MqttMessage) + 'a;
+type ConnectionCallback<'a> = dyn FnMut(bool) + 'a;
+
+/// Events received by the MQTT task, waiting for the update loop
+type EventQueue = Arc<Mutex<VecDeque<MqttEvent>>>;
+
+/// Enums the different errors possible when working with an MqttClient
+#[derive(Debug)]
+pub enum MqttError {
+    ConnectionError,
+    Disconnected,
+    InvalidTopic,
+    PublishError,
+    SubscribeError,
+}
+
+/// Enums the delivery guarantees of a message:
+/// - `AtMostOnce`: The message is sent once, and lost if the connection fails (QoS 0).
+/// - `AtLeastOnce`: The message is sent until the broker acknowledges it, so it may arrive more
+///   than once (QoS 1).
+/// - `ExactlyOnce`: The message arrives exactly once, with a slower handshake (QoS 2).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MqttQos {
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+/// A message received on a subscribed topic:
+/// - `topic`: The topic the message was published on.
+/// - `payload`: The data of the message.
+#[derive(Debug, Clone)]
+pub struct MqttMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Settings of the connection to the broker:
+/// - `client_id`: The id of the client. If it is not set, one is made from the MAC address.
+/// - `credentials`: The username and password of the client, if the broker requires them.
+/// - `keep_alive`: The time between the pings that keep the connection alive.
+/// - `reconnect_delay`: The time waited before reconnecting when the connection drops.
+/// - `network_timeout`: The time waited for the broker before considering the connection lost.
+/// - `last_will`: The message the broker publishes if the connection is lost without a
+///   disconnection.
+/// - `tls`: The TlsOptions of `mqtts://` connections.
+#[derive(Debug, Clone, Default)]
+pub struct MqttOptions {
+    client_id: Option<String>,
+    credentials: Option<(String, String)>,
+    keep_alive: Option<Duration>,
+    reconnect_delay: Option<Duration>,
+    network_timeout: Option<Duration>,
+    last_will: Option<LastWill>,
+    tls: Option<TlsOptions>,
+}
+
+/// Message published by the broker on behalf of the client when its connection is lost, so other
+/// clients know the device went offline
+#[derive(Debug, Clone)]
+struct LastWill {
+    topic: String,
+    payload: Vec<u8>,
+    qos: MqttQos,
+    retain: bool,
+}
+
+/// Enums the events received from the broker
+enum MqttEvent {
+    Connected,
+    Disconnected,
+    Message(MqttMessage),
+}
+
+/// A topic filter the client is subscribed to, with the callback of its messages
+struct Subscription<'a> {
+    filter: String,
+    qos: MqttQos,
+    callback: Box<MessageCallback<'a>>,
+}
+
+/// MQTT client. It connects on its own task and reconnects whenever the connection drops,
+/// subscribing again to every topic. Messages are received through callbacks executed in the
+/// update loop of the microcontroller.
+struct _MqttClient<'a> {
+    client: Option<EspMqttClient<'static>>,
+    events: EventQueue,
+    connected: bool,
+    subscriptions: Vec<Subscription<'a>>,
+    connection_callback: Option<Box<ConnectionCallback<'a>>>,
+}
+
+/// MQTT client. It connects on its own task and reconnects whenever the connection drops,
+/// subscribing again to every topic. Messages are received through callbacks executed in the
+/// update loop of the microcontroller.
+pub struct MqttClient<'a> {
+    inner: SharableRef<_MqttClient<'a>>,
+}
+
+impl From<MqttQos> for QoS {
+    fn from(value: MqttQos) -> Self {
+        match value {
+            MqttQos::AtMostOnce => QoS::AtMostOnce,
+            MqttQos::AtLeastOnce => QoS::AtLeastOnce,
+            MqttQos::ExactlyOnce => QoS::ExactlyOnce,
+        }
+    }
+}
+
+impl MqttMessage {
+    /// Gets the payload of the message as text
+    ///
+    /// # Returns
+    ///
+    /// An Option. A Some with an &str if the payload is valid UTF-8. Otherwise a None.
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.payload).ok()
+    }
+}
+
+impl MqttOptions {
+    /// Creates MqttOptions with the client id, without credentials
+    ///
+    /// # Arguments
+    ///
+    /// - `client_id`: The id of the client, which must be unique on the broker
+    ///
+    /// # Returns
+    ///
+    /// The new MqttOptions
+    pub fn new(client_id: &str) -> Self {
+        MqttOptions {
+            client_id: Some(client_id.to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the username and password of the client
+    ///
+    /// # Arguments
+    ///
+    /// - `username`: The username of the client
+    /// - `password`: The password of the client
+    ///
+    /// # Returns
+    ///
+    /// The MqttOptions itself
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Sets the time between the pings that keep the connection alive. By default it is 120
+    /// seconds. The broker considers the client lost after one and a half times this time
+    /// without hearing from it, publishing its last will.
+    ///
+    /// # Arguments
+    ///
+    /// - `keep_alive`: The time between pings. A zero duration disables the pings.
+    ///
+    /// # Returns
+    ///
+    /// The MqttOptions itself
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Sets the time waited before reconnecting when the connection drops. By default it is 10
+    /// seconds.
+    ///
+    /// # Arguments
+    ///
+    /// - `reconnect_delay`: The time between reconnection attempts
+    ///
+    /// # Returns
+    ///
+    /// The MqttOptions itself
+    pub fn with_reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self.reconnect_delay = Some(reconnect_delay);
+        self
+    }
+
+    /// Sets the time waited for the broker to answer before considering the connection lost. By
+    /// default it is 10 seconds.
+    ///
+    /// # Arguments
+    ///
+    /// - `network_timeout`: The maximum time to wait for each network operation
+    ///
+    /// # Returns
+    ///
+    /// The MqttOptions itself
+    pub fn with_network_timeout(mut self, network_timeout: Duration) -> Self {
+        self.network_timeout = Some(network_timeout);
+        self
+    }
+
+    /// Sets the last will of the client, a message the broker publishes when the connection is
+    /// lost without calling [MqttClient::disconnect]. Usually it is a retained `offline` message
+    /// on a status topic, on which the client publishes `online` once connected.
+    ///
+    /// # Arguments
+    ///
+    /// - `topic`: The topic of the message, without wildcards
+    /// - `payload`: The data of the message
+    /// - `qos`: The delivery guarantee of the message
+    /// - `retain`: Whether the broker keeps the message for the clients that subscribe later
+    ///
+    /// # Returns
+    ///
+    /// The MqttOptions itself
+    pub fn with_last_will(
+        mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: MqttQos,
+        retain: bool,
+    ) -> Self {
+        self.last_will = Some(LastWill {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+            qos,
+            retain,
+        });
+        self
+    }
+
+    /// Sets the TlsOptions used to connect to the broker, when its url starts with `mqtts://`. By
+    /// default the broker is verified with the certificate bundle. The server name of the
+    /// TlsOptions is not used, the certificate of the broker is verified against the host of the
+    /// url.
+    ///
+    /// # Arguments
+    ///
+    /// - `tls`: The TlsOptions of the connection
+    ///
+    /// # Returns
+    ///
+    /// The MqttOptions itself
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _MqttClient<'a> {
+    /// Creates a new _MqttClient and starts connecting to the broker
+    ///
+    /// # Arguments
+    ///
+    /// - `broker_url`: The url of the broker, such as `mqtt://broker.local:1883`
+    /// - `options`: The MqttOptions of the connection
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when an event is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new _MqttClient, or an `MqttError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MqttError::ConnectionError`: If the client could not be created
+    /// - `MqttError::InvalidTopic`: If the topic of the last will is not valid
+    fn new(broker_url: &str, options: &MqttOptions, notifier: Notifier) -> Result<Self, MqttError> {
+        let events: EventQueue = Arc::new(Mutex::new(VecDeque::new()));
+        let tls = options.tls.clone().unwrap_or_default();
+        let mut config = MqttClientConfiguration {
+            client_id: options.client_id.as_deref(),
+            username: options
+                .credentials
+                .as_ref()
+                .map(|(username, _)| username.as_str()),
+            password: options
+                .credentials
+                .as_ref()
+                .map(|(_, password)| password.as_str()),
+            reconnect_timeout: options.reconnect_delay,
+            server_certificate: tls.ca_certificate(),
+            client_certificate: tls.client_certificate(),
+            private_key: tls.private_key(),
+            crt_bundle_attach: tls
+                .uses_certificate_bundle()
+                .then_some(esp_crt_bundle_attach as _),
+            ..Default::default()
+        };
+        if let Some(keep_alive) = options.keep_alive {
+            config.keep_alive_interval = (!keep_alive.is_zero()).then_some(keep_alive);
+        }
+        if let Some(network_timeout) = options.network_timeout {
+            config.network_timeout = network_timeout;
+        }
+        if let Some(last_will) = &options.last_will {
+            if !is_valid_topic(&last_will.topic) {
+                return Err(MqttError::InvalidTopic);
+            }
+            config.lwt = Some(LwtConfiguration {
+                topic: &last_will.topic,
+                payload: &last_will.payload,
+                qos: last_will.qos.into(),
+                retain: last_will.retain,
+            });
+        }
+        let mut receiver = EventReceiver {
+            events: events.clone(),
+            notifier,
+            capacity: queue_capacities().mqtt_messages,
+            partial: None,
+        };
+        let client =
+            EspMqttClient::new_cb(broker_url, &config, move |event| receiver.receive(event))
+                .map_err(|_| MqttError::ConnectionError)?;
+
+        Ok(_MqttClient {
+            client: Some(client),
+            events,
+            connected: false,
+            subscriptions: vec![],
+            connection_callback: None,
+        })
+    }
+
+    /// Publishes a message on a topic. Messages published while the connection is down are kept
+    /// and sent once it is restored.
+    ///
+    /// # Arguments
+    ///
+    /// - `topic`: The topic to publish on, without wildcards
+    /// - `payload`: The data of the message
+    /// - `qos`: The delivery guarantee of the message
+    /// - `retain`: Whether the broker keeps the message for the clients that subscribe later
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the message was queued, or an `MqttError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MqttError::InvalidTopic`: If the topic is empty or has wildcards
+    /// - `MqttError::Disconnected`: If the client was disconnected with [Self::disconnect]
+    /// - `MqttError::PublishError`: If the message could not be queued
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        payload: &[u8],
+        qos: MqttQos,
+        retain: bool,
+    ) -> Result<(), MqttError> {
+        if !is_valid_topic(topic) {
+            return Err(MqttError::InvalidTopic);
+        }
+        self.client
+            .as_mut()
+            .ok_or(MqttError::Disconnected)?
+            .enqueue(topic, qos.into(), retain, payload)
+            .map_err(|_| MqttError::PublishError)?;
+        Ok(())
+    }
+
+    /// Removes the retained message of a topic from the broker, by publishing an empty retained
+    /// message on it.
+    ///
+    /// # Arguments
+    ///
+    /// - `topic`: The topic whose retained message is removed
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the message was queued, or an `MqttError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MqttError::InvalidTopic`: If the topic is empty or has wildcards
+    /// - `MqttError::Disconnected`: If the client was disconnected with [Self::disconnect]
+    /// - `MqttError::PublishError`: If the message could not be queued
+    pub fn clear_retained(&mut self, topic: &str) -> Result<(), MqttError> {
+        self.publish(topic, &[], MqttQos::AtLeastOnce, true)
+    }
+
+    /// Subscribes to a topic, executing the callback for each message received on it. The filter
+    /// can have wildcards: `+` matches a single level, such as `home/+/temperature`, and `#`
+    /// matches every level after it, such as `home/#`. Subscribing again to the same filter
+    /// replaces its callback.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used. Messages received while the amount of pending messages is at the `mqtt_messages`
+    /// capacity of [crate::utils::queue_config::QueueCapacities] are dropped.
+    ///
+    /// # Arguments
+    ///
+    /// - `filter`: The topic filter to subscribe to
+    /// - `qos`: The maximum delivery guarantee of the messages received
+    /// - `callback`: A closure that receives each message
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the subscription was set, or an `MqttError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MqttError::InvalidTopic`: If the filter is not valid
+    /// - `MqttError::Disconnected`: If the client was disconnected with [Self::disconnect]
+    /// - `MqttError::SubscribeError`: If the subscription could not be sent
+    pub fn subscribe<C: FnMut(&MqttMessage) + 'a>(
+        &mut self,
+        filter: &str,
+        qos: MqttQos,
+        callback: C,
+    ) -> Result<(), MqttError> {
+        if !is_valid_filter(filter) {
+            return Err(MqttError::InvalidTopic);
+        }
+        let client = self.client.as_mut().ok_or(MqttError::Disconnected)?;
+        if self.connected {
+            client
+                .subscribe(filter, qos.into())
+                .map_err(|_| MqttError::SubscribeError)?;
+        }
+        self.subscriptions.retain(|s| s.filter != filter);
+        self.subscriptions.push(Subscription {
+            filter: filter.to_string(),
+            qos,
+            callback: Box::new(callback),
+        });
+        Ok(())
+    }
+
+    /// Unsubscribes from a topic filter set with [Self::subscribe]
+    ///
+    /// # Arguments
+    ///
+    /// - `filter`: The topic filter to unsubscribe from
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the subscription was removed, or an `MqttError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MqttError::InvalidTopic`: If the client is not subscribed to the filter
+    /// - `MqttError::SubscribeError`: If the unsubscription could not be sent
+    pub fn unsubscribe(&mut self, filter: &str) -> Result<(), MqttError> {
+        if !self.subscriptions.iter().any(|s| s.filter == filter) {
+            return Err(MqttError::InvalidTopic);
+        }
+        if let (Some(client), true) = (self.client.as_mut(), self.connected) {
+            client
+                .unsubscribe(filter)
+                .map_err(|_| MqttError::SubscribeError)?;
+        }
+        self.subscriptions.retain(|s| s.filter != filter);
+        Ok(())
+    }
+
+    /// Sets the callback executed when the connection to the broker is established or lost.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives whether the client is connected
+    pub fn on_connection_change<C: FnMut(bool) + 'a>(&mut self, callback: C) {
+        self.connection_callback = Some(Box::new(callback));
+    }
+
+    /// Checks if the client is connected to the broker
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the client is connected
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// Closes the connection to the broker, without reconnecting. The client can not be used
+    /// afterwards.
+    pub fn disconnect(&mut self) {
+        self.client = None;
+        self.connected = false;
+    }
+
+    /// Handles every event received since the last call, subscribing again to every topic when
+    /// the connection is established, and executing the callbacks of the messages.
+    fn handle_events(&mut self) {
+        loop {
+            let event = self
+                .events
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .pop_front();
+            let Some(event) = event else {
+                return;
+            };
+            match event {
+                MqttEvent::Connected => {
+                    self.connected = true;
+                    if let Some(client) = self.client.as_mut() {
+                        for subscription in &self.subscriptions {
+                            _ = client.subscribe(&subscription.filter, subscription.qos.into());
+                        }
+                    }
+                    if let Some(callback) = self.connection_callback.as_mut() {
+                        callback(true);
+                    }
+                }
+                MqttEvent::Disconnected => {
+                    self.connected = false;
+                    if let Some(callback) = self.connection_callback.as_mut() {
+                        callback(false);
+                    }
+                }
+                MqttEvent::Message(message) => {
+                    for subscription in self.subscriptions.iter_mut() {
+                        if topic_matches(&subscription.filter, &message.topic) {
+                            (subscription.callback)(&message);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> MqttClient<'a> {
+    /// Creates a new MqttClient and starts connecting to the broker
+    ///
+    /// # Arguments
+    ///
+    /// - `broker_url`: The url of the broker, such as `mqtt://broker.local:1883`
+    /// - `options`: The MqttOptions of the connection
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when an event is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new MqttClient, or an `MqttError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `MqttError::ConnectionError`: If the client could not be created
+    pub(crate) fn new(
+        broker_url: &str,
+        options: &MqttOptions,
+        notifier: Notifier,
+    ) -> Result<Self, MqttError> {
+        Ok(Self {
+            inner: SharableRef::new_sharable(_MqttClient::new(broker_url, options, notifier)?),
+        })
+    }
+}
+
+impl<'a> InterruptDriver<'a> for MqttClient<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().handle_events();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// Closes the connection to the broker
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.disconnect();
+        Ok(())
+    }
+}
+
+/// Receives the events of the MQTT task, joining the messages that arrive in many chunks, and
+/// queues them for the update loop:
+/// - `events`: The queue of events handled by the update loop.
+/// - `notifier`: A notifier in order to wake up the [crate::Microcontroller].
+/// - `capacity`: The maximum amount of messages waiting in the queue.
+/// - `partial`: The message being received in chunks, with its total size.
+struct EventReceiver {
+    events: EventQueue,
+    notifier: Notifier,
+    capacity: usize,
+    partial: Option<(MqttMessage, usize)>,
+}
+
+impl EventReceiver {
+    /// Handles an event of the MQTT task
+    fn receive(&mut self, event: EspMqttEvent<'_>) {
+        match event.payload() {
+            EventPayload::Connected(_) => self.push(MqttEvent::Connected),
+            EventPayload::Disconnected => self.push(MqttEvent::Disconnected),
+            EventPayload::Received {
+                topic,
+                data,
+                details,
+                ..
+            } => match details {
+                Details::Complete => self.push_message(MqttMessage {
+                    topic: topic.unwrap_or_default().to_string(),
+                    payload: data.to_vec(),
+                }),
+                Details::InitialChunk(chunk) => {
+                    let message = MqttMessage {
+                        topic: topic.unwrap_or_default().to_string(),
+                        payload: data.to_vec(),
+                    };
+                    self.partial = Some((message, chunk.total_data_size));
+                }
+                Details::SubsequentChunk(_) => {
+                    let Some((mut message, total_size)) = self.partial.take() else {
+                        return;
+                    };
+                    message.payload.extend_from_slice(data);
+                    if message.payload.len() >= total_size {
+                        self.push_message(message);
+                    } else {
+                        self.partial = Some((message, total_size));
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Queues a message, dropping it if the queue is full
+    fn push_message(&mut self, message: MqttMessage) {
+        let events = self.events.lock().unwrap_or_else(|err| err.into_inner());
+        let pending_messages = events
+            .iter()
+            .filter(|event| matches!(event, MqttEvent::Message(_)))
+            .count();
+        drop(events);
+        if pending_messages >= self.capacity {
+            record_dropped_item();
+            return;
+        }
+        self.push(MqttEvent::Message(message));
+    }
+
+    /// Queues an event and wakes up the update loop
+    fn push(&mut self, event: MqttEvent) {
+        self.events
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push_back(event);
+        self.notifier.notify();
+    }
+}
+
+/// Checks if a topic can be published on, which requires it not to be empty nor have wildcards
+fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty() && !topic.contains(['+', '#'])
+}
+
+/// Checks if a topic filter is valid: `+` must be a whole level, and `#` must be the whole last
+/// level
+fn is_valid_filter(filter: &str) -> bool {
+    let levels: Vec<&str> = filter.split('/').collect();
+    !filter.is_empty()
+        && levels.iter().enumerate().all(|(i, level)| match *level {
+            "+" => true,
+            "#" => i == levels.len() - 1,
+            level => !level.contains(['+', '#']),
+        })
+}
+
+/// Checks if a topic matches a filter, which may have `+` and `#` wildcards. Topics starting with
+/// `$` are only matched by filters that start with the same level.
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && (filter.starts_with('+') || filter.starts_with('#')) {
+        return false;
+    }
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
@@ -0,0 +1,466 @@
+use super::TlsOptions;
+use esp_idf_svc::{
+    io::EspIOError,
+    sys::esp_crt_bundle_attach,
+    tls::{Config as TlsConfig, EspTls, InternalSocket},
+    ws::{
+        client::{
+            EspWebSocketClient, EspWebSocketClientConfig, WebSocketEvent, WebSocketEventType,
+        },
+        FrameType,
+    },
+};
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+const TCP_SCHEME: &str = "tcp://";
+const TLS_SCHEME: &str = "tls://";
+const WS_SCHEME: &str = "ws://";
+const WSS_SCHEME: &str = "wss://";
+const MBEDTLS_ERR_SSL_WANT_READ: i32 = -0x6900;
+const MBEDTLS_ERR_SSL_TIMEOUT: i32 = -0x6800;
+
+/// Error types related to network stream operations.
+#[derive(Debug)]
+pub enum NetStreamError {
+    ConnectionError,
+    Disconnected,
+    InvalidAddress,
+    ReadError,
+    Timeout,
+    WriteError,
+}
+
+/// The NetStream trait abstracts a connected byte stream over the network, so higher level
+/// protocols can be written once and used over any transport. A `Box<dyn NetStream>` can be
+/// obtained from an url with [connect_net_stream], allowing to select the transport at runtime.
+pub trait NetStream {
+    /// Writes some bytes to the stream.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes to write
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of bytes written, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::Disconnected`: If the stream is closed.
+    /// - `NetStreamError::WriteError`: If the write fails.
+    fn write(&mut self, data: &[u8]) -> Result<usize, NetStreamError>;
+
+    /// Reads the available bytes of the stream, waiting up to the timeout of the stream
+    /// if there are none.
+    ///
+    /// # Arguments
+    ///
+    /// - `buffer`: The buffer where the read bytes are stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of bytes read, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::Timeout`: If no bytes arrived before the timeout.
+    /// - `NetStreamError::Disconnected`: If the stream is closed.
+    /// - `NetStreamError::ReadError`: If the read fails.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, NetStreamError>;
+
+    /// Closes the stream. Further reads and writes will fail.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the stream was closed, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::ConnectionError`: If the stream could not be closed.
+    fn close(&mut self) -> Result<(), NetStreamError>;
+
+    /// Writes every byte of data to the stream.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes to write
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if every byte was written, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::Disconnected`: If the stream is closed.
+    /// - `NetStreamError::WriteError`: If the write fails.
+    fn write_all(&mut self, mut data: &[u8]) -> Result<(), NetStreamError> {
+        while !data.is_empty() {
+            match self.write(data)? {
+                0 => return Err(NetStreamError::Disconnected),
+                n => data = &data[n..],
+            }
+        }
+        Ok(())
+    }
+}
+
+/// NetStream over a plain TCP connection
+pub struct TcpNetStream {
+    stream: TcpStream,
+}
+
+/// NetStream over a TLS connection. Server certificates are verified using the certificate bundle,
+/// unless other TlsOptions are used with [TlsNetStream::connect_with_tls].
+pub struct TlsNetStream {
+    tls: EspTls<InternalSocket>,
+    connected: bool,
+}
+
+/// Data shared between a WebSocketNetStream and the callback of its client
+#[derive(Default)]
+struct WebSocketState {
+    received: VecDeque<u8>,
+    connected: bool,
+}
+
+/// NetStream over a WebSocket connection. Bytes are sent as binary frames, and the data of
+/// every received text or binary frame is concatenated into the stream.
+pub struct WebSocketNetStream {
+    client: EspWebSocketClient<'static>,
+    state: Arc<(Mutex<WebSocketState>, Condvar)>,
+    timeout: Duration,
+}
+
+impl TcpNetStream {
+    /// Creates a new TcpNetStream connected to the address
+    ///
+    /// # Arguments
+    ///
+    /// - `address`: The address to connect to, such as "192.168.0.10:8080" or "example.com:8080"
+    /// - `timeout`: Timeout used for the connection and reads
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TcpNetStream, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::InvalidAddress`: If the address could not be resolved.
+    /// - `NetStreamError::ConnectionError`: If the connection fails.
+    pub fn connect(address: &str, timeout: Duration) -> Result<Self, NetStreamError> {
+        let address = address
+            .to_socket_addrs()
+            .map_err(|_| NetStreamError::InvalidAddress)?
+            .next()
+            .ok_or(NetStreamError::InvalidAddress)?;
+        let stream = TcpStream::connect_timeout(&address, timeout)
+            .map_err(|_| NetStreamError::ConnectionError)?;
+        Self::new(stream, timeout)
+    }
+
+    /// Creates a new TcpNetStream from an already connected TcpStream
+    ///
+    /// # Arguments
+    ///
+    /// - `stream`: The connected TcpStream
+    /// - `timeout`: Timeout used for reads
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TcpNetStream, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::ConnectionError`: If the stream could not be configured.
+    pub fn new(stream: TcpStream, timeout: Duration) -> Result<Self, NetStreamError> {
+        stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|_| NetStreamError::ConnectionError)?;
+        stream
+            .set_nodelay(true)
+            .map_err(|_| NetStreamError::ConnectionError)?;
+        Ok(TcpNetStream { stream })
+    }
+}
+
+impl NetStream for TcpNetStream {
+    fn write(&mut self, data: &[u8]) -> Result<usize, NetStreamError> {
+        self.stream.write(data).map_err(|err| match err.kind() {
+            ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::NotConnected => {
+                NetStreamError::Disconnected
+            }
+            _ => NetStreamError::WriteError,
+        })
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, NetStreamError> {
+        match self.stream.read(buffer) {
+            Ok(0) if !buffer.is_empty() => Err(NetStreamError::Disconnected),
+            Ok(n) => Ok(n),
+            Err(err) => Err(match err.kind() {
+                ErrorKind::WouldBlock | ErrorKind::TimedOut => NetStreamError::Timeout,
+                ErrorKind::ConnectionReset | ErrorKind::NotConnected => {
+                    NetStreamError::Disconnected
+                }
+                _ => NetStreamError::ReadError,
+            }),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), NetStreamError> {
+        self.stream
+            .shutdown(Shutdown::Both)
+            .map_err(|_| NetStreamError::ConnectionError)
+    }
+}
+
+impl TlsNetStream {
+    /// Creates a new TlsNetStream connected to the host
+    ///
+    /// # Arguments
+    ///
+    /// - `host`: The host to connect to. It is also used to verify the server certificate
+    /// - `port`: The port to connect to
+    /// - `timeout`: Timeout used for the connection and reads
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TlsNetStream, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::ConnectionError`: If the connection or the handshake fails.
+    pub fn connect(host: &str, port: u16, timeout: Duration) -> Result<Self, NetStreamError> {
+        Self::connect_with_tls(host, port, timeout, &TlsOptions::new())
+    }
+
+    /// Creates a new TlsNetStream connected to the host, verifying the server and authenticating
+    /// the device with the given TlsOptions
+    ///
+    /// # Arguments
+    ///
+    /// - `host`: The host to connect to. Unless the TlsOptions have a server name, it is also used
+    ///   for the Server Name Indication and to verify the server certificate
+    /// - `port`: The port to connect to
+    /// - `timeout`: Timeout used for the connection and reads
+    /// - `options`: The TlsOptions of the connection
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TlsNetStream, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::ConnectionError`: If the connection or the handshake fails.
+    pub fn connect_with_tls(
+        host: &str,
+        port: u16,
+        timeout: Duration,
+        options: &TlsOptions,
+    ) -> Result<Self, NetStreamError> {
+        let mut tls = EspTls::new().map_err(|_| NetStreamError::ConnectionError)?;
+        let config = TlsConfig {
+            common_name: Some(options.server_name().unwrap_or(host)),
+            ca_cert: options.ca_certificate(),
+            client_cert: options.client_certificate(),
+            client_key: options.private_key(),
+            timeout_ms: timeout.as_millis().try_into().unwrap_or(u32::MAX),
+            use_crt_bundle_attach: options.uses_certificate_bundle(),
+            ..Default::default()
+        };
+        tls.connect(host, port, &config)
+            .map_err(|_| NetStreamError::ConnectionError)?;
+        Ok(TlsNetStream {
+            tls,
+            connected: true,
+        })
+    }
+}
+
+impl NetStream for TlsNetStream {
+    fn write(&mut self, data: &[u8]) -> Result<usize, NetStreamError> {
+        if !self.connected {
+            return Err(NetStreamError::Disconnected);
+        }
+        self.tls.write(data).map_err(|_| NetStreamError::WriteError)
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, NetStreamError> {
+        if !self.connected {
+            return Err(NetStreamError::Disconnected);
+        }
+        match self.tls.read(buffer) {
+            Ok(0) if !buffer.is_empty() => {
+                self.connected = false;
+                Err(NetStreamError::Disconnected)
+            }
+            Ok(n) => Ok(n),
+            Err(err) => Err(match err.code() {
+                MBEDTLS_ERR_SSL_WANT_READ | MBEDTLS_ERR_SSL_TIMEOUT => NetStreamError::Timeout,
+                _ => NetStreamError::ReadError,
+            }),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), NetStreamError> {
+        self.connected = false;
+        Ok(())
+    }
+}
+
+impl WebSocketNetStream {
+    /// Creates a new WebSocketNetStream connected to the uri. Secure uris (wss://) verify the
+    /// server certificate using the certificate bundle.
+    ///
+    /// # Arguments
+    ///
+    /// - `uri`: The uri to connect to, such as "ws://192.168.0.10:8080/stream"
+    /// - `timeout`: Timeout used for the connection, reads and writes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new WebSocketNetStream, or a `NetStreamError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `NetStreamError::ConnectionError`: If the client could not be created.
+    /// - `NetStreamError::Timeout`: If the connection was not established before the timeout.
+    pub fn connect(uri: &str, timeout: Duration) -> Result<Self, NetStreamError> {
+        let state: Arc<(Mutex<WebSocketState>, Condvar)> = Arc::default();
+        let callback_state = state.clone();
+        let config = EspWebSocketClientConfig {
+            crt_bundle_attach: Some(esp_crt_bundle_attach),
+            ..Default::default()
+        };
+        let client = EspWebSocketClient::new(uri, &config, timeout, move |event| {
+            handle_websocket_event(&callback_state, event)
+        })
+        .map_err(|_| NetStreamError::ConnectionError)?;
+
+        let mut stream = WebSocketNetStream {
+            client,
+            state,
+            timeout,
+        };
+        stream.wait_for(|state| state.connected)?;
+        Ok(stream)
+    }
+
+    /// Waits until the condition is met by the shared state, or the timeout is reached
+    fn wait_for<F: Fn(&WebSocketState) -> bool>(
+        &mut self,
+        condition: F,
+    ) -> Result<MutexGuard<'_, WebSocketState>, NetStreamError> {
+        let (lock, condvar) = &*self.state;
+        let guard = lock.lock().map_err(|_| NetStreamError::ReadError)?;
+        let (guard, result) = condvar
+            .wait_timeout_while(guard, self.timeout, |state| !condition(state))
+            .map_err(|_| NetStreamError::ReadError)?;
+        if result.timed_out() {
+            return Err(NetStreamError::Timeout);
+        }
+        Ok(guard)
+    }
+}
+
+impl NetStream for WebSocketNetStream {
+    fn write(&mut self, data: &[u8]) -> Result<usize, NetStreamError> {
+        if !self.client.is_connected() {
+            return Err(NetStreamError::Disconnected);
+        }
+        self.client
+            .send(FrameType::Binary(false), data)
+            .map_err(|_| NetStreamError::WriteError)?;
+        Ok(data.len())
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize, NetStreamError> {
+        let mut state = self.wait_for(|state| !state.received.is_empty() || !state.connected)?;
+        if state.received.is_empty() {
+            return Err(NetStreamError::Disconnected);
+        }
+        let len = buffer.len().min(state.received.len());
+        for (dst, src) in buffer.iter_mut().zip(state.received.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+
+    fn close(&mut self) -> Result<(), NetStreamError> {
+        if let Ok(mut state) = self.state.0.lock() {
+            state.connected = false;
+        }
+        self.client
+            .send(FrameType::SocketClose, &[])
+            .map_err(|_| NetStreamError::ConnectionError)
+    }
+}
+
+/// Updates the shared state of a WebSocketNetStream with each event of its client, waking up
+/// any reader waiting for data.
+fn handle_websocket_event(
+    state: &Arc<(Mutex<WebSocketState>, Condvar)>,
+    event: &Result<WebSocketEvent, EspIOError>,
+) {
+    let Ok(event) = event else {
+        return;
+    };
+    let (lock, condvar) = &**state;
+    let Ok(mut state) = lock.lock() else {
+        return;
+    };
+    match event.event_type {
+        WebSocketEventType::Connected => state.connected = true,
+        WebSocketEventType::Disconnected
+        | WebSocketEventType::Close(_)
+        | WebSocketEventType::Closed => state.connected = false,
+        WebSocketEventType::Text(text) => state.received.extend(text.as_bytes()),
+        WebSocketEventType::Binary(data) => state.received.extend(data),
+        _ => return,
+    }
+    condvar.notify_all();
+}
+
+/// Connects a NetStream selecting the transport from the scheme of the url:
+/// - `tcp://host:port`: [TcpNetStream]
+/// - `tls://host:port`: [TlsNetStream]
+/// - `ws://...` or `wss://...`: [WebSocketNetStream]
+///
+/// # Arguments
+///
+/// - `url`: The url to connect to
+/// - `timeout`: Timeout used for the connection and reads
+///
+/// # Returns
+///
+/// A `Result` with the connected stream, or a `NetStreamError` if it fails.
+///
+/// # Errors
+///
+/// - `NetStreamError::InvalidAddress`: If the scheme is not supported or the address is invalid.
+/// - `NetStreamError::ConnectionError`: If the connection fails.
+/// - `NetStreamError::Timeout`: If the connection was not established before the timeout.
+pub fn connect_net_stream(
+    url: &str,
+    timeout: Duration,
+) -> Result<Box<dyn NetStream>, NetStreamError> {
+    if let Some(address) = url.strip_prefix(TCP_SCHEME) {
+        Ok(Box::new(TcpNetStream::connect(address, timeout)?))
+    } else if let Some(address) = url.strip_prefix(TLS_SCHEME) {
+        let (host, port) = address
+            .rsplit_once(':')
+            .ok_or(NetStreamError::InvalidAddress)?;
+        let port = port.parse().map_err(|_| NetStreamError::InvalidAddress)?;
+        Ok(Box::new(TlsNetStream::connect(host, port, timeout)?))
+    } else if url.starts_with(WS_SCHEME) || url.starts_with(WSS_SCHEME) {
+        Ok(Box::new(WebSocketNetStream::connect(url, timeout)?))
+    } else {
+        Err(NetStreamError::InvalidAddress)
+    }
+}
@@ -0,0 +1,374 @@
+use super::WifiDriver;
+use esp_idf_svc::nvs::{EspNvs, NvsDefault};
+use std::collections::VecDeque;
+
+const NVS_HEAD_KEY: &str = "head";
+const NVS_TAIL_KEY: &str = "tail";
+/// Every message stored in flash starts with the length of its topic
+const TOPIC_LEN_SIZE: usize = 2;
+
+/// Enums the different errors possible when working with the OfflineBuffer
+#[derive(Debug)]
+pub enum OfflineBufferError {
+    MessageTooBig,
+    StorageError,
+}
+
+/// Enums what the OfflineBuffer does with a new message when it is full:
+/// - `DropOldest`: The oldest message is discarded to make room for the new one.
+/// - `DropNewest`: The new message is discarded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DropPolicy {
+    DropOldest,
+    DropNewest,
+}
+
+/// A message waiting to be sent:
+/// - `topic`: Where the message goes, such as an MQTT topic or an HTTP uri.
+/// - `payload`: The content of the message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BufferedMessage {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Messages spilled to the Non-Volatile Storage, as a ring of numbered keys from head to tail
+struct FlashSpillover {
+    nvs: EspNvs<NvsDefault>,
+    head: u32,
+    tail: u32,
+    max_messages: u32,
+}
+
+/// Queues outbound messages (telemetry, HTTP bodies) while the network is down, to send them in
+/// the same order once it is back. Messages are kept in RAM and, if enabled, the oldest ones are
+/// moved to flash when RAM is full, where they also survive a reboot.
+pub struct OfflineBuffer {
+    messages: VecDeque<BufferedMessage>,
+    max_messages: usize,
+    max_bytes: usize,
+    used_bytes: usize,
+    drop_policy: DropPolicy,
+    dropped: u32,
+    flash: Option<FlashSpillover>,
+}
+
+impl BufferedMessage {
+    /// Gets the amount of bytes the message takes in the buffer
+    fn size(&self) -> usize {
+        self.topic.len() + self.payload.len()
+    }
+
+    /// Encodes the message to be stored in flash
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(TOPIC_LEN_SIZE + self.size());
+        data.extend_from_slice(&(self.topic.len() as u16).to_le_bytes());
+        data.extend_from_slice(self.topic.as_bytes());
+        data.extend_from_slice(&self.payload);
+        data
+    }
+
+    /// Decodes a message stored in flash
+    fn decode(data: &[u8]) -> Option<Self> {
+        let topic_len = u16::from_le_bytes(data.get(..TOPIC_LEN_SIZE)?.try_into().ok()?) as usize;
+        let topic = data.get(TOPIC_LEN_SIZE..TOPIC_LEN_SIZE + topic_len)?;
+        Some(BufferedMessage {
+            topic: String::from_utf8(topic.to_vec()).ok()?,
+            payload: data[TOPIC_LEN_SIZE + topic_len..].to_vec(),
+        })
+    }
+}
+
+impl FlashSpillover {
+    /// Gets the amount of messages in flash
+    fn len(&self) -> u32 {
+        self.tail.wrapping_sub(self.head)
+    }
+
+    /// Gets the key of the message at a position of the ring
+    fn key(&self, position: u32) -> String {
+        format!("m{}", position % self.max_messages)
+    }
+
+    /// Stores a message after the newest one
+    fn push(&mut self, message: &BufferedMessage) -> Result<(), OfflineBufferError> {
+        let key = self.key(self.tail);
+        self.nvs
+            .set_raw(&key, &message.encode())
+            .map_err(|_| OfflineBufferError::StorageError)?;
+        self.tail = self.tail.wrapping_add(1);
+        self.store_indexes()
+    }
+
+    /// Reads the oldest message without removing it
+    fn front(&self) -> Result<Option<BufferedMessage>, OfflineBufferError> {
+        if self.len() == 0 {
+            return Ok(None);
+        }
+        let key = self.key(self.head);
+        let len = self
+            .nvs
+            .blob_len(&key)
+            .map_err(|_| OfflineBufferError::StorageError)?
+            .ok_or(OfflineBufferError::StorageError)?;
+        let mut buf = vec![0; len];
+        let data = self
+            .nvs
+            .get_raw(&key, &mut buf)
+            .map_err(|_| OfflineBufferError::StorageError)?
+            .ok_or(OfflineBufferError::StorageError)?;
+        Ok(BufferedMessage::decode(data))
+    }
+
+    /// Removes the oldest message
+    fn pop(&mut self) -> Result<(), OfflineBufferError> {
+        if self.len() == 0 {
+            return Ok(());
+        }
+        let key = self.key(self.head);
+        self.nvs
+            .remove(&key)
+            .map_err(|_| OfflineBufferError::StorageError)?;
+        self.head = self.head.wrapping_add(1);
+        self.store_indexes()
+    }
+
+    /// Stores the head and tail so the messages are found after a reboot
+    fn store_indexes(&mut self) -> Result<(), OfflineBufferError> {
+        self.nvs
+            .set_u32(NVS_HEAD_KEY, self.head)
+            .and_then(|_| self.nvs.set_u32(NVS_TAIL_KEY, self.tail))
+            .map_err(|_| OfflineBufferError::StorageError)
+    }
+}
+
+impl OfflineBuffer {
+    /// Creates a new OfflineBuffer that only keeps messages in RAM
+    ///
+    /// # Arguments
+    ///
+    /// - `max_messages`: The max amount of messages kept in RAM
+    /// - `max_bytes`: The max amount of bytes, counting topics and payloads, kept in RAM
+    /// - `drop_policy`: What to do with new messages when the buffer is full
+    ///
+    /// # Returns
+    ///
+    /// The new OfflineBuffer
+    pub fn new(max_messages: usize, max_bytes: usize, drop_policy: DropPolicy) -> Self {
+        OfflineBuffer {
+            messages: VecDeque::new(),
+            max_messages: max_messages.max(1),
+            max_bytes,
+            used_bytes: 0,
+            drop_policy,
+            dropped: 0,
+            flash: None,
+        }
+    }
+
+    /// Enables moving the oldest messages to flash when RAM is full. Messages left in the
+    /// namespace by a previous run are recovered, and are sent before the new ones.
+    ///
+    /// # Arguments
+    ///
+    /// - `wifi_driver`: The WifiDriver, whose Non-Volatile Storage partition is used
+    /// - `namespace`: The NVS namespace of the buffer, at most 15 characters. Each buffer must
+    ///   use its own namespace
+    /// - `max_messages`: The max amount of messages kept in flash. Changing it between runs
+    ///   discards the messages stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the spillover was enabled, or an `OfflineBufferError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `OfflineBufferError::StorageError`: If the Non-Volatile Storage could not be opened
+    pub fn enable_flash_spillover(
+        &mut self,
+        wifi_driver: &WifiDriver,
+        namespace: &str,
+        max_messages: u32,
+    ) -> Result<(), OfflineBufferError> {
+        let nvs = EspNvs::new(wifi_driver.nvs_partition(), namespace, true)
+            .map_err(|_| OfflineBufferError::StorageError)?;
+        let read_index = |key| nvs.get_u32(key).ok().flatten().unwrap_or(0);
+        let (head, tail) = (read_index(NVS_HEAD_KEY), read_index(NVS_TAIL_KEY));
+
+        let max_messages = max_messages.max(1);
+        let mut flash = FlashSpillover {
+            nvs,
+            head,
+            tail,
+            max_messages,
+        };
+        if flash.len() > max_messages {
+            flash.head = 0;
+            flash.tail = 0;
+            flash.store_indexes()?;
+        }
+        self.flash = Some(flash);
+        Ok(())
+    }
+
+    /// Queues a message, to be sent after every message already queued. If the buffer is full
+    /// the drop policy is applied.
+    ///
+    /// # Arguments
+    ///
+    /// - `topic`: Where the message goes, such as an MQTT topic or an HTTP uri
+    /// - `payload`: The content of the message
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the message was queued or dropped by the policy, or an
+    /// `OfflineBufferError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `OfflineBufferError::MessageTooBig`: If the message is bigger than the max bytes of RAM
+    /// - `OfflineBufferError::StorageError`: If a message could not be moved to flash
+    pub fn push(&mut self, topic: &str, payload: &[u8]) -> Result<(), OfflineBufferError> {
+        let message = BufferedMessage {
+            topic: topic.to_string(),
+            payload: payload.to_vec(),
+        };
+        if message.size() > self.max_bytes {
+            return Err(OfflineBufferError::MessageTooBig);
+        }
+
+        while self.ram_is_full(message.size()) {
+            if !self.make_room()? {
+                self.dropped += 1;
+                return Ok(());
+            }
+        }
+        self.used_bytes += message.size();
+        self.messages.push_back(message);
+        Ok(())
+    }
+
+    /// Sends every queued message in order through the closure, removing each one after it is
+    /// sent. Stops on the first error, leaving that message and the following ones queued, so
+    /// it should be called again after reconnecting.
+    ///
+    /// # Arguments
+    ///
+    /// - `send`: A closure that sends a message, returning an error if it could not
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of messages sent, or the error of the closure. Messages in flash
+    /// that could not be read are discarded.
+    pub fn replay<E, F: FnMut(&BufferedMessage) -> Result<(), E>>(
+        &mut self,
+        mut send: F,
+    ) -> Result<usize, E> {
+        let mut sent = 0;
+        if let Some(flash) = self.flash.as_mut() {
+            while flash.len() > 0 {
+                match flash.front() {
+                    Ok(Some(message)) => {
+                        send(&message)?;
+                        sent += 1;
+                    }
+                    Ok(None) => self.dropped += 1,
+                    Err(_) => break,
+                }
+                if flash.pop().is_err() {
+                    break;
+                }
+            }
+        }
+        while let Some(message) = self.messages.front() {
+            send(message)?;
+            self.used_bytes -= message.size();
+            self.messages.pop_front();
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Gets the amount of messages queued, both in RAM and flash
+    ///
+    /// # Returns
+    ///
+    /// The amount of messages
+    pub fn len(&self) -> usize {
+        let in_flash = self.flash.as_ref().map_or(0, |flash| flash.len() as usize);
+        self.messages.len() + in_flash
+    }
+
+    /// Checks whether there are no messages queued
+    ///
+    /// # Returns
+    ///
+    /// True if the buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the amount of messages discarded because the buffer was full
+    ///
+    /// # Returns
+    ///
+    /// The amount of dropped messages
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    /// Discards every queued message, both in RAM and flash
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the buffer was cleared, or an `OfflineBufferError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `OfflineBufferError::StorageError`: If the messages in flash could not be removed
+    pub fn clear(&mut self) -> Result<(), OfflineBufferError> {
+        self.messages.clear();
+        self.used_bytes = 0;
+        if let Some(flash) = self.flash.as_mut() {
+            while flash.len() > 0 {
+                flash.pop()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks whether a message of the given size does not fit in RAM
+    fn ram_is_full(&self, size: usize) -> bool {
+        self.messages.len() >= self.max_messages || self.used_bytes + size > self.max_bytes
+    }
+
+    /// Frees space in RAM, moving the oldest message to flash or applying the drop policy
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with true if space was freed, false if the new message must be dropped, or an
+    /// `OfflineBufferError` if it fails.
+    fn make_room(&mut self) -> Result<bool, OfflineBufferError> {
+        if let Some(flash) = self.flash.as_mut() {
+            if flash.len() >= flash.max_messages {
+                if self.drop_policy == DropPolicy::DropNewest {
+                    return Ok(false);
+                }
+                flash.pop()?;
+                self.dropped += 1;
+            }
+            if let Some(oldest) = self.messages.front() {
+                flash.push(oldest)?;
+            }
+        } else if self.drop_policy == DropPolicy::DropNewest {
+            return Ok(false);
+        } else {
+            self.dropped += 1;
+        }
+
+        if let Some(oldest) = self.messages.pop_front() {
+            self.used_bytes -= oldest.size();
+        }
+        Ok(true)
+    }
+}
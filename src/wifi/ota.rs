@@ -0,0 +1,237 @@
+use super::http::{Http, HttpError};
+use esp_idf_svc::{
+    hal::reset::restart,
+    ota::{EspOta, SlotState},
+};
+
+const FIRMWARE_CHUNK_SIZE: usize = 4096;
+/// First byte of every ESP-IDF application image
+const IMAGE_MAGIC: u8 = 0xE9;
+
+/// Enums the different errors possible when updating the firmware
+#[derive(Debug)]
+pub enum OtaError {
+    DownloadError(HttpError),
+    InvalidImage,
+    PartitionError,
+    RollbackError,
+    WriteError,
+}
+
+/// Progress of a download, passed to the callback of [OtaUpdater::on_progress]:
+/// - `written`: The bytes of the image written to the partition so far.
+/// - `total`: The size of the image, if the server sent it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OtaProgress {
+    pub written: usize,
+    pub total: Option<usize>,
+}
+
+/// Updates the firmware over the network: the image is downloaded from an url and streamed into
+/// the next OTA partition, so it does not need to fit in memory. Once validated, the partition is
+/// set to boot and the microcontroller restarts. The partition table must have OTA partitions.
+///
+/// Rollback is opt-in, since it changes the bootloader: it is enabled by adding
+/// `CONFIG_BOOTLOADER_APP_ROLLBACK_ENABLE=y` to the `sdkconfig.defaults` of the project. Then the
+/// new firmware boots pending verification, and must confirm it works with
+/// [OtaUpdater::mark_valid]. If it restarts before, for example because it panicked, the
+/// bootloader rolls back to the previous firmware. This also applies to the firmware staged by a
+/// [crate::storage::FieldUpdate]. Without it, the new firmware is kept even if it does not work.
+pub struct OtaUpdater<'a> {
+    on_progress: Option<Box<dyn FnMut(OtaProgress) + 'a>>,
+    restart_after_update: bool,
+}
+
+impl OtaProgress {
+    /// Gets the progress of the download as a percentage
+    ///
+    /// # Returns
+    ///
+    /// An Option. A Some with the percentage if the size of the image is known. Otherwise a None.
+    pub fn percentage(&self) -> Option<u8> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| (self.written.min(total) * 100 / total) as u8)
+    }
+}
+
+impl<'a> OtaUpdater<'a> {
+    /// Creates a new OtaUpdater. By default the microcontroller restarts after an update is
+    /// staged.
+    ///
+    /// # Returns
+    ///
+    /// The new OtaUpdater
+    pub fn new() -> Self {
+        OtaUpdater {
+            on_progress: None,
+            restart_after_update: true,
+        }
+    }
+
+    /// Sets the closure executed each time a piece of the image is written, for example to show
+    /// the progress on a display. It is executed on the task doing the update.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the OtaProgress of the download
+    ///
+    /// # Returns
+    ///
+    /// The OtaUpdater with the callback set
+    pub fn on_progress<F: FnMut(OtaProgress) + 'a>(mut self, callback: F) -> Self {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets whether the microcontroller restarts, to boot the new firmware, right after staging it
+    ///
+    /// # Arguments
+    ///
+    /// - `restart`: Whether to restart after an update is staged
+    ///
+    /// # Returns
+    ///
+    /// The OtaUpdater with the new setting
+    pub fn restart_after_update(mut self, restart: bool) -> Self {
+        self.restart_after_update = restart;
+        self
+    }
+
+    /// Downloads a firmware image and writes it to the next OTA partition, setting it as the boot
+    /// partition. The image is validated by ESP-IDF before it is set, so a partial or corrupted
+    /// download leaves the current firmware untouched. If restarting is enabled and the update
+    /// succeeds, this function does not return.
+    ///
+    /// # Arguments
+    ///
+    /// - `client`: The HttpClient or HttpsClient used for the download
+    /// - `url`: The url of the firmware image, the `.bin` file built for the board
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the update is staged, or an `OtaError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `OtaError::DownloadError`: If the request fails, the status is not successful, or the
+    ///   download ends before the size sent by the server
+    /// - `OtaError::PartitionError`: If there is no OTA partition to write to
+    /// - `OtaError::InvalidImage`: If the file is not a firmware image, or it fails validation
+    /// - `OtaError::WriteError`: If the image could not be written, for example if it is bigger
+    ///   than the partition
+    pub fn update<H: Http>(&mut self, client: &mut H, url: &str) -> Result<(), OtaError> {
+        let mut ota = EspOta::new().map_err(|_| OtaError::PartitionError)?;
+        client.get(url, vec![]).map_err(OtaError::DownloadError)?;
+        let connection = client.get_connection();
+        connection
+            .initiate_response()
+            .map_err(|_| OtaError::DownloadError(HttpError::ListeningError))?;
+        if !(200..300).contains(&connection.status()) {
+            return Err(OtaError::DownloadError(HttpError::RequestError));
+        }
+        let total = connection
+            .header("Content-Length")
+            .and_then(|length| length.parse().ok());
+        let mut update = ota
+            .initiate_update()
+            .map_err(|_| OtaError::PartitionError)?;
+
+        let mut buffer = vec![0; FIRMWARE_CHUNK_SIZE];
+        let mut written = 0;
+        let result = loop {
+            let read = match connection.read(&mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(read) => read,
+                Err(_) => break Err(OtaError::DownloadError(HttpError::ReadError)),
+            };
+            if written == 0 && buffer[0] != IMAGE_MAGIC {
+                break Err(OtaError::InvalidImage);
+            }
+            if update.write(&buffer[..read]).is_err() {
+                break Err(OtaError::WriteError);
+            }
+            written += read;
+            if let Some(callback) = self.on_progress.as_mut() {
+                callback(OtaProgress { written, total });
+            }
+        };
+        let result = result.and_then(|_| match total {
+            Some(total) if total != written => Err(OtaError::DownloadError(HttpError::ReadError)),
+            _ if written == 0 => Err(OtaError::InvalidImage),
+            _ => Ok(()),
+        });
+        if let Err(err) = result {
+            _ = update.abort();
+            return Err(err);
+        }
+        update.complete().map_err(|_| OtaError::InvalidImage)?;
+
+        if self.restart_after_update {
+            restart();
+        }
+        Ok(())
+    }
+
+    /// Checks if the running firmware was just installed and has not confirmed it works yet
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with a bool that indicates whether the firmware is pending verification, or an
+    /// `OtaError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `OtaError::PartitionError`: If the state of the running partition could not be read
+    pub fn is_pending_verification() -> Result<bool, OtaError> {
+        let ota = EspOta::new().map_err(|_| OtaError::PartitionError)?;
+        let slot = ota
+            .get_running_slot()
+            .map_err(|_| OtaError::PartitionError)?;
+        Ok(matches!(slot.state, SlotState::Unverified))
+    }
+
+    /// Confirms the running firmware works, so the bootloader keeps booting it. It should be
+    /// called once the program checked what it needs, such as connecting to the network. It is
+    /// only needed if rollback is enabled, otherwise the firmware is already confirmed.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the firmware was confirmed, or an `OtaError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `OtaError::PartitionError`: If the state of the running partition could not be set
+    pub fn mark_valid() -> Result<(), OtaError> {
+        let mut ota = EspOta::new().map_err(|_| OtaError::PartitionError)?;
+        ota.mark_running_slot_valid()
+            .map_err(|_| OtaError::PartitionError)
+    }
+
+    /// Marks the running firmware as not working and restarts into the previous one. On success
+    /// this function does not return.
+    ///
+    /// # Returns
+    ///
+    /// The `OtaError` of the failure
+    ///
+    /// # Errors
+    ///
+    /// - `OtaError::PartitionError`: If the OTA partitions could not be accessed
+    /// - `OtaError::RollbackError`: If there is no previous firmware to go back to
+    pub fn rollback() -> OtaError {
+        match EspOta::new() {
+            Ok(mut ota) => {
+                _ = ota.mark_running_slot_invalid_and_reboot();
+                OtaError::RollbackError
+            }
+            Err(_) => OtaError::PartitionError,
+        }
+    }
+}
+
+impl Default for OtaUpdater<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
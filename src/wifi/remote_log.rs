@@ -0,0 +1,470 @@
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::{
+    mem,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Syslog facility of the user level messages
+const SYSLOG_FACILITY_USER: u8 = 1;
+const DEFAULT_HOSTNAME: &str = "esp32";
+const DEFAULT_APP_NAME: &str = "esp32framework";
+const DEFAULT_MAX_RECORDS_PER_SECOND: u32 = 20;
+const DEFAULT_BATCH_SIZE: usize = 1;
+const DEFAULT_MAX_BATCH_DELAY: Duration = Duration::from_secs(1);
+/// Records kept, as a multiple of the batch size, while the transport is busy
+const MAX_PENDING_BATCHES: usize = 4;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(1);
+
+type LogSender = dyn FnMut(&[u8]) -> Result<(), ()> + Send;
+
+/// Enums the different errors possible when working with the RemoteLogger
+#[derive(Debug)]
+pub enum RemoteLogError {
+    AlreadyInitialized,
+    InvalidAddress,
+    SendError,
+}
+
+/// Where the log records are shipped:
+/// - `Syslog`: Each record is sent as a syslog (RFC 5424) datagram to a UDP collector.
+/// - `Sender`: Each batch is passed to a closure, with one record per line, for example to publish
+///   it on an MQTT topic.
+enum Transport {
+    Syslog {
+        socket: UdpSocket,
+        address: SocketAddr,
+    },
+    Sender(Box<LogSender>),
+}
+
+/// Logger that forwards the records of the `log` crate to a remote collector, so devices can be
+/// monitored without a serial connection. Records are rate limited, to avoid flooding the network
+/// when something goes wrong, and can be batched to send fewer packets.
+///
+/// The logger is configured with its builder methods and then installed with [Self::install],
+/// after which the records of the framework and the user are shipped.
+pub struct RemoteLogger {
+    transport: Transport,
+    config: LogConfig,
+}
+
+/// Configuration of the records shipped by a RemoteLogger
+struct LogConfig {
+    hostname: String,
+    app_name: String,
+    level: LevelFilter,
+    max_records_per_second: u32,
+    batch_size: usize,
+    max_batch_delay: Duration,
+    echo: bool,
+}
+
+/// Records waiting to be sent and the state of the rate limit
+struct PendingRecords {
+    lines: Vec<String>,
+    oldest: Option<Instant>,
+    window_start: Instant,
+    window_records: u32,
+    dropped_since_sent: u32,
+}
+
+/// The installed logger, shared by every thread that logs
+struct RemoteLogSink {
+    config: LogConfig,
+    transport: Mutex<Transport>,
+    pending: Mutex<PendingRecords>,
+    dropped: AtomicU32,
+}
+
+/// Handle to the installed RemoteLogger, used to flush it and to know how many records were lost
+#[derive(Clone, Copy)]
+pub struct RemoteLogHandle {
+    sink: &'static RemoteLogSink,
+}
+
+impl Transport {
+    /// Sends a batch of lines
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if every line was sent, or a `RemoteLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `RemoteLogError::SendError`: If a datagram could not be sent or the closure failed
+    fn send(&mut self, lines: &[String]) -> Result<(), RemoteLogError> {
+        match self {
+            Transport::Syslog { socket, address } => {
+                for line in lines {
+                    socket
+                        .send_to(line.as_bytes(), *address)
+                        .map_err(|_| RemoteLogError::SendError)?;
+                }
+                Ok(())
+            }
+            Transport::Sender(sender) => {
+                sender(lines.join("\n").as_bytes()).map_err(|_| RemoteLogError::SendError)
+            }
+        }
+    }
+}
+
+impl RemoteLogger {
+    /// Creates a new RemoteLogger that sends every record as a syslog datagram to a UDP collector.
+    /// By default records of level Info and above are sent, at most 20 per second, without batching.
+    ///
+    /// # Arguments
+    ///
+    /// - `address`: The address of the collector, for example "192.168.0.10:514"
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new RemoteLogger, or a `RemoteLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `RemoteLogError::InvalidAddress`: If the address could not be resolved
+    /// - `RemoteLogError::SendError`: If the socket could not be created
+    pub fn syslog(address: &str) -> Result<Self, RemoteLogError> {
+        let address = address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or(RemoteLogError::InvalidAddress)?;
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(|_| RemoteLogError::SendError)?;
+        Ok(Self::with_transport(Transport::Syslog { socket, address }))
+    }
+
+    /// Creates a new RemoteLogger that passes each batch of records to a closure, with one record
+    /// per line in the syslog format. By default records of level Info and above are sent, at most
+    /// 20 per second, without batching.
+    ///
+    /// # Arguments
+    ///
+    /// - `sender`: A closure that ships the batch, for example publishing it on an MQTT topic. It
+    ///   must not block for long, since it is called from the thread that logs
+    ///
+    /// # Returns
+    ///
+    /// The new RemoteLogger
+    pub fn with_sender<S: FnMut(&[u8]) -> Result<(), ()> + Send + 'static>(sender: S) -> Self {
+        Self::with_transport(Transport::Sender(Box::new(sender)))
+    }
+
+    /// Creates a new RemoteLogger with the default configuration
+    fn with_transport(transport: Transport) -> Self {
+        RemoteLogger {
+            transport,
+            config: LogConfig {
+                hostname: DEFAULT_HOSTNAME.to_string(),
+                app_name: DEFAULT_APP_NAME.to_string(),
+                level: LevelFilter::Info,
+                max_records_per_second: DEFAULT_MAX_RECORDS_PER_SECOND,
+                batch_size: DEFAULT_BATCH_SIZE,
+                max_batch_delay: DEFAULT_MAX_BATCH_DELAY,
+                echo: true,
+            },
+        }
+    }
+
+    /// Sets the hostname sent on every record, used by the collector to tell the devices apart
+    ///
+    /// # Arguments
+    ///
+    /// - `hostname`: The name of the device. Spaces are replaced by underscores
+    ///
+    /// # Returns
+    ///
+    /// The RemoteLogger itself
+    pub fn hostname(mut self, hostname: &str) -> Self {
+        self.config.hostname = hostname.replace(' ', "_");
+        self
+    }
+
+    /// Sets the application name sent on every record
+    ///
+    /// # Arguments
+    ///
+    /// - `app_name`: The name of the application. Spaces are replaced by underscores
+    ///
+    /// # Returns
+    ///
+    /// The RemoteLogger itself
+    pub fn app_name(mut self, app_name: &str) -> Self {
+        self.config.app_name = app_name.replace(' ', "_");
+        self
+    }
+
+    /// Sets the minimum level of the records sent
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The LevelFilter to use
+    ///
+    /// # Returns
+    ///
+    /// The RemoteLogger itself
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.config.level = level;
+        self
+    }
+
+    /// Sets the max amount of records sent per second. Records over the limit are dropped, and the
+    /// amount dropped is reported on the next record sent.
+    ///
+    /// # Arguments
+    ///
+    /// - `max_records_per_second`: The max amount of records, 0 for no limit
+    ///
+    /// # Returns
+    ///
+    /// The RemoteLogger itself
+    pub fn rate_limit(mut self, max_records_per_second: u32) -> Self {
+        self.config.max_records_per_second = max_records_per_second;
+        self
+    }
+
+    /// Sets the records to accumulate before sending them. A batch is also sent when its oldest
+    /// record waited the max delay, or when the logger is flushed.
+    ///
+    /// # Arguments
+    ///
+    /// - `batch_size`: The amount of records of each batch
+    /// - `max_delay`: The max time a record waits to be sent. The delay is only checked when a new
+    ///   record is logged
+    ///
+    /// # Returns
+    ///
+    /// The RemoteLogger itself
+    pub fn batching(mut self, batch_size: usize, max_delay: Duration) -> Self {
+        self.config.batch_size = batch_size.max(1);
+        self.config.max_batch_delay = max_delay;
+        self
+    }
+
+    /// Sets whether the records are also printed on the serial console
+    ///
+    /// # Arguments
+    ///
+    /// - `echo`: True to print the records
+    ///
+    /// # Returns
+    ///
+    /// The RemoteLogger itself
+    pub fn echo_to_console(mut self, echo: bool) -> Self {
+        self.config.echo = echo;
+        self
+    }
+
+    /// Installs the logger as the logger of the `log` crate. Only one logger can be installed during
+    /// the execution of the program.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the RemoteLogHandle of the logger, or a `RemoteLogError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `RemoteLogError::AlreadyInitialized`: If a logger was already installed
+    pub fn install(self) -> Result<RemoteLogHandle, RemoteLogError> {
+        let level = self.config.level;
+        let sink: &'static RemoteLogSink = Box::leak(Box::new(RemoteLogSink {
+            config: self.config,
+            transport: Mutex::new(self.transport),
+            pending: Mutex::new(PendingRecords {
+                lines: vec![],
+                oldest: None,
+                window_start: Instant::now(),
+                window_records: 0,
+                dropped_since_sent: 0,
+            }),
+            dropped: AtomicU32::new(0),
+        }));
+        log::set_logger(sink).map_err(|_| RemoteLogError::AlreadyInitialized)?;
+        log::set_max_level(level);
+        Ok(RemoteLogHandle { sink })
+    }
+}
+
+impl LogConfig {
+    /// Formats a record as a syslog line
+    ///
+    /// # Arguments
+    ///
+    /// - `level`: The Level of the record
+    /// - `target`: The module that logged the record
+    /// - `message`: The content of the record
+    ///
+    /// # Returns
+    ///
+    /// The syslog line
+    fn format(&self, level: Level, target: &str, message: &str) -> String {
+        let severity = match level {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        format!(
+            "<{}>1 - {} {} - - - [{}] {}",
+            SYSLOG_FACILITY_USER * 8 + severity,
+            self.hostname,
+            self.app_name,
+            target,
+            message
+        )
+    }
+}
+
+impl PendingRecords {
+    /// Counts a record on the rate limit
+    ///
+    /// # Arguments
+    ///
+    /// - `max_records_per_second`: The max amount of records per second, 0 for no limit
+    ///
+    /// # Returns
+    ///
+    /// True if the record is within the limit
+    fn within_rate_limit(&mut self, max_records_per_second: u32) -> bool {
+        if max_records_per_second == 0 {
+            return true;
+        }
+        if self.window_start.elapsed() >= RATE_LIMIT_WINDOW {
+            self.window_start = Instant::now();
+            self.window_records = 0;
+        }
+        self.window_records += 1;
+        self.window_records <= max_records_per_second
+    }
+
+    /// Takes the lines to send, adding a line with the amount of records dropped since the last
+    /// batch sent
+    ///
+    /// # Arguments
+    ///
+    /// - `config`: The configuration of the logger
+    ///
+    /// # Returns
+    ///
+    /// The lines to send
+    fn take_batch(&mut self, config: &LogConfig) -> Vec<String> {
+        if self.dropped_since_sent > 0 {
+            let message = format!("{} log records dropped", self.dropped_since_sent);
+            let line = config.format(Level::Warn, module_path!(), &message);
+            self.lines.push(line);
+            self.dropped_since_sent = 0;
+        }
+        self.oldest = None;
+        mem::take(&mut self.lines)
+    }
+
+    /// Puts back lines that could not be sent before the lines logged in the meantime, dropping
+    /// the oldest ones if there are too many
+    ///
+    /// # Arguments
+    ///
+    /// - `lines`: The lines that could not be sent
+    /// - `max_lines`: The max amount of lines kept
+    ///
+    /// # Returns
+    ///
+    /// The amount of lines dropped
+    fn restore(&mut self, mut lines: Vec<String>, max_lines: usize) -> u32 {
+        lines.append(&mut self.lines);
+        let excess = lines.len().saturating_sub(max_lines);
+        lines.drain(..excess);
+        self.lines = lines;
+        self.oldest.get_or_insert_with(Instant::now);
+        self.dropped_since_sent += excess as u32;
+        excess as u32
+    }
+}
+
+impl RemoteLogSink {
+    /// Sends the pending records. If the transport is in use, by another thread or because sending
+    /// logged a record, they are kept to be sent with the next batch.
+    fn send_pending(&self) {
+        let lines = match self.pending.lock() {
+            Ok(mut pending) if !pending.lines.is_empty() || pending.dropped_since_sent > 0 => {
+                pending.take_batch(&self.config)
+            }
+            _ => return,
+        };
+
+        let res = match self.transport.try_lock() {
+            Ok(mut transport) => transport.send(&lines),
+            Err(_) => Err(RemoteLogError::SendError),
+        };
+        if res.is_ok() {
+            return;
+        }
+        let max_lines = self.config.batch_size * MAX_PENDING_BATCHES;
+        if let Ok(mut pending) = self.pending.lock() {
+            let dropped = pending.restore(lines, max_lines);
+            self.dropped.fetch_add(dropped, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Log for RemoteLogSink {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.config.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = record.args().to_string();
+        if self.config.echo {
+            println!("{} [{}] {}", record.level(), record.target(), message);
+        }
+
+        let line = self
+            .config
+            .format(record.level(), record.target(), &message);
+        let batch_due = {
+            let Ok(mut pending) = self.pending.lock() else {
+                return;
+            };
+            if !pending.within_rate_limit(self.config.max_records_per_second) {
+                pending.dropped_since_sent += 1;
+                self.dropped.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+            pending.lines.push(line);
+            let oldest = *pending.oldest.get_or_insert_with(Instant::now);
+            pending.lines.len() >= self.config.batch_size
+                || oldest.elapsed() >= self.config.max_batch_delay
+        };
+        if batch_due {
+            self.send_pending();
+        }
+    }
+
+    fn flush(&self) {
+        self.send_pending();
+    }
+}
+
+impl RemoteLogHandle {
+    /// Sends the records waiting for their batch to complete, for example before entering deep sleep
+    pub fn flush(&self) {
+        self.sink.send_pending();
+    }
+
+    /// Gets the amount of records lost, because of the rate limit or because they could not be sent
+    ///
+    /// # Returns
+    ///
+    /// The amount of dropped records
+    pub fn dropped(&self) -> u32 {
+        self.sink.dropped.load(Ordering::SeqCst)
+    }
+}
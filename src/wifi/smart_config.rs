@@ -0,0 +1,366 @@
+use super::WifiError;
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        notification::Notifier,
+    },
+    InterruptDriver,
+};
+use esp_idf_svc::sys::{
+    esp_event_base_t, esp_event_handler_register, esp_event_handler_unregister,
+    esp_smartconfig_set_type, esp_smartconfig_start, esp_smartconfig_stop,
+    smartconfig_event_got_ssid_pswd_t, smartconfig_event_t_SC_EVENT_GOT_SSID_PSWD,
+    smartconfig_event_t_SC_EVENT_SEND_ACK_DONE, smartconfig_start_config_t, smartconfig_type_t,
+    smartconfig_type_t_SC_TYPE_AIRKISS, smartconfig_type_t_SC_TYPE_ESPTOUCH,
+    smartconfig_type_t_SC_TYPE_ESPTOUCH_AIRKISS, smartconfig_type_t_SC_TYPE_ESPTOUCH_V2,
+    ESP_EVENT_ANY_ID, ESP_OK, SC_EVENT,
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    ffi::c_void,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT_THREAD_STACK_SIZE: usize = 2 * 1024;
+
+/// Events of the SmartConfig event handler, and the notifier to wake up the update loop. The
+/// handler is registered without user data, since only one SmartConfig can run at a time.
+static SMART_CONFIG_CHANNEL: Mutex<Option<(SmartConfigEvents, Notifier)>> = Mutex::new(None);
+
+type CredentialsCallback<'a> = dyn FnMut(&SmartConfigCredentials) + 'a;
+type TimeoutCallback<'a> = dyn FnMut() + 'a;
+
+/// Enums the protocols the phone app can use to send the credentials:
+/// - `EspTouch`: The protocol of the Espressif ESP-Touch app.
+/// - `EspTouchV2`: The second version of ESP-Touch, which is faster and more reliable.
+/// - `AirKiss`: The protocol of the WeChat AirKiss.
+/// - `EspTouchAirKiss`: Accepts both ESP-Touch and AirKiss.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SmartConfigType {
+    EspTouch,
+    EspTouchV2,
+    AirKiss,
+    EspTouchAirKiss,
+}
+
+/// Credentials of a network, sent by the phone app:
+/// - `ssid`: The name of the network.
+/// - `password`: The password of the network, empty if it is open.
+/// - `bssid`: The MAC address of the access point the phone is connected to, if it was sent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SmartConfigCredentials {
+    pub ssid: String,
+    pub password: String,
+    pub bssid: Option<[u8; 6]>,
+}
+
+/// Events received by the SmartConfig event handler, waiting for the update loop:
+/// - `credentials`: The credentials received, if any.
+/// - `acknowledged`: Whether the phone app was told the device connected.
+#[derive(Default)]
+struct SmartConfigEvents {
+    credentials: Option<SmartConfigCredentials>,
+    acknowledged: bool,
+}
+
+/// SmartConfig provisioning, where a phone app, such as ESP-Touch, sends the credentials of the
+/// network it is connected to, encoded in the length of the packets it broadcasts. The device
+/// does not need to be connected to any network, nor host one, so it is an alternative to
+/// provisioning through an access point or BLE.
+///
+/// Once the credentials arrive, the callback set with [SmartConfig::on_credentials] must connect
+/// to the network with them, for example with [crate::wifi::WifiDriver::connect]. The app is told
+/// the device connected once it gets an ip address, and then SmartConfig stops.
+struct _SmartConfig<'a> {
+    deadline: Instant,
+    running: bool,
+    credentials_callback: Option<Box<CredentialsCallback<'a>>>,
+    timeout_callback: Option<Box<TimeoutCallback<'a>>>,
+}
+
+/// SmartConfig provisioning, where a phone app, such as ESP-Touch, sends the credentials of the
+/// network it is connected to, encoded in the length of the packets it broadcasts. The device
+/// does not need to be connected to any network, nor host one, so it is an alternative to
+/// provisioning through an access point or BLE.
+///
+/// Once the credentials arrive, the callback set with [SmartConfig::on_credentials] must connect
+/// to the network with them, for example with [crate::wifi::WifiDriver::connect]. The app is told
+/// the device connected once it gets an ip address, and then SmartConfig stops.
+pub struct SmartConfig<'a> {
+    inner: SharableRef<_SmartConfig<'a>>,
+}
+
+impl From<SmartConfigType> for smartconfig_type_t {
+    fn from(value: SmartConfigType) -> Self {
+        match value {
+            SmartConfigType::EspTouch => smartconfig_type_t_SC_TYPE_ESPTOUCH,
+            SmartConfigType::EspTouchV2 => smartconfig_type_t_SC_TYPE_ESPTOUCH_V2,
+            SmartConfigType::AirKiss => smartconfig_type_t_SC_TYPE_AIRKISS,
+            SmartConfigType::EspTouchAirKiss => smartconfig_type_t_SC_TYPE_ESPTOUCH_AIRKISS,
+        }
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _SmartConfig<'a> {
+    /// Creates a new _SmartConfig and starts listening for the credentials. The wifi must
+    /// already be started in station mode.
+    ///
+    /// # Arguments
+    ///
+    /// - `smart_config_type`: The protocol used by the phone app
+    /// - `timeout`: The maximum time to wait for the credentials
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when the credentials arrive
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new _SmartConfig, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::StartingError`: If SmartConfig could not be started, for example if it is
+    ///   already running
+    fn new(
+        smart_config_type: SmartConfigType,
+        timeout: Duration,
+        notifier: Notifier,
+    ) -> Result<Self, WifiError> {
+        let mut channel = SMART_CONFIG_CHANNEL
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        if channel.is_some() {
+            return Err(WifiError::StartingError);
+        }
+        *channel = Some((SmartConfigEvents::default(), notifier.clone()));
+        drop(channel);
+
+        let timeout_notifier = notifier;
+        thread::Builder::new()
+            .stack_size(TIMEOUT_THREAD_STACK_SIZE)
+            .spawn(move || {
+                thread::sleep(timeout);
+                timeout_notifier.notify();
+            })
+            .map_err(|_| {
+                stop_smart_config();
+                WifiError::StartingError
+            })?;
+
+        let config = smartconfig_start_config_t {
+            enable_log: false,
+            esp_touch_v2_enable_crypt: false,
+            esp_touch_v2_key: std::ptr::null_mut(),
+        };
+        let started = unsafe {
+            esp_event_handler_register(
+                SC_EVENT,
+                ESP_EVENT_ANY_ID,
+                Some(smart_config_event_handler),
+                std::ptr::null_mut(),
+            ) == ESP_OK
+                && esp_smartconfig_set_type(smart_config_type.into()) == ESP_OK
+                && esp_smartconfig_start(&config) == ESP_OK
+        };
+        if !started {
+            stop_smart_config();
+            return Err(WifiError::StartingError);
+        }
+
+        Ok(_SmartConfig {
+            deadline: Instant::now() + timeout,
+            running: true,
+            credentials_callback: None,
+            timeout_callback: None,
+        })
+    }
+
+    /// Sets the callback that will be executed when the credentials arrive. It must connect to
+    /// the network, so the phone app is told the provisioning succeeded.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the SmartConfigCredentials
+    ///
+    /// # Returns
+    ///
+    /// The _SmartConfig itself
+    pub fn on_credentials<C: FnMut(&SmartConfigCredentials) + 'a>(
+        &mut self,
+        callback: C,
+    ) -> &mut Self {
+        self.credentials_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets the callback that will be executed if the credentials do not arrive before the
+    /// timeout. SmartConfig is stopped before executing it.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure executed on the timeout
+    ///
+    /// # Returns
+    ///
+    /// The _SmartConfig itself
+    pub fn on_timeout<C: FnMut() + 'a>(&mut self, callback: C) -> &mut Self {
+        self.timeout_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Checks if SmartConfig is still running
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether SmartConfig is listening for the credentials, or waiting to
+    /// tell the phone app the device connected
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Stops SmartConfig, without executing any callback
+    pub fn stop(&mut self) {
+        if self.running {
+            self.running = false;
+            stop_smart_config();
+        }
+    }
+
+    /// Handles the events received since the last call, and the timeout
+    fn handle_events(&mut self) {
+        if !self.running {
+            return;
+        }
+        let (credentials, acknowledged) = match SMART_CONFIG_CHANNEL
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .as_mut()
+        {
+            Some((events, _)) => (events.credentials.take(), events.acknowledged),
+            None => (None, false),
+        };
+
+        if let Some(credentials) = credentials {
+            self.timeout_callback = None;
+            if let Some(callback) = self.credentials_callback.as_mut() {
+                callback(&credentials);
+            }
+        }
+        if acknowledged {
+            self.stop();
+        } else if Instant::now() >= self.deadline {
+            if let Some(mut callback) = self.timeout_callback.take() {
+                self.stop();
+                callback();
+            }
+        }
+    }
+}
+
+impl<'a> SmartConfig<'a> {
+    /// Creates a new SmartConfig and starts listening for the credentials. The wifi must
+    /// already be started in station mode.
+    ///
+    /// # Arguments
+    ///
+    /// - `smart_config_type`: The protocol used by the phone app
+    /// - `timeout`: The maximum time to wait for the credentials
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when the credentials arrive
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new SmartConfig, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::StartingError`: If SmartConfig could not be started, for example if it is
+    ///   already running
+    pub(crate) fn new(
+        smart_config_type: SmartConfigType,
+        timeout: Duration,
+        notifier: Notifier,
+    ) -> Result<Self, WifiError> {
+        Ok(Self {
+            inner: SharableRef::new_sharable(_SmartConfig::new(
+                smart_config_type,
+                timeout,
+                notifier,
+            )?),
+        })
+    }
+}
+
+impl<'a> InterruptDriver<'a> for SmartConfig<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().handle_events();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// Stops SmartConfig
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.stop();
+        Ok(())
+    }
+}
+
+/// Stops SmartConfig and unregisters its event handler
+fn stop_smart_config() {
+    unsafe {
+        esp_smartconfig_stop();
+        esp_event_handler_unregister(SC_EVENT, ESP_EVENT_ANY_ID, Some(smart_config_event_handler));
+    }
+    *SMART_CONFIG_CHANNEL
+        .lock()
+        .unwrap_or_else(|err| err.into_inner()) = None;
+}
+
+/// Reads a string sent by the phone app, which ends at the first null byte
+fn string_until_nul(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Event handler executed by the event loop task for each SmartConfig event. It stores the
+/// credentials and the acknowledgement, and wakes up the microcontroller.
+unsafe extern "C" fn smart_config_event_handler(
+    _arg: *mut c_void,
+    _event_base: esp_event_base_t,
+    event_id: i32,
+    event_data: *mut c_void,
+) {
+    let mut channel = SMART_CONFIG_CHANNEL
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    let Some((events, notifier)) = channel.as_mut() else {
+        return;
+    };
+    match event_id as u32 {
+        smartconfig_event_t_SC_EVENT_GOT_SSID_PSWD if !event_data.is_null() => {
+            let data = &*(event_data as *const smartconfig_event_got_ssid_pswd_t);
+            events.credentials = Some(SmartConfigCredentials {
+                ssid: string_until_nul(&data.ssid),
+                password: string_until_nul(&data.password),
+                bssid: data.bssid_set.then_some(data.bssid),
+            });
+        }
+        smartconfig_event_t_SC_EVENT_SEND_ACK_DONE => events.acknowledged = true,
+        _ => return,
+    }
+    notifier.notify();
+}
@@ -0,0 +1,398 @@
+use super::WifiError;
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        isr_queues::{ISRByteArrayQueue, ISRQueueTrait},
+        notification::Notifier,
+        queue_config::queue_capacities,
+        timer_driver::TimerDriver,
+    },
+    InterruptDriver,
+};
+use esp_idf_svc::sys::{
+    esp_wifi_set_channel, esp_wifi_set_promiscuous, esp_wifi_set_promiscuous_filter,
+    esp_wifi_set_promiscuous_rx_cb, wifi_pkt_rx_ctrl_t, wifi_promiscuous_filter_t,
+    wifi_promiscuous_pkt_t, wifi_promiscuous_pkt_type_t, wifi_second_chan_t_WIFI_SECOND_CHAN_NONE,
+    ESP_OK, WIFI_PROMIS_FILTER_MASK_CTRL, WIFI_PROMIS_FILTER_MASK_DATA,
+    WIFI_PROMIS_FILTER_MASK_MGMT, WIFI_PROMIS_FILTER_MASK_MISC,
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{ffi::c_void, sync::Mutex, time::Duration};
+
+const FRAME_HEADER_SIZE: usize = 3;
+const MIN_CHANNEL: u8 = 1;
+const MAX_CHANNEL: u8 = 13;
+
+/// Queue and notifier used by the promiscuous callback, which does not receive any user data
+static SNIFFER_CHANNEL: Mutex<Option<(ISRByteArrayQueue, Notifier)>> = Mutex::new(None);
+
+type FrameCallback<'a> = dyn FnMut(&SniffedFrame) + 'a;
+
+/// Enums the types of 802.11 frames that can be captured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameType {
+    Management,
+    Control,
+    Data,
+    Misc,
+}
+
+/// A frame captured in promiscuous mode:
+/// - `frame_type`: The type of the frame.
+/// - `channel`: The channel where the frame was captured.
+/// - `rssi`: The signal strength of the frame.
+/// - `data`: The raw 802.11 frame, starting from the MAC header.
+#[derive(Debug, Clone)]
+pub struct SniffedFrame {
+    pub frame_type: FrameType,
+    pub channel: u8,
+    pub rssi: i8,
+    pub data: Vec<u8>,
+}
+
+/// Driver to capture 802.11 frames in promiscuous mode. Frames are received through a callback
+/// executed in the update loop of the microcontroller.
+struct _WifiSniffer<'a> {
+    timer_driver: TimerDriver<'a>,
+    frame_queue: ISRByteArrayQueue,
+    notifier: Notifier,
+    filter: u32,
+    user_callback: Option<Box<FrameCallback<'a>>>,
+}
+
+/// Driver to capture 802.11 frames in promiscuous mode. Frames are received through a callback
+/// executed in the update loop of the microcontroller.
+pub struct WifiSniffer<'a> {
+    inner: SharableRef<_WifiSniffer<'a>>,
+}
+
+impl FrameType {
+    /// Gets the promiscuous filter mask of the frame type
+    fn get_mask(&self) -> u32 {
+        match self {
+            FrameType::Management => WIFI_PROMIS_FILTER_MASK_MGMT,
+            FrameType::Control => WIFI_PROMIS_FILTER_MASK_CTRL,
+            FrameType::Data => WIFI_PROMIS_FILTER_MASK_DATA,
+            FrameType::Misc => WIFI_PROMIS_FILTER_MASK_MISC,
+        }
+    }
+
+    /// Gets the code used to send the frame type through the queue
+    fn get_code(&self) -> u8 {
+        match self {
+            FrameType::Management => 0,
+            FrameType::Control => 1,
+            FrameType::Data => 2,
+            FrameType::Misc => 3,
+        }
+    }
+
+    /// Creates a FrameType from the code used to send it through the queue
+    fn from_code(code: u8) -> Self {
+        match code {
+            0 => FrameType::Management,
+            1 => FrameType::Control,
+            2 => FrameType::Data,
+            _ => FrameType::Misc,
+        }
+    }
+}
+
+impl SniffedFrame {
+    /// Decodes a frame received through the queue. The first bytes are the type, channel and rssi,
+    /// the rest is the frame itself.
+    fn from_queue_bytes(bytes: Vec<u8>) -> Option<Self> {
+        if bytes.len() < FRAME_HEADER_SIZE {
+            return None;
+        }
+        Some(SniffedFrame {
+            frame_type: FrameType::from_code(bytes[0]),
+            channel: bytes[1],
+            rssi: bytes[2] as i8,
+            data: bytes[FRAME_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _WifiSniffer<'a> {
+    /// Creates a new _WifiSniffer that captures every type of frame
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used for the channel hopping
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a frame is captured
+    ///
+    /// # Returns
+    ///
+    /// The new _WifiSniffer
+    fn new(timer_driver: TimerDriver<'a>, notifier: Notifier) -> Self {
+        _WifiSniffer {
+            timer_driver,
+            frame_queue: ISRByteArrayQueue::new(queue_capacities().sniffer_frames),
+            notifier,
+            filter: WIFI_PROMIS_FILTER_MASK_MGMT
+                | WIFI_PROMIS_FILTER_MASK_CTRL
+                | WIFI_PROMIS_FILTER_MASK_DATA
+                | WIFI_PROMIS_FILTER_MASK_MISC,
+            user_callback: None,
+        }
+    }
+
+    /// Sets the callback that will be executed for each captured frame.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives each captured frame
+    ///
+    /// # Returns
+    ///
+    /// The _WifiSniffer itself
+    pub fn on_frame<C: FnMut(&SniffedFrame) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.user_callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets which types of frames will be captured. By default every type is captured.
+    ///
+    /// # Arguments
+    ///
+    /// - `frame_types`: The types of frames to capture
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the filter was set, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::PromiscuousError`: If the filter could not be set
+    pub fn set_filter(&mut self, frame_types: &[FrameType]) -> Result<(), WifiError> {
+        self.filter = frame_types.iter().fold(0, |mask, t| mask | t.get_mask());
+        let filter = wifi_promiscuous_filter_t {
+            filter_mask: self.filter,
+        };
+        match unsafe { esp_wifi_set_promiscuous_filter(&filter) } {
+            ESP_OK => Ok(()),
+            _ => Err(WifiError::PromiscuousError),
+        }
+    }
+
+    /// Sets the channel where frames are captured. Stops any channel hopping.
+    ///
+    /// # Arguments
+    ///
+    /// - `channel`: The channel, from 1 to 13
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the channel was set, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If the channel is invalid or could not be set
+    /// - `WifiError::TimerDriverError`: If the channel hopping could not be stopped
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), WifiError> {
+        self.stop_channel_hopping()?;
+        set_channel(channel)
+    }
+
+    /// Periodically changes the channel where frames are captured, going through every channel received.
+    ///
+    /// # Arguments
+    ///
+    /// - `channels`: The channels to go through, from 1 to 13
+    /// - `dwell_time`: Time spent on each channel
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the channel hopping started, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If any channel is invalid or could not be set
+    /// - `WifiError::TimerDriverError`: If the timer driver could not be enabled
+    pub fn start_channel_hopping(
+        &mut self,
+        channels: Vec<u8>,
+        dwell_time: Duration,
+    ) -> Result<(), WifiError> {
+        if channels
+            .iter()
+            .any(|c| !(MIN_CHANNEL..=MAX_CHANNEL).contains(c))
+        {
+            return Err(WifiError::ConfigurationError);
+        }
+        let first_channel = *channels.first().ok_or(WifiError::ConfigurationError)?;
+        set_channel(first_channel)?;
+
+        let mut i = 0;
+        self.timer_driver.interrupt_after_n_times(
+            dwell_time.as_micros().try_into().unwrap_or(u64::MAX),
+            None,
+            true,
+            move || {
+                i = (i + 1) % channels.len();
+                _ = set_channel(channels[i]);
+            },
+        );
+        Ok(self.timer_driver.enable()?)
+    }
+
+    /// Stops the channel hopping, staying on the current channel
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the channel hopping stopped, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::TimerDriverError`: If the timer driver could not be disabled
+    pub fn stop_channel_hopping(&mut self) -> Result<(), WifiError> {
+        Ok(self.timer_driver.disable()?)
+    }
+
+    /// Starts capturing frames.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if promiscuous mode was enabled, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::PromiscuousError`: If promiscuous mode could not be enabled
+    pub fn start(&mut self) -> Result<(), WifiError> {
+        *SNIFFER_CHANNEL.lock().unwrap() = Some((self.frame_queue.clone(), self.notifier.clone()));
+        let filter = wifi_promiscuous_filter_t {
+            filter_mask: self.filter,
+        };
+        unsafe {
+            if esp_wifi_set_promiscuous_rx_cb(Some(promiscuous_callback)) != ESP_OK
+                || esp_wifi_set_promiscuous_filter(&filter) != ESP_OK
+                || esp_wifi_set_promiscuous(true) != ESP_OK
+            {
+                return Err(WifiError::PromiscuousError);
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops capturing frames and any channel hopping.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if promiscuous mode was disabled, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::PromiscuousError`: If promiscuous mode could not be disabled
+    /// - `WifiError::TimerDriverError`: If the channel hopping could not be stopped
+    pub fn stop(&mut self) -> Result<(), WifiError> {
+        self.stop_channel_hopping()?;
+        if unsafe { esp_wifi_set_promiscuous(false) } != ESP_OK {
+            return Err(WifiError::PromiscuousError);
+        }
+        *SNIFFER_CHANNEL.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// Executes the user callback once for each frame captured since the last call
+    fn handle_frames(&mut self) {
+        while let Ok(bytes) = self.frame_queue.try_recv() {
+            if let (Some(frame), Some(callback)) = (
+                SniffedFrame::from_queue_bytes(bytes),
+                self.user_callback.as_mut(),
+            ) {
+                callback(&frame);
+            }
+        }
+    }
+}
+
+impl<'a> WifiSniffer<'a> {
+    /// Creates a new WifiSniffer that captures every type of frame
+    ///
+    /// # Arguments
+    ///
+    /// - `timer_driver`: A TimerDriver used for the channel hopping
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a frame is captured
+    ///
+    /// # Returns
+    ///
+    /// The new WifiSniffer
+    pub(crate) fn new(timer_driver: TimerDriver<'a>, notifier: Notifier) -> Self {
+        Self {
+            inner: SharableRef::new_sharable(_WifiSniffer::new(timer_driver, notifier)),
+        }
+    }
+}
+
+impl<'a> InterruptDriver<'a> for WifiSniffer<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().handle_frames();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+
+    /// Stops capturing frames
+    fn teardown(&mut self) -> Result<(), Esp32FrameworkError> {
+        Ok(self.stop()?)
+    }
+}
+
+/// Sets the primary channel of the wifi
+///
+/// # Arguments
+///
+/// - `channel`: The channel, from 1 to 13
+///
+/// # Returns
+///
+/// A `Result` with Ok if the channel was set, or a `WifiError` if it fails.
+///
+/// # Errors
+///
+/// - `WifiError::ConfigurationError`: If the channel is invalid or could not be set
+fn set_channel(channel: u8) -> Result<(), WifiError> {
+    if !(MIN_CHANNEL..=MAX_CHANNEL).contains(&channel) {
+        return Err(WifiError::ConfigurationError);
+    }
+    match unsafe { esp_wifi_set_channel(channel, wifi_second_chan_t_WIFI_SECOND_CHAN_NONE) } {
+        ESP_OK => Ok(()),
+        _ => Err(WifiError::ConfigurationError),
+    }
+}
+
+/// Callback executed by the wifi driver for each captured frame. It sends the frame through
+/// the queue, prefixed with its type, channel and rssi, and wakes up the microcontroller.
+unsafe extern "C" fn promiscuous_callback(buf: *mut c_void, pkt_type: wifi_promiscuous_pkt_type_t) {
+    let Ok(mut channel) = SNIFFER_CHANNEL.try_lock() else {
+        return;
+    };
+    let Some((queue, notifier)) = channel.as_mut() else {
+        return;
+    };
+    let packet = &*(buf as *const wifi_promiscuous_pkt_t);
+    let rx_ctrl: &wifi_pkt_rx_ctrl_t = &packet.rx_ctrl;
+    let len = rx_ctrl.sig_len() as usize;
+    let payload = std::slice::from_raw_parts(packet.payload.as_ptr(), len);
+
+    let mut bytes = Vec::with_capacity(FRAME_HEADER_SIZE + len);
+    bytes.push(FrameType::from_code(pkt_type as u8).get_code());
+    bytes.push(rx_ctrl.channel() as u8);
+    bytes.push(rx_ctrl.rssi() as i8 as u8);
+    bytes.extend_from_slice(payload);
+
+    if queue.try_send(bytes).is_ok() {
+        notifier.notify();
+    }
+}
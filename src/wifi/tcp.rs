@@ -0,0 +1,434 @@
+use std::{
+    future::poll_fn,
+    io::{self, ErrorKind, Read, Write},
+    net::{Shutdown, SocketAddr, TcpListener as StdTcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex, Once},
+    task::{Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Time between the checks of the sockets awaited by async operations
+const SOCKET_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const WATCHER_THREAD_STACK_SIZE: usize = 4 * 1024;
+const CONNECT_THREAD_STACK_SIZE: usize = 4 * 1024;
+
+/// Wakers of the tasks whose sockets were not ready, woken by the watcher thread to try again
+static PENDING_WAKERS: Mutex<Vec<Waker>> = Mutex::new(Vec::new());
+static WATCHER_STARTED: Once = Once::new();
+
+/// Outcome of a connection made on another thread, with the waker of the task awaiting it
+type PendingConnection = Arc<Mutex<(Option<Result<TcpClient, TcpError>>, Option<Waker>)>>;
+
+/// Enums the different errors possible when working with TCP sockets
+#[derive(Debug)]
+pub enum TcpError {
+    AcceptError,
+    BindError,
+    ConnectionError,
+    Disconnected,
+    InvalidAddress,
+    ReadError,
+    Timeout,
+    WriteError,
+}
+
+/// TCP connection to another device, with blocking and async reads and writes. The async
+/// versions let other tasks and the drivers keep running while waiting for the socket, when used
+/// with [crate::Microcontroller::block_on].
+pub struct TcpClient {
+    stream: TcpStream,
+    timeout: Option<Duration>,
+}
+
+/// TCP socket listening for the connections of other devices, with blocking and async accepts.
+pub struct TcpListener {
+    listener: StdTcpListener,
+}
+
+impl TcpClient {
+    /// Creates a new TcpClient connected to the address
+    ///
+    /// # Arguments
+    ///
+    /// - `address`: The address to connect to, such as "192.168.0.10:23" or "example.com:23"
+    /// - `timeout`: An `Option<Duration>` with the maximum time to wait for the connection and
+    ///   for each read and write. If it is None, they wait forever.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TcpClient, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::InvalidAddress`: If the address could not be resolved.
+    /// - `TcpError::ConnectionError`: If the connection fails.
+    /// - `TcpError::Timeout`: If the connection was not established before the timeout.
+    pub fn connect(address: &str, timeout: Option<Duration>) -> Result<Self, TcpError> {
+        let address = address
+            .to_socket_addrs()
+            .map_err(|_| TcpError::InvalidAddress)?
+            .next()
+            .ok_or(TcpError::InvalidAddress)?;
+        let stream = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&address, timeout),
+            None => TcpStream::connect(address),
+        }
+        .map_err(|err| match err.kind() {
+            ErrorKind::TimedOut | ErrorKind::WouldBlock => TcpError::Timeout,
+            _ => TcpError::ConnectionError,
+        })?;
+        Self::new(stream, timeout)
+    }
+
+    /// Async version of [Self::connect]. The connection is made on another thread, since the
+    /// address resolution and the handshake can not be done without blocking.
+    pub async fn connect_async(address: &str, timeout: Option<Duration>) -> Result<Self, TcpError> {
+        let pending: PendingConnection = Arc::new(Mutex::new((None, None)));
+        let thread_pending = pending.clone();
+        let address = address.to_string();
+        thread::Builder::new()
+            .stack_size(CONNECT_THREAD_STACK_SIZE)
+            .spawn(move || {
+                let client = Self::connect(&address, timeout);
+                let mut pending = thread_pending.lock().unwrap_or_else(|err| err.into_inner());
+                pending.0 = Some(client);
+                if let Some(waker) = pending.1.take() {
+                    waker.wake();
+                }
+            })
+            .map_err(|_| TcpError::ConnectionError)?;
+
+        poll_fn(|cx| {
+            let mut pending = pending.lock().unwrap_or_else(|err| err.into_inner());
+            match pending.0.take() {
+                Some(client) => Poll::Ready(client),
+                None => {
+                    pending.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Creates a new TcpClient from an already connected TcpStream
+    ///
+    /// # Arguments
+    ///
+    /// - `stream`: The connected TcpStream
+    /// - `timeout`: An `Option<Duration>` with the maximum time to wait for each read and write
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TcpClient, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::ConnectionError`: If the stream could not be configured.
+    fn new(stream: TcpStream, timeout: Option<Duration>) -> Result<Self, TcpError> {
+        stream
+            .set_nodelay(true)
+            .map_err(|_| TcpError::ConnectionError)?;
+        let mut client = TcpClient { stream, timeout };
+        client.set_timeout(timeout)?;
+        Ok(client)
+    }
+
+    /// Sets the maximum time to wait for each read and write
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: An `Option<Duration>` with the new timeout. If it is None, they wait forever.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the timeout was set, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::ConnectionError`: If the socket could not be configured.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), TcpError> {
+        self.stream
+            .set_read_timeout(timeout)
+            .and_then(|_| self.stream.set_write_timeout(timeout))
+            .map_err(|_| TcpError::ConnectionError)?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Gets the address of the other end of the connection
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the SocketAddr of the peer, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::Disconnected`: If the connection is closed.
+    pub fn peer_address(&self) -> Result<SocketAddr, TcpError> {
+        self.stream.peer_addr().map_err(|_| TcpError::Disconnected)
+    }
+
+    /// Reads the available bytes, waiting up to the timeout if there are none.
+    ///
+    /// # Arguments
+    ///
+    /// - `buffer`: The buffer where the read bytes are stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of bytes read, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::Timeout`: If no bytes arrived before the timeout.
+    /// - `TcpError::Disconnected`: If the connection is closed.
+    /// - `TcpError::ReadError`: If the read fails.
+    pub fn read(&mut self, buffer: &mut [u8]) -> Result<usize, TcpError> {
+        read_result(self.stream.read(buffer), buffer)
+    }
+
+    /// Async version of [Self::read]
+    pub async fn read_async(&mut self, buffer: &mut [u8]) -> Result<usize, TcpError> {
+        let result = when_ready(&mut self.stream, self.timeout, |stream| stream.read(buffer)).await;
+        read_result(result, buffer)
+    }
+
+    /// Writes some bytes, waiting up to the timeout if the send buffer is full.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes to write
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of bytes written, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::Timeout`: If no byte could be written before the timeout.
+    /// - `TcpError::Disconnected`: If the connection is closed.
+    /// - `TcpError::WriteError`: If the write fails.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, TcpError> {
+        self.stream.write(data).map_err(write_error)
+    }
+
+    /// Async version of [Self::write]
+    pub async fn write_async(&mut self, data: &[u8]) -> Result<usize, TcpError> {
+        when_ready(&mut self.stream, self.timeout, |stream| stream.write(data))
+            .await
+            .map_err(write_error)
+    }
+
+    /// Writes every byte of data.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes to write
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if every byte was written, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::Timeout`: If a write did not finish before the timeout.
+    /// - `TcpError::Disconnected`: If the connection is closed.
+    /// - `TcpError::WriteError`: If the write fails.
+    pub fn write_all(&mut self, mut data: &[u8]) -> Result<(), TcpError> {
+        while !data.is_empty() {
+            match self.write(data)? {
+                0 => return Err(TcpError::Disconnected),
+                n => data = &data[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Async version of [Self::write_all]
+    pub async fn write_all_async(&mut self, mut data: &[u8]) -> Result<(), TcpError> {
+        while !data.is_empty() {
+            match self.write_async(data).await? {
+                0 => return Err(TcpError::Disconnected),
+                n => data = &data[n..],
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes the connection. Further reads and writes will fail.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the connection was closed, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::ConnectionError`: If the connection could not be closed.
+    pub fn close(&mut self) -> Result<(), TcpError> {
+        self.stream
+            .shutdown(Shutdown::Both)
+            .map_err(|_| TcpError::ConnectionError)
+    }
+}
+
+impl TcpListener {
+    /// Creates a new TcpListener, listening for connections on the port of every network the
+    /// device is connected to.
+    ///
+    /// # Arguments
+    ///
+    /// - `port`: The port to listen on
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new TcpListener, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::BindError`: If the port is already in use, or the wifi driver was not started.
+    pub fn bind(port: u16) -> Result<Self, TcpError> {
+        let listener = StdTcpListener::bind(("0.0.0.0", port)).map_err(|_| TcpError::BindError)?;
+        Ok(TcpListener { listener })
+    }
+
+    /// Gets the port the listener is bound to, which is useful when binding to port 0
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the port, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::BindError`: If the address of the listener could not be read.
+    pub fn port(&self) -> Result<u16, TcpError> {
+        self.listener
+            .local_addr()
+            .map(|address| address.port())
+            .map_err(|_| TcpError::BindError)
+    }
+
+    /// Waits for the next connection.
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: An `Option<Duration>` with the maximum time to wait for each read and write
+    ///   of the accepted connection.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with a TcpClient connected to the other device, or a `TcpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `TcpError::AcceptError`: If the connection could not be accepted.
+    /// - `TcpError::ConnectionError`: If the accepted connection could not be configured.
+    pub fn accept(&mut self, timeout: Option<Duration>) -> Result<TcpClient, TcpError> {
+        let (stream, _) = self.listener.accept().map_err(|_| TcpError::AcceptError)?;
+        TcpClient::new(stream, timeout)
+    }
+
+    /// Async version of [Self::accept]
+    pub async fn accept_async(&mut self, timeout: Option<Duration>) -> Result<TcpClient, TcpError> {
+        self.listener
+            .set_nonblocking(true)
+            .map_err(|_| TcpError::AcceptError)?;
+        let listener = &self.listener;
+        let accepted = poll_fn(|cx| match listener.accept() {
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                wake_later(cx.waker());
+                Poll::Pending
+            }
+            accepted => Poll::Ready(accepted),
+        })
+        .await;
+        _ = self.listener.set_nonblocking(false);
+        let (stream, _) = accepted.map_err(|_| TcpError::AcceptError)?;
+        stream
+            .set_nonblocking(false)
+            .map_err(|_| TcpError::ConnectionError)?;
+        TcpClient::new(stream, timeout)
+    }
+}
+
+/// Executes an operation with the socket in non blocking mode, trying again each time the
+/// watcher thread wakes the task until the socket is ready or the timeout passes.
+///
+/// # Arguments
+///
+/// - `stream`: The socket used by the operation
+/// - `timeout`: An `Option<Duration>` with the maximum time to wait for the socket
+/// - `operation`: The read or write to execute
+///
+/// # Returns
+///
+/// The result of the operation, or an error of kind TimedOut if the timeout passed.
+async fn when_ready<T, F>(
+    stream: &mut TcpStream,
+    timeout: Option<Duration>,
+    mut operation: F,
+) -> io::Result<T>
+where
+    F: FnMut(&mut TcpStream) -> io::Result<T>,
+{
+    stream.set_nonblocking(true)?;
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let result = poll_fn(|cx| match operation(stream) {
+        Err(err) if err.kind() == ErrorKind::WouldBlock => {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Poll::Ready(Err(ErrorKind::TimedOut.into()));
+            }
+            wake_later(cx.waker());
+            Poll::Pending
+        }
+        result => Poll::Ready(result),
+    })
+    .await;
+    stream.set_nonblocking(false)?;
+    result
+}
+
+/// Registers the waker of a task whose socket was not ready, so it is woken in the next check of
+/// the watcher thread, which is started on the first use.
+pub(super) fn wake_later(waker: &Waker) {
+    WATCHER_STARTED.call_once(|| {
+        _ = thread::Builder::new()
+            .stack_size(WATCHER_THREAD_STACK_SIZE)
+            .spawn(|| loop {
+                thread::sleep(SOCKET_POLL_INTERVAL);
+                let wakers = std::mem::take(
+                    &mut *PENDING_WAKERS.lock().unwrap_or_else(|err| err.into_inner()),
+                );
+                wakers.into_iter().for_each(Waker::wake);
+            });
+    });
+    PENDING_WAKERS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .push(waker.clone());
+}
+
+/// Turns the result of a read into the result of a TcpClient read
+fn read_result(result: io::Result<usize>, buffer: &[u8]) -> Result<usize, TcpError> {
+    match result {
+        Ok(0) if !buffer.is_empty() => Err(TcpError::Disconnected),
+        Ok(n) => Ok(n),
+        Err(err) => Err(match err.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => TcpError::Timeout,
+            ErrorKind::ConnectionReset | ErrorKind::NotConnected => TcpError::Disconnected,
+            _ => TcpError::ReadError,
+        }),
+    }
+}
+
+/// Turns the error of a write into the error of a TcpClient write
+fn write_error(err: io::Error) -> TcpError {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => TcpError::Timeout,
+        ErrorKind::BrokenPipe | ErrorKind::ConnectionReset | ErrorKind::NotConnected => {
+            TcpError::Disconnected
+        }
+        _ => TcpError::WriteError,
+    }
+}
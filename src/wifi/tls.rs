@@ -0,0 +1,126 @@
+use esp_idf_svc::tls::X509;
+
+/// TLS settings of a secure connection, shared by the HTTPS, MQTTS and TLS stream clients:
+/// - `ca_certificate`: The PEM certificate of the authority that signed the certificate of the
+///   server. If it is not set, the server is verified with the certificate bundle, which has the
+///   authorities trusted by the common browsers.
+/// - `client_certificate`: The PEM certificate and private key of the device, for servers that
+///   authenticate their clients with certificates (mutual TLS).
+/// - `server_name`: The name sent in the Server Name Indication and used to verify the certificate
+///   of the server, when it is not the host being connected to, such as when connecting to an ip
+///   address.
+///
+/// Certificates are copied once and kept for the rest of the program, since the connections
+/// point to them while they are open.
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    ca_certificate: Option<&'static [u8]>,
+    client_certificate: Option<(&'static [u8], &'static [u8])>,
+    server_name: Option<String>,
+}
+
+impl TlsOptions {
+    /// Creates the default TlsOptions, which verify the server with the certificate bundle and do
+    /// not authenticate the device.
+    ///
+    /// # Returns
+    ///
+    /// The new TlsOptions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the certificate of the authority used to verify the server, instead of the
+    /// certificate bundle. It is needed for servers with self signed certificates, such as a
+    /// broker on the local network.
+    ///
+    /// # Arguments
+    ///
+    /// - `pem`: The PEM encoded certificate
+    ///
+    /// # Returns
+    ///
+    /// The TlsOptions itself
+    pub fn with_ca_certificate(mut self, pem: &[u8]) -> Self {
+        self.ca_certificate = Some(leak_pem(pem));
+        self
+    }
+
+    /// Sets the certificate and private key of the device, for servers that authenticate their
+    /// clients with certificates.
+    ///
+    /// # Arguments
+    ///
+    /// - `certificate_pem`: The PEM encoded certificate of the device
+    /// - `private_key_pem`: The PEM encoded private key of the certificate, not encrypted
+    ///
+    /// # Returns
+    ///
+    /// The TlsOptions itself
+    pub fn with_client_certificate(
+        mut self,
+        certificate_pem: &[u8],
+        private_key_pem: &[u8],
+    ) -> Self {
+        self.client_certificate = Some((leak_pem(certificate_pem), leak_pem(private_key_pem)));
+        self
+    }
+
+    /// Sets the name sent in the Server Name Indication and expected on the certificate of the
+    /// server. By default the host being connected to is used.
+    ///
+    /// # Arguments
+    ///
+    /// - `server_name`: The name of the server, such as `broker.example.com`
+    ///
+    /// # Returns
+    ///
+    /// The TlsOptions itself
+    pub fn with_server_name(mut self, server_name: &str) -> Self {
+        self.server_name = Some(server_name.to_string());
+        self
+    }
+
+    /// Checks if the server is verified with the certificate bundle, which happens when no
+    /// authority certificate was set
+    pub(crate) fn uses_certificate_bundle(&self) -> bool {
+        self.ca_certificate.is_none()
+    }
+
+    /// Gets the null terminated PEM of the authority certificate, if any
+    pub(crate) fn ca_certificate_pem(&self) -> Option<&'static [u8]> {
+        self.ca_certificate
+    }
+
+    /// Gets the authority certificate, if any
+    pub(crate) fn ca_certificate(&self) -> Option<X509<'static>> {
+        self.ca_certificate.map(X509::pem_until_nul)
+    }
+
+    /// Gets the certificate of the device, if any
+    pub(crate) fn client_certificate(&self) -> Option<X509<'static>> {
+        self.client_certificate
+            .map(|(certificate, _)| X509::pem_until_nul(certificate))
+    }
+
+    /// Gets the private key of the certificate of the device, if any
+    pub(crate) fn private_key(&self) -> Option<X509<'static>> {
+        self.client_certificate
+            .map(|(_, private_key)| X509::pem_until_nul(private_key))
+    }
+
+    /// Gets the name used for the Server Name Indication, if it is not the host
+    pub(crate) fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+}
+
+/// Copies a PEM with the null terminator mbedtls requires to parse it, keeping it for the rest of
+/// the program
+fn leak_pem(pem: &[u8]) -> &'static [u8] {
+    let mut pem = pem.to_vec();
+    if pem.last() != Some(&0) {
+        pem.push(0);
+    }
+    Box::leak(pem.into_boxed_slice())
+}
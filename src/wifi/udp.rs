@@ -0,0 +1,269 @@
+use super::tcp::wake_later;
+use std::{
+    future::poll_fn,
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4, ToSocketAddrs, UdpSocket as StdUdpSocket},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+/// Enums the different errors possible when working with UDP sockets
+#[derive(Debug)]
+pub enum UdpError {
+    BindError,
+    ConfigurationError,
+    InvalidAddress,
+    MulticastError,
+    ReadError,
+    Timeout,
+    WriteError,
+}
+
+/// UDP socket, which sends and receives datagrams without a connection. It can receive the
+/// datagrams of multicast groups and send broadcasts to the whole network, as used by discovery
+/// protocols. The async versions of its operations let other tasks and the drivers keep running
+/// while waiting for a datagram, when used with [crate::Microcontroller::block_on].
+pub struct UdpSocket {
+    socket: StdUdpSocket,
+    timeout: Option<Duration>,
+    groups: Vec<Ipv4Addr>,
+}
+
+impl UdpSocket {
+    /// Creates a new UdpSocket bound to the port on every network the device is connected to
+    ///
+    /// # Arguments
+    ///
+    /// - `port`: The port to receive datagrams on. If it is 0, a free port is chosen.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new UdpSocket, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::BindError`: If the port is already in use, or the wifi driver was not started.
+    pub fn bind(port: u16) -> Result<Self, UdpError> {
+        let socket = StdUdpSocket::bind(("0.0.0.0", port)).map_err(|_| UdpError::BindError)?;
+        Ok(UdpSocket {
+            socket,
+            timeout: None,
+            groups: vec![],
+        })
+    }
+
+    /// Gets the port the socket is bound to, which is useful when binding to port 0
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the port, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::BindError`: If the address of the socket could not be read.
+    pub fn port(&self) -> Result<u16, UdpError> {
+        self.socket
+            .local_addr()
+            .map(|address| address.port())
+            .map_err(|_| UdpError::BindError)
+    }
+
+    /// Sets the maximum time to wait for a datagram when receiving. By default it waits forever.
+    ///
+    /// # Arguments
+    ///
+    /// - `timeout`: An `Option<Duration>` with the new timeout. If it is None, it waits forever.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the timeout was set, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::ConfigurationError`: If the socket could not be configured.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> Result<(), UdpError> {
+        self.socket
+            .set_read_timeout(timeout)
+            .map_err(|_| UdpError::ConfigurationError)?;
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    /// Sends a datagram to an address
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes of the datagram
+    /// - `address`: The address to send to, such as "192.168.0.10:5000" or "example.com:5000"
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of bytes sent, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::InvalidAddress`: If the address could not be resolved.
+    /// - `UdpError::WriteError`: If the datagram could not be sent, for example if it is too long.
+    pub fn send_to(&mut self, data: &[u8], address: &str) -> Result<usize, UdpError> {
+        let address = address
+            .to_socket_addrs()
+            .map_err(|_| UdpError::InvalidAddress)?
+            .next()
+            .ok_or(UdpError::InvalidAddress)?;
+        self.socket
+            .send_to(data, address)
+            .map_err(|_| UdpError::WriteError)
+    }
+
+    /// Sends a datagram to every device of the networks the device is connected to.
+    /// Broadcasting is enabled on the socket if needed.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The bytes of the datagram
+    /// - `port`: The port to send to
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the amount of bytes sent, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::ConfigurationError`: If broadcasting could not be enabled.
+    /// - `UdpError::WriteError`: If the datagram could not be sent.
+    pub fn broadcast(&mut self, data: &[u8], port: u16) -> Result<usize, UdpError> {
+        if !self.socket.broadcast().unwrap_or(false) {
+            self.socket
+                .set_broadcast(true)
+                .map_err(|_| UdpError::ConfigurationError)?;
+        }
+        self.socket
+            .send_to(data, SocketAddrV4::new(Ipv4Addr::BROADCAST, port))
+            .map_err(|_| UdpError::WriteError)
+    }
+
+    /// Waits for a datagram, up to the timeout of the socket. If the datagram is longer than the
+    /// buffer, the rest of it is discarded.
+    ///
+    /// # Arguments
+    ///
+    /// - `buffer`: The buffer where the datagram is stored
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the length of the datagram and the address of its sender, or an
+    /// `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::Timeout`: If no datagram arrived before the timeout.
+    /// - `UdpError::ReadError`: If the datagram could not be received.
+    pub fn recv_from(&mut self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), UdpError> {
+        self.socket.recv_from(buffer).map_err(read_error)
+    }
+
+    /// Async version of [Self::recv_from]
+    pub async fn recv_from_async(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, SocketAddr), UdpError> {
+        self.socket
+            .set_nonblocking(true)
+            .map_err(|_| UdpError::ConfigurationError)?;
+        let socket = &self.socket;
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let received = poll_fn(|cx| match socket.recv_from(buffer) {
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return Poll::Ready(Err(ErrorKind::TimedOut.into()));
+                }
+                wake_later(cx.waker());
+                Poll::Pending
+            }
+            received => Poll::Ready(received),
+        })
+        .await;
+        _ = self.socket.set_nonblocking(false);
+        received.map_err(read_error)
+    }
+
+    /// Joins a multicast group, so the datagrams sent to it are received by the socket
+    ///
+    /// # Arguments
+    ///
+    /// - `group`: The address of the group, between 224.0.0.0 and 239.255.255.255
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the group was joined, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::InvalidAddress`: If the address is not a multicast address.
+    /// - `UdpError::MulticastError`: If the group could not be joined.
+    pub fn join_multicast(&mut self, group: Ipv4Addr) -> Result<(), UdpError> {
+        if !group.is_multicast() {
+            return Err(UdpError::InvalidAddress);
+        }
+        if self.groups.contains(&group) {
+            return Ok(());
+        }
+        self.socket
+            .join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+            .map_err(|_| UdpError::MulticastError)?;
+        self.groups.push(group);
+        Ok(())
+    }
+
+    /// Leaves a multicast group joined with [Self::join_multicast]
+    ///
+    /// # Arguments
+    ///
+    /// - `group`: The address of the group
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the group was left, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::MulticastError`: If the group was not joined, or could not be left.
+    pub fn leave_multicast(&mut self, group: Ipv4Addr) -> Result<(), UdpError> {
+        if !self.groups.contains(&group) {
+            return Err(UdpError::MulticastError);
+        }
+        self.socket
+            .leave_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED)
+            .map_err(|_| UdpError::MulticastError)?;
+        self.groups.retain(|joined| *joined != group);
+        Ok(())
+    }
+
+    /// Sets the amount of routers the multicast datagrams sent by the socket can cross. By
+    /// default it is 1, so they stay in the local network.
+    ///
+    /// # Arguments
+    ///
+    /// - `ttl`: The time to live of the datagrams
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if it was set, or an `UdpError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `UdpError::ConfigurationError`: If the socket could not be configured.
+    pub fn set_multicast_ttl(&mut self, ttl: u32) -> Result<(), UdpError> {
+        self.socket
+            .set_multicast_ttl_v4(ttl)
+            .map_err(|_| UdpError::ConfigurationError)
+    }
+}
+
+/// Turns the error of a receive into the error of an UdpSocket receive
+fn read_error(err: io::Error) -> UdpError {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => UdpError::Timeout,
+        _ => UdpError::ReadError,
+    }
+}
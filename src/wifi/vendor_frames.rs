@@ -0,0 +1,91 @@
+use super::{FrameType, SniffedFrame};
+
+const FRAME_CONTROL_ACTION: [u8; 2] = [0xD0, 0x00];
+const VENDOR_SPECIFIC_CATEGORY: u8 = 127;
+const BROADCAST_ADDRESS: [u8; 6] = [0xFF; 6];
+const MAC_HEADER_SIZE: usize = 24;
+const ACTION_HEADER_SIZE: usize = MAC_HEADER_SIZE + 4;
+const MAX_FRAME_SIZE: usize = 1500;
+
+/// Max amount of bytes that can be sent as the payload of a vendor specific action frame
+pub const MAX_VENDOR_PAYLOAD_SIZE: usize = MAX_FRAME_SIZE - ACTION_HEADER_SIZE;
+
+/// A broadcast vendor specific action frame (category 127):
+/// - `source`: MAC address of the sender.
+/// - `oui`: Organizationally unique identifier used to tell appart custom protocols.
+/// - `payload`: The data of the frame.
+///
+/// Frames can be sent with [super::WifiDriver::send_vendor_action_frame] and received by parsing
+/// the frames captured by a [super::WifiSniffer].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VendorActionFrame {
+    pub source: [u8; 6],
+    pub oui: [u8; 3],
+    pub payload: Vec<u8>,
+}
+
+impl VendorActionFrame {
+    /// Encodes the frame as a raw 802.11 frame addressed to broadcast. The sequence number is
+    /// left empty since it is filled by the wifi driver.
+    ///
+    /// # Returns
+    ///
+    /// A `Vec<u8>` with the raw frame
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut frame = Vec::with_capacity(ACTION_HEADER_SIZE + self.payload.len());
+        frame.extend_from_slice(&FRAME_CONTROL_ACTION);
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&BROADCAST_ADDRESS);
+        frame.extend_from_slice(&self.source);
+        frame.extend_from_slice(&BROADCAST_ADDRESS);
+        frame.extend_from_slice(&[0, 0]);
+        frame.push(VENDOR_SPECIFIC_CATEGORY);
+        frame.extend_from_slice(&self.oui);
+        frame.extend_from_slice(&self.payload);
+        frame
+    }
+
+    /// Parses a captured frame as a vendor specific action frame.
+    ///
+    /// # Arguments
+    ///
+    /// - `frame`: A frame captured by a [super::WifiSniffer]
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the VendorActionFrame, or None if the frame is not a vendor specific action frame
+    pub fn from_sniffed_frame(frame: &SniffedFrame) -> Option<Self> {
+        if frame.frame_type != FrameType::Management {
+            return None;
+        }
+        Self::from_bytes(&frame.data)
+    }
+
+    /// Parses a raw 802.11 frame as a vendor specific action frame. Trailing bytes of the
+    /// frame check sequence, if any, are kept in the payload.
+    ///
+    /// # Arguments
+    ///
+    /// - `data`: The raw frame, starting from the MAC header
+    ///
+    /// # Returns
+    ///
+    /// An `Option` with the VendorActionFrame, or None if the frame is not a vendor specific action frame
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        if data.len() < ACTION_HEADER_SIZE
+            || data[0] != FRAME_CONTROL_ACTION[0]
+            || data[MAC_HEADER_SIZE] != VENDOR_SPECIFIC_CATEGORY
+        {
+            return None;
+        }
+        let mut source = [0; 6];
+        source.copy_from_slice(&data[10..16]);
+        let mut oui = [0; 3];
+        oui.copy_from_slice(&data[MAC_HEADER_SIZE + 1..ACTION_HEADER_SIZE]);
+        Some(VendorActionFrame {
+            source,
+            oui,
+            payload: data[ACTION_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
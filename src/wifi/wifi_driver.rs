@@ -1,4 +1,13 @@
-use crate::microcontroller_src::peripherals::PeripheralError;
+use crate::{
+    microcontroller_src::peripherals::PeripheralError,
+    utils::{
+        esp32_framework_error::Esp32FrameworkError,
+        event_log::{record_event, EventKind},
+        notification::Notifier,
+        timer_driver::TimerDriverError,
+    },
+    InterruptDriver,
+};
 use esp_idf_svc::{
     eventloop::EspSystemEventLoop,
     hal::{
@@ -6,13 +15,23 @@ use esp_idf_svc::{
         task::block_on,
     },
     nvs::EspDefaultNvsPartition,
-    sys::ESP_ERR_TIMEOUT,
+    sys::{
+        esp_wifi_80211_tx, esp_wifi_get_mac, wifi_interface_t_WIFI_IF_STA, ESP_ERR_TIMEOUT, ESP_OK,
+    },
     timer::EspTaskTimerService,
-    wifi::{AccessPointInfo, AsyncWifi, AuthMethod, ClientConfiguration, Configuration, EspWifi},
+    wifi::{
+        AccessPointConfiguration, AccessPointInfo, AsyncWifi, AuthMethod, ClientConfiguration,
+        Configuration, EspWifi,
+    },
 };
 use std::{net::Ipv4Addr, time::Duration};
 
-use super::http::{Http, HttpClient, HttpsClient};
+use super::{
+    enterprise::disable_enterprise,
+    http::{Http, HttpClient, HttpsClient},
+    EnterpriseCredentials, VendorActionFrame, WifiConnectionInfo, WifiDisconnectReason,
+    WifiEventSubscriptions, WifiEvents, MAX_VENDOR_PAYLOAD_SIZE,
+};
 
 /// Error types related to WIFI operations.
 #[derive(Debug)]
@@ -21,27 +40,61 @@ pub enum WifiError {
     ConnectingError,
     ConnectionTimeout,
     DnsNotFound,
+    FrameTooLong,
     HttpError,
     InformationError,
     NvsAlreadyTaken,
     PeripheralError(PeripheralError),
+    PromiscuousError,
     StartingError,
+    TimerDriverError(TimerDriverError),
+    TransmissionError,
     WifiNotInitialized,
     ScanError,
 }
 
-/// Abstraction of an Acces Point with its basic information.
+const MIN_ACCESS_POINT_CHANNEL: u8 = 1;
+const MAX_ACCESS_POINT_CHANNEL: u8 = 13;
+const MAX_ACCESS_POINT_CLIENTS: u16 = 10;
+const MIN_ACCESS_POINT_PASSWORD_LEN: usize = 8;
+const MAX_HOSTNAME_LEN: usize = 32;
+
+/// Authentication methods an access point hosted by the device can require from its clients:
+/// - `Open`: No password is required.
+/// - `WPA2Personal`: WPA2 with a password.
+/// - `WPAWPA2Personal`: WPA or WPA2 with a password, for older clients.
+/// - `WPA3Personal`: WPA3 with a password.
+/// - `WPA2WPA3Personal`: WPA2 or WPA3 with a password.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessPointAuth {
+    Open,
+    WPA2Personal,
+    WPAWPA2Personal,
+    WPA3Personal,
+    WPA2WPA3Personal,
+}
+
+/// Abstraction of an Acces Point with its basic information:
+/// - `ssid`: The name of the network.
+/// - `bssid`: The MAC address of the access point, which tells apart the access points of a
+///   network with many of them.
+/// - `authentication_method`: The authentication method the network requires.
+/// - `signal_strength`: The RSSI of the access point, in dBm.
+/// - `channel`: The primary channel of the access point.
 #[derive(Debug)]
 pub struct AccesPoint {
     pub ssid: String,
+    pub bssid: [u8; 6],
     pub authentication_method: String,
     pub signal_strength: i8,
+    pub channel: u8,
 }
 
 impl From<AccessPointInfo> for AccesPoint {
     fn from(value: AccessPointInfo) -> Self {
         AccesPoint {
             ssid: value.ssid.to_string(),
+            bssid: value.bssid,
             authentication_method: match value.auth_method {
                 Some(AuthMethod::WEP) => String::from("WEP"),
                 Some(AuthMethod::WPA) => String::from("WPA"),
@@ -54,14 +107,38 @@ impl From<AccessPointInfo> for AccesPoint {
                 _ => String::from("None"),
             },
             signal_strength: value.signal_strength,
+            channel: value.channel,
+        }
+    }
+}
+
+impl From<AccessPointAuth> for AuthMethod {
+    fn from(value: AccessPointAuth) -> Self {
+        match value {
+            AccessPointAuth::Open => AuthMethod::None,
+            AccessPointAuth::WPA2Personal => AuthMethod::WPA2Personal,
+            AccessPointAuth::WPAWPA2Personal => AuthMethod::WPAWPA2Personal,
+            AccessPointAuth::WPA3Personal => AuthMethod::WPA3Personal,
+            AccessPointAuth::WPA2WPA3Personal => AuthMethod::WPA2WPA3Personal,
         }
     }
 }
 
 /// Abstraction of the driver that controls the wifi. It simplifies
 /// the wifi connection and the creation of an HTTP client.
+/// - `controller`: The wifi driver of esp-idf-svc.
+/// - `nvs`: The Non-Volatile Storage partition used to save the wifi configuration.
+/// - `enterprise`: The credentials of the last WPA2-Enterprise connection, kept alive since the
+///   driver points to their certificates.
+/// - `events`: The callbacks of the lifecycle events of the wifi.
+/// - `_subscriptions`: The subscriptions to the system event loop that feed `events`, dropped with
+///   the driver.
 pub struct WifiDriver<'a> {
     controller: AsyncWifi<EspWifi<'a>>,
+    nvs: EspDefaultNvsPartition,
+    enterprise: Option<EnterpriseCredentials>,
+    events: WifiEvents<'a>,
+    _subscriptions: WifiEventSubscriptions,
 }
 
 impl<'a> WifiDriver<'a> {
@@ -75,6 +152,8 @@ impl<'a> WifiDriver<'a> {
     ///
     /// - `event_loop`: Microcontroller's event loop.
     /// - `modem`: Microcontroller's modem peripheral.
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when a wifi
+    ///   event is received
     ///
     /// # Returns
     ///
@@ -88,20 +167,37 @@ impl<'a> WifiDriver<'a> {
     pub(crate) fn new(
         event_loop: EspSystemEventLoop,
         modem: modem::Modem,
+        notifier: Notifier,
     ) -> Result<Self, WifiError> {
         let nvs = EspDefaultNvsPartition::take().map_err(|_| WifiError::NvsAlreadyTaken)?;
         let timer_service = EspTaskTimerService::new().map_err(|_| WifiError::StartingError)?;
+        let (events, subscriptions) =
+            WifiEvents::new(&event_loop, notifier).map_err(|_| WifiError::StartingError)?;
         Ok(WifiDriver {
             controller: AsyncWifi::wrap(
-                EspWifi::new(modem, event_loop.clone(), Some(nvs))
+                EspWifi::new(modem, event_loop.clone(), Some(nvs.clone()))
                     .map_err(|_| WifiError::StartingError)?,
                 event_loop,
                 timer_service,
             )
             .map_err(|_| WifiError::StartingError)?,
+            nvs,
+            enterprise: None,
+            events,
+            _subscriptions: subscriptions,
         })
     }
 
+    /// Gets the Non-Volatile Storage partition taken by the driver, so other drivers can store
+    /// their own data on it.
+    ///
+    /// # Returns
+    ///
+    /// A clone of the EspDefaultNvsPartition
+    pub(crate) fn nvs_partition(&self) -> EspDefaultNvsPartition {
+        self.nvs.clone()
+    }
+
     /// Attempts a connection to the desired wifi network.
     ///
     /// If a password is passed, it connects using the WPAWPA2Personal Authentication method.
@@ -141,13 +237,73 @@ impl<'a> WifiDriver<'a> {
         timeout: Option<Duration>,
     ) -> Result<(), WifiError> {
         self.set_connection_configuration(ssid, password)?;
+        if self.enterprise.take().is_some() {
+            disable_enterprise();
+        }
 
         self.controller
             .start()
             .await
             .map_err(|_| WifiError::StartingError)?;
 
-        self._connect(timeout).await
+        self._connect(timeout).await.map_err(|err| {
+            record_event(EventKind::WifiDropped, &format!("{:?} {}", err, ssid));
+            err
+        })
+    }
+
+    /// Attempts a connection to a WPA2-Enterprise wifi network, such as eduroam, authenticating
+    /// with the credentials of an account instead of a shared password.
+    ///
+    /// If a timeout is passed it will timeout after attempting a connection for that time
+    ///
+    /// # Arguments
+    ///
+    /// - `ssid`: A &str representing the SSID to connect to.
+    /// - `credentials`: The `EnterpriseCredentials` of the account.
+    /// - `timeout`: An `Option<Duration>` that may contain the dessired timeout
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the connection completed successfully, or an `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If the configuration of the wifi driver fails, or the
+    ///   credentials are rejected by the driver.
+    /// - `WifiError::StartingError`: Error while starting wifi driver.
+    /// - `WifiError::ConnectingError`: Error while connecting to wifi, which includes the server
+    ///   rejecting the credentials.
+    /// - `WifiError::ConnectionTimeout`: TimedOut while trying to connect.
+    pub fn connect_enterprise(
+        &mut self,
+        ssid: &str,
+        credentials: EnterpriseCredentials,
+        timeout: Option<Duration>,
+    ) -> Result<(), WifiError> {
+        block_on(self.connect_enterprise_async(ssid, credentials, timeout))
+    }
+
+    /// Async version of [Self::connect_enterprise]
+    pub async fn connect_enterprise_async(
+        &mut self,
+        ssid: &str,
+        credentials: EnterpriseCredentials,
+        timeout: Option<Duration>,
+    ) -> Result<(), WifiError> {
+        self.set_client_configuration(ssid, AuthMethod::WPA2Enterprise, "")?;
+        let credentials = self.enterprise.insert(credentials);
+        credentials.apply()?;
+
+        self.controller
+            .start()
+            .await
+            .map_err(|_| WifiError::StartingError)?;
+
+        self._connect(timeout).await.map_err(|err| {
+            record_event(EventKind::WifiDropped, &format!("{:?} {}", err, ssid));
+            err
+        })
     }
 
     /// Sets the necessary configurations to attempt a connection
@@ -177,17 +333,45 @@ impl<'a> WifiDriver<'a> {
             None => AuthMethod::None,
         };
         let wifi_pass = password.unwrap_or("".to_string());
+        self.set_client_configuration(ssid, auth_method, &wifi_pass)
+    }
 
-        let wifi_configuration: Configuration = Configuration::Client(ClientConfiguration {
+    /// Sets the station configuration of the driver, keeping the access point if it was started
+    ///
+    /// # Arguments
+    ///
+    /// - `ssid`: A &str representing the SSID to connect to.
+    /// - `auth_method`: The AuthMethod of the network.
+    /// - `password`: A &str with the password of the SSID, empty if it does not have one.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the configuration completed successfully, or an `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If the configuration of the wifi driver fails.
+    fn set_client_configuration(
+        &mut self,
+        ssid: &str,
+        auth_method: AuthMethod,
+        password: &str,
+    ) -> Result<(), WifiError> {
+        let client_configuration = ClientConfiguration {
             ssid: ssid.try_into().map_err(|_| WifiError::ConfigurationError)?,
             bssid: None, // MAC address
             auth_method,
-            password: (wifi_pass.as_str())
+            password: password
                 .try_into()
                 .map_err(|_| WifiError::ConfigurationError)?,
             channel: None,
             ..Default::default()
-        });
+        };
+
+        let wifi_configuration = match self.access_point_configuration() {
+            Some(access_point) => Configuration::Mixed(client_configuration, access_point),
+            None => Configuration::Client(client_configuration),
+        };
 
         self.controller
             .set_configuration(&wifi_configuration)
@@ -230,7 +414,133 @@ impl<'a> WifiDriver<'a> {
         Ok(())
     }
 
-    /// Scans for nearby Wi-Fi networks and returns a vector of discovered access points.
+    /// Hosts a wifi network on the device, so other devices can connect to it directly, for example
+    /// to configure the device or to control it locally without a router. If the driver is
+    /// connected to a network, it stays connected, and the access point uses the channel of that
+    /// network instead of the given one. Clients get an address from the DHCP server of the device,
+    /// which can be reached at [Self::get_access_point_address_info].
+    ///
+    /// # Arguments
+    ///
+    /// - `ssid`: A &str with the name of the network, of at most 32 bytes.
+    /// - `auth`: The `AccessPointAuth` the clients must use to connect.
+    /// - `password`: An `Option<&str>` with the password of the network, of 8 to 64 bytes. It must
+    ///   be None if `auth` is `AccessPointAuth::Open`.
+    /// - `channel`: The channel of the network, from 1 to 13.
+    /// - `max_clients`: The maximum number of clients that can be connected at the same time, from
+    ///   1 to 10.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the access point started, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If any of the arguments is invalid, or the configuration
+    ///   of the wifi driver fails.
+    /// - `WifiError::StartingError`: Error while starting wifi driver.
+    pub fn start_access_point(
+        &mut self,
+        ssid: &str,
+        auth: AccessPointAuth,
+        password: Option<&str>,
+        channel: u8,
+        max_clients: u16,
+    ) -> Result<(), WifiError> {
+        block_on(self.start_access_point_async(ssid, auth, password, channel, max_clients))
+    }
+
+    /// Async version of [Self::start_access_point]
+    pub async fn start_access_point_async(
+        &mut self,
+        ssid: &str,
+        auth: AccessPointAuth,
+        password: Option<&str>,
+        channel: u8,
+        max_clients: u16,
+    ) -> Result<(), WifiError> {
+        let valid_password = match (auth, password) {
+            (AccessPointAuth::Open, password) => password.is_none(),
+            (_, Some(password)) => password.len() >= MIN_ACCESS_POINT_PASSWORD_LEN,
+            (_, None) => false,
+        };
+        if !valid_password
+            || !(MIN_ACCESS_POINT_CHANNEL..=MAX_ACCESS_POINT_CHANNEL).contains(&channel)
+            || !(1..=MAX_ACCESS_POINT_CLIENTS).contains(&max_clients)
+        {
+            return Err(WifiError::ConfigurationError);
+        }
+
+        let access_point = AccessPointConfiguration {
+            ssid: ssid.try_into().map_err(|_| WifiError::ConfigurationError)?,
+            channel,
+            auth_method: auth.into(),
+            password: password
+                .unwrap_or_default()
+                .try_into()
+                .map_err(|_| WifiError::ConfigurationError)?,
+            max_connections: max_clients,
+            ..Default::default()
+        };
+        let wifi_configuration = match self.client_configuration() {
+            Some(client) => Configuration::Mixed(client, access_point),
+            None => Configuration::AccessPoint(access_point),
+        };
+        self.controller
+            .set_configuration(&wifi_configuration)
+            .map_err(|_| WifiError::ConfigurationError)?;
+
+        self.start_if_needed_async().await
+    }
+
+    /// Stops hosting the wifi network started with [Self::start_access_point], disconnecting its
+    /// clients. If the driver is connected to a network, it stays connected.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the access point stopped, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If the configuration of the wifi driver fails.
+    pub fn stop_access_point(&mut self) -> Result<(), WifiError> {
+        let wifi_configuration = match self.client_configuration() {
+            Some(client) => Configuration::Client(client),
+            None => Configuration::None,
+        };
+        self.controller
+            .set_configuration(&wifi_configuration)
+            .map_err(|_| WifiError::ConfigurationError)
+    }
+
+    /// Checks if the device is hosting a wifi network.
+    ///
+    /// # Returns
+    ///
+    /// A bool that indicates whether the access point is started or not.
+    pub fn is_access_point_started(&self) -> bool {
+        self.is_started() && self.access_point_configuration().is_some()
+    }
+
+    /// Gets the station configuration currently set on the driver, if any
+    fn client_configuration(&self) -> Option<ClientConfiguration> {
+        match self.controller.get_configuration() {
+            Ok(Configuration::Client(client)) | Ok(Configuration::Mixed(client, _)) => Some(client),
+            _ => None,
+        }
+    }
+
+    /// Gets the access point configuration currently set on the driver, if any
+    fn access_point_configuration(&self) -> Option<AccessPointConfiguration> {
+        match self.controller.get_configuration() {
+            Ok(Configuration::AccessPoint(access_point))
+            | Ok(Configuration::Mixed(_, access_point)) => Some(access_point),
+            _ => None,
+        }
+    }
+
+    /// Scans for nearby Wi-Fi networks and returns a vector of discovered access points, sorted
+    /// from the strongest signal to the weakest.
     ///     
     ///  # Returns
     ///
@@ -246,12 +556,7 @@ impl<'a> WifiDriver<'a> {
 
     /// Async version of [Self::scan]
     pub async fn scan_async(&mut self) -> Result<Vec<AccesPoint>, WifiError> {
-        if !self.is_started() {
-            self.controller
-                .start()
-                .await
-                .map_err(|_| WifiError::StartingError)?;
-        }
+        self.start_if_needed_async().await?;
 
         let results: Vec<AccessPointInfo> = self
             .controller
@@ -259,10 +564,124 @@ impl<'a> WifiDriver<'a> {
             .await
             .map_err(|_| WifiError::ScanError)?;
 
-        let parsed_results: Vec<AccesPoint> = results.into_iter().map(AccesPoint::from).collect();
+        let mut parsed_results: Vec<AccesPoint> =
+            results.into_iter().map(AccesPoint::from).collect();
+        parsed_results.sort_by(|a, b| b.signal_strength.cmp(&a.signal_strength));
 
         Ok(parsed_results)
     }
+
+    /// Scans for the access points of a given Wi-Fi network, sorted from the strongest signal to
+    /// the weakest, so the best one can be picked before connecting.
+    ///
+    /// # Arguments
+    ///
+    /// - `ssid`: A &str with the name of the network
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a vector of the discovered access points of the network, which is
+    /// empty if the network was not found. Else, a `WifiError`.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ScanError`: If the scan operation fails to complete successfully.
+    /// - `WifiError::StartingError`: Error while starting wifi driver.
+    pub fn scan_ssid(&mut self, ssid: &str) -> Result<Vec<AccesPoint>, WifiError> {
+        block_on(self.scan_ssid_async(ssid))
+    }
+
+    /// Async version of [Self::scan_ssid]
+    pub async fn scan_ssid_async(&mut self, ssid: &str) -> Result<Vec<AccesPoint>, WifiError> {
+        let mut access_points = self.scan_async().await?;
+        access_points.retain(|access_point| access_point.ssid == ssid);
+        Ok(access_points)
+    }
+
+    /// Starts the wifi driver if it was not already started, without connecting to any network.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the driver is started, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::StartingError`: If the driver could not be started
+    pub(crate) async fn start_if_needed_async(&mut self) -> Result<(), WifiError> {
+        if !self.is_started() {
+            self.controller
+                .start()
+                .await
+                .map_err(|_| WifiError::StartingError)?;
+        }
+        Ok(())
+    }
+
+    /// Starts the driver in station mode, keeping the access point if it is hosting one. If no
+    /// network was configured, an empty client configuration is set.
+    pub(crate) async fn start_station_async(&mut self) -> Result<(), WifiError> {
+        if self.client_configuration().is_none() {
+            self.set_client_configuration("", AuthMethod::None, "")?;
+        }
+        self.start_if_needed_async().await
+    }
+
+    /// Broadcasts a vendor specific action frame. This allows custom low latency broadcast
+    /// protocols between devices, which can receive the frames through a [super::WifiSniffer].
+    /// The driver must be started, but it does not need to be connected to a network. Frames
+    /// are sent on the current channel.
+    ///
+    /// # Arguments
+    ///
+    /// - `oui`: Organizationally unique identifier of the protocol
+    /// - `payload`: The data to send, of at most [MAX_VENDOR_PAYLOAD_SIZE] bytes
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the frame was sent, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::WifiNotInitialized`: If the driver is not started.
+    /// - `WifiError::FrameTooLong`: If the payload exceeds [MAX_VENDOR_PAYLOAD_SIZE].
+    /// - `WifiError::InformationError`: If the MAC address of the device could not be obtained.
+    /// - `WifiError::TransmissionError`: If the frame could not be sent.
+    pub fn send_vendor_action_frame(
+        &mut self,
+        oui: [u8; 3],
+        payload: &[u8],
+    ) -> Result<(), WifiError> {
+        if !self.is_started() {
+            return Err(WifiError::WifiNotInitialized);
+        }
+        if payload.len() > MAX_VENDOR_PAYLOAD_SIZE {
+            return Err(WifiError::FrameTooLong);
+        }
+        let mut source = [0; 6];
+        if unsafe { esp_wifi_get_mac(wifi_interface_t_WIFI_IF_STA, source.as_mut_ptr()) } != ESP_OK
+        {
+            return Err(WifiError::InformationError);
+        }
+        let frame = VendorActionFrame {
+            source,
+            oui,
+            payload: payload.to_vec(),
+        }
+        .to_bytes();
+
+        match unsafe {
+            esp_wifi_80211_tx(
+                wifi_interface_t_WIFI_IF_STA,
+                frame.as_ptr() as *const _,
+                frame.len() as i32,
+                true,
+            )
+        } {
+            ESP_OK => Ok(()),
+            _ => Err(WifiError::TransmissionError),
+        }
+    }
+
     /// Checks if the driver is already started.
     ///
     /// # Returns
@@ -305,6 +724,76 @@ impl<'a> WifiDriver<'a> {
         Ok(info.ip)
     }
 
+    /// Gets the ip address of the device on the network it hosts, which the clients of the access
+    /// point can use to reach it.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing a Ipv4Addr with the device ip address or a `WifiError` in case of failure.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::InformationError`: If WiFi driver can not get its own ip.
+    pub fn get_access_point_address_info(&self) -> Result<Ipv4Addr, WifiError> {
+        let netif = self.controller.wifi().ap_netif();
+        let info = netif
+            .get_ip_info()
+            .map_err(|_| WifiError::InformationError)?;
+        Ok(info.ip)
+    }
+
+    /// Sets the name the device sends to the DHCP server, so it shows up with that name on the
+    /// client list of the router, instead of `espressif`. It must be set before connecting, since
+    /// it is only sent when a lease is requested.
+    ///
+    /// # Arguments
+    ///
+    /// - `hostname`: A &str of at most 32 letters, digits and hyphens, which does not start nor
+    ///   end with a hyphen.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with Ok if the hostname was set, or a `WifiError` if it fails.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::ConfigurationError`: If the hostname is not valid or could not be set.
+    pub fn set_hostname(&mut self, hostname: &str) -> Result<(), WifiError> {
+        let valid = !hostname.is_empty()
+            && hostname.len() <= MAX_HOSTNAME_LEN
+            && !hostname.starts_with('-')
+            && !hostname.ends_with('-')
+            && hostname
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return Err(WifiError::ConfigurationError);
+        }
+        self.controller
+            .wifi_mut()
+            .sta_netif_mut()
+            .set_hostname(hostname)
+            .map_err(|_| WifiError::ConfigurationError)
+    }
+
+    /// Gets the name the device sends to the DHCP server.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing the hostname or a `WifiError` in case of failure.
+    ///
+    /// # Errors
+    ///
+    /// - `WifiError::InformationError`: If the hostname could not be obtained.
+    pub fn get_hostname(&self) -> Result<String, WifiError> {
+        self.controller
+            .wifi()
+            .sta_netif()
+            .get_hostname()
+            .map(|hostname| hostname.to_string())
+            .map_err(|_| WifiError::InformationError)
+    }
+
     /// Gets the DNS ip address.
     ///
     /// # Returns
@@ -351,6 +840,109 @@ impl<'a> WifiDriver<'a> {
     pub fn get_https_client(&self) -> Result<HttpsClient, WifiError> {
         HttpsClient::new().map_err(|_| WifiError::HttpError)
     }
+
+    /// Sets the callback executed when the device connects to a network, before it gets an ip address.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the WifiConnectionInfo of the network
+    ///
+    /// # Returns
+    ///
+    /// The WifiDriver itself
+    pub fn on_connected<C: FnMut(&WifiConnectionInfo) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.events.on_connected(callback);
+        self
+    }
+
+    /// Sets the callback executed when the device disconnects from a network, or fails to connect to it.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the WifiDisconnectReason of the disconnection
+    ///
+    /// # Returns
+    ///
+    /// The WifiDriver itself
+    pub fn on_disconnected<C: FnMut(WifiDisconnectReason) + 'a>(
+        &mut self,
+        callback: C,
+    ) -> &mut Self {
+        self.events.on_disconnected(callback);
+        self
+    }
+
+    /// Sets the callback executed when the device gets an ip address from the network it is connected to.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the ip address of the device
+    ///
+    /// # Returns
+    ///
+    /// The WifiDriver itself
+    pub fn on_got_ip<C: FnMut(Ipv4Addr) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.events.on_got_ip(callback);
+        self
+    }
+
+    /// Sets the callback executed when a client joins the access point hosted by the device.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the MAC address of the client
+    ///
+    /// # Returns
+    ///
+    /// The WifiDriver itself
+    pub fn on_client_joined<C: FnMut([u8; 6]) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.events.on_client_joined(callback);
+        self
+    }
+
+    /// Sets the callback executed when a client leaves the access point hosted by the device.
+    ///
+    /// Note: For the callback to be executed, the method [crate::Microcontroller::wait_for_updates] must
+    /// be called periodicly, unless using an async aproach in which case [crate::Microcontroller::block_on]
+    /// must be used.
+    ///
+    /// # Arguments
+    ///
+    /// - `callback`: A closure that receives the MAC address of the client
+    ///
+    /// # Returns
+    ///
+    /// The WifiDriver itself
+    pub fn on_client_left<C: FnMut([u8; 6]) + 'a>(&mut self, callback: C) -> &mut Self {
+        self.events.on_client_left(callback);
+        self
+    }
+}
+
+impl<'a> InterruptDriver<'a> for WifiDriver<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.events.update_interrupt()
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        self.events.get_updater()
+    }
 }
 
 impl From<PeripheralError> for WifiError {
@@ -358,3 +950,9 @@ impl From<PeripheralError> for WifiError {
         Self::PeripheralError(value)
     }
 }
+
+impl From<TimerDriverError> for WifiError {
+    fn from(value: TimerDriverError) -> Self {
+        Self::TimerDriverError(value)
+    }
+}
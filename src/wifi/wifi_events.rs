@@ -0,0 +1,307 @@
+use crate::{
+    utils::{
+        auxiliary::{SharableRef, SharableRefExt},
+        esp32_framework_error::Esp32FrameworkError,
+        notification::Notifier,
+        queue_config::{queue_capacities, record_dropped_item},
+    },
+    InterruptDriver,
+};
+use esp_idf_svc::{
+    eventloop::{EspSubscription, EspSystemEventLoop, System},
+    netif::IpEvent,
+    sys::{
+        wifi_err_reason_t_WIFI_REASON_4WAY_HANDSHAKE_TIMEOUT,
+        wifi_err_reason_t_WIFI_REASON_ASSOC_FAIL, wifi_err_reason_t_WIFI_REASON_ASSOC_LEAVE,
+        wifi_err_reason_t_WIFI_REASON_AUTH_FAIL, wifi_err_reason_t_WIFI_REASON_BEACON_TIMEOUT,
+        wifi_err_reason_t_WIFI_REASON_CONNECTION_FAIL,
+        wifi_err_reason_t_WIFI_REASON_HANDSHAKE_TIMEOUT, wifi_err_reason_t_WIFI_REASON_NO_AP_FOUND,
+        EspError,
+    },
+    wifi::WifiEvent,
+};
+use sharable_reference_macro::sharable_reference_wrapper;
+use std::{
+    collections::VecDeque,
+    net::Ipv4Addr,
+    sync::{Arc, Mutex},
+};
+
+const MAC_SIZE: usize = 6;
+
+/// Events waiting for the update loop
+type EventQueue = Arc<Mutex<VecDeque<LifecycleEvent>>>;
+
+type ConnectedCallback<'a> = dyn FnMut(&WifiConnectionInfo) + 'a;
+type DisconnectedCallback<'a> = dyn FnMut(WifiDisconnectReason) + 'a;
+type GotIpCallback<'a> = dyn FnMut(Ipv4Addr) + 'a;
+type ClientCallback<'a> = dyn FnMut([u8; MAC_SIZE]) + 'a;
+
+/// Enums the reasons of a disconnection from a network:
+/// - `AssociationFailed`: The access point rejected the connection.
+/// - `AuthenticationFailed`: The password or credentials are wrong.
+/// - `BeaconTimeout`: The signal of the access point was lost.
+/// - `ConnectionFailed`: The connection could not be established.
+/// - `HandshakeTimeout`: The access point did not complete the handshake, usually because of a
+///   wrong password.
+/// - `Left`: The device disconnected on purpose.
+/// - `NoAccessPointFound`: The network was not found.
+/// - `Other`: Any other reason, with its 802.11 or ESP-IDF code.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WifiDisconnectReason {
+    AssociationFailed,
+    AuthenticationFailed,
+    BeaconTimeout,
+    ConnectionFailed,
+    HandshakeTimeout,
+    Left,
+    NoAccessPointFound,
+    Other(u16),
+}
+
+/// Information of the network the device connected to:
+/// - `ssid`: The name of the network.
+/// - `bssid`: The MAC address of the access point.
+/// - `channel`: The channel of the network.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WifiConnectionInfo {
+    pub ssid: String,
+    pub bssid: [u8; MAC_SIZE],
+    pub channel: u8,
+}
+
+/// Enums the events of the wifi received from the system event loop
+enum LifecycleEvent {
+    Connected(WifiConnectionInfo),
+    Disconnected(WifiDisconnectReason),
+    GotIp(Ipv4Addr),
+    ClientJoined([u8; MAC_SIZE]),
+    ClientLeft([u8; MAC_SIZE]),
+}
+
+/// Callbacks of the lifecycle events of the wifi, executed in the update loop of the
+/// microcontroller. Owned by the WifiDriver, which sets them.
+struct _WifiEvents<'a> {
+    events: EventQueue,
+    connected_callback: Option<Box<ConnectedCallback<'a>>>,
+    disconnected_callback: Option<Box<DisconnectedCallback<'a>>>,
+    got_ip_callback: Option<Box<GotIpCallback<'a>>>,
+    client_joined_callback: Option<Box<ClientCallback<'a>>>,
+    client_left_callback: Option<Box<ClientCallback<'a>>>,
+}
+
+/// Callbacks of the lifecycle events of the wifi, executed in the update loop of the
+/// microcontroller. Owned by the WifiDriver, which sets them.
+pub(crate) struct WifiEvents<'a> {
+    inner: SharableRef<_WifiEvents<'a>>,
+}
+
+/// Subscriptions to the wifi and ip events of the system event loop, which queue the events for
+/// a WifiEvents. The events stop being received once they are dropped.
+pub(crate) struct WifiEventSubscriptions {
+    _wifi: EspSubscription<'static, System>,
+    _ip: EspSubscription<'static, System>,
+}
+
+impl From<u32> for WifiDisconnectReason {
+    #[allow(non_upper_case_globals)]
+    fn from(value: u32) -> Self {
+        match value {
+            wifi_err_reason_t_WIFI_REASON_ASSOC_FAIL => WifiDisconnectReason::AssociationFailed,
+            wifi_err_reason_t_WIFI_REASON_AUTH_FAIL => WifiDisconnectReason::AuthenticationFailed,
+            wifi_err_reason_t_WIFI_REASON_BEACON_TIMEOUT => WifiDisconnectReason::BeaconTimeout,
+            wifi_err_reason_t_WIFI_REASON_CONNECTION_FAIL => WifiDisconnectReason::ConnectionFailed,
+            wifi_err_reason_t_WIFI_REASON_HANDSHAKE_TIMEOUT
+            | wifi_err_reason_t_WIFI_REASON_4WAY_HANDSHAKE_TIMEOUT => {
+                WifiDisconnectReason::HandshakeTimeout
+            }
+            wifi_err_reason_t_WIFI_REASON_ASSOC_LEAVE => WifiDisconnectReason::Left,
+            wifi_err_reason_t_WIFI_REASON_NO_AP_FOUND => WifiDisconnectReason::NoAccessPointFound,
+            code => WifiDisconnectReason::Other(code as u16),
+        }
+    }
+}
+
+#[sharable_reference_wrapper]
+impl<'a> _WifiEvents<'a> {
+    /// Creates a new _WifiEvents without callbacks
+    ///
+    /// # Arguments
+    ///
+    /// - `events`: The queue where the subscriptions leave the events
+    ///
+    /// # Returns
+    ///
+    /// The new _WifiEvents
+    fn new(events: EventQueue) -> Self {
+        _WifiEvents {
+            events,
+            connected_callback: None,
+            disconnected_callback: None,
+            got_ip_callback: None,
+            client_joined_callback: None,
+            client_left_callback: None,
+        }
+    }
+
+    /// Sets the callback executed when the device connects to a network
+    pub(crate) fn on_connected<C: FnMut(&WifiConnectionInfo) + 'a>(&mut self, callback: C) {
+        self.connected_callback = Some(Box::new(callback));
+    }
+
+    /// Sets the callback executed when the device disconnects from a network
+    pub(crate) fn on_disconnected<C: FnMut(WifiDisconnectReason) + 'a>(&mut self, callback: C) {
+        self.disconnected_callback = Some(Box::new(callback));
+    }
+
+    /// Sets the callback executed when the device gets an ip address from the network
+    pub(crate) fn on_got_ip<C: FnMut(Ipv4Addr) + 'a>(&mut self, callback: C) {
+        self.got_ip_callback = Some(Box::new(callback));
+    }
+
+    /// Sets the callback executed when a client joins the access point of the device
+    pub(crate) fn on_client_joined<C: FnMut([u8; MAC_SIZE]) + 'a>(&mut self, callback: C) {
+        self.client_joined_callback = Some(Box::new(callback));
+    }
+
+    /// Sets the callback executed when a client leaves the access point of the device
+    pub(crate) fn on_client_left<C: FnMut([u8; MAC_SIZE]) + 'a>(&mut self, callback: C) {
+        self.client_left_callback = Some(Box::new(callback));
+    }
+
+    /// Executes the callback of each event received since the last call
+    fn handle_events(&mut self) {
+        loop {
+            let event = self
+                .events
+                .lock()
+                .unwrap_or_else(|err| err.into_inner())
+                .pop_front();
+            let Some(event) = event else {
+                return;
+            };
+            match event {
+                LifecycleEvent::Connected(info) => {
+                    if let Some(callback) = self.connected_callback.as_mut() {
+                        callback(&info);
+                    }
+                }
+                LifecycleEvent::Disconnected(reason) => {
+                    if let Some(callback) = self.disconnected_callback.as_mut() {
+                        callback(reason);
+                    }
+                }
+                LifecycleEvent::GotIp(ip) => {
+                    if let Some(callback) = self.got_ip_callback.as_mut() {
+                        callback(ip);
+                    }
+                }
+                LifecycleEvent::ClientJoined(mac) => {
+                    if let Some(callback) = self.client_joined_callback.as_mut() {
+                        callback(mac);
+                    }
+                }
+                LifecycleEvent::ClientLeft(mac) => {
+                    if let Some(callback) = self.client_left_callback.as_mut() {
+                        callback(mac);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> WifiEvents<'a> {
+    /// Creates a new WifiEvents, subscribing to the wifi and ip events of the system event loop
+    ///
+    /// # Arguments
+    ///
+    /// - `event_loop`: The system event loop where the wifi driver posts its events
+    /// - `notifier`: A notifier in order to wake up the [crate::Microcontroller] when an event is received
+    ///
+    /// # Returns
+    ///
+    /// A `Result` with the new WifiEvents and the subscriptions that feed it, or an `EspError` if
+    /// the subscriptions fail. The subscriptions must be kept alive for the events to be received.
+    pub(crate) fn new(
+        event_loop: &EspSystemEventLoop,
+        notifier: Notifier,
+    ) -> Result<(Self, WifiEventSubscriptions), EspError> {
+        let events: EventQueue = Arc::new(Mutex::new(VecDeque::new()));
+
+        let (queue, wifi_notifier) = (events.clone(), notifier.clone());
+        let wifi = event_loop.subscribe::<WifiEvent, _>(move |event| {
+            let event = match event {
+                WifiEvent::StaConnected(connected) => {
+                    LifecycleEvent::Connected(WifiConnectionInfo {
+                        ssid: connected.ssid().to_string(),
+                        bssid: mac_from(&connected.bssid()[..]),
+                        channel: connected.channel(),
+                    })
+                }
+                WifiEvent::StaDisconnected(disconnected) => LifecycleEvent::Disconnected(
+                    WifiDisconnectReason::from(disconnected.reason() as u32),
+                ),
+                WifiEvent::ApStaConnected(client) => {
+                    LifecycleEvent::ClientJoined(mac_from(&client.mac()[..]))
+                }
+                WifiEvent::ApStaDisconnected(client) => {
+                    LifecycleEvent::ClientLeft(mac_from(&client.mac()[..]))
+                }
+                _ => return,
+            };
+            queue_event(&queue, &wifi_notifier, event);
+        })?;
+
+        let queue = events.clone();
+        let ip = event_loop.subscribe::<IpEvent, _>(move |event| {
+            if let IpEvent::DhcpIpAssigned(assignment) = event {
+                queue_event(&queue, &notifier, LifecycleEvent::GotIp(assignment.ip()));
+            }
+        })?;
+
+        let wifi_events = Self {
+            inner: SharableRef::new_sharable(_WifiEvents::new(events)),
+        };
+        Ok((
+            wifi_events,
+            WifiEventSubscriptions {
+                _wifi: wifi,
+                _ip: ip,
+            },
+        ))
+    }
+}
+
+impl<'a> InterruptDriver<'a> for WifiEvents<'a> {
+    fn update_interrupt(&mut self) -> Result<(), Esp32FrameworkError> {
+        self.inner.deref_mut().handle_events();
+        Ok(())
+    }
+
+    fn get_updater(&self) -> Box<dyn InterruptDriver<'a> + 'a> {
+        Box::new(Self {
+            inner: self.inner.clone(),
+        })
+    }
+}
+
+/// Copies a MAC address received in an event
+fn mac_from(bytes: &[u8]) -> [u8; MAC_SIZE] {
+    let mut mac = [0; MAC_SIZE];
+    mac.copy_from_slice(&bytes[..MAC_SIZE]);
+    mac
+}
+
+/// Queues an event received from the system event loop and wakes up the microcontroller. If the
+/// queue is full the event is dropped.
+fn queue_event(queue: &EventQueue, notifier: &Notifier, event: LifecycleEvent) {
+    let mut events = queue.lock().unwrap_or_else(|err| err.into_inner());
+    if events.len() >= queue_capacities().wifi_events {
+        record_dropped_item();
+        return;
+    }
+    events.push_back(event);
+    drop(events);
+    notifier.notify();
+}